@@ -1,11 +1,16 @@
 #[macro_use]
 extern crate serde;
 
-use clap::{value_t, App, Arg, SubCommand};
+use clap::{value_t, values_t, App, Arg, SubCommand};
 
 mod hash_fns;
 mod rational_post;
+mod seal;
+#[cfg(feature = "simulate")]
+mod simulate;
 mod stacked;
+mod trace;
+mod verify_throughput;
 
 fn main() {
     pretty_env_logger::init_timed();
@@ -102,12 +107,108 @@ fn main() {
     let hash_cmd = SubCommand::with_name("hash-constraints")
         .about("Benchmark hash function inside of a circuit");
 
-    let matches = App::new("benchy")
+    let verify_throughput_cmd = SubCommand::with_name("verify-throughput")
+        .about(
+            "Measure seal proof verification throughput at several batch sizes and thread counts",
+        )
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .required(true)
+                .help("The sector size in KiB, used to determine the number of public inputs")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("partitions")
+                .long("partitions")
+                .help("How many circuit partitions a seal proof has")
+                .default_value("2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("batch-sizes")
+                .long("batch-sizes")
+                .help("Comma-separated list of batch sizes; 1 means unbatched verification")
+                .default_value("1,8,64")
+                .require_delimiter(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .help("Comma-separated list of thread counts")
+                .default_value("1")
+                .require_delimiter(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("proofs")
+                .long("proofs")
+                .help("Minimum number of proofs to verify per sample")
+                .default_value("128")
+                .takes_value(true),
+        );
+
+    #[cfg(feature = "simulate")]
+    let simulate_cmd = SubCommand::with_name("simulate")
+        .about("Simulate a stacked seal with a cheap hasher, reporting per stage timings and resource usage")
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .required(true)
+                .help("The sector size in KiB")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("layers")
+                .long("layers")
+                .help("How many layers to use")
+                .default_value("10")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("challenges")
+                .long("challenges")
+                .help("How many challenges to prove per partition")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("partitions")
+                .long("partitions")
+                .help("How many circuit partitions to use")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("groth")
+                .long("groth")
+                .help("Also generate and verify the circuit proof."),
+        );
+
+    let seal_cmd = SubCommand::with_name("seal")
+        .about("Seal a sector and prove it with a PoSt through the public API, reporting per stage timings and resource usage")
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .required(true)
+                .help("The sector size in KiB")
+                .takes_value(true),
+        );
+
+    let app = App::new("benchy")
         .version("0.1")
         .subcommand(stacked_cmd)
         .subcommand(rational_post_cmd)
         .subcommand(hash_cmd)
-        .get_matches();
+        .subcommand(verify_throughput_cmd)
+        .subcommand(seal_cmd);
+
+    // Only built with the `simulate` feature, as it needs the insecure `TestHasher`.
+    #[cfg(feature = "simulate")]
+    let app = app.subcommand(simulate_cmd);
+
+    let matches = app.get_matches();
 
     match matches.subcommand() {
         ("stacked", Some(m)) => {
@@ -141,6 +242,38 @@ fn main() {
         ("hash-constraints", Some(_m)) => {
             hash_fns::run().expect("hash-constraints failed");
         }
+        ("verify-throughput", Some(m)) => {
+            Ok(())
+                .and_then(|_| {
+                    verify_throughput::run(verify_throughput::RunOpts {
+                        sector_size: value_t!(m, "size", u64)? * 1024,
+                        partitions: value_t!(m, "partitions", u8)?,
+                        batch_sizes: values_t!(m, "batch-sizes", usize)?,
+                        thread_counts: values_t!(m, "threads", usize)?,
+                        proofs_per_sample: value_t!(m, "proofs", usize)?,
+                    })
+                })
+                .expect("verify-throughput failed");
+        }
+        #[cfg(feature = "simulate")]
+        ("simulate", Some(m)) => {
+            Ok(())
+                .and_then(|_| {
+                    simulate::run(simulate::RunOpts {
+                        size: value_t!(m, "size", usize)? * 1024,
+                        layers: value_t!(m, "layers", usize)?,
+                        challenges: value_t!(m, "challenges", usize)?,
+                        partitions: value_t!(m, "partitions", usize)?,
+                        groth: m.is_present("groth"),
+                    })
+                })
+                .expect("simulate failed");
+        }
+        ("seal", Some(m)) => {
+            Ok(())
+                .and_then(|_| seal::run(value_t!(m, "size", u64)? * 1024))
+                .expect("seal failed");
+        }
         _ => panic!("carnation"),
     }
 }
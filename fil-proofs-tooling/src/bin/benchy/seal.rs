@@ -0,0 +1,160 @@
+//! End to end seal throughput.
+//!
+//! Seals a sector of random data through the public `filecoin_proofs` API, in the stages a miner
+//! runs them in: pre-commit, commit phase 1 (the vanilla proofs) and commit phase 2 (the SNARK).
+//! It then verifies the seal, and generates and verifies a PoSt over it, reporting the time and
+//! resources each stage took.
+//! The report is meant to be collected for every commit, so performance regressions show up as
+//! a change in a stage of the same name.
+
+use std::collections::BTreeMap;
+use std::io::stdout;
+
+use failure::format_err;
+use log::info;
+use rand::random;
+use tempfile::NamedTempFile;
+
+use fil_proofs_tooling::Metadata;
+use filecoin_proofs::fr32::write_padded;
+use filecoin_proofs::pieces::get_aligned_source;
+use filecoin_proofs::types::{
+    PaddedBytesAmount, PoRepConfig, PoStConfig, RegisteredSealProof, SectorSize,
+    UnpaddedBytesAmount,
+};
+use filecoin_proofs::{
+    generate_post, prove_from_phase1_output, seal_commit_phase1_from_pre_commit, seal_pre_commit,
+    verify_post, verify_seal, PrivateReplicaInfo, PublicReplicaInfo,
+};
+use storage_proofs::sector::SectorId;
+
+use crate::trace::{StageTrace, Tracer};
+
+const CHALLENGE_SEED: [u8; 32] = [0; 32];
+const PROVER_ID: [u8; 32] = [0; 32];
+const SECTOR_ID: u64 = 0;
+const TICKET: [u8; 32] = [0; 32];
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Inputs {
+    sector_size: u64,
+    partitions: u8,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Report {
+    inputs: Inputs,
+    stages: Vec<StageTrace>,
+}
+
+impl Report {
+    /// Print all results to stdout
+    pub fn print(&self) {
+        let wrapped = Metadata::wrap(&self).expect("failed to retrieve metadata");
+        serde_json::to_writer(stdout(), &wrapped).expect("cannot write report JSON to stdout");
+    }
+}
+
+pub fn run(sector_size: u64) -> Result<(), failure::Error> {
+    info!("Benchy seal: sector-size={}", sector_size);
+
+    let registered_proof = RegisteredSealProof::from_sector_size(SectorSize(sector_size))
+        .ok_or_else(|| format_err!("no seal proof is registered for {} bytes", sector_size))?;
+    let partitions = registered_proof.partitions();
+    let porep_config = PoRepConfig(SectorSize(sector_size), partitions);
+    let post_config = PoStConfig(SectorSize(sector_size));
+    let sector_id = SectorId::from(SECTOR_ID);
+
+    let unpadded_size = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+
+    let mut staged_file = NamedTempFile::new()?;
+    let sealed_file = NamedTempFile::new()?;
+    let cache_dir = tempfile::tempdir()?;
+    let sealed_path = sealed_file
+        .path()
+        .to_str()
+        .ok_or_else(|| format_err!("sealed sector path is not UTF-8"))?
+        .to_string();
+
+    // Preparing the data is not part of the measured pipeline.
+    let data: Vec<u8> = (0..u64::from(unpadded_size)).map(|_| random()).collect();
+    let (_, mut aligned_data) = get_aligned_source(&data[..], &[], unpadded_size);
+    write_padded(&mut aligned_data, &mut staged_file)?;
+
+    let mut tracer = Tracer::new();
+
+    let pre_commit = tracer.stage("pre-commit", || {
+        seal_pre_commit(
+            porep_config,
+            cache_dir.path(),
+            staged_file.path(),
+            sealed_file.path(),
+            PROVER_ID,
+            sector_id,
+            TICKET,
+            &[unpadded_size],
+        )
+    })?;
+
+    let (mut seal_output, phase1_output) = tracer.stage("commit-phase1", || {
+        seal_commit_phase1_from_pre_commit(porep_config, PROVER_ID, sector_id, TICKET, &pre_commit)
+    })?;
+
+    // Proven here, rather than by the prover at the `remote_prover` setting.
+    seal_output.proof = tracer.stage("commit-phase2", || {
+        prove_from_phase1_output(porep_config, &phase1_output)
+    })?;
+
+    tracer.stage("verify-seal", || {
+        let verified = verify_seal(
+            porep_config,
+            seal_output.comm_r,
+            seal_output.comm_d,
+            PROVER_ID,
+            sector_id,
+            TICKET,
+            &seal_output.proof,
+        )?;
+        if !verified {
+            return Err(format_err!("seal verification failed"));
+        }
+
+        Ok(())
+    })?;
+
+    let mut priv_replica_info = BTreeMap::new();
+    priv_replica_info.insert(
+        sector_id,
+        PrivateReplicaInfo::new(sealed_path, seal_output.comm_r, seal_output.p_aux.clone()),
+    );
+    let mut pub_replica_info = BTreeMap::new();
+    pub_replica_info.insert(sector_id, PublicReplicaInfo::new(seal_output.comm_r));
+
+    let post_proof = tracer.stage("generate-post", || {
+        generate_post(post_config, &CHALLENGE_SEED, &priv_replica_info)
+    })?;
+
+    tracer.stage("verify-post", || {
+        let verified = verify_post(post_config, &CHALLENGE_SEED, &post_proof, &pub_replica_info)?;
+        if !verified {
+            return Err(format_err!("PoSt verification failed"));
+        }
+
+        Ok(())
+    })?;
+
+    info!("seal benchmark finished in {:?}", tracer.start.elapsed());
+
+    Report {
+        inputs: Inputs {
+            sector_size,
+            partitions: partitions.0,
+        },
+        stages: tracer.stages,
+    }
+    .print();
+
+    Ok(())
+}
@@ -0,0 +1,207 @@
+//! Seal simulation for capacity planning.
+//!
+//! Runs the stages of a stacked seal in the order the real pipeline does, on a sector of the
+//! requested size, but with all tree and column hashing done by the insecure, cheap `TestHasher`
+//! instead of Pedersen. Labeling, disk layout and IO sizes are the real ones, so the reported
+//! per-stage timings and resource traces show where time and storage bandwidth go, without the
+//! cost of a real seal. The produced proofs are worthless.
+
+use std::fs::OpenOptions;
+use std::io::stdout;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use log::info;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use fil_proofs_tooling::Metadata;
+use storage_proofs::circuit::stacked::StackedCompound;
+use storage_proofs::compound_proof::{self, CompoundProof};
+use storage_proofs::crypto::pedersen::jubjub_params;
+use storage_proofs::data::Data;
+use storage_proofs::drgporep;
+use storage_proofs::drgraph::{new_seed, GraphConfig, BASE_DEGREE};
+use storage_proofs::hasher::{Hasher, TestHasher};
+use storage_proofs::porep::PoRep;
+use storage_proofs::proof::ProofScheme;
+use storage_proofs::settings;
+use storage_proofs::stacked::{
+    self, ChallengeRequirements, CommRHasher, LayerChallenges, StackedDrg, EXP_DEGREE,
+};
+use storage_proofs::util::NODE_SIZE;
+
+use crate::trace::{StageTrace, Tracer};
+
+type H = TestHasher;
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Inputs {
+    sector_size: usize,
+    layers: usize,
+    partitions: usize,
+    partition_challenges: usize,
+    hasher: String,
+    groth: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Report {
+    inputs: Inputs,
+    stages: Vec<StageTrace>,
+}
+
+impl Report {
+    /// Print all results to stdout
+    pub fn print(&self) {
+        let wrapped = Metadata::wrap(&self).expect("failed to retrieve metadata");
+        serde_json::to_writer(stdout(), &wrapped).expect("cannot write report JSON to stdout");
+    }
+}
+
+pub struct RunOpts {
+    pub size: usize,
+    pub layers: usize,
+    pub challenges: usize,
+    pub partitions: usize,
+    pub groth: bool,
+}
+
+pub fn run(opts: RunOpts) -> Result<(), failure::Error> {
+    let layer_challenges = LayerChallenges::new(opts.layers, opts.challenges);
+    let nodes = opts.size / NODE_SIZE;
+
+    info!(
+        "Benchy simulate: size={}, layers={}, partitions={}",
+        opts.size, opts.layers, opts.partitions
+    );
+
+    let rng = &mut XorShiftRng::from_seed([0x3dbe_6259, 0x8d31_3d76, 0x3237_db17, 0xe5bc_0654]);
+    let replica_id: <H as Hasher>::Domain = rng.gen();
+
+    let mut tracer = Tracer::new();
+
+    let pp = tracer.stage("setup", || {
+        let sp = stacked::SetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed(),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges: layer_challenges.clone(),
+            porep_id: rng.gen(),
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        };
+
+        Ok(StackedDrg::<H>::setup(&sp)?)
+    })?;
+
+    // The sector is written to the working directory, as it would be by a real seal, so that the
+    // traced IO hits the storage being planned for.
+    let path = format!("./simulated-sector-{:?}", Utc::now());
+
+    let (pub_inputs, priv_inputs) = tracer.stage("replicate", || {
+        let file = OpenOptions::new().write(true).create(true).open(&path)?;
+        file.set_len(opts.size as u64)?;
+        drop(file);
+
+        // Replicated from the path, like `seal`, so the replica is mapped and written the same way.
+        let data = Data::from_path(PathBuf::from(&path));
+        let (tau, (p_aux, t_aux)) = StackedDrg::<H>::replicate(&pp, &replica_id, data, None)?;
+
+        let pub_inputs = stacked::PublicInputs::<<H as Hasher>::Domain> {
+            replica_id,
+            seed: None,
+            tau: Some(tau),
+            k: Some(0),
+        };
+
+        Ok((pub_inputs, stacked::PrivateInputs { p_aux, t_aux }))
+    })?;
+
+    let vanilla_proofs = tracer.stage("vanilla-prove", || {
+        Ok(StackedDrg::<H>::prove_all_partitions(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            opts.partitions,
+        )?)
+    })?;
+
+    tracer.stage("vanilla-verify", || {
+        let verified = StackedDrg::<H>::verify_all_partitions(&pp, &pub_inputs, &vanilla_proofs)?;
+        if !verified {
+            panic!("verification failed");
+        }
+
+        Ok(())
+    })?;
+
+    if opts.groth {
+        let window_size = settings::SETTINGS
+            .lock()
+            .unwrap()
+            .pedersen_hash_exp_window_size;
+        let engine_params = jubjub_params(window_size);
+        let compound_public_params = compound_proof::PublicParams {
+            vanilla_params: pp.clone(),
+            engine_params,
+            partitions: Some(opts.partitions),
+        };
+
+        let groth_params = tracer.stage("load-groth-params", || {
+            Ok(StackedCompound::groth_params(
+                &compound_public_params.vanilla_params,
+                engine_params,
+            )?)
+        })?;
+
+        let proof = tracer.stage("circuit-prove", || {
+            Ok(StackedCompound::prove(
+                &compound_public_params,
+                &pub_inputs,
+                &priv_inputs,
+                &groth_params,
+            )?)
+        })?;
+
+        tracer.stage("circuit-verify", || {
+            let verified = StackedCompound::verify(
+                &compound_public_params,
+                &pub_inputs,
+                &proof,
+                &ChallengeRequirements {
+                    minimum_challenges: 1,
+                },
+            )?;
+            if !verified {
+                panic!("verification failed");
+            }
+
+            Ok(())
+        })?;
+    }
+
+    std::fs::remove_file(&path)?;
+
+    info!("simulation finished in {:?}", tracer.start.elapsed());
+
+    Report {
+        inputs: Inputs {
+            sector_size: opts.size,
+            layers: opts.layers,
+            partitions: opts.partitions,
+            partition_challenges: layer_challenges.challenges_count_all(),
+            hasher: H::name(),
+            groth: opts.groth,
+        },
+        stages: tracer.stages,
+    }
+    .print();
+
+    Ok(())
+}
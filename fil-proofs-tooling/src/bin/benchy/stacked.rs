@@ -5,7 +5,6 @@ use std::{io, u32};
 use bellperson::Circuit;
 use chrono::Utc;
 use failure::bail;
-use fil_sapling_crypto::jubjub::JubjubBls12;
 use log::info;
 use memmap::MmapMut;
 use memmap::MmapOptions;
@@ -16,6 +15,7 @@ use fil_proofs_tooling::{measure, FuncMeasurement, Metadata};
 use storage_proofs::circuit::metric::MetricCS;
 use storage_proofs::circuit::stacked::StackedCompound;
 use storage_proofs::compound_proof::{self, CompoundProof};
+use storage_proofs::crypto::pedersen::jubjub_params;
 use storage_proofs::drgporep;
 use storage_proofs::drgraph::*;
 use storage_proofs::hasher::{Blake2sHasher, Hasher, PedersenHasher, Sha256Hasher};
@@ -23,8 +23,9 @@ use storage_proofs::porep::PoRep;
 use storage_proofs::proof::ProofScheme;
 use storage_proofs::settings;
 use storage_proofs::stacked::{
-    self, ChallengeRequirements, LayerChallenges, StackedDrg, EXP_DEGREE,
+    self, ChallengeRequirements, CommRHasher, LayerChallenges, StackedDrg, EXP_DEGREE,
 };
+use storage_proofs::util::NODE_SIZE;
 
 fn file_backed_mmap_from_zeroes(n: usize, use_tmp: bool) -> Result<MmapMut, failure::Error> {
     let file: File = if use_tmp {
@@ -132,8 +133,12 @@ where
                 degree: BASE_DEGREE,
                 expansion_degree: EXP_DEGREE,
                 seed: new_seed(),
+                graph_config: GraphConfig::default(),
             },
             layer_challenges: layer_challenges.clone(),
+            porep_id: rng.gen(),
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
         };
 
         let pp = StackedDrg::<H>::setup(&sp)?;
@@ -149,7 +154,7 @@ where
                 return_value: (pub_inputs, priv_inputs),
             } = measure(|| {
                 let (tau, (p_aux, t_aux)) =
-                    StackedDrg::<H>::replicate(&pp, &replica_id, &mut data, None)?;
+                    StackedDrg::<H>::replicate(&pp, &replica_id, (&mut data[..]).into(), None)?;
 
                 let pb = stacked::PublicInputs::<H::Domain> {
                     replica_id,
@@ -315,16 +320,16 @@ fn do_circuit_work<H: 'static + Hasher>(
         .lock()
         .unwrap()
         .pedersen_hash_exp_window_size;
-    let engine_params = JubjubBls12::new_with_window_size(window_size);
+    let engine_params = jubjub_params(window_size);
     let compound_public_params = compound_proof::PublicParams {
         vanilla_params: pp.clone(),
-        engine_params: &engine_params,
+        engine_params,
         partitions: Some(*partitions),
     };
 
     if *bench || *circuit {
         let mut cs = MetricCS::<Bls12>::new();
-        StackedCompound::blank_circuit(&pp, &engine_params).synthesize(&mut cs)?;
+        StackedCompound::blank_circuit(&pp, engine_params).synthesize(&mut cs)?;
 
         report.outputs.circuit_num_inputs = Some(cs.num_inputs() as u64);
         report.outputs.circuit_num_constraints = Some(cs.num_constraints() as u64);
@@ -341,7 +346,7 @@ fn do_circuit_work<H: 'static + Hasher>(
         // We should also allow the serialized vanilla proofs to be passed (as a file) to the example
         // and skip replication/vanilla-proving entirely.
         let gparams =
-            StackedCompound::groth_params(&compound_public_params.vanilla_params, &engine_params)?;
+            StackedCompound::groth_params(&compound_public_params.vanilla_params, engine_params)?;
 
         let multi_proof = {
             let FuncMeasurement {
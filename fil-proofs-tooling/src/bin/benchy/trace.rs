@@ -0,0 +1,74 @@
+use std::time::Instant;
+
+use log::info;
+
+use fil_proofs_tooling::{
+    measure, peak_rss_bytes, FuncMeasurement, GpuSampler, GpuUtilization, IoCounters,
+};
+
+/// Time and resources spent in one stage of a benchmark.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StageTrace {
+    pub name: &'static str,
+    /// When the stage started, relative to the start of the benchmark.
+    pub start_ms: u64,
+    pub wall_time_ms: u64,
+    pub cpu_time_ms: u64,
+    /// Storage IO of the stage, where the platform reports it.
+    pub io: Option<IoCounters>,
+    /// Peak resident set size of the process at the end of the stage.
+    pub peak_rss_bytes: Option<u64>,
+    /// GPU utilization during the stage, where an NVIDIA GPU reports it.
+    pub gpu: Option<GpuUtilization>,
+}
+
+/// Records the stages of a benchmark in order.
+pub struct Tracer {
+    pub start: Instant,
+    pub stages: Vec<StageTrace>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Tracer {
+            start: Instant::now(),
+            stages: Vec::new(),
+        }
+    }
+
+    pub fn stage<T, F>(&mut self, name: &'static str, f: F) -> Result<T, failure::Error>
+    where
+        F: FnOnce() -> Result<T, failure::Error>,
+    {
+        info!("running stage: {}", name);
+
+        let start_ms = self.start.elapsed().as_millis() as u64;
+        let io_before = IoCounters::current();
+        let gpu_sampler = GpuSampler::start();
+
+        let FuncMeasurement {
+            cpu_time,
+            wall_time,
+            return_value,
+        } = measure(f)?;
+
+        let gpu = gpu_sampler.stop();
+        let io = match (io_before, IoCounters::current()) {
+            (Some(before), Some(after)) => Some(after.since(&before)),
+            _ => None,
+        };
+
+        self.stages.push(StageTrace {
+            name,
+            start_ms,
+            wall_time_ms: wall_time.as_millis() as u64,
+            cpu_time_ms: cpu_time.as_millis() as u64,
+            io,
+            peak_rss_bytes: peak_rss_bytes(),
+            gpu,
+        });
+
+        Ok(return_value)
+    }
+}
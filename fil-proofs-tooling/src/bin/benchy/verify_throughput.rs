@@ -0,0 +1,71 @@
+use std::io::stdout;
+
+use fil_proofs_tooling::Metadata;
+use filecoin_proofs::throughput::{
+    seal_public_input_count, verification_throughput, ThroughputConfig, ThroughputSample,
+};
+use filecoin_proofs::types::{PoRepConfig, PoRepProofPartitions, SectorSize};
+use log::info;
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Inputs {
+    sector_size: u64,
+    public_inputs: usize,
+    proofs_per_sample: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Report {
+    inputs: Inputs,
+    outputs: Vec<ThroughputSample>,
+}
+
+impl Report {
+    /// Print all results to stdout
+    pub fn print(&self) {
+        let wrapped = Metadata::wrap(&self).expect("failed to retrieve metadata");
+        serde_json::to_writer(stdout(), &wrapped).expect("cannot write report JSON to stdout");
+    }
+}
+
+pub struct RunOpts {
+    pub sector_size: u64,
+    pub partitions: u8,
+    pub batch_sizes: Vec<usize>,
+    pub thread_counts: Vec<usize>,
+    pub proofs_per_sample: usize,
+}
+
+pub fn run(opts: RunOpts) -> Result<(), failure::Error> {
+    let porep_config = PoRepConfig(
+        SectorSize(opts.sector_size),
+        PoRepProofPartitions(opts.partitions),
+    );
+    let public_inputs = seal_public_input_count(porep_config)?;
+
+    info!(
+        "Benchy verify-throughput: sector-size={}, public-inputs={}",
+        opts.sector_size, public_inputs
+    );
+
+    let outputs = verification_throughput(&ThroughputConfig {
+        public_inputs,
+        batch_sizes: opts.batch_sizes,
+        thread_counts: opts.thread_counts,
+        proofs_per_sample: opts.proofs_per_sample,
+    })?;
+
+    Report {
+        inputs: Inputs {
+            sector_size: opts.sector_size,
+            public_inputs,
+            proofs_per_sample: opts.proofs_per_sample,
+        },
+        outputs,
+    }
+    .print();
+
+    Ok(())
+}
@@ -1,5 +1,8 @@
 pub mod measure;
 pub mod metadata;
 
-pub use measure::{measure, FuncMeasurement};
+pub use measure::{
+    gpu_utilization_percent, measure, peak_rss_bytes, FuncMeasurement, GpuSampler, GpuUtilization,
+    IoCounters,
+};
 pub use metadata::Metadata;
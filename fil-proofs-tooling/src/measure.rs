@@ -1,6 +1,12 @@
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
 use cpu_time::ProcessTime;
+use serde::Serialize;
 
 pub struct FuncMeasurement<T> {
     pub cpu_time: Duration,
@@ -23,3 +29,165 @@ where
         return_value: x,
     })
 }
+
+/// Bytes this process caused to be read from and written to storage, as accounted by the
+/// kernel. Reads served from the page cache are not included.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IoCounters {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+impl IoCounters {
+    /// Returns the counters of the current process, or `None` where `/proc/self/io` is not
+    /// available.
+    pub fn current() -> Option<Self> {
+        let io = fs::read_to_string("/proc/self/io").ok()?;
+
+        Some(IoCounters {
+            read_bytes: proc_field(&io, "read_bytes:")?,
+            write_bytes: proc_field(&io, "write_bytes:")?,
+        })
+    }
+
+    /// Returns the bytes transferred since `earlier`.
+    pub fn since(&self, earlier: &IoCounters) -> IoCounters {
+        IoCounters {
+            read_bytes: self.read_bytes.saturating_sub(earlier.read_bytes),
+            write_bytes: self.write_bytes.saturating_sub(earlier.write_bytes),
+        }
+    }
+}
+
+/// Returns the peak resident set size of the current process in bytes, or `None` where
+/// `/proc/self/status` is not available.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+
+    proc_field(&status, "VmHWM:").map(|kib| kib * 1024)
+}
+
+/// How busy the GPUs were while a `GpuSampler` was running.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GpuUtilization {
+    pub mean_percent: f64,
+    pub peak_percent: u32,
+    pub samples: usize,
+}
+
+/// Polls the utilization of the NVIDIA GPUs of the machine through `nvidia-smi` in the
+/// background, averaged over all GPUs, until stopped.
+pub struct GpuSampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Vec<u32>>,
+}
+
+impl GpuSampler {
+    const INTERVAL: Duration = Duration::from_millis(200);
+
+    pub fn start() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+
+        let handle = thread::spawn(move || {
+            let mut samples = Vec::new();
+
+            while !stop_thread.load(Ordering::SeqCst) {
+                match gpu_utilization_percent() {
+                    Some(percent) => samples.push(percent),
+                    // No GPU or no driver, which will not change while sampling.
+                    None => break,
+                }
+                thread::sleep(Self::INTERVAL);
+            }
+
+            samples
+        });
+
+        GpuSampler { stop, handle }
+    }
+
+    /// Stops sampling, returning `None` if no utilization could be read.
+    pub fn stop(self) -> Option<GpuUtilization> {
+        self.stop.store(true, Ordering::SeqCst);
+        let samples = self.handle.join().ok()?;
+
+        if samples.is_empty() {
+            return None;
+        }
+
+        Some(GpuUtilization {
+            mean_percent: samples.iter().map(|&s| f64::from(s)).sum::<f64>() / samples.len() as f64,
+            peak_percent: *samples.iter().max()?,
+            samples: samples.len(),
+        })
+    }
+}
+
+/// Returns the current utilization of the NVIDIA GPUs in percent, averaged over all GPUs, or
+/// `None` where `nvidia-smi` is not available.
+pub fn gpu_utilization_percent() -> Option<u32> {
+    let output = Command::new("nvidia-smi")
+        .args(&[
+            "--query-gpu=utilization.gpu",
+            "--format=csv,noheader,nounits",
+        ])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_gpu_utilization(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the output of `nvidia-smi --query-gpu=utilization.gpu`, one percentage per GPU.
+fn parse_gpu_utilization(output: &str) -> Option<u32> {
+    let percents = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    if percents.is_empty() {
+        return None;
+    }
+
+    Some(percents.iter().sum::<u32>() / percents.len() as u32)
+}
+
+fn proc_field(content: &str, name: &str) -> Option<u64> {
+    content
+        .lines()
+        .find(|line| line.starts_with(name))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proc_field() {
+        let status = "Name:\tbenchy\nVmPeak:\t  123 kB\nVmHWM:\t    4567 kB\n";
+
+        assert_eq!(proc_field(status, "VmHWM:"), Some(4567));
+        assert_eq!(proc_field(status, "VmRSS:"), None);
+        assert_eq!(proc_field(status, "Name:"), None);
+    }
+
+    #[test]
+    fn test_parse_gpu_utilization() {
+        assert_eq!(parse_gpu_utilization("42\n"), Some(42));
+        assert_eq!(parse_gpu_utilization("10\n 30\n"), Some(20));
+        assert_eq!(parse_gpu_utilization(""), None);
+        assert_eq!(parse_gpu_utilization("[Not Supported]\n"), None);
+    }
+}
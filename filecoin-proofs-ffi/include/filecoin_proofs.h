@@ -0,0 +1,173 @@
+#ifndef FILECOIN_PROOFS_H
+#define FILECOIN_PROOFS_H
+
+/* The C API of filecoin-proofs-ffi. Kept by hand, in step with src/types.rs and src/api.rs. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Seal proofs, the index into RegisteredSealProof::ALL. */
+#define FIL_SEAL_PROOF_2KIB 0
+#define FIL_SEAL_PROOF_8MIB 1
+#define FIL_SEAL_PROOF_512MIB 2
+#define FIL_SEAL_PROOF_32GIB 3
+#define FIL_SEAL_PROOF_64GIB 4
+
+/* PoSt proofs, the index into RegisteredPoStProof::ALL. */
+#define FIL_POST_PROOF_2KIB 0
+#define FIL_POST_PROOF_8MIB 1
+#define FIL_POST_PROOF_512MIB 2
+#define FIL_POST_PROOF_32GIB 3
+#define FIL_POST_PROOF_64GIB 4
+
+typedef enum FFIResponseStatus {
+  FFIResponseStatus_NoError = 0,
+  /* The arguments were invalid, e.g. a null pointer or an unknown proof. */
+  FFIResponseStatus_CallerError = 1,
+  /* The operation itself failed. */
+  FFIResponseStatus_ReceiverError = 2,
+} FFIResponseStatus;
+
+typedef struct FFIPrivateReplicaInfo {
+  uint64_t sector_id;
+  uint8_t comm_r[32];
+  uint8_t comm_c[32];
+  uint8_t comm_r_last[32];
+  /* The path of the sealed replica, a NUL terminated UTF-8 string. */
+  const char *replica_path;
+  bool is_fault;
+} FFIPrivateReplicaInfo;
+
+typedef struct FFIPublicReplicaInfo {
+  uint64_t sector_id;
+  uint8_t comm_r[32];
+  bool is_fault;
+} FFIPublicReplicaInfo;
+
+/* Every response must be freed with its fil_destroy_* function. error_msg is set unless
+ * status_code is FFIResponseStatus_NoError. */
+
+typedef struct FFISealResponse {
+  FFIResponseStatus status_code;
+  const char *error_msg;
+  uint8_t comm_r[32];
+  uint8_t comm_d[32];
+  uint8_t comm_c[32];
+  uint8_t comm_r_last[32];
+  const uint8_t *proof_ptr;
+  size_t proof_len;
+  /* The commitments of the pieces, in order. */
+  const uint8_t (*comm_ps_ptr)[32];
+  size_t comm_ps_len;
+} FFISealResponse;
+
+typedef struct FFISealPreCommitResponse {
+  FFIResponseStatus status_code;
+  const char *error_msg;
+  uint8_t comm_r[32];
+  uint8_t comm_d[32];
+  /* The opaque encoding of the pre-commit, to be passed to fil_seal_commit. */
+  const uint8_t *pre_commit_ptr;
+  size_t pre_commit_len;
+} FFISealPreCommitResponse;
+
+typedef struct FFIVerifySealResponse {
+  FFIResponseStatus status_code;
+  const char *error_msg;
+  bool is_valid;
+} FFIVerifySealResponse;
+
+typedef struct FFIUnsealResponse {
+  FFIResponseStatus status_code;
+  const char *error_msg;
+  /* The number of unpadded bytes written to the output file. */
+  uint64_t num_bytes_written;
+} FFIUnsealResponse;
+
+typedef struct FFIGeneratePoStResponse {
+  FFIResponseStatus status_code;
+  const char *error_msg;
+  const uint8_t *proof_ptr;
+  size_t proof_len;
+} FFIGeneratePoStResponse;
+
+typedef struct FFIVerifyPoStResponse {
+  FFIResponseStatus status_code;
+  const char *error_msg;
+  bool is_valid;
+} FFIVerifyPoStResponse;
+
+FFISealResponse *fil_seal(uint64_t registered_proof,
+                          const char *staged_path,
+                          const char *sealed_path,
+                          const uint8_t (*prover_id)[32],
+                          uint64_t sector_id,
+                          const uint8_t (*ticket)[32],
+                          const uint64_t *piece_sizes_ptr,
+                          size_t piece_sizes_len);
+
+FFISealPreCommitResponse *fil_seal_pre_commit(uint64_t registered_proof,
+                                               const char *cache_dir_path,
+                                               const char *staged_path,
+                                               const char *sealed_path,
+                                               const uint8_t (*prover_id)[32],
+                                               uint64_t sector_id,
+                                               const uint8_t (*ticket)[32],
+                                               const uint64_t *piece_sizes_ptr,
+                                               size_t piece_sizes_len);
+
+FFISealResponse *fil_seal_commit(uint64_t registered_proof,
+                                 const uint8_t (*prover_id)[32],
+                                 uint64_t sector_id,
+                                 const uint8_t (*ticket)[32],
+                                 const uint8_t *pre_commit_ptr,
+                                 size_t pre_commit_len);
+
+FFIVerifySealResponse *fil_verify_seal(uint64_t registered_proof,
+                                       const uint8_t (*comm_r)[32],
+                                       const uint8_t (*comm_d)[32],
+                                       const uint8_t (*prover_id)[32],
+                                       uint64_t sector_id,
+                                       const uint8_t (*ticket)[32],
+                                       const uint8_t *proof_ptr,
+                                       size_t proof_len);
+
+FFIUnsealResponse *fil_unseal(uint64_t registered_proof,
+                              const char *sealed_path,
+                              const char *output_path,
+                              const uint8_t (*prover_id)[32],
+                              uint64_t sector_id,
+                              const uint8_t (*comm_d)[32],
+                              const uint8_t (*ticket)[32],
+                              uint64_t offset,
+                              uint64_t num_bytes);
+
+FFIGeneratePoStResponse *fil_generate_post(uint64_t registered_proof,
+                                           const uint8_t (*challenge_seed)[32],
+                                           const FFIPrivateReplicaInfo *replicas_ptr,
+                                           size_t replicas_len);
+
+FFIVerifyPoStResponse *fil_verify_post(uint64_t registered_proof,
+                                       const uint8_t (*challenge_seed)[32],
+                                       const FFIPublicReplicaInfo *replicas_ptr,
+                                       size_t replicas_len,
+                                       const uint8_t *proof_ptr,
+                                       size_t proof_len);
+
+void fil_destroy_seal_response(FFISealResponse *response);
+void fil_destroy_seal_pre_commit_response(FFISealPreCommitResponse *response);
+void fil_destroy_verify_seal_response(FFIVerifySealResponse *response);
+void fil_destroy_unseal_response(FFIUnsealResponse *response);
+void fil_destroy_generate_post_response(FFIGeneratePoStResponse *response);
+void fil_destroy_verify_post_response(FFIVerifyPoStResponse *response);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FILECOIN_PROOFS_H */
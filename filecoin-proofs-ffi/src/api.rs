@@ -0,0 +1,531 @@
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+
+use failure::Error;
+use filecoin_proofs as api;
+use filecoin_proofs::types::{
+    RegisteredPoStProof, RegisteredSealProof, UnpaddedByteIndex, UnpaddedBytesAmount,
+};
+use storage_proofs::hasher::pedersen::PedersenDomain;
+use storage_proofs::hasher::Domain;
+use storage_proofs::sector::SectorId;
+use storage_proofs::versioned;
+
+use crate::types::*;
+
+/// Invalid arguments, reported as `FFIResponseStatus::CallerError`.
+#[derive(Debug, Fail)]
+#[fail(display = "{}", _0)]
+struct CallerError(String);
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Runs `f` and hands its response over to C. Errors and panics become failed responses, so they
+/// never cross the API.
+fn respond<R, F>(f: F) -> *mut R
+where
+    R: Response,
+    F: FnOnce() -> Result<R>,
+{
+    let response = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(response)) => response,
+        Ok(Err(err)) => {
+            let status_code = if err.downcast_ref::<CallerError>().is_some() {
+                FFIResponseStatus::CallerError
+            } else {
+                FFIResponseStatus::ReceiverError
+            };
+            error!("{}", err);
+
+            let mut response = R::default();
+            response.set_error(status_code, err.to_string());
+            response
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(|msg| msg.to_string()))
+                .unwrap_or_else(|| "unknown panic".to_string());
+            error!("panicked: {}", message);
+
+            let mut response = R::default();
+            response.set_error(FFIResponseStatus::ReceiverError, message);
+            response
+        }
+    };
+
+    Box::into_raw(Box::new(response))
+}
+
+/// The seal proof with the given index into `RegisteredSealProof::ALL`.
+fn seal_proof(registered_proof: u64) -> Result<RegisteredSealProof> {
+    RegisteredSealProof::ALL
+        .get(registered_proof as usize)
+        .cloned()
+        .ok_or_else(|| CallerError(format!("unknown seal proof {}", registered_proof)).into())
+}
+
+/// The PoSt with the given index into `RegisteredPoStProof::ALL`.
+fn post_proof(registered_proof: u64) -> Result<RegisteredPoStProof> {
+    RegisteredPoStProof::ALL
+        .get(registered_proof as usize)
+        .cloned()
+        .ok_or_else(|| CallerError(format!("unknown PoSt proof {}", registered_proof)).into())
+}
+
+unsafe fn c_str_to_path(ptr: *const c_char, name: &str) -> Result<PathBuf> {
+    if ptr.is_null() {
+        return Err(CallerError(format!("{} is null", name)).into());
+    }
+
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map(PathBuf::from)
+        .map_err(|_| CallerError(format!("{} is not valid UTF-8", name)).into())
+}
+
+unsafe fn read_bytes32(ptr: *const [u8; 32], name: &str) -> Result<[u8; 32]> {
+    if ptr.is_null() {
+        return Err(CallerError(format!("{} is null", name)).into());
+    }
+
+    Ok(*ptr)
+}
+
+unsafe fn slice<'a, T>(ptr: *const T, len: usize, name: &str) -> Result<&'a [T]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if ptr.is_null() {
+        return Err(CallerError(format!("{} is null", name)).into());
+    }
+
+    Ok(std::slice::from_raw_parts(ptr, len))
+}
+
+fn domain_bytes(domain: &PedersenDomain) -> Result<[u8; 32]> {
+    let mut bytes = [0; 32];
+    domain.write_bytes(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Inserts the replica of `sector_id`, failing if the caller passed the sector before.
+fn insert_replica<T>(
+    replicas: &mut BTreeMap<SectorId, T>,
+    sector_id: u64,
+    replica: T,
+) -> Result<()> {
+    if replicas.insert(SectorId::from(sector_id), replica).is_some() {
+        return Err(CallerError(format!("duplicate sector id {}", sector_id)).into());
+    }
+
+    Ok(())
+}
+
+fn seal_response(output: api::SealOutput) -> Result<FFISealResponse> {
+    let mut response = FFISealResponse::default();
+    response.comm_r = output.comm_r;
+    response.comm_d = output.comm_d;
+    response.comm_c = domain_bytes(&output.p_aux.comm_c)?;
+    response.comm_r_last = domain_bytes(&output.p_aux.comm_r_last)?;
+    let (proof_ptr, proof_len) = into_raw_parts(output.proof);
+    response.proof_ptr = proof_ptr;
+    response.proof_len = proof_len;
+    let (comm_ps_ptr, comm_ps_len) = into_raw_parts(output.comm_ps);
+    response.comm_ps_ptr = comm_ps_ptr;
+    response.comm_ps_len = comm_ps_len;
+
+    Ok(response)
+}
+
+unsafe fn piece_sizes(ptr: *const u64, len: usize) -> Result<Vec<UnpaddedBytesAmount>> {
+    Ok(slice(ptr, len, "piece_sizes")?
+        .iter()
+        .map(|size| UnpaddedBytesAmount(*size))
+        .collect())
+}
+
+/// Seals the staged sector at `staged_path` into `sealed_path` and proves the replication.
+///
+/// `registered_proof` is an index into `RegisteredSealProof::ALL`, and `piece_sizes_ptr` points
+/// to the unpadded sizes of the pieces in the staged sector, in order.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn fil_seal(
+    registered_proof: u64,
+    staged_path: *const c_char,
+    sealed_path: *const c_char,
+    prover_id: *const [u8; 32],
+    sector_id: u64,
+    ticket: *const [u8; 32],
+    piece_sizes_ptr: *const u64,
+    piece_sizes_len: usize,
+) -> *mut FFISealResponse {
+    respond(|| {
+        let registered_proof = seal_proof(registered_proof)?;
+        let staged_path = c_str_to_path(staged_path, "staged_path")?;
+        let sealed_path = c_str_to_path(sealed_path, "sealed_path")?;
+        let prover_id = read_bytes32(prover_id, "prover_id")?;
+        let ticket = read_bytes32(ticket, "ticket")?;
+        let piece_sizes = piece_sizes(piece_sizes_ptr, piece_sizes_len)?;
+
+        let output = api::seal(
+            registered_proof.into(),
+            &staged_path,
+            &sealed_path,
+            prover_id,
+            SectorId::from(sector_id),
+            ticket,
+            &piece_sizes,
+        )?;
+
+        seal_response(output)
+    })
+}
+
+/// Replicates the staged sector at `staged_path` into `sealed_path`, the first half of
+/// `fil_seal`, and stores its layers and trees in `cache_dir_path`. The response holds the
+/// commitments and an opaque, versioned encoding of the pre-commit, which `fil_seal_commit`
+/// finishes the seal from, e.g. in another process.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn fil_seal_pre_commit(
+    registered_proof: u64,
+    cache_dir_path: *const c_char,
+    staged_path: *const c_char,
+    sealed_path: *const c_char,
+    prover_id: *const [u8; 32],
+    sector_id: u64,
+    ticket: *const [u8; 32],
+    piece_sizes_ptr: *const u64,
+    piece_sizes_len: usize,
+) -> *mut FFISealPreCommitResponse {
+    respond(|| {
+        let registered_proof = seal_proof(registered_proof)?;
+        let cache_dir_path = c_str_to_path(cache_dir_path, "cache_dir_path")?;
+        let staged_path = c_str_to_path(staged_path, "staged_path")?;
+        let sealed_path = c_str_to_path(sealed_path, "sealed_path")?;
+        let prover_id = read_bytes32(prover_id, "prover_id")?;
+        let ticket = read_bytes32(ticket, "ticket")?;
+        let piece_sizes = piece_sizes(piece_sizes_ptr, piece_sizes_len)?;
+
+        let output = api::seal_pre_commit(
+            registered_proof.into(),
+            &cache_dir_path,
+            &staged_path,
+            &sealed_path,
+            prover_id,
+            SectorId::from(sector_id),
+            ticket,
+            &piece_sizes,
+        )?;
+
+        let mut response = FFISealPreCommitResponse::default();
+        response.comm_r = output.comm_r;
+        response.comm_d = output.comm_d;
+        let (pre_commit_ptr, pre_commit_len) = into_raw_parts(versioned::to_bytes(&output)?);
+        response.pre_commit_ptr = pre_commit_ptr;
+        response.pre_commit_len = pre_commit_len;
+
+        Ok(response)
+    })
+}
+
+/// Finishes a seal started by `fil_seal_pre_commit`, with the same proof and ids, from the
+/// pre-commit it returned. Responds like `fil_seal`.
+#[no_mangle]
+pub unsafe extern "C" fn fil_seal_commit(
+    registered_proof: u64,
+    prover_id: *const [u8; 32],
+    sector_id: u64,
+    ticket: *const [u8; 32],
+    pre_commit_ptr: *const u8,
+    pre_commit_len: usize,
+) -> *mut FFISealResponse {
+    respond(|| {
+        let registered_proof = seal_proof(registered_proof)?;
+        let prover_id = read_bytes32(prover_id, "prover_id")?;
+        let ticket = read_bytes32(ticket, "ticket")?;
+        let pre_commit: api::SealPreCommitOutput =
+            versioned::read_from(slice(pre_commit_ptr, pre_commit_len, "pre_commit")?)
+                .map_err(|err| CallerError(format!("invalid pre_commit: {}", err)))?;
+
+        let output = api::seal_commit(
+            registered_proof.into(),
+            prover_id,
+            SectorId::from(sector_id),
+            ticket,
+            &pre_commit,
+        )?;
+
+        seal_response(output)
+    })
+}
+
+/// Verifies a proof returned by `fil_seal`.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn fil_verify_seal(
+    registered_proof: u64,
+    comm_r: *const [u8; 32],
+    comm_d: *const [u8; 32],
+    prover_id: *const [u8; 32],
+    sector_id: u64,
+    ticket: *const [u8; 32],
+    proof_ptr: *const u8,
+    proof_len: usize,
+) -> *mut FFIVerifySealResponse {
+    respond(|| {
+        let registered_proof = seal_proof(registered_proof)?;
+
+        let is_valid = api::verify_seal(
+            registered_proof.into(),
+            read_bytes32(comm_r, "comm_r")?,
+            read_bytes32(comm_d, "comm_d")?,
+            read_bytes32(prover_id, "prover_id")?,
+            SectorId::from(sector_id),
+            read_bytes32(ticket, "ticket")?,
+            slice(proof_ptr, proof_len, "proof")?,
+        )?;
+
+        let mut response = FFIVerifySealResponse::default();
+        response.is_valid = is_valid;
+
+        Ok(response)
+    })
+}
+
+/// Unseals the sector at `sealed_path` and writes `num_bytes` unpadded bytes of it, starting at
+/// the unpadded `offset`, to `output_path`.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn fil_unseal(
+    registered_proof: u64,
+    sealed_path: *const c_char,
+    output_path: *const c_char,
+    prover_id: *const [u8; 32],
+    sector_id: u64,
+    comm_d: *const [u8; 32],
+    ticket: *const [u8; 32],
+    offset: u64,
+    num_bytes: u64,
+) -> *mut FFIUnsealResponse {
+    respond(|| {
+        let registered_proof = seal_proof(registered_proof)?;
+
+        let written = api::get_unsealed_range(
+            registered_proof.into(),
+            c_str_to_path(sealed_path, "sealed_path")?,
+            c_str_to_path(output_path, "output_path")?,
+            read_bytes32(prover_id, "prover_id")?,
+            SectorId::from(sector_id),
+            read_bytes32(comm_d, "comm_d")?,
+            read_bytes32(ticket, "ticket")?,
+            UnpaddedByteIndex(offset),
+            UnpaddedBytesAmount(num_bytes),
+        )?;
+
+        let mut response = FFIUnsealResponse::default();
+        response.num_bytes_written = u64::from(written);
+
+        Ok(response)
+    })
+}
+
+/// Generates a PoSt over the given replicas.
+#[no_mangle]
+pub unsafe extern "C" fn fil_generate_post(
+    registered_proof: u64,
+    challenge_seed: *const [u8; 32],
+    replicas_ptr: *const FFIPrivateReplicaInfo,
+    replicas_len: usize,
+) -> *mut FFIGeneratePoStResponse {
+    respond(|| {
+        let registered_proof = post_proof(registered_proof)?;
+        let challenge_seed = read_bytes32(challenge_seed, "challenge_seed")?;
+
+        let mut replicas = BTreeMap::new();
+        for replica in slice(replicas_ptr, replicas_len, "replicas")? {
+            let access = c_str_to_path(replica.replica_path, "replica_path")?
+                .to_string_lossy()
+                .into_owned();
+            let aux = api::PersistentAux {
+                comm_c: PedersenDomain::try_from_bytes(&replica.comm_c)?,
+                comm_r_last: PedersenDomain::try_from_bytes(&replica.comm_r_last)?,
+            };
+            let info = if replica.is_fault {
+                api::PrivateReplicaInfo::new_faulty(access, replica.comm_r, aux)
+            } else {
+                api::PrivateReplicaInfo::new(access, replica.comm_r, aux)
+            };
+
+            insert_replica(&mut replicas, replica.sector_id, info)?;
+        }
+
+        let proof = api::generate_post(registered_proof.into(), &challenge_seed, &replicas)?;
+
+        let mut response = FFIGeneratePoStResponse::default();
+        let (proof_ptr, proof_len) = into_raw_parts(proof);
+        response.proof_ptr = proof_ptr;
+        response.proof_len = proof_len;
+
+        Ok(response)
+    })
+}
+
+/// Verifies a proof returned by `fil_generate_post`.
+#[no_mangle]
+pub unsafe extern "C" fn fil_verify_post(
+    registered_proof: u64,
+    challenge_seed: *const [u8; 32],
+    replicas_ptr: *const FFIPublicReplicaInfo,
+    replicas_len: usize,
+    proof_ptr: *const u8,
+    proof_len: usize,
+) -> *mut FFIVerifyPoStResponse {
+    respond(|| {
+        let registered_proof = post_proof(registered_proof)?;
+        let challenge_seed = read_bytes32(challenge_seed, "challenge_seed")?;
+
+        let mut replicas = BTreeMap::new();
+        for replica in slice(replicas_ptr, replicas_len, "replicas")? {
+            let info = if replica.is_fault {
+                api::PublicReplicaInfo::new_faulty(replica.comm_r)
+            } else {
+                api::PublicReplicaInfo::new(replica.comm_r)
+            };
+
+            insert_replica(&mut replicas, replica.sector_id, info)?;
+        }
+
+        let is_valid = api::verify_post(
+            registered_proof.into(),
+            &challenge_seed,
+            slice(proof_ptr, proof_len, "proof")?,
+            &replicas,
+        )?;
+
+        let mut response = FFIVerifyPoStResponse::default();
+        response.is_valid = is_valid;
+
+        Ok(response)
+    })
+}
+
+macro_rules! destructor {
+    ($name:ident, $response:ty) => {
+        /// Frees a response and everything it points to. Null is ignored.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(response: *mut $response) {
+            if !response.is_null() {
+                drop(Box::from_raw(response));
+            }
+        }
+    };
+}
+
+destructor!(fil_destroy_seal_response, FFISealResponse);
+destructor!(fil_destroy_seal_pre_commit_response, FFISealPreCommitResponse);
+destructor!(fil_destroy_verify_seal_response, FFIVerifySealResponse);
+destructor!(fil_destroy_unseal_response, FFIUnsealResponse);
+destructor!(fil_destroy_generate_post_response, FFIGeneratePoStResponse);
+destructor!(fil_destroy_verify_post_response, FFIVerifyPoStResponse);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::CString;
+    use std::ptr;
+
+    unsafe fn error_msg(error_msg: *const c_char) -> String {
+        CStr::from_ptr(error_msg).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_caller_errors() {
+        unsafe {
+            let bytes = [0u8; 32];
+            let proof = [0u8; 4];
+
+            let response = fil_verify_seal(
+                RegisteredSealProof::ALL.len() as u64,
+                &bytes,
+                &bytes,
+                &bytes,
+                1,
+                &bytes,
+                proof.as_ptr(),
+                proof.len(),
+            );
+            assert_eq!((*response).status_code, FFIResponseStatus::CallerError);
+            assert!(!(*response).error_msg.is_null());
+            assert!(!(*response).is_valid);
+            fil_destroy_verify_seal_response(response);
+
+            let output_path = CString::new("/tmp/unsealed").unwrap();
+            let response = fil_unseal(
+                0,
+                ptr::null(),
+                output_path.as_ptr(),
+                &bytes,
+                1,
+                &bytes,
+                &bytes,
+                0,
+                1,
+            );
+            assert_eq!((*response).status_code, FFIResponseStatus::CallerError);
+            assert_eq!(error_msg((*response).error_msg), "sealed_path is null");
+            fil_destroy_unseal_response(response);
+
+            let response = fil_verify_post(0, &bytes, ptr::null(), 1, proof.as_ptr(), 4);
+            assert_eq!((*response).status_code, FFIResponseStatus::CallerError);
+            assert_eq!(error_msg((*response).error_msg), "replicas is null");
+            fil_destroy_verify_post_response(response);
+
+            let replica = FFIPublicReplicaInfo {
+                sector_id: 1,
+                comm_r: bytes,
+                is_fault: false,
+            };
+            let replicas = [replica, replica];
+            let response = fil_verify_post(
+                0,
+                &bytes,
+                replicas.as_ptr(),
+                replicas.len(),
+                proof.as_ptr(),
+                proof.len(),
+            );
+            assert_eq!((*response).status_code, FFIResponseStatus::CallerError);
+            assert_eq!(error_msg((*response).error_msg), "duplicate sector id 1");
+            fil_destroy_verify_post_response(response);
+
+            let response = fil_seal_commit(0, &bytes, 1, &bytes, proof.as_ptr(), proof.len());
+            assert_eq!((*response).status_code, FFIResponseStatus::CallerError);
+            assert!((*response).proof_ptr.is_null());
+            fil_destroy_seal_response(response);
+        }
+    }
+
+    #[test]
+    fn test_responses_free_their_data() {
+        let mut response = FFIGeneratePoStResponse::default();
+        let (proof_ptr, proof_len) = into_raw_parts(vec![1u8, 2, 3]);
+        response.proof_ptr = proof_ptr;
+        response.proof_len = proof_len;
+        response.set_error(FFIResponseStatus::ReceiverError, "with\0NUL".to_string());
+
+        unsafe {
+            assert_eq!(error_msg(response.error_msg), "with");
+            fil_destroy_generate_post_response(Box::into_raw(Box::new(response)));
+            fil_destroy_seal_response(ptr::null_mut());
+        }
+    }
+}
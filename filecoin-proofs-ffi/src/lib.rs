@@ -0,0 +1,19 @@
+//! A C API to seal, unseal, prove and verify with `filecoin-proofs`.
+//!
+//! Every function returns a pointer to a response, allocated here, which the caller must free with
+//! the matching `fil_destroy_*` function. Responses carry a status code and, on failure, an error
+//! message; errors and panics never cross the API. Proofs are chosen by their index into
+//! `RegisteredSealProof::ALL` and `RegisteredPoStProof::ALL`. The declarations for C are in
+//! `include/filecoin_proofs.h`.
+
+#![deny(clippy::all, clippy::perf, clippy::correctness)]
+
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate log;
+
+mod api;
+pub mod types;
+
+pub use api::*;
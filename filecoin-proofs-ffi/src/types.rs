@@ -0,0 +1,229 @@
+//! The structs passed across the C API. Every response is allocated by the API and must be freed
+//! with its `fil_destroy_*` function, which also frees everything it points to.
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FFIResponseStatus {
+    NoError = 0,
+    /// The arguments were invalid, e.g. a null pointer or an unknown proof.
+    CallerError = 1,
+    /// The operation itself failed.
+    ReceiverError = 2,
+}
+
+/// A private replica, as passed to `fil_generate_post`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FFIPrivateReplicaInfo {
+    pub sector_id: u64,
+    pub comm_r: [u8; 32],
+    pub comm_c: [u8; 32],
+    pub comm_r_last: [u8; 32],
+    /// The path of the sealed replica, a NUL terminated UTF-8 string.
+    pub replica_path: *const c_char,
+    pub is_fault: bool,
+}
+
+/// A public replica, as passed to `fil_verify_post`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FFIPublicReplicaInfo {
+    pub sector_id: u64,
+    pub comm_r: [u8; 32],
+    pub is_fault: bool,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFISealResponse {
+    pub status_code: FFIResponseStatus,
+    pub error_msg: *const c_char,
+    pub comm_r: [u8; 32],
+    pub comm_d: [u8; 32],
+    pub comm_c: [u8; 32],
+    pub comm_r_last: [u8; 32],
+    pub proof_ptr: *const u8,
+    pub proof_len: usize,
+    /// The commitments of the pieces, in order.
+    pub comm_ps_ptr: *const [u8; 32],
+    pub comm_ps_len: usize,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFISealPreCommitResponse {
+    pub status_code: FFIResponseStatus,
+    pub error_msg: *const c_char,
+    pub comm_r: [u8; 32],
+    pub comm_d: [u8; 32],
+    /// The opaque encoding of the pre-commit, to be passed to `fil_seal_commit`.
+    pub pre_commit_ptr: *const u8,
+    pub pre_commit_len: usize,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFIVerifySealResponse {
+    pub status_code: FFIResponseStatus,
+    pub error_msg: *const c_char,
+    pub is_valid: bool,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFIUnsealResponse {
+    pub status_code: FFIResponseStatus,
+    pub error_msg: *const c_char,
+    /// The number of unpadded bytes written to the output file.
+    pub num_bytes_written: u64,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFIGeneratePoStResponse {
+    pub status_code: FFIResponseStatus,
+    pub error_msg: *const c_char,
+    pub proof_ptr: *const u8,
+    pub proof_len: usize,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFIVerifyPoStResponse {
+    pub status_code: FFIResponseStatus,
+    pub error_msg: *const c_char,
+    pub is_valid: bool,
+}
+
+/// The parts every response shares.
+pub trait Response: Default {
+    fn set_error(&mut self, status_code: FFIResponseStatus, error_msg: String);
+}
+
+macro_rules! impl_response {
+    ($response:ty { $($field:ident: $default:expr),* $(,)? }) => {
+        impl Default for $response {
+            fn default() -> Self {
+                Self {
+                    status_code: FFIResponseStatus::NoError,
+                    error_msg: ptr::null(),
+                    $($field: $default),*
+                }
+            }
+        }
+
+        impl Response for $response {
+            fn set_error(&mut self, status_code: FFIResponseStatus, error_msg: String) {
+                // An interior NUL would truncate the message, so it is cut off there.
+                let error_msg = error_msg.split('\0').next().unwrap_or("");
+
+                self.status_code = status_code;
+                self.error_msg = CString::new(error_msg)
+                    .expect("no interior NUL")
+                    .into_raw();
+            }
+        }
+    };
+}
+
+impl_response!(FFISealResponse {
+    comm_r: [0; 32],
+    comm_d: [0; 32],
+    comm_c: [0; 32],
+    comm_r_last: [0; 32],
+    proof_ptr: ptr::null(),
+    proof_len: 0,
+    comm_ps_ptr: ptr::null(),
+    comm_ps_len: 0,
+});
+impl_response!(FFISealPreCommitResponse {
+    comm_r: [0; 32],
+    comm_d: [0; 32],
+    pre_commit_ptr: ptr::null(),
+    pre_commit_len: 0,
+});
+impl_response!(FFIVerifySealResponse { is_valid: false });
+impl_response!(FFIUnsealResponse {
+    num_bytes_written: 0
+});
+impl_response!(FFIGeneratePoStResponse {
+    proof_ptr: ptr::null(),
+    proof_len: 0,
+});
+impl_response!(FFIVerifyPoStResponse { is_valid: false });
+
+/// Hands the elements of `vec` over to C, see `free_raw_parts`.
+pub fn into_raw_parts<T>(vec: Vec<T>) -> (*const T, usize) {
+    let len = vec.len();
+    if len == 0 {
+        return (ptr::null(), 0);
+    }
+
+    (Box::into_raw(vec.into_boxed_slice()) as *const T, len)
+}
+
+/// Frees elements handed over by `into_raw_parts`.
+unsafe fn free_raw_parts<T>(ptr: *const T, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(std::slice::from_raw_parts_mut(
+            ptr as *mut T,
+            len,
+        )));
+    }
+}
+
+unsafe fn free_error_msg(error_msg: *const c_char) {
+    if !error_msg.is_null() {
+        drop(CString::from_raw(error_msg as *mut c_char));
+    }
+}
+
+impl Drop for FFISealResponse {
+    fn drop(&mut self) {
+        unsafe {
+            free_error_msg(self.error_msg);
+            free_raw_parts(self.proof_ptr, self.proof_len);
+            free_raw_parts(self.comm_ps_ptr, self.comm_ps_len);
+        }
+    }
+}
+
+impl Drop for FFISealPreCommitResponse {
+    fn drop(&mut self) {
+        unsafe {
+            free_error_msg(self.error_msg);
+            free_raw_parts(self.pre_commit_ptr, self.pre_commit_len);
+        }
+    }
+}
+
+impl Drop for FFIVerifySealResponse {
+    fn drop(&mut self) {
+        unsafe { free_error_msg(self.error_msg) }
+    }
+}
+
+impl Drop for FFIUnsealResponse {
+    fn drop(&mut self) {
+        unsafe { free_error_msg(self.error_msg) }
+    }
+}
+
+impl Drop for FFIGeneratePoStResponse {
+    fn drop(&mut self) {
+        unsafe {
+            free_error_msg(self.error_msg);
+            free_raw_parts(self.proof_ptr, self.proof_len);
+        }
+    }
+}
+
+impl Drop for FFIVerifyPoStResponse {
+    fn drop(&mut self) {
+        unsafe { free_error_msg(self.error_msg) }
+    }
+}
@@ -57,6 +57,7 @@ fn do_the_work<H: Hasher>(data_size: usize, challenge_count: usize) {
             degree: m,
             expansion_degree: 0,
             seed: new_seed(),
+            graph_config: GraphConfig::default(),
         },
         private: true,
         challenges_count: challenge_count,
@@ -70,7 +71,8 @@ fn do_the_work<H: Hasher>(data_size: usize, challenge_count: usize) {
 
     info!("running replicate");
     let (tau, aux) =
-        DrgPoRep::<H, _>::replicate(&pp, &replica_id.into(), &mut mmapped, None).unwrap();
+        DrgPoRep::<H, _>::replicate(&pp, &replica_id.into(), (&mut mmapped[..]).into(), None)
+            .unwrap();
 
     let pub_inputs = PublicInputs::<H::Domain> {
         replica_id: Some(replica_id.into()),
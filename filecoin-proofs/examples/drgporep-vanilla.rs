@@ -69,6 +69,7 @@ fn do_the_work<H: Hasher>(data_size: usize, challenge_count: usize) {
             degree: m,
             expansion_degree: 0,
             seed: new_seed(),
+            graph_config: GraphConfig::default(),
         },
         private: true,
         challenges_count: challenge_count,
@@ -86,7 +87,7 @@ fn do_the_work<H: Hasher>(data_size: usize, challenge_count: usize) {
 
     start_profile("replicate");
     let (tau, aux) =
-        DrgPoRep::<H, _>::replicate(&pp, &replica_id, data.as_mut_slice(), None).unwrap();
+        DrgPoRep::<H, _>::replicate(&pp, &replica_id, data.as_mut_slice().into(), None).unwrap();
     stop_profile();
     let pub_inputs = PublicInputs {
         replica_id: Some(replica_id),
@@ -21,11 +21,11 @@ use std::time::{Duration, Instant};
 use std::u32;
 
 use bellperson::Circuit;
-use fil_sapling_crypto::jubjub::JubjubBls12;
 
 use storage_proofs::circuit::metric::*;
 use storage_proofs::circuit::stacked::StackedCompound;
 use storage_proofs::compound_proof::{self, CompoundProof};
+use storage_proofs::crypto::pedersen::jubjub_params;
 use storage_proofs::drgporep;
 use storage_proofs::drgraph::*;
 use storage_proofs::example_helper::prettyb;
@@ -35,8 +35,9 @@ use storage_proofs::porep::PoRep;
 use storage_proofs::proof::ProofScheme;
 use storage_proofs::settings;
 use storage_proofs::stacked::{
-    self, ChallengeRequirements, LayerChallenges, StackedDrg, EXP_DEGREE,
+    self, ChallengeRequirements, CommRHasher, LayerChallenges, StackedDrg, EXP_DEGREE,
 };
+use storage_proofs::util::NODE_SIZE;
 
 // We can only one of the profilers at a time, either CPU (`profile`)
 // or memory (`heap-profile`), duplicating the function so they won't
@@ -178,8 +179,12 @@ fn do_the_work<H: 'static>(
             degree: m,
             expansion_degree,
             seed: new_seed(),
+            graph_config: GraphConfig::default(),
         },
         layer_challenges: layer_challenges.clone(),
+        porep_id: rng.gen(),
+        comm_r_hasher: CommRHasher::Pedersen,
+        node_size: NODE_SIZE,
     };
 
     info!("running setup");
@@ -203,7 +208,7 @@ fn do_the_work<H: 'static>(
 
         start_profile("replicate");
         let (tau, (p_aux, t_aux)) =
-            StackedDrg::<H>::replicate(&pp, &replica_id, &mut data, None).unwrap();
+            StackedDrg::<H>::replicate(&pp, &replica_id, (&mut data[..]).into(), None).unwrap();
         stop_profile();
         let pub_inputs = stacked::PublicInputs::<H::Domain> {
             replica_id,
@@ -282,17 +287,17 @@ fn do_the_work<H: 'static>(
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let engine_params = JubjubBls12::new_with_window_size(window_size);
+        let engine_params = jubjub_params(window_size);
         let compound_public_params = compound_proof::PublicParams {
             vanilla_params: pp.clone(),
-            engine_params: &engine_params,
+            engine_params,
             partitions: Some(partitions),
         };
         if circuit || bench {
             info!("Performing circuit bench.");
             let mut cs = MetricCS::<Bls12>::new();
 
-            StackedCompound::blank_circuit(&pp, &engine_params)
+            StackedCompound::blank_circuit(&pp, engine_params)
                 .synthesize(&mut cs)
                 .expect("failed to synthesize circuit");
 
@@ -317,7 +322,7 @@ fn do_the_work<H: 'static>(
             info!("Performing circuit groth.");
             let gparams = StackedCompound::groth_params(
                 &compound_public_params.vanilla_params,
-                &engine_params,
+                engine_params,
             )
             .unwrap();
 
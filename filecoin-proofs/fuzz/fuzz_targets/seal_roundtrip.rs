@@ -0,0 +1,109 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+use filecoin_proofs::pieces::{add_piece, compute_comm_d, sum_piece_bytes_with_alignment};
+use filecoin_proofs::{PaddedBytesAmount, SectorSize, UnpaddedBytesAmount};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use storage_proofs::drgporep::DrgParams;
+use storage_proofs::drgraph::{new_seed_from, GraphConfig, BASE_DEGREE};
+use storage_proofs::hasher::pedersen::PedersenDomain;
+use storage_proofs::hasher::{Domain, PedersenHasher};
+use storage_proofs::porep::PoRep;
+use storage_proofs::proof::ProofScheme;
+use storage_proofs::stacked::{
+    CommRHasher, LayerChallenges, PrivateInputs, PublicInputs, SetupParams, StackedDrg, EXP_DEGREE,
+};
+use storage_proofs::util::NODE_SIZE;
+
+type Drg<'a> = StackedDrg<'a, PedersenHasher>;
+
+// The input is a 16 byte rng seed, a byte choosing a sector size of 1KiB to 8KiB, and then two
+// bytes for the size of each piece, until the sector is full. Every layout is staged with
+// `add_piece`, sealed, proven and verified, and the replica decoded again.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 17 {
+        return;
+    }
+
+    let mut seed = [0u32; 4];
+    for (word, bytes) in seed.iter_mut().zip(data[..16].chunks(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    // XorShiftRng rejects an all zero seed.
+    seed[0] |= 1;
+    let rng = &mut XorShiftRng::from_seed(seed);
+
+    let sector_size = SectorSize(1024 << (data[16] % 4));
+    let sector_bytes = u64::from(PaddedBytesAmount::from(sector_size));
+    let sector_piece_bytes = u64::from(UnpaddedBytesAmount::from(sector_size));
+
+    let mut staged = Vec::new();
+    let mut piece_lengths = Vec::new();
+    let mut piece_infos = Vec::new();
+    for size in data[17..].chunks(2).filter(|size| size.len() == 2) {
+        let piece_size = UnpaddedBytesAmount(
+            1 + u64::from(u16::from_le_bytes([size[0], size[1]])) % sector_piece_bytes,
+        );
+
+        let mut lengths = piece_lengths.clone();
+        lengths.push(piece_size);
+        if u64::from(sum_piece_bytes_with_alignment(&lengths)) > sector_piece_bytes {
+            break;
+        }
+
+        let piece: Vec<u8> = (0..u64::from(piece_size)).map(|_| rng.gen()).collect();
+        let piece_info = add_piece(&piece[..], &mut staged, piece_size, &piece_lengths)
+            .expect("failed to add piece");
+        piece_infos.push(piece_info);
+        piece_lengths = lengths;
+    }
+    staged.resize(sector_bytes as usize, 0);
+
+    let comm_d = compute_comm_d(sector_size, &piece_infos).expect("failed to compute comm_d");
+
+    let sp = SetupParams {
+        drg: DrgParams {
+            nodes: sector_bytes as usize / NODE_SIZE,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            seed: new_seed_from(rng),
+            graph_config: GraphConfig::default(),
+        },
+        layer_challenges: LayerChallenges::new(2, 4),
+        porep_id: rng.gen(),
+        comm_r_hasher: CommRHasher::default(),
+        node_size: NODE_SIZE,
+    };
+    let pp = Drg::setup(&sp).expect("setup failed");
+
+    let replica_id: PedersenDomain = rng.gen();
+    let mut replica = staged.clone();
+    let (tau, (p_aux, t_aux)) =
+        Drg::replicate(&pp, &replica_id, replica.as_mut_slice().into(), None)
+            .expect("replication failed");
+    assert_eq!(
+        tau.comm_d.into_bytes(),
+        comm_d.to_vec(),
+        "comm_d of the replica differs from comm_d of the pieces"
+    );
+
+    let pub_inputs = PublicInputs {
+        replica_id,
+        seed: None,
+        tau: Some(tau),
+        k: None,
+    };
+    let priv_inputs = PrivateInputs { p_aux, t_aux };
+
+    let proofs = Drg::prove_all_partitions(&pp, &pub_inputs, &priv_inputs, 1)
+        .expect("failed to generate partition proofs");
+    assert!(Drg::verify_all_partitions(&pp, &pub_inputs, &proofs)
+        .expect("failed to verify partition proofs"));
+
+    let extracted = Drg::extract_all(&pp, &replica_id, &replica).expect("extraction failed");
+    assert_eq!(
+        extracted, staged,
+        "the replica does not decode to the staged data"
+    );
+});
@@ -0,0 +1,300 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use storage_proofs::cancel::CancellationToken;
+
+use crate::error;
+
+/// A seal running on its own thread under its own `CancellationToken`, so it can be aborted
+/// while it runs, see `abort`.
+#[derive(Debug)]
+pub struct RunningSeal<T> {
+    token: CancellationToken,
+    thread: JoinHandle<T>,
+}
+
+impl<T: Send + 'static> RunningSeal<T> {
+    /// Runs `seal`, e.g. a closure calling `seal`, on a new thread.
+    pub fn spawn<F: FnOnce() -> T + Send + 'static>(seal: F) -> Self {
+        let token = CancellationToken::new();
+        let thread = {
+            let token = token.clone();
+            thread::spawn(move || token.install(seal))
+        };
+
+        RunningSeal { token, thread }
+    }
+
+    /// Waits for the seal to finish, and returns its result.
+    pub fn join(self) -> thread::Result<T> {
+        self.thread.join()
+    }
+
+    /// Cancels the seal, waits for it to stop, and only then removes its files with
+    /// `abort_seal`, so they are never removed from under it.
+    pub fn abort<C: AsRef<Path>, R: AsRef<Path>>(
+        self,
+        cache_dir: C,
+        replica_path: R,
+    ) -> error::Result<u64> {
+        self.token.cancel();
+        if self.thread.join().is_err() {
+            warn!("aborted seal panicked");
+        }
+
+        abort_seal(cache_dir, replica_path)
+    }
+}
+
+/// Abandons a sector, removing the (possibly partial) replica at `replica_path` and everything
+/// below `cache_dir`, and returns the number of bytes reclaimed.
+///
+/// The cache directory is first renamed out of the way, so it disappears at once even if
+/// removing its contents fails halfway. Renamed directories left behind by earlier aborts of the
+/// same sector are removed as well. Missing paths are treated as already cleaned up, which
+/// makes the call safe to repeat. To guard against removing the wrong thing, `cache_dir` has to
+/// be a directory and `replica_path` a regular file, or either a symlink, which is removed but
+/// never followed.
+///
+/// This must not be called while `seal` is still running for the same sector. To abort a seal
+/// while it runs, run it as a `RunningSeal` and call `RunningSeal::abort`.
+///
+pub fn abort_seal<C: AsRef<Path>, R: AsRef<Path>>(
+    cache_dir: C,
+    replica_path: R,
+) -> error::Result<u64> {
+    let cache_dir = cache_dir.as_ref();
+    let replica_path = replica_path.as_ref();
+
+    let cache_type = fs::symlink_metadata(cache_dir).map(|m| m.file_type()).ok();
+    if let Some(file_type) = cache_type {
+        ensure!(
+            file_type.is_dir() || file_type.is_symlink(),
+            "cache dir {} is not a directory",
+            cache_dir.display()
+        );
+    }
+    let replica_metadata = fs::symlink_metadata(replica_path).ok();
+    if let Some(ref metadata) = replica_metadata {
+        let file_type = metadata.file_type();
+        ensure!(
+            file_type.is_file() || file_type.is_symlink(),
+            "replica {} is not a regular file",
+            replica_path.display()
+        );
+    }
+
+    let mut reclaimed = 0;
+
+    if let Some(metadata) = replica_metadata {
+        fs::remove_file(replica_path)?;
+        if metadata.file_type().is_file() {
+            reclaimed += metadata.len();
+        }
+        info!("removed replica {}", replica_path.display());
+    }
+
+    match cache_type {
+        Some(file_type) if file_type.is_symlink() => {
+            fs::remove_file(cache_dir)?;
+            info!("removed cache dir link {}", cache_dir.display());
+        }
+        Some(_) => {
+            let doomed = aborted_path(cache_dir);
+            fs::rename(cache_dir, &doomed)?;
+
+            let size = dir_size(&doomed)?;
+            fs::remove_dir_all(&doomed)?;
+            reclaimed += size;
+            info!("removed cache dir {}", cache_dir.display());
+        }
+        None => {}
+    }
+
+    reclaimed += sweep_aborted(cache_dir)?;
+
+    Ok(reclaimed)
+}
+
+/// Removes the renamed cache dirs of earlier aborts of the sector at `cache_dir`, see
+/// `aborted_path`, which were interrupted before their removal finished.
+fn sweep_aborted(cache_dir: &Path) -> error::Result<u64> {
+    let name = match cache_dir.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Ok(0),
+    };
+    let parent = match cache_dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.aborted-", name);
+
+    let entries = match fs::read_dir(parent) {
+        Ok(entries) => entries,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut reclaimed = 0;
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(&prefix) {
+            continue;
+        }
+
+        // Links are never followed, like the cache dir itself.
+        let path = entry.path();
+        if !fs::symlink_metadata(&path)?.is_dir() {
+            continue;
+        }
+
+        reclaimed += dir_size(&path)?;
+        fs::remove_dir_all(&path)?;
+        info!("removed stale {}", path.display());
+    }
+
+    Ok(reclaimed)
+}
+
+/// A sibling of `dir`, which can be reached by a rename on the same file system.
+fn aborted_path(dir: &Path) -> PathBuf {
+    let mut name = dir
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(format!(".aborted-{}", std::process::id()));
+
+    dir.with_file_name(name)
+}
+
+fn dir_size(dir: &Path) -> error::Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = fs::symlink_metadata(entry.path())?;
+
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_seal() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let replica_path = dir.path().join("replica");
+
+        fs::create_dir_all(cache_dir.join("layers")).unwrap();
+        fs::write(cache_dir.join("tree-d"), vec![0; 64]).unwrap();
+        fs::write(cache_dir.join("layers").join("layer-1"), vec![0; 128]).unwrap();
+        fs::write(&replica_path, vec![0; 256]).unwrap();
+
+        let reclaimed = abort_seal(&cache_dir, &replica_path).expect("abort_seal failed");
+        assert_eq!(reclaimed, 64 + 128 + 256);
+        assert!(!cache_dir.exists());
+        assert!(!replica_path.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        // Aborting again is a no-op.
+        assert_eq!(abort_seal(&cache_dir, &replica_path).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_abort_seal_sweeps_stale_aborts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let stale = dir.path().join("cache.aborted-1");
+        let other = dir.path().join("other.aborted-1");
+
+        fs::create_dir(&stale).unwrap();
+        fs::write(stale.join("tree-d"), vec![0; 64]).unwrap();
+        fs::create_dir(&other).unwrap();
+
+        let reclaimed = abort_seal(&cache_dir, dir.path().join("replica")).unwrap();
+        assert_eq!(reclaimed, 64);
+        assert!(!stale.exists());
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn test_running_seal_abort() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let replica_path = dir.path().join("replica");
+        fs::create_dir(&cache_dir).unwrap();
+        fs::write(&replica_path, vec![0; 256]).unwrap();
+
+        // Keeps writing to its cache dir until it is cancelled.
+        let seal = {
+            let cache_dir = cache_dir.clone();
+            RunningSeal::spawn(move || -> storage_proofs::error::Result<()> {
+                loop {
+                    storage_proofs::cancel::check()?;
+                    fs::write(cache_dir.join("layer"), vec![0; 16])?;
+                    thread::sleep(std::time::Duration::from_millis(1));
+                }
+            })
+        };
+
+        seal.abort(&cache_dir, &replica_path).unwrap();
+        assert!(!cache_dir.exists());
+        assert!(!replica_path.exists());
+    }
+
+    #[test]
+    fn test_abort_seal_rejects_wrong_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, vec![0; 16]).unwrap();
+
+        assert!(abort_seal(&file, dir.path().join("missing")).is_err());
+        assert!(abort_seal(dir.path().join("missing"), dir.path()).is_err());
+        assert!(file.exists());
+
+        #[cfg(unix)]
+        {
+            let socket = dir.path().join("socket");
+            let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+            assert!(abort_seal(dir.path().join("missing"), &socket).is_err());
+            assert!(socket.exists());
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_abort_seal_removes_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let real_cache_dir = dir.path().join("real-cache");
+        let real_replica = dir.path().join("real-replica");
+        fs::create_dir(&real_cache_dir).unwrap();
+        fs::write(real_cache_dir.join("tree-d"), vec![0; 64]).unwrap();
+        fs::write(&real_replica, vec![0; 256]).unwrap();
+
+        let cache_dir = dir.path().join("cache");
+        let replica_path = dir.path().join("replica");
+        symlink(&real_cache_dir, &cache_dir).unwrap();
+        symlink(&real_replica, &replica_path).unwrap();
+
+        assert_eq!(abort_seal(&cache_dir, &replica_path).unwrap(), 0);
+        assert!(fs::symlink_metadata(&cache_dir).is_err());
+        assert!(fs::symlink_metadata(&replica_path).is_err());
+
+        // The targets are left alone.
+        assert!(real_cache_dir.join("tree-d").exists());
+        assert!(real_replica.exists());
+    }
+}
@@ -0,0 +1,427 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use merkletree::hash::Algorithm;
+use rand::{thread_rng, Rng};
+use storage_proofs::hasher::pedersen::{PedersenDomain, PedersenFunction};
+use storage_proofs::hasher::Domain;
+use storage_proofs::merkle::disk_tree_leafs;
+use storage_proofs::stacked::TREE_R_LAST_FILE;
+use storage_proofs::util::NODE_SIZE;
+
+use crate::api::{as_safe_commitment, Commitment, PersistentAux};
+use crate::error;
+use crate::parameters::{post_setup_params, public_params};
+use crate::replica_file::ReplicaFile;
+use crate::types::{PaddedBytesAmount, PoStConfig};
+
+/// A problem found by `check_sector`, which would make a PoSt over the sector fail or cost more
+/// than it should.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SectorFault {
+    /// A file of the sector does not exist.
+    MissingFile(PathBuf),
+    /// A file of the sector exists, but does not have the size of the sector, or its replica
+    /// header does not match the sector.
+    MalformedFile(PathBuf),
+    /// The replica node, or its path in `tree_r_last`, does not lead to `comm_r_last`.
+    CorruptNode(u64),
+    /// `comm_r` is not the commitment to `comm_c` and `comm_r_last`.
+    CommitmentMismatch,
+}
+
+/// The outcome of `check_sector`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SectorHealth {
+    /// The replica nodes which were checked.
+    pub checked_nodes: Vec<u64>,
+    /// Every fault found, in the order they were found.
+    pub faults: Vec<SectorFault>,
+}
+
+impl SectorHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.faults.is_empty()
+    }
+}
+
+/// Checks that the sealed sector at `replica_path`, with the `tree_r_last` stored in `cache_dir`,
+/// can still be proven against `comm_r`, so faults can be declared before a PoSt deadline
+/// rather than discovered by a failing PoSt.
+///
+/// As many nodes as a PoSt challenges are sampled at random. Each is read from the replica and
+/// its path through the stored `tree_r_last` is recomputed up to `comm_r_last`.
+///
+/// Besides the sector's files and `comm_r`, this takes the `post_config` it is proven with,
+/// which gives its size and the number of sampled nodes, and its `aux`, whose `comm_r_last` the
+/// paths must lead to and which must hash to `comm_r` along with `comm_c`.
+///
+/// Errors are returned when the check itself cannot run, which includes a missing or malformed
+/// `tree_r_last`, as rebuilding it means hashing the whole replica. Problems with the replica
+/// and the commitments are reported as faults.
+pub fn check_sector<R: AsRef<Path>, C: AsRef<Path>>(
+    post_config: PoStConfig,
+    replica_path: R,
+    cache_dir: C,
+    comm_r: Commitment,
+    aux: &PersistentAux,
+) -> error::Result<SectorHealth> {
+    check_sector_with_rng(
+        post_config,
+        replica_path,
+        cache_dir,
+        comm_r,
+        aux,
+        &mut thread_rng(),
+    )
+}
+
+/// `check_sector`, with the checked nodes drawn from `rng` rather than the thread's rng, so that
+/// a check can be reproduced.
+pub fn check_sector_with_rng<R: AsRef<Path>, C: AsRef<Path>, G: Rng>(
+    post_config: PoStConfig,
+    replica_path: R,
+    cache_dir: C,
+    comm_r: Commitment,
+    aux: &PersistentAux,
+    rng: &mut G,
+) -> error::Result<SectorHealth> {
+    let sector_size = u64::from(PaddedBytesAmount::from(post_config));
+    let nodes = sector_size / NODE_SIZE as u64;
+
+    let challenges_count = post_setup_params(post_config).challenges_count;
+    let checked_nodes: Vec<u64> = (0..challenges_count)
+        .map(|_| rng.gen_range(0, nodes))
+        .collect();
+
+    check_sector_nodes(
+        post_config,
+        replica_path.as_ref(),
+        cache_dir.as_ref(),
+        comm_r,
+        aux,
+        checked_nodes,
+    )
+}
+
+/// `check_sector` of the given nodes. Takes the `post_config` and `aux` of the sector besides its
+/// files and `comm_r`, see `check_sector`.
+fn check_sector_nodes(
+    post_config: PoStConfig,
+    replica_path: &Path,
+    cache_dir: &Path,
+    comm_r: Commitment,
+    aux: &PersistentAux,
+    checked_nodes: Vec<u64>,
+) -> error::Result<SectorHealth> {
+    let sector_bytes = PaddedBytesAmount::from(post_config);
+    let sector_size = u64::from(sector_bytes);
+    let pp = public_params(sector_bytes, 1);
+
+    let mut faults = Vec::new();
+
+    let expected_comm_r: PedersenDomain = pp.comm_r_hasher.hash(aux.comm_c, aux.comm_r_last).into();
+    if as_safe_commitment(&comm_r, "comm_r")? != expected_comm_r {
+        faults.push(SectorFault::CommitmentMismatch);
+    }
+
+    let replica = match ReplicaFile::open(replica_path, sector_bytes, Some(&comm_r)) {
+        Ok(replica) => Some(replica),
+        Err(_) if !replica_path.exists() => {
+            faults.push(SectorFault::MissingFile(replica_path.to_path_buf()));
+            None
+        }
+        Err(err) => {
+            info!("not using the replica: {}", err);
+            faults.push(SectorFault::MalformedFile(replica_path.to_path_buf()));
+            None
+        }
+    };
+    if !faults.is_empty() {
+        // Nothing can be checked against a replica which is missing or does not match comm_r.
+        return Ok(SectorHealth {
+            checked_nodes,
+            faults,
+        });
+    }
+    let mut replica = replica.expect("a replica which failed to open is a fault");
+
+    let tree_path = cache_dir.join(TREE_R_LAST_FILE);
+
+    let leafs = disk_tree_leafs(&tree_path)
+        .map_err(|err| format_err!("{} is unavailable: {}", tree_path.display(), err))?;
+    ensure!(
+        leafs as u64 * NODE_SIZE as u64 == sector_size,
+        "{} has {} leaves, which does not match the sector size of {} bytes",
+        tree_path.display(),
+        leafs,
+        sector_size
+    );
+
+    let mut tree = File::open(&tree_path)?;
+    for &node in &checked_nodes {
+        let leaf = PedersenDomain::try_from_bytes(&replica.read_node(node)?)?;
+        if !disk_path_is_valid(&mut tree, leafs as u64, node, leaf, aux.comm_r_last)? {
+            faults.push(SectorFault::CorruptNode(node));
+        }
+    }
+
+    for fault in &faults {
+        warn!("{}: {:?}", replica_path.display(), fault);
+    }
+
+    Ok(SectorHealth {
+        checked_nodes,
+        faults,
+    })
+}
+
+/// Reads the element at `index` of `file`, which holds one element per `NODE_SIZE` bytes.
+fn read_element(file: &mut File, index: u64) -> error::Result<PedersenDomain> {
+    let mut buf = [0u8; NODE_SIZE];
+    file.seek(SeekFrom::Start(index * NODE_SIZE as u64))?;
+    file.read_exact(&mut buf)?;
+
+    Ok(PedersenDomain::try_from_bytes(&buf)?)
+}
+
+/// Recomputes the path of `node` through the tree of `leafs` leaves stored in `tree`, starting
+/// from `leaf` as read from the replica, and checks that it ends at `root`.
+fn disk_path_is_valid(
+    tree: &mut File,
+    leafs: u64,
+    node: u64,
+    leaf: PedersenDomain,
+    root: PedersenDomain,
+) -> error::Result<bool> {
+    if read_element(tree, node)? != leaf {
+        return Ok(false);
+    }
+
+    let mut a = PedersenFunction::default();
+    let mut hash = leaf;
+    let mut index = node;
+    let mut row_start = 0;
+    let mut row_len = leafs;
+    let mut height = 0;
+
+    while row_len > 1 {
+        let sibling = read_element(tree, row_start + (index ^ 1))?;
+        let (left, right) = if index & 1 == 1 {
+            (sibling, hash)
+        } else {
+            (hash, sibling)
+        };
+
+        a.reset();
+        hash = a.node(left, right, height);
+
+        row_start += row_len;
+        row_len /= 2;
+        index /= 2;
+        height += 1;
+    }
+
+    Ok(hash == root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use paired::bls12_381::{Bls12, Fr};
+    use rand::{SeedableRng, XorShiftRng};
+    use storage_proofs::drgraph::Graph;
+    use storage_proofs::fr32::fr_into_bytes;
+
+    use crate::api::commitment_from_fr;
+    use crate::replica_file::ReplicaHeader;
+    use crate::types::SectorSize;
+
+    const SECTOR_SIZE: u64 = 1024;
+
+    struct Sector {
+        _dir: tempfile::TempDir,
+        replica_path: PathBuf,
+        cache_dir: PathBuf,
+        comm_r: Commitment,
+        aux: PersistentAux,
+    }
+
+    fn sector() -> Sector {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe_6259, 0x8d31_3d76, 0x3237_db17, 0xe5bc_0654]);
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().to_path_buf();
+        let replica_path = dir.path().join("replica");
+
+        let data: Vec<u8> = (0..SECTOR_SIZE as usize / NODE_SIZE)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen::<Fr>()))
+            .collect();
+        fs::write(&replica_path, &data).unwrap();
+
+        let tree = public_params(PaddedBytesAmount(SECTOR_SIZE), 1)
+            .graph
+            .merkle_tree(&data)
+            .unwrap();
+        let elements: Vec<u8> = (0..tree.len())
+            .flat_map(|i| tree.read_at(i).into_bytes())
+            .collect();
+        fs::write(cache_dir.join(TREE_R_LAST_FILE), &elements).unwrap();
+
+        let aux = PersistentAux {
+            comm_c: rng.gen(),
+            comm_r_last: tree.root(),
+        };
+        let comm_r = commitment_from_fr::<Bls12>(
+            public_params(PaddedBytesAmount(SECTOR_SIZE), 1)
+                .comm_r_hasher
+                .hash(aux.comm_c, aux.comm_r_last),
+        );
+
+        Sector {
+            _dir: dir,
+            replica_path,
+            cache_dir,
+            comm_r,
+            aux,
+        }
+    }
+
+    fn try_check_all(sector: &Sector) -> error::Result<SectorHealth> {
+        check_sector_nodes(
+            PoStConfig(SectorSize(SECTOR_SIZE)),
+            &sector.replica_path,
+            &sector.cache_dir,
+            sector.comm_r,
+            &sector.aux,
+            (0..SECTOR_SIZE / NODE_SIZE as u64).collect(),
+        )
+    }
+
+    fn check_all(sector: &Sector) -> SectorHealth {
+        try_check_all(sector).unwrap()
+    }
+
+    fn overwrite(path: &Path, offset: u64) {
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[0u8; NODE_SIZE]).unwrap();
+    }
+
+    #[test]
+    fn test_check_sector_healthy() {
+        let sector = sector();
+
+        let health = check_sector(
+            PoStConfig(SectorSize(SECTOR_SIZE)),
+            &sector.replica_path,
+            &sector.cache_dir,
+            sector.comm_r,
+            &sector.aux,
+        )
+        .unwrap();
+        assert!(!health.checked_nodes.is_empty());
+        assert!(health.is_healthy(), "{:?}", health.faults);
+
+        assert!(check_all(&sector).is_healthy());
+    }
+
+    #[test]
+    fn test_check_sector_with_rng_is_reproducible() {
+        let sector = sector();
+        let check = |seed| {
+            check_sector_with_rng(
+                PoStConfig(SectorSize(SECTOR_SIZE)),
+                &sector.replica_path,
+                &sector.cache_dir,
+                sector.comm_r,
+                &sector.aux,
+                &mut XorShiftRng::from_seed(seed),
+            )
+            .unwrap()
+        };
+
+        let seed = [0x3dbe_6259, 0x8d31_3d76, 0x3237_db17, 0xe5bc_0654];
+        let health = check(seed);
+        assert!(health.is_healthy(), "{:?}", health.faults);
+        assert_eq!(health.checked_nodes, check(seed).checked_nodes);
+    }
+
+    #[test]
+    fn test_check_sector_corrupt_replica() {
+        let sector = sector();
+        overwrite(&sector.replica_path, 5 * NODE_SIZE as u64);
+
+        let health = check_all(&sector);
+        assert_eq!(health.faults, vec![SectorFault::CorruptNode(5)]);
+    }
+
+    #[test]
+    fn test_check_sector_corrupt_tree() {
+        let sector = sector();
+
+        // The first element of the second row is on the path of nodes 2 and 3 only.
+        let leafs = SECTOR_SIZE / NODE_SIZE as u64;
+        overwrite(
+            &sector.cache_dir.join(TREE_R_LAST_FILE),
+            leafs * NODE_SIZE as u64,
+        );
+
+        let health = check_all(&sector);
+        assert_eq!(
+            health.faults,
+            vec![SectorFault::CorruptNode(2), SectorFault::CorruptNode(3)]
+        );
+    }
+
+    #[test]
+    fn test_check_sector_missing_files() {
+        let sector = sector();
+        let tree_path = sector.cache_dir.join(TREE_R_LAST_FILE);
+
+        // The paths can't be checked without tree_r_last.
+        fs::write(&tree_path, vec![0; NODE_SIZE]).unwrap();
+        assert!(try_check_all(&sector).is_err());
+        fs::remove_file(&tree_path).unwrap();
+        assert!(try_check_all(&sector).is_err());
+
+        fs::remove_file(&sector.replica_path).unwrap();
+        let health = check_all(&sector);
+        assert_eq!(
+            health.faults,
+            vec![SectorFault::MissingFile(sector.replica_path.clone())]
+        );
+    }
+
+    #[test]
+    fn test_check_sector_commitment_mismatch() {
+        let mut sector = sector();
+        sector.comm_r = [0; 32];
+
+        let health = check_all(&sector);
+        assert_eq!(health.faults, vec![SectorFault::CommitmentMismatch]);
+    }
+
+    #[test]
+    fn test_check_sector_headered_replica() {
+        let mut sector = sector();
+        let raw_path = sector.replica_path.clone();
+        sector.replica_path = raw_path.with_file_name("headered");
+
+        let header = ReplicaHeader::new(PaddedBytesAmount(SECTOR_SIZE), sector.comm_r);
+        ReplicaFile::create_headered(&raw_path, &sector.replica_path, &header).unwrap();
+        assert!(check_all(&sector).is_healthy());
+
+        // A replica sealed for another sector is never read.
+        let header = ReplicaHeader::new(PaddedBytesAmount(SECTOR_SIZE), [0; 32]);
+        ReplicaFile::create_headered(&raw_path, &sector.replica_path, &header).unwrap();
+        assert_eq!(
+            check_all(&sector).faults,
+            vec![SectorFault::MalformedFile(sector.replica_path.clone())]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::path::Path;
+
+use storage_proofs::hasher::pedersen::PedersenDomain;
+use storage_proofs::merkle::disk_tree_root;
+use storage_proofs::stacked::{layer_file_name, TREE_C_FILE, TREE_D_FILE, TREE_R_LAST_FILE};
+use storage_proofs::util::ensure_writable;
+
+use crate::error;
+
+/// Removes the parts of a stored `TemporaryAux` in `cache_dir` which are only needed to prove a
+/// seal, once that proof has been generated, and returns the number of bytes reclaimed.
+///
+/// The labels of every layer, `tree_d` and `tree_c` are removed. `tree_r_last` and any other
+/// file, such as a stored `PersistentAux`, are kept for generating PoSts. Nothing is removed
+/// unless `tree_r_last` is present and well-formed, so a cache which could no longer back a PoSt
+/// is never produced. Missing files are treated as already removed, which makes the call safe to
+/// repeat.
+///
+pub fn clear_cache<P: AsRef<Path>>(cache_dir: P) -> error::Result<u64> {
+    let cache_dir = cache_dir.as_ref();
+    ensure_writable(cache_dir)?;
+
+    let tree_r_last = cache_dir.join(TREE_R_LAST_FILE);
+    disk_tree_root::<PedersenDomain, _>(&tree_r_last).map_err(|err| {
+        format_err!(
+            "{} cannot back a PoSt, not clearing {}: {}",
+            tree_r_last.display(),
+            cache_dir.display(),
+            err
+        )
+    })?;
+
+    let mut reclaimed = 0;
+
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => continue,
+        };
+
+        if name != TREE_D_FILE && name != TREE_C_FILE && !is_layer_file(name) {
+            continue;
+        }
+
+        let metadata = fs::symlink_metadata(entry.path())?;
+        if metadata.is_dir() {
+            continue;
+        }
+
+        fs::remove_file(entry.path())?;
+        reclaimed += metadata.len();
+        info!("removed {}", entry.path().display());
+    }
+
+    Ok(reclaimed)
+}
+
+fn is_layer_file(name: &str) -> bool {
+    name.trim_start_matches("layer-")
+        .trim_end_matches(".dat")
+        .parse::<usize>()
+        .map(|layer| layer_file_name(layer) == name)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use storage_proofs::util::NODE_SIZE;
+
+    #[test]
+    fn test_clear_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path();
+
+        // A tree with 4 leaves has 7 elements.
+        let tree = vec![0u8; 7 * NODE_SIZE];
+        fs::write(cache_dir.join(layer_file_name(1)), vec![0; 128]).unwrap();
+        fs::write(cache_dir.join(layer_file_name(2)), vec![0; 128]).unwrap();
+        fs::write(cache_dir.join(TREE_D_FILE), &tree).unwrap();
+        fs::write(cache_dir.join(TREE_C_FILE), &tree).unwrap();
+        fs::write(cache_dir.join(TREE_R_LAST_FILE), &tree).unwrap();
+        fs::write(cache_dir.join("p_aux"), vec![0; 64]).unwrap();
+
+        let reclaimed = clear_cache(cache_dir).expect("clear_cache failed");
+        assert_eq!(reclaimed, 2 * 128 + 2 * tree.len() as u64);
+
+        let mut left: Vec<_> = fs::read_dir(cache_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            vec!["p_aux".to_string(), TREE_R_LAST_FILE.to_string()]
+        );
+
+        // Clearing again is a no-op.
+        assert_eq!(clear_cache(cache_dir).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_clear_cache_requires_tree_r_last() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path();
+
+        fs::write(cache_dir.join(TREE_D_FILE), vec![0; 7 * NODE_SIZE]).unwrap();
+        assert!(clear_cache(cache_dir).is_err());
+
+        // A truncated tree_r_last is no better than none.
+        fs::write(cache_dir.join(TREE_R_LAST_FILE), vec![0; 6 * NODE_SIZE]).unwrap();
+        assert!(clear_cache(cache_dir).is_err());
+
+        assert!(cache_dir.join(TREE_D_FILE).exists());
+    }
+
+    #[test]
+    fn test_is_layer_file() {
+        assert!(is_layer_file(&layer_file_name(1)));
+        assert!(is_layer_file(&layer_file_name(11)));
+        assert!(!is_layer_file("layer-.dat"));
+        assert!(!is_layer_file("layer-1.dat.tmp"));
+        assert!(!is_layer_file(TREE_R_LAST_FILE));
+    }
+}
@@ -0,0 +1,174 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use storage_proofs::circuit::stacked::StackedCompound;
+use storage_proofs::compound_proof::CompoundProof;
+use storage_proofs::sector::SectorId;
+use storage_proofs::versioned;
+
+use crate::api::{
+    seal_public_inputs, verify_seal, verify_vanilla_seal_proofs, Commitment, ProverId, Ticket,
+    VanillaSealProof,
+};
+use crate::caches::get_stacked_params;
+use crate::error;
+use crate::parameters::porep_public_params;
+use crate::types::{PaddedBytesAmount, PoRepConfig};
+
+/// Everything the SNARK of a seal is computed from: the vanilla proofs of all partitions, with
+/// the commitments and ids they are checked against. Returned by `seal_commit_phase1`, and
+/// serializable, so `prove_from_phase1_output` can run on a different machine.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SealCommitPhase1Output {
+    pub vanilla_proofs: Vec<VanillaSealProof>,
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub prover_id: ProverId,
+    pub sector_id: SectorId,
+    /// Derives, together with the other ids and `comm_d`, the replica id the challenges are
+    /// derived from.
+    pub ticket: Ticket,
+}
+
+impl SealCommitPhase1Output {
+    /// Writes the canonical, versioned encoding, see `storage_proofs::versioned`.
+    pub fn write_into<W: Write>(&self, writer: W) -> error::Result<()> {
+        versioned::write_into(self, writer).map_err(Into::into)
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> error::Result<Self> {
+        versioned::read_from(reader).map_err(Into::into)
+    }
+}
+
+/// Computes the SNARK of a seal from the output of `seal_commit_phase1`, and returns the proof
+/// `seal` would have returned.
+///
+/// The output may come from another machine, so its vanilla proofs are verified before the
+/// Groth parameters are even loaded, and invalid ones are an error.
+///
+pub fn prove_from_phase1_output(
+    porep_config: PoRepConfig,
+    phase1_output: &SealCommitPhase1Output,
+) -> error::Result<Vec<u8>> {
+    let SealCommitPhase1Output {
+        vanilla_proofs,
+        comm_r,
+        comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+    } = phase1_output;
+
+    ensure!(
+        verify_vanilla_seal_proofs(
+            porep_config,
+            *comm_r,
+            *comm_d,
+            *prover_id,
+            *sector_id,
+            *ticket,
+            vanilla_proofs,
+        )?,
+        "the vanilla proofs of the phase 1 output do not verify"
+    );
+
+    let public_inputs = seal_public_inputs(comm_r, comm_d, prover_id, *sector_id, ticket)?;
+    let compound_public_params = porep_public_params(porep_config)?;
+
+    let groth_params = get_stacked_params(porep_config)?;
+
+    info!(
+        "got groth params ({}) while sealing",
+        u64::from(PaddedBytesAmount::from(porep_config))
+    );
+
+    let proof = StackedCompound::prove_from_vanilla(
+        &compound_public_params,
+        &public_inputs,
+        vanilla_proofs,
+        &groth_params,
+    )?
+    .as_bytes();
+
+    // Verification is cheap when parameters are cached,
+    // and it is never correct to return a proof which does not verify.
+    verify_seal(
+        porep_config,
+        *comm_r,
+        *comm_d,
+        *prover_id,
+        *sector_id,
+        *ticket,
+        &proof,
+    )
+    .expect("post-seal verification sanity check failed");
+
+    Ok(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Seek, SeekFrom};
+
+    use rand::Rng;
+    use tempfile::NamedTempFile;
+
+    use crate::api::seal_commit_phase1;
+    use crate::constants::{DEFAULT_POREP_PROOF_PARTITIONS, SECTOR_SIZE_ONE_KIB};
+    use crate::fr32::write_padded;
+    use crate::types::{SectorSize, UnpaddedBytesAmount};
+
+    #[test]
+    fn test_phase1_output() -> Result<(), failure::Error> {
+        let porep_config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let piece_size = UnpaddedBytesAmount::from(PaddedBytesAmount::from(porep_config));
+
+        let mut piece_file = NamedTempFile::new()?;
+        let piece_bytes: Vec<u8> = (0..piece_size.0).map(|_| rand::random::<u8>()).collect();
+        piece_file.write_all(&piece_bytes)?;
+        piece_file.seek(SeekFrom::Start(0))?;
+
+        let mut staged_sector_file = NamedTempFile::new()?;
+        write_padded(&mut piece_file, &mut staged_sector_file)?;
+        let sealed_sector_file = NamedTempFile::new()?;
+
+        let (output, phase1_output) = seal_commit_phase1(
+            porep_config,
+            staged_sector_file.path(),
+            sealed_sector_file.path(),
+            [1; 32],
+            SectorId::from(7),
+            rand::thread_rng().gen(),
+            &[piece_size],
+        )?;
+        assert!(output.proof.is_empty());
+        assert_eq!(output.comm_r, phase1_output.comm_r);
+        assert_eq!(output.comm_d, phase1_output.comm_d);
+
+        let mut encoded = Vec::new();
+        phase1_output.write_into(&mut encoded)?;
+        let decoded = SealCommitPhase1Output::read_from(&encoded[..])?;
+        assert!(verify_vanilla_seal_proofs(
+            porep_config,
+            decoded.comm_r,
+            decoded.comm_d,
+            decoded.prover_id,
+            decoded.sector_id,
+            decoded.ticket,
+            &decoded.vanilla_proofs,
+        )?);
+
+        // Proofs for another sector are rejected before any parameters are loaded.
+        let mut other = decoded.clone();
+        other.sector_id = SectorId::from(8);
+        assert!(prove_from_phase1_output(porep_config, &other).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+//! Election proof-of-spacetime.
+//!
+//! A miner first derives the sectors challenged by the election randomness and computes a
+//! partial ticket for each of them with `generate_candidates`. The candidates whose tickets win
+//! the election are then proven with `generate_post`, one SNARK per winner, and checked with
+//! `verify_post`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use paired::bls12_381::Bls12;
+use rayon::prelude::*;
+use storage_proofs::circuit::election_post::ElectionPoStCompound;
+use storage_proofs::circuit::multi_proof::MultiProof;
+use storage_proofs::compound_proof::{self, CompoundProof};
+use storage_proofs::election_post::{self, ElectionPoSt};
+use storage_proofs::hasher::pedersen::{PedersenDomain, PedersenHasher};
+use storage_proofs::proof::{NoRequirements, ProofScheme};
+use storage_proofs::sector::*;
+
+pub use storage_proofs::election_post::Candidate;
+
+use crate::api::{ChallengeSeed, PrivateReplicaInfo, ProverId, PublicReplicaInfo, Tree};
+use crate::caches::{get_election_post_params, get_election_post_verifying_key};
+use crate::error;
+use crate::parameters::election_post_setup_params;
+use crate::singletons::ENGINE_PARAMS;
+use crate::types::{PaddedBytesAmount, PoStConfig};
+
+fn setup<'a>(
+    post_config: PoStConfig,
+) -> error::Result<compound_proof::PublicParams<'a, Bls12, ElectionPoSt<'a, PedersenHasher>>> {
+    let vanilla_params = election_post_setup_params(post_config);
+
+    Ok(compound_proof::PublicParams {
+        vanilla_params: ElectionPoSt::<PedersenHasher>::setup(&vanilla_params)?,
+        engine_params: *ENGINE_PARAMS,
+        partitions: None,
+    })
+}
+
+/// Draws `challenge_count` sectors from the non-faulty `replicas` and computes their partial
+/// tickets.
+pub fn generate_candidates(
+    post_config: PoStConfig,
+    randomness: &ChallengeSeed,
+    challenge_count: usize,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
+    prover_id: ProverId,
+) -> error::Result<Vec<Candidate>> {
+    let sector_size = u64::from(PaddedBytesAmount::from(post_config));
+    let pub_params = setup(post_config)?;
+
+    let sectors: OrderedSectorSet = replicas
+        .iter()
+        .filter(|(_, replica)| !replica.is_fault())
+        .map(|(id, _)| *id)
+        .collect();
+    let challenged_sectors =
+        election_post::generate_sector_challenges(randomness, challenge_count, &sectors)?;
+
+    // Build each challenged tree only once, even if its sector was drawn multiple times.
+    let mut unique_sectors = challenged_sectors.clone();
+    unique_sectors.sort_unstable();
+    unique_sectors.dedup();
+
+    let trees: BTreeMap<SectorId, Tree> = unique_sectors
+        .into_par_iter()
+        .map(|id| {
+            let replica = &replicas[&id];
+            replica.merkle_tree(sector_size).map(|tree| (id, tree))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(election_post::generate_candidates::<PedersenHasher>(
+        &pub_params.vanilla_params,
+        &challenged_sectors,
+        &trees,
+        &prover_id,
+        randomness,
+    )?)
+}
+
+/// Generates an election proof-of-spacetime for each of the `winners`, as returned by
+/// `generate_candidates`.
+pub fn generate_post(
+    post_config: PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
+    winners: Vec<Candidate>,
+    prover_id: ProverId,
+) -> error::Result<Vec<Vec<u8>>> {
+    let sector_size = u64::from(PaddedBytesAmount::from(post_config));
+    let groth_params = get_election_post_params(post_config)?;
+    let pub_params = setup(post_config)?;
+
+    winners
+        .into_iter()
+        .map(|winner| -> error::Result<Vec<u8>> {
+            let replica = replicas
+                .get(&winner.sector_id)
+                .ok_or_else(|| format_err!("missing replica for {}", winner.sector_id))?;
+            let tree = replica.merkle_tree(sector_size)?;
+
+            let pub_inputs = election_post::PublicInputs {
+                randomness: *randomness,
+                sector_id: winner.sector_id,
+                prover_id,
+                comm_r: replica.safe_comm_r()?,
+                partial_ticket: winner.partial_ticket,
+                sector_challenge_index: winner.sector_challenge_index,
+            };
+
+            let priv_inputs = election_post::PrivateInputs::<PedersenHasher> {
+                tree: &tree,
+                comm_c: replica.safe_comm_c()?,
+                comm_r_last: replica.safe_comm_r_last()?,
+            };
+
+            let proof =
+                ElectionPoStCompound::prove(&pub_params, &pub_inputs, &priv_inputs, &groth_params)?;
+
+            Ok(proof.as_bytes())
+        })
+        .collect()
+}
+
+/// Verifies the election proofs-of-spacetime of the `winners`, in order.
+///
+/// Besides the proofs themselves this checks that every winner was actually drawn for the given
+/// `randomness`, that no draw is claimed twice, and that its ticket is derived from its partial
+/// ticket.
+pub fn verify_post(
+    post_config: PoStConfig,
+    randomness: &ChallengeSeed,
+    challenge_count: usize,
+    proofs: &[Vec<u8>],
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    winners: &[Candidate],
+    prover_id: ProverId,
+) -> error::Result<bool> {
+    if proofs.len() != winners.len() {
+        return Err(format_err!(
+            "{} proofs but {} winners",
+            proofs.len(),
+            winners.len()
+        ));
+    }
+
+    // Each draw wins at most once, or a single winning sector could be claimed repeatedly.
+    let mut claimed = BTreeSet::new();
+    if !winners
+        .iter()
+        .all(|winner| claimed.insert(winner.sector_challenge_index))
+    {
+        return Ok(false);
+    }
+
+    let sectors: OrderedSectorSet = replicas
+        .iter()
+        .filter(|(_, replica)| !replica.is_fault())
+        .map(|(id, _)| *id)
+        .collect();
+    let challenged_sectors =
+        election_post::generate_sector_challenges(randomness, challenge_count, &sectors)?;
+
+    let pub_params = setup(post_config)?;
+    let verifying_key = get_election_post_verifying_key(post_config)?;
+
+    for (proof, winner) in proofs.iter().zip(winners) {
+        let drawn = challenged_sectors.get(winner.sector_challenge_index as usize);
+        if drawn != Some(&winner.sector_id) {
+            return Ok(false);
+        }
+
+        if winner.ticket != election_post::finalize_ticket(&winner.partial_ticket) {
+            return Ok(false);
+        }
+
+        let comm_r = replicas
+            .get(&winner.sector_id)
+            .ok_or_else(|| format_err!("missing replica for {}", winner.sector_id))?
+            .safe_comm_r()?;
+
+        let pub_inputs = election_post::PublicInputs::<PedersenDomain> {
+            randomness: *randomness,
+            sector_id: winner.sector_id,
+            prover_id,
+            comm_r,
+            partial_ticket: winner.partial_ticket,
+            sector_challenge_index: winner.sector_challenge_index,
+        };
+
+        let proof = MultiProof::from_bytes(None, &proof[..], &verifying_key)?;
+
+        if !ElectionPoStCompound::verify(&pub_params, &pub_inputs, &proof, &NoRequirements)? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ff::Field;
+    use paired::bls12_381::Fr;
+
+    use crate::types::SectorSize;
+
+    #[test]
+    fn test_verify_post_rejects_repeated_draws() {
+        let winner = Candidate {
+            sector_id: SectorId::from(1),
+            partial_ticket: Fr::zero(),
+            ticket: [0; 32],
+            sector_challenge_index: 0,
+        };
+
+        // Rejected before any proof is looked at, so neither replicas nor proofs are needed.
+        let valid = verify_post(
+            PoStConfig(SectorSize(1024)),
+            &[0; 32],
+            1,
+            &[vec![], vec![]],
+            &BTreeMap::new(),
+            &[winner.clone(), winner],
+            [0; 32],
+        )
+        .unwrap();
+        assert!(!valid);
+    }
+}
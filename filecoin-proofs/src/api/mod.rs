@@ -1,49 +1,74 @@
+use std::cmp::{max, min};
 use std::convert::TryInto;
 use std::fs::{copy, File, OpenOptions};
 use std::io::prelude::*;
 use std::io::{BufWriter, Cursor, Read, SeekFrom};
 use std::path::{Path, PathBuf};
 
-use memmap::MmapOptions;
 use paired::bls12_381::Bls12;
 use paired::Engine;
+use serde::{Deserialize, Serialize};
 
-use crate::caches::{get_stacked_params, get_stacked_verifying_key};
+use crate::caches::get_stacked_verifying_key;
 use crate::constants::{
     MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
     MINIMUM_RESERVED_LEAVES_FOR_PIECE_IN_SECTOR as MIN_NUM_LEAVES, POREP_MINIMUM_CHALLENGES,
-    SINGLE_PARTITION_PROOF_LEN,
 };
 use crate::error;
 use crate::file_cleanup::FileCleanup;
-use crate::fr32::{write_padded, write_unpadded};
-use crate::parameters::{public_params, setup_params};
-use crate::pieces::{get_aligned_source, get_piece_alignment, PieceAlignment};
-use crate::singletons::ENGINE_PARAMS;
+use crate::fr32::write_unpadded;
+use crate::parameters::{porep_public_params, public_params};
+use crate::pieces::{self, compute_alignment, PieceAlignment};
+use crate::remote_prover::default_prover;
+use crate::replica_file::ReplicaFile;
 use crate::types::{
-    PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, SectorSize, UnpaddedByteIndex,
-    UnpaddedBytesAmount,
+    PaddedBytesAmount, PoRepConfig, SectorSize, UnpaddedByteIndex, UnpaddedBytesAmount,
 };
 
 use storage_proofs::circuit::multi_proof::MultiProof;
 use storage_proofs::circuit::stacked::StackedCompound;
-use storage_proofs::compound_proof::{self, CompoundProof};
+use storage_proofs::compound_proof::CompoundProof;
+use storage_proofs::data::Data;
 use storage_proofs::drgraph::{DefaultTreeHasher, Graph};
 use storage_proofs::fr32::{bytes_into_fr, fr_into_bytes, Fr32Ary};
 use storage_proofs::hasher::pedersen::{PedersenDomain, PedersenHasher};
 use storage_proofs::hasher::{Domain, Hasher};
+use storage_proofs::measurements::{self, Operation};
 use storage_proofs::merkle::MerkleTree;
 use storage_proofs::piece_inclusion_proof::{
     generate_piece_commitment_bytes_from_source, piece_inclusion_proofs, PieceInclusionProof,
     PieceSpec,
 };
 use storage_proofs::porep::PoRep;
+use storage_proofs::proof::ProofScheme;
 use storage_proofs::sector::SectorId;
-use storage_proofs::stacked::{self, generate_replica_id, ChallengeRequirements, StackedDrg, Tau};
-use tempfile::tempfile;
+use storage_proofs::stacked::{
+    self, generate_replica_id, layer_file_name, ChallengeRequirements, StackedDrg, Tau,
+    TemporaryAuxAsStored,
+};
 
+mod abort;
+mod check_sector;
+mod clear_cache;
+mod commit;
+pub mod election_post;
 mod post;
+mod proving_set;
+mod resources;
+mod vanilla;
+mod verifier;
+
+pub use crate::api::abort::*;
+pub use crate::api::check_sector::*;
+pub use crate::api::clear_cache::*;
+pub use crate::api::commit::*;
 pub use crate::api::post::*;
+pub use crate::api::proving_set::*;
+pub use crate::api::resources::*;
+pub use crate::api::vanilla::*;
+pub use crate::api::verifier::*;
+pub use storage_proofs::cancel::{cancellable, CancellationToken};
+pub use storage_proofs::progress::{observe, Phase, PhaseEvent, SealProgress};
 
 pub type Commitment = Fr32Ary;
 pub type ChallengeSeed = [u8; 32];
@@ -92,7 +117,7 @@ fn generate_pseudo_piece_specs(piece_lengths: &[UnpaddedBytesAmount]) -> Vec<Pse
             let PieceAlignment {
                 left_bytes,
                 right_bytes,
-            } = get_piece_alignment(byte_index, unpadded_piece_length);
+            } = compute_alignment(byte_index, unpadded_piece_length);
 
             let padded_piece_length = PaddedBytesAmount::from(unpadded_piece_length);
             let padded_left_bytes = PaddedBytesAmount::from(left_bytes);
@@ -150,6 +175,12 @@ fn generate_piece_specs_from_source(
 /// Seals the staged sector at `in_path` in place, saving the resulting replica
 /// to `out_path`.
 ///
+/// The SNARK is computed by the prover server at the `remote_prover` setting, if it is set, see
+/// `remote_prover`.
+///
+/// Progress is reported to the observer installed with `observe`, if any. Run under
+/// `cancellable`, sealing stops with `Error::Cancelled` soon after the token is cancelled.
+///
 pub fn seal<T: AsRef<Path>>(
     porep_config: PoRepConfig,
     in_path: T,
@@ -159,8 +190,169 @@ pub fn seal<T: AsRef<Path>>(
     ticket: Ticket,
     piece_lengths: &[UnpaddedBytesAmount],
 ) -> error::Result<SealOutput> {
+    let (mut output, phase1_output) = seal_commit_phase1(
+        porep_config,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_lengths,
+    )?;
+
+    output.proof = default_prover()?.prove(porep_config, &phase1_output)?;
+
+    Ok(output)
+}
+
+/// Everything `seal_commit` needs to finish a seal started by `seal_pre_commit`: the
+/// commitments of the replica, where its layers and trees were stored, and its pieces. It can be
+/// serialized, so the commit can happen in another process.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SealPreCommitOutput {
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub p_aux: PersistentAux,
+    pub t_aux: TemporaryAuxAsStored,
+    pub piece_specs: Vec<PieceSpec>,
+}
+
+/// Replicates the staged sector at `in_path` into `out_path`, like the first half of `seal`, and
+/// stores the layers and trees of the replica in `cache_path`. `seal_commit` proves the replica
+/// from the returned output, e.g. once the commitments are on chain, and `cache_path` may be
+/// cleared after that, see `clear_cache`.
+///
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit<T: AsRef<Path>>(
+    porep_config: PoRepConfig,
+    cache_path: T,
+    in_path: T,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_lengths: &[UnpaddedBytesAmount],
+) -> error::Result<SealPreCommitOutput> {
+    let replication = replicate_sector(
+        porep_config,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_lengths,
+    )?;
+
+    let t_aux = replication.t_aux.convert_to_stored(cache_path.as_ref())?;
+
+    Ok(SealPreCommitOutput {
+        comm_r: commitment_from_fr::<Bls12>(replication.tau.comm_r.into()),
+        comm_d: commitment_from_fr::<Bls12>(replication.tau.comm_d.into()),
+        p_aux: replication.p_aux,
+        t_aux,
+        piece_specs: replication.piece_specs,
+    })
+}
+
+/// Finishes the seal started by `seal_pre_commit`, with the same ids, and returns what `seal`
+/// would have. The layers and trees are loaded from where `seal_pre_commit` stored them. The
+/// SNARK is computed by the prover at the `remote_prover` setting, like in `seal`.
+///
+pub fn seal_commit(
+    porep_config: PoRepConfig,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    pre_commit: &SealPreCommitOutput,
+) -> error::Result<SealOutput> {
+    let (mut output, phase1_output) =
+        seal_commit_phase1_from_pre_commit(porep_config, prover_id, sector_id, ticket, pre_commit)?;
+
+    output.proof = default_prover()?.prove(porep_config, &phase1_output)?;
+
+    Ok(output)
+}
+
+/// `seal_commit`, but stops short of the SNARK like `seal_commit_phase1`: returns the
+/// `SealOutput` with an empty `proof`, and the `SealCommitPhase1Output` to compute it from with
+/// `prove_from_phase1_output`.
+///
+pub fn seal_commit_phase1_from_pre_commit(
+    porep_config: PoRepConfig,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    pre_commit: &SealPreCommitOutput,
+) -> error::Result<(SealOutput, SealCommitPhase1Output)> {
+    let comm_r = as_safe_commitment(&pre_commit.comm_r, "comm_r")?;
+    let comm_d = as_safe_commitment(&pre_commit.comm_d, "comm_d")?;
+
+    let replication = Replication {
+        replica_id: generate_replica_id::<DefaultTreeHasher>(
+            &prover_id,
+            sector_id.into(),
+            &ticket,
+            comm_d,
+        ),
+        tau: Tau { comm_r, comm_d },
+        p_aux: pre_commit.p_aux.clone(),
+        t_aux: stacked::TemporaryAux::load(&pre_commit.t_aux)?,
+        piece_specs: pre_commit.piece_specs.clone(),
+    };
+
+    prove_replication(porep_config, replication, prover_id, sector_id, ticket)
+}
+
+/// Seals like `seal`, but stops short of the SNARK. The returned `SealOutput` has an empty
+/// `proof`, which `prove_from_phase1_output` computes from the returned
+/// `SealCommitPhase1Output`. That needs neither the replica nor its trees, so it can run on
+/// another machine, e.g. one with the Groth parameters and a GPU.
+///
+pub fn seal_commit_phase1<T: AsRef<Path>>(
+    porep_config: PoRepConfig,
+    in_path: T,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_lengths: &[UnpaddedBytesAmount],
+) -> error::Result<(SealOutput, SealCommitPhase1Output)> {
+    let replication = replicate_sector(
+        porep_config,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_lengths,
+    )?;
+
+    prove_replication(porep_config, replication, prover_id, sector_id, ticket)
+}
+
+/// A replicated sector, with everything its vanilla proofs are generated from.
+struct Replication {
+    replica_id: PedersenDomain,
+    tau: Tau<PedersenDomain>,
+    p_aux: PersistentAux,
+    t_aux: stacked::TemporaryAux<DefaultTreeHasher>,
+    piece_specs: Vec<PieceSpec>,
+}
+
+fn replicate_sector<T: AsRef<Path>>(
+    porep_config: PoRepConfig,
+    in_path: T,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_lengths: &[UnpaddedBytesAmount],
+) -> error::Result<Replication> {
     let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
 
+    // Checked before anything is written, so a read-only setup is not left with partial replicas.
+    storage_proofs::util::ensure_writable(out_path.as_ref())?;
+
     let mut cleanup = FileCleanup::new(&out_path);
 
     // Copy unsealed data to output location, where it will be sealed in place.
@@ -169,24 +361,18 @@ pub fn seal<T: AsRef<Path>>(
 
     // Zero-pad the data to the requested size by extending the underlying file if needed.
     f_data.set_len(sector_bytes as u64)?;
+    drop(f_data);
 
-    let mut data = unsafe { MmapOptions::new().map_mut(&f_data).unwrap() };
-
-    let compound_setup_params = compound_proof::SetupParams {
-        vanilla_params: &setup_params(
-            PaddedBytesAmount::from(porep_config),
-            usize::from(PoRepProofPartitions::from(porep_config)),
-        ),
-        engine_params: &(*ENGINE_PARAMS),
-        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
-    };
+    // The replica is only mapped while it is needed, rather than for the whole replication.
+    let mut data = Data::from_path(out_path.as_ref().to_path_buf());
+    data.ensure_data()?;
 
-    let compound_public_params = StackedCompound::setup(&compound_setup_params)?;
+    let compound_public_params = porep_public_params(porep_config)?;
 
     let data_tree = compound_public_params
         .vanilla_params
         .graph
-        .merkle_tree(&data)?;
+        .merkle_tree(data.as_slice()?)?;
 
     let replica_id = generate_replica_id::<DefaultTreeHasher>(
         &prover_id,
@@ -198,12 +384,44 @@ pub fn seal<T: AsRef<Path>>(
     let (tau, (p_aux, t_aux)) = StackedDrg::replicate(
         &compound_public_params.vanilla_params,
         &replica_id,
-        &mut data,
+        data,
         Some(data_tree),
     )?;
 
     let mut in_data = OpenOptions::new().read(true).open(&in_path)?;
     let piece_specs = generate_piece_specs_from_source(&mut in_data, &piece_lengths)?;
+
+    // If we succeeded in replicating, protect output from being cleaned up. The replica has
+    // already been flushed by `replicate`.
+    cleanup.success = true;
+
+    Ok(Replication {
+        replica_id,
+        tau,
+        p_aux,
+        t_aux,
+        piece_specs,
+    })
+}
+
+/// Generates the piece inclusion proofs and the vanilla proofs of a replicated sector, and
+/// returns the outputs of `seal_commit_phase1`.
+fn prove_replication(
+    porep_config: PoRepConfig,
+    replication: Replication,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+) -> error::Result<(SealOutput, SealCommitPhase1Output)> {
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    let Replication {
+        replica_id,
+        tau,
+        p_aux,
+        t_aux,
+        piece_specs,
+    } = replication;
+
     let piece_inclusion_proofs =
         piece_inclusion_proofs::<PedersenHasher>(&piece_specs, &t_aux.tree_d)?;
     let comm_ps: Vec<Commitment> = piece_specs
@@ -211,15 +429,11 @@ pub fn seal<T: AsRef<Path>>(
         .map(|piece_spec| piece_spec.comm_p)
         .collect();
 
-    // If we succeeded in replicating, flush the data and protect output from being cleaned up.
-    data.flush()?;
-    cleanup.success = true;
-
-    let public_tau = tau;
+    let compound_public_params = porep_public_params(porep_config)?;
 
     let public_inputs = stacked::PublicInputs {
         replica_id,
-        tau: Some(public_tau.clone()),
+        tau: Some(tau.clone()),
         k: None,
         seed: None,
     };
@@ -229,28 +443,17 @@ pub fn seal<T: AsRef<Path>>(
         t_aux,
     };
 
-    let groth_params = get_stacked_params(porep_config)?;
-
-    info!(
-        "got groth params ({}) while sealing",
-        u64::from(PaddedBytesAmount::from(porep_config))
-    );
-
-    let proof = StackedCompound::prove(
-        &compound_public_params,
-        &public_inputs,
-        &private_inputs,
-        &groth_params,
-    )?;
-
-    let mut buf = Vec::with_capacity(
-        SINGLE_PARTITION_PROOF_LEN * usize::from(PoRepProofPartitions::from(porep_config)),
-    );
-
-    proof.write(&mut buf)?;
+    let vanilla_proofs = measurements::measure(Operation::VanillaProof, None, 0, || {
+        StackedDrg::prove_all_partitions(
+            &compound_public_params.vanilla_params,
+            &public_inputs,
+            &private_inputs,
+            porep_config.partitions(),
+        )
+    })?;
 
-    let comm_r = commitment_from_fr::<Bls12>(public_tau.comm_r.into());
-    let comm_d = commitment_from_fr::<Bls12>(public_tau.comm_d.into());
+    let comm_r = commitment_from_fr::<Bls12>(tau.comm_r.into());
+    let comm_d = commitment_from_fr::<Bls12>(tau.comm_d.into());
 
     let valid_pieces = PieceInclusionProof::verify_all(
         &comm_d,
@@ -267,27 +470,24 @@ pub fn seal<T: AsRef<Path>>(
         return Err(format_err!("pip verification sanity check failed"));
     }
 
-    // Verification is cheap when parameters are cached,
-    // and it is never correct to return a proof which does not verify.
-    verify_seal(
-        porep_config,
+    let output = SealOutput {
+        comm_r,
+        comm_d,
+        p_aux,
+        proof: Vec::new(),
+        comm_ps,
+        piece_inclusion_proofs,
+    };
+    let phase1_output = SealCommitPhase1Output {
+        vanilla_proofs,
         comm_r,
         comm_d,
         prover_id,
         sector_id,
         ticket,
-        &buf,
-    )
-    .expect("post-seal verification sanity check failed");
+    };
 
-    Ok(SealOutput {
-        comm_r,
-        comm_d,
-        p_aux,
-        proof: buf,
-        comm_ps,
-        piece_inclusion_proofs,
-    })
+    Ok((output, phase1_output))
 }
 
 /// Verifies the output of some previously-run seal operation.
@@ -303,33 +503,9 @@ pub fn verify_seal(
 ) -> error::Result<bool> {
     let sector_bytes = PaddedBytesAmount::from(porep_config);
 
-    let comm_r = as_safe_commitment(&comm_r, "comm_r")?;
-    let comm_d = as_safe_commitment(&comm_d, "comm_d")?;
+    let public_inputs = seal_public_inputs(&comm_r, &comm_d, &prover_id, sector_id, &ticket)?;
 
-    let replica_id =
-        generate_replica_id::<DefaultTreeHasher>(&prover_id, sector_id.into(), &ticket, comm_d);
-
-    let compound_setup_params = compound_proof::SetupParams {
-        vanilla_params: &setup_params(
-            PaddedBytesAmount::from(porep_config),
-            usize::from(PoRepProofPartitions::from(porep_config)),
-        ),
-        engine_params: &(*ENGINE_PARAMS),
-        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
-    };
-
-    let compound_public_params: compound_proof::PublicParams<
-        '_,
-        Bls12,
-        StackedDrg<'_, DefaultTreeHasher>,
-    > = StackedCompound::setup(&compound_setup_params)?;
-
-    let public_inputs = stacked::PublicInputs::<<DefaultTreeHasher as Hasher>::Domain> {
-        replica_id,
-        tau: Some(Tau { comm_r, comm_d }),
-        seed: None,
-        k: None,
-    };
+    let compound_public_params = porep_public_params(porep_config)?;
 
     let verifying_key = get_stacked_verifying_key(porep_config)?;
 
@@ -338,11 +514,8 @@ pub fn verify_seal(
         u64::from(sector_bytes)
     );
 
-    let proof = MultiProof::new_from_reader(
-        Some(usize::from(PoRepProofPartitions::from(porep_config))),
-        proof_vec,
-        &verifying_key,
-    )?;
+    let proof =
+        MultiProof::from_bytes(compound_public_params.partitions, proof_vec, &verifying_key)?;
 
     StackedCompound::verify(
         &compound_public_params,
@@ -355,6 +528,96 @@ pub fn verify_seal(
     .map_err(Into::into)
 }
 
+/// Verifies many seal proofs at once. The i-th proof is checked against the i-th element of
+/// each of the other slices, and the result is true only if every proof is valid.
+///
+/// All proofs are folded into a single randomized Groth16 batch check, which is considerably
+/// faster than calling `verify_seal` for each of them. A false result does not say which proof
+/// was invalid; fall back to `verify_seal` if that is needed.
+pub fn verify_batch_seal(
+    porep_config: PoRepConfig,
+    comm_r_ins: &[Commitment],
+    comm_d_ins: &[Commitment],
+    prover_ids: &[ProverId],
+    sector_ids: &[SectorId],
+    tickets: &[Ticket],
+    proof_vecs: &[&[u8]],
+) -> error::Result<bool> {
+    let count = proof_vecs.len();
+    ensure!(
+        comm_r_ins.len() == count
+            && comm_d_ins.len() == count
+            && prover_ids.len() == count
+            && sector_ids.len() == count
+            && tickets.len() == count,
+        "all inputs to verify_batch_seal must have the same length"
+    );
+
+    if count == 0 {
+        return Ok(true);
+    }
+
+    let compound_public_params = porep_public_params(porep_config)?;
+    let verifying_key = get_stacked_verifying_key(porep_config)?;
+
+    info!(
+        "got verifying key ({}) while verifying {} seals",
+        u64::from(PaddedBytesAmount::from(porep_config)),
+        count
+    );
+
+    let mut public_inputs = Vec::with_capacity(count);
+    let mut proofs = Vec::with_capacity(count);
+
+    for i in 0..count {
+        public_inputs.push(seal_public_inputs(
+            &comm_r_ins[i],
+            &comm_d_ins[i],
+            &prover_ids[i],
+            sector_ids[i],
+            &tickets[i],
+        )?);
+
+        proofs.push(MultiProof::from_bytes(
+            compound_public_params.partitions,
+            proof_vecs[i],
+            &verifying_key,
+        )?);
+    }
+
+    StackedCompound::batch_verify(
+        &compound_public_params,
+        &public_inputs,
+        &proofs,
+        &ChallengeRequirements {
+            minimum_challenges: POREP_MINIMUM_CHALLENGES,
+        },
+    )
+    .map_err(Into::into)
+}
+
+/// The public inputs a seal proof is verified against.
+pub(crate) fn seal_public_inputs(
+    comm_r: &Commitment,
+    comm_d: &Commitment,
+    prover_id: &ProverId,
+    sector_id: SectorId,
+    ticket: &Ticket,
+) -> error::Result<stacked::PublicInputs<<DefaultTreeHasher as Hasher>::Domain>> {
+    let comm_r = as_safe_commitment(comm_r, "comm_r")?;
+    let comm_d = as_safe_commitment(comm_d, "comm_d")?;
+
+    let replica_id =
+        generate_replica_id::<DefaultTreeHasher>(prover_id, sector_id.into(), ticket, comm_d);
+
+    Ok(stacked::PublicInputs {
+        replica_id,
+        tau: Some(Tau { comm_r, comm_d }),
+        seed: None,
+        k: None,
+    })
+}
+
 /// Verify that the provided PIP proves the piece is included in the sector.
 ///
 pub fn verify_piece_inclusion_proof(
@@ -368,7 +631,7 @@ pub fn verify_piece_inclusion_proof(
         piece_inclusion_proof.try_into()?;
     let comm_d = storage_proofs::hasher::pedersen::PedersenDomain::try_from_bytes(comm_d)?;
     let comm_p = storage_proofs::hasher::pedersen::PedersenDomain::try_from_bytes(comm_p)?;
-    let piece_alignment = get_piece_alignment(UnpaddedBytesAmount(0), piece_size);
+    let piece_alignment = compute_alignment(UnpaddedBytesAmount(0), piece_size);
     let piece_size_with_alignment =
         PaddedBytesAmount::from(piece_size + piece_alignment.right_bytes);
 
@@ -389,22 +652,13 @@ pub fn generate_piece_commitment<T: std::io::Read>(
     unpadded_piece_file: T,
     unpadded_piece_size: UnpaddedBytesAmount,
 ) -> error::Result<Commitment> {
-    let mut padded_piece_file = tempfile()?;
-
-    let (_, mut source) = get_aligned_source(unpadded_piece_file, &[], unpadded_piece_size);
-    write_padded(&mut source, &mut padded_piece_file)?;
-
-    let _ = padded_piece_file.seek(SeekFrom::Start(0))?;
-
-    let comm_p =
-        generate_piece_commitment_bytes_from_source::<PedersenHasher>(&mut padded_piece_file)?;
-    Ok(comm_p)
+    pieces::generate_piece_commitment(unpadded_piece_file, unpadded_piece_size)
+        .map(|piece_info| piece_info.commitment)
 }
 
-/// Unseals the sector at `sealed_path` and returns the bytes for a piece
+/// Unseals the sector at `sealed_path` and writes the bytes for a piece
 /// whose first (unpadded) byte begins at `offset` and ends at `offset` plus
-/// `num_bytes`, inclusive. Note that the entire sector is unsealed each time
-/// this function is called.
+/// `num_bytes`, inclusive, to `output_path`, see `unseal_range_to_writer`.
 #[allow(clippy::too_many_arguments)]
 pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>>(
     porep_config: PoRepConfig,
@@ -417,29 +671,92 @@ pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>>(
     offset: UnpaddedByteIndex,
     num_bytes: UnpaddedBytesAmount,
 ) -> error::Result<(UnpaddedBytesAmount)> {
-    let comm_d = storage_proofs::hasher::pedersen::PedersenDomain::try_from_bytes(&comm_d)?;
+    let f_out = File::create(output_path)?;
+
+    unseal_range_to_writer(
+        porep_config,
+        sealed_path,
+        None,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+        BufWriter::new(f_out),
+    )
+}
 
+/// Unseals `num_bytes` (unpadded) bytes from `offset` of the sector at `sealed_path`, and writes
+/// them to `writer` as they are decoded and unpadded, e.g. straight to a network socket. Only the
+/// nodes holding the range are read, and a chunk of them is held in memory at a time. The replica
+/// may be raw or headered, see `ReplicaFile::open`.
+///
+/// Decoding needs the labels of the last layer. They are read from `cache_dir` if it still has
+/// them, as stored by `TemporaryAux::convert_to_stored`, and regenerated otherwise, which takes
+/// about as long as replicating the sector.
+///
+#[allow(clippy::too_many_arguments)]
+pub fn unseal_range_to_writer<T: AsRef<Path>, W: Write>(
+    porep_config: PoRepConfig,
+    sealed_path: T,
+    cache_dir: Option<&Path>,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+    mut writer: W,
+) -> error::Result<UnpaddedBytesAmount> {
+    let sector_bytes = PaddedBytesAmount::from(porep_config);
+    let offset = usize::from(offset);
+    let end = offset + usize::from(num_bytes);
+    ensure!(
+        end <= usize::from(UnpaddedBytesAmount::from(porep_config)),
+        "requested unsealing of {} bytes from {}, past the end of the sector",
+        usize::from(num_bytes),
+        offset
+    );
+
+    let comm_d = PedersenDomain::try_from_bytes(&comm_d)?;
     let replica_id =
         generate_replica_id::<DefaultTreeHasher>(&prover_id, sector_id.into(), &ticket, comm_d);
 
-    let f_in = File::open(sealed_path)?;
-    let mut data = Vec::new();
-    f_in.take(u64::from(PaddedBytesAmount::from(porep_config)))
-        .read_to_end(&mut data)?;
+    let pp = public_params(sector_bytes, porep_config.partitions());
+    let last_layer = cache_dir
+        .map(|dir| dir.join(layer_file_name(pp.layer_challenges.layers())))
+        .filter(|path| path.exists());
 
-    let f_out = File::create(output_path)?;
-    let mut buf_writer = BufWriter::new(f_out);
+    // Every run of 4 padded nodes holds 127 bytes of data, so the range is decoded from the start
+    // of the run it begins in to the end of the run it ends in. Chunks of decoded nodes are whole
+    // runs, as `EXTRACT_CHUNK_NODES` is a multiple of 4.
+    let first_run = offset / 127;
+    let end_run = (end + 126) / 127;
 
-    let unsealed = StackedDrg::extract_all(
-        &public_params(
-            PaddedBytesAmount::from(porep_config),
-            usize::from(PoRepProofPartitions::from(porep_config)),
-        ),
+    let mut replica = ReplicaFile::open(sealed_path, sector_bytes, None)?;
+    let mut data = replica.data_at((first_run * 128) as u64)?;
+
+    let mut chunk_offset = first_run * 127;
+    let mut written = 0;
+    StackedDrg::<DefaultTreeHasher>::extract_range(
+        &pp,
         &replica_id,
-        &data,
-    )?;
+        last_layer.as_ref().map(PathBuf::as_path),
+        &mut data,
+        first_run * 4..end_run * 4,
+        |chunk| {
+            let chunk_end = chunk_offset + chunk.len() / 128 * 127;
+            let (start, stop) = (max(offset, chunk_offset), min(end, chunk_end));
+            if start < stop {
+                written += write_unpadded(chunk, &mut writer, start - chunk_offset, stop - start)?;
+            }
+            chunk_offset = chunk_end;
 
-    let written = write_unpadded(&unsealed, &mut buf_writer, offset.into(), num_bytes.into())?;
+            Ok(())
+        },
+    )?;
+    writer.flush()?;
 
     Ok(UnpaddedBytesAmount(written as u64))
 }
@@ -457,7 +774,9 @@ mod tests {
     use std::collections::BTreeMap;
     use std::io::{Seek, SeekFrom};
 
-    use crate::constants::SECTOR_SIZE_ONE_KIB;
+    use crate::constants::{
+        DEFAULT_POREP_PROOF_PARTITIONS, SECTOR_SIZE_ONE_KIB, SINGLE_PARTITION_PROOF_LEN,
+    };
     use crate::error::ExpectWithBacktrace;
     use crate::types::{PoStConfig, SectorSize};
 
@@ -478,20 +797,6 @@ mod tests {
         Ok(comm_p)
     }
 
-    fn add_piece<R, W>(
-        mut source: &mut R,
-        target: &mut W,
-        piece_size: UnpaddedBytesAmount,
-        piece_lengths: &[UnpaddedBytesAmount],
-    ) -> std::io::Result<usize>
-    where
-        R: Read + ?Sized,
-        W: Read + Write + Seek + ?Sized,
-    {
-        let (_, mut aligned_source) = get_aligned_source(&mut source, &piece_lengths, piece_size);
-        write_padded(&mut aligned_source, target)
-    }
-
     #[test]
     fn test_generate_piece_commitment_up_to_minimum() -> Result<(), failure::Error> {
         // test comm_p generation for all byte lengths up to the minimum piece alignment when
@@ -591,7 +896,10 @@ mod tests {
 
         {
             let result = verify_seal(
-                PoRepConfig(SectorSize(SECTOR_SIZE_ONE_KIB), PoRepProofPartitions(2)),
+                PoRepConfig(
+                    SectorSize(SECTOR_SIZE_ONE_KIB),
+                    DEFAULT_POREP_PROOF_PARTITIONS,
+                ),
                 not_convertible_to_fr_bytes,
                 convertible_to_fr_bytes,
                 [0; 32],
@@ -615,7 +923,10 @@ mod tests {
 
         {
             let result = verify_seal(
-                PoRepConfig(SectorSize(SECTOR_SIZE_ONE_KIB), PoRepProofPartitions(2)),
+                PoRepConfig(
+                    SectorSize(SECTOR_SIZE_ONE_KIB),
+                    DEFAULT_POREP_PROOF_PARTITIONS,
+                ),
                 convertible_to_fr_bytes,
                 not_convertible_to_fr_bytes,
                 [0; 32],
@@ -638,6 +949,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_verify_batch_seal_input_lengths() {
+        let porep_config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+
+        let result = verify_batch_seal(porep_config, &[], &[], &[], &[], &[], &[]);
+        assert!(result.unwrap(), "an empty batch is trivially valid");
+
+        let result = verify_batch_seal(
+            porep_config,
+            &[[0; 32]],
+            &[[0; 32]],
+            &[[0; 32]],
+            &[SectorId::from(0)],
+            &[],
+            &[&[]],
+        );
+        assert!(result.is_err(), "mismatched input lengths must be rejected");
+    }
+
     #[test]
     fn test_verify_post_fr32_validation() {
         let not_convertible_to_fr_bytes = [255; 32];
@@ -669,6 +1002,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_verify_post_with_invalid_verifying_key() {
+        let mut replicas = BTreeMap::new();
+        replicas.insert(1.into(), PublicReplicaInfo::new([0; 32]));
+
+        let result = verify_post_with_verifying_key(
+            PoStConfig(SectorSize(SECTOR_SIZE_ONE_KIB)),
+            &[0; 32],
+            &vec![0; SINGLE_PARTITION_PROOF_LEN],
+            &replicas,
+            &[0; 16],
+        );
+
+        assert!(
+            result.is_err(),
+            "a truncated verifying key must be rejected"
+        );
+    }
+
     #[test]
     #[ignore]
     fn test_pip_lifecycle() -> Result<(), failure::Error> {
@@ -690,15 +1042,19 @@ mod tests {
         piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
 
         let mut staged_sector_file = NamedTempFile::new()?;
-        add_piece(
-            &mut piece_file,
+        let piece_info = pieces::add_piece(
+            piece_file.as_file_mut(),
             &mut staged_sector_file,
             number_of_bytes_in_piece,
             &[],
         )?;
+        assert_eq!(piece_info.commitment, comm_p);
 
         let sealed_sector_file = NamedTempFile::new()?;
-        let config = PoRepConfig(SectorSize(sector_size.clone()), PoRepProofPartitions(2));
+        let config = PoRepConfig(
+            SectorSize(sector_size.clone()),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
 
         let output = seal(
             config,
@@ -727,4 +1083,188 @@ mod tests {
 
         Ok(())
     }
+
+    fn staged_sector(config: PoRepConfig) -> Result<NamedTempFile, failure::Error> {
+        let number_of_bytes_in_piece = UnpaddedBytesAmount::from(config);
+        let piece_bytes: Vec<u8> = (0..number_of_bytes_in_piece.0)
+            .map(|_| rand::random::<u8>())
+            .collect();
+
+        let mut piece_file = NamedTempFile::new()?;
+        piece_file.write_all(&piece_bytes)?;
+        piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+        let mut staged_sector_file = NamedTempFile::new()?;
+        pieces::add_piece(
+            piece_file.as_file_mut(),
+            &mut staged_sector_file,
+            number_of_bytes_in_piece,
+            &[],
+        )?;
+
+        Ok(staged_sector_file)
+    }
+
+    #[test]
+    fn test_seal_pre_commit() -> Result<(), failure::Error> {
+        let config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let staged_sector_file = staged_sector(config)?;
+        let cache_dir = tempfile::tempdir()?;
+        let (prover_id, sector_id, ticket) = ([1; 32], SectorId::from(7), [2; 32]);
+        let piece_lengths = [UnpaddedBytesAmount::from(config)];
+
+        let sealed_sector_file = NamedTempFile::new()?;
+        let pre_commit = seal_pre_commit(
+            config,
+            cache_dir.path(),
+            staged_sector_file.path(),
+            sealed_sector_file.path(),
+            prover_id,
+            sector_id,
+            ticket,
+            &piece_lengths,
+        )?;
+
+        // The commit may happen in another process.
+        let pre_commit: SealPreCommitOutput =
+            serde_json::from_str(&serde_json::to_string(&pre_commit)?)?;
+
+        let t_aux = stacked::TemporaryAux::<DefaultTreeHasher>::load(&pre_commit.t_aux)?;
+        assert_eq!(
+            commitment_from_fr::<Bls12>(t_aux.tree_d.root().into()),
+            pre_commit.comm_d
+        );
+
+        // Replication is deterministic, so the commitments match those of a one-shot seal.
+        let other_sealed_sector_file = NamedTempFile::new()?;
+        let (output, _) = seal_commit_phase1(
+            config,
+            staged_sector_file.path(),
+            other_sealed_sector_file.path(),
+            prover_id,
+            sector_id,
+            ticket,
+            &piece_lengths,
+        )?;
+        assert_eq!(output.comm_r, pre_commit.comm_r);
+        assert_eq!(output.comm_d, pre_commit.comm_d);
+        assert_eq!(output.p_aux, pre_commit.p_aux);
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    fn test_seal_pre_commit_and_commit() -> Result<(), failure::Error> {
+        let config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let staged_sector_file = staged_sector(config)?;
+        let sealed_sector_file = NamedTempFile::new()?;
+        let cache_dir = tempfile::tempdir()?;
+        let (prover_id, sector_id, ticket) = ([1; 32], SectorId::from(7), [2; 32]);
+
+        let pre_commit = seal_pre_commit(
+            config,
+            cache_dir.path(),
+            staged_sector_file.path(),
+            sealed_sector_file.path(),
+            prover_id,
+            sector_id,
+            ticket,
+            &[UnpaddedBytesAmount::from(config)],
+        )?;
+        let output = seal_commit(config, prover_id, sector_id, ticket, &pre_commit)?;
+
+        assert_eq!(output.comm_r, pre_commit.comm_r);
+        assert!(verify_seal(
+            config,
+            output.comm_r,
+            output.comm_d,
+            prover_id,
+            sector_id,
+            ticket,
+            &output.proof,
+        )?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unseal_range_to_writer() -> Result<(), failure::Error> {
+        let config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let number_of_bytes_in_piece = UnpaddedBytesAmount::from(config);
+
+        let piece_bytes: Vec<u8> = (0..number_of_bytes_in_piece.0)
+            .map(|_| rand::random::<u8>())
+            .collect();
+
+        let mut piece_file = NamedTempFile::new()?;
+        piece_file.write_all(&piece_bytes)?;
+        piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+        let mut staged_sector_file = NamedTempFile::new()?;
+        pieces::add_piece(
+            piece_file.as_file_mut(),
+            &mut staged_sector_file,
+            number_of_bytes_in_piece,
+            &[],
+        )?;
+
+        let sealed_sector_file = NamedTempFile::new()?;
+        let (prover_id, sector_id, ticket) = ([1; 32], SectorId::from(7), [2; 32]);
+        let (output, _) = seal_commit_phase1(
+            config,
+            staged_sector_file.path(),
+            sealed_sector_file.path(),
+            prover_id,
+            sector_id,
+            ticket,
+            &[number_of_bytes_in_piece],
+        )?;
+
+        let unseal = |offset: u64, len: u64, writer: &mut Vec<u8>| {
+            unseal_range_to_writer(
+                config,
+                sealed_sector_file.path(),
+                None,
+                prover_id,
+                sector_id,
+                output.comm_d,
+                ticket,
+                UnpaddedByteIndex(offset),
+                UnpaddedBytesAmount(len),
+                writer,
+            )
+        };
+
+        // Ranges starting and ending within runs of 4 nodes, and on their boundaries.
+        for &(offset, len) in &[
+            (0, number_of_bytes_in_piece.0),
+            (100, 300),
+            (127, 127),
+            (1000, 16),
+        ] {
+            let mut unsealed = Vec::new();
+            assert_eq!(
+                unseal(offset, len, &mut unsealed)?,
+                UnpaddedBytesAmount(len)
+            );
+            assert_eq!(
+                unsealed.as_slice(),
+                &piece_bytes[offset as usize..(offset + len) as usize]
+            );
+        }
+
+        assert!(unseal(1000, 17, &mut Vec::new()).is_err());
+
+        Ok(())
+    }
 }
@@ -1,6 +1,4 @@
 use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::Read;
 
 use rayon::prelude::*;
 use storage_proofs::circuit::multi_proof::MultiProof;
@@ -14,9 +12,10 @@ use storage_proofs::rational_post;
 use storage_proofs::sector::*;
 
 use crate::api::{as_safe_commitment, ChallengeSeed, Commitment, PersistentAux, Tree};
-use crate::caches::{get_post_params, get_post_verifying_key};
+use crate::caches::{get_post_params, get_post_verifying_key, Bls12VerifyingKey};
 use crate::error;
 use crate::parameters::{post_setup_params, public_params};
+use crate::replica_file::ReplicaFile;
 use crate::singletons::ENGINE_PARAMS;
 use crate::types::{PaddedBytesAmount, PoStConfig};
 
@@ -69,6 +68,10 @@ impl PrivateReplicaInfo {
         as_safe_commitment(&self.comm_r, "comm_r")
     }
 
+    pub fn is_fault(&self) -> bool {
+        self.is_fault
+    }
+
     pub fn safe_comm_c(&self) -> Result<PedersenDomain, failure::Error> {
         Ok(self.aux.comm_c)
     }
@@ -77,13 +80,12 @@ impl PrivateReplicaInfo {
         Ok(self.aux.comm_r_last)
     }
 
-    /// Generate the merkle tree of this particular replica.
+    /// Generate the merkle tree of this particular replica. The replica may be raw or headered,
+    /// see `ReplicaFile::open`.
     pub fn merkle_tree(&self, sector_size: u64) -> Result<Tree, Error> {
-        let mut f_in = File::open(&self.access)?;
-        let mut data = Vec::new();
-        f_in.read_to_end(&mut data)?;
-
         let bytes = PaddedBytesAmount(sector_size as u64);
+        let data = ReplicaFile::open(&self.access, bytes, Some(&self.comm_r))?.read_data()?;
+
         public_params(bytes, 1).graph.merkle_tree(&data)
     }
 }
@@ -128,6 +130,10 @@ impl PublicReplicaInfo {
     pub fn safe_comm_r(&self) -> Result<PedersenDomain, failure::Error> {
         as_safe_commitment(&self.comm_r, "comm_r")
     }
+
+    pub fn is_fault(&self) -> bool {
+        self.is_fault
+    }
 }
 
 /// Generates a proof-of-spacetime.
@@ -144,7 +150,7 @@ pub fn generate_post(
     let vanilla_params = post_setup_params(post_config);
     let setup_params = compound_proof::SetupParams {
         vanilla_params: &vanilla_params,
-        engine_params: &(*ENGINE_PARAMS),
+        engine_params: *ENGINE_PARAMS,
         partitions: None,
     };
 
@@ -252,7 +258,7 @@ pub fn generate_post(
 
     let proof = RationalPoStCompound::prove(&pub_params, &pub_inputs, &priv_inputs, &groth_params)?;
 
-    Ok(proof.to_vec())
+    Ok(proof.as_bytes())
 }
 
 /// Verifies a proof-of-spacetime.
@@ -262,72 +268,133 @@ pub fn verify_post(
     proof: &[u8],
     replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
 ) -> error::Result<bool> {
-    let sector_size = u64::from(PaddedBytesAmount::from(post_config));
-    let sector_count = replicas.len() as u64;
+    let verifying_key = get_post_verifying_key(post_config)?;
+
+    verify_post_inner(post_config, challenge_seed, proof, replicas, &verifying_key)
+}
+
+/// Verifies a proof-of-spacetime against a serialized verifying key supplied by the caller,
+/// e.g. one embedded into a validator with `include_bytes!`.
+///
+/// Unlike `verify_post` this never touches the parameter cache, so it can neither end up
+/// generating parameters nor write to disk. Apart from the verifying key and the proof, memory
+/// use only grows with the challenge count of `post_config`, and no work is spread across
+/// threads.
+pub fn verify_post_with_verifying_key(
+    post_config: PoStConfig,
+    challenge_seed: &ChallengeSeed,
+    proof: &[u8],
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    verifying_key: &[u8],
+) -> error::Result<bool> {
+    let verifying_key = Bls12VerifyingKey::read(verifying_key)?;
 
+    verify_post_inner(post_config, challenge_seed, proof, replicas, &verifying_key)
+}
+
+fn verify_post_inner(
+    post_config: PoStConfig,
+    challenge_seed: &ChallengeSeed,
+    proof: &[u8],
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    verifying_key: &Bls12VerifyingKey,
+) -> error::Result<bool> {
     let vanilla_params = post_setup_params(post_config);
     let setup_params = compound_proof::SetupParams {
         vanilla_params: &vanilla_params,
-        engine_params: &(*ENGINE_PARAMS),
+        engine_params: *ENGINE_PARAMS,
         partitions: None,
     };
 
-    let sectors = replicas.keys().copied().collect();
-    let faults = replicas
-        .iter()
-        .filter_map(
-            |(id, replica)| {
-                if replica.is_fault {
-                    Some(*id)
-                } else {
-                    None
-                }
-            },
-        )
-        .collect();
-
-    let challenges = rational_post::derive_challenges(
-        vanilla_params.challenges_count,
-        sector_size,
-        &sectors,
-        challenge_seed,
-        &faults,
-    )?;
-
-    // Match the replicas to the challenges, as these are the only ones required.
-    let comm_rs: Vec<_> = challenges
-        .iter()
-        .map(|c| {
-            if let Some(replica) = replicas.get(&c.sector) {
-                replica.safe_comm_r()
-            } else {
-                Err(format_err!(
-                    "Invalid challenge generated: {}, only {} sectors are being proven",
-                    c.sector,
-                    sector_count
-                ))
-            }
-        })
-        .collect::<Result<_, _>>()?;
+    let inputs = PoStInputs::new(post_config, challenge_seed, replicas)?;
 
     let public_params: compound_proof::PublicParams<
         _,
         rational_post::RationalPoSt<PedersenHasher>,
     > = RationalPoStCompound::setup(&setup_params)?;
 
-    let public_inputs = rational_post::PublicInputs::<PedersenDomain> {
-        challenges: &challenges,
-        comm_rs: &comm_rs,
-        faults: &faults,
-    };
-
-    let verifying_key = get_post_verifying_key(post_config)?;
+    let proof = MultiProof::from_bytes(None, &proof[..], verifying_key)?;
 
-    let proof = MultiProof::new_from_reader(None, &proof[..], &verifying_key)?;
-
-    let is_valid =
-        RationalPoStCompound::verify(&public_params, &public_inputs, &proof, &NoRequirements)?;
+    let is_valid = RationalPoStCompound::verify(
+        &public_params,
+        &inputs.public_inputs(),
+        &proof,
+        &NoRequirements,
+    )?;
 
     // Since callers may rely on previous mocked success, just pretend verification succeeded, for now.
     Ok(is_valid)
 }
+
+/// The challenges of a proof-of-spacetime over some replicas, with the faults and the
+/// commitments of the challenged replicas, which together make up its public inputs.
+pub(crate) struct PoStInputs {
+    challenges: Vec<rational_post::Challenge>,
+    comm_rs: Vec<PedersenDomain>,
+    faults: OrderedSectorSet,
+}
+
+impl PoStInputs {
+    pub(crate) fn new(
+        post_config: PoStConfig,
+        challenge_seed: &ChallengeSeed,
+        replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    ) -> error::Result<Self> {
+        let sector_size = u64::from(PaddedBytesAmount::from(post_config));
+        let sector_count = replicas.len() as u64;
+
+        let vanilla_params = post_setup_params(post_config);
+
+        let sectors = replicas.keys().copied().collect();
+        let faults = replicas
+            .iter()
+            .filter_map(
+                |(id, replica)| {
+                    if replica.is_fault {
+                        Some(*id)
+                    } else {
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        let challenges = rational_post::derive_challenges(
+            vanilla_params.challenges_count,
+            sector_size,
+            &sectors,
+            challenge_seed,
+            &faults,
+        )?;
+
+        // Match the replicas to the challenges, as these are the only ones required.
+        let comm_rs: Vec<_> = challenges
+            .iter()
+            .map(|c| {
+                if let Some(replica) = replicas.get(&c.sector) {
+                    replica.safe_comm_r()
+                } else {
+                    Err(format_err!(
+                        "Invalid challenge generated: {}, only {} sectors are being proven",
+                        c.sector,
+                        sector_count
+                    ))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(PoStInputs {
+            challenges,
+            comm_rs,
+            faults,
+        })
+    }
+
+    pub(crate) fn public_inputs(&self) -> rational_post::PublicInputs<PedersenDomain> {
+        rational_post::PublicInputs {
+            challenges: &self.challenges,
+            comm_rs: &self.comm_rs,
+            faults: &self.faults,
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use storage_proofs::sector::SectorId;
+
+use crate::api::{as_safe_commitment, Commitment, PersistentAux};
+use crate::api::{PrivateReplicaInfo, PublicReplicaInfo};
+use crate::error;
+use crate::types::{PaddedBytesAmount, PoStConfig};
+
+/// Everything the prover needs to know about the sectors of one PoSt, as a single serializable
+/// artifact.
+///
+/// Sectors are kept ordered by id, so two sets with the same content always serialize
+/// identically.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvingSet {
+    sector_size: u64,
+    sectors: Vec<ProvingSetSector>,
+}
+
+/// A single sector of a `ProvingSet`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvingSetSector {
+    pub sector_id: SectorId,
+    pub comm_r: Commitment,
+    pub aux: PersistentAux,
+    /// Path to the sealed replica.
+    pub replica_path: PathBuf,
+    /// Is this sector declared faulty?
+    pub is_fault: bool,
+}
+
+/// The sectors which differ between two proving sets, each in ascending order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProvingSetDiff {
+    /// Sectors only in the new set.
+    pub added: Vec<SectorId>,
+    /// Sectors only in the old set.
+    pub removed: Vec<SectorId>,
+    /// Sectors in both sets, but with a different entry.
+    pub changed: Vec<SectorId>,
+}
+
+impl ProvingSetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl ProvingSet {
+    pub fn new(post_config: PoStConfig) -> Self {
+        ProvingSet {
+            sector_size: u64::from(PaddedBytesAmount::from(post_config)),
+            sectors: Vec::new(),
+        }
+    }
+
+    pub fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+
+    pub fn sectors(&self) -> &[ProvingSetSector] {
+        &self.sectors
+    }
+
+    pub fn len(&self) -> usize {
+        self.sectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sectors.is_empty()
+    }
+
+    pub fn get(&self, sector_id: SectorId) -> Option<&ProvingSetSector> {
+        self.position(sector_id)
+            .ok()
+            .map(|index| &self.sectors[index])
+    }
+
+    /// Adds `sector`, failing if the set already contains a sector with the same id.
+    pub fn insert(&mut self, sector: ProvingSetSector) -> error::Result<()> {
+        match self.position(sector.sector_id) {
+            Ok(_) => Err(format_err!(
+                "sector {} is already part of the proving set",
+                sector.sector_id
+            )),
+            Err(index) => {
+                self.sectors.insert(index, sector);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn remove(&mut self, sector_id: SectorId) -> Option<ProvingSetSector> {
+        self.position(sector_id)
+            .ok()
+            .map(|index| self.sectors.remove(index))
+    }
+
+    fn position(&self, sector_id: SectorId) -> Result<usize, usize> {
+        self.sectors
+            .binary_search_by_key(&sector_id, |sector| sector.sector_id)
+    }
+
+    /// Checks that the set matches `post_config`, and that it can actually be proven: every
+    /// commitment is valid, and every sector which is not faulty has a replica of the right size.
+    pub fn validate(&self, post_config: PoStConfig) -> error::Result<()> {
+        let sector_size = u64::from(PaddedBytesAmount::from(post_config));
+        ensure!(
+            self.sector_size == sector_size,
+            "proving set is for sectors of {} bytes, not {}",
+            self.sector_size,
+            sector_size
+        );
+
+        // Sets read from untrusted input are not necessarily ordered.
+        ensure!(
+            self.sectors
+                .windows(2)
+                .all(|pair| pair[0].sector_id < pair[1].sector_id),
+            "sectors of the proving set are not ordered or not unique"
+        );
+
+        for sector in &self.sectors {
+            as_safe_commitment(&sector.comm_r, "comm_r")?;
+
+            if sector.is_fault {
+                continue;
+            }
+
+            let metadata = fs::metadata(&sector.replica_path).map_err(|err| {
+                format_err!(
+                    "replica of sector {} at {}: {}",
+                    sector.sector_id,
+                    sector.replica_path.display(),
+                    err
+                )
+            })?;
+            ensure!(
+                metadata.len() == sector_size,
+                "replica of sector {} has {} bytes, expected {}",
+                sector.sector_id,
+                metadata.len(),
+                sector_size
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the sectors which were added, removed or changed going from `self` to `other`.
+    pub fn diff(&self, other: &ProvingSet) -> ProvingSetDiff {
+        let old: BTreeMap<_, _> = self.sectors.iter().map(|s| (s.sector_id, s)).collect();
+        let new: BTreeMap<_, _> = other.sectors.iter().map(|s| (s.sector_id, s)).collect();
+        let ids: BTreeSet<_> = old.keys().chain(new.keys()).copied().collect();
+
+        let mut diff = ProvingSetDiff::default();
+        for id in ids {
+            match (old.get(&id), new.get(&id)) {
+                (None, Some(_)) => diff.added.push(id),
+                (Some(_), None) => diff.removed.push(id),
+                (Some(a), Some(b)) if a != b => diff.changed.push(id),
+                _ => {}
+            }
+        }
+
+        diff
+    }
+
+    /// Returns the replicas as expected by `generate_post`.
+    pub fn private_replicas(&self) -> BTreeMap<SectorId, PrivateReplicaInfo> {
+        self.sectors
+            .iter()
+            .map(|sector| {
+                let access = sector.replica_path.to_string_lossy().into_owned();
+                let info = if sector.is_fault {
+                    PrivateReplicaInfo::new_faulty(access, sector.comm_r, sector.aux.clone())
+                } else {
+                    PrivateReplicaInfo::new(access, sector.comm_r, sector.aux.clone())
+                };
+                (sector.sector_id, info)
+            })
+            .collect()
+    }
+
+    /// Returns the replicas as expected by `verify_post`.
+    pub fn public_replicas(&self) -> BTreeMap<SectorId, PublicReplicaInfo> {
+        self.sectors
+            .iter()
+            .map(|sector| {
+                let info = if sector.is_fault {
+                    PublicReplicaInfo::new_faulty(sector.comm_r)
+                } else {
+                    PublicReplicaInfo::new(sector.comm_r)
+                };
+                (sector.sector_id, info)
+            })
+            .collect()
+    }
+
+    /// Serializes the set into its compact binary (CBOR) form.
+    pub fn to_bytes(&self) -> error::Result<Vec<u8>> {
+        Ok(serde_cbor::to_vec(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> error::Result<Self> {
+        Ok(serde_cbor::from_slice(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::constants::SECTOR_SIZE_ONE_KIB;
+    use crate::types::SectorSize;
+
+    fn sector(id: u64, replica_path: PathBuf) -> ProvingSetSector {
+        ProvingSetSector {
+            sector_id: SectorId::from(id),
+            comm_r: [id as u8; 32],
+            aux: PersistentAux::default(),
+            replica_path,
+            is_fault: false,
+        }
+    }
+
+    #[test]
+    fn test_proving_set() {
+        let post_config = PoStConfig(SectorSize(SECTOR_SIZE_ONE_KIB));
+        let dir = tempfile::tempdir().unwrap();
+        let replica = dir.path().join("replica");
+        fs::write(&replica, vec![0; SECTOR_SIZE_ONE_KIB as usize]).unwrap();
+
+        let mut set = ProvingSet::new(post_config);
+        set.insert(sector(3, replica.clone())).unwrap();
+        set.insert(sector(1, replica.clone())).unwrap();
+        assert!(set.insert(sector(3, replica.clone())).is_err());
+        assert_eq!(set.sectors()[0].sector_id, SectorId::from(1));
+        set.validate(post_config).expect("valid proving set");
+
+        let decoded = ProvingSet::from_bytes(&set.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, set);
+        assert!(set.diff(&decoded).is_empty());
+
+        let mut other = set.clone();
+        other.remove(SectorId::from(1)).unwrap();
+        other.insert(sector(2, replica.clone())).unwrap();
+        other.remove(SectorId::from(3)).unwrap();
+        other
+            .insert(ProvingSetSector {
+                is_fault: true,
+                ..sector(3, replica.clone())
+            })
+            .unwrap();
+
+        let diff = set.diff(&other);
+        assert_eq!(diff.added, vec![SectorId::from(2)]);
+        assert_eq!(diff.removed, vec![SectorId::from(1)]);
+        assert_eq!(diff.changed, vec![SectorId::from(3)]);
+
+        assert!(other.public_replicas()[&SectorId::from(3)].is_fault());
+        assert!(!other.private_replicas()[&SectorId::from(2)].is_fault());
+    }
+
+    #[test]
+    fn test_proving_set_validation() {
+        let post_config = PoStConfig(SectorSize(SECTOR_SIZE_ONE_KIB));
+        let dir = tempfile::tempdir().unwrap();
+        let replica = dir.path().join("replica");
+        fs::write(&replica, vec![0; 16]).unwrap();
+
+        // The replica is too short.
+        let mut set = ProvingSet::new(post_config);
+        set.insert(sector(1, replica.clone())).unwrap();
+        assert!(set.validate(post_config).is_err());
+
+        // Faulty sectors don't need a replica.
+        let mut set = ProvingSet::new(post_config);
+        set.insert(ProvingSetSector {
+            is_fault: true,
+            ..sector(1, dir.path().join("missing"))
+        })
+        .unwrap();
+        set.validate(post_config).expect("valid proving set");
+
+        assert!(set
+            .validate(PoStConfig(SectorSize(SECTOR_SIZE_ONE_KIB * 2)))
+            .is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs;
+
+use storage_proofs::gpu::GPU_ENABLED;
+use storage_proofs::resources::{estimate_replication, PhaseResources};
+
+use crate::constants::{sector_size_constants, LAYERS};
+use crate::types::{PaddedBytesAmount, PoRepConfig};
+
+pub use storage_proofs::resources::{available_memory, LayerStorage};
+
+/// The peak resources `seal` needs, per phase. Labeling and building the trees correspond to
+/// what is elsewhere called PreCommit 1 and 2, the vanilla and circuit proofs to Commit 1 and 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceEstimate {
+    pub labeling: PhaseResources,
+    pub trees: PhaseResources,
+    pub vanilla_proof: PhaseResources,
+    pub circuit_proof: PhaseResources,
+}
+
+impl ResourceEstimate {
+    pub fn peak_memory(&self) -> u64 {
+        self.phases()
+            .iter()
+            .map(|phase| phase.memory)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn peak_disk(&self) -> u64 {
+        self.phases()
+            .iter()
+            .map(|phase| phase.disk)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn peak_gpu_memory(&self) -> u64 {
+        self.phases()
+            .iter()
+            .map(|phase| phase.gpu_memory)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn phases(&self) -> [PhaseResources; 4] {
+        [
+            self.labeling,
+            self.trees,
+            self.vanilla_proof,
+            self.circuit_proof,
+        ]
+    }
+}
+
+/// Estimates the peak memory, disk and GPU memory of sealing and proving a sector of
+/// `porep_config`, with the layers kept as `mode` says, see
+/// `storage_proofs::resources::estimate_replication`.
+///
+/// Circuit proofs load the Groth parameters, so they are estimated by the size of the parameter
+/// file, if it is in the cache, and are 0 otherwise. With the `gpu` feature, the parameters are
+/// moved to the GPU as well.
+pub fn estimate_resources(porep_config: PoRepConfig, mode: LayerStorage) -> ResourceEstimate {
+    let sector_bytes = u64::from(PaddedBytesAmount::from(porep_config));
+    let layers = sector_size_constants(sector_bytes).map_or(LAYERS, |c| c.layers);
+    let replication = estimate_replication(sector_bytes, layers, mode);
+
+    let params_bytes =
+        fs::metadata(porep_config.get_cache_params_path()).map_or(0, |metadata| metadata.len());
+
+    ResourceEstimate {
+        labeling: replication.labeling,
+        trees: replication.trees,
+        vanilla_proof: replication.vanilla_proof,
+        circuit_proof: PhaseResources {
+            memory: params_bytes,
+            disk: params_bytes,
+            gpu_memory: if GPU_ENABLED { params_bytes } else { 0 },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::{PoRepProofPartitions, SectorSize};
+
+    #[test]
+    fn test_estimate_resources() {
+        let sector_size = 1024;
+        let config = PoRepConfig(SectorSize(sector_size), PoRepProofPartitions(1));
+
+        let in_memory = estimate_resources(config, LayerStorage::Memory);
+        let on_disk = estimate_resources(config, LayerStorage::Disk);
+
+        assert!(in_memory.labeling.memory > on_disk.labeling.memory);
+        assert!(on_disk.labeling.memory >= 2 * sector_size);
+        assert!(on_disk.trees.disk > in_memory.trees.disk);
+        assert!(on_disk.peak_memory() >= on_disk.labeling.memory);
+        assert_eq!(in_memory.circuit_proof, on_disk.circuit_proof);
+    }
+}
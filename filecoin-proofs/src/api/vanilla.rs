@@ -0,0 +1,154 @@
+use storage_proofs::drgraph::DefaultTreeHasher;
+use storage_proofs::sector::SectorId;
+use storage_proofs::stacked::{self, generate_replica_id, verify_seal_proofs, Tau};
+
+use crate::api::{as_safe_commitment, Commitment, ProverId, Ticket};
+use crate::constants::POREP_MINIMUM_CHALLENGES;
+use crate::error;
+use crate::parameters::{public_params, validate_partitions};
+use crate::types::{PaddedBytesAmount, PoRepConfig};
+
+/// The vanilla (non-SNARK) proofs of a single partition of a seal.
+pub type VanillaSealProof = Vec<stacked::Proof<DefaultTreeHasher>>;
+
+/// Verifies the vanilla proofs of all partitions of a seal, without going through the circuit.
+/// Neither Groth parameters nor verifying keys are required, which makes this suitable for
+/// checking proofs before they are handed off to SNARK generation. Services which link only
+/// `storage-proofs` can call `stacked::verify_seal_proofs`, which this wraps, directly.
+///
+pub fn verify_vanilla_seal_proofs(
+    porep_config: PoRepConfig,
+    comm_r: Commitment,
+    comm_d: Commitment,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    partition_proofs: &[VanillaSealProof],
+) -> error::Result<bool> {
+    let partitions = porep_config.partitions();
+    validate_partitions(partitions)?;
+
+    let comm_r = as_safe_commitment(&comm_r, "comm_r")?;
+    let comm_d = as_safe_commitment(&comm_d, "comm_d")?;
+
+    let replica_id =
+        generate_replica_id::<DefaultTreeHasher>(&prover_id, sector_id.into(), &ticket, comm_d);
+
+    let vanilla_params = public_params(PaddedBytesAmount::from(porep_config), partitions);
+
+    verify_seal_proofs(
+        &vanilla_params,
+        partitions,
+        POREP_MINIMUM_CHALLENGES,
+        replica_id,
+        Tau { comm_r, comm_d },
+        partition_proofs,
+    )
+    .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paired::bls12_381::Bls12;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use storage_proofs::drgraph::Graph;
+    use storage_proofs::fr32::fr_into_bytes;
+    use storage_proofs::porep::PoRep;
+    use storage_proofs::proof::ProofScheme;
+    use storage_proofs::stacked::StackedDrg;
+
+    use crate::api::commitment_from_fr;
+    use crate::constants::{DEFAULT_POREP_PROOF_PARTITIONS, SECTOR_SIZE_ONE_KIB};
+    use crate::types::SectorSize;
+
+    #[test]
+    fn test_verify_vanilla_seal_proofs() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let porep_config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let partitions = porep_config.partitions();
+        let sector_bytes = PaddedBytesAmount::from(porep_config);
+
+        let prover_id = [1; 32];
+        let sector_id = SectorId::from(7);
+        let ticket = [2; 32];
+
+        let mut data: Vec<u8> = (0..usize::from(sector_bytes) / 32)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let pp = public_params(sector_bytes, partitions);
+        let data_tree = pp
+            .graph
+            .merkle_tree(&data)
+            .expect("failed to build data tree");
+        let replica_id = generate_replica_id::<DefaultTreeHasher>(
+            &prover_id,
+            sector_id.into(),
+            &ticket,
+            data_tree.root(),
+        );
+
+        let (tau, (p_aux, t_aux)) = StackedDrg::replicate(
+            &pp,
+            &replica_id,
+            data.as_mut_slice().into(),
+            Some(data_tree),
+        )
+        .expect("replication failed");
+
+        let public_inputs = stacked::PublicInputs {
+            replica_id,
+            tau: Some(tau.clone()),
+            seed: None,
+            k: None,
+        };
+        let private_inputs = stacked::PrivateInputs::<DefaultTreeHasher> { p_aux, t_aux };
+
+        let proofs = StackedDrg::<DefaultTreeHasher>::prove_all_partitions(
+            &pp,
+            &public_inputs,
+            &private_inputs,
+            partitions,
+        )
+        .expect("failed to generate vanilla proofs");
+
+        let comm_r = commitment_from_fr::<Bls12>(tau.comm_r.into());
+        let comm_d = commitment_from_fr::<Bls12>(tau.comm_d.into());
+
+        let verify = |ticket: Ticket, proofs: &[VanillaSealProof]| {
+            verify_vanilla_seal_proofs(
+                porep_config,
+                comm_r,
+                comm_d,
+                prover_id,
+                sector_id,
+                ticket,
+                proofs,
+            )
+            .expect("failed to verify vanilla proofs")
+        };
+
+        assert!(verify(ticket, &proofs), "valid proofs did not verify");
+        assert!(
+            !verify([3; 32], &proofs),
+            "proofs verified for another ticket"
+        );
+        assert!(
+            !verify(ticket, &proofs[..1]),
+            "proofs verified with a missing partition"
+        );
+
+        let mut truncated = proofs.clone();
+        truncated[0].pop();
+        assert!(
+            !verify(ticket, &truncated),
+            "proofs verified with a missing challenge"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+
+use paired::bls12_381::Bls12;
+use storage_proofs::circuit::multi_proof::MultiProof;
+use storage_proofs::circuit::rational_post::{RationalPoStCircuit, RationalPoStCompound};
+use storage_proofs::circuit::stacked::StackedCompound;
+use storage_proofs::compound_proof::CompoundProof;
+use storage_proofs::hasher::PedersenHasher;
+use storage_proofs::rational_post::RationalPoSt;
+use storage_proofs::sector::SectorId;
+
+use crate::api::post::{PoStInputs, PublicReplicaInfo};
+use crate::api::{seal_public_inputs, ChallengeSeed, Commitment, ProverId, Ticket};
+use crate::caches::{get_post_verifying_key, get_stacked_verifying_key};
+use crate::error;
+use crate::parameters::{porep_public_params, post_public_params};
+use crate::types::{PaddedBytesAmount, PoRepConfig, PoStConfig};
+
+pub use storage_proofs::verifier_export::VerifierExport;
+
+/// A seal proof, with everything `verify_seal` checks it against.
+#[derive(Clone, Copy, Debug)]
+pub struct SealSample<'a> {
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub prover_id: ProverId,
+    pub sector_id: SectorId,
+    pub ticket: Ticket,
+    pub proof: &'a [u8],
+}
+
+/// A proof-of-spacetime, with everything `verify_post` checks it against.
+#[derive(Clone, Copy, Debug)]
+pub struct PoStSample<'a> {
+    pub challenge_seed: ChallengeSeed,
+    pub replicas: &'a BTreeMap<SectorId, PublicReplicaInfo>,
+    pub proof: &'a [u8],
+}
+
+/// Exports the verifying key of seal proofs for `porep_config`, for verifiers outside of Rust,
+/// see `storage_proofs::verifier_export`.
+///
+/// With a `sample`, its circuit proofs are added to the export, with the public inputs of each
+/// partition, and the export is self-checked: re-imported from its JSON, and every circuit proof
+/// verified with the re-imported key. An export which fails the check is an error.
+pub fn export_seal_verifier(
+    porep_config: PoRepConfig,
+    sample: Option<SealSample>,
+) -> error::Result<VerifierExport> {
+    let compound_public_params = porep_public_params(porep_config)?;
+    let verifying_key = get_stacked_verifying_key(porep_config)?;
+
+    let mut export = VerifierExport::new(
+        &format!(
+            "STACKED[{}]",
+            usize::from(PaddedBytesAmount::from(porep_config))
+        ),
+        StackedCompound::partition_count(&compound_public_params),
+        &verifying_key,
+    );
+
+    if let Some(sample) = sample {
+        let public_inputs = seal_public_inputs(
+            &sample.comm_r,
+            &sample.comm_d,
+            &sample.prover_id,
+            sample.sector_id,
+            &sample.ticket,
+        )?;
+        let proof = MultiProof::from_bytes(
+            compound_public_params.partitions,
+            sample.proof,
+            &verifying_key,
+        )?;
+
+        for (k, circuit_proof) in proof.circuit_proofs.iter().enumerate() {
+            let inputs = StackedCompound::generate_public_inputs(
+                &public_inputs,
+                &compound_public_params.vanilla_params,
+                Some(k),
+            );
+            export.add_sample(circuit_proof, &inputs)?;
+        }
+
+        export.self_check()?;
+    }
+
+    Ok(export)
+}
+
+/// Exports the verifying key of proofs-of-spacetime for `post_config`, like
+/// `export_seal_verifier`. A PoSt consists of a single circuit proof.
+pub fn export_post_verifier(
+    post_config: PoStConfig,
+    sample: Option<PoStSample>,
+) -> error::Result<VerifierExport> {
+    let verifying_key = get_post_verifying_key(post_config)?;
+
+    let mut export = VerifierExport::new(
+        &format!(
+            "POST[{}]",
+            usize::from(PaddedBytesAmount::from(post_config))
+        ),
+        1,
+        &verifying_key,
+    );
+
+    if let Some(sample) = sample {
+        let inputs = PoStInputs::new(post_config, &sample.challenge_seed, sample.replicas)?;
+        let proof = MultiProof::from_bytes(None, sample.proof, &verifying_key)?;
+
+        let public_inputs = <RationalPoStCompound<PedersenHasher> as CompoundProof<
+            Bls12,
+            RationalPoSt<PedersenHasher>,
+            RationalPoStCircuit<Bls12, PedersenHasher>,
+        >>::generate_public_inputs(
+            &inputs.public_inputs(),
+            &post_public_params(post_config),
+            Some(0),
+        );
+        export.add_sample(&proof.circuit_proofs[0], &public_inputs)?;
+
+        export.self_check()?;
+    }
+
+    Ok(export)
+}
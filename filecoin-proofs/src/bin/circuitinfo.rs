@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::io::stdout;
+
+use bellperson::Circuit;
+use clap::{value_t, values_t, App, Arg};
+use paired::bls12_381::Bls12;
+use serde::Serialize;
+
+use filecoin_proofs::constants::SECTOR_SIZE_CONSTANTS;
+use filecoin_proofs::parameters::{post_public_params, public_params};
+use filecoin_proofs::singletons::ENGINE_PARAMS;
+use filecoin_proofs::types::*;
+use storage_proofs::circuit::count::{CountingCS, GadgetCounts};
+use storage_proofs::circuit::rational_post::{RationalPoStCircuit, RationalPoStCompound};
+use storage_proofs::circuit::stacked::StackedCompound;
+use storage_proofs::compound_proof::CompoundProof;
+use storage_proofs::hasher::pedersen::PedersenHasher;
+use storage_proofs::rational_post::RationalPoSt;
+
+/// The size of one partition circuit, broken down by gadget.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct CircuitInfo {
+    sector_size: u64,
+    partitions: usize,
+    total: GadgetCounts,
+    gadgets: BTreeMap<String, GadgetCounts>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Report {
+    porep: Vec<CircuitInfo>,
+    post: Vec<CircuitInfo>,
+}
+
+fn count<C: Circuit<Bls12>>(
+    circuit: C,
+    depth: usize,
+) -> (GadgetCounts, BTreeMap<String, GadgetCounts>) {
+    let mut cs = CountingCS::<Bls12>::new();
+    circuit
+        .synthesize(&mut cs)
+        .expect("failed to synthesize circuit");
+
+    (cs.total(), cs.rollup(depth))
+}
+
+fn porep_info(porep_config: PoRepConfig, depth: usize) -> CircuitInfo {
+    let partitions = porep_config.partitions();
+    let public_params = public_params(PaddedBytesAmount::from(porep_config), partitions);
+    let circuit = StackedCompound::blank_circuit(&public_params, *ENGINE_PARAMS);
+    let (total, gadgets) = count(circuit, depth);
+
+    CircuitInfo {
+        sector_size: u64::from(PaddedBytesAmount::from(porep_config)),
+        partitions,
+        total,
+        gadgets,
+    }
+}
+
+fn post_info(post_config: PoStConfig, depth: usize) -> CircuitInfo {
+    let post_public_params = post_public_params(post_config);
+    let circuit: RationalPoStCircuit<Bls12, PedersenHasher> =
+        <RationalPoStCompound<PedersenHasher> as CompoundProof<
+            Bls12,
+            RationalPoSt<PedersenHasher>,
+            RationalPoStCircuit<Bls12, PedersenHasher>,
+        >>::blank_circuit(&post_public_params, *ENGINE_PARAMS);
+    let (total, gadgets) = count(circuit, depth);
+
+    CircuitInfo {
+        sector_size: u64::from(PaddedBytesAmount::from(post_config)),
+        partitions: 1,
+        total,
+        gadgets,
+    }
+}
+
+// Run this from the command-line to see where the constraints of the PoRep and PoSt circuits go.
+pub fn main() {
+    pretty_env_logger::init_timed();
+
+    let matches = App::new("circuitinfo")
+        .version("0.1")
+        .about("Count the constraints, inputs and aux variables of the PoRep and PoSt circuits per gadget")
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .help("Sector sizes in bytes to report on, defaults to all sizes with published parameters")
+                .require_delimiter(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("depth")
+                .long("depth")
+                .help("How many namespaces deep to break down the counts")
+                .default_value("2")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let sizes: Vec<u64> = if matches.is_present("size") {
+        values_t!(matches, "size", u64).unwrap_or_else(|e| e.exit())
+    } else {
+        SECTOR_SIZE_CONSTANTS
+            .iter()
+            .map(|constants| constants.sector_size)
+            .collect()
+    };
+    let depth = value_t!(matches, "depth", usize).unwrap_or_else(|e| e.exit());
+
+    let mut report = Report {
+        porep: Vec::new(),
+        post: Vec::new(),
+    };
+
+    for size in sizes {
+        let sector_size = SectorSize(size);
+        let registered_proof = RegisteredSealProof::from_sector_size(sector_size)
+            .unwrap_or_else(|| panic!("no seal proof is registered for {} bytes", size));
+
+        report.porep.push(porep_info(
+            PoRepConfig(sector_size, registered_proof.partitions()),
+            depth,
+        ));
+        report.post.push(post_info(PoStConfig(sector_size), depth));
+    }
+
+    serde_json::to_writer(stdout(), &report).expect("cannot write report JSON to stdout");
+}
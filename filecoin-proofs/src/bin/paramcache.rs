@@ -14,14 +14,18 @@ use storage_proofs::compound_proof::CompoundProof;
 use storage_proofs::hasher::pedersen::PedersenHasher;
 use storage_proofs::parameter_cache::CacheableParameters;
 use storage_proofs::rational_post::RationalPoSt;
-
-const POREP_PROOF_PARTITION_CHOICES: [PoRepProofPartitions; 1] = [PoRepProofPartitions(2)];
-
-const PUBLISHED_SECTOR_SIZES: [u64; 4] = [
-    SECTOR_SIZE_ONE_KIB,
-    SECTOR_SIZE_16_MIB,
-    SECTOR_SIZE_256_MIB,
-    SECTOR_SIZE_1_GIB,
+use storage_proofs::stacked::{
+    parent_cache_path, ParentCache, ParentCacheManifest, ParentCacheManifestEntry,
+};
+
+const POREP_PROOF_PARTITION_CHOICES: [PoRepProofPartitions; 1] = [DEFAULT_POREP_PROOF_PARTITIONS];
+
+const PUBLISHED_SECTOR_SIZES: [u64; 5] = [
+    SECTOR_SIZE_2_KIB,
+    SECTOR_SIZE_8_MIB,
+    SECTOR_SIZE_512_MIB,
+    SECTOR_SIZE_32_GIB,
+    SECTOR_SIZE_64_GIB,
 ];
 
 fn cache_porep_params(porep_config: PoRepConfig) {
@@ -33,21 +37,20 @@ fn cache_porep_params(porep_config: PoRepConfig) {
 
     let public_params = public_params(
         PaddedBytesAmount::from(porep_config),
-        usize::from(PoRepProofPartitions::from(porep_config)),
+        porep_config.partitions(),
     );
 
     {
-        let circuit = StackedCompound::blank_circuit(&public_params, &ENGINE_PARAMS);
+        let circuit = StackedCompound::blank_circuit(&public_params, *ENGINE_PARAMS);
         let _ = StackedCompound::get_param_metadata(circuit, &public_params);
     }
-    {
-        let circuit = StackedCompound::blank_circuit(&public_params, &ENGINE_PARAMS);
-        let _ = StackedCompound::get_groth_params(circuit, &public_params);
-    }
-    {
-        let circuit = StackedCompound::blank_circuit(&public_params, &ENGINE_PARAMS);
-        let _ = StackedCompound::get_verifying_key(circuit, &public_params);
-    }
+    let blank_circuit = || StackedCompound::blank_circuit(&public_params, *ENGINE_PARAMS);
+    let _ = StackedCompound::get_groth_params(blank_circuit, &public_params);
+    let _ = StackedCompound::get_verifying_key(blank_circuit, &public_params);
+
+    porep_config
+        .check_circuit_identifier()
+        .expect("cached PoRep parameters were generated for another circuit");
 }
 
 fn cache_post_params(post_config: PoStConfig) {
@@ -65,40 +68,57 @@ fn cache_post_params(post_config: PoStConfig) {
                 Bls12,
                 RationalPoSt<PedersenHasher>,
                 RationalPoStCircuit<Bls12, PedersenHasher>,
-            >>::blank_circuit(&post_public_params, &ENGINE_PARAMS);
+            >>::blank_circuit(&post_public_params, *ENGINE_PARAMS);
         let _ = <RationalPoStCompound<PedersenHasher>>::get_param_metadata(
             post_circuit,
             &post_public_params,
         )
         .expect("failed to get metadata");
     }
-    {
-        let post_circuit: RationalPoStCircuit<Bls12, PedersenHasher> =
-            <RationalPoStCompound<PedersenHasher> as CompoundProof<
-                Bls12,
-                RationalPoSt<PedersenHasher>,
-                RationalPoStCircuit<Bls12, PedersenHasher>,
-            >>::blank_circuit(&post_public_params, &ENGINE_PARAMS);
-        let _ = <RationalPoStCompound<PedersenHasher>>::get_groth_params(
-            post_circuit,
-            &post_public_params,
-        )
-        .expect("failed to get groth params");
-    }
-    {
-        let post_circuit: RationalPoStCircuit<Bls12, PedersenHasher> =
-            <RationalPoStCompound<PedersenHasher> as CompoundProof<
-                Bls12,
-                RationalPoSt<PedersenHasher>,
-                RationalPoStCircuit<Bls12, PedersenHasher>,
-            >>::blank_circuit(&post_public_params, &ENGINE_PARAMS);
+    let blank_post_circuit = || -> RationalPoStCircuit<Bls12, PedersenHasher> {
+        <RationalPoStCompound<PedersenHasher> as CompoundProof<
+            Bls12,
+            RationalPoSt<PedersenHasher>,
+            RationalPoStCircuit<Bls12, PedersenHasher>,
+        >>::blank_circuit(&post_public_params, *ENGINE_PARAMS)
+    };
+    let _ = <RationalPoStCompound<PedersenHasher>>::get_groth_params(
+        blank_post_circuit,
+        &post_public_params,
+    )
+    .expect("failed to get groth params");
+    let _ = <RationalPoStCompound<PedersenHasher>>::get_verifying_key(
+        blank_post_circuit,
+        &post_public_params,
+    )
+    .expect("failed to get verifying key");
+
+    post_config
+        .check_circuit_identifier()
+        .expect("cached PoSt parameters were generated for another circuit");
+}
 
-        let _ = <RationalPoStCompound<PedersenHasher>>::get_verifying_key(
-            post_circuit,
-            &post_public_params,
-        )
-        .expect("failed to get verifying key");
-    }
+// Generates the parent caches of the given sector sizes, and prints the manifest of their digests,
+// the content of `storage-proofs/parent_cache.json`. The caches are checked against their own
+// digests only, so the manifest can be regenerated after the graphs changed.
+fn print_parent_cache_manifest(sizes: &[u64]) {
+    let manifest: ParentCacheManifest = sizes
+        .iter()
+        .map(|size| {
+            let public_params = public_params(PaddedBytesAmount(*size), 1);
+            let graph = &public_params.graph;
+
+            let cache = ParentCache::generate_and_verify(&parent_cache_path(graph), graph, None)
+                .expect("failed to generate parent cache");
+
+            ParentCacheManifestEntry::new(&public_params.porep_id, *size, cache.digest())
+        })
+        .collect();
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&manifest).expect("failed to serialize manifest")
+    );
 }
 
 // Run this from the command-line to pre-generate the groth parameters used by the API.
@@ -114,11 +134,17 @@ pub fn main() {
                 .help("generate only Groth parameters and keys useful for testing")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("parent-cache-manifest")
+                .long("parent-cache-manifest")
+                .help("generate only the parent caches, and print the manifest of their digests")
+                .takes_value(false),
+        )
         .get_matches();
 
     let test_only: bool = matches.is_present("test-only");
 
-    let smallest = vec![SECTOR_SIZE_ONE_KIB];
+    let smallest = vec![SECTOR_SIZE_ONE_KIB, SECTOR_SIZE_2_KIB];
 
     let sizes: &[u64] = if test_only {
         &smallest
@@ -126,6 +152,11 @@ pub fn main() {
         &PUBLISHED_SECTOR_SIZES
     };
 
+    if matches.is_present("parent-cache-manifest") {
+        print_parent_cache_manifest(sizes);
+        return;
+    }
+
     for size in sizes {
         cache_post_params(PoStConfig(SectorSize(*size)));
 
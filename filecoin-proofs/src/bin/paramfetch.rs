@@ -1,5 +1,5 @@
 use std::collections::HashSet;
-use std::fs::{create_dir_all, rename, File};
+use std::fs::{create_dir_all, rename, File, OpenOptions};
 use std::io::copy;
 use std::io::prelude::*;
 use std::io::{BufReader, Stdout};
@@ -14,7 +14,7 @@ use failure::err_msg;
 use flate2::read::GzDecoder;
 use itertools::Itertools;
 use pbr::{ProgressBar, Units};
-use reqwest::{header, Client, Proxy, Url};
+use reqwest::{header, Client, Proxy, StatusCode, Url};
 use tar::Archive;
 
 use filecoin_proofs::param::*;
@@ -26,9 +26,19 @@ const ERROR_PARAMETER_FILE: &str = "failed to find file in cache";
 const ERROR_PARAMETER_ID: &str = "failed to find key in manifest";
 
 const IPGET_PATH: &str = "/var/tmp/ipget";
-const DEFAULT_PARAMETERS: &str = include_str!("../../parameters.json");
 const IPGET_VERSION: &str = "v0.4.0";
 
+const PARTIAL_DOWNLOAD_EXT: &str = "partial";
+
+/// Where parameter files are downloaded from.
+enum FetchSource {
+    Ipget {
+        bin_path: PathBuf,
+        args: Option<String>,
+    },
+    Gateway(String),
+}
+
 struct FetchProgress<R> {
     inner: R,
     progress_bar: ProgressBar<Stdout>,
@@ -53,6 +63,10 @@ pub fn main() {
                 "
 Set {} to specify Groth parameter and verifying key-cache directory.
 Defaults to '{}'
+
+Files are fetched by the CID recorded for them in the manifest and checked
+against its digest. Use parampublish to add files to IPFS and write the
+manifest.
 ",
                 PARAMETER_CACHE_ENV_VAR,
                 PARAMETER_CACHE_DIR
@@ -95,6 +109,14 @@ Defaults to '{}'
                 .long("verbose")
                 .help("Print diagnostic information to stdout"),
         )
+        .arg(
+            Arg::with_name("gateway")
+                .conflicts_with_all(&["ipget-bin", "ipget-args", "ipget-version"])
+                .takes_value(true)
+                .short("g")
+                .long("gateway")
+                .help("Download over HTTP from an IPFS gateway (e.g. https://ipfs.io/ipfs) instead of using ipget; interrupted downloads are resumed"),
+        )
         .arg(
             Arg::with_name("ipget-bin")
                 .conflicts_with("ipget-version")
@@ -151,7 +173,7 @@ fn fetch(matches: &ArgMatches) -> Result<()> {
         })?
     } else {
         println!("using built-in manifest");
-        serde_json::from_str(&DEFAULT_PARAMETERS)?
+        default_parameter_map()?
     };
 
     let retry = matches.is_present("retry");
@@ -204,19 +226,28 @@ fn fetch(matches: &ArgMatches) -> Result<()> {
     }
 
     let is_verbose = matches.is_present("verbose");
-    let ipget_bin_path = matches.value_of("ipget-bin");
-    let ipget_version = matches.value_of("ipget-version").unwrap_or(IPGET_VERSION);
-    let ipget_args = matches.value_of("ipget-args");
 
-    // Make sure we have ipget available
-    if ipget_bin_path.is_none() {
-        ensure_ipget(is_verbose, ipget_version)?;
-    }
-
-    let ipget_path = if let Some(p) = ipget_bin_path {
-        PathBuf::from(p)
+    let source = if let Some(gateway) = matches.value_of("gateway") {
+        FetchSource::Gateway(gateway.trim_end_matches('/').to_string())
     } else {
-        PathBuf::from(&get_ipget_bin(ipget_version))
+        let ipget_bin_path = matches.value_of("ipget-bin");
+        let ipget_version = matches.value_of("ipget-version").unwrap_or(IPGET_VERSION);
+
+        // Make sure we have ipget available
+        if ipget_bin_path.is_none() {
+            ensure_ipget(is_verbose, ipget_version)?;
+        }
+
+        let ipget_path = if let Some(p) = ipget_bin_path {
+            PathBuf::from(p)
+        } else {
+            PathBuf::from(&get_ipget_bin(ipget_version))
+        };
+
+        FetchSource::Ipget {
+            bin_path: ipget_path,
+            args: matches.value_of("ipget-args").map(String::from),
+        }
     };
 
     loop {
@@ -228,7 +259,7 @@ fn fetch(matches: &ArgMatches) -> Result<()> {
             print!("downloading file... ");
             io::stdout().flush().unwrap();
 
-            match fetch_parameter_file(is_verbose, &manifest, &filename, &ipget_path, ipget_args) {
+            match fetch_parameter_file(is_verbose, &manifest, &filename, &source) {
                 Ok(_) => println!("ok\n"),
                 Err(err) => println!("error: {}\n", err),
             }
@@ -357,20 +388,117 @@ fn fetch_parameter_file(
     is_verbose: bool,
     parameter_map: &ParameterMap,
     filename: &str,
-    ipget_bin_path: impl AsRef<Path>,
-    ipget_args: Option<impl AsRef<str>>,
+    source: &FetchSource,
 ) -> Result<()> {
     let parameter_data = parameter_map_lookup(parameter_map, filename)?;
     let path = get_full_path_for_file_within_cache(filename);
 
     create_dir_all(parameter_cache_dir())?;
-    download_file_with_ipget(
-        &parameter_data.cid,
-        path,
-        is_verbose,
-        ipget_bin_path,
-        ipget_args,
-    )
+
+    match source {
+        FetchSource::Ipget { bin_path, args } => download_file_with_ipget(
+            &parameter_data.cid,
+            path,
+            is_verbose,
+            bin_path,
+            args.as_ref(),
+        ),
+        FetchSource::Gateway(gateway) => {
+            download_file_with_gateway(gateway, &parameter_data.cid, path, is_verbose)
+        }
+    }
+}
+
+/// Download a file from an HTTP gateway, resuming from a previously
+/// interrupted download if one is found next to the target.
+///
+/// Bytes are written to `<target>.partial`, which is only moved into place
+/// once the response has been read in full. Gateways which ignore the range
+/// request cause the download to start over.
+fn download_file_with_gateway(
+    gateway: &str,
+    cid: impl AsRef<str>,
+    target: impl AsRef<Path>,
+    is_verbose: bool,
+) -> Result<()> {
+    let url = Url::parse(&format!("{}/{}", gateway, cid.as_ref()))?;
+    let partial_path = partial_download_path(target.as_ref());
+
+    let offset = fs::metadata(&partial_path).map(|m| m.len()).unwrap_or(0);
+
+    let client = Client::builder()
+        .proxy(Proxy::custom(move |url| env_proxy::for_url(&url).to_url()))
+        .build()?;
+
+    let mut req = client.get(url.as_str());
+    if offset > 0 {
+        req = req.header(header::RANGE, format!("bytes={}-", offset));
+    }
+
+    let res = req.send()?;
+
+    let (mut file, offset) = match res.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            (OpenOptions::new().append(true).open(&partial_path)?, offset)
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+            // The partial file is already complete (or corrupt, in which case
+            // the digest check which follows every fetch will reject it).
+            rename(&partial_path, target.as_ref())?;
+            return Ok(());
+        }
+        status if status.is_success() => (File::create(&partial_path)?, 0),
+        status => {
+            return Err(failure::format_err!(
+                "failed to download {}: {}",
+                url,
+                status
+            ))
+        }
+    };
+
+    if is_verbose {
+        let remaining: u64 = res
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|ct_len| ct_len.to_str().ok())
+            .and_then(|ct_len| ct_len.parse().ok())
+            .unwrap_or(0);
+
+        if offset > 0 {
+            println!("resuming at byte {}", offset);
+        }
+
+        let mut pb = ProgressBar::new(offset + remaining);
+        pb.set_units(Units::Bytes);
+        pb.set(offset);
+
+        let mut source = FetchProgress {
+            inner: res,
+            progress_bar: pb,
+        };
+
+        let _ = copy(&mut source, &mut file)?;
+    } else {
+        let mut source = res;
+        let _ = copy(&mut source, &mut file)?;
+    }
+
+    file.sync_all()?;
+    rename(&partial_path, target.as_ref())?;
+
+    Ok(())
+}
+
+fn partial_download_path(target: &Path) -> PathBuf {
+    let mut file_name = target
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    file_name.push(".");
+    file_name.push(PARTIAL_DOWNLOAD_EXT);
+
+    target.with_file_name(file_name)
 }
 
 fn download_file_with_ipget(
@@ -19,17 +19,17 @@ pub fn main() {
 
     let public_params = public_params(
         PaddedBytesAmount::from(SectorSize(SECTOR_SIZE_ONE_KIB)),
-        usize::from(PoRepProofPartitions(2)),
+        usize::from(DEFAULT_POREP_PROOF_PARTITIONS),
     );
 
-    let circuit = StackedCompound::blank_circuit(&public_params, &ENGINE_PARAMS);
+    let circuit = StackedCompound::blank_circuit(&public_params, *ENGINE_PARAMS);
     let mut params = phase21::MPCParameters::new(circuit).unwrap();
 
     let rng = &mut OsRng::new().unwrap();
     let hash = params.contribute(rng);
 
     {
-        let circuit = StackedCompound::blank_circuit(&public_params, &ENGINE_PARAMS);
+        let circuit = StackedCompound::blank_circuit(&public_params, *ENGINE_PARAMS);
         let contributions = params.verify(circuit).expect("parameters should be valid!");
 
         // We need to check the `contributions` to see if our `hash`
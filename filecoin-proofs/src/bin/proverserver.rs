@@ -0,0 +1,39 @@
+#[macro_use]
+extern crate log;
+
+use std::net::TcpListener;
+use std::process::exit;
+
+use clap::{App, Arg};
+
+use filecoin_proofs::prover_server::serve;
+
+// Run this on a machine with the Groth parameters, and set `remote_prover` to its address on the
+// machines which seal, to prove their seals here.
+pub fn main() {
+    pretty_env_logger::init_timed();
+
+    let matches = App::new("proverserver")
+        .version("0.1")
+        .about("Prove seals for other machines, from the output of seal_commit_phase1")
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .help("The address to accept requests on")
+                .default_value("127.0.0.1:7878")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let address = matches.value_of("listen").expect("defaulted");
+    let listener = TcpListener::bind(address).unwrap_or_else(|err| {
+        error!("failed to listen on {}: {}", address, err);
+        exit(1);
+    });
+    info!("proving seals on {}", address);
+
+    if let Err(err) = serve(listener) {
+        error!("{}", err);
+        exit(1);
+    }
+}
@@ -1,19 +1,22 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Mutex, Once};
 
 use bellperson::groth16;
 use paired::bls12_381::Bls12;
 
+use storage_proofs::circuit::election_post::{ElectionPoStCircuit, ElectionPoStCompound};
 use storage_proofs::circuit::rational_post::RationalPoStCircuit;
 use storage_proofs::circuit::rational_post::RationalPoStCompound;
 use storage_proofs::circuit::stacked::StackedCompound;
 use storage_proofs::compound_proof::CompoundProof;
+use storage_proofs::election_post::ElectionPoSt;
 use storage_proofs::hasher::PedersenHasher;
 use storage_proofs::rational_post::RationalPoSt;
 
 use crate::error;
-use crate::parameters::{post_public_params, public_params};
+use crate::param::register_published_parameter_digests;
+use crate::parameters::{election_post_public_params, post_public_params, public_params};
 use crate::singletons::ENGINE_PARAMS;
 use crate::types::*;
 
@@ -29,6 +32,8 @@ lazy_static! {
     static ref VERIFYING_KEY_MEMORY_CACHE: Mutex<VerifyingKeyMemCache> = Default::default();
 }
 
+static REGISTER_PUBLISHED_DIGESTS: Once = Once::new();
+
 pub fn cache_lookup<F, G>(
     cache_ref: &Mutex<Cache<G>>,
     identifier: String,
@@ -50,6 +55,11 @@ where
 
     info!("no params in memory cache for {}", &identifier);
 
+    // Published parameters are verified against their digests when read from disk.
+    REGISTER_PUBLISHED_DIGESTS.call_once(|| {
+        register_published_parameter_digests().expect("invalid built-in parameter manifest")
+    });
+
     let new_entry = Arc::new(generator()?);
     let res = new_entry.clone();
     {
@@ -88,11 +98,11 @@ pub fn get_stacked_params(
 ) -> error::Result<Arc<groth16::Parameters<Bls12>>> {
     let public_params = public_params(
         PaddedBytesAmount::from(porep_config),
-        usize::from(PoRepProofPartitions::from(porep_config)),
+        porep_config.partitions(),
     );
 
     let parameters_generator =
-        || StackedCompound::groth_params(&public_params, &ENGINE_PARAMS).map_err(Into::into);
+        || StackedCompound::groth_params(&public_params, *ENGINE_PARAMS).map_err(Into::into);
 
     Ok(lookup_groth_params(
         format!(
@@ -111,7 +121,7 @@ pub fn get_post_params(post_config: PoStConfig) -> error::Result<Arc<groth16::Pa
             Bls12,
             RationalPoSt<PedersenHasher>,
             RationalPoStCircuit<Bls12, PedersenHasher>,
-        >>::groth_params(&post_public_params, &ENGINE_PARAMS)
+        >>::groth_params(&post_public_params, *ENGINE_PARAMS)
         .map_err(Into::into)
     };
 
@@ -129,11 +139,11 @@ pub fn get_stacked_verifying_key(
 ) -> error::Result<Arc<Bls12VerifyingKey>> {
     let public_params = public_params(
         PaddedBytesAmount::from(porep_config),
-        usize::from(PoRepProofPartitions::from(porep_config)),
+        porep_config.partitions(),
     );
 
     let vk_generator =
-        || StackedCompound::verifying_key(&public_params, &ENGINE_PARAMS).map_err(Into::into);
+        || StackedCompound::verifying_key(&public_params, *ENGINE_PARAMS).map_err(Into::into);
 
     Ok(lookup_verifying_key(
         format!(
@@ -152,7 +162,7 @@ pub fn get_post_verifying_key(post_config: PoStConfig) -> error::Result<Arc<Bls1
             Bls12,
             RationalPoSt<PedersenHasher>,
             RationalPoStCircuit<Bls12, PedersenHasher>,
-        >>::verifying_key(&post_public_params, &ENGINE_PARAMS)
+        >>::verifying_key(&post_public_params, *ENGINE_PARAMS)
         .map_err(Into::into)
     };
 
@@ -164,3 +174,49 @@ pub fn get_post_verifying_key(post_config: PoStConfig) -> error::Result<Arc<Bls1
         vk_generator,
     )?)
 }
+
+pub fn get_election_post_params(
+    post_config: PoStConfig,
+) -> error::Result<Arc<groth16::Parameters<Bls12>>> {
+    let post_public_params = election_post_public_params(post_config);
+
+    let parameters_generator = || {
+        <ElectionPoStCompound<PedersenHasher> as CompoundProof<
+            Bls12,
+            ElectionPoSt<PedersenHasher>,
+            ElectionPoStCircuit<Bls12, PedersenHasher>,
+        >>::groth_params(&post_public_params, *ENGINE_PARAMS)
+        .map_err(Into::into)
+    };
+
+    Ok(lookup_groth_params(
+        format!(
+            "ELECTION_POST[{}]",
+            usize::from(PaddedBytesAmount::from(post_config))
+        ),
+        parameters_generator,
+    )?)
+}
+
+pub fn get_election_post_verifying_key(
+    post_config: PoStConfig,
+) -> error::Result<Arc<Bls12VerifyingKey>> {
+    let post_public_params = election_post_public_params(post_config);
+
+    let vk_generator = || {
+        <ElectionPoStCompound<PedersenHasher> as CompoundProof<
+            Bls12,
+            ElectionPoSt<PedersenHasher>,
+            ElectionPoStCircuit<Bls12, PedersenHasher>,
+        >>::verifying_key(&post_public_params, *ENGINE_PARAMS)
+        .map_err(Into::into)
+    };
+
+    Ok(lookup_verifying_key(
+        format!(
+            "ELECTION_POST[{}]",
+            usize::from(PaddedBytesAmount::from(post_config))
+        ),
+        vk_generator,
+    )?)
+}
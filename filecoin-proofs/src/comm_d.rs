@@ -0,0 +1,333 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use storage_proofs::hasher::pedersen::PedersenDomain;
+use storage_proofs::hasher::Domain;
+use storage_proofs::util::NODE_SIZE;
+use tempfile::NamedTempFile;
+
+use crate::api::Commitment;
+use crate::constants::{
+    MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
+    MINIMUM_RESERVED_LEAVES_FOR_PIECE_IN_SECTOR as MIN_NUM_LEAVES,
+};
+use crate::error;
+use crate::pieces::{
+    aligned_piece_leaves, compute_alignment, hash_node, zero_roots, PieceAlignment, PieceInfo,
+};
+use crate::types::{PaddedBytesAmount, SectorSize, UnpaddedByteIndex, UnpaddedBytesAmount};
+
+/// Builds the `comm_d` of a sector incrementally, as its pieces arrive.
+///
+/// Only the roots of the completed subtrees on the right edge of the data tree are kept, so the
+/// state is logarithmic in the sector size. It is persisted to `path` after every piece, which
+/// allows ingestion to resume across restarts.
+#[derive(Debug)]
+pub struct CommDBuilder {
+    path: PathBuf,
+    state: CommDBuilderState,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CommDBuilderState {
+    sector_size: u64,
+    /// Unpadded bytes taken by the pieces added so far, including their alignment.
+    written_bytes: UnpaddedBytesAmount,
+    /// Pieces added so far, in order.
+    pieces: Vec<PieceInfo>,
+    /// Roots of the completed subtrees, left to right, with their heights.
+    subtrees: Vec<(Commitment, usize)>,
+}
+
+impl CommDBuilder {
+    /// Opens the builder persisted at `path`, or starts a new one for an empty sector if there is
+    /// none yet.
+    pub fn open<P: AsRef<Path>>(path: P, sector_size: SectorSize) -> error::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if !path.exists() {
+            let builder = CommDBuilder {
+                path,
+                state: CommDBuilderState {
+                    sector_size: sector_size.0,
+                    written_bytes: UnpaddedBytesAmount(0),
+                    pieces: Vec::new(),
+                    subtrees: Vec::new(),
+                },
+            };
+            builder.persist()?;
+
+            return Ok(builder);
+        }
+
+        let state: CommDBuilderState = serde_json::from_reader(File::open(&path)?)?;
+        if state.sector_size != sector_size.0 {
+            return Err(format_err!(
+                "comm_d builder at {:?} is for sectors of {} bytes, not {}",
+                path,
+                state.sector_size,
+                sector_size.0
+            ));
+        }
+
+        Ok(CommDBuilder { path, state })
+    }
+
+    /// Returns the pieces added so far, in order.
+    pub fn pieces(&self) -> &[PieceInfo] {
+        &self.state.pieces
+    }
+
+    /// Returns the number of unpadded bytes still available, ignoring alignment of the next piece.
+    pub fn remaining_bytes(&self) -> UnpaddedBytesAmount {
+        UnpaddedBytesAmount::from(SectorSize(self.state.sector_size)) - self.state.written_bytes
+    }
+
+    /// Returns true if no more pieces can be added.
+    pub fn is_full(&self) -> bool {
+        self.position() == self.sector_leaves()
+    }
+
+    /// Adds the next piece to the sector and persists the updated state. Returns the unpadded
+    /// byte offset at which the piece starts.
+    pub fn add_piece(&mut self, piece_info: PieceInfo) -> error::Result<UnpaddedByteIndex> {
+        let PieceAlignment {
+            left_bytes,
+            right_bytes,
+        } = compute_alignment(self.state.written_bytes, piece_info.size);
+
+        let start = self.state.written_bytes + left_bytes;
+        let start_leaf = (usize::from(start) / MINIMUM_PIECE_SIZE) * MIN_NUM_LEAVES;
+        let leaves = aligned_piece_leaves(piece_info.size);
+
+        if start_leaf + leaves > self.sector_leaves() {
+            return Err(format_err!(
+                "piece of {} bytes does not fit into the remaining {} bytes",
+                u64::from(piece_info.size),
+                u64::from(self.remaining_bytes())
+            ));
+        }
+
+        // Validate the commitment before changing any state.
+        PedersenDomain::try_from_bytes(&piece_info.commitment)?;
+
+        let mut state = self.state.clone();
+        fill_zeros(&mut state.subtrees, start_leaf);
+        push_subtree(
+            &mut state.subtrees,
+            piece_info.commitment,
+            leaves.trailing_zeros() as usize,
+        );
+        state.written_bytes = start + piece_info.size + right_bytes;
+        state.pieces.push(piece_info);
+
+        let previous = std::mem::replace(&mut self.state, state);
+        if let Err(err) = self.persist() {
+            self.state = previous;
+            return Err(err);
+        }
+
+        Ok(UnpaddedByteIndex::from(start))
+    }
+
+    /// Fills the rest of the sector with zeros and returns its `comm_d`.
+    pub fn finalize(mut self) -> error::Result<Commitment> {
+        let sector_leaves = self.sector_leaves();
+        fill_zeros(&mut self.state.subtrees, sector_leaves);
+
+        match self.state.subtrees.as_slice() {
+            [(comm_d, _)] => Ok(*comm_d),
+            _ => Err(format_err!(
+                "malformed comm_d builder state at {:?}",
+                self.path
+            )),
+        }
+    }
+
+    /// Removes the persisted state.
+    pub fn remove(self) -> error::Result<()> {
+        fs::remove_file(&self.path)?;
+
+        Ok(())
+    }
+
+    fn sector_leaves(&self) -> usize {
+        usize::from(PaddedBytesAmount::from(SectorSize(self.state.sector_size))) / NODE_SIZE
+    }
+
+    /// The first leaf which is not yet covered by a completed subtree.
+    fn position(&self) -> usize {
+        self.state
+            .subtrees
+            .iter()
+            .map(|&(_, height)| 1 << height)
+            .sum()
+    }
+
+    // Writes a temporary file next to the state and renames it over the state, so that the state
+    // is replaced atomically.
+    fn persist(&self) -> error::Result<()> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut file = NamedTempFile::new_in(dir)?;
+        {
+            let mut writer = BufWriter::new(file.as_file_mut());
+            serde_json::to_writer(&mut writer, &self.state)?;
+            writer.flush()?;
+        }
+        file.as_file().sync_all()?;
+
+        file.persist(&self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Pushes a subtree of `height` and merges all completed pairs of subtrees.
+fn push_subtree(subtrees: &mut Vec<(Commitment, usize)>, root: Commitment, height: usize) {
+    subtrees.push((root, height));
+
+    while subtrees.len() > 1 {
+        let (right, right_height) = subtrees[subtrees.len() - 1];
+        let (left, left_height) = subtrees[subtrees.len() - 2];
+
+        if left_height != right_height {
+            break;
+        }
+
+        subtrees.truncate(subtrees.len() - 2);
+
+        let left = PedersenDomain::try_from_bytes(&left).expect("invalid subtree root");
+        let right = PedersenDomain::try_from_bytes(&right).expect("invalid subtree root");
+
+        let mut root = [0; NODE_SIZE];
+        hash_node(left, right, left_height)
+            .write_bytes(&mut root)
+            .expect("failed to write subtree root");
+
+        subtrees.push((root, left_height + 1));
+    }
+}
+
+/// Pushes zero subtrees until all leaves before `end` are covered.
+fn fill_zeros(subtrees: &mut Vec<(Commitment, usize)>, end: usize) {
+    let mut position: usize = subtrees.iter().map(|&(_, height)| 1 << height).sum();
+    let zero_roots = zero_roots((end - position).next_power_of_two().trailing_zeros() as usize);
+
+    while position < end {
+        // The largest aligned subtree starting at `position` which does not extend past `end`.
+        let mut height = if position == 0 {
+            zero_roots.len() - 1
+        } else {
+            position.trailing_zeros() as usize
+        };
+        while position + (1 << height) > end {
+            height -= 1;
+        }
+
+        let mut root = [0; NODE_SIZE];
+        zero_roots[height]
+            .write_bytes(&mut root)
+            .expect("failed to write subtree root");
+
+        push_subtree(subtrees, root, height);
+        position += 1 << height;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::constants::SECTOR_SIZE_ONE_KIB;
+    use crate::pieces::{compute_comm_d, generate_piece_commitment};
+
+    fn random_piece(rng: &mut XorShiftRng, size: u64) -> PieceInfo {
+        let bytes: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+
+        generate_piece_commitment(&bytes[..], UnpaddedBytesAmount(size))
+            .expect("failed to generate piece commitment")
+    }
+
+    #[test]
+    fn test_comm_d_builder_matches_compute_comm_d() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+        let dir = tempfile::tempdir().unwrap();
+
+        let table: Vec<Vec<u64>> = vec![
+            vec![],
+            vec![127],
+            vec![1016],
+            vec![100, 200],
+            vec![127, 254, 127],
+            vec![127, 508],
+        ];
+
+        for (i, piece_sizes) in table.into_iter().enumerate() {
+            let path = dir.path().join(format!("comm-d-{}", i));
+            let pieces: Vec<_> = piece_sizes
+                .into_iter()
+                .map(|size| random_piece(rng, size))
+                .collect();
+
+            for piece in &pieces {
+                // Reopen for every piece, as if they arrived across restarts.
+                let mut builder = CommDBuilder::open(&path, sector_size).unwrap();
+                builder.add_piece(*piece).unwrap();
+            }
+
+            let builder = CommDBuilder::open(&path, sector_size).unwrap();
+            assert_eq!(builder.pieces(), pieces.as_slice());
+            assert_eq!(
+                builder.finalize().unwrap(),
+                compute_comm_d(sector_size, &pieces).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_comm_d_builder_offsets() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut builder = CommDBuilder::open(dir.path().join("comm-d"), sector_size).unwrap();
+
+        assert_eq!(
+            builder.add_piece(random_piece(rng, 127)).unwrap(),
+            UnpaddedByteIndex(0)
+        );
+        assert_eq!(
+            builder.add_piece(random_piece(rng, 200)).unwrap(),
+            UnpaddedByteIndex(254)
+        );
+        assert!(!builder.is_full());
+
+        assert_eq!(
+            builder.add_piece(random_piece(rng, 508)).unwrap(),
+            UnpaddedByteIndex(508)
+        );
+        assert!(builder.is_full());
+
+        // A full sector rejects further pieces, without losing its state.
+        assert!(builder.add_piece(random_piece(rng, 127)).is_err());
+        assert_eq!(builder.pieces().len(), 3);
+    }
+
+    #[test]
+    fn test_comm_d_builder_sector_size_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comm-d");
+
+        CommDBuilder::open(&path, SectorSize(SECTOR_SIZE_ONE_KIB)).unwrap();
+        assert!(CommDBuilder::open(&path, SectorSize(SECTOR_SIZE_ONE_KIB * 2)).is_err());
+    }
+}
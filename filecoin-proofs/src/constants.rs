@@ -1,8 +1,23 @@
 use storage_proofs::util::NODE_SIZE;
 
+use crate::types::PoRepProofPartitions;
+
 pub const POREP_MINIMUM_CHALLENGES: usize = 12; // FIXME: 8,000
+pub const DEFAULT_POREP_PROOF_PARTITIONS: PoRepProofPartitions = PoRepProofPartitions(2);
 pub const SINGLE_PARTITION_PROOF_LEN: usize = 192;
 
+pub const SECTOR_SIZE_2_KIB: u64 = 1 << 11;
+
+pub const SECTOR_SIZE_8_MIB: u64 = 1 << 23;
+
+pub const SECTOR_SIZE_512_MIB: u64 = 1 << 29;
+
+pub const SECTOR_SIZE_32_GIB: u64 = 1 << 35;
+
+pub const SECTOR_SIZE_64_GIB: u64 = 1 << 36;
+
+// Sizes without registered proofs, used by tests and benchmarks.
+
 pub const SECTOR_SIZE_ONE_KIB: u64 = 1024;
 
 pub const SECTOR_SIZE_16_MIB: u64 = 1 << 24;
@@ -11,8 +26,90 @@ pub const SECTOR_SIZE_256_MIB: u64 = 1 << 28;
 
 pub const SECTOR_SIZE_1_GIB: u64 = 1 << 30;
 
+/// Number of layers of the stacked DRG, for all sector sizes. The published parameters are for
+/// this count; changing it changes every PoRep circuit and needs newly published parameters.
+pub const LAYERS: usize = 4;
+
+/// Arity of the replica and column merkle trees. Only binary trees are supported.
+pub const TREE_ARITY: usize = 2;
+
+/// The proof constants used for sectors of one supported size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectorSizeConstants {
+    pub sector_size: u64,
+    /// Number of layers of the stacked DRG.
+    pub layers: usize,
+    /// Minimum number of PoRep challenges, summed over all partitions.
+    pub porep_minimum_challenges: usize,
+    pub partitions: PoRepProofPartitions,
+    /// Arity of the replica and column merkle trees.
+    pub tree_arity: usize,
+}
+
+const fn sector_size_constants_for(sector_size: u64) -> SectorSizeConstants {
+    SectorSizeConstants {
+        sector_size,
+        layers: LAYERS,
+        porep_minimum_challenges: POREP_MINIMUM_CHALLENGES,
+        partitions: DEFAULT_POREP_PROOF_PARTITIONS,
+        tree_arity: TREE_ARITY,
+    }
+}
+
+/// The constants of every supported sector size. Each of them needs published parameters in
+/// `parameters.json`.
+pub const SECTOR_SIZE_CONSTANTS: [SectorSizeConstants; 5] = [
+    sector_size_constants_for(SECTOR_SIZE_2_KIB),
+    sector_size_constants_for(SECTOR_SIZE_8_MIB),
+    sector_size_constants_for(SECTOR_SIZE_512_MIB),
+    sector_size_constants_for(SECTOR_SIZE_32_GIB),
+    sector_size_constants_for(SECTOR_SIZE_64_GIB),
+];
+
+/// Returns the constants of the given sector size, if it is supported.
+pub fn sector_size_constants(sector_size: u64) -> Option<&'static SectorSizeConstants> {
+    SECTOR_SIZE_CONSTANTS
+        .iter()
+        .find(|constants| constants.sector_size == sector_size)
+}
+
 pub const MINIMUM_RESERVED_LEAVES_FOR_PIECE_IN_SECTOR: usize = 4;
 
 // Bit padding causes bytes to only be aligned at every 127 bytes (for 31.75 bytes).
 pub const MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR: usize =
     (MINIMUM_RESERVED_LEAVES_FOR_PIECE_IN_SECTOR * NODE_SIZE) - 1;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::{RegisteredPoStProof, RegisteredSealProof};
+
+    #[test]
+    fn sector_size_constants_are_consistent() {
+        for constants in SECTOR_SIZE_CONSTANTS.iter() {
+            assert_eq!(
+                sector_size_constants(constants.sector_size),
+                Some(constants)
+            );
+            assert!(constants.sector_size.is_power_of_two());
+            assert!(constants.layers > 0);
+            assert_eq!(
+                constants.porep_minimum_challenges % usize::from(constants.partitions),
+                0,
+                "challenges of {} byte sectors do not split evenly across partitions",
+                constants.sector_size
+            );
+            assert_eq!(constants.tree_arity, TREE_ARITY);
+        }
+
+        assert_eq!(sector_size_constants(SECTOR_SIZE_ONE_KIB), None);
+
+        for proof in RegisteredSealProof::ALL.iter() {
+            assert!(sector_size_constants(proof.sector_size().0).is_some());
+        }
+        for proof in RegisteredPoStProof::ALL.iter() {
+            assert!(sector_size_constants(proof.sector_size().0).is_some());
+        }
+    }
+}
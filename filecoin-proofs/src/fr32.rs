@@ -1001,6 +1001,8 @@ mod tests {
     use super::*;
     use itertools::Itertools;
     use paired::bls12_381::Bls12;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
     use rand::{Rng, SeedableRng, XorShiftRng};
     use std::io::Cursor;
     use storage_proofs::fr32::bytes_into_fr;
@@ -1357,4 +1359,50 @@ mod tests {
 
     // TODO: Add a test that drops the last part of an element and tries to recover
     // the rest of the data (may already be present in some form in the above tests).
+
+    proptest! {
+        // Raw data of any length, padded in two calls split anywhere, so the second call
+        // usually starts in the middle of an element and of a byte.
+        #[test]
+        fn prop_write_padded_roundtrip(ref data in vec(any::<u8>(), 1..1024), split in any::<usize>()) {
+            let split = split % (data.len() + 1);
+
+            let mut cursor = Cursor::new(Vec::new());
+            let mut written = write_padded(&mut &data[..split], &mut cursor).unwrap();
+            written += write_padded(&mut &data[split..], &mut cursor).unwrap();
+            let padded = cursor.into_inner();
+
+            prop_assert_eq!(written, data.len());
+            prop_assert_eq!(
+                padded.len(),
+                FR32_PADDING_MAP.transform_byte_offset(data.len(), true)
+            );
+            validate_fr32(&padded[..padded.len() / 32 * 32]);
+            prop_assert_eq!(&padded[..], &*bit_vec_padding(data.clone()));
+
+            let mut unpadded = Vec::new();
+            let unpadded_written = write_unpadded(&padded, &mut unpadded, 0, data.len()).unwrap();
+            prop_assert_eq!(unpadded_written, data.len());
+            prop_assert_eq!(&unpadded, data);
+        }
+
+        // Any range of the raw data can be recovered from the padded data on its own.
+        #[test]
+        fn prop_write_unpadded_range(
+            ref data in vec(any::<u8>(), 1..1024),
+            offset in any::<usize>(),
+            len in any::<usize>()
+        ) {
+            let offset = offset % data.len();
+            let len = 1 + len % (data.len() - offset);
+
+            let mut cursor = Cursor::new(Vec::new());
+            write_padded(&mut &data[..], &mut cursor).unwrap();
+            let padded = cursor.into_inner();
+
+            let mut unpadded = Vec::new();
+            write_unpadded(&padded, &mut unpadded, offset, len).unwrap();
+            prop_assert_eq!(&unpadded[..], &data[offset..offset + len]);
+        }
+    }
 }
@@ -6,21 +6,33 @@ extern crate lazy_static;
 extern crate failure;
 #[macro_use]
 extern crate log;
+#[cfg(test)]
+#[macro_use]
+extern crate proptest;
 
 mod api;
 mod caches;
 mod file_cleanup;
 
+pub mod comm_d;
 pub mod constants;
 pub mod error;
 pub mod fr32;
 pub mod param;
 pub mod parameters;
 pub mod pieces;
+pub mod placement;
+#[cfg(feature = "prover-server")]
+pub mod prover_server;
+pub mod remote_prover;
+pub mod replica_file;
+pub mod scheduler;
 pub mod serde_big_array;
 pub mod singletons;
+pub mod throughput;
 pub mod types;
 
 pub use api::*;
 pub use constants::SINGLE_PARTITION_PROOF_LEN;
+pub use storage_proofs::measurements;
 pub use types::*;
@@ -4,17 +4,20 @@ use std::fs::File;
 use std::io::{stdin, stdout, Write};
 use std::path::{Path, PathBuf};
 
-use blake2b_simd::State as Blake2b;
 use failure::Error;
 use failure::Error as FailureError;
 use serde::{Deserialize, Serialize};
 
 use storage_proofs::parameter_cache::{
-    parameter_cache_dir, CacheEntryMetadata, PARAMETER_METADATA_EXT,
+    parameter_cache_dir, parameter_digest, register_parameter_digests, CacheEntryMetadata,
+    PARAMETER_METADATA_EXT,
 };
 
 const ERROR_STRING: &str = "invalid string";
 
+/// The manifest of published parameters.
+const DEFAULT_PARAMETERS: &str = include_str!("../parameters.json");
+
 pub type Result<T> = ::std::result::Result<T, Error>;
 pub type ParameterMap = BTreeMap<String, ParameterData>;
 
@@ -35,12 +38,26 @@ pub fn get_full_path_for_file_within_cache(filename: &str) -> PathBuf {
 // Produces a BLAKE2b checksum for a file within the cache
 pub fn get_digest_for_file_within_cache(filename: &str) -> Result<String> {
     let path = get_full_path_for_file_within_cache(filename);
-    let mut file = File::open(path)?;
-    let mut hasher = Blake2b::new();
 
-    std::io::copy(&mut file, &mut hasher)?;
+    Ok(parameter_digest(path)?)
+}
+
+// Parses the built-in manifest of published parameters
+pub fn default_parameter_map() -> Result<ParameterMap> {
+    Ok(serde_json::from_str(DEFAULT_PARAMETERS)?)
+}
+
+// Registers the digests of all published parameters, so that they are verified when loaded
+pub fn register_published_parameter_digests() -> Result<()> {
+    let parameter_map = default_parameter_map()?;
+
+    register_parameter_digests(
+        parameter_map
+            .into_iter()
+            .map(|(filename, data)| (filename, data.digest)),
+    );
 
-    Ok(hasher.finalize().to_hex()[..32].into())
+    Ok(())
 }
 
 // Prompts the user to approve/reject the message
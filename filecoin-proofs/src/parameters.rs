@@ -1,27 +1,99 @@
+use paired::bls12_381::Bls12;
+use storage_proofs::analysis::challenge_count_for_security;
+use storage_proofs::compound_proof;
 use storage_proofs::drgporep::DrgParams;
-use storage_proofs::drgraph::{DefaultTreeHasher, BASE_DEGREE};
+use storage_proofs::drgraph::{DefaultTreeHasher, GraphConfig, BASE_DEGREE};
+use storage_proofs::election_post::{self, ElectionPoSt};
 use storage_proofs::hasher::PedersenHasher;
 use storage_proofs::proof::ProofScheme;
 use storage_proofs::rational_post::{self, RationalPoSt};
-use storage_proofs::stacked::{self, LayerChallenges, StackedDrg, EXP_DEGREE};
+use storage_proofs::stacked::{self, CommRHasher, LayerChallenges, StackedDrg, EXP_DEGREE};
+use storage_proofs::util::NODE_SIZE;
 
-use crate::constants::POREP_MINIMUM_CHALLENGES;
-use crate::types::{PaddedBytesAmount, PoStConfig};
+use crate::constants::{sector_size_constants, LAYERS, POREP_MINIMUM_CHALLENGES};
+use crate::error;
+use crate::singletons::ENGINE_PARAMS;
+use crate::types::{PaddedBytesAmount, PoRepConfig, PoStConfig};
 
-const POST_CHALLENGE_COUNT: usize = 30; // TODO: correct value
+/// Target soundness of a rational PoSt, in bits.
+const POST_SECURITY_BITS: u32 = 10;
+/// Fraction of a sector whose loss a rational PoSt must detect.
+const POST_SPACEGAP: f64 = 0.2;
+/// Number of rational PoSts over which the target soundness is reached.
+const POST_PROOFS_PER_PERIOD: usize = 1;
 
-const LAYERS: usize = 4; // TODO: 10;
+const ELECTION_POST_CHALLENGE_COUNT: usize = 40; // TODO: correct value
+const ELECTION_POST_CHALLENGED_NODES: usize = 1;
 
 const DRG_SEED: [u32; 7] = [1, 2, 3, 4, 5, 6, 7]; // Arbitrary, need a theory for how to vary this over time.
 
+/// Version of the PoRep construction. Bump it whenever replicas or proofs produced by a new
+/// release would be incompatible with those of the previous one.
+pub const POREP_PROOF_VERSION: u64 = 2;
+
 type PostSetupParams = rational_post::SetupParams;
 pub type PostPublicParams = rational_post::PublicParams;
+pub type ElectionPostPublicParams = election_post::PublicParams;
+pub type PoRepCompoundPublicParams =
+    compound_proof::PublicParams<'static, Bls12, StackedDrg<'static, DefaultTreeHasher>>;
+
+/// Returns the `porep_id` of sectors of the given size, which separates them from sectors of any
+/// other size or proof version: their graphs, labels and challenges all differ.
+///
+/// The id is laid out as the sector size and `POREP_PROOF_VERSION`, each as little-endian u64,
+/// followed by zeros.
+pub fn porep_id(sector_bytes: PaddedBytesAmount) -> [u8; 32] {
+    let mut porep_id = [0u8; 32];
+    porep_id[..8].copy_from_slice(&u64::from(sector_bytes).to_le_bytes());
+    porep_id[8..16].copy_from_slice(&POREP_PROOF_VERSION.to_le_bytes());
+
+    porep_id
+}
 
 pub fn public_params(
     sector_bytes: PaddedBytesAmount,
     partitions: usize,
 ) -> stacked::PublicParams<DefaultTreeHasher> {
-    StackedDrg::<DefaultTreeHasher>::setup(&setup_params(sector_bytes, partitions)).unwrap()
+    StackedDrg::<DefaultTreeHasher>::setup(&setup_params(sector_bytes, partitions).unwrap())
+        .unwrap()
+}
+
+/// Returns the compound public parameters for the given PoRep configuration, deriving the
+/// partition count from it, so that proving and verifying always agree.
+pub fn porep_public_params(porep_config: PoRepConfig) -> error::Result<PoRepCompoundPublicParams> {
+    let partitions = porep_config.partitions();
+
+    let vanilla_params = StackedDrg::<DefaultTreeHasher>::setup(&setup_params(
+        PaddedBytesAmount::from(porep_config),
+        partitions,
+    )?)?;
+
+    Ok(compound_proof::PublicParams {
+        vanilla_params,
+        engine_params: *ENGINE_PARAMS,
+        partitions: Some(partitions),
+    })
+}
+
+/// Checks that the minimum number of challenges can be evenly split across `partitions`.
+pub fn validate_partitions(partitions: usize) -> error::Result<()> {
+    validate_partitions_for(partitions, POREP_MINIMUM_CHALLENGES)
+}
+
+fn validate_partitions_for(partitions: usize, minimum_challenges: usize) -> error::Result<()> {
+    if partitions == 0 {
+        return Err(format_err!("partition count must be greater than zero"));
+    }
+
+    if minimum_challenges % partitions != 0 {
+        return Err(format_err!(
+            "minimum challenge count ({}) is not divisible by the partition count ({})",
+            minimum_challenges,
+            partitions
+        ));
+    }
+
+    Ok(())
 }
 
 pub fn post_public_params(post_config: PoStConfig) -> PostPublicParams {
@@ -32,31 +104,70 @@ pub fn post_setup_params(post_config: PoStConfig) -> PostSetupParams {
     let size = PaddedBytesAmount::from(post_config);
 
     rational_post::SetupParams {
-        challenges_count: POST_CHALLENGE_COUNT,
+        challenges_count: challenge_count_for_security(
+            POST_SECURITY_BITS,
+            POST_SPACEGAP,
+            size.into(),
+            POST_PROOFS_PER_PERIOD,
+        ),
+        sector_size: size.into(),
+    }
+}
+
+pub fn election_post_public_params(post_config: PoStConfig) -> ElectionPostPublicParams {
+    ElectionPoSt::<PedersenHasher>::setup(&election_post_setup_params(post_config)).unwrap()
+}
+
+pub fn election_post_setup_params(post_config: PoStConfig) -> election_post::SetupParams {
+    let size = PaddedBytesAmount::from(post_config);
+
+    election_post::SetupParams {
         sector_size: size.into(),
+        challenge_count: ELECTION_POST_CHALLENGE_COUNT,
+        challenged_nodes: ELECTION_POST_CHALLENGED_NODES,
     }
 }
 
-pub fn setup_params(sector_bytes: PaddedBytesAmount, partitions: usize) -> stacked::SetupParams {
+pub fn setup_params(
+    sector_bytes: PaddedBytesAmount,
+    partitions: usize,
+) -> error::Result<stacked::SetupParams> {
+    let id = porep_id(sector_bytes);
+
+    // Sizes without an entry in the constants table, only used for testing and benchmarks, get
+    // the defaults.
+    let constants = sector_size_constants(u64::from(sector_bytes));
+    let layers = constants.map_or(LAYERS, |c| c.layers);
+    let minimum_challenges =
+        constants.map_or(POREP_MINIMUM_CHALLENGES, |c| c.porep_minimum_challenges);
+
     let sector_bytes = usize::from(sector_bytes);
 
-    let challenges = select_challenges(partitions, POREP_MINIMUM_CHALLENGES, LAYERS);
+    validate_partitions_for(partitions, minimum_challenges)?;
+
+    let challenges = select_challenges(partitions, minimum_challenges, layers);
 
     assert!(
-        sector_bytes % 32 == 0,
-        "sector_bytes ({}) must be a multiple of 32",
+        sector_bytes % NODE_SIZE == 0,
+        "sector_bytes ({}) must be a multiple of {}",
         sector_bytes,
+        NODE_SIZE,
     );
-    let nodes = sector_bytes / 32;
-    stacked::SetupParams {
+    let nodes = sector_bytes / NODE_SIZE;
+    Ok(stacked::SetupParams {
         drg: DrgParams {
             nodes,
             degree: BASE_DEGREE,
             expansion_degree: EXP_DEGREE,
             seed: DRG_SEED,
+            graph_config: GraphConfig::default(),
         },
         layer_challenges: challenges,
-    }
+        porep_id: id,
+        // Rational PoSt recomputes comm_r from comm_c and comm_r_last with Pedersen.
+        comm_r_hasher: CommRHasher::Pedersen,
+        node_size: NODE_SIZE,
+    })
 }
 
 fn select_challenges(
@@ -77,8 +188,13 @@ fn select_challenges(
 mod tests {
     use super::*;
 
-    use crate::constants::POREP_MINIMUM_CHALLENGES;
-    use crate::types::PoRepProofPartitions;
+    use crate::constants::{
+        DEFAULT_POREP_PROOF_PARTITIONS, POREP_MINIMUM_CHALLENGES, SECTOR_SIZE_16_MIB,
+        SECTOR_SIZE_CONSTANTS, SECTOR_SIZE_ONE_KIB,
+    };
+    use crate::types::{PoRepProofPartitions, SectorSize};
+    use storage_proofs::drgraph::Graph;
+    use storage_proofs::stacked::ParentCache;
 
     #[test]
     fn partition_layer_challenges_test() {
@@ -86,10 +202,99 @@ mod tests {
             select_challenges(partitions, POREP_MINIMUM_CHALLENGES, LAYERS).challenges_count_all()
         };
         // Update to ensure all supported PoRepProofPartitions options are represented here.
-        assert_eq!(6, f(usize::from(PoRepProofPartitions(2))));
+        assert_eq!(6, f(usize::from(DEFAULT_POREP_PROOF_PARTITIONS)));
 
         assert_eq!(12, f(1));
         assert_eq!(6, f(2));
         assert_eq!(3, f(4));
     }
+
+    #[test]
+    fn validate_partitions_test() {
+        assert!(validate_partitions(1).is_ok());
+        assert!(validate_partitions(2).is_ok());
+        assert!(validate_partitions(4).is_ok());
+
+        assert!(validate_partitions(0).is_err());
+        assert!(validate_partitions(5).is_err());
+
+        let sector_bytes = PaddedBytesAmount(SECTOR_SIZE_ONE_KIB);
+        assert!(setup_params(sector_bytes, 2).is_ok());
+        assert!(setup_params(sector_bytes, 0).is_err());
+        assert!(setup_params(sector_bytes, 5).is_err());
+    }
+
+    #[test]
+    fn porep_public_params_partitions_test() {
+        let config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let pub_params = porep_public_params(config).expect("failed to build public params");
+
+        assert_eq!(pub_params.partitions, Some(config.partitions()));
+        assert_eq!(
+            pub_params
+                .vanilla_params
+                .layer_challenges
+                .challenges_count_all()
+                * config.partitions(),
+            POREP_MINIMUM_CHALLENGES
+        );
+
+        let invalid = PoRepConfig(SectorSize(SECTOR_SIZE_ONE_KIB), PoRepProofPartitions(5));
+        assert!(porep_public_params(invalid).is_err());
+    }
+
+    #[test]
+    fn sector_size_constants_test() {
+        for constants in SECTOR_SIZE_CONSTANTS.iter() {
+            let partitions = usize::from(constants.partitions);
+            let sp = setup_params(PaddedBytesAmount(constants.sector_size), partitions).unwrap();
+
+            assert_eq!(sp.layer_challenges.layers(), constants.layers);
+            assert!(
+                sp.layer_challenges.challenges_count_all() * partitions
+                    >= constants.porep_minimum_challenges
+            );
+            assert_eq!(sp.drg.nodes * NODE_SIZE, constants.sector_size as usize);
+        }
+    }
+
+    #[test]
+    fn post_challenge_count_test() {
+        let count = |size| post_setup_params(PoStConfig(SectorSize(size))).challenges_count;
+
+        assert_eq!(count(SECTOR_SIZE_ONE_KIB), 29);
+        assert_eq!(count(SECTOR_SIZE_16_MIB), 32);
+    }
+
+    #[test]
+    fn porep_id_test() {
+        let one_kib = PaddedBytesAmount(SECTOR_SIZE_ONE_KIB);
+        let sixteen_mib = PaddedBytesAmount(SECTOR_SIZE_16_MIB);
+
+        assert_eq!(porep_id(one_kib), porep_id(one_kib));
+        assert_ne!(porep_id(one_kib), porep_id(sixteen_mib));
+
+        let pub_params = public_params(one_kib, 1);
+        assert_eq!(pub_params.porep_id, porep_id(one_kib));
+        assert_ne!(
+            pub_params.graph.seed(),
+            public_params(sixteen_mib, 1).graph.seed()
+        );
+    }
+
+    #[test]
+    fn parent_cache_manifest_test() {
+        let pub_params = public_params(PaddedBytesAmount(SECTOR_SIZE_ONE_KIB), 1);
+        let porep_id = Some(&pub_params.porep_id);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("parents.cache");
+
+        // Fails if the manifest lists the cache, and the generated parents don't match it.
+        ParentCache::generate_and_verify(&path, &pub_params.graph, porep_id)
+            .expect("invalid parent cache");
+    }
 }
@@ -1,10 +1,37 @@
 use std::io::Cursor;
-use std::io::Read;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::iter::Iterator;
 
-use crate::constants::MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE;
-use crate::types::{UnpaddedByteIndex, UnpaddedBytesAmount};
+use merkletree::hash::Algorithm;
+use serde::{Deserialize, Serialize};
+use storage_proofs::hasher::pedersen::{PedersenDomain, PedersenHasher};
+use storage_proofs::hasher::{Domain, Hasher};
+use storage_proofs::merkle::builder::TreeBuilder;
+use storage_proofs::merkle::MerkleTree;
+use storage_proofs::piece_inclusion_proof::{
+    generate_piece_commitment_bytes_from_source, PieceInclusionProof,
+};
+use storage_proofs::util::NODE_SIZE;
+use tempfile::tempfile;
 
+use crate::api::Commitment;
+use crate::constants::{
+    MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
+    MINIMUM_RESERVED_LEAVES_FOR_PIECE_IN_SECTOR as MIN_NUM_LEAVES,
+};
+use crate::error;
+use crate::fr32::write_padded;
+use crate::types::{PaddedBytesAmount, SectorSize, UnpaddedByteIndex, UnpaddedBytesAmount};
+
+/// The commitment (`comm_p`) and unpadded size of a piece which is to be placed in a sector.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PieceInfo {
+    pub commitment: Commitment,
+    pub size: UnpaddedBytesAmount,
+}
+
+/// The zero bytes to add before and after a piece, as returned by `compute_alignment`.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PieceAlignment {
     pub left_bytes: UnpaddedBytesAmount,
     pub right_bytes: UnpaddedBytesAmount,
@@ -19,7 +46,7 @@ pub fn sum_piece_bytes_with_alignment(pieces: &[UnpaddedBytesAmount]) -> Unpadde
             let PieceAlignment {
                 left_bytes,
                 right_bytes,
-            } = get_piece_alignment(acc, *piece_bytes);
+            } = compute_alignment(acc, *piece_bytes);
 
             acc + left_bytes + *piece_bytes + right_bytes
         })
@@ -33,7 +60,7 @@ pub fn get_piece_start_byte(
 ) -> UnpaddedByteIndex {
     // sum up all the bytes taken by the ordered pieces
     let last_byte = sum_piece_bytes_with_alignment(&pieces);
-    let alignment = get_piece_alignment(last_byte, piece_bytes);
+    let alignment = compute_alignment(last_byte, piece_bytes);
 
     // add only the left padding of the target piece to give the start of that piece's data
     UnpaddedByteIndex::from(last_byte + alignment.left_bytes)
@@ -44,8 +71,8 @@ pub fn get_piece_start_byte(
 /// len(piece) == len(sector size)/(2^n) and sufficient left padding to ensure simple merkle proof
 /// construction.
 ///
-pub fn get_piece_alignment(
-    written_bytes: UnpaddedBytesAmount,
+pub fn compute_alignment(
+    existing_bytes: UnpaddedBytesAmount,
     piece_bytes: UnpaddedBytesAmount,
 ) -> PieceAlignment {
     let mut piece_bytes_needed = MINIMUM_PIECE_SIZE as u64;
@@ -58,7 +85,7 @@ pub fn get_piece_alignment(
     }
 
     // Calculate the bytes being affected from the left of the piece by the previous piece.
-    let encroaching = u64::from(written_bytes) % piece_bytes_needed;
+    let encroaching = u64::from(existing_bytes) % piece_bytes_needed;
 
     // Calculate the bytes to push from the left to ensure a clean piece merkle root.
     let left_bytes = if encroaching > 0 {
@@ -100,7 +127,7 @@ pub fn get_aligned_source(
     piece_bytes: UnpaddedBytesAmount,
 ) -> (UnpaddedBytesAmount, impl Read) {
     let written_bytes = sum_piece_bytes_with_alignment(pieces);
-    let piece_alignment = get_piece_alignment(written_bytes, piece_bytes);
+    let piece_alignment = compute_alignment(written_bytes, piece_bytes);
     let expected_num_bytes_written =
         piece_alignment.left_bytes + piece_bytes + piece_alignment.right_bytes;
 
@@ -110,12 +137,372 @@ pub fn get_aligned_source(
     )
 }
 
+/// Computes `comm_p` for the first `piece_size` bytes of `source`. The piece is aligned and bit
+/// padded exactly as it would be when added to a staged sector.
+///
+pub fn generate_piece_commitment<T: Read>(
+    source: T,
+    piece_size: UnpaddedBytesAmount,
+) -> error::Result<PieceInfo> {
+    let mut padded_piece_file = tempfile()?;
+
+    let (_, mut source) = get_aligned_source(source.take(u64::from(piece_size)), &[], piece_size);
+    write_padded(&mut source, &mut padded_piece_file)?;
+
+    let _ = padded_piece_file.seek(SeekFrom::Start(0))?;
+
+    let commitment =
+        generate_piece_commitment_bytes_from_source::<PedersenHasher>(&mut padded_piece_file)?;
+
+    Ok(PieceInfo {
+        commitment,
+        size: piece_size,
+    })
+}
+
+/// The unpadded bytes `add_piece` pads at a time, a multiple of `MINIMUM_PIECE_SIZE`.
+const ADD_PIECE_CHUNK_SIZE: usize = MINIMUM_PIECE_SIZE * 1024;
+
+/// Adds the first `piece_size` bytes of `source` to the staged sector `target`, which already
+/// holds the pieces of `piece_lengths`, and returns the `PieceInfo` of the added piece.
+///
+/// The piece is aligned and bit padded exactly as `get_aligned_source` and `write_padded` would
+/// do, and `target` must be positioned right after the pieces it already holds. `comm_p` is
+/// computed from the padded bytes as they are written, so `source` is only read once.
+///
+pub fn add_piece<R: Read, W: Write>(
+    source: R,
+    mut target: W,
+    piece_size: UnpaddedBytesAmount,
+    piece_lengths: &[UnpaddedBytesAmount],
+) -> error::Result<PieceInfo> {
+    let PieceAlignment {
+        left_bytes,
+        right_bytes,
+    } = compute_alignment(sum_piece_bytes_with_alignment(piece_lengths), piece_size);
+
+    // Alignment always comes in multiples of `MINIMUM_PIECE_SIZE`, which pads to whole nodes. So
+    // the left alignment pads to zeros, and so does every chunk of the piece on its own.
+    io::copy(
+        &mut io::repeat(0).take(u64::from(PaddedBytesAmount::from(left_bytes))),
+        &mut target,
+    )?;
+
+    let aligned_bytes = piece_size + right_bytes;
+    let mut aligned_source = source
+        .take(u64::from(piece_size))
+        .chain(io::repeat(0).take(u64::from(right_bytes)));
+
+    let mut chunk = vec![0; ADD_PIECE_CHUNK_SIZE];
+    let mut padded_piece = Vec::with_capacity(usize::from(PaddedBytesAmount::from(aligned_bytes)));
+    let mut read_bytes = 0;
+
+    loop {
+        let chunk_len = read_full(&mut aligned_source, &mut chunk)?;
+        if chunk_len == 0 {
+            break;
+        }
+        read_bytes += chunk_len as u64;
+
+        let mut padded_chunk = Cursor::new(Vec::new());
+        write_padded(&mut &chunk[..chunk_len], &mut padded_chunk)?;
+        let padded_chunk = padded_chunk.into_inner();
+
+        target.write_all(&padded_chunk)?;
+        padded_piece.extend_from_slice(&padded_chunk);
+    }
+
+    ensure!(
+        read_bytes == u64::from(aligned_bytes),
+        "the source of a piece of {} bytes ended early",
+        u64::from(piece_size)
+    );
+
+    let commitment =
+        generate_piece_commitment_bytes_from_source::<PedersenHasher>(&mut &padded_piece[..])?;
+
+    Ok(PieceInfo {
+        commitment,
+        size: piece_size,
+    })
+}
+
+/// Reads from `source` until `buf` is full or the source is exhausted, and returns the number of
+/// bytes read.
+fn read_full<R: Read>(source: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match source.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Computes the `comm_d` of a sector of `sector_size` holding the given pieces, in order, with
+/// the remaining space filled with zeros. This matches the root of the data tree built while
+/// sealing, without requiring access to the sector data.
+///
+pub fn compute_comm_d(
+    sector_size: SectorSize,
+    piece_infos: &[PieceInfo],
+) -> error::Result<Commitment> {
+    let (sector_height, pieces) = place_pieces(sector_size, piece_infos)?;
+    let zero_roots = zero_roots(sector_height);
+
+    let mut comm_d = [0; NODE_SIZE];
+    subtree_root(&pieces, 0, sector_height, &zero_roots).write_bytes(&mut comm_d)?;
+
+    Ok(comm_d)
+}
+
+/// Builds the data tree (`tree_d`) of a sector of `sector_size` from the staged sector read from
+/// `staged`, which holds the given pieces and is zero padded to the sector size if it is shorter.
+///
+/// The staged sector is streamed through a `TreeBuilder`, so it is read exactly once, e.g. right
+/// as it is staged. The subtree of every piece is checked against its `comm_p`, so staged data
+/// which does not match the committed pieces is rejected before it is sealed.
+///
+pub fn build_tree_d<R: Read>(
+    sector_size: SectorSize,
+    staged: R,
+    piece_infos: &[PieceInfo],
+) -> error::Result<MerkleTree<PedersenDomain, <PedersenHasher as Hasher>::Function>> {
+    let (sector_height, pieces) = place_pieces(sector_size, piece_infos)?;
+    let sector_bytes = u64::from(PaddedBytesAmount::from(sector_size));
+    let leafs = 1 << sector_height;
+
+    let mut builder = TreeBuilder::<PedersenHasher>::new(leafs)?;
+    io::copy(
+        &mut staged.chain(io::repeat(0)).take(sector_bytes),
+        &mut builder,
+    )?;
+    let tree = builder.finish()?;
+
+    for piece in &pieces {
+        let row_start: usize = (0..piece.height).map(|height| leafs >> height).sum();
+        ensure!(
+            tree.read_at(row_start + (piece.position >> piece.height)) == piece.root,
+            "the staged data of the piece at leaf {} does not match its comm_p",
+            piece.position
+        );
+    }
+
+    Ok(tree)
+}
+
+/// Generates an inclusion proof for each of the given pieces against the `comm_d` of a sector
+/// holding them, as computed by `compute_comm_d`. Only the piece commitments are required.
+///
+pub fn generate_piece_inclusion_proofs(
+    sector_size: SectorSize,
+    piece_infos: &[PieceInfo],
+) -> error::Result<Vec<PieceInclusionProof<PedersenHasher>>> {
+    let (sector_height, pieces) = place_pieces(sector_size, piece_infos)?;
+    let zero_roots = zero_roots(sector_height);
+
+    Ok(pieces
+        .iter()
+        .map(|piece| {
+            let proof_elements = (piece.height..sector_height)
+                .map(|height| {
+                    let sibling = ((piece.position >> height) ^ 1) << height;
+                    subtree_root(&pieces, sibling, height, &zero_roots)
+                })
+                .collect();
+
+            PieceInclusionProof::from_elements(piece.position, proof_elements)
+        })
+        .collect())
+}
+
+/// Verifies that `piece_inclusion_proof` proves the piece described by `piece_info` is contained
+/// in the sector committed to by `comm_d`, starting at the unpadded byte `offset`.
+///
+pub fn verify_piece_inclusion_proof_at_offset(
+    piece_inclusion_proof: &PieceInclusionProof<PedersenHasher>,
+    comm_d: &Commitment,
+    piece_info: &PieceInfo,
+    offset: UnpaddedByteIndex,
+    sector_size: SectorSize,
+) -> error::Result<bool> {
+    let offset = usize::from(offset);
+
+    if offset % MINIMUM_PIECE_SIZE != 0
+        || piece_inclusion_proof.position() != (offset / MINIMUM_PIECE_SIZE) * MIN_NUM_LEAVES
+    {
+        return Ok(false);
+    }
+
+    let comm_d = PedersenDomain::try_from_bytes(comm_d)?;
+    let comm_p = PedersenDomain::try_from_bytes(&piece_info.commitment)?;
+    let sector_leaves = usize::from(PaddedBytesAmount::from(sector_size)) / NODE_SIZE;
+
+    Ok(piece_inclusion_proof.verify(
+        &comm_d,
+        &comm_p,
+        aligned_piece_leaves(piece_info.size),
+        sector_leaves,
+    ))
+}
+
+/// A piece commitment placed in the data tree of a sector, as the root of the subtree of height
+/// `height` whose first leaf is `position`.
+struct PlacedPiece {
+    root: PedersenDomain,
+    position: usize,
+    height: usize,
+}
+
+/// Returns the number of leaves taken by a piece of `piece_size`, including its right alignment.
+pub(crate) fn aligned_piece_leaves(piece_size: UnpaddedBytesAmount) -> usize {
+    let PieceAlignment { right_bytes, .. } = compute_alignment(UnpaddedBytesAmount(0), piece_size);
+
+    usize::from(PaddedBytesAmount::from(piece_size + right_bytes)) / NODE_SIZE
+}
+
+/// Lays out the pieces in a sector the same way `get_aligned_source` does when they are added, and
+/// returns the height of the sector's data tree alongside the placed pieces.
+fn place_pieces(
+    sector_size: SectorSize,
+    piece_infos: &[PieceInfo],
+) -> error::Result<(usize, Vec<PlacedPiece>)> {
+    let sector_leaves = usize::from(PaddedBytesAmount::from(sector_size)) / NODE_SIZE;
+
+    if !sector_leaves.is_power_of_two() {
+        return Err(format_err!("invalid sector size: {}", sector_size.0));
+    }
+
+    let mut written_bytes = UnpaddedBytesAmount(0);
+    let mut pieces = Vec::with_capacity(piece_infos.len());
+
+    for piece_info in piece_infos {
+        let PieceAlignment {
+            left_bytes,
+            right_bytes,
+        } = compute_alignment(written_bytes, piece_info.size);
+
+        let start = usize::from(written_bytes + left_bytes);
+        let position = (start / MINIMUM_PIECE_SIZE) * MIN_NUM_LEAVES;
+        let leaves = aligned_piece_leaves(piece_info.size);
+
+        if position + leaves > sector_leaves {
+            return Err(format_err!(
+                "pieces do not fit into a sector of {} bytes",
+                sector_size.0
+            ));
+        }
+
+        pieces.push(PlacedPiece {
+            root: PedersenDomain::try_from_bytes(&piece_info.commitment)?,
+            position,
+            height: leaves.trailing_zeros() as usize,
+        });
+
+        written_bytes = written_bytes + left_bytes + piece_info.size + right_bytes;
+    }
+
+    Ok((sector_leaves.trailing_zeros() as usize, pieces))
+}
+
+/// The height of the data tree of a 64GiB sector, the largest `ZERO_ROOTS` covers.
+const MAX_ZERO_ROOT_HEIGHT: usize = 31;
+
+lazy_static! {
+    /// The roots of all-zero subtrees, indexed by height, up to `MAX_ZERO_ROOT_HEIGHT`.
+    static ref ZERO_ROOTS: Vec<PedersenDomain> = {
+        let mut roots = vec![PedersenDomain::default()];
+
+        for height in 0..MAX_ZERO_ROOT_HEIGHT {
+            roots.push(hash_node(roots[height], roots[height], height));
+        }
+
+        roots
+    };
+}
+
+/// Returns the roots of all-zero subtrees, indexed by height, up to and including `max_height`.
+pub(crate) fn zero_roots(max_height: usize) -> Vec<PedersenDomain> {
+    let mut roots = ZERO_ROOTS[..=max_height.min(MAX_ZERO_ROOT_HEIGHT)].to_vec();
+
+    // Only trees larger than any supported sector are extended beyond the table.
+    for height in MAX_ZERO_ROOT_HEIGHT..max_height {
+        roots.push(hash_node(roots[height], roots[height], height));
+    }
+
+    roots
+}
+
+/// Returns the `comm_p` of a piece of `piece_size` zero bytes, as `generate_piece_commitment` would
+/// compute it, without hashing the piece.
+///
+pub fn zero_piece_commitment(piece_size: UnpaddedBytesAmount) -> Commitment {
+    let height = aligned_piece_leaves(piece_size).trailing_zeros() as usize;
+
+    let mut comm_p = [0; NODE_SIZE];
+    zero_roots(height)[height]
+        .write_bytes(&mut comm_p)
+        .expect("failed to write zero piece commitment");
+
+    comm_p
+}
+
+/// Computes the root of the subtree of `height` starting at leaf `position`.
+fn subtree_root(
+    pieces: &[PlacedPiece],
+    position: usize,
+    height: usize,
+    zero_roots: &[PedersenDomain],
+) -> PedersenDomain {
+    let end = position + (1 << height);
+    let mut contained = pieces
+        .iter()
+        .filter(|piece| piece.position >= position && piece.position < end);
+
+    match (contained.next(), contained.next()) {
+        (None, _) => zero_roots[height],
+        (Some(piece), None) if piece.position == position && piece.height == height => piece.root,
+        _ => {
+            let child_height = height - 1;
+            let left = subtree_root(pieces, position, child_height, zero_roots);
+            let right = subtree_root(
+                pieces,
+                position + (1 << child_height),
+                child_height,
+                zero_roots,
+            );
+
+            hash_node(left, right, child_height)
+        }
+    }
+}
+
+pub(crate) fn hash_node(
+    left: PedersenDomain,
+    right: PedersenDomain,
+    height: usize,
+) -> PedersenDomain {
+    let mut hasher = <PedersenHasher as Hasher>::Function::default();
+    hasher.node(left, right, height)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use storage_proofs::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
+    use storage_proofs::test_helper::test_rng;
+
+    use crate::constants::SECTOR_SIZE_ONE_KIB;
+
     #[test]
-    fn test_get_piece_alignment() {
+    fn test_compute_alignment() {
         let table = vec![
             (0, 0, (0, 127)),
             (0, 127, (0, 0)),
@@ -136,7 +523,7 @@ mod tests {
             let PieceAlignment {
                 left_bytes: UnpaddedBytesAmount(actual_left_align),
                 right_bytes: UnpaddedBytesAmount(actual_right_align),
-            } = get_piece_alignment(
+            } = compute_alignment(
                 UnpaddedBytesAmount(bytes_in_sector),
                 UnpaddedBytesAmount(bytes_in_piece),
             );
@@ -147,6 +534,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_add_piece() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let piece_sizes: Vec<_> = vec![100, 254, 127, 300]
+            .into_iter()
+            .map(UnpaddedBytesAmount)
+            .collect();
+
+        let mut expected = Cursor::new(Vec::new());
+        let mut staged = Vec::new();
+
+        for (i, &piece_size) in piece_sizes.iter().enumerate() {
+            let piece_bytes: Vec<u8> = (0..u64::from(piece_size)).map(|_| rng.gen()).collect();
+
+            let (_, mut source) =
+                get_aligned_source(&piece_bytes[..], &piece_sizes[..i], piece_size);
+            write_padded(&mut source, &mut expected).unwrap();
+
+            let piece_info =
+                add_piece(&piece_bytes[..], &mut staged, piece_size, &piece_sizes[..i]).unwrap();
+            assert_eq!(
+                piece_info,
+                generate_piece_commitment(&piece_bytes[..], piece_size).unwrap()
+            );
+            assert_eq!(&staged, expected.get_ref());
+        }
+
+        // A source which ends before its piece does is an error.
+        assert!(add_piece(&[0u8; 100][..], Vec::new(), UnpaddedBytesAmount(101), &[]).is_err());
+    }
+
+    #[test]
+    fn test_zero_piece_commitment() {
+        for &piece_size in &[0, 1, 127, 200, 254, 1016, 4064] {
+            let piece_size = UnpaddedBytesAmount(piece_size);
+            let zeros = vec![0u8; usize::from(piece_size)];
+
+            assert_eq!(
+                zero_piece_commitment(piece_size),
+                generate_piece_commitment(&zeros[..], piece_size)
+                    .unwrap()
+                    .commitment,
+            );
+        }
+
+        // Beyond the table, the roots are still those of zero subtrees.
+        let roots = zero_roots(MAX_ZERO_ROOT_HEIGHT + 2);
+        assert_eq!(roots.len(), MAX_ZERO_ROOT_HEIGHT + 3);
+        assert_eq!(&roots[..=MAX_ZERO_ROOT_HEIGHT], &ZERO_ROOTS[..]);
+        let top = MAX_ZERO_ROOT_HEIGHT + 1;
+        assert_eq!(roots[top + 1], hash_node(roots[top], roots[top], top));
+    }
+
     #[test]
     fn test_get_piece_start_byte() {
         let pieces = [
@@ -168,4 +608,178 @@ mod tests {
             UnpaddedByteIndex(254)
         );
     }
+
+    /// Builds the staged sector for the given pieces and returns it with its piece infos.
+    fn stage_pieces(
+        rng: &mut XorShiftRng,
+        sector_size: SectorSize,
+        piece_sizes: &[UnpaddedBytesAmount],
+    ) -> (Vec<u8>, Vec<PieceInfo>) {
+        let mut staged = Cursor::new(Vec::new());
+        let mut piece_infos = Vec::new();
+
+        for (i, &piece_size) in piece_sizes.iter().enumerate() {
+            let piece_bytes: Vec<u8> = (0..u64::from(piece_size)).map(|_| rng.gen()).collect();
+
+            piece_infos.push(
+                generate_piece_commitment(&piece_bytes[..], piece_size)
+                    .expect("failed to generate piece commitment"),
+            );
+
+            let (_, mut source) =
+                get_aligned_source(&piece_bytes[..], &piece_sizes[..i], piece_size);
+            write_padded(&mut source, &mut staged).expect("failed to write piece");
+        }
+
+        let mut staged = staged.into_inner();
+        staged.resize(usize::from(PaddedBytesAmount::from(sector_size)), 0);
+
+        (staged, piece_infos)
+    }
+
+    fn data_tree_root(staged: &[u8]) -> Commitment {
+        let graph = BucketGraph::<PedersenHasher>::new(
+            staged.len() / NODE_SIZE,
+            BASE_DEGREE,
+            0,
+            new_seed_from(&mut test_rng()),
+        );
+        let tree = graph
+            .merkle_tree(staged)
+            .expect("failed to build data tree");
+
+        let mut root = [0; NODE_SIZE];
+        tree.root()
+            .write_bytes(&mut root)
+            .expect("failed to write root");
+
+        root
+    }
+
+    #[test]
+    fn test_compute_comm_d() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+
+        let table: Vec<Vec<u64>> = vec![
+            vec![],
+            vec![127],
+            vec![1016],
+            vec![100, 200],
+            vec![127, 254, 127],
+            vec![127, 508],
+        ];
+
+        for piece_sizes in table {
+            let piece_sizes: Vec<_> = piece_sizes.into_iter().map(UnpaddedBytesAmount).collect();
+            let (staged, piece_infos) = stage_pieces(rng, sector_size, &piece_sizes);
+
+            assert_eq!(
+                compute_comm_d(sector_size, &piece_infos).expect("failed to compute comm_d"),
+                data_tree_root(&staged),
+                "comm_d mismatch for pieces {:?}",
+                piece_sizes
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_tree_d() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+        let piece_sizes = [UnpaddedBytesAmount(127), UnpaddedBytesAmount(200)];
+
+        let (mut staged, piece_infos) = stage_pieces(rng, sector_size, &piece_sizes);
+
+        // The pieces end after 512 padded bytes, the rest is zero padded.
+        let tree = build_tree_d(sector_size, &staged[..512], &piece_infos).unwrap();
+        let mut root = [0; NODE_SIZE];
+        tree.root().write_bytes(&mut root).unwrap();
+        assert_eq!(root, data_tree_root(&staged));
+        assert_eq!(root, compute_comm_d(sector_size, &piece_infos).unwrap());
+
+        // Staged data which does not match the comm_p of its piece is rejected.
+        staged[300] ^= 1;
+        assert!(build_tree_d(sector_size, &staged[..], &piece_infos).is_err());
+    }
+
+    #[test]
+    fn test_compute_comm_d_single_piece() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+
+        let (_, piece_infos) = stage_pieces(rng, sector_size, &[UnpaddedBytesAmount(1016)]);
+
+        assert_eq!(
+            compute_comm_d(sector_size, &piece_infos).expect("failed to compute comm_d"),
+            piece_infos[0].commitment
+        );
+    }
+
+    #[test]
+    fn test_compute_comm_d_overflow() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+
+        let (_, mut piece_infos) = stage_pieces(rng, sector_size, &[UnpaddedBytesAmount(508)]);
+        piece_infos.push(piece_infos[0]);
+        piece_infos.push(piece_infos[0]);
+
+        assert!(compute_comm_d(sector_size, &piece_infos).is_err());
+    }
+
+    #[test]
+    fn test_piece_inclusion_proofs() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+        let piece_sizes = [
+            UnpaddedBytesAmount(127),
+            UnpaddedBytesAmount(200),
+            UnpaddedBytesAmount(127),
+        ];
+
+        let (staged, piece_infos) = stage_pieces(rng, sector_size, &piece_sizes);
+        let comm_d = data_tree_root(&staged);
+
+        let proofs = generate_piece_inclusion_proofs(sector_size, &piece_infos)
+            .expect("failed to generate piece inclusion proofs");
+        assert_eq!(proofs.len(), piece_infos.len());
+
+        for (i, (proof, piece_info)) in proofs.iter().zip(piece_infos.iter()).enumerate() {
+            let offset = get_piece_start_byte(&piece_sizes[..i], piece_sizes[i]);
+
+            assert!(
+                verify_piece_inclusion_proof_at_offset(
+                    proof,
+                    &comm_d,
+                    piece_info,
+                    offset,
+                    sector_size
+                )
+                .expect("failed to verify piece inclusion proof"),
+                "piece {} did not verify",
+                i
+            );
+
+            let wrong_offset = UnpaddedByteIndex(u64::from(offset) + MINIMUM_PIECE_SIZE as u64);
+            assert!(!verify_piece_inclusion_proof_at_offset(
+                proof,
+                &comm_d,
+                piece_info,
+                wrong_offset,
+                sector_size
+            )
+            .expect("failed to verify piece inclusion proof"));
+
+            let other_piece = &piece_infos[(i + 1) % piece_infos.len()];
+            assert!(!verify_piece_inclusion_proof_at_offset(
+                proof,
+                &comm_d,
+                other_piece,
+                offset,
+                sector_size
+            )
+            .expect("failed to verify piece inclusion proof"));
+        }
+    }
 }
@@ -0,0 +1,256 @@
+use std::cmp::Reverse;
+
+use storage_proofs::hasher::Domain;
+use storage_proofs::util::NODE_SIZE;
+
+use crate::api::Commitment;
+use crate::constants::MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE;
+use crate::error;
+use crate::pieces::{compute_alignment, compute_comm_d, zero_roots, PieceInfo};
+use crate::types::{SectorSize, UnpaddedByteIndex, UnpaddedBytesAmount};
+
+/// A region of a sector, reserved either for a piece or for padding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    /// Index of the piece in the planner's input, or `None` for a padding piece.
+    pub piece: Option<usize>,
+    /// Unpadded byte offset at which the region starts.
+    pub offset: UnpaddedByteIndex,
+    /// Unpadded bytes reserved, including the piece's right alignment.
+    pub size: UnpaddedBytesAmount,
+}
+
+/// The planned layout of a sector, covering all of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectorPlan {
+    /// All regions of the sector, in order.
+    pub placements: Vec<Placement>,
+}
+
+impl SectorPlan {
+    /// Returns the indices of the planned pieces, in the order they have to be added.
+    pub fn piece_order(&self) -> Vec<usize> {
+        self.placements.iter().filter_map(|p| p.piece).collect()
+    }
+
+    /// Returns the total number of unpadded bytes taken up by padding.
+    pub fn padding_bytes(&self) -> UnpaddedBytesAmount {
+        self.placements
+            .iter()
+            .filter(|p| p.piece.is_none())
+            .fold(UnpaddedBytesAmount(0), |acc, p| acc + p.size)
+    }
+}
+
+/// Plans the placement of pieces of the given sizes into a sector of `sector_size`.
+///
+/// Pieces are placed in order of decreasing aligned size, which never requires padding between
+/// them. The remainder of the sector is covered by padding pieces, each of which is aligned the
+/// same way a piece of its size would be. Fails if the pieces do not fit.
+pub fn plan_placement(
+    sector_size: SectorSize,
+    piece_sizes: &[UnpaddedBytesAmount],
+) -> error::Result<SectorPlan> {
+    let sector_bytes = u64::from(UnpaddedBytesAmount::from(sector_size));
+
+    let mut order: Vec<usize> = (0..piece_sizes.len()).collect();
+    order.sort_by_key(|&i| Reverse(u64::from(aligned_size(piece_sizes[i]))));
+
+    let mut placements = Vec::new();
+    let mut written_bytes = UnpaddedBytesAmount(0);
+
+    for i in order {
+        let piece_size = piece_sizes[i];
+        let alignment = compute_alignment(written_bytes, piece_size);
+        let start = written_bytes + alignment.left_bytes;
+
+        push_padding(&mut placements, written_bytes, start);
+
+        let size = piece_size + alignment.right_bytes;
+        placements.push(Placement {
+            piece: Some(i),
+            offset: UnpaddedByteIndex::from(start),
+            size,
+        });
+
+        written_bytes = start + size;
+    }
+
+    if u64::from(written_bytes) > sector_bytes {
+        return Err(format_err!(
+            "pieces take {} bytes, but the sector only holds {}",
+            u64::from(written_bytes),
+            sector_bytes
+        ));
+    }
+
+    push_padding(
+        &mut placements,
+        written_bytes,
+        UnpaddedBytesAmount(sector_bytes),
+    );
+
+    Ok(SectorPlan { placements })
+}
+
+/// Plans the placement of `pieces` like `plan_placement`, and computes the `comm_d` of the
+/// resulting sector.
+pub fn plan_sector(
+    sector_size: SectorSize,
+    pieces: &[PieceInfo],
+) -> error::Result<(SectorPlan, Commitment)> {
+    let piece_sizes: Vec<_> = pieces.iter().map(|p| p.size).collect();
+    let plan = plan_placement(sector_size, &piece_sizes)?;
+
+    let ordered: Vec<_> = plan.piece_order().into_iter().map(|i| pieces[i]).collect();
+    let comm_d = compute_comm_d(sector_size, &ordered)?;
+
+    Ok((plan, comm_d))
+}
+
+/// Returns the piece info of a padding piece of `size` unpadded bytes, which consists only of zeros.
+/// `size` must be an aligned piece size, as used in a `Placement`.
+pub fn padding_piece(size: UnpaddedBytesAmount) -> error::Result<PieceInfo> {
+    if aligned_size(size) != size {
+        return Err(format_err!(
+            "{} bytes is not an aligned piece size",
+            u64::from(size)
+        ));
+    }
+
+    let units = u64::from(size) / MINIMUM_PIECE_SIZE as u64;
+    let leaves = (units as usize) * (MINIMUM_PIECE_SIZE + 1) / NODE_SIZE;
+    let height = leaves.trailing_zeros() as usize;
+
+    let mut commitment = [0; NODE_SIZE];
+    zero_roots(height)[height].write_bytes(&mut commitment)?;
+
+    Ok(PieceInfo { commitment, size })
+}
+
+/// Returns the unpadded size of a piece of `piece_size`, including its right alignment.
+fn aligned_size(piece_size: UnpaddedBytesAmount) -> UnpaddedBytesAmount {
+    piece_size + compute_alignment(UnpaddedBytesAmount(0), piece_size).right_bytes
+}
+
+/// Covers the unpadded bytes from `start` to `end` with padding pieces, using the largest
+/// aligned pieces possible.
+fn push_padding(
+    placements: &mut Vec<Placement>,
+    start: UnpaddedBytesAmount,
+    end: UnpaddedBytesAmount,
+) {
+    let unit = MINIMUM_PIECE_SIZE as u64;
+    let mut position = u64::from(start) / unit;
+    let end = u64::from(end) / unit;
+
+    while position < end {
+        let mut units = if position == 0 {
+            (end - position).next_power_of_two()
+        } else {
+            1 << position.trailing_zeros()
+        };
+        while position + units > end {
+            units /= 2;
+        }
+
+        placements.push(Placement {
+            piece: None,
+            offset: UnpaddedByteIndex(position * unit),
+            size: UnpaddedBytesAmount(units * unit),
+        });
+
+        position += units;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::constants::SECTOR_SIZE_ONE_KIB;
+    use crate::pieces::generate_piece_commitment;
+
+    fn sizes(sizes: &[u64]) -> Vec<UnpaddedBytesAmount> {
+        sizes.iter().cloned().map(UnpaddedBytesAmount).collect()
+    }
+
+    #[test]
+    fn test_plan_placement() {
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+
+        let plan = plan_placement(sector_size, &sizes(&[100, 300, 127])).unwrap();
+        assert_eq!(plan.piece_order(), vec![1, 0, 2]);
+        assert_eq!(
+            plan.placements,
+            vec![
+                Placement {
+                    piece: Some(1),
+                    offset: UnpaddedByteIndex(0),
+                    size: UnpaddedBytesAmount(508),
+                },
+                Placement {
+                    piece: Some(0),
+                    offset: UnpaddedByteIndex(508),
+                    size: UnpaddedBytesAmount(127),
+                },
+                Placement {
+                    piece: Some(2),
+                    offset: UnpaddedByteIndex(635),
+                    size: UnpaddedBytesAmount(127),
+                },
+                Placement {
+                    piece: None,
+                    offset: UnpaddedByteIndex(762),
+                    size: UnpaddedBytesAmount(254),
+                },
+            ]
+        );
+        assert_eq!(plan.padding_bytes(), UnpaddedBytesAmount(254));
+
+        let empty = plan_placement(sector_size, &[]).unwrap();
+        assert_eq!(empty.padding_bytes(), UnpaddedBytesAmount(1016));
+        assert_eq!(empty.placements.len(), 1);
+
+        assert!(plan_placement(sector_size, &sizes(&[508, 300, 127])).is_err());
+    }
+
+    #[test]
+    fn test_plan_sector_comm_d() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let sector_size = SectorSize(SECTOR_SIZE_ONE_KIB);
+
+        let pieces: Vec<_> = sizes(&[127, 300, 100])
+            .into_iter()
+            .map(|size| {
+                let bytes: Vec<u8> = (0..u64::from(size)).map(|_| rng.gen()).collect();
+                generate_piece_commitment(&bytes[..], size).unwrap()
+            })
+            .collect();
+
+        let (plan, comm_d) = plan_sector(sector_size, &pieces).unwrap();
+
+        // Adding the padding pieces explicitly must not change comm_d.
+        let with_padding: Vec<_> = plan
+            .placements
+            .iter()
+            .map(|p| match p.piece {
+                Some(i) => pieces[i],
+                None => padding_piece(p.size).unwrap(),
+            })
+            .collect();
+
+        assert_eq!(compute_comm_d(sector_size, &with_padding).unwrap(), comm_d);
+    }
+
+    #[test]
+    fn test_padding_piece() {
+        let zeros = vec![0u8; 254];
+        let expected = generate_piece_commitment(&zeros[..], UnpaddedBytesAmount(254)).unwrap();
+
+        assert_eq!(padding_piece(UnpaddedBytesAmount(254)).unwrap(), expected);
+        assert!(padding_piece(UnpaddedBytesAmount(200)).is_err());
+    }
+}
@@ -0,0 +1,372 @@
+//! A prover server: proves seals for `HttpProver`s, on a machine with the Groth parameters and,
+//! usually, a GPU.
+//!
+//! The server speaks just enough HTTP/1.1 for `HttpProver`: it accepts `POST`s of versioned
+//! `ProveRequest`s at `PROVE_PATH`, and answers each with the proof, or with the error as text.
+//! Requests are proven one at a time, in the order they arrive, and every connection is closed
+//! after its response. A client which stops sending or receiving for `IO_TIMEOUT` is dropped, so
+//! it can't hold up the requests behind it. Request lines and headers are bounded by
+//! `MAX_LINE_LEN` and `MAX_HEADERS`, so a client can't make the server buffer them without end.
+//! Requests for a sector size and partition count of no `RegisteredSealProof` are rejected, and a
+//! request which panics while it is proven is answered with the panic, so neither takes the
+//! server down.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+use crate::api::prove_from_phase1_output;
+use crate::error;
+use crate::remote_prover::{ProveRequest, PROVE_PATH};
+use crate::types::RegisteredSealProof;
+
+/// Requests with longer bodies are rejected. Far above the size of the vanilla proofs of the
+/// largest supported sectors.
+pub const MAX_REQUEST_LEN: usize = 1 << 30;
+
+/// Request and header lines longer than this, in bytes, are rejected.
+pub const MAX_LINE_LEN: usize = 8 << 10;
+
+/// Requests with more headers than this are rejected.
+pub const MAX_HEADERS: usize = 64;
+
+/// How long a read or write of a connection may block before it is given up.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Accepts and proves requests on `listener`. Connections which fail before they are handled,
+/// e.g. because the client already went away, are logged and skipped.
+pub fn serve(listener: TcpListener) -> error::Result<()> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("failed to accept a connection: {}", err);
+                continue;
+            }
+        };
+        let peer = match stream.peer_addr() {
+            Ok(peer) => peer,
+            Err(err) => {
+                warn!("failed to get the peer of a connection: {}", err);
+                continue;
+            }
+        };
+
+        if let Err(err) = handle(stream) {
+            warn!("failed to answer {}: {}", peer, err);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle(stream: TcpStream) -> error::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let response = match read_request(&mut reader) {
+        Ok(request) => respond(request),
+        Err(err) => {
+            let status = if err.downcast_ref::<HeadersTooLarge>().is_some() {
+                431
+            } else {
+                400
+            };
+            Response::error(status, err)
+        }
+    };
+
+    response.write(stream)
+}
+
+fn respond(request: Request) -> Response {
+    if request.path != PROVE_PATH {
+        return Response::error(404, format_err!("no such path: {}", request.path));
+    }
+    if request.method != "POST" {
+        return Response::error(405, format_err!("method not allowed: {}", request.method));
+    }
+
+    let prove_request = match ProveRequest::read_from(&request.body[..]) {
+        Ok(prove_request) => prove_request,
+        Err(err) => return Response::error(400, err),
+    };
+
+    // Proving asserts on the configuration, which comes from the network.
+    let porep_config = prove_request.porep_config();
+    match RegisteredSealProof::from_sector_size(porep_config.0) {
+        Some(proof) if proof.partitions().0 == prove_request.partitions => {}
+        _ => {
+            return Response::error(
+                400,
+                format_err!(
+                    "unsupported seal proof: {} byte sectors in {} partitions",
+                    prove_request.sector_size,
+                    prove_request.partitions
+                ),
+            );
+        }
+    }
+
+    let phase1_output = &prove_request.phase1_output;
+    info!(
+        "proving sector {:?} of prover {:?}",
+        phase1_output.sector_id, phase1_output.prover_id
+    );
+
+    let proved = panic::catch_unwind(AssertUnwindSafe(|| {
+        prove_from_phase1_output(porep_config, phase1_output)
+    }));
+
+    match proved {
+        Ok(Ok(proof)) => Response {
+            status: 200,
+            body: proof,
+        },
+        Ok(Err(err)) => Response::error(500, err),
+        Err(panic) => Response::error(500, storage_proofs::error::Error::from(panic).into()),
+    }
+}
+
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// A request line or headers beyond `MAX_LINE_LEN` or `MAX_HEADERS`, answered with 431.
+#[derive(Debug, Fail)]
+#[fail(display = "request header fields too large: {}", _0)]
+struct HeadersTooLarge(String);
+
+/// Reads a line into `line`, failing with `HeadersTooLarge` if it exceeds `MAX_LINE_LEN`.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> error::Result<usize> {
+    line.clear();
+    let len = reader
+        .by_ref()
+        .take(MAX_LINE_LEN as u64 + 1)
+        .read_line(line)?;
+    if len > MAX_LINE_LEN {
+        return Err(HeadersTooLarge(format!("a line exceeds {} bytes", MAX_LINE_LEN)).into());
+    }
+
+    Ok(len)
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> error::Result<Request> {
+    let mut line = String::new();
+    read_line(reader, &mut line)?;
+
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version)) if version.starts_with("HTTP/1.") => {
+            (method.to_string(), path.to_string())
+        }
+        _ => return Err(format_err!("malformed request line: {:?}", line.trim_end())),
+    };
+
+    let mut content_length = None;
+    let mut headers = 0;
+    loop {
+        ensure!(
+            read_line(reader, &mut line)? > 0,
+            "connection closed in the request headers"
+        );
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return Err(HeadersTooLarge(format!("more than {} headers", MAX_HEADERS)).into());
+        }
+
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or_default();
+        if name.eq_ignore_ascii_case("content-length") {
+            let value = parts.next().unwrap_or_default().trim();
+            content_length = Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| format_err!("invalid content-length: {:?}", value))?,
+            );
+        }
+    }
+
+    let content_length = content_length.unwrap_or(0);
+    ensure!(
+        content_length <= MAX_REQUEST_LEN,
+        "request of {} bytes exceeds the maximum of {}",
+        content_length,
+        MAX_REQUEST_LEN
+    );
+
+    // Read as it arrives rather than allocated up front, so a client can't make the server
+    // reserve MAX_REQUEST_LEN bytes with a header alone.
+    let mut body = Vec::new();
+    reader
+        .by_ref()
+        .take(content_length as u64)
+        .read_to_end(&mut body)?;
+    ensure!(
+        body.len() == content_length,
+        "connection closed after {} of {} body bytes",
+        body.len(),
+        content_length
+    );
+
+    Ok(Request { method, path, body })
+}
+
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn error(status: u16, err: failure::Error) -> Self {
+        Response {
+            status,
+            body: err.to_string().into_bytes(),
+        }
+    }
+
+    fn write<W: Write>(&self, mut writer: W) -> error::Result<()> {
+        let (reason, content_type) = match self.status {
+            200 => ("OK", "application/octet-stream"),
+            400 => ("Bad Request", "text/plain"),
+            404 => ("Not Found", "text/plain"),
+            405 => ("Method Not Allowed", "text/plain"),
+            431 => ("Request Header Fields Too Large", "text/plain"),
+            _ => ("Internal Server Error", "text/plain"),
+        };
+
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason,
+            content_type,
+            self.body.len()
+        )?;
+        writer.write_all(&self.body)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use storage_proofs::sector::SectorId;
+
+    use crate::api::SealCommitPhase1Output;
+    use crate::constants::{
+        DEFAULT_POREP_PROOF_PARTITIONS, SECTOR_SIZE_2_KIB, SECTOR_SIZE_ONE_KIB,
+    };
+    use crate::remote_prover::{HttpProver, RemoteProver};
+    use crate::types::{PoRepConfig, PoRepProofPartitions, SectorSize};
+
+    #[test]
+    fn test_read_request() {
+        let raw = b"POST /v1/seal-commit HTTP/1.1\r\nHost: x\r\nCONTENT-LENGTH: 3\r\n\r\nabcdef";
+        let request = read_request(&mut &raw[..]).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, PROVE_PATH);
+        assert_eq!(request.body, b"abc");
+
+        let malformed: &[&[u8]] = &[
+            b"POST /v1/seal-commit\r\n\r\n",
+            b"POST /v1/seal-commit HTTP/1.1\r\nContent-Length: x\r\n\r\n",
+            b"POST /v1/seal-commit HTTP/1.1\r\nContent-Length: 4\r\n\r\nabc",
+            // Claims the largest body allowed, but sends only a few bytes of it.
+            b"POST /v1/seal-commit HTTP/1.1\r\nContent-Length: 1073741824\r\n\r\nabc",
+            b"POST /v1/seal-commit HTTP/1.1\r\nContent-Length: 4\r\n",
+        ];
+        for raw in malformed {
+            assert!(read_request(&mut &raw[..]).is_err());
+        }
+
+        let long_line = format!("POST /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+        let many_headers = format!(
+            "POST /v1/seal-commit HTTP/1.1\r\n{}\r\n",
+            "Host: x\r\n".repeat(MAX_HEADERS + 1)
+        );
+        for raw in &[long_line, many_headers] {
+            let err = read_request(&mut raw.as_bytes()).unwrap_err();
+            assert!(err.downcast_ref::<HeadersTooLarge>().is_some(), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_seal_proofs_are_rejected() {
+        let phase1_output = SealCommitPhase1Output {
+            vanilla_proofs: Vec::new(),
+            comm_r: [1; 32],
+            comm_d: [2; 32],
+            prover_id: [3; 32],
+            sector_id: SectorId::from(4),
+            ticket: [5; 32],
+        };
+
+        let configs = [
+            PoRepConfig(SectorSize(1000), DEFAULT_POREP_PROOF_PARTITIONS),
+            PoRepConfig(
+                SectorSize(SECTOR_SIZE_ONE_KIB),
+                DEFAULT_POREP_PROOF_PARTITIONS,
+            ),
+            PoRepConfig(SectorSize(SECTOR_SIZE_2_KIB), PoRepProofPartitions(0)),
+        ];
+        for porep_config in &configs {
+            let mut body = Vec::new();
+            ProveRequest::new(*porep_config, phase1_output.clone())
+                .write_into(&mut body)
+                .unwrap();
+
+            let response = respond(Request {
+                method: "POST".to_string(),
+                path: PROVE_PATH.to_string(),
+                body,
+            });
+            assert_eq!(response.status, 400);
+        }
+    }
+
+    #[test]
+    fn test_invalid_phase1_output_is_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || serve(listener));
+
+        let porep_config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_2_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let phase1_output = SealCommitPhase1Output {
+            vanilla_proofs: Vec::new(),
+            comm_r: [1; 32],
+            comm_d: [2; 32],
+            prover_id: [3; 32],
+            sector_id: SectorId::from(4),
+            ticket: [5; 32],
+        };
+
+        // The server answers with its error, which the client passes on.
+        let err = HttpProver::new(&url)
+            .unwrap()
+            .prove(porep_config, &phase1_output)
+            .unwrap_err();
+        assert!(err.to_string().contains("500"), "{}", err);
+        assert!(err.to_string().contains("do not verify"), "{}", err);
+    }
+}
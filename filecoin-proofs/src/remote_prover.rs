@@ -0,0 +1,318 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use reqwest::{Client, Proxy};
+use serde::{Deserialize, Serialize};
+use storage_proofs::settings;
+use storage_proofs::versioned;
+
+use crate::api::{prove_from_phase1_output, verify_seal, SealCommitPhase1Output};
+use crate::error;
+use crate::types::{PoRepConfig, PoRepProofPartitions, SectorSize};
+
+/// The path a prover server accepts `ProveRequest`s at.
+pub const PROVE_PATH: &str = "/v1/seal-commit";
+
+/// Computes the SNARK of a seal from the output of `seal_commit_phase1`, here or on another
+/// machine. `seal` uses the prover returned by `default_prover`.
+pub trait RemoteProver {
+    /// Returns the proof `prove_from_phase1_output` returns for the same arguments.
+    fn prove(
+        &self,
+        porep_config: PoRepConfig,
+        phase1_output: &SealCommitPhase1Output,
+    ) -> error::Result<Vec<u8>>;
+}
+
+/// Proves in this process.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalProver;
+
+impl RemoteProver for LocalProver {
+    fn prove(
+        &self,
+        porep_config: PoRepConfig,
+        phase1_output: &SealCommitPhase1Output,
+    ) -> error::Result<Vec<u8>> {
+        prove_from_phase1_output(porep_config, phase1_output)
+    }
+}
+
+/// Proves on the prover server at `url`, see `prover_server`.
+#[derive(Debug)]
+pub struct HttpProver {
+    url: String,
+    client: Client,
+}
+
+impl HttpProver {
+    /// `url` is the base URL of the server, e.g. `http://prover.local:7878`. Requests never time
+    /// out.
+    pub fn new(url: &str) -> error::Result<Self> {
+        Self::with_timeout(url, None)
+    }
+
+    /// Fails requests which take longer than `timeout`, which must allow for proving to take
+    /// minutes. The server answers nothing until the proof is done, so a request to a server which
+    /// went silent otherwise blocks forever.
+    pub fn with_timeout(url: &str, timeout: Option<Duration>) -> error::Result<Self> {
+        let client = Client::builder()
+            .proxy(Proxy::custom(move |url| env_proxy::for_url(&url).to_url()))
+            .timeout(timeout)
+            .build()?;
+
+        Ok(HttpProver {
+            url: format!("{}{}", url.trim_end_matches('/'), PROVE_PATH),
+            client,
+        })
+    }
+}
+
+impl RemoteProver for HttpProver {
+    fn prove(
+        &self,
+        porep_config: PoRepConfig,
+        phase1_output: &SealCommitPhase1Output,
+    ) -> error::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        ProveRequest::new(porep_config, phase1_output.clone()).write_into(&mut body)?;
+
+        info!("proving seal on {}", self.url);
+        let mut response = self.client.post(&self.url).body(body).send()?;
+        if !response.status().is_success() {
+            return Err(format_err!(
+                "prover server {} failed with {}: {}",
+                self.url,
+                response.status(),
+                response.text().unwrap_or_default()
+            ));
+        }
+
+        let mut proof = Vec::new();
+        response.read_to_end(&mut proof)?;
+
+        // The server is trusted with the work, not with its result.
+        ensure!(
+            verify_seal(
+                porep_config,
+                phase1_output.comm_r,
+                phase1_output.comm_d,
+                phase1_output.prover_id,
+                phase1_output.sector_id,
+                phase1_output.ticket,
+                &proof,
+            )?,
+            "prover server {} returned a proof which does not verify",
+            self.url
+        );
+
+        Ok(proof)
+    }
+}
+
+/// Proves with `primary`, and again with `fallback` if `primary` fails.
+#[derive(Debug)]
+pub struct FallbackProver<P, F> {
+    pub primary: P,
+    pub fallback: F,
+}
+
+impl<P: RemoteProver, F: RemoteProver> RemoteProver for FallbackProver<P, F> {
+    fn prove(
+        &self,
+        porep_config: PoRepConfig,
+        phase1_output: &SealCommitPhase1Output,
+    ) -> error::Result<Vec<u8>> {
+        self.primary
+            .prove(porep_config, phase1_output)
+            .or_else(|err| {
+                warn!("proving seal with the fallback prover: {}", err);
+                self.fallback.prove(porep_config, phase1_output)
+            })
+    }
+}
+
+/// Returns the prover at the `remote_prover` setting, if it is set, or a `LocalProver`.
+///
+/// Seals the prover server fails, or doesn't prove within `remote_prover_timeout_secs`, are proven
+/// locally, so that a server which went down can't hold them up indefinitely.
+pub fn default_prover() -> error::Result<Box<dyn RemoteProver + Send>> {
+    let (url, timeout_secs) = {
+        let settings = settings::SETTINGS
+            .lock()
+            .expect("remote_prover settings lock failure");
+        (
+            settings.remote_prover.clone(),
+            settings.remote_prover_timeout_secs,
+        )
+    };
+
+    if url.is_empty() {
+        Ok(Box::new(LocalProver))
+    } else {
+        let timeout = if timeout_secs == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(timeout_secs))
+        };
+
+        Ok(Box::new(FallbackProver {
+            primary: HttpProver::with_timeout(&url, timeout)?,
+            fallback: LocalProver,
+        }))
+    }
+}
+
+/// The body of a request to a prover server: a phase 1 output, with the configuration it is
+/// proven for.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProveRequest {
+    pub sector_size: u64,
+    pub partitions: u8,
+    pub phase1_output: SealCommitPhase1Output,
+}
+
+impl ProveRequest {
+    pub fn new(porep_config: PoRepConfig, phase1_output: SealCommitPhase1Output) -> Self {
+        ProveRequest {
+            sector_size: (porep_config.0).0,
+            partitions: (porep_config.1).0,
+            phase1_output,
+        }
+    }
+
+    pub fn porep_config(&self) -> PoRepConfig {
+        PoRepConfig(
+            SectorSize(self.sector_size),
+            PoRepProofPartitions(self.partitions),
+        )
+    }
+
+    /// Writes the canonical, versioned encoding, see `storage_proofs::versioned`.
+    pub fn write_into<W: Write>(&self, writer: W) -> error::Result<()> {
+        versioned::write_into(self, writer).map_err(Into::into)
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> error::Result<Self> {
+        versioned::read_from(reader).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    use storage_proofs::sector::SectorId;
+
+    use crate::constants::{DEFAULT_POREP_PROOF_PARTITIONS, SECTOR_SIZE_ONE_KIB};
+
+    fn phase1_output() -> SealCommitPhase1Output {
+        SealCommitPhase1Output {
+            vanilla_proofs: Vec::new(),
+            comm_r: [1; 32],
+            comm_d: [2; 32],
+            prover_id: [3; 32],
+            sector_id: SectorId::from(4),
+            ticket: [5; 32],
+        }
+    }
+
+    /// Fails every proof.
+    #[derive(Debug)]
+    struct FailingProver;
+
+    impl RemoteProver for FailingProver {
+        fn prove(
+            &self,
+            _porep_config: PoRepConfig,
+            _phase1_output: &SealCommitPhase1Output,
+        ) -> error::Result<Vec<u8>> {
+            Err(format_err!("prover failed"))
+        }
+    }
+
+    /// Returns the sector id as the proof.
+    #[derive(Debug)]
+    struct SectorIdProver;
+
+    impl RemoteProver for SectorIdProver {
+        fn prove(
+            &self,
+            _porep_config: PoRepConfig,
+            phase1_output: &SealCommitPhase1Output,
+        ) -> error::Result<Vec<u8>> {
+            Ok(u64::from(phase1_output.sector_id).to_le_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn test_prove_request_roundtrip() {
+        let porep_config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let request = ProveRequest::new(porep_config, phase1_output());
+
+        let mut encoded = Vec::new();
+        request.write_into(&mut encoded).unwrap();
+        let decoded = ProveRequest::read_from(&encoded[..]).unwrap();
+
+        assert_eq!(
+            decoded.porep_config().partitions(),
+            porep_config.partitions()
+        );
+        assert_eq!(decoded.sector_size, SECTOR_SIZE_ONE_KIB);
+        assert_eq!(decoded.phase1_output.sector_id, SectorId::from(4));
+        assert_eq!(decoded.phase1_output.comm_d, [2; 32]);
+    }
+
+    #[test]
+    fn test_silent_server_times_out() {
+        // Accepts connections, but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let porep_config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let prover = HttpProver::with_timeout(&url, Some(Duration::from_millis(200))).unwrap();
+
+        let start = Instant::now();
+        assert!(prover.prove(porep_config, &phase1_output()).is_err());
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        drop(listener);
+    }
+
+    #[test]
+    fn test_fallback_prover() {
+        let porep_config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+
+        let prover = FallbackProver {
+            primary: FailingProver,
+            fallback: SectorIdProver,
+        };
+        let proof = prover.prove(porep_config, &phase1_output()).unwrap();
+        assert_eq!(proof, 4u64.to_le_bytes().to_vec());
+
+        // The fallback is only asked if the primary fails.
+        let prover = FallbackProver {
+            primary: SectorIdProver,
+            fallback: FailingProver,
+        };
+        assert!(prover.prove(porep_config, &phase1_output()).is_ok());
+
+        let prover = FallbackProver {
+            primary: FailingProver,
+            fallback: FailingProver,
+        };
+        assert!(prover.prove(porep_config, &phase1_output()).is_err());
+    }
+}
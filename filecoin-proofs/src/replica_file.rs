@@ -0,0 +1,360 @@
+//! The file formats of sealed sectors.
+//!
+//! A raw replica is the sealed sector and nothing else, as written by `seal`. A headered replica
+//! starts with a `ReplicaHeader`, which names the sector size, proof version and `comm_r` the
+//! replica was sealed for, followed by the same bytes as the raw replica. The header is padded to
+//! `REPLICA_HEADER_LEN`, so the sector data stays page aligned, and the padding is left as a hole
+//! in the file.
+//!
+//! Both are opened with `ReplicaFile::open`, which tells them apart by their length. A headered
+//! replica which was sealed for another sector, or whose header is corrupt, fails to open, so it
+//! is rejected before any proving work is done.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use blake2b_simd::Params as Blake2b;
+use storage_proofs::error::{Error, Result};
+use storage_proofs::util::NODE_SIZE;
+
+use crate::api::Commitment;
+use crate::parameters::porep_id;
+use crate::types::PaddedBytesAmount;
+
+/// The length of the header of a headered replica, including its padding.
+pub const REPLICA_HEADER_LEN: u64 = 4096;
+
+/// The version of `ReplicaHeader` written by this release.
+pub const REPLICA_HEADER_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"FILREPLC";
+const CHECKSUM_LEN: usize = 16;
+
+/// magic, version, sector size, porep id, comm_r
+const FIELDS_LEN: usize = 8 + 4 + 8 + 32 + 32;
+
+/// The format of a replica file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplicaFormat {
+    Raw,
+    Headered,
+}
+
+/// What a headered replica was sealed for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplicaHeader {
+    pub version: u32,
+    pub sector_size: u64,
+    pub porep_id: [u8; 32],
+    pub comm_r: Commitment,
+}
+
+impl ReplicaHeader {
+    /// The header of a replica of `sector_bytes`, sealed to `comm_r` by this release.
+    pub fn new(sector_bytes: PaddedBytesAmount, comm_r: Commitment) -> Self {
+        ReplicaHeader {
+            version: REPLICA_HEADER_VERSION,
+            sector_size: u64::from(sector_bytes),
+            porep_id: porep_id(sector_bytes),
+            comm_r,
+        }
+    }
+
+    /// The fields, in little-endian, followed by a checksum of them. The rest of the header is
+    /// padding.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FIELDS_LEN + CHECKSUM_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.sector_size.to_le_bytes());
+        bytes.extend_from_slice(&self.porep_id);
+        bytes.extend_from_slice(&self.comm_r);
+
+        let checksum = checksum(&bytes);
+        bytes.extend_from_slice(&checksum);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, String> {
+        let (fields, stored_checksum) = bytes.split_at(FIELDS_LEN);
+        if fields[..8] != MAGIC[..] {
+            return Err("missing replica header".into());
+        }
+        if checksum(fields)[..] != stored_checksum[..CHECKSUM_LEN] {
+            return Err("corrupt replica header".into());
+        }
+
+        let mut version = [0; 4];
+        version.copy_from_slice(&fields[8..12]);
+        let mut sector_size = [0; 8];
+        sector_size.copy_from_slice(&fields[12..20]);
+        let mut porep_id = [0; 32];
+        porep_id.copy_from_slice(&fields[20..52]);
+        let mut comm_r = [0; 32];
+        comm_r.copy_from_slice(&fields[52..84]);
+
+        Ok(ReplicaHeader {
+            version: u32::from_le_bytes(version),
+            sector_size: u64::from_le_bytes(sector_size),
+            porep_id,
+            comm_r,
+        })
+    }
+}
+
+fn checksum(fields: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = Blake2b::new().hash_length(CHECKSUM_LEN).hash(fields);
+    let mut checksum = [0; CHECKSUM_LEN];
+    checksum.copy_from_slice(hash.as_bytes());
+
+    checksum
+}
+
+/// An open replica of either format, checked against the sector it is expected to hold.
+#[derive(Debug)]
+pub struct ReplicaFile {
+    file: File,
+    path: PathBuf,
+    header: Option<ReplicaHeader>,
+    sector_size: u64,
+}
+
+impl ReplicaFile {
+    /// Opens the replica of `sector_bytes` at `path`, in either format.
+    ///
+    /// A headered replica must have been sealed by this release for `sector_bytes`, and for
+    /// `comm_r`, if it is given. A raw replica carries nothing to check but its length.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        sector_bytes: PaddedBytesAmount,
+        comm_r: Option<&Commitment>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let sector_size = u64::from(sector_bytes);
+        let file = File::open(path).map_err(|err| Error::IoAt(path.to_path_buf(), err))?;
+        let len = file.metadata()?.len();
+
+        if len == sector_size {
+            Ok(ReplicaFile {
+                file,
+                path: path.to_path_buf(),
+                header: None,
+                sector_size,
+            })
+        } else if len == REPLICA_HEADER_LEN + sector_size {
+            Self::open_headered(file, path, sector_bytes, comm_r)
+        } else {
+            Err(invalid(
+                path,
+                format!(
+                    "{} bytes is neither a raw nor a headered replica of {} bytes",
+                    len, sector_size
+                ),
+            ))
+        }
+    }
+
+    fn open_headered(
+        mut file: File,
+        path: &Path,
+        sector_bytes: PaddedBytesAmount,
+        comm_r: Option<&Commitment>,
+    ) -> Result<Self> {
+        let mut bytes = [0; FIELDS_LEN + CHECKSUM_LEN];
+        file.read_exact(&mut bytes)?;
+        let header = ReplicaHeader::from_bytes(&bytes).map_err(|msg| invalid(path, msg))?;
+
+        let expected = ReplicaHeader::new(sector_bytes, *comm_r.unwrap_or(&header.comm_r));
+        if header.version != expected.version {
+            return Err(invalid(
+                path,
+                format!("unsupported replica header version {}", header.version),
+            ));
+        }
+        if header.sector_size != expected.sector_size || header.porep_id != expected.porep_id {
+            return Err(invalid(
+                path,
+                "sealed for another sector size or proof".into(),
+            ));
+        }
+        if header.comm_r != expected.comm_r {
+            return Err(invalid(path, "sealed for another comm_r".into()));
+        }
+
+        Ok(ReplicaFile {
+            file,
+            path: path.to_path_buf(),
+            header: Some(header),
+            sector_size: expected.sector_size,
+        })
+    }
+
+    /// Writes the raw replica at `raw_path` as a headered replica to `out_path`.
+    pub fn create_headered<R: AsRef<Path>, O: AsRef<Path>>(
+        raw_path: R,
+        out_path: O,
+        header: &ReplicaHeader,
+    ) -> Result<Self> {
+        let sector_bytes = PaddedBytesAmount(header.sector_size);
+        let mut raw = Self::open(raw_path, sector_bytes, None)?;
+        if raw.format() != ReplicaFormat::Raw {
+            return Err(invalid(&raw.path, "already has a header".into()));
+        }
+
+        let out_path = out_path.as_ref();
+        let mut out = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(out_path)
+            .map_err(|err| Error::IoAt(out_path.to_path_buf(), err))?;
+
+        out.write_all(&header.to_bytes())?;
+        out.seek(SeekFrom::Start(REPLICA_HEADER_LEN))?;
+        io::copy(&mut raw.data()?, &mut out)?;
+        out.sync_all()?;
+        drop(out);
+
+        Self::open(out_path, sector_bytes, Some(&header.comm_r))
+    }
+
+    pub fn format(&self) -> ReplicaFormat {
+        match self.header {
+            Some(_) => ReplicaFormat::Headered,
+            None => ReplicaFormat::Raw,
+        }
+    }
+
+    pub fn header(&self) -> Option<&ReplicaHeader> {
+        self.header.as_ref()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The offset of the sector data in the file.
+    pub fn data_offset(&self) -> u64 {
+        match self.header {
+            Some(_) => REPLICA_HEADER_LEN,
+            None => 0,
+        }
+    }
+
+    /// Returns a reader over the sector data, from its start.
+    pub fn data(&mut self) -> Result<io::Take<&mut File>> {
+        self.data_at(0)
+    }
+
+    /// Returns a reader over the sector data, from `offset` bytes into it.
+    pub fn data_at(&mut self, offset: u64) -> Result<io::Take<&mut File>> {
+        if offset > self.sector_size {
+            return Err(Error::OutOfBounds(
+                offset as usize,
+                self.sector_size as usize,
+            ));
+        }
+
+        self.file
+            .seek(SeekFrom::Start(self.data_offset() + offset))?;
+        Ok((&mut self.file).take(self.sector_size - offset))
+    }
+
+    /// Reads all of the sector data.
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.sector_size as usize);
+        self.data()?.read_to_end(&mut data)?;
+
+        Ok(data)
+    }
+
+    /// Reads the node at index `node` of the sector data.
+    pub fn read_node(&mut self, node: u64) -> Result<[u8; NODE_SIZE]> {
+        let offset = node * NODE_SIZE as u64;
+        if offset + NODE_SIZE as u64 > self.sector_size {
+            return Err(Error::OutOfBounds(
+                offset as usize,
+                self.sector_size as usize,
+            ));
+        }
+
+        let mut buf = [0; NODE_SIZE];
+        self.file
+            .seek(SeekFrom::Start(self.data_offset() + offset))?;
+        self.file.read_exact(&mut buf)?;
+
+        Ok(buf)
+    }
+}
+
+fn invalid(path: &Path, msg: String) -> Error {
+    Error::InvalidReplica(path.to_path_buf(), msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::constants::{SECTOR_SIZE_16_MIB, SECTOR_SIZE_ONE_KIB};
+
+    const SECTOR_BYTES: PaddedBytesAmount = PaddedBytesAmount(SECTOR_SIZE_ONE_KIB);
+
+    #[test]
+    fn test_replica_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let raw_path = dir.path().join("raw");
+        let headered_path = dir.path().join("headered");
+
+        let data: Vec<u8> = (0..SECTOR_SIZE_ONE_KIB).map(|i| i as u8).collect();
+        fs::write(&raw_path, &data).unwrap();
+
+        let mut raw = ReplicaFile::open(&raw_path, SECTOR_BYTES, Some(&[1; 32])).unwrap();
+        assert_eq!(raw.format(), ReplicaFormat::Raw);
+        assert_eq!(raw.read_data().unwrap(), data);
+
+        let header = ReplicaHeader::new(SECTOR_BYTES, [1; 32]);
+        let mut headered =
+            ReplicaFile::create_headered(&raw_path, &headered_path, &header).unwrap();
+        assert_eq!(headered.format(), ReplicaFormat::Headered);
+        assert_eq!(headered.header(), Some(&header));
+        assert_eq!(headered.read_data().unwrap(), data);
+        assert_eq!(headered.read_node(1).unwrap()[..], data[32..64]);
+        assert!(headered.read_node(32).is_err());
+
+        // A headered replica only opens for the sector it was sealed for.
+        assert!(ReplicaFile::open(&headered_path, SECTOR_BYTES, None).is_ok());
+        assert!(ReplicaFile::open(&headered_path, SECTOR_BYTES, Some(&[2; 32])).is_err());
+        assert!(ReplicaFile::open(
+            &headered_path,
+            PaddedBytesAmount(SECTOR_SIZE_16_MIB),
+            Some(&[1; 32])
+        )
+        .is_err());
+        assert!(ReplicaFile::create_headered(&headered_path, &raw_path, &header).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let raw_path = dir.path().join("raw");
+        let headered_path = dir.path().join("headered");
+        fs::write(&raw_path, vec![0; SECTOR_SIZE_ONE_KIB as usize]).unwrap();
+
+        let header = ReplicaHeader::new(SECTOR_BYTES, [1; 32]);
+        ReplicaFile::create_headered(&raw_path, &headered_path, &header).unwrap();
+
+        let mut bytes = fs::read(&headered_path).unwrap();
+        bytes[60] ^= 1;
+        fs::write(&headered_path, &bytes).unwrap();
+        assert!(ReplicaFile::open(&headered_path, SECTOR_BYTES, None).is_err());
+
+        // Neither format.
+        bytes.truncate(bytes.len() - 1);
+        fs::write(&headered_path, &bytes).unwrap();
+        assert!(ReplicaFile::open(&headered_path, SECTOR_BYTES, None).is_err());
+    }
+}
@@ -0,0 +1,614 @@
+//! Sealing several sectors at once, without orchestrating the phases by hand.
+//!
+//! A `SealScheduler` runs up to `max_concurrent` replications (`seal_commit_phase1`) at a time,
+//! each on its own share of the core budget, and starts them at least `stagger` apart, so that
+//! their memory and disk peaks don't line up. Their SNARKs are computed one at a time by a single
+//! prover, as they would only contend for the GPU otherwise.
+//!
+//! `worker_status` summarizes the seals of a scheduler, together with the verification throughput
+//! of the machine, for the status output of a worker.
+
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use storage_proofs::cancel::CancellationToken;
+use storage_proofs::sector::SectorId;
+
+use crate::api::{seal_commit_phase1, ProverId, SealCommitPhase1Output, SealOutput, Ticket};
+use crate::error;
+use crate::remote_prover::{default_prover, RemoteProver};
+use crate::throughput::{verification_throughput, ThroughputConfig, ThroughputSample};
+use crate::types::{PoRepConfig, UnpaddedBytesAmount};
+
+/// How many seals run at once, and on how many cores.
+#[derive(Clone, Debug)]
+pub struct SchedulerConfig {
+    /// Replications running at the same time.
+    pub max_concurrent: usize,
+    /// Cores shared equally by the running replications.
+    pub cores: usize,
+    /// Minimum time between the starts of two replications.
+    pub stagger: Duration,
+    /// Measures the verification throughput once on startup, to be reported by `worker_status`.
+    pub verification_throughput: Option<ThroughputConfig>,
+}
+
+/// The arguments of `seal`, for a seal to be scheduled.
+#[derive(Clone, Debug)]
+pub struct SealTask {
+    pub porep_config: PoRepConfig,
+    pub in_path: PathBuf,
+    pub out_path: PathBuf,
+    pub prover_id: ProverId,
+    pub sector_id: SectorId,
+    pub ticket: Ticket,
+    pub piece_lengths: Vec<UnpaddedBytesAmount>,
+}
+
+/// Identifies a seal submitted to a `SealScheduler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+/// Where a seal is at.
+#[derive(Clone, Debug)]
+pub enum SealStatus {
+    Queued,
+    /// Running `seal_commit_phase1`.
+    Replicating,
+    /// Replicated, with the SNARKs of other seals in front of it.
+    WaitingForProver,
+    Proving,
+    /// Sealed, `seal` would have returned this output.
+    Done(SealOutput),
+    Failed(String),
+}
+
+/// What a `SealScheduler` reports of itself, see `SealScheduler::worker_status`.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WorkerStatus {
+    pub queued: usize,
+    pub replicating: usize,
+    pub waiting_for_prover: usize,
+    pub proving: usize,
+    pub done: usize,
+    pub failed: usize,
+    /// Empty until the measurement requested by `SchedulerConfig::verification_throughput` is
+    /// done, and if none was requested.
+    pub verification_throughput: Vec<ThroughputSample>,
+}
+
+impl SealStatus {
+    /// Whether the seal is over, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        match self {
+            SealStatus::Done(_) | SealStatus::Failed(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A queue of seals, replicated concurrently and proven one at a time, see the module
+/// documentation.
+///
+/// Dropping the scheduler cancels the running replications, and discards the seals which haven't
+/// started or are waiting for the prover. It blocks until the SNARK being computed, if any, is
+/// done.
+#[derive(Debug)]
+pub struct SealScheduler {
+    shared: Arc<Shared>,
+    replicators: Vec<JoinHandle<()>>,
+    prover: Option<JoinHandle<()>>,
+}
+
+type Replicated = (TaskId, PoRepConfig, SealOutput, SealCommitPhase1Output);
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    token: CancellationToken,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: u64,
+    queue: VecDeque<(TaskId, SealTask)>,
+    statuses: HashMap<TaskId, SealStatus>,
+    last_start: Option<Instant>,
+    shutdown: bool,
+    verification_throughput: Vec<ThroughputSample>,
+}
+
+impl SealScheduler {
+    /// Proves with the prover at the `remote_prover` setting, see `default_prover`.
+    pub fn new(config: SchedulerConfig) -> error::Result<Self> {
+        Self::with_prover(config, default_prover()?)
+    }
+
+    pub fn with_prover(
+        config: SchedulerConfig,
+        prover: Box<dyn RemoteProver + Send>,
+    ) -> error::Result<Self> {
+        ensure!(config.max_concurrent > 0, "max_concurrent must be positive");
+        ensure!(config.cores > 0, "cores must be positive");
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+            token: CancellationToken::new(),
+        });
+        let (sender, receiver) = mpsc::channel();
+
+        // Rounded down, so the budget is never exceeded, but every seal gets at least one core.
+        let threads = std::cmp::max(config.cores / config.max_concurrent, 1);
+        let replicators: Vec<JoinHandle<()>> = (0..config.max_concurrent)
+            .map(|_| {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .map_err(|err| format_err!("failed to build thread pool: {}", err))?;
+                let shared = shared.clone();
+                let sender = sender.clone();
+                let stagger = config.stagger;
+
+                Ok(thread::spawn(move || {
+                    replicate_tasks(&shared, &pool, stagger, &sender)
+                }))
+            })
+            .collect::<error::Result<_>>()?;
+
+        let prover = {
+            let shared = shared.clone();
+            thread::spawn(move || prove_tasks(&shared, prover.as_ref(), receiver))
+        };
+
+        // Not joined on drop, as a measurement can't be cancelled; it only ever holds the state.
+        if let Some(throughput_config) = config.verification_throughput {
+            let shared = shared.clone();
+            thread::spawn(move || match verification_throughput(&throughput_config) {
+                Ok(samples) => shared.lock().verification_throughput = samples,
+                Err(err) => warn!("failed to measure verification throughput: {}", err),
+            });
+        }
+
+        Ok(SealScheduler {
+            shared,
+            replicators,
+            prover: Some(prover),
+        })
+    }
+
+    /// Queues `task`, to be started once a replication slot is free.
+    pub fn submit_seal(&self, task: SealTask) -> TaskId {
+        let mut state = self.shared.lock();
+        let id = TaskId(state.next_id);
+        state.next_id += 1;
+        state.queue.push_back((id, task));
+        state.statuses.insert(id, SealStatus::Queued);
+        self.shared.changed.notify_all();
+
+        id
+    }
+
+    /// The status of the seal `id`, or `None` if it was never submitted.
+    pub fn poll_status(&self, id: TaskId) -> Option<SealStatus> {
+        self.shared.lock().statuses.get(&id).cloned()
+    }
+
+    /// Counts the seals by status, for the status output of a worker.
+    pub fn worker_status(&self) -> WorkerStatus {
+        let state = self.shared.lock();
+        let mut status = WorkerStatus {
+            verification_throughput: state.verification_throughput.clone(),
+            ..Default::default()
+        };
+        for seal_status in state.statuses.values() {
+            let count = match seal_status {
+                SealStatus::Queued => &mut status.queued,
+                SealStatus::Replicating => &mut status.replicating,
+                SealStatus::WaitingForProver => &mut status.waiting_for_prover,
+                SealStatus::Proving => &mut status.proving,
+                SealStatus::Done(_) => &mut status.done,
+                SealStatus::Failed(_) => &mut status.failed,
+            };
+            *count += 1;
+        }
+
+        status
+    }
+
+    /// Blocks until the seal `id` is finished, and returns its final status, or `None` if it was
+    /// never submitted.
+    pub fn wait(&self, id: TaskId) -> Option<SealStatus> {
+        let mut state = self.shared.lock();
+        loop {
+            match state.statuses.get(&id) {
+                Some(status) if status.is_finished() => return Some(status.clone()),
+                Some(_) => {}
+                None => return None,
+            }
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .expect("scheduler lock poisoned");
+        }
+    }
+}
+
+impl Drop for SealScheduler {
+    fn drop(&mut self) {
+        {
+            let mut state = self.shared.lock();
+            state.shutdown = true;
+            state.queue.clear();
+        }
+        self.shared.token.cancel();
+        self.shared.changed.notify_all();
+
+        for replicator in self.replicators.drain(..) {
+            let _ = replicator.join();
+        }
+        if let Some(prover) = self.prover.take() {
+            let _ = prover.join();
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<State> {
+        self.state.lock().expect("scheduler lock poisoned")
+    }
+
+    fn set_status(&self, id: TaskId, status: SealStatus) {
+        self.lock().statuses.insert(id, status);
+        self.changed.notify_all();
+    }
+
+    /// Blocks until a task may start, or returns `None` once the scheduler is shut down.
+    fn next_task(&self, stagger: Duration) -> Option<(TaskId, SealTask)> {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return None;
+            }
+
+            let wait = state
+                .last_start
+                .map(|last_start| stagger.checked_sub(last_start.elapsed()))
+                .unwrap_or_default();
+            match wait {
+                Some(wait) if !state.queue.is_empty() => {
+                    state = self
+                        .changed
+                        .wait_timeout(state, wait)
+                        .expect("scheduler lock poisoned")
+                        .0;
+                }
+                _ => {
+                    if let Some((id, task)) = state.queue.pop_front() {
+                        state.last_start = Some(Instant::now());
+                        state.statuses.insert(id, SealStatus::Replicating);
+                        self.changed.notify_all();
+
+                        return Some((id, task));
+                    }
+
+                    state = self.changed.wait(state).expect("scheduler lock poisoned");
+                }
+            }
+        }
+    }
+}
+
+fn replicate_tasks(
+    shared: &Shared,
+    pool: &rayon::ThreadPool,
+    stagger: Duration,
+    sender: &Sender<Replicated>,
+) {
+    while let Some((id, task)) = shared.next_task(stagger) {
+        info!("replicating sector {:?}", task.sector_id);
+        // Installed on the pool, where replication captures the token of the current thread.
+        let result = catch_panic(|| {
+            pool.install(|| {
+                shared.token.install(|| {
+                    seal_commit_phase1(
+                        task.porep_config,
+                        &task.in_path,
+                        &task.out_path,
+                        task.prover_id,
+                        task.sector_id,
+                        task.ticket,
+                        &task.piece_lengths,
+                    )
+                })
+            })
+        });
+
+        match result {
+            Ok((output, phase1_output)) => {
+                shared.set_status(id, SealStatus::WaitingForProver);
+                if sender
+                    .send((id, task.porep_config, output, phase1_output))
+                    .is_err()
+                {
+                    shared.set_status(id, SealStatus::Failed("prover stopped".to_string()));
+                }
+            }
+            Err(err) => shared.set_status(id, SealStatus::Failed(err.to_string())),
+        }
+    }
+}
+
+fn prove_tasks(shared: &Shared, prover: &dyn RemoteProver, receiver: Receiver<Replicated>) {
+    for (id, porep_config, mut output, phase1_output) in receiver {
+        if shared.lock().shutdown {
+            return;
+        }
+
+        shared.set_status(id, SealStatus::Proving);
+        let status = match catch_panic(|| prover.prove(porep_config, &phase1_output)) {
+            Ok(proof) => {
+                output.proof = proof;
+                SealStatus::Done(output)
+            }
+            Err(err) => SealStatus::Failed(err.to_string()),
+        };
+        shared.set_status(id, status);
+    }
+}
+
+/// Runs `f`, with a panic turned into an error, so a panicking seal fails on its own and its
+/// waiters are woken, rather than leaving it running forever and stopping the thread.
+fn catch_panic<T, F: FnOnce() -> error::Result<T>>(f: F) -> error::Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|panic| Err(storage_proofs::error::Error::from(panic).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Seek, SeekFrom, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tempfile::NamedTempFile;
+
+    use crate::constants::{DEFAULT_POREP_PROOF_PARTITIONS, SECTOR_SIZE_ONE_KIB};
+    use crate::fr32::write_padded;
+    use crate::types::{PaddedBytesAmount, SectorSize};
+
+    /// Returns the sector id as the proof, and checks that it is never called concurrently.
+    #[derive(Debug, Default)]
+    struct FakeProver {
+        running: AtomicUsize,
+        proven: AtomicUsize,
+    }
+
+    impl RemoteProver for Arc<FakeProver> {
+        fn prove(
+            &self,
+            _porep_config: PoRepConfig,
+            phase1_output: &SealCommitPhase1Output,
+        ) -> error::Result<Vec<u8>> {
+            assert_eq!(self.running.fetch_add(1, Ordering::SeqCst), 0);
+            thread::sleep(Duration::from_millis(20));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.proven.fetch_add(1, Ordering::SeqCst);
+
+            Ok(u64::from(phase1_output.sector_id).to_le_bytes().to_vec())
+        }
+    }
+
+    /// Panics for sector 0, and returns the sector id as the proof of any other.
+    #[derive(Debug)]
+    struct PanickingProver;
+
+    impl RemoteProver for PanickingProver {
+        fn prove(
+            &self,
+            _porep_config: PoRepConfig,
+            phase1_output: &SealCommitPhase1Output,
+        ) -> error::Result<Vec<u8>> {
+            let sector_id = u64::from(phase1_output.sector_id);
+            assert_ne!(sector_id, 0, "prover panicked");
+
+            Ok(sector_id.to_le_bytes().to_vec())
+        }
+    }
+
+    /// Reports each sector it starts proving, and proves it only once released.
+    #[derive(Debug)]
+    struct GatedProver {
+        started: Sender<u64>,
+        release: Receiver<()>,
+    }
+
+    impl RemoteProver for GatedProver {
+        fn prove(
+            &self,
+            _porep_config: PoRepConfig,
+            phase1_output: &SealCommitPhase1Output,
+        ) -> error::Result<Vec<u8>> {
+            let sector_id = u64::from(phase1_output.sector_id);
+            self.started.send(sector_id).unwrap();
+            self.release.recv().unwrap();
+
+            Ok(sector_id.to_le_bytes().to_vec())
+        }
+    }
+
+    fn staged_task(sector_id: u64) -> (SealTask, Vec<NamedTempFile>) {
+        let porep_config = PoRepConfig(
+            SectorSize(SECTOR_SIZE_ONE_KIB),
+            DEFAULT_POREP_PROOF_PARTITIONS,
+        );
+        let piece_size = UnpaddedBytesAmount::from(PaddedBytesAmount::from(porep_config));
+
+        let mut piece_file = NamedTempFile::new().unwrap();
+        let piece_bytes: Vec<u8> = (0..piece_size.0).map(|_| rand::random::<u8>()).collect();
+        piece_file.write_all(&piece_bytes).unwrap();
+        piece_file.seek(SeekFrom::Start(0)).unwrap();
+
+        let mut staged_sector_file = NamedTempFile::new().unwrap();
+        write_padded(&mut piece_file, &mut staged_sector_file).unwrap();
+        let sealed_sector_file = NamedTempFile::new().unwrap();
+
+        let task = SealTask {
+            porep_config,
+            in_path: staged_sector_file.path().to_path_buf(),
+            out_path: sealed_sector_file.path().to_path_buf(),
+            prover_id: [1; 32],
+            sector_id: SectorId::from(sector_id),
+            ticket: [2; 32],
+            piece_lengths: vec![piece_size],
+        };
+
+        (task, vec![staged_sector_file, sealed_sector_file])
+    }
+
+    #[test]
+    fn test_seal_scheduler() {
+        let prover = Arc::new(FakeProver::default());
+        let config = SchedulerConfig {
+            max_concurrent: 2,
+            cores: 2,
+            stagger: Duration::from_millis(10),
+            verification_throughput: Some(ThroughputConfig {
+                public_inputs: 4,
+                batch_sizes: vec![1],
+                thread_counts: vec![1],
+                proofs_per_sample: 1,
+            }),
+        };
+        let scheduler = SealScheduler::with_prover(config, Box::new(prover.clone())).unwrap();
+
+        let (tasks, _files): (Vec<_>, Vec<_>) = (0..4).map(staged_task).unzip();
+        let ids: Vec<_> = tasks
+            .into_iter()
+            .map(|task| scheduler.submit_seal(task))
+            .collect();
+        assert!(scheduler.poll_status(TaskId(100)).is_none());
+
+        for (sector_id, &id) in ids.iter().enumerate() {
+            match scheduler.wait(id) {
+                Some(SealStatus::Done(output)) => {
+                    assert_eq!(output.proof, (sector_id as u64).to_le_bytes().to_vec());
+                }
+                status => panic!("seal {:?} ended as {:?}", id, status),
+            }
+        }
+        assert_eq!(prover.proven.load(Ordering::SeqCst), ids.len());
+
+        let status = scheduler.worker_status();
+        assert_eq!(status.done, ids.len());
+        assert_eq!(status.queued + status.replicating + status.failed, 0);
+
+        // Missing inputs fail the seal, not the scheduler.
+        let (mut task, _files) = staged_task(4);
+        task.in_path = PathBuf::from("/nonexistent/staged-sector");
+        let id = scheduler.submit_seal(task);
+        assert!(match scheduler.wait(id) {
+            Some(SealStatus::Failed(_)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_seal_scheduler_prover_panics() {
+        let config = SchedulerConfig {
+            max_concurrent: 1,
+            cores: 1,
+            stagger: Duration::from_millis(0),
+            verification_throughput: None,
+        };
+        let scheduler = SealScheduler::with_prover(config, Box::new(PanickingProver)).unwrap();
+
+        let (tasks, _files): (Vec<_>, Vec<_>) = (0..2).map(staged_task).unzip();
+        let ids: Vec<_> = tasks
+            .into_iter()
+            .map(|task| scheduler.submit_seal(task))
+            .collect();
+
+        match scheduler.wait(ids[0]) {
+            Some(SealStatus::Failed(err)) => assert!(err.contains("prover panicked"), "{}", err),
+            status => panic!("seal ended as {:?}", status),
+        }
+
+        // The prover keeps proving the seals behind the one which panicked.
+        assert!(match scheduler.wait(ids[1]) {
+            Some(SealStatus::Done(_)) => true,
+            _ => false,
+        });
+
+        let status = scheduler.worker_status();
+        assert_eq!((status.done, status.failed, status.proving), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_worker_status() {
+        let (started_sender, started) = mpsc::channel();
+        let (release, release_receiver) = mpsc::channel();
+        let prover = GatedProver {
+            started: started_sender,
+            release: release_receiver,
+        };
+        let config = SchedulerConfig {
+            max_concurrent: 1,
+            cores: 1,
+            stagger: Duration::from_millis(0),
+            verification_throughput: None,
+        };
+        let scheduler = SealScheduler::with_prover(config, Box::new(prover)).unwrap();
+
+        let total = |status: &WorkerStatus| {
+            status.queued
+                + status.replicating
+                + status.waiting_for_prover
+                + status.proving
+                + status.done
+                + status.failed
+        };
+
+        let (tasks, _files): (Vec<_>, Vec<_>) = (0..2).map(staged_task).unzip();
+        let ids: Vec<_> = tasks
+            .into_iter()
+            .map(|task| scheduler.submit_seal(task))
+            .collect();
+
+        // Every seal is counted once, in whichever status it is in.
+        let status = scheduler.worker_status();
+        assert_eq!(total(&status), 2);
+        assert_eq!(status.done, 0);
+        assert!(status.verification_throughput.is_empty());
+
+        assert_eq!(started.recv().unwrap(), 0);
+        let status = scheduler.worker_status();
+        assert_eq!(total(&status), 2);
+        assert_eq!((status.proving, status.done), (1, 0));
+
+        release.send(()).unwrap();
+        assert_eq!(started.recv().unwrap(), 1);
+        let status = scheduler.worker_status();
+        assert_eq!(total(&status), 2);
+        assert_eq!((status.proving, status.done), (1, 1));
+
+        release.send(()).unwrap();
+        for &id in &ids {
+            assert!(scheduler.wait(id).unwrap().is_finished());
+        }
+
+        let status = scheduler.worker_status();
+        assert_eq!(status.done, 2);
+        assert_eq!(total(&status), 2);
+        assert!(status.verification_throughput.is_empty());
+    }
+}
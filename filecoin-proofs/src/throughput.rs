@@ -0,0 +1,255 @@
+//! Measures how many seal proofs per second this machine can verify.
+//!
+//! Groth16 verification cost depends only on the number of public inputs, not on the size of the
+//! circuit that produced a proof. The benchmark therefore uses a tiny circuit with as many public
+//! inputs as a real seal proof, which makes it cheap to run without any cached parameters.
+//!
+//! Samples serialize with serde. `SealScheduler::worker_status` reports them, when the scheduler
+//! is configured to measure them, and `benchy verify-throughput` prints them as JSON.
+
+use std::time::{Duration, Instant};
+
+use bellperson::groth16;
+use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+use ff::Field;
+use paired::bls12_381::{Bls12, Fr};
+use rand::{thread_rng, Rng, SeedableRng, XorShiftRng};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use storage_proofs::batch::verify_proofs_batch;
+use storage_proofs::circuit::stacked::StackedCompound;
+use storage_proofs::compound_proof::CompoundProof;
+use storage_proofs::drgraph::DefaultTreeHasher;
+use storage_proofs::hasher::Hasher;
+use storage_proofs::stacked::{self, Tau};
+
+use crate::error;
+use crate::parameters::porep_public_params;
+use crate::types::PoRepConfig;
+
+/// What to measure. Every combination of batch size and thread count yields one sample.
+#[derive(Clone, Debug)]
+pub struct ThroughputConfig {
+    /// Public inputs per proof, see `seal_public_input_count`.
+    pub public_inputs: usize,
+    /// Proofs folded into each batch check. A batch size of 1 uses plain Groth16 verification.
+    pub batch_sizes: Vec<usize>,
+    /// Sizes of the thread pools which verify batches concurrently.
+    pub thread_counts: Vec<usize>,
+    /// Minimum number of proofs verified per sample; rounded up to a whole number of batches.
+    pub proofs_per_sample: usize,
+}
+
+/// The verification throughput observed for one batch size and thread count.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ThroughputSample {
+    pub batch_size: usize,
+    pub threads: usize,
+    pub proofs: usize,
+    pub wall_time_ms: u64,
+    pub proofs_per_second: f64,
+}
+
+/// Returns the number of public inputs of a single partition proof for `porep_config`.
+pub fn seal_public_input_count(porep_config: PoRepConfig) -> error::Result<usize> {
+    let compound_public_params = porep_public_params(porep_config)?;
+
+    let public_inputs = stacked::PublicInputs::<<DefaultTreeHasher as Hasher>::Domain> {
+        replica_id: Default::default(),
+        tau: Some(Tau {
+            comm_r: Default::default(),
+            comm_d: Default::default(),
+        }),
+        seed: None,
+        k: None,
+    };
+
+    Ok(StackedCompound::generate_public_inputs(
+        &public_inputs,
+        &compound_public_params.vanilla_params,
+        Some(0),
+    )
+    .len())
+}
+
+/// Measures verification throughput for every batch size and thread count in `config`.
+pub fn verification_throughput(config: &ThroughputConfig) -> error::Result<Vec<ThroughputSample>> {
+    ensure!(
+        config.batch_sizes.iter().all(|&n| n > 0),
+        "batch sizes must be positive"
+    );
+    ensure!(
+        config.thread_counts.iter().all(|&n| n > 0),
+        "thread counts must be positive"
+    );
+    ensure!(
+        config.proofs_per_sample > 0,
+        "proofs_per_sample must be positive"
+    );
+
+    let max_batch_size = config.batch_sizes.iter().cloned().max().unwrap_or(0);
+    if max_batch_size == 0 || config.thread_counts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rng = &mut XorShiftRng::from_seed([0x3dbe_6259, 0x8d31_3d76, 0x3237_db17, 0xe5bc_0654]);
+
+    let params = groth16::generate_random_parameters::<Bls12, _, _>(
+        SquaresCircuit {
+            roots: vec![None; config.public_inputs],
+        },
+        rng,
+    )?;
+    let pvk = groth16::prepare_verifying_key(&params.vk);
+
+    let mut proofs = Vec::with_capacity(max_batch_size);
+    let mut inputs = Vec::with_capacity(max_batch_size);
+    for _ in 0..max_batch_size {
+        let roots: Vec<Fr> = (0..config.public_inputs).map(|_| rng.gen()).collect();
+        let squares = roots
+            .iter()
+            .map(|root| {
+                let mut square = *root;
+                square.square();
+                square
+            })
+            .collect::<Vec<_>>();
+
+        let circuit = SquaresCircuit {
+            roots: roots.into_iter().map(Some).collect(),
+        };
+        proofs.push(groth16::create_random_proof(circuit, &params, rng)?);
+        inputs.push(squares);
+    }
+
+    let mut samples = Vec::new();
+
+    for &threads in &config.thread_counts {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|err| format_err!("failed to build thread pool: {}", err))?;
+
+        for &batch_size in &config.batch_sizes {
+            let batches = (config.proofs_per_sample + batch_size - 1) / batch_size;
+            let proof_refs: Vec<_> = proofs[..batch_size].iter().collect();
+            let batch_inputs = &inputs[..batch_size];
+
+            let start = Instant::now();
+            let valid = pool.install(|| {
+                (0..batches)
+                    .into_par_iter()
+                    .map(|_| {
+                        if batch_size == 1 {
+                            groth16::verify_proof(&pvk, proof_refs[0], &batch_inputs[0])
+                                .map_err(Into::into)
+                        } else {
+                            verify_proofs_batch(
+                                &params.vk,
+                                &mut thread_rng(),
+                                &proof_refs,
+                                batch_inputs,
+                            )
+                            .map_err(Into::into)
+                        }
+                    })
+                    .collect::<error::Result<Vec<bool>>>()
+            })?;
+            let elapsed = start.elapsed();
+
+            ensure!(
+                valid.into_iter().all(|v| v),
+                "benchmark proofs failed to verify"
+            );
+
+            let proofs_verified = batches * batch_size;
+            samples.push(ThroughputSample {
+                batch_size,
+                threads,
+                proofs: proofs_verified,
+                wall_time_ms: duration_millis(elapsed),
+                proofs_per_second: proofs_verified as f64 / duration_secs(elapsed),
+            });
+
+            info!(
+                "verified {} proofs in batches of {} on {} threads in {:?}",
+                proofs_verified, batch_size, threads, elapsed
+            );
+        }
+    }
+
+    Ok(samples)
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// Proves knowledge of a square root of each public input.
+struct SquaresCircuit {
+    roots: Vec<Option<Fr>>,
+}
+
+impl Circuit<Bls12> for SquaresCircuit {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        for (i, root) in self.roots.into_iter().enumerate() {
+            let x = cs.alloc(
+                || format!("root_{}", i),
+                || root.ok_or(SynthesisError::AssignmentMissing),
+            )?;
+            let y = cs.alloc_input(
+                || format!("square_{}", i),
+                || {
+                    let mut square = root.ok_or(SynthesisError::AssignmentMissing)?;
+                    square.square();
+                    Ok(square)
+                },
+            )?;
+
+            cs.enforce(
+                || format!("root_{} squared", i),
+                |lc| lc + x,
+                |lc| lc + x,
+                |lc| lc + y,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verification_throughput() {
+        let config = ThroughputConfig {
+            public_inputs: 4,
+            batch_sizes: vec![1, 3],
+            thread_counts: vec![1, 2],
+            proofs_per_sample: 4,
+        };
+
+        let samples = verification_throughput(&config).unwrap();
+        assert_eq!(samples.len(), 4);
+
+        for sample in &samples {
+            assert!(sample.proofs >= config.proofs_per_sample);
+            assert_eq!(sample.proofs % sample.batch_size, 0);
+            assert!(sample.proofs_per_second > 0.0);
+        }
+
+        assert!(verification_throughput(&ThroughputConfig {
+            batch_sizes: vec![0],
+            ..config
+        })
+        .is_err());
+    }
+}
@@ -3,6 +3,7 @@ mod porep_config;
 mod porep_proof_partitions;
 mod post_config;
 mod post_proof_partitions;
+mod registered_proof;
 mod sector_class;
 mod sector_size;
 
@@ -11,5 +12,6 @@ pub use self::porep_config::*;
 pub use self::porep_proof_partitions::*;
 pub use self::post_config::*;
 pub use self::post_proof_partitions::*;
+pub use self::registered_proof::*;
 pub use self::sector_class::*;
 pub use self::sector_size::*;
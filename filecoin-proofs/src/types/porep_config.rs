@@ -2,9 +2,12 @@ use std::path::PathBuf;
 
 use paired::bls12_381::Bls12;
 use storage_proofs::circuit::stacked::{StackedCircuit, StackedCompound};
+use storage_proofs::compound_proof::CompoundProof;
 use storage_proofs::drgraph::DefaultTreeHasher;
 use storage_proofs::parameter_cache::{self, CacheableParameters};
 
+use crate::error;
+use crate::singletons::ENGINE_PARAMS;
 use crate::types::*;
 
 #[derive(Clone, Copy, Debug)]
@@ -35,6 +38,17 @@ impl From<PoRepConfig> for PoRepProofPartitions {
 }
 
 impl PoRepConfig {
+    /// Returns the number of partitions the proof of this configuration is split into.
+    pub fn partitions(self) -> usize {
+        usize::from(self.1)
+    }
+
+    /// Returns the id which separates sectors of this configuration from those of any other
+    /// sector size or proof version.
+    pub fn porep_id(self) -> [u8; 32] {
+        crate::parameters::porep_id(self.into())
+    }
+
     /// Returns the cache identifier as used by `storage-proofs::paramater_cache`.
     pub fn get_cache_identifier(&self) -> String {
         let params = crate::parameters::public_params(self.0.into(), self.1.into());
@@ -44,6 +58,20 @@ impl PoRepConfig {
         )
     }
 
+    /// Checks that the cached parameters of this configuration were generated for its circuit,
+    /// see `CacheableParameters::check_circuit_identifier`.
+    pub fn check_circuit_identifier(&self) -> error::Result<()> {
+        let params = crate::parameters::public_params(self.0.into(), self.1.into());
+        let circuit = StackedCompound::blank_circuit(&params, *ENGINE_PARAMS);
+
+        <StackedCompound as CacheableParameters<Bls12, StackedCircuit<_, DefaultTreeHasher>, _>>::check_circuit_identifier(
+            circuit,
+            &params,
+        )?;
+
+        Ok(())
+    }
+
     pub fn get_cache_metadata_path(&self) -> PathBuf {
         let id = self.get_cache_identifier();
         parameter_cache::parameter_cache_metadata_path(&id)
@@ -2,9 +2,14 @@ use std::path::PathBuf;
 
 use paired::bls12_381::Bls12;
 use storage_proofs::circuit::rational_post::{RationalPoStCircuit, RationalPoStCompound};
+use storage_proofs::compound_proof::CompoundProof;
 use storage_proofs::drgraph::DefaultTreeHasher;
 use storage_proofs::parameter_cache::{self, CacheableParameters};
+use storage_proofs::rational_post::RationalPoSt;
 
+use crate::error;
+use crate::parameters::PostPublicParams;
+use crate::singletons::ENGINE_PARAMS;
 use crate::types::*;
 
 #[derive(Clone, Copy, Debug)]
@@ -38,6 +43,21 @@ impl PoStConfig {
         >>::cache_identifier(&params)
     }
 
+    /// Checks that the cached parameters of this configuration were generated for its circuit,
+    /// see `CacheableParameters::check_circuit_identifier`.
+    pub fn check_circuit_identifier(self) -> error::Result<()> {
+        let params = crate::parameters::post_public_params(self);
+        let circuit = self.blank_circuit(&params);
+
+        <RationalPoStCompound<DefaultTreeHasher> as CacheableParameters<
+            Bls12,
+            RationalPoStCircuit<_, DefaultTreeHasher>,
+            _,
+        >>::check_circuit_identifier(circuit, &params)?;
+
+        Ok(())
+    }
+
     pub fn get_cache_metadata_path(self) -> PathBuf {
         let id = self.get_cache_identifier();
         parameter_cache::parameter_cache_metadata_path(&id)
@@ -52,4 +72,15 @@ impl PoStConfig {
         let id = self.get_cache_identifier();
         parameter_cache::parameter_cache_params_path(&id)
     }
+
+    fn blank_circuit(
+        self,
+        params: &PostPublicParams,
+    ) -> RationalPoStCircuit<'static, Bls12, DefaultTreeHasher> {
+        <RationalPoStCompound<DefaultTreeHasher> as CompoundProof<
+            Bls12,
+            RationalPoSt<DefaultTreeHasher>,
+            RationalPoStCircuit<Bls12, DefaultTreeHasher>,
+        >>::blank_circuit(params, *ENGINE_PARAMS)
+    }
 }
@@ -0,0 +1,209 @@
+use storage_proofs::stacked::LayerChallenges;
+
+use crate::constants::{
+    sector_size_constants, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_512_MIB,
+    SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB,
+};
+use crate::error;
+use crate::parameters::{
+    porep_public_params, post_public_params, setup_params, PoRepCompoundPublicParams,
+    PostPublicParams, POREP_PROOF_VERSION,
+};
+use crate::types::*;
+
+/// A seal proof supported by this release. Each variant pins down every parameter of the proof,
+/// so new versions can be added next to the existing ones without changing their meaning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RegisteredSealProof {
+    StackedDrg2KiBV1,
+    StackedDrg8MiBV1,
+    StackedDrg512MiBV1,
+    StackedDrg32GiBV1,
+    StackedDrg64GiBV1,
+}
+
+impl RegisteredSealProof {
+    pub const ALL: [RegisteredSealProof; 5] = [
+        RegisteredSealProof::StackedDrg2KiBV1,
+        RegisteredSealProof::StackedDrg8MiBV1,
+        RegisteredSealProof::StackedDrg512MiBV1,
+        RegisteredSealProof::StackedDrg32GiBV1,
+        RegisteredSealProof::StackedDrg64GiBV1,
+    ];
+
+    /// Returns the version of the PoRep construction this proof belongs to.
+    pub fn version(self) -> u64 {
+        POREP_PROOF_VERSION
+    }
+
+    pub fn sector_size(self) -> SectorSize {
+        use RegisteredSealProof::*;
+
+        match self {
+            StackedDrg2KiBV1 => SectorSize(SECTOR_SIZE_2_KIB),
+            StackedDrg8MiBV1 => SectorSize(SECTOR_SIZE_8_MIB),
+            StackedDrg512MiBV1 => SectorSize(SECTOR_SIZE_512_MIB),
+            StackedDrg32GiBV1 => SectorSize(SECTOR_SIZE_32_GIB),
+            StackedDrg64GiBV1 => SectorSize(SECTOR_SIZE_64_GIB),
+        }
+    }
+
+    pub fn partitions(self) -> PoRepProofPartitions {
+        sector_size_constants(self.sector_size().0)
+            .expect("registered proof without sector size constants")
+            .partitions
+    }
+
+    pub fn layers(self) -> usize {
+        self.layer_challenges().layers()
+    }
+
+    /// Returns the number of challenges of a single partition, summed over all layers.
+    pub fn challenge_count(self) -> usize {
+        self.layer_challenges().challenges_count_all()
+    }
+
+    // Read from the setup params, as building the public params also builds the graph.
+    fn layer_challenges(self) -> LayerChallenges {
+        setup_params(self.sector_size().into(), usize::from(self.partitions()))
+            .expect("registered proof with invalid partitions")
+            .layer_challenges
+    }
+
+    pub fn porep_id(self) -> [u8; 32] {
+        PoRepConfig::from(self).porep_id()
+    }
+
+    /// Returns the identifier of the circuit, under which its parameters are cached.
+    pub fn circuit_identifier(self) -> String {
+        PoRepConfig::from(self).get_cache_identifier()
+    }
+
+    pub fn public_params(self) -> error::Result<PoRepCompoundPublicParams> {
+        porep_public_params(self.into())
+    }
+
+    /// Returns the current proof for sectors of the given size, if there is one.
+    pub fn from_sector_size(sector_size: SectorSize) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .cloned()
+            .find(|proof| proof.sector_size().0 == sector_size.0)
+    }
+}
+
+impl From<RegisteredSealProof> for PoRepConfig {
+    fn from(x: RegisteredSealProof) -> Self {
+        PoRepConfig(x.sector_size(), x.partitions())
+    }
+}
+
+/// A PoSt supported by this release, see `RegisteredSealProof`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RegisteredPoStProof {
+    RationalPoSt2KiBV1,
+    RationalPoSt8MiBV1,
+    RationalPoSt512MiBV1,
+    RationalPoSt32GiBV1,
+    RationalPoSt64GiBV1,
+}
+
+impl RegisteredPoStProof {
+    pub const ALL: [RegisteredPoStProof; 5] = [
+        RegisteredPoStProof::RationalPoSt2KiBV1,
+        RegisteredPoStProof::RationalPoSt8MiBV1,
+        RegisteredPoStProof::RationalPoSt512MiBV1,
+        RegisteredPoStProof::RationalPoSt32GiBV1,
+        RegisteredPoStProof::RationalPoSt64GiBV1,
+    ];
+
+    pub fn sector_size(self) -> SectorSize {
+        use RegisteredPoStProof::*;
+
+        match self {
+            RationalPoSt2KiBV1 => SectorSize(SECTOR_SIZE_2_KIB),
+            RationalPoSt8MiBV1 => SectorSize(SECTOR_SIZE_8_MIB),
+            RationalPoSt512MiBV1 => SectorSize(SECTOR_SIZE_512_MIB),
+            RationalPoSt32GiBV1 => SectorSize(SECTOR_SIZE_32_GIB),
+            RationalPoSt64GiBV1 => SectorSize(SECTOR_SIZE_64_GIB),
+        }
+    }
+
+    pub fn challenge_count(self) -> usize {
+        self.public_params().challenges_count
+    }
+
+    /// Returns the identifier of the circuit, under which its parameters are cached.
+    pub fn circuit_identifier(self) -> String {
+        PoStConfig::from(self).get_cache_identifier()
+    }
+
+    pub fn public_params(self) -> PostPublicParams {
+        post_public_params(self.into())
+    }
+
+    /// Returns the current proof for sectors of the given size, if there is one.
+    pub fn from_sector_size(sector_size: SectorSize) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .cloned()
+            .find(|proof| proof.sector_size().0 == sector_size.0)
+    }
+}
+
+impl From<RegisteredPoStProof> for PoStConfig {
+    fn from(x: RegisteredPoStProof) -> Self {
+        PoStConfig(x.sector_size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_registered_seal_proofs_are_distinct() {
+        let ids: HashSet<_> = RegisteredSealProof::ALL
+            .iter()
+            .map(|proof| proof.porep_id())
+            .collect();
+        assert_eq!(ids.len(), RegisteredSealProof::ALL.len());
+
+        for proof in RegisteredSealProof::ALL.iter() {
+            assert_eq!(
+                RegisteredSealProof::from_sector_size(proof.sector_size()),
+                Some(*proof)
+            );
+            assert!(proof.layers() > 0);
+            assert!(proof.challenge_count() > 0);
+        }
+
+        assert_eq!(
+            RegisteredSealProof::from_sector_size(SectorSize(1024)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_circuit_identifiers() {
+        let seal = RegisteredSealProof::StackedDrg2KiBV1;
+        let post = RegisteredPoStProof::RationalPoSt2KiBV1;
+        assert_ne!(seal.circuit_identifier(), post.circuit_identifier());
+    }
+
+    #[test]
+    fn test_registered_post_proofs() {
+        for proof in RegisteredPoStProof::ALL.iter() {
+            assert_eq!(
+                RegisteredPoStProof::from_sector_size(proof.sector_size()),
+                Some(*proof)
+            );
+            assert_eq!(
+                proof.public_params().sector_size,
+                u64::from(PaddedBytesAmount::from(PoStConfig::from(*proof)))
+            );
+        }
+    }
+}
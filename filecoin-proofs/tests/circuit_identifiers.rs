@@ -0,0 +1,124 @@
+//! The parameter cache setting is read once per process, so these tests run in their own binary to
+//! point the cache at a temporary directory.
+
+#[macro_use]
+extern crate lazy_static;
+
+use std::fs;
+
+use filecoin_proofs::parameters::public_params;
+use filecoin_proofs::singletons::ENGINE_PARAMS;
+use filecoin_proofs::{
+    PaddedBytesAmount, PoRepConfig, PoStConfig, RegisteredPoStProof, RegisteredSealProof,
+};
+use storage_proofs::circuit::stacked::StackedCompound;
+use storage_proofs::compound_proof::CompoundProof;
+use storage_proofs::error::Error;
+use storage_proofs::parameter_cache::{CacheableParameters, PARAMETER_CACHE_ENV_VAR};
+
+lazy_static! {
+    // Set before any test reads the settings, whichever of them runs first.
+    static ref CACHE_DIR: tempfile::TempDir = {
+        let dir = tempfile::tempdir().unwrap();
+        std::env::set_var(PARAMETER_CACHE_ENV_VAR, dir.path());
+        dir
+    };
+}
+
+#[test]
+fn test_circuit_identifiers_are_pinned() {
+    lazy_static::initialize(&CACHE_DIR);
+
+    // The published parameters are looked up by these identifiers, so a change to the public
+    // parameters of a registered proof must come with newly published parameters.
+    let seal = [
+        (
+            RegisteredSealProof::StackedDrg2KiBV1,
+            "stacked-proof-of-replication-85a36c0df91c6a7230b68b3b7d1e8a10d560661a4b1bc2f22e9977b8eff28ac4",
+        ),
+        (
+            RegisteredSealProof::StackedDrg8MiBV1,
+            "stacked-proof-of-replication-03d6b285d6a8e234e7f20d48609c17107bf0249244b64ec905b925faac8e0fe3",
+        ),
+        (
+            RegisteredSealProof::StackedDrg512MiBV1,
+            "stacked-proof-of-replication-19583caa9919ffa36328ca6e5431d19c2d02ee337ec1d44424ec770386e68148",
+        ),
+        (
+            RegisteredSealProof::StackedDrg32GiBV1,
+            "stacked-proof-of-replication-0e29be3160bebe45c92f19454c2468189be76a5aba08aca32f519b753572dee9",
+        ),
+        (
+            RegisteredSealProof::StackedDrg64GiBV1,
+            "stacked-proof-of-replication-5a67f02823e69861a1264567b0cadc32298d6ea91bc513cf3ddf39acd9efed65",
+        ),
+    ];
+    let post = [
+        (
+            RegisteredPoStProof::RationalPoSt2KiBV1,
+            "proof-of-spacetime-rational-dc508b3cc2d543f9cb4b654a03fd2ab843668d0f5b665a4bac49de58809281ce",
+        ),
+        (
+            RegisteredPoStProof::RationalPoSt8MiBV1,
+            "proof-of-spacetime-rational-001bf30d9032fa73b88bcdc0b4fa5e11d33ee6abc8c9fce68528875500ebb019",
+        ),
+        (
+            RegisteredPoStProof::RationalPoSt512MiBV1,
+            "proof-of-spacetime-rational-577827cc9e19e7578970bcfad237c6c1178eca8d1c01ffc07b6ccf04961565b2",
+        ),
+        (
+            RegisteredPoStProof::RationalPoSt32GiBV1,
+            "proof-of-spacetime-rational-6758bbe2ef1245cdd48a587f13ed87fd1dfe7bf2ce7db09ace9a62180b0a0f33",
+        ),
+        (
+            RegisteredPoStProof::RationalPoSt64GiBV1,
+            "proof-of-spacetime-rational-6383078c542427ceabafc77abe5c39155346a8dee176b2b73ab1fb7e999d5b60",
+        ),
+    ];
+
+    assert_eq!(seal.len(), RegisteredSealProof::ALL.len());
+    for (proof, expected) in seal.iter() {
+        assert_eq!(proof.circuit_identifier(), *expected, "{:?}", proof);
+    }
+
+    assert_eq!(post.len(), RegisteredPoStProof::ALL.len());
+    for (proof, expected) in post.iter() {
+        assert_eq!(proof.circuit_identifier(), *expected, "{:?}", proof);
+    }
+}
+
+#[test]
+fn test_circuit_identifiers_are_checked() {
+    lazy_static::initialize(&CACHE_DIR);
+
+    let porep_config = PoRepConfig::from(RegisteredSealProof::StackedDrg2KiBV1);
+    let post_config = PoStConfig::from(RegisteredPoStProof::RationalPoSt2KiBV1);
+
+    // Nothing was generated yet, so there is no digest to check against.
+    assert!(porep_config.check_circuit_identifier().is_err());
+    assert!(post_config.check_circuit_identifier().is_err());
+
+    // Records the digest, as generating the parameters does.
+    let params = public_params(
+        PaddedBytesAmount::from(porep_config),
+        porep_config.partitions(),
+    );
+    let circuit = StackedCompound::blank_circuit(&params, *ENGINE_PARAMS);
+    let meta = StackedCompound::get_param_metadata(circuit, &params).unwrap();
+    assert!(meta.circuit_digest.is_some());
+
+    porep_config.check_circuit_identifier().unwrap();
+
+    let meta_path = porep_config.get_cache_metadata_path();
+    assert!(meta_path.starts_with(CACHE_DIR.path()));
+
+    // Metadata without a digest can't vouch for the circuit.
+    fs::write(&meta_path, r#"{"sector_size":2048}"#).unwrap();
+    match porep_config.check_circuit_identifier() {
+        Err(err) => match err.downcast_ref::<Error>() {
+            Some(Error::CircuitChanged(_)) => {}
+            _ => panic!("expected a changed circuit, got {:?}", err),
+        },
+        Ok(()) => panic!("metadata without a digest passed the check"),
+    }
+}
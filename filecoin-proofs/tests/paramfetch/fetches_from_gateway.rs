@@ -0,0 +1,113 @@
+use std::collections::btree_map::BTreeMap;
+use std::collections::HashMap;
+
+use failure::Error as FailureError;
+
+use crate::paramfetch::support::gateway::FakeGateway;
+use crate::paramfetch::support::rand_bytes_with_blake2b;
+use crate::paramfetch::support::session::ParamFetchSessionBuilder;
+use crate::support::tmp_manifest;
+use filecoin_proofs::param::ParameterData;
+
+#[test]
+fn fetches_file_from_gateway() -> Result<(), FailureError> {
+    let mut manifest: BTreeMap<String, ParameterData> = BTreeMap::new();
+
+    let (aaa_bytes, aaa_checksum) = rand_bytes_with_blake2b()?;
+
+    manifest.insert(
+        "aaa.vk".to_string(),
+        ParameterData {
+            cid: "aaacid".to_string(),
+            digest: aaa_checksum,
+            sector_size: 1234,
+        },
+    );
+
+    let manifest_pbuf = tmp_manifest(Some(manifest))?;
+
+    let mut files = HashMap::new();
+    files.insert("aaacid".to_string(), aaa_bytes);
+    let gateway = FakeGateway::new(files);
+
+    let mut session = ParamFetchSessionBuilder::new(Some(manifest_pbuf))
+        .with_session_timeout_ms(5000)
+        .whitelisted_sector_sizes(vec!["1234".to_string()])
+        .with_gateway(gateway.url())
+        .build();
+
+    session.exp_string("fetching: aaa.vk")?;
+    session.exp_string("downloading file... ok")?;
+    session.exp_string("is file valid... yes")?;
+    session.exp_string("done")?;
+
+    Ok(())
+}
+
+#[test]
+fn rejects_file_from_gateway_if_checksum_does_not_match_manifest() -> Result<(), FailureError> {
+    let mut manifest: BTreeMap<String, ParameterData> = BTreeMap::new();
+
+    let (aaa_bytes, _) = rand_bytes_with_blake2b()?;
+
+    manifest.insert(
+        "aaa.vk".to_string(),
+        ParameterData {
+            cid: "aaacid".to_string(),
+            digest: "obviouslywrong".to_string(),
+            sector_size: 1234,
+        },
+    );
+
+    let manifest_pbuf = tmp_manifest(Some(manifest))?;
+
+    let mut files = HashMap::new();
+    files.insert("aaacid".to_string(), aaa_bytes);
+    let gateway = FakeGateway::new(files);
+
+    let mut session = ParamFetchSessionBuilder::new(Some(manifest_pbuf))
+        .with_session_timeout_ms(5000)
+        .whitelisted_sector_sizes(vec!["1234".to_string()])
+        .with_gateway(gateway.url())
+        .build();
+
+    session.exp_string("downloading file... ok")?;
+    session.exp_string("is file valid... no")?;
+    session.exp_string("1 files failed to be fetched")?;
+    session.exp_string("fatal error: some files failed to be fetched")?;
+
+    Ok(())
+}
+
+#[test]
+fn reports_file_missing_from_gateway() -> Result<(), FailureError> {
+    let mut manifest: BTreeMap<String, ParameterData> = BTreeMap::new();
+
+    manifest.insert(
+        "aaa.vk".to_string(),
+        ParameterData {
+            cid: "aaacid".to_string(),
+            digest: "".to_string(),
+            sector_size: 1234,
+        },
+    );
+
+    let manifest_pbuf = tmp_manifest(Some(manifest))?;
+
+    // the gateway knows nothing about the manifest's cid
+    let gateway = FakeGateway::new(HashMap::new());
+
+    let mut session = ParamFetchSessionBuilder::new(Some(manifest_pbuf))
+        .with_session_timeout_ms(5000)
+        .whitelisted_sector_sizes(vec!["1234".to_string()])
+        .with_gateway(gateway.url())
+        .build();
+
+    session.exp_string("downloading file... error: failed to download")?;
+    session.exp_string("404")?;
+    session.exp_string("does file exist... no")?;
+    session.exp_string("1 files failed to be fetched")?;
+    session.exp_string("fatal error: some files failed to be fetched")?;
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
 mod support;
 
+pub mod fetches_from_gateway;
 pub mod prompts_to_fetch;
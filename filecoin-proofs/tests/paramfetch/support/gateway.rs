@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+/// A minimal HTTP server standing in for an IPFS gateway. Files are served
+/// from `/<cid>`; any other path produces a 404.
+pub struct FakeGateway {
+    url: String,
+}
+
+impl FakeGateway {
+    pub fn new(files: HashMap<String, Vec<u8>>) -> FakeGateway {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind gateway");
+        let url = format!(
+            "http://{}",
+            listener
+                .local_addr()
+                .expect("failed to get gateway address")
+        );
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    let _ = respond(stream, &files);
+                }
+            }
+        });
+
+        FakeGateway { url }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+fn respond(mut stream: TcpStream, files: &HashMap<String, Vec<u8>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // drain the headers; range requests are answered with the full body
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+    }
+
+    let cid = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or("")
+        .trim_start_matches('/');
+
+    match files.get(cid) {
+        Some(bytes) => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                bytes.len()
+            )?;
+            stream.write_all(bytes)?;
+        }
+        None => {
+            write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )?;
+        }
+    }
+
+    stream.flush()
+}
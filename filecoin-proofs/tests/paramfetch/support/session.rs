@@ -17,6 +17,7 @@ pub struct ParamFetchSessionBuilder {
     session_timeout_ms: u64,
     whitelisted_sector_sizes: Option<Vec<String>>,
     manifest: Option<PathBuf>,
+    gateway: Option<String>,
     prompt_enabled: bool,
 }
 
@@ -28,6 +29,7 @@ impl ParamFetchSessionBuilder {
             cache_dir: temp_dir,
             session_timeout_ms: 1000,
             manifest,
+            gateway: None,
             prompt_enabled: true,
             whitelisted_sector_sizes: None,
         }
@@ -48,6 +50,12 @@ impl ParamFetchSessionBuilder {
         self
     }
 
+    /// Download over HTTP from the provided gateway instead of using ipget.
+    pub fn with_gateway<S: AsRef<str>>(mut self, url: S) -> ParamFetchSessionBuilder {
+        self.gateway = Some(url.as_ref().to_string());
+        self
+    }
+
     /// Create a file with the provided bytes in the cache directory.
     pub fn with_file_and_bytes<P: AsRef<Path>, R: Read>(
         self,
@@ -88,15 +96,20 @@ impl ParamFetchSessionBuilder {
             "".to_string()
         };
 
+        let source_argument = match self.gateway {
+            Some(url) => format!("--gateway={:?}", url),
+            None => format!("--ipget-bin={:?}", "true"),
+        };
+
         let cmd = format!(
-            "{}={} {:?} {} {} {} --ipget-bin={:?}",
+            "{}={} {:?} {} {} {} {}",
             PARAMETER_CACHE_ENV_VAR,
             cache_dir_path,
             paramfetch_path,
             if self.prompt_enabled { "" } else { "--all" },
             json_argument,
             whitelist,
-            "true"
+            source_argument
         );
 
         p.execute(&cmd, ".*").expect("could not execute paramfetch");
@@ -24,7 +24,13 @@ fn ignores_files_unrecognized_extensions() {
             let (mut session, _) = ParamPublishSessionBuilder::new()
                 .with_session_timeout_ms(1000)
                 .with_files(&to_create)
-                .with_metadata("aaa.meta", &CacheEntryMetadata { sector_size: 1234 })
+                .with_metadata(
+                    "aaa.meta",
+                    &CacheEntryMetadata {
+                        sector_size: 1234,
+                        circuit_digest: None,
+                    },
+                )
                 .build();
 
             for _ in 0..to_prompt.len() {
@@ -52,8 +58,20 @@ fn displays_sector_size_in_prompt() {
             let (mut session, _) = ParamPublishSessionBuilder::new()
                 .with_session_timeout_ms(1000)
                 .with_files(&to_create)
-                .with_metadata("aaa.meta", &CacheEntryMetadata { sector_size: 1234 })
-                .with_metadata("xxx.meta", &CacheEntryMetadata { sector_size: 4444 })
+                .with_metadata(
+                    "aaa.meta",
+                    &CacheEntryMetadata {
+                        sector_size: 1234,
+                        circuit_digest: None,
+                    },
+                )
+                .with_metadata(
+                    "xxx.meta",
+                    &CacheEntryMetadata {
+                        sector_size: 4444,
+                        circuit_digest: None,
+                    },
+                )
                 .build();
 
             let mut map: BTreeMap<&str, String> = BTreeMap::new();
@@ -83,7 +101,13 @@ fn all_flag_disables_prompt() -> Result<(), FailureError> {
         .with_session_timeout_ms(1000)
         .with_prompt_disabled()
         .with_files(&filenames)
-        .with_metadata("aaa.meta", &CacheEntryMetadata { sector_size: 1234 })
+        .with_metadata(
+            "aaa.meta",
+            &CacheEntryMetadata {
+                sector_size: 1234,
+                circuit_digest: None,
+            },
+        )
         .build();
 
     session.exp_string("publishing 2 files")?;
@@ -21,7 +21,13 @@ fn writes_json_manifest() -> Result<(), FailureError> {
     let (mut session, files_in_cache) = ParamPublishSessionBuilder::new()
         .with_session_timeout_ms(1000)
         .with_files(&filenames)
-        .with_metadata("aaa.meta", &CacheEntryMetadata { sector_size: 1234 })
+        .with_metadata(
+            "aaa.meta",
+            &CacheEntryMetadata {
+                sector_size: 1234,
+                circuit_digest: None,
+            },
+        )
         .write_manifest_to(manifest_path.clone())
         .with_ipfs_bin(&ipfs)
         .with_prompt_disabled()
@@ -0,0 +1,78 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Bencher, Criterion, ParameterizedBenchmark};
+use merkletree::hash::Algorithm;
+use rand::{thread_rng, Rng};
+use storage_proofs::hasher::{
+    Blake2sHasher, Domain, HashFunction, Hasher, PedersenHasher, Sha256Hasher,
+};
+use storage_proofs::stacked::Column;
+
+fn hash_leaf<H: Hasher>(b: &mut Bencher, bytes: &usize) {
+    let mut rng = thread_rng();
+    let data: Vec<u8> = (0..*bytes).map(|_| rng.gen()).collect();
+
+    b.iter(|| black_box(<H::Function as HashFunction<H::Domain>>::hash(&data)))
+}
+
+fn hash_node<H: Hasher>(b: &mut Bencher, height: &usize) {
+    let mut rng = thread_rng();
+    let left: H::Domain = rng.gen();
+    let right: H::Domain = rng.gen();
+
+    b.iter(|| black_box(H::Function::default().node(left, right, *height)))
+}
+
+// Columns are always hashed with Pedersen, whatever the hasher of the trees.
+fn hash_column_pedersen(b: &mut Bencher, layers: &usize) {
+    let mut rng = thread_rng();
+    let column = Column::<PedersenHasher>::new(0, (0..*layers).map(|_| rng.gen()).collect());
+
+    b.iter(|| black_box(column.hash()))
+}
+
+// The same column bytes, hashed with another hasher, for comparison.
+fn hash_column<H: Hasher>(b: &mut Bencher, layers: &usize) {
+    let mut rng = thread_rng();
+    let rows: Vec<H::Domain> = (0..*layers).map(|_| rng.gen()).collect();
+    let data: Vec<u8> = rows.iter().flat_map(|row| row.into_bytes()).collect();
+
+    b.iter(|| black_box(<H::Function as HashFunction<H::Domain>>::hash(&data)))
+}
+
+fn leaf_benchmark(c: &mut Criterion) {
+    let params = vec![32, 64, 10 * 32, 1024];
+
+    c.bench(
+        "hasher-leaf",
+        ParameterizedBenchmark::new("pedersen", hash_leaf::<PedersenHasher>, params)
+            .with_function("blake2s", hash_leaf::<Blake2sHasher>)
+            .with_function("sha256", hash_leaf::<Sha256Hasher>),
+    );
+}
+
+fn node_benchmark(c: &mut Criterion) {
+    let params = vec![0, 30];
+
+    c.bench(
+        "hasher-node",
+        ParameterizedBenchmark::new("pedersen", hash_node::<PedersenHasher>, params)
+            .with_function("blake2s", hash_node::<Blake2sHasher>)
+            .with_function("sha256", hash_node::<Sha256Hasher>),
+    );
+}
+
+fn column_benchmark(c: &mut Criterion) {
+    let params = vec![2, 4, 8, 11];
+
+    c.bench(
+        "hasher-column",
+        ParameterizedBenchmark::new("pedersen", hash_column_pedersen, params)
+            .with_function("blake2s", hash_column::<Blake2sHasher>)
+            .with_function("sha256", hash_column::<Sha256Hasher>),
+    );
+}
+
+criterion_group!(benches, leaf_benchmark, node_benchmark, column_benchmark);
+criterion_main!(benches);
@@ -5,7 +5,7 @@ use bellperson::groth16::*;
 use bellperson::{Circuit, ConstraintSystem, SynthesisError};
 use criterion::{black_box, Criterion, ParameterizedBenchmark};
 use fil_sapling_crypto::circuit::boolean::{self, Boolean};
-use fil_sapling_crypto::jubjub::{JubjubBls12, JubjubEngine};
+use fil_sapling_crypto::jubjub::JubjubEngine;
 use paired::bls12_381::Bls12;
 use rand::{thread_rng, Rng};
 use storage_proofs::circuit::bench::BenchCS;
@@ -72,12 +72,11 @@ fn pedersen_circuit_benchmark(c: &mut Criterion) {
         .lock()
         .unwrap()
         .pedersen_hash_exp_window_size;
-    let jubjub_params = JubjubBls12::new_with_window_size(window_size);
-    let jubjub_params2 = JubjubBls12::new_with_window_size(window_size);
+    let jubjub_params = pedersen::jubjub_params(window_size);
     let mut rng1 = thread_rng();
     let groth_params = generate_random_parameters::<Bls12, _, _>(
         PedersenExample {
-            params: &jubjub_params,
+            params: jubjub_params,
             data: &vec![None; 256],
         },
         &mut rng1,
@@ -97,7 +96,7 @@ fn pedersen_circuit_benchmark(c: &mut Criterion) {
                 b.iter(|| {
                     let proof = create_random_proof(
                         PedersenExample {
-                            params: &jubjub_params,
+                            params: jubjub_params,
                             data: data.as_slice(),
                         },
                         &groth_params,
@@ -117,7 +116,7 @@ fn pedersen_circuit_benchmark(c: &mut Criterion) {
             b.iter(|| {
                 let mut cs = BenchCS::<Bls12>::new();
                 PedersenExample {
-                    params: &jubjub_params2,
+                    params: jubjub_params,
                     data: data.as_slice(),
                 }
                 .synthesize(&mut cs)
@@ -0,0 +1,74 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Criterion, ParameterizedBenchmark, Throughput};
+use rand::{thread_rng, Rng};
+use storage_proofs::drgraph::{new_seed, BASE_DEGREE};
+use storage_proofs::hasher::sha256::Sha256Hasher;
+use storage_proofs::stacked::{
+    label_layer, Labeler, Prefetch, PrefetchHint, StackedBucketGraph, EXP_DEGREE,
+};
+use storage_proofs::util::NODE_SIZE;
+
+/// Large enough for the layers to be far out of the CPU caches.
+const NODES: usize = 1 << 20;
+
+struct Layer {
+    graph: StackedBucketGraph<Sha256Hasher>,
+    labeler: Labeler,
+    labels: Vec<u8>,
+    exp_labels: Vec<u8>,
+}
+
+fn pregenerate_layer() -> Layer {
+    let mut rng = thread_rng();
+    let graph = StackedBucketGraph::new_stacked(NODES, BASE_DEGREE, EXP_DEGREE, new_seed());
+    let porep_id: [u8; 32] = rng.gen();
+    let replica_id: [u8; 32] = rng.gen();
+
+    Layer {
+        graph,
+        labeler: Labeler::new(&porep_id, &replica_id, NODE_SIZE),
+        labels: vec![0; NODES * NODE_SIZE],
+        exp_labels: (0..NODES * NODE_SIZE).map(|_| rng.gen()).collect(),
+    }
+}
+
+fn label(layer: &mut Layer, prefetch: Prefetch) {
+    label_layer(
+        &layer.graph,
+        &layer.labeler,
+        0,
+        &mut layer.labels,
+        Some(&layer.exp_labels),
+        NODE_SIZE,
+        prefetch,
+        |_| Ok(()),
+    )
+    .unwrap();
+}
+
+/// Labels a layer with every prefetch hint over a range of distances, to pick the settings for
+/// a CPU. Without a hint the distance doesn't matter, which gives the baseline.
+fn prefetch_benchmark(c: &mut Criterion) {
+    let distances = vec![1, 2, 4, 8, 16, 32, 64];
+    let bench_hint = |hint| {
+        move |b: &mut criterion::Bencher, distance: &usize| {
+            let mut layer = pregenerate_layer();
+            let prefetch = Prefetch::new(hint, *distance).unwrap();
+            b.iter(|| black_box(label(&mut layer, prefetch)))
+        }
+    };
+
+    c.bench(
+        "label-layer-prefetch",
+        ParameterizedBenchmark::new("none", bench_hint(PrefetchHint::None), distances)
+            .with_function("t0", bench_hint(PrefetchHint::T0))
+            .with_function("t1", bench_hint(PrefetchHint::T1))
+            .sample_size(10)
+            .throughput(|_| Throughput::Bytes((NODES * NODE_SIZE) as u32)),
+    );
+}
+
+criterion_group!(benches, prefetch_benchmark);
+criterion_main!(benches);
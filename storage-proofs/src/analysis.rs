@@ -0,0 +1,120 @@
+//! Challenge counts needed to reach a target soundness.
+//!
+//! A prover missing a fraction `spacegap` of the nodes of a sector answers a uniformly drawn
+//! challenge with probability at most `1 - spacegap`, so it passes `n` independent challenges
+//! with probability at most `(1 - spacegap)^n`. The counts below are the smallest `n` for which
+//! that probability does not exceed the target soundness of `2^-bits`.
+
+use std::cmp;
+
+use crate::stacked::LayerChallenges;
+use crate::util::NODE_SIZE;
+
+/// Returns the fraction of the nodes of a sector of `sector_size` bytes a prover is missing when
+/// it is missing `spacegap` of the sector, which is at least one node.
+fn missing_fraction(spacegap: f64, sector_size: u64) -> f64 {
+    let nodes = (sector_size / NODE_SIZE as u64) as f64;
+
+    (spacegap * nodes).ceil().max(1.0).min(nodes) / nodes
+}
+
+/// Returns the number of challenges each of the `proofs_per_period` proofs of a proving period
+/// needs, so that a prover missing `spacegap` (a fraction in `(0, 1)`) of a sector of
+/// `sector_size` bytes passes all of them with probability at most `2^-bits`.
+pub fn challenge_count_for_security(
+    bits: u32,
+    spacegap: f64,
+    sector_size: u64,
+    proofs_per_period: usize,
+) -> usize {
+    assert!(
+        spacegap > 0.0 && spacegap < 1.0,
+        "spacegap must be in (0, 1), got {}",
+        spacegap
+    );
+    assert!(proofs_per_period > 0, "at least one proof per period");
+    assert!(
+        sector_size >= NODE_SIZE as u64,
+        "sector must hold at least one node"
+    );
+
+    let missing = missing_fraction(spacegap, sector_size);
+    if missing >= 1.0 {
+        // Every challenge hits a missing node.
+        return 1;
+    }
+
+    let bits_per_challenge = -(1.0 - missing).log2();
+    let total = (f64::from(bits) / bits_per_challenge).ceil() as usize;
+
+    cmp::max(1, (total + proofs_per_period - 1) / proofs_per_period)
+}
+
+/// Returns a PoRep challenge schedule over `layers` layers in which every layer is challenged
+/// often enough for a single partition to reach `2^-bits` soundness against a prover missing
+/// `spacegap` of that layer.
+pub fn layer_challenges_for_security(
+    bits: u32,
+    spacegap: f64,
+    sector_size: u64,
+    layers: usize,
+) -> LayerChallenges {
+    let count = challenge_count_for_security(bits, spacegap, sector_size, 1);
+
+    // The untapered schedule challenges every layer but the first half as often.
+    LayerChallenges::new(layers, 2 * count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_KIB: u64 = 1024;
+    const ONE_GIB: u64 = 1 << 30;
+
+    #[test]
+    fn test_challenge_count_for_security() {
+        // -log2(0.8) ~= 0.3219 bits per challenge.
+        assert_eq!(challenge_count_for_security(10, 0.2, ONE_GIB, 1), 32);
+        assert_eq!(challenge_count_for_security(20, 0.2, ONE_GIB, 1), 63);
+
+        // The challenges are spread over the proofs of the period.
+        assert_eq!(challenge_count_for_security(10, 0.2, ONE_GIB, 2), 16);
+        assert_eq!(challenge_count_for_security(10, 0.2, ONE_GIB, 1000), 1);
+
+        // Small sectors round the gap up to whole nodes, 7 of 32 here.
+        assert_eq!(challenge_count_for_security(10, 0.2, ONE_KIB, 1), 29);
+
+        // A sector of a single node is always caught.
+        assert_eq!(
+            challenge_count_for_security(10, 0.2, NODE_SIZE as u64, 1),
+            1
+        );
+    }
+
+    #[test]
+    fn test_challenge_count_reaches_target() {
+        for &bits in &[1, 10, 40, 80] {
+            for &spacegap in &[0.01, 0.1, 0.2, 0.5] {
+                let count = challenge_count_for_security(bits, spacegap, ONE_GIB, 1);
+                let missing = missing_fraction(spacegap, ONE_GIB);
+                let escape = |n: usize| (1.0 - missing).powi(n as i32);
+
+                assert!(escape(count) <= 2f64.powi(-(bits as i32)));
+                assert!(escape(count - 1) > 2f64.powi(-(bits as i32)) || count == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_layer_challenges_for_security() {
+        let layers = 4;
+        let count = challenge_count_for_security(10, 0.2, ONE_GIB, 1);
+        let challenges = layer_challenges_for_security(10, 0.2, ONE_GIB, layers);
+
+        assert_eq!(challenges.layers(), layers);
+        for layer in 1..=layers {
+            assert!(challenges.challenges_count(layer) >= count);
+        }
+    }
+}
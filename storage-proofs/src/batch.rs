@@ -0,0 +1,177 @@
+use bellperson::groth16::{Proof, VerifyingKey};
+use bellperson::SynthesisError;
+use ff::{Field, PrimeField};
+use paired::{CurveAffine, CurveProjective, Engine};
+use rand::Rng;
+
+use crate::error::Result;
+
+/// Verifies many Groth16 proofs made against the same verifying key at once.
+///
+/// The verification equation of every proof is raised to a fresh random scalar and the
+/// results are multiplied together, so the whole batch costs one Miller loop per proof plus
+/// three, a single final exponentiation, and one multi-exponentiation over `vk.ic` for the
+/// public inputs. An invalid proof makes the batch fail with overwhelming probability, but the
+/// result does not say which proof it was.
+///
+/// `rng` must not be predictable by whoever produced the proofs.
+pub fn verify_proofs_batch<E: Engine, R: Rng>(
+    vk: &VerifyingKey<E>,
+    rng: &mut R,
+    proofs: &[&Proof<E>],
+    public_inputs: &[Vec<E::Fr>],
+) -> Result<bool> {
+    if proofs.len() != public_inputs.len() {
+        return Err(format_err!(
+            "{} proofs but {} sets of public inputs",
+            proofs.len(),
+            public_inputs.len()
+        )
+        .into());
+    }
+
+    if proofs.is_empty() {
+        return Ok(true);
+    }
+
+    // ic_scalars[0] accumulates the sum of all randomizers, which is also the exponent
+    // applied to e(alpha, beta).
+    let mut ic_scalars = vec![E::Fr::zero(); vk.ic.len()];
+    let mut acc_c = E::G1::zero();
+    let mut scaled_a = Vec::with_capacity(proofs.len());
+
+    for (proof, inputs) in proofs.iter().zip(public_inputs) {
+        if inputs.len() + 1 != vk.ic.len() {
+            return Err(SynthesisError::MalformedVerifyingKey.into());
+        }
+
+        let r: E::Fr = rng.gen();
+
+        ic_scalars[0].add_assign(&r);
+        for (scalar, input) in ic_scalars[1..].iter_mut().zip(inputs) {
+            let mut term = *input;
+            term.mul_assign(&r);
+            scalar.add_assign(&term);
+        }
+
+        acc_c.add_assign(&proof.c.mul(r.into_repr()));
+        scaled_a.push(proof.a.mul(r.into_repr()).into_affine().prepare());
+    }
+
+    let mut acc_ic = E::G1::zero();
+    for (base, scalar) in vk.ic.iter().zip(&ic_scalars) {
+        acc_ic.add_assign(&base.mul(scalar.into_repr()));
+    }
+
+    let mut acc_alpha = vk.alpha_g1.mul(ic_scalars[0].into_repr());
+    acc_alpha.negate();
+    acc_ic.negate();
+    acc_c.negate();
+
+    let acc_alpha = acc_alpha.into_affine().prepare();
+    let acc_ic = acc_ic.into_affine().prepare();
+    let acc_c = acc_c.into_affine().prepare();
+
+    let beta = vk.beta_g2.prepare();
+    let gamma = vk.gamma_g2.prepare();
+    let delta = vk.delta_g2.prepare();
+    let b_prepared: Vec<_> = proofs.iter().map(|proof| proof.b.prepare()).collect();
+
+    let mut terms: Vec<_> = scaled_a.iter().zip(b_prepared.iter()).collect();
+    terms.push((&acc_alpha, &beta));
+    terms.push((&acc_ic, &gamma));
+    terms.push((&acc_c, &delta));
+
+    Ok(E::final_exponentiation(&E::miller_loop(terms.iter()))
+        .map(|result| result == E::Fqk::one())
+        .unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::groth16;
+    use bellperson::{Circuit, ConstraintSystem};
+    use paired::bls12_381::{Bls12, Fr};
+    use rand::{SeedableRng, XorShiftRng};
+
+    /// Proves knowledge of `x` and `y` with `x * y = z` for a public `z`.
+    #[derive(Clone)]
+    struct MulCircuit {
+        x: Option<Fr>,
+        y: Option<Fr>,
+    }
+
+    impl Circuit<Bls12> for MulCircuit {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> ::std::result::Result<(), SynthesisError> {
+            let x = cs.alloc(|| "x", || self.x.ok_or(SynthesisError::AssignmentMissing))?;
+            let y = cs.alloc(|| "y", || self.y.ok_or(SynthesisError::AssignmentMissing))?;
+            let z = cs.alloc_input(
+                || "z",
+                || {
+                    let mut z = self.x.ok_or(SynthesisError::AssignmentMissing)?;
+                    z.mul_assign(&self.y.ok_or(SynthesisError::AssignmentMissing)?);
+                    Ok(z)
+                },
+            )?;
+
+            cs.enforce(|| "x * y = z", |lc| lc + x, |lc| lc + y, |lc| lc + z);
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn batch_verification_matches_single_verification() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let params = groth16::generate_random_parameters::<Bls12, _, _>(
+            MulCircuit { x: None, y: None },
+            rng,
+        )
+        .unwrap();
+        let pvk = groth16::prepare_verifying_key(&params.vk);
+
+        let mut proofs = Vec::new();
+        let mut inputs = Vec::new();
+        for _ in 0..5 {
+            let x: Fr = rng.gen();
+            let y: Fr = rng.gen();
+            let mut z = x;
+            z.mul_assign(&y);
+
+            let circuit = MulCircuit {
+                x: Some(x),
+                y: Some(y),
+            };
+            let proof = groth16::create_random_proof(circuit, &params, rng).unwrap();
+            assert!(groth16::verify_proof(&pvk, &proof, &[z]).unwrap());
+
+            proofs.push(proof);
+            inputs.push(vec![z]);
+        }
+
+        let proof_refs: Vec<_> = proofs.iter().collect();
+
+        assert!(verify_proofs_batch(&params.vk, rng, &proof_refs, &inputs).unwrap());
+        assert!(verify_proofs_batch(&params.vk, rng, &proof_refs[..1], &inputs[..1]).unwrap());
+        assert!(verify_proofs_batch(&params.vk, rng, &[], &[]).unwrap());
+
+        // A single wrong public input spoils the whole batch.
+        let mut bad_inputs = inputs.clone();
+        bad_inputs[3][0].add_assign(&Fr::one());
+        assert!(!verify_proofs_batch(&params.vk, rng, &proof_refs, &bad_inputs).unwrap());
+
+        // So does a proof swapped in for a different statement.
+        let mut swapped = proof_refs.clone();
+        swapped.swap(0, 1);
+        assert!(!verify_proofs_batch(&params.vk, rng, &swapped, &inputs).unwrap());
+
+        assert!(verify_proofs_batch(&params.vk, rng, &proof_refs, &inputs[..4]).is_err());
+        assert!(verify_proofs_batch(&params.vk, rng, &proof_refs[..1], &[vec![]]).is_err());
+    }
+}
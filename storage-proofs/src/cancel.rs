@@ -0,0 +1,118 @@
+//! Cooperative cancellation of replication and proving.
+//!
+//! Operations run under `cancellable` check its `CancellationToken` at node and challenge
+//! granularity, and return `Error::Cancelled` soon after it has been cancelled from another
+//! thread. This way an orchestrator can abort a doomed seal without killing the process. Like the
+//! progress observer, the token is installed for the current thread and follows the work to the
+//! threads it is handed to.
+
+use std::cell::RefCell;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+
+/// A flag shared between the operation and whoever may cancel it.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every operation running under this token, or any of its clones.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with `Error::Cancelled` once the token has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        Ok(())
+    }
+
+    /// Runs `f` with this as the token of the current thread, see `current`.
+    pub fn install<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let previous = CURRENT.with(|current| current.replace(self.clone()));
+        // Restored on unwinding too, the thread may be reused by a pool.
+        let _restore = Restore(previous);
+
+        f()
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancellationToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        CancellationToken(flag)
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<CancellationToken> = RefCell::new(CancellationToken::default());
+}
+
+/// Runs `f`, which fails with `Error::Cancelled` once `token` is cancelled. A token that is
+/// already cancelled fails right away.
+pub fn cancellable<R, F: FnOnce() -> Result<R>>(token: &CancellationToken, f: F) -> Result<R> {
+    token.check()?;
+    token.install(f)
+}
+
+/// The token of the current thread, which is never cancelled outside of `cancellable`. Capture it
+/// before moving work to other threads, and check it or `install` it there.
+pub fn current() -> CancellationToken {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Fails with `Error::Cancelled` once the token of the current thread has been cancelled.
+pub fn check() -> Result<()> {
+    current().check()
+}
+
+struct Restore(CancellationToken);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = mem::replace(&mut self.0, CancellationToken::default());
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellable() {
+        let token = CancellationToken::new();
+
+        let result = cancellable(&token, || {
+            check()?;
+
+            let current = current();
+            std::thread::spawn(move || current.cancel()).join().unwrap();
+
+            check()?;
+            Ok(())
+        });
+
+        match result {
+            Err(Error::Cancelled) => {}
+            other => panic!("expected Cancelled, got {:?}", other),
+        }
+        assert!(token.is_cancelled());
+
+        // Outside of `cancellable` nothing is cancelled, and a cancelled token fails right away.
+        assert!(check().is_ok());
+        assert!(cancellable(&token, || -> Result<()> { panic!("must not run") }).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Domain separated hashing for challenge derivation.
+//!
+//! Every proof derives its challenges with its own `ChallengeHash`, which prefixes the hashed
+//! data with a tag naming the proof, what is derived and a version. Challenges of different
+//! proofs, or of different versions of the same proof, are therefore independent even when they
+//! are derived from identical inputs.
+
+use blake2s_simd::Params as Blake2s;
+use sha2::{Digest, Sha256};
+
+/// The hash function challenges are derived with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChallengeHashFunction {
+    Blake2s,
+    Sha256,
+}
+
+/// What challenges are derived for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChallengeDomain {
+    /// Leaf challenges of a stacked DRG seal, derived from a seed drawn after replication.
+    StackedDrgInteractive,
+    /// Leaf challenges of a stacked DRG seal, derived from its own `comm_r`.
+    StackedDrgNonInteractive,
+    /// Sector and leaf challenges of a rational PoSt.
+    RationalPoSt,
+    /// Sectors drawn for an election PoSt.
+    ElectionPoStSector,
+    /// Leafs challenged within a sector drawn for an election PoSt.
+    ElectionPoStLeaf,
+}
+
+impl ChallengeDomain {
+    fn name(self) -> &'static [u8] {
+        match self {
+            ChallengeDomain::StackedDrgInteractive => b"stacked-drg-interactive",
+            ChallengeDomain::StackedDrgNonInteractive => b"stacked-drg-non-interactive",
+            ChallengeDomain::RationalPoSt => b"rational-post",
+            ChallengeDomain::ElectionPoStSector => b"election-post-sector",
+            ChallengeDomain::ElectionPoStLeaf => b"election-post-leaf",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChallengeHash {
+    pub function: ChallengeHashFunction,
+    pub domain: ChallengeDomain,
+    pub version: u32,
+}
+
+pub const STACKED_DRG_INTERACTIVE_CHALLENGE_HASH: ChallengeHash = ChallengeHash::new(
+    ChallengeHashFunction::Blake2s,
+    ChallengeDomain::StackedDrgInteractive,
+    1,
+);
+
+pub const STACKED_DRG_NON_INTERACTIVE_CHALLENGE_HASH: ChallengeHash = ChallengeHash::new(
+    ChallengeHashFunction::Blake2s,
+    ChallengeDomain::StackedDrgNonInteractive,
+    1,
+);
+
+pub const RATIONAL_POST_CHALLENGE_HASH: ChallengeHash = ChallengeHash::new(
+    ChallengeHashFunction::Blake2s,
+    ChallengeDomain::RationalPoSt,
+    1,
+);
+
+pub const ELECTION_POST_SECTOR_CHALLENGE_HASH: ChallengeHash = ChallengeHash::new(
+    ChallengeHashFunction::Sha256,
+    ChallengeDomain::ElectionPoStSector,
+    1,
+);
+
+pub const ELECTION_POST_LEAF_CHALLENGE_HASH: ChallengeHash = ChallengeHash::new(
+    ChallengeHashFunction::Sha256,
+    ChallengeDomain::ElectionPoStLeaf,
+    1,
+);
+
+impl ChallengeHash {
+    pub const fn new(
+        function: ChallengeHashFunction,
+        domain: ChallengeDomain,
+        version: u32,
+    ) -> Self {
+        ChallengeHash {
+            function,
+            domain,
+            version,
+        }
+    }
+
+    /// Returns the tag prefixed to all hashed data: the domain name, zero padded to 28 bytes,
+    /// followed by the version as little-endian u32.
+    pub fn tag(self) -> [u8; 32] {
+        let name = self.domain.name();
+        assert!(name.len() <= 28, "domain name too long");
+
+        let mut tag = [0u8; 32];
+        tag[..name.len()].copy_from_slice(name);
+        tag[28..].copy_from_slice(&self.version.to_le_bytes());
+
+        tag
+    }
+
+    /// Hashes the concatenation of `parts`, after the tag.
+    pub fn hash(self, parts: &[&[u8]]) -> [u8; 32] {
+        let mut out = [0u8; 32];
+
+        match self.function {
+            ChallengeHashFunction::Blake2s => {
+                let mut hasher = Blake2s::new().hash_length(32).to_state();
+                hasher.update(&self.tag());
+                for part in parts {
+                    hasher.update(part);
+                }
+                out.copy_from_slice(hasher.finalize().as_bytes());
+            }
+            ChallengeHashFunction::Sha256 => {
+                let mut hasher = Sha256::new();
+                hasher.input(&self.tag()[..]);
+                for part in parts {
+                    hasher.input(part);
+                }
+                out.copy_from_slice(hasher.result().as_slice());
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_hashes_are_domain_separated() {
+        let data: &[&[u8]] = &[b"some", b"data"];
+
+        let hashes = [
+            STACKED_DRG_INTERACTIVE_CHALLENGE_HASH,
+            STACKED_DRG_NON_INTERACTIVE_CHALLENGE_HASH,
+            RATIONAL_POST_CHALLENGE_HASH,
+            ELECTION_POST_SECTOR_CHALLENGE_HASH,
+            ELECTION_POST_LEAF_CHALLENGE_HASH,
+            ChallengeHash {
+                version: 2,
+                ..STACKED_DRG_INTERACTIVE_CHALLENGE_HASH
+            },
+            ChallengeHash {
+                function: ChallengeHashFunction::Sha256,
+                ..STACKED_DRG_INTERACTIVE_CHALLENGE_HASH
+            },
+        ];
+
+        for (i, a) in hashes.iter().enumerate() {
+            assert_eq!(a.hash(data), a.hash(&[b"somedata"]));
+
+            for b in &hashes[i + 1..] {
+                assert_ne!(a.hash(data), b.hash(data), "{:?} and {:?} collide", a, b);
+            }
+        }
+    }
+}
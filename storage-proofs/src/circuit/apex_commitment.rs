@@ -246,13 +246,13 @@ mod tests {
     use ff::ScalarEngine;
     use fil_sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
     use fil_sapling_crypto::circuit::num::AllocatedNum;
-    use fil_sapling_crypto::jubjub::JubjubBls12;
     use paired::bls12_381::Bls12;
     use rand::{Rng, SeedableRng, XorShiftRng};
 
     use crate::circuit::constraint;
     use crate::circuit::test::TestConstraintSystem;
     use crate::crypto;
+    use crate::crypto::pedersen::jubjub_params;
     use crate::fr32::fr_into_bytes;
     use crate::settings;
 
@@ -286,7 +286,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
 
         for n in 1..max_len {
             let size = 1 << n;
@@ -310,7 +310,7 @@ mod tests {
                 })
                 .unwrap();
 
-            let (bc, root) = T::commit(&mut outer_cs.namespace(|| "apex_commit"), &nums, &params)
+            let (bc, root) = T::commit(&mut outer_cs.namespace(|| "apex_commit"), &nums, params)
                 .expect("apex commitment failed");
 
             constraint::equal(
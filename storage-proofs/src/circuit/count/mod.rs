@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::ops::AddAssign;
+
+use bellperson::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use paired::Engine;
+
+/// The constraints and variables allocated by a gadget.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GadgetCounts {
+    pub constraints: usize,
+    pub inputs: usize,
+    pub aux: usize,
+}
+
+impl AddAssign for GadgetCounts {
+    fn add_assign(&mut self, other: GadgetCounts) {
+        self.constraints += other.constraints;
+        self.inputs += other.inputs;
+        self.aux += other.aux;
+    }
+}
+
+/// A constraint system which only counts, attributing every constraint and variable to the
+/// namespace it was created in. Witnesses are never computed, so blank circuits can be counted.
+///
+/// Namespaces are identified by their path, with every run of digits replaced by `*`, so that
+/// e.g. the gadgets of `challenge_0/encoding_proof_1` and `challenge_7/encoding_proof_3` are
+/// counted together as `challenge_*/encoding_proof_*`.
+pub struct CountingCS<E: Engine> {
+    path: String,
+    path_lens: Vec<usize>,
+    total: GadgetCounts,
+    gadgets: BTreeMap<String, GadgetCounts>,
+    _e: PhantomData<E>,
+}
+
+impl<E: Engine> CountingCS<E> {
+    pub fn new() -> Self {
+        CountingCS::default()
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.total.constraints
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.total.inputs
+    }
+
+    /// The counts of the whole circuit. The inputs include the constant `ONE`.
+    pub fn total(&self) -> GadgetCounts {
+        self.total
+    }
+
+    /// The counts of every namespace, excluding those of the namespaces nested in it.
+    pub fn gadgets(&self) -> &BTreeMap<String, GadgetCounts> {
+        &self.gadgets
+    }
+
+    /// The counts of every namespace at most `depth` deep, including those of the namespaces
+    /// nested in it.
+    pub fn rollup(&self, depth: usize) -> BTreeMap<String, GadgetCounts> {
+        let mut rolled = BTreeMap::new();
+
+        for (path, counts) in &self.gadgets {
+            let prefix = path.split('/').take(depth).collect::<Vec<_>>().join("/");
+            *rolled.entry(prefix).or_insert_with(GadgetCounts::default) += *counts;
+        }
+
+        rolled
+    }
+
+    fn current(&mut self) -> &mut GadgetCounts {
+        if !self.gadgets.contains_key(&self.path) {
+            self.gadgets
+                .insert(self.path.clone(), GadgetCounts::default());
+        }
+
+        self.gadgets.get_mut(&self.path).expect("inserted above")
+    }
+}
+
+/// Replaces every run of digits in `name` with `*`.
+fn collapse_indices(name: &str) -> String {
+    let mut collapsed = String::with_capacity(name.len());
+    let mut in_digits = false;
+
+    for c in name.chars() {
+        if c.is_ascii_digit() {
+            if !in_digits {
+                collapsed.push('*');
+            }
+            in_digits = true;
+        } else {
+            collapsed.push(c);
+            in_digits = false;
+        }
+    }
+
+    collapsed
+}
+
+impl<E: Engine> Default for CountingCS<E> {
+    fn default() -> Self {
+        CountingCS {
+            path: String::new(),
+            path_lens: Vec::new(),
+            total: GadgetCounts {
+                inputs: 1,
+                ..GadgetCounts::default()
+            },
+            gadgets: BTreeMap::new(),
+            _e: PhantomData,
+        }
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for CountingCS<E> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.total.aux;
+        self.total.aux += 1;
+        self.current().aux += 1;
+
+        Ok(Variable::new_unchecked(Index::Aux(index)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.total.inputs;
+        self.total.inputs += 1;
+        self.current().inputs += 1;
+
+        Ok(Variable::new_unchecked(Index::Input(index)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, _a: LA, _b: LB, _c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        self.total.constraints += 1;
+        self.current().constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.path_lens.push(self.path.len());
+        if !self.path.is_empty() {
+            self.path.push('/');
+        }
+        self.path.push_str(&collapse_indices(&name_fn().into()));
+    }
+
+    fn pop_namespace(&mut self) {
+        let len = self
+            .path_lens
+            .pop()
+            .expect("popped a namespace which was never pushed");
+        self.path.truncate(len);
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use fil_sapling_crypto::circuit::boolean::Boolean;
+    use paired::bls12_381::Bls12;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::pedersen::pedersen_md_no_padding;
+    use crate::circuit::test::TestConstraintSystem;
+    use crate::crypto::pedersen::jubjub_params;
+    use crate::settings;
+    use crate::util::bytes_into_boolean_vec;
+
+    #[test]
+    fn test_collapse_indices() {
+        assert_eq!(collapse_indices("challenge_12"), "challenge_*");
+        assert_eq!(collapse_indices("comm_r_0_input"), "comm_r_*_input");
+        assert_eq!(
+            collapse_indices("replica_column_proof"),
+            "replica_column_proof"
+        );
+    }
+
+    #[test]
+    fn test_counting_cs_namespaces() {
+        let mut cs = CountingCS::<Bls12>::new();
+
+        for i in 0..3 {
+            let mut cs = cs.namespace(|| format!("challenge_{}", i));
+            cs.alloc(|| "leaf", || unreachable!()).unwrap();
+            {
+                let mut cs = cs.namespace(|| "inclusion");
+                cs.alloc(|| "path", || unreachable!()).unwrap();
+                cs.enforce(|| "path", |lc| lc, |lc| lc, |lc| lc);
+            }
+            cs.alloc_input(|| "input", || unreachable!()).unwrap();
+        }
+
+        assert_eq!(
+            cs.total(),
+            GadgetCounts {
+                constraints: 3,
+                inputs: 4,
+                aux: 6,
+            }
+        );
+        assert_eq!(
+            cs.gadgets()["challenge_*"],
+            GadgetCounts {
+                constraints: 0,
+                inputs: 3,
+                aux: 3,
+            }
+        );
+        assert_eq!(
+            cs.gadgets()["challenge_*/inclusion"],
+            GadgetCounts {
+                constraints: 3,
+                inputs: 0,
+                aux: 3,
+            }
+        );
+
+        let rolled = cs.rollup(1);
+        assert_eq!(rolled.len(), 1);
+        assert_eq!(rolled["challenge_*"].constraints, 3);
+        assert_eq!(rolled["challenge_*"].aux, 6);
+    }
+
+    #[test]
+    fn test_counting_cs_matches_test_cs() {
+        let window_size = settings::SETTINGS
+            .lock()
+            .unwrap()
+            .pedersen_hash_exp_window_size;
+        let params = jubjub_params(window_size);
+        let rng = &mut XorShiftRng::from_seed([0x3dbe_6259, 0x8d31_3d76, 0x3237_db17, 0xe5bc_0654]);
+        let data: Vec<u8> = (0..64).map(|_| rng.gen()).collect();
+
+        let mut test_cs = TestConstraintSystem::<Bls12>::new();
+        let bits: Vec<Boolean> = bytes_into_boolean_vec(
+            test_cs.namespace(|| "data"),
+            Some(data.as_slice()),
+            data.len(),
+        )
+        .unwrap();
+        pedersen_md_no_padding(test_cs.namespace(|| "hash"), params, &bits).unwrap();
+
+        // The counting system never sees the data.
+        let mut cs = CountingCS::<Bls12>::new();
+        let bits: Vec<Boolean> =
+            bytes_into_boolean_vec(cs.namespace(|| "data"), None, data.len()).unwrap();
+        pedersen_md_no_padding(cs.namespace(|| "hash"), params, &bits).unwrap();
+
+        assert_eq!(cs.num_constraints(), test_cs.num_constraints());
+        assert_eq!(cs.num_inputs(), test_cs.num_inputs());
+        assert_eq!(
+            cs.rollup(1)["hash"].constraints + cs.rollup(1)["data"].constraints,
+            cs.num_constraints()
+        );
+    }
+}
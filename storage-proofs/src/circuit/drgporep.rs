@@ -514,8 +514,9 @@ mod tests {
     use super::*;
     use crate::circuit::test::*;
     use crate::compound_proof;
+    use crate::crypto::pedersen::jubjub_params;
     use crate::drgporep;
-    use crate::drgraph::{graph_height, new_seed, BucketGraph, BASE_DEGREE};
+    use crate::drgraph::{graph_height, new_seed_from, BucketGraph, GraphConfig, BASE_DEGREE};
     use crate::fr32::{bytes_into_fr, fr_into_bytes};
     use crate::hasher::{Blake2sHasher, Hasher, PedersenHasher};
     use crate::porep::PoRep;
@@ -524,7 +525,6 @@ mod tests {
     use crate::util::data_at_node;
 
     use ff::Field;
-    use fil_sapling_crypto::jubjub::JubjubBls12;
     use rand::{Rand, Rng, SeedableRng, XorShiftRng};
 
     #[test]
@@ -533,7 +533,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let nodes = 12;
@@ -560,7 +560,8 @@ mod tests {
                 nodes,
                 degree,
                 expansion_degree: 0,
-                seed: new_seed(),
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
             },
             private: false,
             challenges_count: 1,
@@ -571,7 +572,7 @@ mod tests {
         let (tau, aux) = drgporep::DrgPoRep::<PedersenHasher, _>::replicate(
             &pp,
             &replica_id.into(),
-            data.as_mut_slice(),
+            data.as_mut_slice().into(),
             None,
         )
         .expect("failed to replicate");
@@ -676,7 +677,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         // 1 GB
@@ -722,7 +723,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let nodes = 5;
@@ -735,7 +736,7 @@ mod tests {
             .collect();
 
         // Only generate seed once. It would be bad if we used different seeds in the same test.
-        let seed = new_seed();
+        let seed = new_seed_from(rng);
 
         let setup_params = compound_proof::SetupParams {
             vanilla_params: &drgporep::SetupParams {
@@ -744,6 +745,7 @@ mod tests {
                     degree,
                     expansion_degree: 0,
                     seed,
+                    graph_config: GraphConfig::default(),
                 },
                 private: false,
                 challenges_count: 2,
@@ -758,7 +760,7 @@ mod tests {
         let (tau, aux) = drgporep::DrgPoRep::<H, _>::replicate(
             &public_params.vanilla_params,
             &replica_id.into(),
-            data.as_mut_slice(),
+            data.as_mut_slice().into(),
             None,
         )
         .expect("failed to replicate");
@@ -781,6 +783,7 @@ mod tests {
                     degree,
                     expansion_degree: 0,
                     seed,
+                    graph_config: GraphConfig::default(),
                 },
                 private: false,
                 challenges_count: 2,
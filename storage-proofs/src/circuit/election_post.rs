@@ -0,0 +1,567 @@
+use std::marker::PhantomData;
+
+use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+use fil_sapling_crypto::circuit::boolean::Boolean;
+use fil_sapling_crypto::circuit::num;
+use fil_sapling_crypto::jubjub::JubjubEngine;
+use paired::bls12_381::{Bls12, Fr};
+
+use crate::circuit::constraint;
+use crate::circuit::pedersen::pedersen_md_no_padding;
+use crate::circuit::por::{PoRCircuit, PoRCompound};
+use crate::circuit::stacked::hash::hash2;
+use crate::circuit::variables::Root;
+use crate::compound_proof::{CircuitComponent, CompoundProof};
+use crate::drgraph;
+use crate::election_post::{self, ElectionPoSt};
+use crate::gpu_lock::Priority;
+use crate::hasher::Hasher;
+use crate::merklepor;
+use crate::parameter_cache::{CacheableParameters, ParameterSetMetadata};
+use crate::proof::ProofScheme;
+use crate::util::NODE_SIZE;
+
+/// This is the `ElectionPoSt` circuit.
+pub struct ElectionPoStCircuit<'a, E: JubjubEngine, H: Hasher> {
+    /// Paramters for the engine.
+    pub params: &'a E::Params,
+    pub comm_r: Option<E::Fr>,
+    pub comm_c: Option<E::Fr>,
+    pub comm_r_last: Option<E::Fr>,
+    pub leafs: Vec<Option<E::Fr>>,
+    #[allow(clippy::type_complexity)]
+    pub paths: Vec<Vec<Option<(E::Fr, bool)>>>,
+    pub partial_ticket: Option<E::Fr>,
+    pub randomness: Option<E::Fr>,
+    pub prover_id: Option<E::Fr>,
+    pub sector_id: Option<E::Fr>,
+    _h: PhantomData<H>,
+}
+
+pub struct ElectionPoStCompound<H>
+where
+    H: Hasher,
+{
+    _h: PhantomData<H>,
+}
+
+impl<E: JubjubEngine, C: Circuit<E>, P: ParameterSetMetadata, H: Hasher>
+    CacheableParameters<E, C, P> for ElectionPoStCompound<H>
+{
+    fn cache_prefix() -> String {
+        String::from("proof-of-spacetime-election")
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ComponentPrivateInputs {}
+
+impl<'a, E: JubjubEngine, H: Hasher> CircuitComponent for ElectionPoStCircuit<'a, E, H> {
+    type ComponentPrivateInputs = ComponentPrivateInputs;
+}
+
+impl<'a, H> CompoundProof<'a, Bls12, ElectionPoSt<'a, H>, ElectionPoStCircuit<'a, Bls12, H>>
+    for ElectionPoStCompound<H>
+where
+    H: 'a + Hasher,
+{
+    fn gpu_priority() -> Priority {
+        Priority::High
+    }
+
+    fn generate_public_inputs(
+        pub_in: &<ElectionPoSt<'a, H> as ProofScheme<'a>>::PublicInputs,
+        pub_params: &<ElectionPoSt<'a, H> as ProofScheme<'a>>::PublicParams,
+        _partition_k: Option<usize>,
+    ) -> Vec<Fr> {
+        let mut inputs = Vec::new();
+
+        let por_pub_params = merklepor::PublicParams {
+            leaves: (pub_params.sector_size as usize / NODE_SIZE),
+            private: true,
+        };
+
+        inputs.push(pub_in.comm_r.into());
+
+        for n in 0..pub_params.challenge_count {
+            let challenge = election_post::generate_leaf_challenge(
+                pub_params,
+                &pub_in.randomness,
+                pub_in.sector_challenge_index,
+                n as u64,
+            );
+
+            for i in 0..pub_params.challenged_nodes {
+                let por_pub_inputs = merklepor::PublicInputs {
+                    commitment: None,
+                    challenge: challenge as usize + i,
+                };
+                let por_inputs = PoRCompound::<H>::generate_public_inputs(
+                    &por_pub_inputs,
+                    &por_pub_params,
+                    None,
+                );
+
+                inputs.extend(por_inputs);
+            }
+        }
+
+        inputs.extend(
+            election_post::partial_ticket_prefix(
+                &pub_in.randomness,
+                &pub_in.prover_id,
+                pub_in.sector_id,
+            )
+            .iter(),
+        );
+        inputs.push(pub_in.partial_ticket);
+
+        inputs
+    }
+
+    fn circuit(
+        pub_in: &<ElectionPoSt<'a, H> as ProofScheme<'a>>::PublicInputs,
+        _priv_in: <ElectionPoStCircuit<'a, Bls12, H> as CircuitComponent>::ComponentPrivateInputs,
+        vanilla_proof: &<ElectionPoSt<'a, H> as ProofScheme<'a>>::Proof,
+        _pub_params: &<ElectionPoSt<'a, H> as ProofScheme<'a>>::PublicParams,
+        engine_params: &'a <Bls12 as JubjubEngine>::Params,
+    ) -> ElectionPoStCircuit<'a, Bls12, H> {
+        let leafs: Vec<_> = vanilla_proof
+            .leafs()
+            .iter()
+            .map(|c| Some((**c).into()))
+            .collect();
+
+        let paths: Vec<Vec<_>> = vanilla_proof
+            .paths()
+            .iter()
+            .map(|v| v.iter().map(|p| Some(((*p).0.into(), p.1))).collect())
+            .collect();
+
+        let [randomness, prover_id, sector_id] = election_post::partial_ticket_prefix(
+            &pub_in.randomness,
+            &pub_in.prover_id,
+            pub_in.sector_id,
+        );
+
+        ElectionPoStCircuit {
+            params: engine_params,
+            comm_r: Some(pub_in.comm_r.into()),
+            comm_c: Some(vanilla_proof.comm_c.into()),
+            comm_r_last: Some((*vanilla_proof.comm_r_last()).into()),
+            leafs,
+            paths,
+            partial_ticket: Some(pub_in.partial_ticket),
+            randomness: Some(randomness),
+            prover_id: Some(prover_id),
+            sector_id: Some(sector_id),
+            _h: PhantomData,
+        }
+    }
+
+    fn blank_circuit(
+        pub_params: &<ElectionPoSt<'a, H> as ProofScheme<'a>>::PublicParams,
+        params: &'a <Bls12 as JubjubEngine>::Params,
+    ) -> ElectionPoStCircuit<'a, Bls12, H> {
+        let leaves_count = pub_params.leaves_count();
+        let height = drgraph::graph_height(pub_params.sector_size as usize / NODE_SIZE);
+
+        ElectionPoStCircuit {
+            params,
+            comm_r: None,
+            comm_c: None,
+            comm_r_last: None,
+            leafs: vec![None; leaves_count],
+            paths: vec![vec![None; height]; leaves_count],
+            partial_ticket: None,
+            randomness: None,
+            prover_id: None,
+            sector_id: None,
+            _h: PhantomData,
+        }
+    }
+}
+
+impl<'a, E: JubjubEngine, H: Hasher> Circuit<E> for ElectionPoStCircuit<'a, E, H> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let params = self.params;
+        let comm_r = self.comm_r;
+        let comm_c = self.comm_c;
+        let comm_r_last = self.comm_r_last;
+        let leafs = self.leafs;
+        let paths = self.paths;
+        let partial_ticket = self.partial_ticket;
+        let randomness = self.randomness;
+        let prover_id = self.prover_id;
+        let sector_id = self.sector_id;
+
+        assert_eq!(paths.len(), leafs.len());
+
+        let comm_r_last_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_r_last"), || {
+            comm_r_last.ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        let comm_c_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_c"), || {
+            comm_c.ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        let comm_r_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_r"), || {
+            comm_r.ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        comm_r_num.inputize(cs.namespace(|| "comm_r_input"))?;
+
+        // Verify H(Comm_C || comm_r_last) == comm_r
+        {
+            let comm_c_bits = comm_c_num.into_bits_le(cs.namespace(|| "comm_c_bits"))?;
+            let comm_r_last_bits =
+                comm_r_last_num.into_bits_le(cs.namespace(|| "comm_r_last_bits"))?;
+
+            let hash_num = hash2(
+                cs.namespace(|| "H_comm_c_comm_r_last"),
+                params,
+                &comm_c_bits,
+                &comm_r_last_bits,
+            )?;
+
+            // Check actual equality
+            constraint::equal(
+                cs,
+                || "enforce_comm_c_comm_r_last_hash_comm_r",
+                &comm_r_num,
+                &hash_num,
+            );
+        }
+
+        // Verify the inclusion of all challenged leafs
+        let mut leaf_nums = Vec::with_capacity(leafs.len());
+        for (i, (leaf, path)) in leafs.into_iter().zip(paths.into_iter()).enumerate() {
+            let leaf_num =
+                num::AllocatedNum::alloc(cs.namespace(|| format!("leaf_{}", i)), || {
+                    leaf.ok_or_else(|| SynthesisError::AssignmentMissing)
+                })?;
+
+            PoRCircuit::<E, H>::synthesize(
+                cs.namespace(|| format!("challenge_inclusion_{}", i)),
+                params,
+                Root::Var(leaf_num.clone()),
+                path,
+                Root::from_allocated::<CS>(comm_r_last_num.clone()),
+                true,
+            )?;
+
+            leaf_nums.push(leaf_num);
+        }
+
+        // Verify the partial ticket
+        {
+            let mut preimage = Vec::new();
+            for (name, value) in &[
+                ("randomness", randomness),
+                ("prover_id", prover_id),
+                ("sector_id", sector_id),
+            ] {
+                let value_num = num::AllocatedNum::alloc(cs.namespace(|| *name), || {
+                    value.ok_or_else(|| SynthesisError::AssignmentMissing)
+                })?;
+                value_num.inputize(cs.namespace(|| format!("{}_input", name)))?;
+
+                preimage.extend(padded_bits(
+                    cs.namespace(|| format!("{}_bits", name)),
+                    &value_num,
+                )?);
+            }
+
+            for (i, leaf_num) in leaf_nums.iter().enumerate() {
+                preimage.extend(padded_bits(
+                    cs.namespace(|| format!("leaf_{}_bits", i)),
+                    leaf_num,
+                )?);
+            }
+
+            let partial_ticket_num =
+                pedersen_md_no_padding(cs.namespace(|| "partial_ticket_hash"), params, &preimage)?;
+
+            let expected_num = num::AllocatedNum::alloc(cs.namespace(|| "partial_ticket"), || {
+                partial_ticket.ok_or_else(|| SynthesisError::AssignmentMissing)
+            })?;
+            expected_num.inputize(cs.namespace(|| "partial_ticket_input"))?;
+
+            constraint::equal(
+                cs,
+                || "enforce_partial_ticket",
+                &expected_num,
+                &partial_ticket_num,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The little-endian bits of `num`, padded to full bytes.
+fn padded_bits<E, CS>(
+    mut cs: CS,
+    num: &num::AllocatedNum<E>,
+) -> Result<Vec<Boolean>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    let mut bits = num.into_bits_le(cs.namespace(|| "bits"))?;
+    while bits.len() % 8 > 0 {
+        bits.push(Boolean::Constant(false));
+    }
+
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use ff::Field;
+    use fil_sapling_crypto::jubjub::JubjubBls12;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::test::*;
+    use crate::compound_proof;
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
+    use crate::election_post::{generate_candidates, generate_sector_challenges};
+    use crate::fr32::fr_into_bytes;
+    use crate::hasher::pedersen::*;
+    use crate::proof::NoRequirements;
+    use crate::sector::OrderedSectorSet;
+    use crate::stacked::hash::hash2 as vanilla_hash2;
+
+    #[test]
+    fn test_election_post_circuit_with_bls12_381() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 32;
+        let sector_size = leaves * 32;
+
+        let pub_params = ElectionPoSt::<PedersenHasher>::setup(&election_post::SetupParams {
+            sector_size,
+            challenge_count: 2,
+            challenged_nodes: 1,
+        })
+        .expect("setup failed");
+
+        let randomness: [u8; 32] = rng.gen();
+        let prover_id: [u8; 32] = rng.gen();
+
+        let mut sectors = OrderedSectorSet::new();
+        let mut trees = BTreeMap::new();
+        for i in 0..2 {
+            sectors.insert(i.into());
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+
+            let graph = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
+            trees.insert(i.into(), graph.merkle_tree(data.as_slice()).unwrap());
+        }
+
+        let challenged_sectors = generate_sector_challenges(&randomness, 1, &sectors).unwrap();
+        let candidates = generate_candidates::<PedersenHasher>(
+            &pub_params,
+            &challenged_sectors,
+            &trees,
+            &prover_id,
+            &randomness,
+        )
+        .unwrap();
+        let candidate = &candidates[0];
+
+        let tree = &trees[&candidate.sector_id];
+        let comm_c: PedersenDomain = rng.gen();
+        let comm_r_last = tree.root();
+        let comm_r: PedersenDomain = vanilla_hash2(&comm_c, &comm_r_last).into();
+
+        let pub_inputs = election_post::PublicInputs {
+            randomness,
+            sector_id: candidate.sector_id,
+            prover_id,
+            comm_r,
+            partial_ticket: candidate.partial_ticket,
+            sector_challenge_index: candidate.sector_challenge_index,
+        };
+
+        let priv_inputs = election_post::PrivateInputs::<PedersenHasher> {
+            tree,
+            comm_c,
+            comm_r_last,
+        };
+
+        let proof = ElectionPoSt::<PedersenHasher>::prove(&pub_params, &pub_inputs, &priv_inputs)
+            .expect("proving failed");
+
+        let is_valid = ElectionPoSt::<PedersenHasher>::verify(&pub_params, &pub_inputs, &proof)
+            .expect("verification failed");
+        assert!(is_valid);
+
+        // actual circuit test
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+
+        let instance = ElectionPoStCompound::<PedersenHasher>::circuit(
+            &pub_inputs,
+            ComponentPrivateInputs::default(),
+            &proof,
+            &pub_params,
+            params,
+        );
+
+        instance
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+
+        // comm_r, two challenges, randomness, prover_id, sector_id and the partial ticket
+        assert_eq!(cs.num_inputs(), 8, "wrong number of inputs");
+        assert_eq!(cs.get_input(0, "ONE"), Fr::one());
+
+        let generated_inputs = ElectionPoStCompound::<PedersenHasher>::generate_public_inputs(
+            &pub_inputs,
+            &pub_params,
+            None,
+        );
+        let expected_inputs = cs.get_inputs();
+
+        for ((input, label), generated_input) in
+            expected_inputs.iter().skip(1).zip(generated_inputs.iter())
+        {
+            assert_eq!(input, generated_input, "{}", label);
+        }
+
+        assert_eq!(
+            generated_inputs.len(),
+            expected_inputs.len() - 1,
+            "inputs are not the same length"
+        );
+
+        // A wrong partial ticket must not satisfy the circuit.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let mut instance = ElectionPoStCompound::<PedersenHasher>::circuit(
+            &pub_inputs,
+            ComponentPrivateInputs::default(),
+            &proof,
+            &pub_params,
+            params,
+        );
+        instance.partial_ticket = Some(rng.gen());
+        instance
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+        assert!(
+            !cs.is_satisfied(),
+            "constraints satisfied with a bad ticket"
+        );
+    }
+
+    #[ignore] // Slow test – run only when compiled for release.
+    #[test]
+    fn election_post_test_compound() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 32;
+        let sector_size = leaves * 32;
+
+        let setup_params = compound_proof::SetupParams {
+            vanilla_params: &election_post::SetupParams {
+                sector_size,
+                challenge_count: 2,
+                challenged_nodes: 1,
+            },
+            engine_params: params,
+            partitions: None,
+        };
+
+        let pub_params =
+            ElectionPoStCompound::<PedersenHasher>::setup(&setup_params).expect("setup failed");
+
+        let randomness: [u8; 32] = rng.gen();
+        let prover_id: [u8; 32] = rng.gen();
+
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
+        let mut trees = BTreeMap::new();
+        trees.insert(0.into(), graph.merkle_tree(data.as_slice()).unwrap());
+
+        let candidates = generate_candidates::<PedersenHasher>(
+            &pub_params.vanilla_params,
+            &[0.into()],
+            &trees,
+            &prover_id,
+            &randomness,
+        )
+        .unwrap();
+        let candidate = &candidates[0];
+
+        let tree = &trees[&candidate.sector_id];
+        let comm_c: PedersenDomain = rng.gen();
+        let comm_r_last = tree.root();
+        let comm_r: PedersenDomain = vanilla_hash2(&comm_c, &comm_r_last).into();
+
+        let pub_inputs = election_post::PublicInputs {
+            randomness,
+            sector_id: candidate.sector_id,
+            prover_id,
+            comm_r,
+            partial_ticket: candidate.partial_ticket,
+            sector_challenge_index: candidate.sector_challenge_index,
+        };
+
+        let priv_inputs = election_post::PrivateInputs::<PedersenHasher> {
+            tree,
+            comm_c,
+            comm_r_last,
+        };
+
+        let gparams = ElectionPoStCompound::<PedersenHasher>::groth_params(
+            &pub_params.vanilla_params,
+            &params,
+        )
+        .expect("failed to create groth params");
+
+        let proof = ElectionPoStCompound::<PedersenHasher>::prove(
+            &pub_params,
+            &pub_inputs,
+            &priv_inputs,
+            &gparams,
+        )
+        .expect("proving failed");
+
+        let (circuit, inputs) = ElectionPoStCompound::<PedersenHasher>::circuit_for_test(
+            &pub_params,
+            &pub_inputs,
+            &priv_inputs,
+        );
+
+        {
+            let mut cs = TestConstraintSystem::new();
+
+            circuit.synthesize(&mut cs).expect("failed to synthesize");
+            assert!(cs.is_satisfied());
+            assert!(cs.verify(&inputs));
+        }
+
+        let verified = ElectionPoStCompound::<PedersenHasher>::verify(
+            &pub_params,
+            &pub_inputs,
+            &proof,
+            &NoRequirements,
+        )
+        .expect("failed while verifying");
+
+        assert!(verified);
+    }
+}
@@ -4,6 +4,7 @@ pub mod por;
 
 pub mod apex_commitment;
 pub mod drgporep;
+pub mod election_post;
 pub mod kdf;
 pub mod multi_proof;
 pub mod pedersen;
@@ -18,5 +19,7 @@ pub mod xor;
 
 // FIXME: Can we make a config like for test?
 pub mod bench;
+pub mod count;
 pub mod metric;
+pub mod shape;
 pub mod test;
@@ -1,9 +1,13 @@
 use bellperson::groth16;
 
-use crate::error::Result;
-use paired::Engine;
+use crate::error::{Error, Result};
+use paired::{CurveAffine, EncodedPoint, Engine};
 use std::io::{self, Read, Write};
 
+/// The Groth16 proofs of all partitions of a proof, each checked against the same verifying key.
+///
+/// Encodes as the concatenation of the compressed proofs, in partition order, so every encoding
+/// of `n` partitions is exactly `n * MultiProof::proof_len()` bytes long.
 pub struct MultiProof<'a, E: Engine> {
     pub circuit_proofs: Vec<groth16::Proof<E>>,
     pub verifying_key: &'a groth16::VerifyingKey<E>,
@@ -20,15 +24,20 @@ impl<'a, E: Engine> MultiProof<'a, E> {
         }
     }
 
+    /// The length of the encoding of a single partition proof.
+    pub fn proof_len() -> usize {
+        2 * <<E::G1Affine as CurveAffine>::Compressed as EncodedPoint>::size()
+            + <<E::G2Affine as CurveAffine>::Compressed as EncodedPoint>::size()
+    }
+
+    /// Reads the proofs of `partitions` partitions, or of one if `None`, from `reader`. Reads no
+    /// further, so use `from_bytes` to reject trailing bytes.
     pub fn new_from_reader<R: Read>(
         partitions: Option<usize>,
         mut reader: R,
         verifying_key: &'a groth16::VerifyingKey<E>,
     ) -> Result<Self> {
-        let num_proofs = match partitions {
-            Some(n) => n,
-            None => 1,
-        };
+        let num_proofs = num_proofs(partitions)?;
         let proofs = (0..num_proofs)
             .map(|_| groth16::Proof::read(&mut reader))
             .collect::<io::Result<Vec<_>>>()?;
@@ -36,6 +45,21 @@ impl<'a, E: Engine> MultiProof<'a, E> {
         Ok(Self::new(proofs, verifying_key))
     }
 
+    /// Decodes the proofs of `partitions` partitions, or of one if `None`. Fails with
+    /// `Error::InvalidProofLength` unless `bytes` is exactly as long as their encoding.
+    pub fn from_bytes(
+        partitions: Option<usize>,
+        bytes: &[u8],
+        verifying_key: &'a groth16::VerifyingKey<E>,
+    ) -> Result<Self> {
+        let expected = num_proofs(partitions)? * Self::proof_len();
+        if bytes.len() != expected {
+            return Err(Error::InvalidProofLength(bytes.len(), expected));
+        }
+
+        Self::new_from_reader(partitions, bytes, verifying_key)
+    }
+
     pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
         for proof in &self.circuit_proofs {
             proof.write(&mut writer)?
@@ -43,9 +67,80 @@ impl<'a, E: Engine> MultiProof<'a, E> {
         Ok(())
     }
 
-    pub fn to_vec(&self) -> Vec<u8> {
-        let mut out = Vec::new();
+    /// The encoding read by `from_bytes`.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.circuit_proofs.len() * Self::proof_len());
         self.write(&mut out).expect("known allocation target");
         out
     }
+
+    /// The number of partition proofs.
+    pub fn len(&self) -> usize {
+        self.circuit_proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.circuit_proofs.is_empty()
+    }
+}
+
+fn num_proofs(partitions: Option<usize>) -> Result<usize> {
+    match partitions {
+        None => Ok(1),
+        Some(0) => Err(Error::InvalidPartitionCount(0)),
+        Some(n) => Ok(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paired::bls12_381::{Bls12, G1, G2};
+    use paired::CurveProjective;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    #[test]
+    fn test_multi_proof_bytes() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let verifying_key = groth16::VerifyingKey::<Bls12> {
+            alpha_g1: rng.gen::<G1>().into_affine(),
+            beta_g1: rng.gen::<G1>().into_affine(),
+            beta_g2: rng.gen::<G2>().into_affine(),
+            gamma_g2: rng.gen::<G2>().into_affine(),
+            delta_g1: rng.gen::<G1>().into_affine(),
+            delta_g2: rng.gen::<G2>().into_affine(),
+            ic: Vec::new(),
+        };
+        let proofs = (0..3)
+            .map(|_| groth16::Proof::<Bls12> {
+                a: rng.gen::<G1>().into_affine(),
+                b: rng.gen::<G2>().into_affine(),
+                c: rng.gen::<G1>().into_affine(),
+            })
+            .collect::<Vec<_>>();
+
+        let multi_proof = MultiProof::new(proofs.clone(), &verifying_key);
+        let bytes = multi_proof.as_bytes();
+        assert_eq!(MultiProof::<Bls12>::proof_len(), 192);
+        assert_eq!(bytes.len(), 3 * 192);
+
+        let decoded = MultiProof::from_bytes(Some(3), &bytes, &verifying_key).unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert!(decoded.circuit_proofs == proofs);
+
+        // Only exactly the encoding of the given partitions is accepted.
+        for (partitions, len) in &[
+            (Some(3), bytes.len() - 1),
+            (Some(2), bytes.len()),
+            (None, bytes.len()),
+            (Some(0), 0),
+        ] {
+            assert!(MultiProof::from_bytes(*partitions, &bytes[..*len], &verifying_key).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(MultiProof::from_bytes(Some(3), &trailing, &verifying_key).is_err());
+    }
 }
@@ -85,11 +85,11 @@ mod tests {
     use super::*;
     use crate::circuit::test::TestConstraintSystem;
     use crate::crypto;
+    use crate::crypto::pedersen::jubjub_params;
     use crate::settings;
     use crate::util::bytes_into_boolean_vec;
     use bellperson::ConstraintSystem;
     use fil_sapling_crypto::circuit::boolean::Boolean;
-    use fil_sapling_crypto::jubjub::JubjubBls12;
     use paired::bls12_381::Bls12;
     use rand::{Rng, SeedableRng, XorShiftRng};
 
@@ -106,7 +106,7 @@ mod tests {
         for (bytes, constraints) in &cases {
             let mut cs = TestConstraintSystem::<Bls12>::new();
             let data: Vec<u8> = (0..*bytes).map(|_| rng.gen()).collect();
-            let params = &JubjubBls12::new_with_window_size(window_size);
+            let params = jubjub_params(window_size);
 
             let data_bits: Vec<Boolean> = {
                 let mut cs = cs.namespace(|| "data");
@@ -152,7 +152,7 @@ mod tests {
         for (bytes, constraints) in &cases {
             let mut cs = TestConstraintSystem::<Bls12>::new();
             let data: Vec<u8> = (0..*bytes).map(|_| rng.gen()).collect();
-            let params = &JubjubBls12::new_with_window_size(window_size);
+            let params = jubjub_params(window_size);
 
             let data_bits: Vec<Boolean> = {
                 let mut cs = cs.namespace(|| "data");
@@ -141,6 +141,17 @@ impl<'a, E: JubjubEngine, H: Hasher> Circuit<E> for PoRCircuit<'a, E, H> {
     where
         E: JubjubEngine,
     {
+        self.synthesize_path(cs).map(|_| ())
+    }
+}
+
+impl<'a, E: JubjubEngine, H: Hasher> PoRCircuit<'a, E, H> {
+    /// Synthesizes the circuit, see `Circuit::synthesize`, and returns the `is_right` bits of the
+    /// auth path, from the leaf up. They are the little-endian bits of the challenged node.
+    fn synthesize_path<CS: ConstraintSystem<E>>(
+        self,
+        cs: &mut CS,
+    ) -> Result<Vec<boolean::Boolean>, SynthesisError> {
         let params = self.params;
         let value = self.value;
         let auth_path = self.auth_path;
@@ -207,12 +218,10 @@ impl<'a, E: JubjubEngine, H: Hasher> Circuit<E> for PoRCircuit<'a, E, H> {
                 }
             }
 
-            Ok(())
+            Ok(auth_path_bits)
         }
     }
-}
 
-impl<'a, E: JubjubEngine, H: Hasher> PoRCircuit<'a, E, H> {
     pub fn synthesize<CS>(
         mut cs: CS,
         params: &E::Params,
@@ -236,6 +245,32 @@ impl<'a, E: JubjubEngine, H: Hasher> PoRCircuit<'a, E, H> {
 
         por.synthesize(&mut cs)
     }
+
+    /// Like `synthesize`, but returns the `is_right` bits of the auth path, which are the
+    /// little-endian bits of the challenged node.
+    pub fn synthesize_with_path<CS>(
+        mut cs: CS,
+        params: &E::Params,
+        value: Root<E>,
+        auth_path: Vec<Option<(E::Fr, bool)>>,
+        root: Root<E>,
+        private: bool,
+    ) -> Result<Vec<boolean::Boolean>, SynthesisError>
+    where
+        E: JubjubEngine,
+        CS: ConstraintSystem<E>,
+    {
+        let por = PoRCircuit::<E, H> {
+            params,
+            value,
+            auth_path,
+            root,
+            private,
+            _h: Default::default(),
+        };
+
+        por.synthesize_path(&mut cs)
+    }
 }
 
 #[cfg(test)]
@@ -245,12 +280,12 @@ mod tests {
     use crate::proof::NoRequirements;
     use ff::Field;
     use fil_sapling_crypto::circuit::multipack;
-    use fil_sapling_crypto::jubjub::JubjubBls12;
     use rand::{Rng, SeedableRng, XorShiftRng};
 
     use crate::circuit::test::*;
     use crate::compound_proof;
-    use crate::drgraph::{new_seed, BucketGraph, Graph, BASE_DEGREE};
+    use crate::crypto::pedersen::jubjub_params;
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
     use crate::fr32::{bytes_into_fr, fr_into_bytes};
     use crate::hasher::{Blake2sHasher, Domain, Hasher, PedersenHasher};
     use crate::merklepor;
@@ -270,7 +305,7 @@ mod tests {
         let data: Vec<u8> = (0..leaves)
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
-        let graph = BucketGraph::<PedersenHasher>::new(leaves, BASE_DEGREE, 0, new_seed());
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, BASE_DEGREE, 0, new_seed_from(rng));
         let tree = graph.merkle_tree(data.as_slice()).unwrap();
 
         for i in 0..3 {
@@ -284,7 +319,7 @@ mod tests {
                     leaves,
                     private: false,
                 },
-                engine_params: &JubjubBls12::new_with_window_size(window_size),
+                engine_params: jubjub_params(window_size),
                 partitions: None,
             };
             let public_params =
@@ -351,7 +386,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let leaves = 6;
@@ -363,7 +398,7 @@ mod tests {
                 .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
                 .collect();
 
-            let graph = BucketGraph::<H>::new(leaves, BASE_DEGREE, 0, new_seed());
+            let graph = BucketGraph::<H>::new(leaves, BASE_DEGREE, 0, new_seed_from(rng));
             let tree = graph.merkle_tree(data.as_slice()).unwrap();
 
             // -- MerklePoR
@@ -469,7 +504,7 @@ mod tests {
         let data: Vec<u8> = (0..leaves)
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
-        let graph = BucketGraph::<H>::new(leaves, BASE_DEGREE, 0, new_seed());
+        let graph = BucketGraph::<H>::new(leaves, BASE_DEGREE, 0, new_seed_from(rng));
         let tree = graph.merkle_tree(data.as_slice()).unwrap();
 
         for i in 0..3 {
@@ -483,7 +518,7 @@ mod tests {
                     leaves,
                     private: true,
                 },
-                engine_params: &JubjubBls12::new_with_window_size(window_size),
+                engine_params: jubjub_params(window_size),
                 partitions: None,
             };
             let public_params = PoRCompound::<H>::setup(&setup_params).expect("setup failed");
@@ -539,7 +574,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let leaves = 6;
@@ -551,7 +586,8 @@ mod tests {
                 .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
                 .collect();
 
-            let graph = BucketGraph::<PedersenHasher>::new(leaves, BASE_DEGREE, 0, new_seed());
+            let graph =
+                BucketGraph::<PedersenHasher>::new(leaves, BASE_DEGREE, 0, new_seed_from(rng));
             let tree = graph.merkle_tree(data.as_slice()).unwrap();
 
             // -- MerklePoR
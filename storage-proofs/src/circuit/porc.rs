@@ -284,12 +284,12 @@ mod tests {
     use super::*;
 
     use ff::Field;
-    use fil_sapling_crypto::jubjub::JubjubBls12;
     use rand::{Rng, SeedableRng, XorShiftRng};
 
     use crate::circuit::test::*;
     use crate::compound_proof;
-    use crate::drgraph::{new_seed, BucketGraph, Graph, BASE_DEGREE};
+    use crate::crypto::pedersen::jubjub_params;
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
     use crate::fr32::fr_into_bytes;
     use crate::hasher::pedersen::*;
     use crate::porc::{self, PoRC};
@@ -302,7 +302,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let leaves = 32;
@@ -320,10 +320,10 @@ mod tests {
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
 
-        let graph1 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph1 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree1 = graph1.merkle_tree(data1.as_slice()).unwrap();
 
-        let graph2 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph2 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree2 = graph2.merkle_tree(data2.as_slice()).unwrap();
 
         let challenges = vec![rng.gen_range(0, leaves), rng.gen_range(0, leaves)];
@@ -396,7 +396,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let leaves = 32;
@@ -421,10 +421,10 @@ mod tests {
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
 
-        let graph1 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph1 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree1 = graph1.merkle_tree(data1.as_slice()).unwrap();
 
-        let graph2 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph2 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree2 = graph2.merkle_tree(data2.as_slice()).unwrap();
 
         let pub_inputs = porc::PublicInputs {
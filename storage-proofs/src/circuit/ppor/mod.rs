@@ -124,7 +124,8 @@ impl<'a, E: JubjubEngine> Circuit<E> for ParallelProofOfRetrievability<'a, E> {
 mod tests {
     use super::*;
     use crate::circuit::test::*;
-    use crate::drgraph::{new_seed, BucketGraph, Graph, BASE_DEGREE};
+    use crate::crypto::pedersen::jubjub_params;
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
     use crate::fr32::{bytes_into_fr, fr_into_bytes};
     use crate::hasher::pedersen::*;
     use crate::merklepor;
@@ -132,7 +133,6 @@ mod tests {
     use crate::settings;
     use crate::util::data_at_node;
     use ff::Field;
-    use fil_sapling_crypto::jubjub::JubjubBls12;
     use paired::bls12_381::*;
     use rand::{Rng, SeedableRng, XorShiftRng};
 
@@ -142,7 +142,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let leaves = 16;
@@ -156,7 +156,8 @@ mod tests {
                 .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
                 .collect();
 
-            let graph = BucketGraph::<PedersenHasher>::new(leaves, BASE_DEGREE, 0, new_seed());
+            let graph =
+                BucketGraph::<PedersenHasher>::new(leaves, BASE_DEGREE, 0, new_seed_from(rng));
             let tree = graph.merkle_tree(data.as_slice()).unwrap();
 
             let pub_inputs: Vec<_> = (0..leaves)
@@ -11,6 +11,7 @@ use crate::circuit::stacked::hash::hash2;
 use crate::circuit::variables::Root;
 use crate::compound_proof::{CircuitComponent, CompoundProof};
 use crate::drgraph;
+use crate::gpu_lock::Priority;
 use crate::hasher::Hasher;
 use crate::merklepor;
 use crate::parameter_cache::{CacheableParameters, ParameterSetMetadata};
@@ -58,6 +59,10 @@ impl<'a, H> CompoundProof<'a, Bls12, RationalPoSt<'a, H>, RationalPoStCircuit<'a
 where
     H: 'a + Hasher,
 {
+    fn gpu_priority() -> Priority {
+        Priority::High
+    }
+
     fn generate_public_inputs(
         pub_in: &<RationalPoSt<'a, H> as ProofScheme<'a>>::PublicInputs,
         pub_params: &<RationalPoSt<'a, H> as ProofScheme<'a>>::PublicParams,
@@ -279,7 +284,7 @@ mod tests {
 
     use crate::circuit::test::*;
     use crate::compound_proof;
-    use crate::drgraph::{new_seed, BucketGraph, Graph, BASE_DEGREE};
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
     use crate::fr32::fr_into_bytes;
     use crate::hasher::pedersen::*;
     use crate::proof::{NoRequirements, ProofScheme};
@@ -308,10 +313,10 @@ mod tests {
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
 
-        let graph1 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph1 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree1 = graph1.merkle_tree(data1.as_slice()).unwrap();
 
-        let graph2 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph2 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree2 = graph2.merkle_tree(data2.as_slice()).unwrap();
 
         let faults = OrderedSectorSet::new();
@@ -443,10 +448,10 @@ mod tests {
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
 
-        let graph1 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph1 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree1 = graph1.merkle_tree(data1.as_slice()).unwrap();
 
-        let graph2 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph2 = BucketGraph::<PedersenHasher>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree2 = graph2.merkle_tree(data2.as_slice()).unwrap();
 
         let faults = OrderedSectorSet::new();
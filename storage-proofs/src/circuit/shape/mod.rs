@@ -0,0 +1,193 @@
+use std::marker::PhantomData;
+
+use bellperson::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use ff::{PrimeField, PrimeFieldRepr};
+use paired::Engine;
+use sha2::{Digest, Sha256};
+
+/// A constraint system which only hashes the shape of a circuit: its variables, and every
+/// constraint with the variables and coefficients of its linear combinations. Annotations and
+/// witnesses are ignored, so blank circuits have the same digest as any other instance of them.
+///
+/// Circuits with the same digest have the same Groth16 parameters.
+pub struct ShapeCS<E: Engine> {
+    hasher: Sha256,
+    inputs: usize,
+    aux: usize,
+    constraints: usize,
+    buf: Vec<u8>,
+    _e: PhantomData<E>,
+}
+
+impl<E: Engine> ShapeCS<E> {
+    pub fn new() -> Self {
+        ShapeCS::default()
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.constraints
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// The digest of the shape synthesized so far.
+    pub fn digest(self) -> [u8; 32] {
+        let mut hasher = self.hasher;
+        for count in &[self.inputs, self.aux, self.constraints] {
+            hasher.input(&(*count as u64).to_le_bytes());
+        }
+
+        let mut digest = [0; 32];
+        digest.copy_from_slice(&hasher.result());
+        digest
+    }
+
+    fn hash_lc(&mut self, lc: &LinearCombination<E>) {
+        self.buf.clear();
+        self.buf
+            .extend_from_slice(&(lc.as_ref().len() as u64).to_le_bytes());
+        for (var, coeff) in lc.as_ref() {
+            let (kind, index) = match var.get_unchecked() {
+                Index::Input(i) => (0u8, i),
+                Index::Aux(i) => (1u8, i),
+            };
+            self.buf.push(kind);
+            self.buf.extend_from_slice(&(index as u64).to_le_bytes());
+            coeff
+                .into_repr()
+                .write_le(&mut self.buf)
+                .expect("writing to a vec can't fail");
+        }
+        self.hasher.input(&self.buf);
+    }
+}
+
+impl<E: Engine> Default for ShapeCS<E> {
+    fn default() -> Self {
+        ShapeCS {
+            hasher: Sha256::default(),
+            // The constant `ONE`.
+            inputs: 1,
+            aux: 0,
+            constraints: 0,
+            buf: Vec::new(),
+            _e: PhantomData,
+        }
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for ShapeCS<E> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.aux;
+        self.aux += 1;
+
+        Ok(Variable::new_unchecked(Index::Aux(index)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.inputs;
+        self.inputs += 1;
+
+        Ok(Variable::new_unchecked(Index::Input(index)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        self.hash_lc(&a(LinearCombination::zero()));
+        self.hash_lc(&b(LinearCombination::zero()));
+        self.hash_lc(&c(LinearCombination::zero()));
+        self.constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::Circuit;
+    use ff::Field;
+    use paired::bls12_381::{Bls12, Fr};
+
+    /// Proves knowledge of `x` with `x^exponent = y`, by repeated multiplication.
+    struct Power {
+        exponent: usize,
+        coeff: Fr,
+    }
+
+    impl Circuit<Bls12> for Power {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let x = cs.alloc(|| "x", || Ok(Fr::one()))?;
+            let mut acc = x;
+            for i in 1..self.exponent {
+                let next = cs.alloc(|| format!("acc_{}", i), || Ok(Fr::one()))?;
+                cs.enforce(
+                    || format!("power_{}", i),
+                    |lc| lc + acc,
+                    |lc| lc + (self.coeff, x),
+                    |lc| lc + next,
+                );
+                acc = next;
+            }
+            let y = cs.alloc_input(|| "y", || Ok(Fr::one()))?;
+            cs.enforce(|| "y", |lc| lc + acc, |lc| lc + CS::one(), |lc| lc + y);
+
+            Ok(())
+        }
+    }
+
+    fn digest(circuit: Power) -> [u8; 32] {
+        let mut cs = ShapeCS::<Bls12>::new();
+        circuit.synthesize(&mut cs).unwrap();
+        assert_eq!(cs.num_inputs(), 2);
+        cs.digest()
+    }
+
+    #[test]
+    fn test_shape_digest() {
+        let power = |exponent, coeff| Power { exponent, coeff };
+        let digest_3 = digest(power(3, Fr::one()));
+
+        assert_eq!(digest(power(3, Fr::one())), digest_3);
+        assert_ne!(digest(power(4, Fr::one())), digest_3);
+
+        let mut two = Fr::one();
+        two.double();
+        assert_ne!(digest(power(3, two)), digest_3);
+    }
+}
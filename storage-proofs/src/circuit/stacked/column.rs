@@ -1,11 +1,9 @@
 use bellperson::{ConstraintSystem, SynthesisError};
 use fil_sapling_crypto::circuit::num;
-use fil_sapling_crypto::jubjub::JubjubEngine;
 use paired::bls12_381::{Bls12, Fr};
 
-use crate::circuit::stacked::hash::hash_single_column;
 use crate::hasher::Hasher;
-use crate::stacked::{Column as VanillaColumn, PublicParams};
+use crate::stacked::Column as VanillaColumn;
 
 #[derive(Debug, Clone)]
 pub struct Column {
@@ -25,11 +23,11 @@ impl<H: Hasher> From<VanillaColumn<H>> for Column {
 }
 
 impl Column {
-    /// Create an empty `Column`, used in `blank_circuit`s.
-    pub fn empty<H: Hasher>(params: &PublicParams<H>) -> Self {
+    /// Create an empty `Column` of `layers` rows, used in `blank_circuit`s.
+    pub fn empty_with_layers(layers: usize) -> Self {
         Column {
             index: None,
-            rows: vec![None; params.layer_challenges.layers()],
+            rows: vec![None; layers],
         }
     }
 
@@ -39,11 +37,20 @@ impl Column {
         &self.rows[row_index]
     }
 
-    pub fn hash<CS: ConstraintSystem<Bls12>>(
-        self,
+    /// Allocates the rows, the label of the node at every layer.
+    pub fn alloc_rows<CS: ConstraintSystem<Bls12>>(
+        &self,
         mut cs: CS,
-        params: &<Bls12 as JubjubEngine>::Params,
-    ) -> Result<num::AllocatedNum<Bls12>, SynthesisError> {
-        hash_single_column(cs.namespace(|| "column_hash"), params, &self.rows)
+    ) -> Result<Vec<num::AllocatedNum<Bls12>>, SynthesisError> {
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                num::AllocatedNum::alloc(cs.namespace(|| format!("row_{}_num", i)), || {
+                    row.map(Into::into)
+                        .ok_or_else(|| SynthesisError::AssignmentMissing)
+                })
+            })
+            .collect()
     }
 }
@@ -5,10 +5,11 @@ use paired::bls12_381::{Bls12, Fr};
 
 use crate::circuit::{
     constraint,
-    stacked::{column::Column, params::InclusionPath},
+    stacked::{column::Column, hash::hash_single_column, params::InclusionPath},
 };
+use crate::drgraph::Graph;
 use crate::hasher::Hasher;
-use crate::stacked::{ColumnProof as VanillaColumnProof, PublicParams};
+use crate::stacked::ColumnProof as VanillaColumnProof;
 
 #[derive(Debug, Clone)]
 pub struct ColumnProof<H: Hasher> {
@@ -17,11 +18,12 @@ pub struct ColumnProof<H: Hasher> {
 }
 
 impl<H: Hasher> ColumnProof<H> {
-    /// Create an empty `ColumnProof`, used in `blank_circuit`s.
-    pub fn empty(params: &PublicParams<H>) -> Self {
+    /// Create an empty `ColumnProof` of a column of `layers` rows, in a tree over the nodes of
+    /// `graph`, used in `blank_circuit`s.
+    pub fn empty_with_layers(layers: usize, graph: &impl Graph<H>) -> Self {
         ColumnProof {
-            column: Column::empty(params),
-            inclusion_path: InclusionPath::empty(&params.graph),
+            column: Column::empty_with_layers(layers),
+            inclusion_path: InclusionPath::empty(graph),
         }
     }
 
@@ -29,18 +31,20 @@ impl<H: Hasher> ColumnProof<H> {
         self.column.get_node_at_layer(layer)
     }
 
+    /// Verifies the opening of the column in `comm_c`, and returns its allocated rows.
     pub fn synthesize<CS: ConstraintSystem<Bls12>>(
         self,
         mut cs: CS,
         params: &<Bls12 as JubjubEngine>::Params,
         comm_c: &num::AllocatedNum<Bls12>,
-    ) -> Result<(), SynthesisError> {
+    ) -> Result<Vec<num::AllocatedNum<Bls12>>, SynthesisError> {
         let ColumnProof {
             inclusion_path,
             column,
         } = self;
 
-        let c_i = column.hash(cs.namespace(|| "column_hash"), params)?;
+        let rows = column.alloc_rows(cs.namespace(|| "column_rows"))?;
+        let c_i = hash_single_column(cs.namespace(|| "column_hash"), params, &rows)?;
 
         let leaf_num = inclusion_path.alloc_value(cs.namespace(|| "leaf"))?;
 
@@ -54,7 +58,7 @@ impl<H: Hasher> ColumnProof<H> {
             leaf_num,
         )?;
 
-        Ok(())
+        Ok(rows)
     }
 }
 
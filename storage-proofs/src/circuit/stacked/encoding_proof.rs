@@ -3,7 +3,11 @@ use fil_sapling_crypto::circuit::{boolean::Boolean, num};
 use fil_sapling_crypto::jubjub::JubjubEngine;
 use paired::bls12_381::{Bls12, Fr};
 
-use crate::circuit::{constraint, kdf::kdf, stacked::encode::encode, uint64};
+use crate::circuit::{
+    constraint,
+    stacked::{encode::encode, label::create_label},
+    uint64,
+};
 use crate::drgraph::Graph;
 use crate::hasher::Hasher;
 use crate::stacked::{EncodingProof as VanillaEncodingProof, PublicParams};
@@ -22,21 +26,34 @@ impl EncodingProof {
         } else {
             params.graph.degree()
         };
+        Self::empty_with_degree(degree)
+    }
+
+    /// Create an empty proof of a label with `degree` parents, used in `blank_circuit`s.
+    pub fn empty_with_degree(degree: usize) -> Self {
         EncodingProof {
             node: None,
             parents: vec![None; degree],
         }
     }
 
-    fn create_key<CS: ConstraintSystem<Bls12>>(
+    /// Witnesses the index of the node, as 64 little-endian bits, for circuits which don't take
+    /// it from an inclusion path.
+    pub fn alloc_node<CS: ConstraintSystem<Bls12>>(
+        &self,
+        cs: CS,
+    ) -> Result<Vec<Boolean>, SynthesisError> {
+        Ok(uint64::UInt64::alloc(cs, self.node)?.to_bits_le())
+    }
+
+    /// Witnesses the labels of the parents, for circuits which don't take them from opened
+    /// columns.
+    pub fn alloc_parents<CS: ConstraintSystem<Bls12>>(
+        &self,
         mut cs: CS,
-        _params: &<Bls12 as JubjubEngine>::Params,
-        replica_id: &[Boolean],
-        node: Option<u64>,
-        parents: Vec<Option<Fr>>,
-    ) -> Result<num::AllocatedNum<Bls12>, SynthesisError> {
-        let parents_num = parents
-            .into_iter()
+    ) -> Result<Vec<num::AllocatedNum<Bls12>>, SynthesisError> {
+        self.parents
+            .iter()
             .enumerate()
             .map(|(i, parent)| {
                 num::AllocatedNum::alloc(cs.namespace(|| format!("parent_{}_num", i)), || {
@@ -45,46 +62,22 @@ impl EncodingProof {
                         .ok_or_else(|| SynthesisError::AssignmentMissing)
                 })
             })
-            .collect::<Result<Vec<_>, _>>()?;
-        let parents_bits = parents_num
-            .into_iter()
-            .enumerate()
-            .map(|(i, parent)| {
-                let mut bits =
-                    parent.into_bits_le(cs.namespace(|| format!("parent_{}_bits", i)))?;
-                while bits.len() % 8 > 0 {
-                    bits.push(Boolean::Constant(false));
-                }
-                Ok(bits)
-            })
-            .collect::<Result<Vec<_>, SynthesisError>>()?;
-
-        let node_num = uint64::UInt64::alloc(cs.namespace(|| "node"), node)?;
-
-        kdf(
-            cs.namespace(|| "create_key"),
-            replica_id,
-            parents_bits,
-            Some(node_num),
-        )
+            .collect()
     }
 
+    /// Enforces that the key of `node`, derived from `parents`, is `exp_encoded_node`.
     pub fn synthesize_key<CS: ConstraintSystem<Bls12>>(
-        self,
+        &self,
         mut cs: CS,
-        params: &<Bls12 as JubjubEngine>::Params,
-        replica_id: &[Boolean],
+        _params: &<Bls12 as JubjubEngine>::Params,
+        key_id: &[Boolean],
+        node: &[Boolean],
+        parents: &[num::AllocatedNum<Bls12>],
         exp_encoded_node: &num::AllocatedNum<Bls12>,
     ) -> Result<(), SynthesisError> {
-        let EncodingProof { node, parents } = self;
+        assert_eq!(parents.len(), self.parents.len(), "wrong number of parents");
 
-        let key = Self::create_key(
-            cs.namespace(|| "create_key"),
-            params,
-            replica_id,
-            node,
-            parents,
-        )?;
+        let key = create_label(cs.namespace(|| "create_key"), key_id, node, parents)?;
 
         // enforce equality
         constraint::equal(&mut cs, || "equality_key", &exp_encoded_node, &key);
@@ -92,23 +85,22 @@ impl EncodingProof {
         Ok(())
     }
 
+    /// Enforces that `decoded_node`, encoded with the key of `node` derived from `parents`, is
+    /// `exp_encoded_node`.
+    #[allow(clippy::too_many_arguments)]
     pub fn synthesize_decoded<CS: ConstraintSystem<Bls12>>(
-        self,
+        &self,
         mut cs: CS,
-        params: &<Bls12 as JubjubEngine>::Params,
-        replica_id: &[Boolean],
+        _params: &<Bls12 as JubjubEngine>::Params,
+        key_id: &[Boolean],
+        node: &[Boolean],
+        parents: &[num::AllocatedNum<Bls12>],
         exp_encoded_node: &num::AllocatedNum<Bls12>,
         decoded_node: &num::AllocatedNum<Bls12>,
     ) -> Result<(), SynthesisError> {
-        let EncodingProof { node, parents } = self;
-
-        let key = Self::create_key(
-            cs.namespace(|| "create_key"),
-            params,
-            replica_id,
-            node,
-            parents,
-        )?;
+        assert_eq!(parents.len(), self.parents.len(), "wrong number of parents");
+
+        let key = create_label(cs.namespace(|| "create_key"), key_id, node, parents)?;
 
         let encoded_node = encode(cs.namespace(|| "encode"), &key, decoded_node)?;
 
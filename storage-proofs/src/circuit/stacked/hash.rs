@@ -1,11 +1,45 @@
 use bellperson::{ConstraintSystem, SynthesisError};
-use ff::Field;
+use ff::{Field, PrimeField};
+use fil_sapling_crypto::circuit::blake2s::blake2s as blake2s_circuit;
 use fil_sapling_crypto::circuit::boolean::Boolean;
 use fil_sapling_crypto::circuit::num;
 use fil_sapling_crypto::jubjub::JubjubEngine;
 
 use crate::circuit::pedersen::{pedersen_compression_num as pedersen, pedersen_md_no_padding};
+use crate::crypto::domain::HashDomain;
 use crate::crypto::pedersen::PEDERSEN_BLOCK_SIZE;
+use crate::stacked::CommRHasher;
+
+/// Computes `comm_r = H(comm_c || comm_r_last)`, matching `CommRHasher::hash`.
+pub fn comm_r_hash<E, CS>(
+    mut cs: CS,
+    params: &E::Params,
+    comm_r_hasher: CommRHasher,
+    comm_c: &[Boolean],
+    comm_r_last: &[Boolean],
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    match comm_r_hasher {
+        CommRHasher::Pedersen => hash2(cs, params, comm_c, comm_r_last),
+        CommRHasher::Blake2s => {
+            let preimage = concat_padded(comm_c, comm_r_last);
+            let hash_bits = blake2s_circuit(
+                cs.namespace(|| "blake2s"),
+                &preimage,
+                HashDomain::CommR.personalization(),
+            )?;
+
+            // Truncate the same way `bytes_into_fr_repr_safe` does.
+            pack_bits(
+                cs.namespace(|| "pack"),
+                &hash_bits[..E::Fr::CAPACITY as usize],
+            )
+        }
+    }
+}
 
 /// Hash two elements together.
 pub fn hash2<E, CS>(
@@ -18,6 +52,13 @@ where
     E: JubjubEngine,
     CS: ConstraintSystem<E>,
 {
+    let values = concat_padded(first, second);
+
+    hash1(cs.namespace(|| "hash2"), params, &values)
+}
+
+/// Concatenates two bit strings, padding each to full bytes.
+fn concat_padded(first: &[Boolean], second: &[Boolean]) -> Vec<Boolean> {
     let mut values = Vec::new();
     values.extend_from_slice(first);
 
@@ -32,7 +73,39 @@ where
         values.push(Boolean::Constant(false));
     }
 
-    hash1(cs.namespace(|| "hash2"), params, &values)
+    values
+}
+
+/// Packs little-endian bits into a single field element, constraining the result.
+pub(crate) fn pack_bits<E, CS>(
+    mut cs: CS,
+    bits: &[Boolean],
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    assert!(bits.len() <= E::Fr::CAPACITY as usize);
+
+    let mut packed = num::Num::<E>::zero();
+    let mut coeff = E::Fr::one();
+    for bit in bits {
+        packed = packed.add_bool_with_coeff(CS::one(), bit, coeff);
+        coeff.double();
+    }
+
+    let packed_num = num::AllocatedNum::alloc(cs.namespace(|| "packed"), || {
+        packed.get_value().ok_or(SynthesisError::AssignmentMissing)
+    })?;
+
+    cs.enforce(
+        || "packing constraint",
+        |_| packed.lc(E::Fr::one()),
+        |lc| lc + CS::one(),
+        |lc| lc + packed_num.get_variable(),
+    );
+
+    Ok(packed_num)
 }
 
 /// Hash a list of bits.
@@ -57,25 +130,18 @@ where
     }
 }
 
-/// Hash a list of bits.
+/// Hash the allocated rows of a column.
 pub fn hash_single_column<E, CS>(
     mut cs: CS,
     params: &E::Params,
-    rows: &[Option<E::Fr>],
+    rows: &[num::AllocatedNum<E>],
 ) -> Result<num::AllocatedNum<E>, SynthesisError>
 where
     E: JubjubEngine,
     CS: ConstraintSystem<E>,
 {
     let mut bits = Vec::new();
-    for (i, row) in rows.iter().enumerate() {
-        let row_num = num::AllocatedNum::alloc(
-            cs.namespace(|| format!("hash_single_column_row_{}_num", i)),
-            || {
-                row.map(Into::into)
-                    .ok_or_else(|| SynthesisError::AssignmentMissing)
-            },
-        )?;
+    for (i, row_num) in rows.iter().enumerate() {
         let mut row_bits =
             row_num.into_bits_le(cs.namespace(|| format!("hash_single_column_row_{}_bits", i)))?;
         // pad to full bytes
@@ -99,6 +165,7 @@ mod tests {
     use rand::{Rng, SeedableRng, XorShiftRng};
 
     use crate::circuit::test::TestConstraintSystem;
+    use crate::crypto::pedersen::jubjub_params;
     use crate::fr32::fr_into_bytes;
     use crate::settings;
     use crate::stacked::hash::hash2 as vanilla_hash2;
@@ -111,7 +178,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
 
         for _ in 0..10 {
             let mut cs = TestConstraintSystem::<Bls12>::new();
@@ -144,4 +211,49 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_comm_r_hash_circuit() {
+        let mut rng = XorShiftRng::from_seed([0x5dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = &JubjubBls12::new();
+
+        for comm_r_hasher in &[CommRHasher::Pedersen, CommRHasher::Blake2s] {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+
+            let comm_c: Fr = rng.gen();
+            let comm_r_last: Fr = rng.gen();
+
+            let comm_c_bits = num::AllocatedNum::alloc(cs.namespace(|| "comm_c"), || Ok(comm_c))
+                .unwrap()
+                .into_bits_le(cs.namespace(|| "comm_c_bits"))
+                .unwrap();
+            let comm_r_last_bits =
+                num::AllocatedNum::alloc(cs.namespace(|| "comm_r_last"), || Ok(comm_r_last))
+                    .unwrap()
+                    .into_bits_le(cs.namespace(|| "comm_r_last_bits"))
+                    .unwrap();
+
+            let out = comm_r_hash(
+                cs.namespace(|| "comm_r"),
+                params,
+                *comm_r_hasher,
+                &comm_c_bits,
+                &comm_r_last_bits,
+            )
+            .expect("comm_r_hash failed");
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+
+            let expected = comm_r_hasher.hash(
+                fr_into_bytes::<Bls12>(&comm_c),
+                fr_into_bytes::<Bls12>(&comm_r_last),
+            );
+            assert_eq!(
+                expected,
+                out.get_value().unwrap(),
+                "circuit and non circuit do not match for {:?}",
+                comm_r_hasher
+            );
+        }
+    }
 }
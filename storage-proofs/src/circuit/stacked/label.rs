@@ -0,0 +1,113 @@
+use bellperson::{ConstraintSystem, SynthesisError};
+use ff::PrimeField;
+use fil_sapling_crypto::circuit::blake2s::blake2s as blake2s_circuit;
+use fil_sapling_crypto::circuit::{boolean::Boolean, num};
+use paired::bls12_381::{Bls12, Fr};
+
+use crate::circuit::stacked::hash::pack_bits;
+
+/// Derives the label of a node, enforcing the computation of `Labeler::label`:
+/// `Blake2s(porep_id || replica_id || node || parents)`, with the two most significant bits
+/// stripped.
+///
+/// `key_id` holds the bits of `porep_id || replica_id`, `node` the 64 little-endian bits of the
+/// node index and `parents` the labels of the parents, in order. The message spans several
+/// blocks, which are compressed in turn, just as the vanilla hasher does.
+pub fn create_label<CS: ConstraintSystem<Bls12>>(
+    mut cs: CS,
+    key_id: &[Boolean],
+    node: &[Boolean],
+    parents: &[num::AllocatedNum<Bls12>],
+) -> Result<num::AllocatedNum<Bls12>, SynthesisError> {
+    assert_eq!(key_id.len() % 8, 0, "key_id must be whole bytes");
+    assert_eq!(node.len(), 64, "node must be 64 bits");
+
+    let mut message = key_id.to_vec();
+    message.extend_from_slice(node);
+
+    for (i, parent) in parents.iter().enumerate() {
+        let mut bits = parent.into_bits_le(cs.namespace(|| format!("parent_{}_bits", i)))?;
+        // pad to full bytes
+        while bits.len() % 8 > 0 {
+            bits.push(Boolean::Constant(false));
+        }
+        message.extend(bits);
+    }
+
+    let personalization = vec![0u8; 8];
+    let hash_bits = blake2s_circuit(cs.namespace(|| "blake2s"), &message, &personalization)?;
+
+    // Strip the two most significant bits, like `Labeler::label`.
+    pack_bits(cs.namespace(|| "pack"), &hash_bits[..Fr::CAPACITY as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ff::Field;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::test::TestConstraintSystem;
+    use crate::circuit::uint64::UInt64;
+    use crate::fr32::{bytes_into_fr, fr_into_bytes};
+    use crate::stacked::label::Labeler;
+    use crate::util::{bytes_into_bits, NODE_SIZE};
+
+    #[test]
+    fn test_create_label() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let porep_id = [7u8; 32];
+        let replica_id = fr_into_bytes::<Bls12>(&rng.gen());
+        let node = 1234u64;
+        let parents: Vec<Fr> = (0..3).map(|_| rng.gen()).collect();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+
+        let key_id: Vec<Boolean> = bytes_into_bits(&porep_id)
+            .into_iter()
+            .chain(bytes_into_bits(&replica_id))
+            .map(Boolean::Constant)
+            .collect();
+        let node_bits = UInt64::alloc(cs.namespace(|| "node"), Some(node))
+            .unwrap()
+            .to_bits_le();
+        let parents_num = parents
+            .iter()
+            .enumerate()
+            .map(|(i, parent)| {
+                num::AllocatedNum::alloc(cs.namespace(|| format!("parent_{}", i)), || Ok(*parent))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let label = create_label(
+            cs.namespace(|| "create_label"),
+            &key_id,
+            &node_bits,
+            &parents_num,
+        )
+        .expect("create_label failed");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+
+        let parents_bytes: Vec<Vec<u8>> = parents.iter().map(fr_into_bytes::<Bls12>).collect();
+        let mut expected = [0u8; NODE_SIZE];
+        Labeler::new(&porep_id, &replica_id, NODE_SIZE).label(
+            node,
+            parents_bytes.iter().map(|p| &p[..]),
+            &mut expected,
+        );
+        assert_eq!(
+            label.get_value().unwrap(),
+            bytes_into_fr::<Bls12>(&expected).unwrap(),
+            "circuit and non circuit do not match"
+        );
+
+        // The label is bound to the hash, any other value is rejected.
+        let mut other = label.get_value().unwrap();
+        other.add_assign(&Fr::one());
+        cs.set("create_label/pack/packed/num", other);
+        assert!(!cs.is_satisfied());
+    }
+}
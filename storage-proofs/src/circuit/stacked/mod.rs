@@ -3,7 +3,10 @@ mod column_proof;
 mod encode;
 mod encoding_proof;
 pub(crate) mod hash;
+mod label;
 mod params;
 mod proof;
+mod window;
 
 pub use proof::{StackedCircuit, StackedCompound};
+pub use window::{WindowStackedCircuit, WindowStackedCompound};
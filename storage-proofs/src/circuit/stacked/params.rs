@@ -11,7 +11,8 @@ use crate::drgraph::Graph;
 use crate::hasher::Hasher;
 use crate::merkle::MerkleProof;
 use crate::stacked::{
-    Proof as VanillaProof, PublicParams, ReplicaColumnProof as VanillaReplicaColumnProof,
+    LayerChallenges, Proof as VanillaProof, PublicParams,
+    ReplicaColumnProof as VanillaReplicaColumnProof,
 };
 
 #[derive(Debug, Clone)]
@@ -41,22 +42,24 @@ impl<H: Hasher> Proof<H> {
         Proof {
             comm_d_proof: InclusionPath::empty(&params.graph),
             comm_r_last_proof: InclusionPath::empty(&params.graph),
-            replica_column_proof: ReplicaColumnProof::empty(params),
+            replica_column_proof: ReplicaColumnProof::empty(params, challenge_index),
             encoding_proofs,
         }
     }
 
-    /// Circuit synthesis.
+    /// Circuit synthesis. `key_id` holds the bits of `porep_id || replica_id`, from which every
+    /// label key is derived.
     #[allow(clippy::too_many_arguments)]
     pub fn synthesize<CS: ConstraintSystem<Bls12>>(
         self,
         mut cs: CS,
         params: &<Bls12 as JubjubEngine>::Params,
-        layers: usize,
+        layer_challenges: &LayerChallenges,
+        challenge_index: usize,
         comm_d: &num::AllocatedNum<Bls12>,
         comm_c: &num::AllocatedNum<Bls12>,
         comm_r_last: &num::AllocatedNum<Bls12>,
-        replica_id: &[Boolean],
+        key_id: &[Boolean],
     ) -> Result<(), SynthesisError> {
         let Proof {
             comm_d_proof,
@@ -65,52 +68,69 @@ impl<H: Hasher> Proof<H> {
             encoding_proofs,
         } = self;
 
-        // verify initial data layer
+        // verify initial data layer, the bits of its path are those of the challenged node
         let comm_d_leaf = comm_d_proof.alloc_value(cs.namespace(|| "comm_d_leaf"))?;
-        comm_d_proof.synthesize(
+        let mut node = comm_d_proof.synthesize(
             cs.namespace(|| "comm_d_inclusion"),
             params,
             comm_d.clone(),
             comm_d_leaf.clone(),
         )?;
+        // Labels are derived from the index of the node as u64.
+        assert!(node.len() <= 64, "tree too deep");
+        node.resize(64, Boolean::Constant(false));
 
         let comm_r_last_data_leaf =
             comm_r_last_proof.alloc_value(cs.namespace(|| "comm_r_last_data_leaf"))?;
 
-        // verify encodings
-        for (i, proof) in encoding_proofs.into_iter().enumerate() {
-            let layer = i + 1;
+        // verify replica column openings, of the expander parents only if a layer after the first
+        // depends on them
+        assert!(
+            layer_challenges.needs_exp_parents(challenge_index)
+                || replica_column_proof.exp_parents.is_empty(),
+            "expander parents opened for a challenge of the first layer only"
+        );
+        let columns = replica_column_proof.synthesize(
+            cs.namespace(|| "replica_column_proof"),
+            params,
+            comm_c,
+        )?;
+
+        // verify encodings, which are only present for the included layers, and whose keys are
+        // derived from the labels in the opened columns
+        let layers = layer_challenges.layers();
+        let included_layers = layer_challenges.included_layers(challenge_index);
+        assert_eq!(
+            included_layers.len(),
+            encoding_proofs.len(),
+            "wrong number of encoding proofs"
+        );
+
+        for (layer, proof) in included_layers.into_iter().zip(encoding_proofs) {
+            let parents = columns.parents_at_layer(layer);
 
             if layer == layers {
                 proof.synthesize_decoded(
                     cs.namespace(|| format!("encoding_proof_{}", layer)),
                     params,
-                    replica_id,
+                    key_id,
+                    &node,
+                    &parents,
                     &comm_r_last_data_leaf,
                     &comm_d_leaf,
                 )?;
             } else {
-                let raw = replica_column_proof.c_x.get_node_at_layer(layer);
-                let encoded_node = num::AllocatedNum::alloc(
-                    cs.namespace(|| format!("enc_node_{}", layer)),
-                    || {
-                        raw.map(Into::into)
-                            .ok_or_else(|| SynthesisError::AssignmentMissing)
-                    },
-                )?;
-
                 proof.synthesize_key(
                     cs.namespace(|| format!("encoding_proof_{}", layer)),
                     params,
-                    replica_id,
-                    &encoded_node,
+                    key_id,
+                    &node,
+                    &parents,
+                    columns.node_at_layer(layer),
                 )?;
             }
         }
 
-        // verify replica column openings
-        replica_column_proof.synthesize(cs.namespace(|| "replica_column_proof"), params, comm_c)?;
-
         // verify final replica layer
         comm_r_last_proof.synthesize(
             cs.namespace(|| "comm_r_last_data_inclusion"),
@@ -169,18 +189,20 @@ impl<H: Hasher> InclusionPath<H> {
         })
     }
 
+    /// Verifies the inclusion of `leaf` in the tree with `root`, and returns the bits of the
+    /// path, which are the little-endian bits of the index of the leaf.
     pub fn synthesize<CS: ConstraintSystem<Bls12>>(
         self,
         cs: CS,
         params: &<Bls12 as JubjubEngine>::Params,
         root: num::AllocatedNum<Bls12>,
         leaf: num::AllocatedNum<Bls12>,
-    ) -> Result<(), SynthesisError> {
+    ) -> Result<Vec<Boolean>, SynthesisError> {
         let InclusionPath { auth_path, .. } = self;
 
         let root = Root::from_allocated::<CS>(root.clone());
         let value = Root::from_allocated::<CS>(leaf);
-        PoRCircuit::<Bls12, H>::synthesize(cs, params, value, auth_path, root, true)
+        PoRCircuit::<Bls12, H>::synthesize_with_path(cs, params, value, auth_path, root, true)
     }
 }
 
@@ -204,21 +226,52 @@ pub struct ReplicaColumnProof<H: Hasher> {
 }
 
 impl<H: Hasher> ReplicaColumnProof<H> {
-    /// Create an empty proof, used in `blank_circuit`s.
-    pub fn empty(params: &PublicParams<H>) -> Self {
+    /// Create an empty proof for the challenge with the given index, used in `blank_circuit`s.
+    /// The expander parents are left out if the challenge is only included in the first layer.
+    pub fn empty(params: &PublicParams<H>, challenge_index: usize) -> Self {
+        let expansion_degree = if params.layer_challenges.needs_exp_parents(challenge_index) {
+            params.graph.expansion_degree()
+        } else {
+            0
+        };
+
+        Self::empty_with_degrees(
+            params.layer_challenges.layers(),
+            &params.graph,
+            params.graph.base_graph().degree(),
+            expansion_degree,
+        )
+    }
+
+    /// Create an empty proof of columns of `layers` rows, in a tree over the nodes of `graph`,
+    /// with the given numbers of parents, used in `blank_circuit`s.
+    pub fn empty_with_degrees(
+        layers: usize,
+        graph: &impl Graph<H>,
+        base_degree: usize,
+        expansion_degree: usize,
+    ) -> Self {
+        let column = ColumnProof::empty_with_layers(layers, graph);
+
         ReplicaColumnProof {
-            c_x: ColumnProof::empty(params),
-            drg_parents: vec![ColumnProof::empty(params); params.graph.base_graph().degree()],
-            exp_parents: vec![ColumnProof::empty(params); params.graph.expansion_degree()],
+            c_x: column.clone(),
+            drg_parents: vec![column.clone(); base_degree],
+            exp_parents: vec![column; expansion_degree],
         }
     }
 
+    /// The label of the challenged node at `layer`.
+    pub fn get_node_at_layer(&self, layer: usize) -> &Option<Fr> {
+        self.c_x.get_node_at_layer(layer)
+    }
+
+    /// Verifies the openings of all columns in `comm_c`, and returns their allocated rows.
     pub fn synthesize<CS: ConstraintSystem<Bls12>>(
         self,
         mut cs: CS,
         params: &<Bls12 as JubjubEngine>::Params,
         comm_c: &num::AllocatedNum<Bls12>,
-    ) -> Result<(), SynthesisError> {
+    ) -> Result<AllocatedColumns, SynthesisError> {
         let ReplicaColumnProof {
             c_x,
             drg_parents,
@@ -226,19 +279,63 @@ impl<H: Hasher> ReplicaColumnProof<H> {
         } = self;
 
         // c_x
-        c_x.synthesize(cs.namespace(|| "c_x"), params, comm_c)?;
+        let c_x = c_x.synthesize(cs.namespace(|| "c_x"), params, comm_c)?;
 
         // drg parents
-        for (i, parent) in drg_parents.into_iter().enumerate() {
-            parent.synthesize(cs.namespace(|| format!("drg_parent_{}", i)), params, comm_c)?;
-        }
+        let drg_parents = drg_parents
+            .into_iter()
+            .enumerate()
+            .map(|(i, parent)| {
+                parent.synthesize(cs.namespace(|| format!("drg_parent_{}", i)), params, comm_c)
+            })
+            .collect::<Result<_, _>>()?;
 
         // exp parents
-        for (i, parent) in exp_parents.into_iter().enumerate() {
-            parent.synthesize(cs.namespace(|| format!("exp_parent_{}", i)), params, comm_c)?;
+        let exp_parents = exp_parents
+            .into_iter()
+            .enumerate()
+            .map(|(i, parent)| {
+                parent.synthesize(cs.namespace(|| format!("exp_parent_{}", i)), params, comm_c)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(AllocatedColumns {
+            c_x,
+            drg_parents,
+            exp_parents,
+        })
+    }
+}
+
+/// The rows of the columns opened by a `ReplicaColumnProof`, once verified.
+pub struct AllocatedColumns {
+    c_x: Vec<num::AllocatedNum<Bls12>>,
+    drg_parents: Vec<Vec<num::AllocatedNum<Bls12>>>,
+    exp_parents: Vec<Vec<num::AllocatedNum<Bls12>>>,
+}
+
+impl AllocatedColumns {
+    /// The label of the challenged node at `layer`.
+    pub fn node_at_layer(&self, layer: usize) -> &num::AllocatedNum<Bls12> {
+        &self.c_x[layer - 1]
+    }
+
+    /// The labels the label of the challenged node at `layer` is derived from: those of the base
+    /// parents at the same layer and, after the first layer, those of the expander parents at the
+    /// previous one.
+    pub fn parents_at_layer(&self, layer: usize) -> Vec<num::AllocatedNum<Bls12>> {
+        assert!(layer > 0, "layer must be greater than 0");
+
+        let mut parents: Vec<_> = self
+            .drg_parents
+            .iter()
+            .map(|rows| rows[layer - 1].clone())
+            .collect();
+        if layer > 1 {
+            parents.extend(self.exp_parents.iter().map(|rows| rows[layer - 2].clone()));
         }
 
-        Ok(())
+        parents
     }
 }
 
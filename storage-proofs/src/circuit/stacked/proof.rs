@@ -8,7 +8,7 @@ use paired::bls12_381::{Bls12, Fr};
 use crate::circuit::por::PoRCompound;
 use crate::circuit::{
     constraint,
-    stacked::{hash::hash2, params::Proof},
+    stacked::{hash::comm_r_hash, params::Proof},
 };
 use crate::compound_proof::{CircuitComponent, CompoundProof};
 use crate::drgraph::{Graph, BASE_DEGREE};
@@ -16,7 +16,8 @@ use crate::hasher::Hasher;
 use crate::merklepor;
 use crate::parameter_cache::{CacheableParameters, ParameterSetMetadata};
 use crate::proof::ProofScheme;
-use crate::stacked::{StackedDrg, EXP_DEGREE};
+use crate::stacked::{CommRHasher, StackedDrg, EXP_DEGREE};
+use crate::util::bytes_into_bits;
 
 /// Stacked DRG based Proof of Replication.
 ///
@@ -97,87 +98,33 @@ impl<'a, H: Hasher> Circuit<Bls12> for StackedCircuit<'a, Bls12, H> {
             assert_eq!(graph.expansion_degree(), EXP_DEGREE);
         }
 
-        // Allocate replica_id
-        let replica_id_num = num::AllocatedNum::alloc(cs.namespace(|| "replica_id_num"), || {
-            replica_id
-                .map(Into::into)
-                .ok_or_else(|| SynthesisError::AssignmentMissing)
-        })?;
-
-        let mut replica_id_bits =
-            replica_id_num.into_bits_le(cs.namespace(|| "replica_id_bits"))?;
-        // pad
-        while replica_id_bits.len() % 8 > 0 {
-            replica_id_bits.push(Boolean::Constant(false));
-        }
-
-        // Allocate comm_d as Fr
-        let comm_d_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_d"), || {
-            comm_d
-                .map(Into::into)
-                .ok_or_else(|| SynthesisError::AssignmentMissing)
-        })?;
-
-        // make comm_d a public input
-        comm_d_num.inputize(cs.namespace(|| "comm_d_input"))?;
-
-        // Allocate comm_r as Fr
-        let comm_r_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_r"), || {
-            comm_r
-                .map(Into::into)
-                .ok_or_else(|| SynthesisError::AssignmentMissing)
-        })?;
-
-        // make comm_r a public input
-        comm_r_num.inputize(cs.namespace(|| "comm_r_input"))?;
-
-        // Allocate comm_r_last as Fr
-        let comm_r_last_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_r_last"), || {
-            comm_r_last
-                .map(Into::into)
-                .ok_or_else(|| SynthesisError::AssignmentMissing)
-        })?;
-
-        // Allocate comm_r_last as booleans
-        let comm_r_last_bits = comm_r_last_num.into_bits_le(cs.namespace(|| "comm_r_last_bits"))?;
-
-        // Allocate comm_c as Fr
-        let comm_c_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_c"), || {
-            comm_c
-                .map(Into::into)
-                .ok_or_else(|| SynthesisError::AssignmentMissing)
-        })?;
-
-        // Allocate comm_c as booleans
-        let comm_c_bits = comm_c_num.into_bits_le(cs.namespace(|| "comm_c_bits"))?;
-
-        // Verify comm_r = H(comm_c || comm_r_last)
-        {
-            let hash_num = hash2(
-                cs.namespace(|| "H_comm_c_comm_r_last"),
-                params,
-                &comm_c_bits,
-                &comm_r_last_bits,
-            )?;
-
-            // Check actual equality
-            constraint::equal(
-                cs,
-                || "enforce comm_r = H(comm_c || comm_r_last)",
-                &comm_r_num,
-                &hash_num,
-            );
-        }
+        let PartitionCommitments {
+            key_id_bits,
+            comm_d: comm_d_num,
+            comm_c: comm_c_num,
+            comm_r_last: comm_r_last_num,
+        } = synthesize_commitments(
+            cs,
+            params,
+            &public_params.porep_id,
+            public_params.comm_r_hasher,
+            replica_id.map(Into::into),
+            comm_d.map(Into::into),
+            comm_r.map(Into::into),
+            comm_r_last.map(Into::into),
+            comm_c.map(Into::into),
+        )?;
 
         for (i, proof) in proofs.into_iter().enumerate() {
             proof.synthesize(
                 &mut cs.namespace(|| format!("challenge_{}", i)),
                 &self.params,
-                public_params.layer_challenges.layers(),
+                &public_params.layer_challenges,
+                i,
                 &comm_d_num,
                 &comm_c_num,
                 &comm_r_last_num,
-                &replica_id_bits,
+                &key_id_bits,
             )?;
         }
 
@@ -185,6 +132,107 @@ impl<'a, H: Hasher> Circuit<Bls12> for StackedCircuit<'a, Bls12, H> {
     }
 }
 
+/// The commitments of a partition, allocated, and the bits of `porep_id || replica_id`, which
+/// every label key is derived from.
+pub(super) struct PartitionCommitments {
+    pub key_id_bits: Vec<Boolean>,
+    pub comm_d: num::AllocatedNum<Bls12>,
+    pub comm_c: num::AllocatedNum<Bls12>,
+    pub comm_r_last: num::AllocatedNum<Bls12>,
+}
+
+/// Allocates the commitments of a partition, makes `comm_d` and `comm_r` public inputs, and
+/// enforces `comm_r = H(comm_c || comm_r_last)`.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn synthesize_commitments<CS: ConstraintSystem<Bls12>>(
+    cs: &mut CS,
+    params: &<Bls12 as JubjubEngine>::Params,
+    porep_id: &[u8; 32],
+    comm_r_hasher: CommRHasher,
+    replica_id: Option<Fr>,
+    comm_d: Option<Fr>,
+    comm_r: Option<Fr>,
+    comm_r_last: Option<Fr>,
+    comm_c: Option<Fr>,
+) -> Result<PartitionCommitments, SynthesisError> {
+    // Allocate replica_id
+    let replica_id_num = num::AllocatedNum::alloc(cs.namespace(|| "replica_id_num"), || {
+        replica_id.ok_or_else(|| SynthesisError::AssignmentMissing)
+    })?;
+
+    let mut replica_id_bits = replica_id_num.into_bits_le(cs.namespace(|| "replica_id_bits"))?;
+    // pad
+    while replica_id_bits.len() % 8 > 0 {
+        replica_id_bits.push(Boolean::Constant(false));
+    }
+
+    // Every key is derived from porep_id || replica_id. The porep_id is fixed by the public
+    // params, so it enters the circuit as constants.
+    let mut key_id_bits: Vec<Boolean> = bytes_into_bits(porep_id)
+        .into_iter()
+        .map(Boolean::Constant)
+        .collect();
+    key_id_bits.extend(replica_id_bits);
+
+    // Allocate comm_d as Fr
+    let comm_d_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_d"), || {
+        comm_d.ok_or_else(|| SynthesisError::AssignmentMissing)
+    })?;
+
+    // make comm_d a public input
+    comm_d_num.inputize(cs.namespace(|| "comm_d_input"))?;
+
+    // Allocate comm_r as Fr
+    let comm_r_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_r"), || {
+        comm_r.ok_or_else(|| SynthesisError::AssignmentMissing)
+    })?;
+
+    // make comm_r a public input
+    comm_r_num.inputize(cs.namespace(|| "comm_r_input"))?;
+
+    // Allocate comm_r_last as Fr
+    let comm_r_last_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_r_last"), || {
+        comm_r_last.ok_or_else(|| SynthesisError::AssignmentMissing)
+    })?;
+
+    // Allocate comm_r_last as booleans
+    let comm_r_last_bits = comm_r_last_num.into_bits_le(cs.namespace(|| "comm_r_last_bits"))?;
+
+    // Allocate comm_c as Fr
+    let comm_c_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_c"), || {
+        comm_c.ok_or_else(|| SynthesisError::AssignmentMissing)
+    })?;
+
+    // Allocate comm_c as booleans
+    let comm_c_bits = comm_c_num.into_bits_le(cs.namespace(|| "comm_c_bits"))?;
+
+    // Verify comm_r = H(comm_c || comm_r_last)
+    {
+        let hash_num = comm_r_hash(
+            cs.namespace(|| "H_comm_c_comm_r_last"),
+            params,
+            comm_r_hasher,
+            &comm_c_bits,
+            &comm_r_last_bits,
+        )?;
+
+        // Check actual equality
+        constraint::equal(
+            cs,
+            || "enforce comm_r = H(comm_c || comm_r_last)",
+            &comm_r_num,
+            &hash_num,
+        );
+    }
+
+    Ok(PartitionCommitments {
+        key_id_bits,
+        comm_d: comm_d_num,
+        comm_c: comm_c_num,
+        comm_r_last: comm_r_last_num,
+    })
+}
+
 #[allow(dead_code)]
 pub struct StackedCompound {
     partitions: Option<usize>,
@@ -231,9 +279,14 @@ impl<'a, H: 'static + Hasher>
             PoRCompound::<H>::generate_public_inputs(&pub_inputs, &por_params, k)
         };
 
-        let all_challenges = pub_in.all_challenges(&pub_params.layer_challenges, graph.size(), k);
+        let all_challenges = pub_in.all_challenges(
+            &pub_params.layer_challenges,
+            &pub_params.porep_id,
+            graph.size(),
+            k,
+        );
 
-        for challenge in all_challenges.into_iter() {
+        for (challenge_index, challenge) in all_challenges.into_iter().enumerate() {
             // comm_d_proof
             inputs.extend(generate_inclusion_inputs(challenge));
 
@@ -250,10 +303,16 @@ impl<'a, H: 'static + Hasher>
                     inputs.extend(generate_inclusion_inputs(parent));
                 }
 
-                // exp parents
-                let exp_parents = graph.expanded_parents(challenge, |p| p.clone());
-                for parent in exp_parents.into_iter() {
-                    inputs.extend(generate_inclusion_inputs(parent as usize));
+                // exp parents, only opened if a layer after the first depends on them
+                if pub_params
+                    .layer_challenges
+                    .needs_exp_parents(challenge_index)
+                {
+                    let mut exp_parents = vec![0; graph.expansion_degree()];
+                    graph.expanded_parents(challenge, &mut exp_parents);
+                    for parent in exp_parents.into_iter() {
+                        inputs.extend(generate_inclusion_inputs(parent as usize));
+                    }
                 }
             }
 
@@ -321,23 +380,28 @@ impl<'a, H: 'static + Hasher>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::mem;
+
     use crate::circuit::metric::*;
+    use crate::circuit::stacked::column_proof::ColumnProof;
     use crate::circuit::test::*;
     use crate::compound_proof;
+    use crate::crypto::pedersen::jubjub_params;
     use crate::drgporep;
-    use crate::drgraph::{new_seed, BASE_DEGREE};
+    use crate::drgraph::{new_seed_from, GraphConfig, BASE_DEGREE};
     use crate::fr32::fr_into_bytes;
     use crate::hasher::{Blake2sHasher, Hasher, PedersenHasher};
     use crate::porep::PoRep;
     use crate::proof::ProofScheme;
     use crate::settings;
+    use crate::stacked::tamper::{valid_proofs, Tamper};
     use crate::stacked::{
-        ChallengeRequirements, LayerChallenges, PrivateInputs, PublicInputs, SetupParams,
-        EXP_DEGREE,
+        ChallengeRequirements, CommRHasher, LayerChallenges, PrivateInputs, Proof as VanillaProof,
+        PublicInputs, SetupParams, Taper, EXP_DEGREE,
     };
+    use crate::util::NODE_SIZE;
 
     use ff::Field;
-    use fil_sapling_crypto::jubjub::JubjubBls12;
     use rand::{Rng, SeedableRng, XorShiftRng};
 
     #[test]
@@ -346,7 +410,7 @@ mod tests {
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let nodes = 5;
         let degree = BASE_DEGREE;
         let expansion_degree = EXP_DEGREE;
@@ -366,15 +430,23 @@ mod tests {
                 nodes,
                 degree,
                 expansion_degree,
-                seed: new_seed(),
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
             },
             layer_challenges: layer_challenges.clone(),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
         };
 
         let pp = StackedDrg::setup(&sp).expect("setup failed");
-        let (tau, (p_aux, t_aux)) =
-            StackedDrg::replicate(&pp, &replica_id.into(), data_copy.as_mut_slice(), None)
-                .expect("replication failed");
+        let (tau, (p_aux, t_aux)) = StackedDrg::replicate(
+            &pp,
+            &replica_id.into(),
+            data_copy.as_mut_slice().into(),
+            None,
+        )
+        .expect("replication failed");
         assert_ne!(data, data_copy);
 
         let pub_inputs = PublicInputs::<<PedersenHasher as Hasher>::Domain> {
@@ -397,8 +469,28 @@ mod tests {
 
         assert!(proofs_are_valid);
 
-        let expected_inputs = 20; // was 39 with "old" stacked all pedersen
-        let expected_constraints = 179_143; // was 432_312 with "old" stacked all pedersen
+        // The only challenge is not included in layer 2, so its expander parents are not opened.
+        assert!(proofs[0][0].replica_column_proofs.exp_parents.is_empty());
+        let column_proof_constraints = {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let column_proof = &proofs[0][0].replica_column_proofs.drg_parents[0];
+            let comm_c = num::AllocatedNum::alloc(cs.namespace(|| "comm_c"), || {
+                Ok((*column_proof.root()).into())
+            })
+            .unwrap();
+            ColumnProof::<PedersenHasher>::from(column_proof.clone())
+                .synthesize(cs.namespace(|| "column_proof"), params, &comm_c)
+                .unwrap();
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            cs.num_constraints()
+        };
+
+        // was 39 with "old" stacked all pedersen, and 20 with the expander parents
+        let expected_inputs = 20 - EXP_DEGREE;
+        // was 432_312 with "old" stacked all pedersen, and 179_143 with the expander parents and
+        // with the node of the label witnessed as 64 bits, instead of taken from the comm_d
+        // path, and the label packed without a constraint
+        let expected_constraints = 179_143 - EXP_DEGREE * column_proof_constraints - 64 + 1;
 
         {
             // Verify that MetricCS returns the same metrics as TestConstraintSystem.
@@ -459,29 +551,99 @@ mod tests {
         );
     }
 
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn test_stacked_tampered_proofs_are_unsatisfied() {
+        let window_size = settings::SETTINGS
+            .lock()
+            .unwrap()
+            .pedersen_hash_exp_window_size;
+        let params = jubjub_params(window_size);
+        let (pp, pub_inputs, proofs) =
+            valid_proofs::<PedersenHasher>(8, LayerChallenges::new(2, 2));
+
+        let is_satisfied = |proofs: &Vec<VanillaProof<PedersenHasher>>| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            StackedCompound::circuit(
+                &pub_inputs,
+                <StackedCircuit<Bls12, PedersenHasher> as CircuitComponent>::ComponentPrivateInputs::default(),
+                proofs,
+                &pp,
+                params,
+            )
+            .synthesize(&mut cs.namespace(|| "stacked drgporep"))
+            .expect("failed to synthesize circuit");
+
+            cs.is_satisfied()
+        };
+        assert!(is_satisfied(&proofs), "constraints not satisfied");
+
+        for (i, proof) in proofs.iter().enumerate() {
+            // One flip of each kind, as every synthesis is slow. The parents of the encoding
+            // proofs are not witnessed, the circuit takes their labels from the opened columns.
+            let mut tampers = Tamper::all(proof);
+            tampers.retain(|tamper| match tamper {
+                Tamper::EncodingParent { .. } => false,
+                _ => true,
+            });
+            tampers.dedup_by_key(|tamper| mem::discriminant(tamper));
+
+            for tamper in tampers {
+                let mut tampered = proofs.clone();
+                tampered[i] = tamper.apply(proof);
+                assert!(
+                    !is_satisfied(&tampered),
+                    "proof {} satisfies the constraints with {:?}",
+                    i,
+                    tamper
+                );
+            }
+        }
+    }
+
     #[test]
     #[ignore] // Slow test – run only when compiled for release.
     fn test_stacked_compound_pedersen() {
-        stacked_test_compound::<PedersenHasher>();
+        stacked_test_compound::<PedersenHasher>(CommRHasher::Pedersen, LayerChallenges::new(2, 3));
     }
 
     #[test]
     #[ignore] // Slow test – run only when compiled for release.
     fn test_stacked_compound_blake2s() {
-        stacked_test_compound::<Blake2sHasher>();
+        stacked_test_compound::<Blake2sHasher>(CommRHasher::Pedersen, LayerChallenges::new(2, 3));
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn test_stacked_compound_blake2s_comm_r() {
+        stacked_test_compound::<PedersenHasher>(CommRHasher::Blake2s, LayerChallenges::new(2, 3));
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn test_stacked_compound_tapered() {
+        let taper = Taper {
+            percent: 50,
+            floor: 1,
+        };
+        stacked_test_compound::<PedersenHasher>(
+            CommRHasher::Pedersen,
+            LayerChallenges::new_tapered(3, 4, taper),
+        );
     }
 
-    fn stacked_test_compound<H: 'static + Hasher>() {
+    fn stacked_test_compound<H: 'static + Hasher>(
+        comm_r_hasher: CommRHasher,
+        layer_challenges: LayerChallenges,
+    ) {
         let window_size = settings::SETTINGS
             .lock()
             .unwrap()
             .pedersen_hash_exp_window_size;
-        let params = &JubjubBls12::new_with_window_size(window_size);
+        let params = jubjub_params(window_size);
         let nodes = 5;
         let degree = 3;
         let expansion_degree = 2;
-        let num_layers = 2;
-        let layer_challenges = LayerChallenges::new(num_layers, 3);
         let partition_count = 1;
 
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
@@ -500,18 +662,33 @@ mod tests {
                     nodes,
                     degree,
                     expansion_degree,
-                    seed: new_seed(),
+                    seed: new_seed_from(rng),
+                    graph_config: GraphConfig::default(),
                 },
                 layer_challenges: layer_challenges.clone(),
+                porep_id: [1; 32],
+                comm_r_hasher,
+                node_size: NODE_SIZE,
             },
             partitions: Some(partition_count),
         };
 
         let public_params = StackedCompound::setup(&setup_params).expect("setup failed");
+
+        // Enough partitions are set up to satisfy the requirements.
+        let required = StackedCompound::setup_for_requirements(
+            &setup_params,
+            &ChallengeRequirements {
+                minimum_challenges: 2 * layer_challenges.challenges_count_all() + 1,
+            },
+        )
+        .expect("setup failed");
+        assert_eq!(required.partitions, Some(3));
+
         let (tau, (p_aux, t_aux)) = StackedDrg::replicate(
             &public_params.vanilla_params,
             &replica_id.into(),
-            data_copy.as_mut_slice(),
+            data_copy.as_mut_slice().into(),
             None,
         )
         .expect("replication failed");
@@ -0,0 +1,527 @@
+use std::marker::PhantomData;
+
+use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+use fil_sapling_crypto::circuit::{boolean::Boolean, num};
+use fil_sapling_crypto::jubjub::JubjubEngine;
+use paired::bls12_381::{Bls12, Fr};
+
+use crate::circuit::por::PoRCompound;
+use crate::circuit::stacked::{
+    column_proof::ColumnProof,
+    encoding_proof::EncodingProof,
+    params::{InclusionPath, ReplicaColumnProof},
+    proof::{synthesize_commitments, PartitionCommitments},
+};
+use crate::compound_proof::{CircuitComponent, CompoundProof};
+use crate::drgraph::BASE_DEGREE;
+use crate::hasher::Hasher;
+use crate::merklepor;
+use crate::parameter_cache::{CacheableParameters, ParameterSetMetadata};
+use crate::proof::ProofScheme;
+use crate::stacked::window::{
+    WindowProof as VanillaWindowProof, WindowPublicParams, WindowStackedDrg,
+};
+use crate::stacked::{LayerChallenges, EXP_DEGREE};
+
+/// The proof of one challenged node of a windowed stacked PoRep.
+#[derive(Debug, Clone)]
+pub struct Proof<H: Hasher> {
+    comm_d_proof: InclusionPath<H>,
+    comm_r_last_proof: InclusionPath<H>,
+    replica_column_proof: ReplicaColumnProof<H>,
+    wrapper_parents: Vec<ColumnProof<H>>,
+    encoding_proofs: Vec<EncodingProof>,
+    wrapper_proof: EncodingProof,
+}
+
+impl<H: Hasher> Proof<H> {
+    /// Create an empty proof, used in `blank_circuit`s.
+    pub fn empty(params: &WindowPublicParams<H>, challenge_index: usize) -> Self {
+        let graph = &params.graph;
+        let layers = params.layer_challenges.layers();
+        let base_degree = graph.base_degree();
+        let expansion_degree = graph.expansion_degree();
+
+        let encoding_proofs = (1..layers)
+            .filter(|layer| {
+                params
+                    .layer_challenges
+                    .include_challenge_at_layer(*layer, challenge_index)
+            })
+            .map(|layer| {
+                if layer == 1 {
+                    EncodingProof::empty_with_degree(base_degree)
+                } else {
+                    EncodingProof::empty_with_degree(base_degree + expansion_degree)
+                }
+            })
+            .collect();
+
+        Proof {
+            comm_d_proof: InclusionPath::empty(graph.wrapper_graph()),
+            comm_r_last_proof: InclusionPath::empty(graph.wrapper_graph()),
+            replica_column_proof: ReplicaColumnProof::empty_with_degrees(
+                layers,
+                graph.wrapper_graph(),
+                base_degree,
+                expansion_degree,
+            ),
+            wrapper_parents: vec![
+                ColumnProof::empty_with_layers(layers, graph.wrapper_graph());
+                expansion_degree
+            ],
+            encoding_proofs,
+            wrapper_proof: EncodingProof::empty_with_degree(expansion_degree),
+        }
+    }
+
+    /// Circuit synthesis. `key_id` holds the bits of `porep_id || replica_id`, from which every
+    /// label key is derived.
+    #[allow(clippy::too_many_arguments)]
+    pub fn synthesize<CS: ConstraintSystem<Bls12>>(
+        self,
+        mut cs: CS,
+        params: &<Bls12 as JubjubEngine>::Params,
+        layer_challenges: &LayerChallenges,
+        challenge_index: usize,
+        comm_d: &num::AllocatedNum<Bls12>,
+        comm_c: &num::AllocatedNum<Bls12>,
+        comm_r_last: &num::AllocatedNum<Bls12>,
+        key_id: &[Boolean],
+    ) -> Result<(), SynthesisError> {
+        let Proof {
+            comm_d_proof,
+            comm_r_last_proof,
+            replica_column_proof,
+            wrapper_parents,
+            encoding_proofs,
+            wrapper_proof,
+        } = self;
+
+        // verify initial data layer
+        let comm_d_leaf = comm_d_proof.alloc_value(cs.namespace(|| "comm_d_leaf"))?;
+        comm_d_proof.synthesize(
+            cs.namespace(|| "comm_d_inclusion"),
+            params,
+            comm_d.clone(),
+            comm_d_leaf.clone(),
+        )?;
+
+        let comm_r_last_data_leaf =
+            comm_r_last_proof.alloc_value(cs.namespace(|| "comm_r_last_data_leaf"))?;
+
+        // verify the labels of the window layers, which are only present for the included layers
+        let wrapper_layer = layer_challenges.layers();
+        let included_layers: Vec<usize> = layer_challenges
+            .included_layers(challenge_index)
+            .into_iter()
+            .filter(|layer| *layer < wrapper_layer)
+            .collect();
+        assert_eq!(
+            included_layers.len(),
+            encoding_proofs.len(),
+            "wrong number of encoding proofs"
+        );
+
+        for (layer, proof) in included_layers.into_iter().zip(encoding_proofs) {
+            let raw = replica_column_proof.get_node_at_layer(layer);
+            let encoded_node =
+                num::AllocatedNum::alloc(cs.namespace(|| format!("enc_node_{}", layer)), || {
+                    raw.map(Into::into)
+                        .ok_or_else(|| SynthesisError::AssignmentMissing)
+                })?;
+
+            let mut cs = cs.namespace(|| format!("encoding_proof_{}", layer));
+            let node = proof.alloc_node(cs.namespace(|| "node"))?;
+            let parents = proof.alloc_parents(cs.namespace(|| "parents"))?;
+            proof.synthesize_key(
+                cs.namespace(|| "key"),
+                params,
+                key_id,
+                &node,
+                &parents,
+                &encoded_node,
+            )?;
+        }
+
+        // verify the key of the wrapper layer encodes the data into the replica
+        {
+            let mut cs = cs.namespace(|| "wrapper_proof");
+            let node = wrapper_proof.alloc_node(cs.namespace(|| "node"))?;
+            let parents = wrapper_proof.alloc_parents(cs.namespace(|| "parents"))?;
+            wrapper_proof.synthesize_decoded(
+                cs.namespace(|| "key"),
+                params,
+                key_id,
+                &node,
+                &parents,
+                &comm_r_last_data_leaf,
+                &comm_d_leaf,
+            )?;
+        }
+
+        // verify replica column openings
+        replica_column_proof.synthesize(cs.namespace(|| "replica_column_proof"), params, comm_c)?;
+
+        // verify the column openings of the wrapper parents
+        for (i, parent) in wrapper_parents.into_iter().enumerate() {
+            parent.synthesize(
+                cs.namespace(|| format!("wrapper_parent_{}", i)),
+                params,
+                comm_c,
+            )?;
+        }
+
+        // verify final replica layer
+        comm_r_last_proof.synthesize(
+            cs.namespace(|| "comm_r_last_data_inclusion"),
+            params,
+            comm_r_last.clone(),
+            comm_r_last_data_leaf,
+        )?;
+
+        Ok(())
+    }
+}
+
+impl<H: Hasher> From<VanillaWindowProof<H>> for Proof<H> {
+    fn from(vanilla_proof: VanillaWindowProof<H>) -> Self {
+        let VanillaWindowProof {
+            comm_d_proofs,
+            comm_r_last_proof,
+            replica_column_proofs,
+            wrapper_parents,
+            encoding_proofs,
+            wrapper_proof,
+        } = vanilla_proof;
+
+        Proof {
+            comm_d_proof: comm_d_proofs.into(),
+            comm_r_last_proof: comm_r_last_proof.into(),
+            replica_column_proof: replica_column_proofs.into(),
+            wrapper_parents: wrapper_parents.into_iter().map(|p| p.into()).collect(),
+            encoding_proofs: encoding_proofs.into_iter().map(|p| p.into()).collect(),
+            wrapper_proof: wrapper_proof.into(),
+        }
+    }
+}
+
+/// Windowed stacked DRG based Proof of Replication.
+pub struct WindowStackedCircuit<'a, E: JubjubEngine, H: 'static + Hasher> {
+    params: &'a E::Params,
+    public_params: WindowPublicParams<H>,
+    replica_id: Option<H::Domain>,
+    comm_d: Option<H::Domain>,
+    comm_r: Option<H::Domain>,
+    comm_r_last: Option<H::Domain>,
+    comm_c: Option<H::Domain>,
+
+    // one proof per challenge
+    proofs: Vec<Proof<H>>,
+
+    _e: PhantomData<E>,
+}
+
+impl<'a, E: JubjubEngine, H: Hasher> CircuitComponent for WindowStackedCircuit<'a, E, H> {
+    type ComponentPrivateInputs = ();
+}
+
+impl<'a, H: Hasher> Circuit<Bls12> for WindowStackedCircuit<'a, Bls12, H> {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let WindowStackedCircuit {
+            params,
+            public_params,
+            proofs,
+            replica_id,
+            comm_r,
+            comm_d,
+            comm_r_last,
+            comm_c,
+            ..
+        } = self;
+
+        if !cfg!(feature = "unchecked-degrees") {
+            assert_eq!(public_params.graph.base_degree(), BASE_DEGREE);
+            assert_eq!(public_params.graph.expansion_degree(), EXP_DEGREE);
+        }
+
+        let PartitionCommitments {
+            key_id_bits,
+            comm_d: comm_d_num,
+            comm_c: comm_c_num,
+            comm_r_last: comm_r_last_num,
+        } = synthesize_commitments(
+            cs,
+            params,
+            &public_params.porep_id,
+            public_params.comm_r_hasher,
+            replica_id.map(Into::into),
+            comm_d.map(Into::into),
+            comm_r.map(Into::into),
+            comm_r_last.map(Into::into),
+            comm_c.map(Into::into),
+        )?;
+
+        for (i, proof) in proofs.into_iter().enumerate() {
+            proof.synthesize(
+                &mut cs.namespace(|| format!("challenge_{}", i)),
+                params,
+                &public_params.layer_challenges,
+                i,
+                &comm_d_num,
+                &comm_c_num,
+                &comm_r_last_num,
+                &key_id_bits,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+pub struct WindowStackedCompound {
+    partitions: Option<usize>,
+}
+
+impl<E: JubjubEngine, C: Circuit<E>, P: ParameterSetMetadata> CacheableParameters<E, C, P>
+    for WindowStackedCompound
+{
+    fn cache_prefix() -> String {
+        String::from("window-stacked-proof-of-replication")
+    }
+}
+
+impl<'a, H: 'static + Hasher>
+    CompoundProof<'a, Bls12, WindowStackedDrg<'a, H>, WindowStackedCircuit<'a, Bls12, H>>
+    for WindowStackedCompound
+{
+    fn generate_public_inputs(
+        pub_in: &<WindowStackedDrg<H> as ProofScheme>::PublicInputs,
+        pub_params: &<WindowStackedDrg<H> as ProofScheme>::PublicParams,
+        k: Option<usize>,
+    ) -> Vec<Fr> {
+        let graph = &pub_params.graph;
+        let tau = pub_in.tau.as_ref().expect("missing tau");
+
+        let mut inputs = vec![tau.comm_d.into(), tau.comm_r.into()];
+
+        let por_params = merklepor::MerklePoR::<H>::setup(&merklepor::SetupParams {
+            leaves: graph.size(),
+            private: true,
+        })
+        .expect("setup failed");
+
+        let generate_inclusion_inputs = |c: usize| {
+            let pub_inputs = merklepor::PublicInputs::<H::Domain> {
+                challenge: c,
+                commitment: None,
+            };
+
+            PoRCompound::<H>::generate_public_inputs(&pub_inputs, &por_params, k)
+        };
+
+        for challenge in pub_params.all_challenges(pub_in, k) {
+            // comm_d_proof
+            inputs.extend(generate_inclusion_inputs(challenge));
+
+            // c_x
+            inputs.extend(generate_inclusion_inputs(challenge));
+
+            // drg parents
+            let mut drg_parents = vec![0; graph.base_degree()];
+            graph.base_parents(challenge, &mut drg_parents);
+            for parent in drg_parents {
+                inputs.extend(generate_inclusion_inputs(parent));
+            }
+
+            // exp parents
+            let mut exp_parents = vec![0; graph.expansion_degree()];
+            graph.expanded_parents(challenge, &mut exp_parents);
+            for parent in exp_parents {
+                inputs.extend(generate_inclusion_inputs(parent));
+            }
+
+            // wrapper parents
+            let mut wrapper_parents = vec![0; graph.expansion_degree()];
+            graph.wrapper_parents(challenge, &mut wrapper_parents);
+            for parent in wrapper_parents {
+                inputs.extend(generate_inclusion_inputs(parent));
+            }
+
+            // final replica layer
+            inputs.extend(generate_inclusion_inputs(challenge));
+        }
+
+        inputs
+    }
+
+    fn circuit<'b>(
+        public_inputs: &'b <WindowStackedDrg<H> as ProofScheme>::PublicInputs,
+        _component_private_inputs: <WindowStackedCircuit<'a, Bls12, H> as CircuitComponent>::ComponentPrivateInputs,
+        vanilla_proof: &'b <WindowStackedDrg<H> as ProofScheme>::Proof,
+        public_params: &'b <WindowStackedDrg<H> as ProofScheme>::PublicParams,
+        engine_params: &'a <Bls12 as JubjubEngine>::Params,
+    ) -> WindowStackedCircuit<'a, Bls12, H> {
+        assert!(
+            !vanilla_proof.is_empty(),
+            "Cannot create a circuit with no vanilla proofs"
+        );
+
+        let comm_r_last = *vanilla_proof[0].comm_r_last();
+        let comm_c = *vanilla_proof[0].comm_c();
+
+        // ensure consistency
+        assert!(vanilla_proof
+            .iter()
+            .all(|p| p.comm_r_last() == &comm_r_last));
+        assert!(vanilla_proof.iter().all(|p| p.comm_c() == &comm_c));
+
+        WindowStackedCircuit {
+            params: engine_params,
+            public_params: public_params.clone(),
+            replica_id: Some(public_inputs.replica_id),
+            comm_d: public_inputs.tau.as_ref().map(|t| t.comm_d),
+            comm_r: public_inputs.tau.as_ref().map(|t| t.comm_r),
+            comm_r_last: Some(comm_r_last),
+            comm_c: Some(comm_c),
+            proofs: vanilla_proof.iter().cloned().map(|p| p.into()).collect(),
+            _e: PhantomData,
+        }
+    }
+
+    fn blank_circuit(
+        public_params: &<WindowStackedDrg<H> as ProofScheme>::PublicParams,
+        params: &'a <Bls12 as JubjubEngine>::Params,
+    ) -> WindowStackedCircuit<'a, Bls12, H> {
+        WindowStackedCircuit {
+            params,
+            public_params: public_params.clone(),
+            replica_id: None,
+            comm_d: None,
+            comm_r: None,
+            comm_r_last: None,
+            comm_c: None,
+            proofs: (0..public_params.layer_challenges.challenges_count_all())
+                .map(|challenge_index| Proof::empty(public_params, challenge_index))
+                .collect(),
+            _e: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ff::Field;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::metric::MetricCS;
+    use crate::circuit::test::TestConstraintSystem;
+    use crate::crypto::pedersen::jubjub_params;
+    use crate::drgporep;
+    use crate::drgraph::GraphConfig;
+    use crate::fr32::fr_into_bytes;
+    use crate::hasher::PedersenHasher;
+    use crate::porep::PoRep;
+    use crate::settings;
+    use crate::stacked::window::WindowSetupParams;
+    use crate::stacked::{CommRHasher, PrivateInputs, PublicInputs};
+    use crate::util::NODE_SIZE;
+
+    #[test]
+    fn window_stacked_input_circuit_with_bls12_381() {
+        let window_size = settings::SETTINGS
+            .lock()
+            .unwrap()
+            .pedersen_hash_exp_window_size;
+        let params = jubjub_params(window_size);
+        let nodes = 16;
+
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let replica_id: Fr = rng.gen();
+        let mut data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let sp = WindowSetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: [1, 2, 3, 4, 5, 6, 7],
+                graph_config: GraphConfig::default(),
+            },
+            window_size: 8,
+            layer_challenges: LayerChallenges::new(2, 1),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        };
+
+        let pp = WindowStackedDrg::<PedersenHasher>::setup(&sp).expect("setup failed");
+        let (tau, (p_aux, t_aux)) = WindowStackedDrg::<PedersenHasher>::replicate(
+            &pp,
+            &replica_id.into(),
+            data.as_mut_slice().into(),
+            None,
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs::<<PedersenHasher as Hasher>::Domain> {
+            replica_id: replica_id.into(),
+            seed: None,
+            tau: Some(tau),
+            k: None,
+        };
+        let priv_inputs = PrivateInputs::<PedersenHasher> { p_aux, t_aux };
+
+        let proofs = WindowStackedDrg::<PedersenHasher>::prove_all_partitions(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            1,
+        )
+        .expect("failed to generate partition proofs");
+        assert!(WindowStackedDrg::<PedersenHasher>::verify_all_partitions(
+            &pp,
+            &pub_inputs,
+            &proofs
+        )
+        .expect("failed to verify partition proofs"));
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        WindowStackedCompound::circuit(&pub_inputs, (), &proofs[0], &pp, params)
+            .synthesize(&mut cs.namespace(|| "window stacked drgporep"))
+            .expect("failed to synthesize circuit");
+
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert_eq!(cs.get_input(0, "ONE"), Fr::one());
+
+        let generated_inputs =
+            WindowStackedCompound::generate_public_inputs(&pub_inputs, &pp, None);
+        let expected_inputs = cs.get_inputs();
+
+        for ((input, label), generated_input) in
+            expected_inputs.iter().skip(1).zip(generated_inputs.iter())
+        {
+            assert_eq!(input, generated_input, "{}", label);
+        }
+        assert_eq!(
+            generated_inputs.len(),
+            expected_inputs.len() - 1,
+            "inputs are not the same length"
+        );
+
+        // The blank circuit has the same shape.
+        let mut blank_cs = MetricCS::<Bls12>::new();
+        <WindowStackedCompound as CompoundProof<_, WindowStackedDrg<PedersenHasher>, _>>::blank_circuit(&pp, params)
+            .synthesize(&mut blank_cs.namespace(|| "window stacked drgporep"))
+            .expect("failed to synthesize blank circuit");
+
+        assert_eq!(blank_cs.num_constraints(), cs.num_constraints());
+        assert_eq!(blank_cs.num_inputs(), cs.num_inputs());
+    }
+}
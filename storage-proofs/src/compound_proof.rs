@@ -1,9 +1,15 @@
 use rayon::prelude::*;
 
+use crate::batch::verify_proofs_batch;
+use crate::cancel;
 use crate::circuit::multi_proof::MultiProof;
 use crate::error::Result;
+use crate::gpu;
+use crate::gpu_lock::Priority;
+use crate::measurements::{self, Operation};
 use crate::parameter_cache::{CacheableParameters, ParameterSetMetadata};
 use crate::partitions;
+use crate::progress::{Phase, Tracker};
 use crate::proof::ProofScheme;
 use crate::settings;
 use bellperson::{groth16, Circuit};
@@ -59,6 +65,26 @@ where
         })
     }
 
+    /// Like `setup`, but with as many partitions as it takes to satisfy `requirements`, see
+    /// `ProofScheme::minimum_partitions`. The partitions of `sp` are ignored.
+    fn setup_for_requirements<'b>(
+        sp: &SetupParams<'a, 'b, E, S>,
+        requirements: &S::Requirements,
+    ) -> Result<PublicParams<'a, E, S>>
+    where
+        E::Params: Sync,
+    {
+        let vanilla_params = S::setup(sp.vanilla_params)?;
+        let partitions = S::minimum_partitions(&vanilla_params, requirements)
+            .ok_or_else(|| format_err!("no number of partitions satisfies the requirements"))?;
+
+        Ok(PublicParams {
+            vanilla_params,
+            engine_params: sp.engine_params,
+            partitions: Some(partitions),
+        })
+    }
+
     fn partition_count(public_params: &PublicParams<'a, E, S>) -> usize {
         match public_params.partitions {
             None => 1,
@@ -78,35 +104,70 @@ where
         E::Params: Sync,
     {
         let partitions = Self::partition_count(pub_params);
-        let partition_count = Self::partition_count(pub_params);
 
-        let vanilla_proofs =
-            S::prove_all_partitions(&pub_params.vanilla_params, &pub_in, priv_in, partitions)?;
+        let vanilla_proofs = measurements::measure(Operation::VanillaProof, None, 0, || {
+            S::prove_all_partitions(&pub_params.vanilla_params, &pub_in, priv_in, partitions)
+        })?;
+
+        Self::prove_from_vanilla(pub_params, pub_in, &vanilla_proofs, groth_params)
+    }
 
-        let sanity_check =
-            S::verify_all_partitions(&pub_params.vanilla_params, &pub_in, &vanilla_proofs)?;
-        assert!(sanity_check, "sanity check failed");
+    /// Generates the circuit proofs of `vanilla_proofs`, one per partition, which need not have
+    /// been generated on this machine. Fails if they are not one valid vanilla proof per
+    /// partition.
+    fn prove_from_vanilla<'b>(
+        pub_params: &'b PublicParams<'a, E, S>,
+        pub_in: &'b S::PublicInputs,
+        vanilla_proofs: &[S::Proof],
+        groth_params: &'b groth16::Parameters<E>,
+    ) -> Result<MultiProof<'b, E>>
+    where
+        E::Params: Sync,
+    {
+        let partition_count = Self::partition_count(pub_params);
 
         // This will always run at least once, since there cannot be zero partitions.
         assert!(partition_count > 0);
 
+        if vanilla_proofs.len() != partition_count {
+            return Err(format_err!(
+                "{} vanilla proofs for {} partitions",
+                vanilla_proofs.len(),
+                partition_count
+            )
+            .into());
+        }
+        if !S::verify_all_partitions(&pub_params.vanilla_params, pub_in, vanilla_proofs)? {
+            return Err(format_err!("vanilla proofs do not verify").into());
+        }
+
         // Use a custom pool for this, so we can control the number of threads being used.
         let pool = rayon::ThreadPoolBuilder::new()
             .num_threads(settings::SETTINGS.lock().unwrap().num_proving_threads)
             .build()
             .expect("failed to build thread pool");
 
+        let tracker = Tracker::new(Phase::CircuitProof, None, partition_count);
+        let token = cancel::current();
         let groth_proofs: Result<Vec<_>> = pool.install(|| {
             vanilla_proofs
                 .par_iter()
                 .map(|vanilla_proof| {
-                    Self::circuit_proof(
-                        pub_in,
-                        &vanilla_proof,
-                        &pub_params.vanilla_params,
-                        &pub_params.engine_params,
-                        groth_params,
-                    )
+                    token.check()?;
+
+                    let groth_proof =
+                        measurements::measure(Operation::CircuitProof, None, 0, || {
+                            Self::circuit_proof(
+                                pub_in,
+                                &vanilla_proof,
+                                &pub_params.vanilla_params,
+                                &pub_params.engine_params,
+                                groth_params,
+                            )
+                        })?;
+                    tracker.advance(1);
+
+                    Ok(groth_proof)
                 })
                 .collect()
         });
@@ -146,6 +207,74 @@ where
         Ok(true)
     }
 
+    /// batch_verify checks many multi-proofs at once, accepting only if every one of them
+    /// would pass `verify`. All circuit proofs are folded into a single randomized Groth16
+    /// batch check, which is much cheaper than verifying them one by one.
+    fn batch_verify(
+        public_params: &PublicParams<'a, E, S>,
+        public_inputs: &[S::PublicInputs],
+        multi_proofs: &[MultiProof<E>],
+        requirements: &S::Requirements,
+    ) -> Result<bool> {
+        if public_inputs.len() != multi_proofs.len() {
+            return Err(format_err!(
+                "{} multi-proofs but {} sets of public inputs",
+                multi_proofs.len(),
+                public_inputs.len()
+            )
+            .into());
+        }
+
+        let verifying_key = match multi_proofs.first() {
+            Some(multi_proof) => multi_proof.verifying_key,
+            None => return Ok(true),
+        };
+
+        let vanilla_public_params = &public_params.vanilla_params;
+        let partition_count = Self::partition_count(public_params);
+
+        if !<S as ProofScheme>::satisfies_requirements(
+            vanilla_public_params,
+            requirements,
+            partition_count,
+        ) {
+            return Ok(false);
+        }
+
+        let mut circuit_proofs = Vec::with_capacity(multi_proofs.len() * partition_count);
+        let mut circuit_inputs = Vec::with_capacity(multi_proofs.len() * partition_count);
+
+        for (multi_proof, pub_in) in multi_proofs.iter().zip(public_inputs) {
+            if multi_proof.circuit_proofs.len() != partition_count
+                || multi_proof.verifying_key != verifying_key
+            {
+                return Ok(false);
+            }
+
+            for (k, circuit_proof) in multi_proof.circuit_proofs.iter().enumerate() {
+                circuit_proofs.push(circuit_proof);
+                circuit_inputs.push(Self::generate_public_inputs(
+                    pub_in,
+                    vanilla_public_params,
+                    Some(k),
+                ));
+            }
+        }
+
+        verify_proofs_batch(
+            verifying_key,
+            &mut OsRng::new().expect("Failed to create `OsRng`"),
+            &circuit_proofs,
+            &circuit_inputs,
+        )
+    }
+
+    /// The priority of this proof's circuit proofs on the GPU, see `gpu_lock`. Proofs with a
+    /// deadline should override this to take the GPU ahead of background work.
+    fn gpu_priority() -> Priority {
+        Priority::Low
+    }
+
     /// circuit_proof creates and synthesizes a circuit from concrete params/inputs, then generates a
     /// groth proof from it. It returns a groth proof.
     /// circuit_proof is used internally and should neither be called nor implemented outside of
@@ -171,7 +300,13 @@ where
             )
         };
 
-        let groth_proof = groth16::create_random_proof(make_circuit(), groth_params, rng)?;
+        let groth_proof = gpu::with_gpu(Self::gpu_priority(), || {
+            Ok(groth16::create_random_proof(
+                make_circuit(),
+                groth_params,
+                rng,
+            )?)
+        })?;
 
         let mut proof_vec = vec![];
         groth_proof.write(&mut proof_vec)?;
@@ -208,7 +343,7 @@ where
         engine_params: &'a E::Params,
     ) -> Result<groth16::Parameters<E>> {
         Self::get_groth_params(
-            Self::blank_circuit(public_params, engine_params),
+            || Self::blank_circuit(public_params, engine_params),
             public_params,
         )
     }
@@ -218,7 +353,7 @@ where
         engine_params: &'a E::Params,
     ) -> Result<groth16::VerifyingKey<E>> {
         Self::get_verifying_key(
-            Self::blank_circuit(public_params, engine_params),
+            || Self::blank_circuit(public_params, engine_params),
             public_params,
         )
     }
@@ -0,0 +1,195 @@
+//! Binding of tree building to physical cores.
+//!
+//! `tree_c` and `tree_r_last` are hashed on the CPU, by default in rayon's global pool, which has
+//! a thread for every logical CPU. With simultaneous multithreading (hyperthreads), two of these
+//! threads share every physical core, and compete for its execution units and caches, so the
+//! second thread adds little but contention. Setting `FIL_PROOFS_TREE_CORE_BINDING=physical`
+//! builds both trees in a single pool with one thread per physical core instead, each pinned to
+//! the first hardware thread of its core. The cores are read from `/sys/devices/system/cpu`.
+//!
+//! Binding is only supported on Linux. Where it is not, or the topology can't be read, trees are
+//! built in the global pool and a warning is logged.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::numa;
+use crate::settings;
+
+/// Where the CPUs of the machine are listed.
+const CPUS_PATH: &str = "/sys/devices/system/cpu";
+
+/// How the threads building trees are bound to cores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreBinding {
+    /// No binding, trees are built in rayon's global pool.
+    None,
+    /// One thread per physical core, pinned to it.
+    Physical,
+}
+
+impl CoreBinding {
+    /// The binding chosen by the `tree_core_binding` setting.
+    pub fn from_settings() -> Result<Self> {
+        let binding = settings::SETTINGS.lock().unwrap().tree_core_binding.clone();
+        CoreBinding::parse(&binding)
+    }
+
+    fn parse(binding: &str) -> Result<Self> {
+        match binding {
+            "none" => Ok(CoreBinding::None),
+            "physical" => Ok(CoreBinding::Physical),
+            other => Err(Error::InvalidSetting(
+                "tree_core_binding",
+                other.to_string(),
+            )),
+        }
+    }
+}
+
+/// Returns one CPU of every physical core of the machine, the lowest numbered of its hardware
+/// threads, in order. Only online CPUs are considered.
+pub fn physical_cores() -> Result<Vec<usize>> {
+    let cpus_path = Path::new(CPUS_PATH);
+    let read = |path: &Path| {
+        fs::read_to_string(path)
+            .map_err(|err| Error::CoreBinding(format!("failed to read {:?}: {}", path, err)))
+    };
+
+    let online = numa::parse_cpu_list(&read(&cpus_path.join("online"))?)?;
+    let siblings = online
+        .iter()
+        .map(|cpu| {
+            let path = cpus_path.join(format!("cpu{}/topology/thread_siblings_list", cpu));
+            Ok((*cpu, numa::parse_cpu_list(&read(&path)?)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(first_threads(&siblings))
+}
+
+/// Picks the lowest numbered hardware thread of every core, given the thread siblings of every
+/// CPU, which include the CPU itself.
+fn first_threads(siblings: &[(usize, Vec<usize>)]) -> Vec<usize> {
+    let cores: BTreeSet<usize> = siblings
+        .iter()
+        .map(|(cpu, threads)| threads.iter().cloned().min().unwrap_or(*cpu))
+        .collect();
+
+    cores.into_iter().collect()
+}
+
+/// The threads trees are built by: rayon's global pool, or a pool bound to physical cores.
+#[derive(Debug, Default)]
+pub struct TreePool(Option<rayon::ThreadPool>);
+
+impl TreePool {
+    /// The pool chosen by the `tree_core_binding` setting. If the cores can't be bound, the
+    /// global pool is used, as binding only affects performance.
+    pub fn from_settings() -> Result<Self> {
+        match CoreBinding::from_settings()? {
+            CoreBinding::None => Ok(TreePool::default()),
+            CoreBinding::Physical => Ok(TreePool::physical().unwrap_or_else(|err| {
+                warn!("building trees on all CPUs: {}", err);
+                TreePool::default()
+            })),
+        }
+    }
+
+    /// A pool with a thread for every physical core, pinned to the first hardware thread of its
+    /// core, see `physical_cores`.
+    pub fn physical() -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(Error::CoreBinding("only supported on Linux".into()));
+        }
+
+        let cores = physical_cores()?;
+        if cores.is_empty() {
+            return Err(Error::CoreBinding("no online CPUs".into()));
+        }
+        info!("building trees on {} physical cores", cores.len());
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(cores.len())
+            .thread_name(|i| format!("tree-core-{}", i))
+            .start_handler(move |i| {
+                if let Err(err) = numa::bind_current_thread(&cores[i..=i]) {
+                    warn!("failed to pin tree building thread: {}", err);
+                }
+            })
+            .build()
+            .map_err(|err| Error::CoreBinding(err.to_string()))?;
+
+        Ok(TreePool(Some(pool)))
+    }
+
+    /// The number of threads of a bound pool, if any.
+    pub fn threads(&self) -> Option<usize> {
+        self.0.as_ref().map(rayon::ThreadPool::current_num_threads)
+    }
+
+    /// Runs `f` in the pool, so that the parallel iterators it uses only run there. Without a
+    /// bound pool, `f` simply runs.
+    pub fn install<R, F>(&self, f: F) -> R
+    where
+        R: Send,
+        F: FnOnce() -> R + Send,
+    {
+        match self.0 {
+            Some(ref pool) => pool.install(f),
+            None => f(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_core_binding() {
+        assert_eq!(CoreBinding::parse("none").unwrap(), CoreBinding::None);
+        assert_eq!(
+            CoreBinding::parse("physical").unwrap(),
+            CoreBinding::Physical
+        );
+        assert!(CoreBinding::parse("hwloc").is_err());
+    }
+
+    #[test]
+    fn test_first_threads() {
+        // Two cores with two hardware threads each, numbered like most x86 machines do.
+        let smt = vec![
+            (0, vec![0, 2]),
+            (1, vec![1, 3]),
+            (2, vec![0, 2]),
+            (3, vec![1, 3]),
+        ];
+        assert_eq!(first_threads(&smt), vec![0, 1]);
+
+        let no_smt = vec![(0, vec![0]), (1, vec![1]), (2, vec![]), (5, vec![5])];
+        assert_eq!(first_threads(&no_smt), vec![0, 1, 2, 5]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_physical_pool() {
+        let pool = match TreePool::physical() {
+            Ok(pool) => pool,
+            // No readable CPU topology in this environment.
+            Err(_) => return,
+        };
+        assert_eq!(pool.threads(), Some(physical_cores().unwrap().len()));
+
+        let sum = pool.install(|| {
+            use rayon::prelude::*;
+            (0..1000u64).into_par_iter().sum::<u64>()
+        });
+        assert_eq!(sum, 499_500);
+
+        assert_eq!(TreePool::default().threads(), None);
+        assert_eq!(TreePool::default().install(|| 7), 7);
+    }
+}
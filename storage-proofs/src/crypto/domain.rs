@@ -0,0 +1,45 @@
+use blake2s_simd::Params as Blake2s;
+
+/// The uses of Blake2s whose outputs must never be mistaken for one another. Each is hashed
+/// with its own personalization, so that equal preimages still hash differently across uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashDomain {
+    /// Combining `comm_c` and `comm_r_last` into `comm_r`, see `CommRHasher::Blake2s`.
+    CommR,
+    /// Deriving an election ticket from a partial ticket, see `finalize_ticket`.
+    Ticket,
+}
+
+impl HashDomain {
+    /// The Blake2s personalization of this domain, also used by the circuits.
+    pub fn personalization(self) -> &'static [u8; 8] {
+        match self {
+            HashDomain::CommR => b"FilCommR",
+            HashDomain::Ticket => b"FilTickt",
+        }
+    }
+
+    /// Blake2s parameters for 32 byte hashes in this domain.
+    pub fn blake2s(self) -> Blake2s {
+        let mut params = Blake2s::new();
+        params.hash_length(32).personal(self.personalization());
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domains_are_separated() {
+        let data = [7u8; 64];
+        let comm_r = HashDomain::CommR.blake2s().hash(&data);
+        let ticket = HashDomain::Ticket.blake2s().hash(&data);
+        let plain = Blake2s::new().hash_length(32).hash(&data);
+
+        assert_ne!(comm_r, ticket);
+        assert_ne!(comm_r, plain);
+        assert_ne!(ticket, plain);
+    }
+}
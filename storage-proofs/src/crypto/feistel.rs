@@ -1,4 +1,5 @@
 use blake2b_simd::blake2b;
+use byteorder::{BigEndian, ByteOrder};
 use std::mem;
 
 pub const FEISTEL_ROUNDS: usize = 3;
@@ -10,6 +11,27 @@ pub type Index = u64;
 
 pub type FeistelPrecomputed = (Index, Index, Index);
 
+/// The round keys of the Feistel network, one per round.
+pub type FeistelKeys = [Index; FEISTEL_ROUNDS];
+
+// Derive the round keys from `seed`, so that distinct seeds yield unrelated
+// permutations. Each key is the first 8 bytes of `Blake2b(seed | round)`;
+// callers permuting many indexes should derive them once and keep them.
+pub fn derive_keys(seed: &[u8]) -> FeistelKeys {
+    let mut keys = [0; FEISTEL_ROUNDS];
+    let mut data = Vec::with_capacity(seed.len() + 1);
+
+    for (round, key) in keys.iter_mut().enumerate() {
+        data.clear();
+        data.extend_from_slice(seed);
+        data.push(round as u8);
+
+        *key = BigEndian::read_u64(&blake2b(&data).as_bytes()[..HALF_FEISTEL_BYTES]);
+    }
+
+    keys
+}
+
 // Find the minimum number of even bits to represent `num_elements`
 // within a `u32` maximum. Returns the left and right masks evenly
 // distributed that together add up to that minimum number of bits.
@@ -194,6 +216,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_derive_keys() {
+        assert_eq!(derive_keys(&[1, 2, 3]), derive_keys(&[1, 2, 3]));
+        assert_ne!(derive_keys(&[1, 2, 3]), derive_keys(&[1, 2, 4]));
+
+        // Derived keys still give a permutation.
+        let n = 4u64.pow(4);
+        let keys = derive_keys(b"seed");
+        let precomputed = precompute(n);
+        let mut permuted: Vec<Index> = (0..n).map(|i| permute(n, i, &keys, precomputed)).collect();
+        permuted.sort();
+        assert_eq!(permuted, (0..n).collect::<Vec<_>>());
+    }
+
     #[test]
     fn test_feistel_power_of_4() {
         // Our implementation is guaranteed to produce a permutation when input size (number of elements)
@@ -1,4 +1,5 @@
 pub mod aes;
+pub mod domain;
 pub mod feistel;
 pub mod kdf;
 pub mod pedersen;
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
 use ff::PrimeFieldRepr;
 use fil_sapling_crypto::jubjub::JubjubBls12;
 use fil_sapling_crypto::pedersen_hash::{pedersen_hash, Personalization};
@@ -7,7 +10,13 @@ use crate::fr32::bytes_into_frs;
 use crate::settings;
 
 lazy_static! {
-    pub static ref JJ_PARAMS: JubjubBls12 = JubjubBls12::new_with_window_size(
+    /// Parameters generated so far, by window size. They are never dropped: there is one entry
+    /// for each window size ever asked for, usually just the configured one.
+    static ref JJ_PARAMS_BY_WINDOW_SIZE: Mutex<HashMap<u32, &'static JubjubBls12>> =
+        Mutex::new(HashMap::new());
+
+    /// The parameters for the configured `pedersen_hash_exp_window_size`.
+    pub static ref JJ_PARAMS: &'static JubjubBls12 = jubjub_params(
         settings::SETTINGS
             .lock()
             .unwrap()
@@ -15,6 +24,18 @@ lazy_static! {
     );
 }
 
+/// The Pedersen hash parameters with precomputed window tables of `window_size` bits, generated
+/// on first use and shared by all later callers, vanilla and circuit code alike.
+pub fn jubjub_params(window_size: u32) -> &'static JubjubBls12 {
+    // The lock is held while generating, so that concurrent callers wait for the one set of
+    // tables rather than each generating their own.
+    let mut params = JJ_PARAMS_BY_WINDOW_SIZE.lock().unwrap();
+
+    *params
+        .entry(window_size)
+        .or_insert_with(|| Box::leak(Box::new(JubjubBls12::new_with_window_size(window_size))))
+}
+
 pub const PEDERSEN_BLOCK_SIZE: usize = 256;
 pub const PEDERSEN_BLOCK_BYTES: usize = PEDERSEN_BLOCK_SIZE / 8;
 
@@ -23,7 +44,7 @@ pub fn pedersen(data: &[u8]) -> Fr {
 }
 
 pub fn pedersen_bits<'a, S: Iterator<Item = &'a [u8]>>(data: Bits<&'a [u8], S>) -> Fr {
-    pedersen_hash::<Bls12, _>(Personalization::None, data, &JJ_PARAMS)
+    pedersen_hash::<Bls12, _>(Personalization::None, data, *JJ_PARAMS)
         .into_xy()
         .0
 }
@@ -61,7 +82,7 @@ fn pedersen_compression_bits<T>(bits: T) -> FrRepr
 where
     T: IntoIterator<Item = bool>,
 {
-    let (x, _) = pedersen_hash::<Bls12, _>(Personalization::None, bits, &JJ_PARAMS).into_xy();
+    let (x, _) = pedersen_hash::<Bls12, _>(Personalization::None, bits, *JJ_PARAMS).into_xy();
     x.into()
 }
 
@@ -326,4 +347,16 @@ mod tests {
 
         assert_eq!(bits, bits_collected);
     }
+
+    #[test]
+    fn test_jubjub_params_are_shared() {
+        let window_size = settings::SETTINGS
+            .lock()
+            .unwrap()
+            .pedersen_hash_exp_window_size;
+
+        assert!(std::ptr::eq(*JJ_PARAMS, jubjub_params(window_size)));
+        assert!(std::ptr::eq(jubjub_params(8), jubjub_params(8)));
+        assert!(!std::ptr::eq(jubjub_params(8), jubjub_params(9)));
+    }
 }
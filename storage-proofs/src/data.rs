@@ -0,0 +1,210 @@
+use std::fs::OpenOptions;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+use memmap::{MmapMut, MmapOptions};
+
+use crate::error::{Error, Result};
+use crate::util::ensure_writable;
+
+/// The data to be replicated, either borrowed from memory or memory-mapped from a file.
+///
+/// Data backed by a file can be unmapped with `drop_data` while it is not needed, and is mapped
+/// again on the next call to `ensure_data`. This keeps the staged sector from having to stay
+/// resident during the phases of replication which do not touch it.
+#[derive(Debug)]
+pub struct Data<'a> {
+    raw: Option<RawData<'a>>,
+    path: Option<PathBuf>,
+    len: usize,
+}
+
+#[derive(Debug)]
+enum RawData<'a> {
+    Slice(&'a mut [u8]),
+    Mmap(MmapMut),
+}
+
+impl<'a> Deref for RawData<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            RawData::Slice(ref raw) => raw,
+            RawData::Mmap(ref raw) => raw,
+        }
+    }
+}
+
+impl<'a> DerefMut for RawData<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            RawData::Slice(ref mut raw) => raw,
+            RawData::Mmap(ref mut raw) => raw,
+        }
+    }
+}
+
+impl<'a> From<&'a mut [u8]> for Data<'a> {
+    fn from(raw: &'a mut [u8]) -> Self {
+        let len = raw.len();
+
+        Data {
+            raw: Some(RawData::Slice(raw)),
+            path: None,
+            len,
+        }
+    }
+}
+
+impl<'a> From<MmapMut> for Data<'a> {
+    fn from(raw: MmapMut) -> Self {
+        let len = raw.len();
+
+        Data {
+            raw: Some(RawData::Mmap(raw)),
+            path: None,
+            len,
+        }
+    }
+}
+
+impl<'a> From<(MmapMut, PathBuf)> for Data<'a> {
+    fn from(raw: (MmapMut, PathBuf)) -> Self {
+        let len = raw.0.len();
+
+        Data {
+            raw: Some(RawData::Mmap(raw.0)),
+            path: Some(raw.1),
+            len,
+        }
+    }
+}
+
+impl<'a> Data<'a> {
+    /// Creates data backed by the file at `path`, which is only mapped once it is needed.
+    pub fn from_path(path: PathBuf) -> Self {
+        Data {
+            raw: None,
+            path: Some(path),
+            len: 0,
+        }
+    }
+
+    /// Returns the data, which fails unless it was loaded with `ensure_data`.
+    pub fn as_slice(&self) -> Result<&[u8]> {
+        match self.raw {
+            Some(ref raw) => Ok(raw),
+            None => Err(Error::DataNotLoaded),
+        }
+    }
+
+    /// Returns the data mutably, see `as_slice`.
+    pub fn as_mut_slice(&mut self) -> Result<&mut [u8]> {
+        match self.raw {
+            Some(ref mut raw) => Ok(raw),
+            None => Err(Error::DataNotLoaded),
+        }
+    }
+
+    /// Returns the path of the file backing the data, if there is one.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Maps the underlying file, if the data is not currently loaded.
+    pub fn ensure_data(&mut self) -> Result<()> {
+        if self.raw.is_some() {
+            return Ok(());
+        }
+
+        let path = self.path.as_ref().ok_or(Error::MissingData)?;
+
+        // Data is only ever mapped to be replicated in place.
+        ensure_writable(path)?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|err| Error::IoAt(path.clone(), err))?;
+        let mmap = unsafe { MmapOptions::new().map_mut(&file) }
+            .map_err(|err| Error::IoAt(path.clone(), err))?;
+
+        self.len = mmap.len();
+        self.raw = Some(RawData::Mmap(mmap));
+
+        Ok(())
+    }
+
+    /// Flushes and unmaps the data, if it is backed by a file. Data which cannot be reloaded
+    /// afterwards is left untouched.
+    pub fn drop_data(&mut self) -> Result<()> {
+        if self.path.is_none() {
+            return Ok(());
+        }
+
+        if let Some(RawData::Mmap(mmap)) = self.raw.take() {
+            mmap.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the length of the data, as of the last time it was loaded.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    #[test]
+    fn test_data_from_slice() {
+        let mut raw = vec![1u8; 64];
+        let mut data = Data::from(raw.as_mut_slice());
+
+        // Borrowed data can not be reloaded, so dropping it is a no-op.
+        data.drop_data().unwrap();
+        data.ensure_data().unwrap();
+        data.as_mut_slice().unwrap()[0] = 2;
+
+        assert_eq!(data.len(), 64);
+        assert_eq!(&data.as_slice().unwrap()[..2], &[2, 1]);
+    }
+
+    #[test]
+    fn test_data_from_path() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[3u8; 128]).unwrap();
+
+        let mut data = Data::from_path(file.path().to_path_buf());
+        assert!(data.is_empty());
+
+        // Unloaded data can't be accessed.
+        match data.as_slice() {
+            Err(Error::DataNotLoaded) => {}
+            other => panic!("expected unloaded data, got {:?}", other),
+        }
+
+        data.ensure_data().unwrap();
+        assert_eq!(data.len(), 128);
+        assert_eq!(data.as_slice().unwrap(), &[3u8; 128][..]);
+
+        data.as_mut_slice().unwrap()[0] = 4;
+        data.drop_data().unwrap();
+
+        // Changes are written back to the file, and the data is mapped again on demand.
+        data.ensure_data().unwrap();
+        assert_eq!(&data.as_slice().unwrap()[..2], &[4, 3]);
+        assert_eq!(&std::fs::read(file.path()).unwrap()[..2], &[4, 3]);
+    }
+}
@@ -5,7 +5,8 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use serde::de::Deserialize;
 use serde::ser::Serialize;
 
-use crate::drgraph::Graph;
+use crate::data::Data;
+use crate::drgraph::{Graph, GraphConfig};
 use crate::error::Result;
 use crate::fr32::bytes_into_fr_repr_safe;
 use crate::hasher::{Domain, Hasher};
@@ -47,6 +48,9 @@ pub struct DrgParams {
 
     // Random seed
     pub seed: [u32; 7],
+
+    // Construction of the base graph
+    pub graph_config: GraphConfig,
 }
 
 #[derive(Debug, Clone)]
@@ -245,11 +249,12 @@ where
     type Requirements = NoRequirements;
 
     fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
-        let graph = G::new(
+        let graph = G::new_with_config(
             sp.drg.nodes,
             sp.drg.degree,
             sp.drg.expansion_degree,
             sp.drg.seed,
+            &sp.drg.graph_config,
         );
 
         Ok(PublicParams::new(graph, sp.private, sp.challenges_count))
@@ -427,23 +432,27 @@ where
     type Tau = porep::Tau<H::Domain>;
     type ProverAux = porep::ProverAux<H>;
 
-    fn replicate(
+    fn replicate<'b>(
         pp: &Self::PublicParams,
         replica_id: &H::Domain,
-        data: &mut [u8],
+        mut data: Data<'b>,
         data_tree: Option<MerkleTree<H::Domain, H::Function>>,
     ) -> Result<(porep::Tau<H::Domain>, porep::ProverAux<H>)> {
+        data.ensure_data()?;
+
         let tree_d = match data_tree {
             Some(tree) => tree,
-            None => pp.graph.merkle_tree(data)?,
+            None => pp.graph.merkle_tree(data.as_slice()?)?,
         };
 
-        vde::encode(&pp.graph, replica_id, data, None)?;
+        vde::encode(&pp.graph, replica_id, data.as_mut_slice()?, None)?;
 
         let comm_d = tree_d.root();
-        let tree_r = pp.graph.merkle_tree(data)?;
+        let tree_r = pp.graph.merkle_tree(data.as_slice()?)?;
         let comm_r = tree_r.root();
 
+        data.drop_data()?;
+
         Ok((
             porep::Tau::new(comm_d, comm_r),
             porep::ProverAux::new(tree_d, tree_r),
@@ -480,7 +489,7 @@ mod tests {
     use std::io::Write;
     use tempfile;
 
-    use crate::drgraph::{new_seed, BucketGraph, BASE_DEGREE};
+    use crate::drgraph::{new_seed_from, BucketGraph, BASE_DEGREE};
     use crate::fr32::fr_into_bytes;
     use crate::hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher};
     use crate::util::data_at_node;
@@ -511,7 +520,8 @@ mod tests {
                 nodes: data.len() / 32,
                 degree: BASE_DEGREE,
                 expansion_degree: 0,
-                seed: new_seed(),
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
             },
             private: false,
             challenges_count: 1,
@@ -519,7 +529,7 @@ mod tests {
 
         let pp = DrgPoRep::<H, BucketGraph<H>>::setup(&sp).expect("setup failed");
 
-        DrgPoRep::replicate(&pp, &replica_id, &mut mmapped_data_copy, None)
+        DrgPoRep::replicate(&pp, &replica_id, (&mut mmapped_data_copy[..]).into(), None)
             .expect("replication failed");
 
         let mut copied = vec![0; data.len()];
@@ -564,7 +574,8 @@ mod tests {
                 nodes: data.len() / 32,
                 degree: BASE_DEGREE,
                 expansion_degree: 0,
-                seed: new_seed(),
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
             },
             private: false,
             challenges_count: 1,
@@ -572,7 +583,7 @@ mod tests {
 
         let pp = DrgPoRep::<H, BucketGraph<H>>::setup(&sp).expect("setup failed");
 
-        DrgPoRep::replicate(&pp, &replica_id, &mut mmapped_data_copy, None)
+        DrgPoRep::replicate(&pp, &replica_id, (&mut mmapped_data_copy[..]).into(), None)
             .expect("replication failed");
 
         let mut copied = vec![0; data.len()];
@@ -621,7 +632,7 @@ mod tests {
             let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
             let degree = BASE_DEGREE;
             let expansion_degree = 0;
-            let seed = new_seed();
+            let seed = new_seed_from(rng);
 
             let replica_id: H::Domain = rng.gen();
             let data: Vec<u8> = (0..nodes)
@@ -639,6 +650,7 @@ mod tests {
                     degree,
                     expansion_degree,
                     seed,
+                    graph_config: GraphConfig::default(),
                 },
                 private: false,
                 challenges_count: 2,
@@ -646,9 +658,13 @@ mod tests {
 
             let pp = DrgPoRep::<H, BucketGraph<_>>::setup(&sp).expect("setup failed");
 
-            let (tau, aux) =
-                DrgPoRep::<H, _>::replicate(&pp, &replica_id, &mut mmapped_data_copy, None)
-                    .expect("replication failed");
+            let (tau, aux) = DrgPoRep::<H, _>::replicate(
+                &pp,
+                &replica_id,
+                (&mut mmapped_data_copy[..]).into(),
+                None,
+            )
+            .expect("replication failed");
 
             let mut copied = vec![0; data.len()];
             copied.copy_from_slice(&mmapped_data_copy);
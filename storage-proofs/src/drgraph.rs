@@ -1,18 +1,18 @@
 use std::cmp;
+use std::fmt;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use blake2s_simd::Params as Blake2s;
 use rand::{ChaChaRng, OsRng, Rng, SeedableRng};
-use rayon::prelude::*;
 
 use crate::error::*;
 use crate::fr32::bytes_into_fr_repr_safe;
 use crate::hasher::pedersen::PedersenHasher;
 use crate::hasher::{Domain, Hasher};
-use crate::merkle::MerkleTree;
+use crate::merkle::{build_tree, MerkleTree};
 use crate::parameter_cache::ParameterSetMetadata;
 use crate::util::{data_at_node, data_at_node_offset, NODE_SIZE};
-use merkletree::merkle::FromIndexedParallelIterator;
 
 /// The default hasher currently in use.
 pub type DefaultTreeHasher = PedersenHasher;
@@ -62,13 +62,7 @@ pub trait Graph<H: Hasher>: ::std::fmt::Debug + Clone + PartialEq + Eq {
             H::Domain::try_from_bytes(d).expect("failed to convert node data to domain element")
         };
 
-        if parallel {
-            Ok(MerkleTree::from_par_iter(
-                (0..self.size()).into_par_iter().map(f),
-            ))
-        } else {
-            Ok(MerkleTree::new((0..self.size()).map(f)))
-        }
+        build_tree::<H, _>(self.size(), f, parallel)
     }
 
     /// Returns the merkle tree depth.
@@ -93,6 +87,25 @@ pub trait Graph<H: Hasher>: ::std::fmt::Debug + Clone + PartialEq + Eq {
     fn degree(&self) -> usize;
 
     fn new(nodes: usize, base_degree: usize, expansion_degree: usize, seed: [u32; 7]) -> Self;
+
+    /// Creates a graph whose base parents are chosen by the construction in `config`. Graphs
+    /// without pluggable constructions only support the default one.
+    fn new_with_config(
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        seed: [u32; 7],
+        config: &GraphConfig,
+    ) -> Self {
+        assert_eq!(
+            config,
+            &GraphConfig::default(),
+            "graph does not support custom constructions"
+        );
+
+        Self::new(nodes, base_degree, expansion_degree, seed)
+    }
+
     fn seed(&self) -> [u32; 7];
 
     /// Creates the encoding key.
@@ -111,24 +124,81 @@ pub fn graph_height(size: usize) -> usize {
     (size as f64).log2().ceil() as usize
 }
 
-/// Bucket sampling algorithm.
-#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+/// Chooses the base parents of the nodes of a DRG.
+///
+/// Implementations can be plugged into `BucketGraph` through `GraphConfig::Custom`, to try
+/// alternative constructions.
+pub trait ParentSampler: fmt::Debug + Send + Sync {
+    /// Uniquely identifies the construction and its parameters. It is part of the identifiers of
+    /// graphs using it, and thereby of their Groth parameters and parent caches.
+    fn identifier(&self) -> String;
+
+    /// Writes the `degree` parents of `node` into `parents[..degree]`, as `Graph::parents`
+    /// does: every parent precedes `node`, except for the first two nodes, which only have node
+    /// 0 as parent.
+    fn parents(&self, seed: &[u32; 7], degree: usize, node: usize, parents: &mut [usize]);
+}
+
+/// The construction choosing the base parents of a DRG.
+#[derive(Debug, Clone)]
+pub enum GraphConfig {
+    /// Bucket sampling, seeded by ChaCha.
+    Bucket,
+    Custom(Arc<dyn ParentSampler>),
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        GraphConfig::Bucket
+    }
+}
+
+impl GraphConfig {
+    fn identifier(&self) -> Option<String> {
+        match self {
+            GraphConfig::Bucket => None,
+            GraphConfig::Custom(sampler) => Some(sampler.identifier()),
+        }
+    }
+}
+
+// Constructions are equal if they choose the same parents, which their identifiers promise.
+impl PartialEq for GraphConfig {
+    fn eq(&self, other: &GraphConfig) -> bool {
+        self.identifier() == other.identifier()
+    }
+}
+
+impl Eq for GraphConfig {}
+
+/// Bucket sampling algorithm, unless another construction is configured.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BucketGraph<H: Hasher> {
     nodes: usize,
     base_degree: usize,
     seed: [u32; 7],
+    config: GraphConfig,
     _h: PhantomData<H>,
 }
 
 impl<H: Hasher> ParameterSetMetadata for BucketGraph<H> {
     fn identifier(&self) -> String {
         // NOTE: Seed is not included because it does not influence parameter generation.
-        format!(
-            "drgraph::BucketGraph{{size: {}; degree: {}; hasher: {}}}",
-            self.nodes,
-            self.degree(),
-            H::name(),
-        )
+        match self.config.identifier() {
+            None => format!(
+                "drgraph::BucketGraph{{size: {}; degree: {}; hasher: {}}}",
+                self.nodes,
+                self.degree(),
+                H::name(),
+            ),
+            Some(construction) => format!(
+                "drgraph::BucketGraph{{size: {}; degree: {}; hasher: {}; construction: {}}}",
+                self.nodes,
+                self.degree(),
+                H::name(),
+                construction,
+            ),
+        }
     }
 
     fn sector_size(&self) -> u64 {
@@ -136,6 +206,13 @@ impl<H: Hasher> ParameterSetMetadata for BucketGraph<H> {
     }
 }
 
+impl<H: Hasher> BucketGraph<H> {
+    /// The construction choosing the parents.
+    pub fn config(&self) -> &GraphConfig {
+        &self.config
+    }
+}
+
 impl<H: Hasher> Graph<H> for BucketGraph<H> {
     type Key = H::Domain;
 
@@ -166,6 +243,11 @@ impl<H: Hasher> Graph<H> for BucketGraph<H> {
     fn parents(&self, node: usize, parents: &mut [usize]) {
         let m = self.degree();
 
+        if let GraphConfig::Custom(ref sampler) = self.config {
+            sampler.parents(&self.seed, m, node, parents);
+            return;
+        }
+
         match node {
             // There are special cases for the first and second node: the first node self
             // references, the second node only references the first node.
@@ -227,6 +309,22 @@ impl<H: Hasher> Graph<H> for BucketGraph<H> {
     }
 
     fn new(nodes: usize, base_degree: usize, expansion_degree: usize, seed: [u32; 7]) -> Self {
+        Self::new_with_config(
+            nodes,
+            base_degree,
+            expansion_degree,
+            seed,
+            &GraphConfig::default(),
+        )
+    }
+
+    fn new_with_config(
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        seed: [u32; 7],
+        config: &GraphConfig,
+    ) -> Self {
         if !cfg!(feature = "unchecked-degrees") {
             assert_eq!(base_degree, BASE_DEGREE);
         }
@@ -237,13 +335,20 @@ impl<H: Hasher> Graph<H> for BucketGraph<H> {
             nodes,
             base_degree,
             seed,
+            config: config.clone(),
             _h: PhantomData,
         }
     }
 }
 
+/// A random graph seed, from the operating system's rng.
 pub fn new_seed() -> [u32; 7] {
-    OsRng::new().expect("Failed to create `OsRng`").gen()
+    new_seed_from(&mut OsRng::new().expect("Failed to create `OsRng`"))
+}
+
+/// A graph seed drawn from `rng`, so that tests and fuzzing can build reproducible graphs.
+pub fn new_seed_from<R: Rng>(rng: &mut R) -> [u32; 7] {
+    rng.gen()
 }
 
 #[cfg(test)]
@@ -253,8 +358,8 @@ mod tests {
     use memmap::MmapMut;
     use memmap::MmapOptions;
 
-    use crate::drgraph::new_seed;
     use crate::hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher};
+    use crate::test_helper::test_rng;
 
     // Create and return an object of MmapMut backed by in-memory copy of data.
     pub fn mmap_from(data: &[u8]) -> MmapMut {
@@ -270,7 +375,7 @@ mod tests {
         let degree = BASE_DEGREE;
 
         for size in vec![3, 10, 200, 2000] {
-            let g = BucketGraph::<H>::new(size, degree, 0, new_seed());
+            let g = BucketGraph::<H>::new(size, degree, 0, new_seed_from(&mut test_rng()));
 
             assert_eq!(g.size(), size, "wrong nodes count");
 
@@ -319,7 +424,7 @@ mod tests {
     }
 
     fn gen_proof<H: Hasher>(parallel: bool) {
-        let g = BucketGraph::<H>::new(5, BASE_DEGREE, 0, new_seed());
+        let g = BucketGraph::<H>::new(5, BASE_DEGREE, 0, new_seed_from(&mut test_rng()));
         let data = vec![2u8; NODE_SIZE * 5];
 
         let mmapped = &mmap_from(&data);
@@ -346,4 +451,49 @@ mod tests {
         gen_proof::<Blake2sHasher>(true);
         gen_proof::<Blake2sHasher>(false);
     }
+
+    /// Chains every node to its predecessor only.
+    #[derive(Debug)]
+    struct PathSampler;
+
+    impl ParentSampler for PathSampler {
+        fn identifier(&self) -> String {
+            "path".into()
+        }
+
+        fn parents(&self, _seed: &[u32; 7], degree: usize, node: usize, parents: &mut [usize]) {
+            for parent in parents.iter_mut().take(degree) {
+                *parent = node.saturating_sub(1);
+            }
+        }
+    }
+
+    #[test]
+    fn graph_custom_construction() {
+        let seed = new_seed_from(&mut test_rng());
+        let config = GraphConfig::Custom(Arc::new(PathSampler));
+
+        let default = BucketGraph::<PedersenHasher>::new(20, BASE_DEGREE, 0, seed);
+        let custom =
+            BucketGraph::<PedersenHasher>::new_with_config(20, BASE_DEGREE, 0, seed, &config);
+
+        for node in 1..20 {
+            let mut parents = vec![0; BASE_DEGREE];
+            custom.parents(node, &mut parents);
+            assert_eq!(parents, vec![node - 1; BASE_DEGREE]);
+        }
+
+        assert_ne!(default, custom);
+        assert_ne!(default.identifier(), custom.identifier());
+        assert_eq!(
+            default,
+            BucketGraph::<PedersenHasher>::new_with_config(
+                20,
+                BASE_DEGREE,
+                0,
+                seed,
+                &GraphConfig::Bucket
+            )
+        );
+    }
 }
@@ -0,0 +1,592 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use byteorder::{ByteOrder, LittleEndian};
+use ff::PrimeField;
+use paired::bls12_381::{Bls12, Fr, FrRepr};
+use rayon::prelude::*;
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+
+use crate::challenge_hash::{
+    ELECTION_POST_LEAF_CHALLENGE_HASH, ELECTION_POST_SECTOR_CHALLENGE_HASH,
+};
+use crate::crypto::domain::HashDomain;
+use crate::crypto::pedersen::pedersen_md_no_padding;
+use crate::drgraph::graph_height;
+use crate::error::{Error, Result};
+use crate::fr32::{bytes_into_fr_repr_safe, fr_into_bytes};
+use crate::hasher::{Domain, Hasher};
+use crate::merkle::{MerkleProof, MerkleTree};
+use crate::parameter_cache::ParameterSetMetadata;
+use crate::proof::{NoRequirements, ProofScheme};
+use crate::sector::*;
+use crate::stacked::hash::hash2;
+use crate::util::NODE_SIZE;
+
+#[derive(Debug, Clone)]
+pub struct SetupParams {
+    /// The size of a sector.
+    pub sector_size: u64,
+    /// How many ranges of a sector are challenged per candidate.
+    pub challenge_count: usize,
+    /// How many consecutive nodes each challenge opens.
+    pub challenged_nodes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PublicParams {
+    /// The size of a sector.
+    pub sector_size: u64,
+    /// How many ranges of a sector are challenged per candidate.
+    pub challenge_count: usize,
+    /// How many consecutive nodes each challenge opens.
+    pub challenged_nodes: usize,
+}
+
+impl ParameterSetMetadata for PublicParams {
+    fn identifier(&self) -> String {
+        format!(
+            "ElectionPoSt::PublicParams{{sector_size: {} challenge_count: {} challenged_nodes: {}}}",
+            self.sector_size(),
+            self.challenge_count,
+            self.challenged_nodes,
+        )
+    }
+
+    fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+}
+
+impl PublicParams {
+    /// The number of leaves opened by a single proof.
+    pub fn leaves_count(&self) -> usize {
+        self.challenge_count * self.challenged_nodes
+    }
+
+    /// The parameters as pretty printed JSON, with the fields in kebab case.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(Into::into)
+    }
+
+    /// Parses parameters written by `to_json`, rejecting any `setup` would not return, and
+    /// missing or unknown fields.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let pub_params: PublicParams = serde_json::from_str(json)?;
+        check_params(
+            pub_params.sector_size,
+            pub_params.challenge_count,
+            pub_params.challenged_nodes,
+        )?;
+
+        Ok(pub_params)
+    }
+}
+
+fn check_params(sector_size: u64, challenge_count: usize, challenged_nodes: usize) -> Result<()> {
+    if challenge_count == 0 || challenged_nodes == 0 {
+        return Err(format_err!("challenge_count and challenged_nodes must not be zero").into());
+    }
+    if sector_size % (challenged_nodes * NODE_SIZE) as u64 != 0 {
+        return Err(format_err!(
+            "sector size {} is not a multiple of the challenged range",
+            sector_size
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicInputs<T: Domain> {
+    pub randomness: [u8; 32],
+    pub sector_id: SectorId,
+    pub prover_id: [u8; 32],
+    pub comm_r: T,
+    pub partial_ticket: Fr,
+    pub sector_challenge_index: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrivateInputs<'a, H: 'a + Hasher> {
+    pub tree: &'a MerkleTree<H::Domain, H::Function>,
+    pub comm_c: H::Domain,
+    pub comm_r_last: H::Domain,
+}
+
+/// A sector which took part in an election, together with its ticket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub sector_id: SectorId,
+    pub partial_ticket: Fr,
+    pub ticket: [u8; 32],
+    pub sector_challenge_index: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proof<H: Hasher> {
+    #[serde(bound(
+        serialize = "MerkleProof<H>: Serialize",
+        deserialize = "MerkleProof<H>: Deserialize<'de>"
+    ))]
+    inclusion_proofs: Vec<MerkleProof<H>>,
+    pub ticket: [u8; 32],
+    pub comm_c: H::Domain,
+}
+
+impl<H: Hasher> Proof<H> {
+    pub fn leafs(&self) -> Vec<&H::Domain> {
+        self.inclusion_proofs
+            .iter()
+            .map(MerkleProof::leaf)
+            .collect()
+    }
+
+    pub fn comm_r_last(&self) -> &H::Domain {
+        self.inclusion_proofs[0].root()
+    }
+
+    pub fn paths(&self) -> Vec<&Vec<(H::Domain, bool)>> {
+        self.inclusion_proofs
+            .iter()
+            .map(MerkleProof::path)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ElectionPoSt<'a, H>
+where
+    H: 'a + Hasher,
+{
+    _h: PhantomData<&'a H>,
+}
+
+impl<'a, H: 'a + Hasher> ProofScheme<'a> for ElectionPoSt<'a, H> {
+    type PublicParams = PublicParams;
+    type SetupParams = SetupParams;
+    type PublicInputs = PublicInputs<H::Domain>;
+    type PrivateInputs = PrivateInputs<'a, H>;
+    type Proof = Proof<H>;
+    type Requirements = NoRequirements;
+
+    fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+        check_params(sp.sector_size, sp.challenge_count, sp.challenged_nodes)?;
+
+        Ok(PublicParams {
+            sector_size: sp.sector_size,
+            challenge_count: sp.challenge_count,
+            challenged_nodes: sp.challenged_nodes,
+        })
+    }
+
+    fn prove<'b>(
+        pub_params: &'b Self::PublicParams,
+        pub_inputs: &'b Self::PublicInputs,
+        priv_inputs: &'b Self::PrivateInputs,
+    ) -> Result<Self::Proof> {
+        if priv_inputs.comm_r_last != priv_inputs.tree.root() {
+            return Err(Error::InvalidCommitment("comm_r_last"));
+        }
+
+        let inclusion_proofs = (0..pub_params.challenge_count)
+            .flat_map(|n| {
+                let challenge = generate_leaf_challenge(
+                    pub_params,
+                    &pub_inputs.randomness,
+                    pub_inputs.sector_challenge_index,
+                    n as u64,
+                );
+
+                (0..pub_params.challenged_nodes).map(move |i| challenge as usize + i)
+            })
+            .map(|challenged_leaf| {
+                MerkleProof::new_from_proof(&priv_inputs.tree.gen_proof(challenged_leaf))
+            })
+            .collect();
+
+        Ok(Proof {
+            inclusion_proofs,
+            ticket: finalize_ticket(&pub_inputs.partial_ticket),
+            comm_c: priv_inputs.comm_c,
+        })
+    }
+
+    fn verify(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        proof: &Self::Proof,
+    ) -> Result<bool> {
+        if proof.inclusion_proofs.len() != pub_params.leaves_count() {
+            return Err(Error::MalformedInput);
+        }
+
+        // verify that H(Comm_c || Comm_r_last) == Comm_R
+        // comm_r_last is the root of the proofs
+        let comm_r_last = proof.comm_r_last();
+        if AsRef::<[u8]>::as_ref(&hash2(&proof.comm_c, comm_r_last))
+            != AsRef::<[u8]>::as_ref(&pub_inputs.comm_r)
+        {
+            return Ok(false);
+        }
+
+        let height = graph_height(pub_params.sector_size as usize / NODE_SIZE);
+        for n in 0..pub_params.challenge_count {
+            let challenge = generate_leaf_challenge(
+                pub_params,
+                &pub_inputs.randomness,
+                pub_inputs.sector_challenge_index,
+                n as u64,
+            );
+
+            for i in 0..pub_params.challenged_nodes {
+                let merkle_proof = &proof.inclusion_proofs[n * pub_params.challenged_nodes + i];
+
+                // all proofs have to be against the same tree
+                if merkle_proof.root() != comm_r_last {
+                    return Ok(false);
+                }
+
+                // validate the path length
+                if merkle_proof.path().len() != height {
+                    return Ok(false);
+                }
+
+                if !merkle_proof.validate(challenge as usize + i) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        // verify the partial ticket is derived from the challenged leafs
+        let leafs: Vec<Fr> = proof.leafs().into_iter().map(|l| (*l).into()).collect();
+        let expected_partial_ticket = partial_ticket(
+            &pub_inputs.randomness,
+            &pub_inputs.prover_id,
+            pub_inputs.sector_id,
+            &leafs,
+        );
+        if expected_partial_ticket != pub_inputs.partial_ticket {
+            return Ok(false);
+        }
+
+        Ok(proof.ticket == finalize_ticket(&pub_inputs.partial_ticket))
+    }
+}
+
+/// Derives the sectors taking part in an election from `randomness`. The same sector may be
+/// drawn more than once, each draw is identified by its index in the returned list.
+pub fn generate_sector_challenges(
+    randomness: &[u8; 32],
+    challenge_count: usize,
+    sectors: &OrderedSectorSet,
+) -> Result<Vec<SectorId>> {
+    if sectors.is_empty() {
+        return Err(format_err!("no sectors to challenge").into());
+    }
+
+    Ok((0..challenge_count as u64)
+        .map(|n| {
+            let hash = ELECTION_POST_SECTOR_CHALLENGE_HASH.hash(&[randomness, &n.to_le_bytes()]);
+
+            let sector_index = (LittleEndian::read_u64(&hash[..8]) % sectors.len() as u64) as usize;
+            *sectors
+                .iter()
+                .nth(sector_index)
+                .expect("invalid challenge generated")
+        })
+        .collect())
+}
+
+/// Generates a candidate for every challenged sector, by hashing the challenged leafs of its tree
+/// into a partial ticket.
+pub fn generate_candidates<H: Hasher>(
+    pub_params: &PublicParams,
+    challenged_sectors: &[SectorId],
+    trees: &BTreeMap<SectorId, MerkleTree<H::Domain, H::Function>>,
+    prover_id: &[u8; 32],
+    randomness: &[u8; 32],
+) -> Result<Vec<Candidate>> {
+    challenged_sectors
+        .par_iter()
+        .enumerate()
+        .map(|(sector_challenge_index, sector_id)| {
+            let tree = trees
+                .get(sector_id)
+                .ok_or_else(|| Error::from(format_err!("missing tree for {}", sector_id)))?;
+
+            Ok(generate_candidate::<H>(
+                pub_params,
+                tree,
+                prover_id,
+                *sector_id,
+                randomness,
+                sector_challenge_index as u64,
+            ))
+        })
+        .collect()
+}
+
+fn generate_candidate<H: Hasher>(
+    pub_params: &PublicParams,
+    tree: &MerkleTree<H::Domain, H::Function>,
+    prover_id: &[u8; 32],
+    sector_id: SectorId,
+    randomness: &[u8; 32],
+    sector_challenge_index: u64,
+) -> Candidate {
+    let leafs: Vec<Fr> = (0..pub_params.challenge_count)
+        .flat_map(|n| {
+            let challenge =
+                generate_leaf_challenge(pub_params, randomness, sector_challenge_index, n as u64);
+
+            (0..pub_params.challenged_nodes)
+                .map(move |i| tree.read_at(challenge as usize + i).into())
+        })
+        .collect();
+
+    let partial_ticket = partial_ticket(randomness, prover_id, sector_id, &leafs);
+
+    Candidate {
+        sector_id,
+        partial_ticket,
+        ticket: finalize_ticket(&partial_ticket),
+        sector_challenge_index,
+    }
+}
+
+/// Returns the first leaf of the `leaf_challenge_index`-th range challenged in a sector.
+pub fn generate_leaf_challenge(
+    pub_params: &PublicParams,
+    randomness: &[u8; 32],
+    sector_challenge_index: u64,
+    leaf_challenge_index: u64,
+) -> u64 {
+    let hash = ELECTION_POST_LEAF_CHALLENGE_HASH.hash(&[
+        randomness,
+        &sector_challenge_index.to_le_bytes(),
+        &leaf_challenge_index.to_le_bytes(),
+    ]);
+
+    let challenged_nodes = pub_params.challenged_nodes as u64;
+    let ranges = pub_params.sector_size / (challenged_nodes * NODE_SIZE as u64);
+
+    (LittleEndian::read_u64(&hash[..8]) % ranges) * challenged_nodes
+}
+
+/// The field elements hashed into a partial ticket, before the challenged leafs.
+pub fn partial_ticket_prefix(
+    randomness: &[u8; 32],
+    prover_id: &[u8; 32],
+    sector_id: SectorId,
+) -> [Fr; 3] {
+    let to_fr = |bytes: &[u8]| {
+        Fr::from_repr(bytes_into_fr_repr_safe(bytes)).expect("truncated bytes are a field element")
+    };
+
+    [
+        to_fr(randomness),
+        to_fr(prover_id),
+        Fr::from_repr(FrRepr::from(u64::from(sector_id))).expect("u64 is a field element"),
+    ]
+}
+
+/// The Pedersen Merkle-Damgard hash of `randomness || prover_id || sector_id || leafs`, which the
+/// circuit recomputes. `finalize_ticket` turns it into the ticket.
+pub fn partial_ticket(
+    randomness: &[u8; 32],
+    prover_id: &[u8; 32],
+    sector_id: SectorId,
+    leafs: &[Fr],
+) -> Fr {
+    let data: Vec<u8> = partial_ticket_prefix(randomness, prover_id, sector_id)
+        .iter()
+        .chain(leafs)
+        .flat_map(fr_into_bytes::<Bls12>)
+        .collect();
+
+    pedersen_md_no_padding(&data)
+}
+
+/// The ticket used in the election, derived from the partial ticket by Blake2s, personalized with
+/// `HashDomain::Ticket`.
+pub fn finalize_ticket(partial_ticket: &Fr) -> [u8; 32] {
+    let hash = HashDomain::Ticket
+        .blake2s()
+        .hash(&fr_into_bytes::<Bls12>(partial_ticket));
+
+    let mut ticket = [0; 32];
+    ticket.copy_from_slice(hash.as_bytes());
+    ticket
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
+    use crate::hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher};
+
+    #[test]
+    fn test_public_params_json() {
+        let pub_params = ElectionPoSt::<PedersenHasher>::setup(&SetupParams {
+            sector_size: 1024,
+            challenge_count: 4,
+            challenged_nodes: 2,
+        })
+        .expect("setup failed");
+
+        let json = pub_params.to_json().unwrap();
+        assert!(json.contains("\"challenged-nodes\": 2"));
+        assert_eq!(PublicParams::from_json(&json).unwrap(), pub_params);
+
+        // Parameters `setup` rejects, and unknown or missing fields.
+        let invalid = [
+            r#"{"sector-size": 1024, "challenge-count": 4, "challenged-nodes": 3}"#,
+            r#"{"sector-size": 1024, "challenge-count": 0, "challenged-nodes": 2}"#,
+            r#"{"sector-size": 1024, "challenge-count": 4, "challenged-nodes": 2, "x": 1}"#,
+            r#"{"sector-size": 1024, "challenge-count": 4}"#,
+        ];
+        for json in invalid.iter() {
+            assert!(PublicParams::from_json(json).is_err(), "{}", json);
+        }
+    }
+
+    fn test_election_post<H: Hasher>() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 32;
+        let sector_size = leaves * 32;
+
+        let pub_params = ElectionPoSt::<H>::setup(&SetupParams {
+            sector_size,
+            challenge_count: 4,
+            challenged_nodes: 2,
+        })
+        .expect("setup failed");
+
+        let randomness: [u8; 32] = rng.gen();
+        let prover_id: [u8; 32] = rng.gen();
+
+        let mut sectors = OrderedSectorSet::new();
+        let mut trees = BTreeMap::new();
+        for i in 0..5 {
+            sectors.insert(i.into());
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+
+            let graph = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
+            trees.insert(i.into(), graph.merkle_tree(data.as_slice()).unwrap());
+        }
+
+        let challenged_sectors =
+            generate_sector_challenges(&randomness, 3, &sectors).expect("failed to challenge");
+        assert_eq!(challenged_sectors.len(), 3);
+
+        let candidates = generate_candidates::<H>(
+            &pub_params,
+            &challenged_sectors,
+            &trees,
+            &prover_id,
+            &randomness,
+        )
+        .expect("failed to generate candidates");
+
+        for candidate in &candidates {
+            let tree = &trees[&candidate.sector_id];
+            let comm_c: H::Domain = rng.gen();
+            let comm_r_last = tree.root();
+            let comm_r: H::Domain = Fr::from(hash2(&comm_c, &comm_r_last)).into();
+
+            let pub_inputs = PublicInputs {
+                randomness,
+                sector_id: candidate.sector_id,
+                prover_id,
+                comm_r,
+                partial_ticket: candidate.partial_ticket,
+                sector_challenge_index: candidate.sector_challenge_index,
+            };
+
+            let priv_inputs = PrivateInputs::<H> {
+                tree,
+                comm_c,
+                comm_r_last,
+            };
+
+            let proof = ElectionPoSt::<H>::prove(&pub_params, &pub_inputs, &priv_inputs)
+                .expect("proving failed");
+            assert_eq!(proof.ticket, candidate.ticket);
+
+            let is_valid = ElectionPoSt::<H>::verify(&pub_params, &pub_inputs, &proof)
+                .expect("verification failed");
+            assert!(is_valid);
+
+            // A proof for another prover must not verify.
+            let wrong_inputs = PublicInputs {
+                prover_id: rng.gen(),
+                ..pub_inputs.clone()
+            };
+            assert!(!ElectionPoSt::<H>::verify(&pub_params, &wrong_inputs, &proof).unwrap());
+
+            // Neither must one for another sector challenge.
+            let wrong_inputs = PublicInputs {
+                sector_challenge_index: candidate.sector_challenge_index + 1,
+                ..pub_inputs
+            };
+            assert!(!ElectionPoSt::<H>::verify(&pub_params, &wrong_inputs, &proof).unwrap());
+        }
+    }
+
+    #[test]
+    fn election_post_pedersen() {
+        test_election_post::<PedersenHasher>();
+    }
+
+    #[test]
+    fn election_post_sha256() {
+        test_election_post::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn election_post_blake2s() {
+        test_election_post::<Blake2sHasher>();
+    }
+
+    #[test]
+    fn test_generate_sector_challenges() {
+        let mut sectors = OrderedSectorSet::new();
+        assert!(generate_sector_challenges(&[0; 32], 10, &sectors).is_err());
+
+        sectors.insert(5.into());
+        sectors.insert(7.into());
+
+        let challenges = generate_sector_challenges(&[1; 32], 10, &sectors).unwrap();
+        assert_eq!(challenges.len(), 10);
+        assert!(challenges.iter().all(|s| sectors.contains(s)));
+        assert_eq!(
+            challenges,
+            generate_sector_challenges(&[1; 32], 10, &sectors).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_generate_leaf_challenge_in_range() {
+        let pub_params = PublicParams {
+            sector_size: 64 * 32,
+            challenge_count: 1,
+            challenged_nodes: 4,
+        };
+
+        for n in 0..100 {
+            let challenge = generate_leaf_challenge(&pub_params, &[3; 32], 1, n);
+            assert_eq!(challenge % 4, 0);
+            assert!(challenge + 4 <= 64);
+        }
+    }
+}
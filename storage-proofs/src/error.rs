@@ -1,4 +1,5 @@
 use std::any::Any;
+use std::path::PathBuf;
 
 use bellperson::SynthesisError;
 
@@ -20,12 +21,16 @@ pub enum Error {
         _0, _1, _2
     )]
     InvalidMerkleTreeArgs(usize, usize, usize),
+    #[fail(display = "invalid node size {}: {}", _0, _1)]
+    InvalidNodeSize(usize, String),
     #[fail(display = "{}", _0)]
     Synthesis(#[cause] SynthesisError),
     #[fail(display = "{}", _0)]
     Io(#[cause] ::std::io::Error),
-    #[fail(display = "tree root and commitment do not match")]
-    InvalidCommitment,
+    #[fail(display = "{:?}: {}", _0, _1)]
+    IoAt(PathBuf, #[cause] ::std::io::Error),
+    #[fail(display = "{} does not match the root of its tree", _0)]
+    InvalidCommitment(&'static str),
     #[fail(display = "malformed input")]
     MalformedInput,
     #[fail(display = "malformed merkle tree")]
@@ -38,10 +43,66 @@ pub enum Error {
     UnalignedPiece,
     #[fail(display = "{}", _0)]
     Serde(#[cause] serde_json::error::Error),
-    #[fail(display = "unclassified error: {}", _0)]
-    Unclassified(String),
+    #[fail(display = "{}", _0)]
+    Bincode(#[cause] bincode::Error),
+    #[fail(display = "unsupported serialization version {}", _0)]
+    UnsupportedSerializationVersion(u32),
+    #[fail(display = "parameter file {} does not match its expected digest", _0)]
+    ParameterDigestMismatch(String),
+    #[fail(
+        display = "circuit {} does not match the digest recorded in its cached metadata",
+        _0
+    )]
+    CircuitChanged(String),
+    #[fail(display = "invalid parent cache: {}", _0)]
+    InvalidParentCache(String),
+    #[fail(display = "invalid graph: {}", _0)]
+    InvalidGraph(String),
+    #[fail(
+        display = "read-only mode is enabled, but {} would have to be written",
+        _0
+    )]
+    ReadOnly(String),
+    #[fail(display = "invalid {} setting: {}", _0, _1)]
+    InvalidSetting(&'static str, String),
+    #[fail(display = "NUMA placement failed: {}", _0)]
+    Numa(String),
+    #[fail(display = "core binding failed: {}", _0)]
+    CoreBinding(String),
+    #[fail(display = "data is neither loaded nor backed by a file")]
+    MissingData,
+    #[fail(display = "data must be loaded before it can be accessed")]
+    DataNotLoaded,
+    #[fail(
+        display = "insufficient data: {} bytes, at least {} are needed",
+        _0, _1
+    )]
+    InsufficientData(usize, usize),
+    #[fail(display = "parameter cache path {:?} has no parent directory", _0)]
+    InvalidParameterCachePath(PathBuf),
+    #[fail(
+        display = "about {} bytes of memory are needed, but only {} are available (set force_low_memory to try anyway)",
+        _0, _1
+    )]
+    InsufficientMemory(u64, u64),
+    #[fail(display = "invalid proof length {}, expected {}", _0, _1)]
+    InvalidProofLength(usize, usize),
+    #[fail(display = "invalid partition count {}", _0)]
+    InvalidPartitionCount(usize),
+    #[fail(display = "invalid verifier export: {}", _0)]
+    InvalidVerifierExport(String),
+    #[fail(display = "invalid public parameters: {}", _0)]
+    InvalidPublicParams(String),
+    #[fail(display = "invalid replica {:?}: {}", _0, _1)]
+    InvalidReplica(PathBuf, String),
+    #[fail(display = "the operation was cancelled")]
+    Cancelled,
+    #[fail(display = "a thread panicked: {}", _0)]
+    Panic(String),
     #[fail(display = "{}", _0)]
     Failure(failure::Error),
+    #[fail(display = "unclassified error: {}", _0)]
+    Unclassified(String),
 }
 
 impl From<SynthesisError> for Error {
@@ -62,9 +123,24 @@ impl From<serde_json::error::Error> for Error {
     }
 }
 
+impl From<bincode::Error> for Error {
+    fn from(inner: bincode::Error) -> Error {
+        Error::Bincode(inner)
+    }
+}
+
 impl From<Box<dyn Any + Send>> for Error {
     fn from(inner: Box<dyn Any + Send>) -> Error {
-        Error::Unclassified(format!("{:?}", dbg!(inner)))
+        // Panics carry their message as either a `&str` or a `String`.
+        let message = match inner.downcast::<String>() {
+            Ok(message) => *message,
+            Err(inner) => match inner.downcast::<&'static str>() {
+                Ok(message) => (*message).to_string(),
+                Err(inner) => format!("{:?}", inner),
+            },
+        };
+
+        Error::Panic(message)
     }
 }
 
@@ -0,0 +1,92 @@
+//! Access to the GPU used for Groth16 proving.
+//!
+//! With the `gpu` feature, bellperson computes the FFTs and multiexps of circuit proofs on the
+//! GPU. A single proof uses most of the device memory, so proofs take turns on the device
+//! selected by `get_gpu_index`, across threads and processes, see `gpu_lock`. If a proof fails on
+//! the GPU, e.g. because the device ran out of memory, it is retried on the CPU.
+//!
+//! bellperson offers no way to choose a device, it uses the ones the driver exposes. To prove on
+//! several devices in parallel, expose a different device to each process (e.g. through
+//! `CUDA_VISIBLE_DEVICES`) and set `FIL_PROOFS_GPU_INDEX` accordingly, so they don't wait for
+//! each other's locks.
+
+use std::env;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::error::Result;
+use crate::gpu_lock::{self, Priority};
+use crate::measurements::{self, Operation};
+use crate::settings;
+
+/// Whether circuit proofs are computed on the GPU.
+pub const GPU_ENABLED: bool = cfg!(feature = "gpu");
+
+/// bellperson skips its GPU kernels while this variable is set.
+const BELLMAN_NO_GPU: &str = "BELLMAN_NO_GPU";
+
+lazy_static! {
+    /// The number of proofs currently falling back to the CPU.
+    static ref CPU_FALLBACKS: Mutex<usize> = Mutex::new(0);
+}
+
+/// Returns the index of the GPU used for proving, `FIL_PROOFS_GPU_INDEX`.
+pub fn get_gpu_index() -> usize {
+    settings::SETTINGS.lock().unwrap().gpu_index
+}
+
+/// Runs `prove` while holding the lock of the selected GPU at the given priority, see
+/// `gpu_lock`. If it fails, it is run again on the CPU. Without the `gpu` feature, `prove` simply
+/// runs once.
+pub fn with_gpu<T, F>(priority: Priority, mut prove: F) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+{
+    if !GPU_ENABLED {
+        return prove();
+    }
+
+    let index = get_gpu_index();
+    let result = {
+        let start = Instant::now();
+        let _lock = gpu_lock::acquire(priority)?;
+        measurements::record(Operation::GpuWait, None, start.elapsed(), 0);
+
+        prove()
+    };
+
+    result.or_else(|err| {
+        warn!("proving on GPU {} failed, retrying on CPU: {}", index, err);
+
+        let _cpu = CpuFallback::new();
+        prove()
+    })
+}
+
+/// Disables the GPU kernels of bellperson while alive.
+///
+/// The switch is process wide, so proofs started concurrently run on the CPU as well, until the
+/// last fallback ends.
+struct CpuFallback;
+
+impl CpuFallback {
+    fn new() -> Self {
+        let mut count = CPU_FALLBACKS.lock().unwrap();
+        if *count == 0 {
+            env::set_var(BELLMAN_NO_GPU, "1");
+        }
+        *count += 1;
+
+        CpuFallback
+    }
+}
+
+impl Drop for CpuFallback {
+    fn drop(&mut self) {
+        let mut count = CPU_FALLBACKS.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            env::remove_var(BELLMAN_NO_GPU);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Cross-process locks for the proving GPU, with priorities.
+//!
+//! Every piece of GPU work holds the device lock (`filecoin-proofs-gpu-<index>.lock` in the
+//! temporary directory) while it runs. `Priority::High` work, such as PoSt proving which has a
+//! deadline, additionally holds a shared priority lock from before it starts waiting until it is
+//! done. `Priority::Low` work, such as sealing, only takes the device while nobody holds the
+//! priority lock, so high priority work never queues behind more than the unit of low priority
+//! work currently running. Long running low priority work can poll `GpuLock::should_yield`
+//! between units to hand over the device even earlier.
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use fs2::FileExt;
+
+use crate::error::Result;
+use crate::gpu::get_gpu_index;
+
+/// How long low priority work waits before retrying a busy device.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    High,
+}
+
+/// Returns the path of the lock file guarding the GPU with the given index.
+pub fn gpu_lock_path(index: usize) -> PathBuf {
+    env::temp_dir().join(format!("filecoin-proofs-gpu-{}.lock", index))
+}
+
+/// Returns the path of the lock file held by high priority work for the GPU with the given index.
+pub fn priority_lock_path(index: usize) -> PathBuf {
+    env::temp_dir().join(format!("filecoin-proofs-gpu-{}.priority.lock", index))
+}
+
+/// Blocks until the selected GPU (see `get_gpu_index`) is available at the given priority.
+pub fn acquire(priority: Priority) -> Result<GpuLock> {
+    GpuLock::acquire(get_gpu_index(), priority)
+}
+
+/// Exclusive access to a GPU, released on drop.
+#[derive(Debug)]
+pub struct GpuLock {
+    index: usize,
+    priority: Priority,
+    device: File,
+    // Held shared for as long as high priority work holds the device.
+    _priority: Option<File>,
+}
+
+impl GpuLock {
+    /// Blocks until the GPU with the given index is available at the given priority.
+    pub fn acquire(index: usize, priority: Priority) -> Result<Self> {
+        let device = open_lock_file(gpu_lock_path(index))?;
+        let priority_file = open_lock_file(priority_lock_path(index))?;
+
+        trace!("waiting for GPU {} at {:?} priority", index, priority);
+        let priority_lock = match priority {
+            Priority::High => {
+                // Announce the wait, so that no new low priority work starts.
+                priority_file.lock_shared()?;
+                device.lock_exclusive()?;
+                Some(priority_file)
+            }
+            Priority::Low => {
+                loop {
+                    // Only succeeds while no high priority work waits or runs.
+                    priority_file.lock_exclusive()?;
+                    let acquired = device.try_lock_exclusive().is_ok();
+                    priority_file.unlock()?;
+
+                    if acquired {
+                        break;
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                None
+            }
+        };
+        trace!("acquired GPU {} at {:?} priority", index, priority);
+
+        Ok(GpuLock {
+            index,
+            priority,
+            device,
+            _priority: priority_lock,
+        })
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Returns true if this is low priority work and high priority work is waiting for the GPU.
+    pub fn should_yield(&self) -> bool {
+        if self.priority == Priority::High {
+            return false;
+        }
+
+        match open_lock_file(priority_lock_path(self.index)) {
+            Ok(file) => match file.try_lock_exclusive() {
+                Ok(()) => {
+                    let _ = file.unlock();
+                    false
+                }
+                Err(_) => true,
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+impl Drop for GpuLock {
+    fn drop(&mut self) {
+        if let Err(err) = self.device.unlock() {
+            warn!("failed to release GPU lock: {}", err);
+        }
+    }
+}
+
+fn open_lock_file(path: PathBuf) -> Result<File> {
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_lock_is_exclusive() {
+        let index = 1 << 20;
+        let lock = GpuLock::acquire(index, Priority::Low).unwrap();
+        assert!(!lock.should_yield());
+
+        let other = open_lock_file(gpu_lock_path(index)).unwrap();
+        assert!(other.try_lock_exclusive().is_err());
+
+        drop(lock);
+        other.try_lock_exclusive().unwrap();
+        other.unlock().unwrap();
+    }
+
+    #[test]
+    fn test_low_priority_yields_to_high_priority() {
+        let index = (1 << 20) + 1;
+        let low = GpuLock::acquire(index, Priority::Low).unwrap();
+
+        let high = thread::spawn(move || {
+            let high = GpuLock::acquire(index, Priority::High).unwrap();
+            assert!(!high.should_yield());
+        });
+
+        while !low.should_yield() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(low);
+        high.join().unwrap();
+
+        // Once the high priority work is done, low priority work runs again.
+        let low = GpuLock::acquire(index, Priority::Low).unwrap();
+        assert!(!low.should_yield());
+    }
+}
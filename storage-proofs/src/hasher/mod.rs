@@ -1,6 +1,8 @@
 pub mod blake2s;
 pub mod pedersen;
 pub mod sha256;
+#[cfg(any(test, feature = "test-hasher"))]
+pub mod test_hasher;
 
 mod digest;
 mod types;
@@ -11,3 +13,5 @@ pub use self::types::{Domain, HashFunction, Hasher};
 pub use self::blake2s::Blake2sHasher;
 pub use self::pedersen::PedersenHasher;
 pub use self::sha256::Sha256Hasher;
+#[cfg(any(test, feature = "test-hasher"))]
+pub use self::test_hasher::TestHasher;
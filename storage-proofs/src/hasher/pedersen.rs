@@ -263,7 +263,7 @@ impl LightAlgorithm<PedersenDomain> for PedersenFunction {
     ) -> PedersenDomain {
         let node_bits = NodeBits::new(&(left.0).0[..], &(right.0).0[..]);
 
-        pedersen_hash::<Bls12, _>(Personalization::None, node_bits, &pedersen::JJ_PARAMS)
+        pedersen_hash::<Bls12, _>(Personalization::None, node_bits, *pedersen::JJ_PARAMS)
             .into_xy()
             .0
             .into()
@@ -0,0 +1,346 @@
+//! A fast, **insecure** hasher for tests.
+//!
+//! `TestHasher` replaces Pedersen with a handful of rounds of `x -> (x + right + c)^3` over `Fr`,
+//! which costs eight constraints per compression in a circuit. It is deterministic and consistent
+//! between the vanilla and the circuit implementation, so whole seal and PoSt flows can be run
+//! with it, but it offers no collision resistance whatsoever. It is only compiled for this crate's
+//! tests and with the `test-hasher` feature, and must never be used for real proofs.
+//!
+//! Elements are plain field elements, so `PedersenDomain` is reused as the domain.
+
+use std::hash::Hasher as StdHasher;
+
+use bellperson::{ConstraintSystem, LinearCombination, SynthesisError};
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use fil_sapling_crypto::circuit::{boolean, num};
+use fil_sapling_crypto::jubjub::JubjubEngine;
+use merkletree::hash::{Algorithm as LightAlgorithm, Hashable};
+use paired::bls12_381::{Bls12, Fr, FrRepr};
+use paired::Engine;
+
+use crate::crypto::{kdf, sloth};
+use crate::hasher::pedersen::PedersenDomain;
+use crate::hasher::{HashFunction, Hasher};
+
+/// Additive round constants; their number is the number of rounds per compression.
+const ROUND_CONSTANTS: [u64; 4] = [0x3dbe_6259, 0x8d31_3d76, 0x3237_db17, 0xe5bc_0654];
+
+/// Bytes absorbed per compression by `TestFunction::hash`, so every chunk fits into `Fr`.
+const CHUNK_BYTES: usize = 31;
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TestHasher {}
+
+impl Hasher for TestHasher {
+    type Domain = PedersenDomain;
+    type Function = TestFunction;
+
+    fn name() -> String {
+        "TestHasher".into()
+    }
+
+    fn kdf(data: &[u8], m: usize) -> Self::Domain {
+        kdf::kdf(data, m).into()
+    }
+
+    #[inline]
+    fn sloth_encode(key: &Self::Domain, ciphertext: &Self::Domain) -> Self::Domain {
+        sloth::encode::<Bls12>(&(*key).into(), &(*ciphertext).into()).into()
+    }
+
+    #[inline]
+    fn sloth_decode(key: &Self::Domain, ciphertext: &Self::Domain) -> Self::Domain {
+        sloth::decode::<Bls12>(&(*key).into(), &(*ciphertext).into()).into()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TestFunction(Fr);
+
+impl Default for TestFunction {
+    fn default() -> TestFunction {
+        TestFunction(Fr::zero())
+    }
+}
+
+impl Hashable<TestFunction> for PedersenDomain {
+    fn hash(&self, state: &mut TestFunction) {
+        state.write(AsRef::<[u8]>::as_ref(self));
+    }
+}
+
+impl StdHasher for TestFunction {
+    #[inline]
+    fn write(&mut self, msg: &[u8]) {
+        self.0 = hash_bytes(msg);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        unimplemented!()
+    }
+}
+
+impl HashFunction<PedersenDomain> for TestFunction {
+    fn hash(data: &[u8]) -> PedersenDomain {
+        hash_bytes(data).into()
+    }
+
+    fn hash_leaf_circuit<E: JubjubEngine, CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        left: &[boolean::Boolean],
+        right: &[boolean::Boolean],
+        _height: usize,
+        _params: &E::Params,
+    ) -> ::std::result::Result<num::AllocatedNum<E>, SynthesisError> {
+        let left = pack(cs.namespace(|| "left"), left)?;
+        let right = pack(cs.namespace(|| "right"), right)?;
+
+        compress_circuit(cs.namespace(|| "compress"), &left, &right)
+    }
+
+    fn hash_circuit<E: JubjubEngine, CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        bits: &[boolean::Boolean],
+        _params: &E::Params,
+    ) -> std::result::Result<num::AllocatedNum<E>, SynthesisError> {
+        let mut acc = num::AllocatedNum::alloc(cs.namespace(|| "zero"), || Ok(E::Fr::zero()))?;
+        cs.enforce(
+            || "zero is zero",
+            |lc| lc + acc.get_variable(),
+            |lc| lc + CS::one(),
+            |lc| lc,
+        );
+
+        for (i, chunk) in bits.chunks(CHUNK_BYTES * 8).enumerate() {
+            let mut cs = cs.namespace(|| format!("chunk {}", i));
+            let chunk = pack(cs.namespace(|| "pack"), chunk)?;
+            acc = compress_circuit(cs.namespace(|| "compress"), &acc, &chunk)?;
+        }
+
+        Ok(acc)
+    }
+}
+
+impl LightAlgorithm<PedersenDomain> for TestFunction {
+    #[inline]
+    fn hash(&mut self) -> PedersenDomain {
+        self.0.into()
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.0 = Fr::zero();
+    }
+
+    fn leaf(&mut self, leaf: PedersenDomain) -> PedersenDomain {
+        leaf
+    }
+
+    fn node(
+        &mut self,
+        left: PedersenDomain,
+        right: PedersenDomain,
+        _height: usize,
+    ) -> PedersenDomain {
+        compress(left.into(), right.into()).into()
+    }
+}
+
+fn round_constant<F: PrimeField>(c: u64) -> F {
+    F::from_repr(<F::Repr as From<u64>>::from(c)).expect("round constant is a field element")
+}
+
+/// Compresses two field elements into one.
+fn compress(left: Fr, right: Fr) -> Fr {
+    let mut x = left;
+    for c in ROUND_CONSTANTS.iter() {
+        let mut t = x;
+        t.add_assign(&right);
+        t.add_assign(&round_constant(*c));
+
+        x = t;
+        x.square();
+        x.mul_assign(&t);
+    }
+
+    x
+}
+
+/// Folds `data`, split into little-endian chunks of `CHUNK_BYTES`, with `compress`.
+fn hash_bytes(data: &[u8]) -> Fr {
+    data.chunks(CHUNK_BYTES).fold(Fr::zero(), |acc, chunk| {
+        let mut repr = FrRepr::default();
+        let mut buf = [0u8; 32];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        repr.read_le(&buf[..]).expect("failed to read chunk");
+
+        compress(acc, Fr::from_repr(repr).expect("chunk is a field element"))
+    })
+}
+
+/// Packs little-endian bits into a field element; longer inputs wrap around the modulus.
+fn pack<E, CS>(
+    mut cs: CS,
+    bits: &[boolean::Boolean],
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let mut packed = num::Num::<E>::zero();
+    let mut coeff = E::Fr::one();
+    for bit in bits {
+        packed = packed.add_bool_with_coeff(CS::one(), bit, coeff);
+        coeff.double();
+    }
+
+    let packed_num = num::AllocatedNum::alloc(cs.namespace(|| "packed"), || {
+        packed.get_value().ok_or(SynthesisError::AssignmentMissing)
+    })?;
+
+    cs.enforce(
+        || "packing constraint",
+        |_| packed.lc(E::Fr::one()),
+        |lc| lc + CS::one(),
+        |lc| lc + packed_num.get_variable(),
+    );
+
+    Ok(packed_num)
+}
+
+/// The circuit version of `compress`.
+fn compress_circuit<E, CS>(
+    mut cs: CS,
+    left: &num::AllocatedNum<E>,
+    right: &num::AllocatedNum<E>,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let mut x = left.clone();
+
+    for (i, c) in ROUND_CONSTANTS.iter().enumerate() {
+        let mut cs = cs.namespace(|| format!("round {}", i));
+        let c: E::Fr = round_constant(*c);
+
+        let t_value = match (x.get_value(), right.get_value()) {
+            (Some(x), Some(right)) => {
+                let mut t = x;
+                t.add_assign(&right);
+                t.add_assign(&c);
+                Some(t)
+            }
+            _ => None,
+        };
+        let t = |lc: LinearCombination<E>| {
+            lc + x.get_variable() + right.get_variable() + (c, CS::one())
+        };
+
+        let t2 = num::AllocatedNum::alloc(cs.namespace(|| "t^2"), || {
+            let mut t2 = t_value.ok_or(SynthesisError::AssignmentMissing)?;
+            t2.square();
+            Ok(t2)
+        })?;
+        cs.enforce(|| "t * t = t^2", t, t, |lc| lc + t2.get_variable());
+
+        let t3 = num::AllocatedNum::alloc(cs.namespace(|| "t^3"), || {
+            let mut t3 = t2.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+            t3.mul_assign(&t_value.ok_or(SynthesisError::AssignmentMissing)?);
+            Ok(t3)
+        })?;
+        cs.enforce(
+            || "t^2 * t = t^3",
+            |lc| lc + t2.get_variable(),
+            t,
+            |lc| lc + t3.get_variable(),
+        );
+
+        x = t3;
+    }
+
+    Ok(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use fil_sapling_crypto::jubjub::JubjubBls12;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::test::TestConstraintSystem;
+    use crate::fr32::fr_into_bytes;
+    use crate::merkle::MerkleTree;
+    use crate::util::bytes_into_boolean_vec;
+
+    #[test]
+    fn test_test_hasher_tree() {
+        let values = ["hello", "world", "you", "two"];
+        let t = MerkleTree::<PedersenDomain, TestFunction>::from_data(values.iter());
+
+        let p = t.gen_proof(2);
+        assert!(p.validate::<TestFunction>());
+
+        let mut a = TestFunction::default();
+        let i1 = a.node(t.read_at(0), t.read_at(1), 0);
+        let i2 = a.node(t.read_at(2), t.read_at(3), 0);
+        assert_eq!(t.read_at(6), a.node(i1, i2, 1));
+
+        // The inputs of `node` are not interchangeable.
+        assert_ne!(i1, a.node(t.read_at(1), t.read_at(0), 0));
+    }
+
+    #[test]
+    fn test_test_hasher_circuit() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = &JubjubBls12::new();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+
+        let left: PedersenDomain = rng.gen();
+        let right: PedersenDomain = rng.gen();
+        let left_bits = num::AllocatedNum::alloc(cs.namespace(|| "left"), || Ok(left.into()))
+            .unwrap()
+            .into_bits_le(cs.namespace(|| "left_bits"))
+            .unwrap();
+        let right_bits = num::AllocatedNum::alloc(cs.namespace(|| "right"), || Ok(right.into()))
+            .unwrap()
+            .into_bits_le(cs.namespace(|| "right_bits"))
+            .unwrap();
+
+        let data: Vec<u8> = (0..2)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let data_bits = {
+            let mut cs = cs.namespace(|| "data");
+            bytes_into_boolean_vec(&mut cs, Some(data.as_slice()), data.len()).unwrap()
+        };
+
+        let before = cs.num_constraints();
+        let node = TestFunction::hash_leaf_circuit(
+            cs.namespace(|| "node"),
+            &left_bits,
+            &right_bits,
+            0,
+            params,
+        )
+        .unwrap();
+        // Two packings and four rounds of two multiplications.
+        assert_eq!(cs.num_constraints() - before, 2 + 4 * 2);
+
+        let before = cs.num_constraints();
+        let hash = TestFunction::hash_circuit(cs.namespace(|| "hash"), &data_bits, params).unwrap();
+        // 64 bytes are absorbed in three chunks.
+        assert_eq!(cs.num_constraints() - before, 1 + 3 * (1 + 4 * 2));
+
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+
+        let expected_node: Fr = TestFunction::default().node(left, right, 0).into();
+        assert_eq!(node.get_value().unwrap(), expected_node);
+
+        let expected_hash: Fr = <TestFunction as HashFunction<PedersenDomain>>::hash(&data).into();
+        assert_eq!(hash.get_value().unwrap(), expected_hash);
+    }
+}
@@ -0,0 +1,299 @@
+//! Memory for the labels of a layer while it is being labeled.
+//!
+//! Labeling reads the labels of random parents, so with regular 4KiB pages nearly every read
+//! misses the TLB. The `layer_huge_pages` setting backs the buffers with huge pages instead:
+//!
+//! - `none`, the default, allocates regular memory,
+//! - `transparent` maps the buffers and advises the kernel to use transparent huge pages,
+//! - `2mib` and `1gib` map them from the reserved huge pages of that size (see
+//!   `/sys/kernel/mm/hugepages`), which have to be set aside beforehand.
+//!
+//! Huge pages are only supported on Linux. When they can't be mapped, e.g. because too few are
+//! reserved, the buffer falls back to regular memory with a warning.
+
+use std::ops::{Deref, DerefMut};
+
+use crate::error::{Error, Result};
+use crate::mlock::{self, LockedRegion};
+use crate::settings;
+
+/// The kind of pages backing label buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HugePages {
+    None,
+    Transparent,
+    Huge2MiB,
+    Huge1GiB,
+}
+
+impl HugePages {
+    /// The kind chosen by the `layer_huge_pages` setting.
+    pub fn from_settings() -> Result<Self> {
+        let huge_pages = settings::SETTINGS.lock().unwrap().layer_huge_pages.clone();
+        HugePages::parse(&huge_pages)
+    }
+
+    fn parse(huge_pages: &str) -> Result<Self> {
+        match huge_pages {
+            "none" => Ok(HugePages::None),
+            "transparent" => Ok(HugePages::Transparent),
+            "2mib" => Ok(HugePages::Huge2MiB),
+            "1gib" => Ok(HugePages::Huge1GiB),
+            other => Err(Error::InvalidSetting("layer_huge_pages", other.to_string())),
+        }
+    }
+}
+
+/// A zeroed buffer of labels, backed by the pages chosen by the `layer_huge_pages` setting.
+pub struct LabelBuffer {
+    // Declared first, so the memory is unlocked before it is released.
+    lock: Option<LockedRegion>,
+    memory: Memory,
+    len: usize,
+}
+
+enum Memory {
+    Heap(Vec<u8>),
+    Mapped(sys::Mapping),
+}
+
+impl LabelBuffer {
+    /// Allocates a zeroed buffer of `len` bytes, see `HugePages::from_settings`. With the
+    /// `layer_mlock` setting, it is locked into memory as far as the budget allows, see `mlock`.
+    pub fn new(len: usize) -> Result<Self> {
+        let mut buffer = LabelBuffer::with_huge_pages(len, HugePages::from_settings()?);
+        if settings::SETTINGS.lock().unwrap().layer_mlock {
+            buffer.lock();
+        }
+
+        Ok(buffer)
+    }
+
+    /// Allocates a zeroed buffer of `len` bytes backed by `huge_pages`, or by regular memory if
+    /// they can't be mapped.
+    pub fn with_huge_pages(len: usize, huge_pages: HugePages) -> Self {
+        let memory = match huge_pages {
+            HugePages::None => Memory::Heap(vec![0u8; len]),
+            _ if len == 0 => Memory::Heap(Vec::new()),
+            _ => match sys::Mapping::new(len, huge_pages) {
+                Ok(mapping) => Memory::Mapped(mapping),
+                Err(err) => {
+                    warn!(
+                        "failed to map {} bytes of {:?} huge pages, using regular memory: {}",
+                        len, huge_pages, err
+                    );
+                    Memory::Heap(vec![0u8; len])
+                }
+            },
+        };
+
+        LabelBuffer {
+            lock: None,
+            memory,
+            len,
+        }
+    }
+
+    /// Locks the buffer into memory until it is dropped, unless that would exceed the mlock
+    /// budget of the process. Returns whether the buffer is locked.
+    pub fn lock(&mut self) -> bool {
+        if self.lock.is_none() {
+            self.lock = mlock::try_lock(self);
+        }
+
+        self.is_locked()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_some()
+    }
+
+    /// Whether the buffer is backed by huge pages.
+    pub fn is_mapped(&self) -> bool {
+        match self.memory {
+            Memory::Heap(_) => false,
+            Memory::Mapped(_) => true,
+        }
+    }
+}
+
+impl Deref for LabelBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.memory {
+            Memory::Heap(ref buffer) => buffer,
+            Memory::Mapped(ref mapping) => unsafe {
+                std::slice::from_raw_parts(mapping.ptr(), self.len)
+            },
+        }
+    }
+}
+
+impl DerefMut for LabelBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self.memory {
+            Memory::Heap(ref mut buffer) => buffer,
+            Memory::Mapped(ref mut mapping) => unsafe {
+                std::slice::from_raw_parts_mut(mapping.ptr(), self.len)
+            },
+        }
+    }
+}
+
+impl std::fmt::Debug for LabelBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LabelBuffer")
+            .field("len", &self.len)
+            .field("mapped", &self.is_mapped())
+            .field("locked", &self.is_locked())
+            .finish()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::ptr;
+
+    use super::HugePages;
+
+    /// The log2 of the page size is passed in the flags from this bit on.
+    const MAP_HUGE_SHIFT: libc::c_int = 26;
+
+    /// An anonymous private mapping, unmapped on drop.
+    pub struct Mapping {
+        ptr: *mut u8,
+        len: usize,
+    }
+
+    // The mapping is owned, and only accessed through `LabelBuffer`, like a `Vec`.
+    unsafe impl Send for Mapping {}
+    unsafe impl Sync for Mapping {}
+
+    impl Mapping {
+        pub fn new(len: usize, huge_pages: HugePages) -> io::Result<Self> {
+            // Mappings are rounded up to whole pages.
+            let page_size = match huge_pages {
+                HugePages::None => 4 << 10,
+                HugePages::Transparent | HugePages::Huge2MiB => 2 << 20,
+                HugePages::Huge1GiB => 1 << 30,
+            };
+            let len = (len + page_size - 1) / page_size * page_size;
+
+            let mut flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+            match huge_pages {
+                HugePages::Huge2MiB => flags |= libc::MAP_HUGETLB | (21 << MAP_HUGE_SHIFT),
+                HugePages::Huge1GiB => flags |= libc::MAP_HUGETLB | (30 << MAP_HUGE_SHIFT),
+                HugePages::None | HugePages::Transparent => {}
+            }
+
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    flags,
+                    -1,
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let mapping = Mapping {
+                ptr: ptr as *mut u8,
+                len,
+            };
+
+            if huge_pages == HugePages::Transparent
+                && unsafe { libc::madvise(ptr, len, libc::MADV_HUGEPAGE) } != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(mapping)
+        }
+
+        pub fn ptr(&self) -> *mut u8 {
+            self.ptr
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::io;
+
+    use super::HugePages;
+
+    pub enum Mapping {}
+
+    impl Mapping {
+        pub fn new(_len: usize, _huge_pages: HugePages) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "huge pages are only supported on Linux",
+            ))
+        }
+
+        pub fn ptr(&self) -> *mut u8 {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_huge_pages() {
+        assert_eq!(HugePages::parse("none").unwrap(), HugePages::None);
+        assert_eq!(
+            HugePages::parse("transparent").unwrap(),
+            HugePages::Transparent
+        );
+        assert_eq!(HugePages::parse("2mib").unwrap(), HugePages::Huge2MiB);
+        assert_eq!(HugePages::parse("1gib").unwrap(), HugePages::Huge1GiB);
+        assert!(HugePages::parse("4kib").is_err());
+    }
+
+    #[test]
+    fn test_label_buffers() {
+        let len = (3 << 20) + 17;
+
+        for huge_pages in &[
+            HugePages::None,
+            HugePages::Transparent,
+            HugePages::Huge2MiB,
+            HugePages::Huge1GiB,
+        ] {
+            // Reserved huge pages may be missing, in which case regular memory is used.
+            let mut buffer = LabelBuffer::with_huge_pages(len, *huge_pages);
+            assert_eq!(buffer.len(), len);
+            assert!(buffer.iter().all(|byte| *byte == 0));
+
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = i as u8;
+            }
+            assert_eq!(buffer[len - 1], (len - 1) as u8);
+        }
+
+        let mut buffer = LabelBuffer::with_huge_pages(len, HugePages::None);
+        assert!(!buffer.is_mapped());
+        // Whether it fits into the mlock budget depends on the environment.
+        let locked = buffer.lock();
+        assert_eq!(buffer.is_locked(), locked);
+
+        assert!(LabelBuffer::with_huge_pages(0, HugePages::Transparent).is_empty());
+    }
+}
@@ -0,0 +1,343 @@
+//! Temporary files for the labels of a layer, read with a page cache policy chosen by the
+//! `layer_io` setting.
+//!
+//! Layers are read sequentially while the replica is encoded and committed to, and randomly
+//! while proving. With the page cache defaults, several seals running at once evict each other's
+//! pages, and random reads pull in read-ahead nobody uses. The `layer_io` setting changes that:
+//!
+//! - `buffered`, the default, keeps layers in regular temporary files, as before,
+//! - `advise` tells the kernel how layers are read (`posix_fadvise`): sequential access doubles
+//!   the read-ahead and fetches the next range in advance, random access disables read-ahead,
+//! - `direct` additionally reads layers past the page cache (`O_DIRECT`) during random access,
+//!   and drops their pages from the cache once they are written.
+//!
+//! Both `advise` and `direct` are only supported on Linux. Where the file system rejects
+//! `O_DIRECT`, e.g. some versions of tmpfs, layers are read through the page cache with a warning.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use merkletree::merkle::Element;
+
+use crate::error::{Error, Result};
+use crate::sequential_writer::{AlignedBuf, WRITE_ALIGNMENT};
+use crate::settings;
+
+/// How the labels of layers on disk are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerIo {
+    Buffered,
+    Advise,
+    Direct,
+}
+
+impl LayerIo {
+    /// The mode chosen by the `layer_io` setting.
+    pub fn from_settings() -> Result<Self> {
+        let layer_io = settings::SETTINGS.lock().unwrap().layer_io.clone();
+        LayerIo::parse(&layer_io)
+    }
+
+    fn parse(layer_io: &str) -> Result<Self> {
+        match layer_io {
+            "buffered" => Ok(LayerIo::Buffered),
+            "advise" => Ok(LayerIo::Advise),
+            "direct" => Ok(LayerIo::Direct),
+            other => Err(Error::InvalidSetting("layer_io", other.to_string())),
+        }
+    }
+}
+
+/// How a layer is about to be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// In order, e.g. while encoding the replica or building `tree_c`.
+    Sequential,
+    /// At scattered nodes, e.g. while proving challenges.
+    Random,
+}
+
+/// The labels of a layer in an anonymous temporary file, which is removed on drop.
+#[derive(Debug)]
+pub struct LabelFile<E: Element> {
+    file: File,
+    len: usize,
+    direct: bool,
+    sequential: AtomicBool,
+    _e: PhantomData<E>,
+}
+
+impl<E: Element> LabelFile<E> {
+    /// Writes `data` to a new file, to be read sequentially at first. With `direct` set, random
+    /// access reads past the page cache, see `advise`.
+    pub fn new_from_slice(data: &[u8], direct: bool) -> Result<Self> {
+        assert_eq!(data.len() % E::byte_len(), 0, "partial label");
+
+        let mut file = tempfile::tempfile()?;
+        file.write_all(data)?;
+        if direct {
+            // Nothing reads the layer again soon, so its pages are better left to other seals.
+            file.sync_data()?;
+            if let Err(err) = sys::advise(&file, 0, 0, sys::Advice::DontNeed) {
+                warn!("failed to drop layer from the page cache: {}", err);
+            }
+        }
+
+        let store = LabelFile {
+            file,
+            len: data.len() / E::byte_len(),
+            direct,
+            sequential: AtomicBool::new(true),
+            _e: PhantomData,
+        };
+        store.advise(Access::Sequential);
+
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Tells the kernel how the layer is read from now on. With `direct` set, random access
+    /// enables `O_DIRECT`, and sequential access goes back to the page cache, so that its
+    /// read-ahead serves the many small reads of sequential passes. Failures only cost
+    /// performance, so they are logged rather than returned.
+    pub fn advise(&self, access: Access) {
+        self.sequential
+            .store(access == Access::Sequential, Ordering::Relaxed);
+
+        let advice = match access {
+            Access::Sequential => sys::Advice::Sequential,
+            Access::Random => sys::Advice::Random,
+        };
+        if let Err(err) = sys::advise(&self.file, 0, 0, advice) {
+            warn!("failed to advise on layer access: {}", err);
+        }
+
+        if self.direct {
+            if let Err(err) = sys::set_direct(&self.file, access == Access::Random) {
+                warn!(
+                    "failed to switch direct IO of layer, using the page cache: {}",
+                    err
+                );
+            }
+        }
+    }
+
+    #[allow(clippy::range_plus_one)]
+    pub fn read_at(&self, index: usize) -> E {
+        E::from_slice(&self.read_bytes(index..index + 1))
+    }
+
+    pub fn read_range(&self, range: Range<usize>) -> Vec<E> {
+        let bytes = self.read_bytes(range.clone());
+
+        // Fetch the next range of a sequential pass in the background, while this one is used.
+        if self.sequential.load(Ordering::Relaxed) && range.end < self.len {
+            let next = range.end..std::cmp::min(range.end + range.len(), self.len);
+            let (offset, len) = self.byte_range(next);
+            if let Err(err) = sys::advise(&self.file, offset, len, sys::Advice::WillNeed) {
+                warn!("failed to read ahead layer: {}", err);
+            }
+        }
+
+        bytes.chunks(E::byte_len()).map(E::from_slice).collect()
+    }
+
+    fn byte_range(&self, range: Range<usize>) -> (u64, u64) {
+        (
+            (range.start * E::byte_len()) as u64,
+            (range.len() * E::byte_len()) as u64,
+        )
+    }
+
+    fn read_bytes(&self, range: Range<usize>) -> Vec<u8> {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "out of bounds"
+        );
+        let (offset, len) = self.byte_range(range);
+        let (offset, len) = (offset as usize, len as usize);
+        if len == 0 {
+            return Vec::new();
+        }
+
+        // Reads are always aligned when `direct` is set, as `O_DIRECT` may be switched on at any
+        // time by another thread.
+        let result = if self.direct {
+            let start = offset / WRITE_ALIGNMENT * WRITE_ALIGNMENT;
+            let end = (offset + len + WRITE_ALIGNMENT - 1) / WRITE_ALIGNMENT * WRITE_ALIGNMENT;
+            let mut buf = AlignedBuf::new(end - start);
+            read_full_at(&self.file, buf.as_mut_slice(), start as u64)
+                .map(|_| buf.as_slice()[offset - start..offset - start + len].to_vec())
+        } else {
+            let mut buf = vec![0; len];
+            read_full_at(&self.file, &mut buf, offset as u64).map(|_| buf)
+        };
+
+        result.expect("failed to read layer")
+    }
+}
+
+/// Fills `buf` from `offset`, or as much of it as the file has, and returns how much was read.
+fn read_full_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match sys::read_at(file, &mut buf[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::AsRawFd;
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Advice {
+        Sequential,
+        Random,
+        WillNeed,
+        DontNeed,
+    }
+
+    /// Advises on `len` bytes from `offset`, or the whole file for a `len` of 0.
+    pub fn advise(file: &File, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+        let advice = match advice {
+            Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            Advice::Random => libc::POSIX_FADV_RANDOM,
+            Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+            Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+        };
+
+        // Returns the error rather than setting `errno`.
+        let err = unsafe {
+            libc::posix_fadvise(
+                file.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                advice,
+            )
+        };
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+
+        Ok(())
+    }
+
+    pub fn set_direct(file: &File, direct: bool) -> io::Result<()> {
+        let fd = file.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let flags = if direct {
+            flags | libc::O_DIRECT
+        } else {
+            flags & !libc::O_DIRECT
+        };
+        if unsafe { libc::fcntl(fd, libc::F_SETFL, flags) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        file.read_at(buf, offset)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::fs::File;
+    use std::io;
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Advice {
+        Sequential,
+        Random,
+        WillNeed,
+        DontNeed,
+    }
+
+    pub fn advise(_file: &File, _offset: u64, _len: u64, _advice: Advice) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn set_direct(_file: &File, _direct: bool) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "direct IO is only supported on Linux",
+        ))
+    }
+
+    #[cfg(unix)]
+    pub fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(file, buf, offset)
+    }
+
+    #[cfg(windows)]
+    pub fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::Rng;
+
+    use crate::hasher::pedersen::PedersenDomain;
+    use crate::hasher::Domain;
+    use crate::test_helper::test_rng;
+
+    #[test]
+    fn test_parse_layer_io() {
+        assert_eq!(LayerIo::parse("buffered").unwrap(), LayerIo::Buffered);
+        assert_eq!(LayerIo::parse("advise").unwrap(), LayerIo::Advise);
+        assert_eq!(LayerIo::parse("direct").unwrap(), LayerIo::Direct);
+        assert!(LayerIo::parse("O_DIRECT").is_err());
+    }
+
+    #[test]
+    fn test_label_file() {
+        let rng = &mut test_rng();
+        // Not a multiple of the alignment, so direct reads end past the end of the file.
+        let labels: Vec<PedersenDomain> = (0..300).map(|_| rng.gen()).collect();
+        let data: Vec<u8> = labels.iter().flat_map(|label| label.into_bytes()).collect();
+
+        for &direct in &[false, true] {
+            let store = LabelFile::<PedersenDomain>::new_from_slice(&data, direct).unwrap();
+            assert_eq!(store.len(), labels.len());
+
+            for &access in &[Access::Sequential, Access::Random] {
+                store.advise(access);
+                assert_eq!(store.read_at(0), labels[0]);
+                assert_eq!(store.read_at(299), labels[299]);
+                assert_eq!(store.read_range(120..260), &labels[120..260]);
+                assert_eq!(store.read_range(0..300), labels);
+                assert!(store.read_range(7..7).is_empty());
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 #![deny(clippy::all, clippy::perf, clippy::correctness)]
 #![allow(clippy::unreadable_literal)]
 #![allow(clippy::type_repetition_in_bounds)]
+#![allow(clippy::mutex_atomic)]
 
 #[macro_use]
 extern crate failure;
@@ -25,26 +26,46 @@ pub mod test_helper;
 
 pub mod example_helper;
 
+pub mod analysis;
+pub mod batch;
+pub mod cancel;
+pub mod challenge_hash;
 pub mod circuit;
 pub mod compound_proof;
+pub mod core_binding;
 pub mod crypto;
+pub mod data;
 pub mod drgporep;
 pub mod drgraph;
+pub mod election_post;
 pub mod error;
 pub mod fr32;
+pub mod gpu;
+pub mod gpu_lock;
 pub mod hasher;
+pub mod label_buffer;
+pub mod label_file;
+pub mod measurements;
 pub mod merkle;
 pub mod merklepor;
+pub mod mlock;
+pub mod numa;
 pub mod parameter_cache;
 pub mod partitions;
 pub mod piece_inclusion_proof;
 pub mod porc;
 pub mod porep;
+pub mod progress;
 pub mod proof;
 pub mod rational_post;
+pub mod resources;
 pub mod sector;
+pub mod sequential_writer;
 pub mod settings;
 pub mod stacked;
+pub mod test_vectors;
 pub mod util;
+pub mod verifier_export;
+pub mod versioned;
 
 pub mod vde;
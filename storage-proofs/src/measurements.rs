@@ -0,0 +1,246 @@
+//! Durations and byte counts of long-running operations.
+//!
+//! Labeling (per layer), tree building, proving and waiting for the GPU are measured whenever
+//! they run. Every measurement is added to process wide totals, see `summaries` and
+//! `prometheus_text`, and handed to the `Exporter` installed with `set_exporter`, if any, so
+//! miners can feed their own metrics pipelines without patching the crate.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Operation {
+    /// Labeling a single layer, the bytes are the size of the layer.
+    LabelLayer,
+    /// Building the tree over the original data.
+    TreeD,
+    /// Building the tree over the column hashes.
+    TreeC,
+    /// Building the tree over the replica.
+    TreeRLast,
+    /// Generating the vanilla proofs of all partitions.
+    VanillaProof,
+    /// Generating the circuit proof of a single partition.
+    CircuitProof,
+    /// Waiting for the lock of the GPU.
+    GpuWait,
+}
+
+impl Operation {
+    /// The name used in exported metrics.
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::LabelLayer => "label_layer",
+            Operation::TreeD => "tree_d",
+            Operation::TreeC => "tree_c",
+            Operation::TreeRLast => "tree_r_last",
+            Operation::VanillaProof => "vanilla_proof",
+            Operation::CircuitProof => "circuit_proof",
+            Operation::GpuWait => "gpu_wait",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Measurement {
+    pub operation: Operation,
+    /// The layer, starting at 1, only set for `Operation::LabelLayer`.
+    pub layer: Option<usize>,
+    pub duration: Duration,
+    /// The bytes processed, 0 where that does not apply.
+    pub bytes: u64,
+}
+
+/// Receives every measurement as it is taken. It is called from the threads doing the work, so
+/// it should return quickly.
+pub trait Exporter: Send + Sync {
+    fn export(&self, measurement: &Measurement);
+}
+
+/// The totals of an operation, and layer, since the start of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub operation: Operation,
+    pub layer: Option<usize>,
+    pub count: u64,
+    pub duration: Duration,
+    pub bytes: u64,
+}
+
+lazy_static! {
+    static ref EXPORTER: RwLock<Option<Arc<dyn Exporter>>> = RwLock::new(None);
+    static ref SUMMARIES: Mutex<BTreeMap<(Operation, Option<usize>), Summary>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Installs the exporter every measurement is handed to, replacing the previous one.
+pub fn set_exporter(exporter: Option<Arc<dyn Exporter>>) {
+    *EXPORTER.write().unwrap() = exporter;
+}
+
+/// Records a measurement.
+pub fn record(operation: Operation, layer: Option<usize>, duration: Duration, bytes: u64) {
+    let measurement = Measurement {
+        operation,
+        layer,
+        duration,
+        bytes,
+    };
+
+    {
+        let mut summaries = SUMMARIES.lock().unwrap();
+        let summary = summaries
+            .entry((operation, layer))
+            .or_insert_with(|| Summary {
+                operation,
+                layer,
+                count: 0,
+                duration: Duration::default(),
+                bytes: 0,
+            });
+        summary.count += 1;
+        summary.duration += duration;
+        summary.bytes += bytes;
+    }
+
+    if let Some(exporter) = EXPORTER.read().unwrap().as_ref() {
+        exporter.export(&measurement);
+    }
+}
+
+/// Runs `f` and records how long it took, if it succeeded.
+pub fn measure<T, E, F>(
+    operation: Operation,
+    layer: Option<usize>,
+    bytes: u64,
+    f: F,
+) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E>,
+{
+    let start = Instant::now();
+    let result = f()?;
+    record(operation, layer, start.elapsed(), bytes);
+
+    Ok(result)
+}
+
+/// The totals of all operations measured so far, ordered by operation and layer.
+pub fn summaries() -> Vec<Summary> {
+    SUMMARIES.lock().unwrap().values().cloned().collect()
+}
+
+/// The totals in the Prometheus text exposition format, ready to be served as is.
+pub fn prometheus_text() -> String {
+    format_prometheus(&summaries())
+}
+
+fn format_prometheus(summaries: &[Summary]) -> String {
+    let labels = |summary: &Summary| match summary.layer {
+        Some(layer) => format!(
+            "{{operation=\"{}\",layer=\"{}\"}}",
+            summary.operation.name(),
+            layer
+        ),
+        None => format!("{{operation=\"{}\"}}", summary.operation.name()),
+    };
+
+    let mut text = String::new();
+    // Writing to a `String` can't fail.
+    let _ = writeln!(
+        text,
+        "# HELP fil_proofs_duration_seconds Time spent in long-running operations."
+    );
+    let _ = writeln!(text, "# TYPE fil_proofs_duration_seconds summary");
+    for summary in summaries {
+        let seconds =
+            summary.duration.as_secs() as f64 + f64::from(summary.duration.subsec_nanos()) / 1e9;
+        let _ = writeln!(
+            text,
+            "fil_proofs_duration_seconds_sum{} {}",
+            labels(summary),
+            seconds
+        );
+        let _ = writeln!(
+            text,
+            "fil_proofs_duration_seconds_count{} {}",
+            labels(summary),
+            summary.count
+        );
+    }
+
+    let _ = writeln!(
+        text,
+        "# HELP fil_proofs_bytes_total Bytes processed by long-running operations."
+    );
+    let _ = writeln!(text, "# TYPE fil_proofs_bytes_total counter");
+    for summary in summaries.iter().filter(|summary| summary.bytes > 0) {
+        let _ = writeln!(
+            text,
+            "fil_proofs_bytes_total{} {}",
+            labels(summary),
+            summary.bytes
+        );
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_prometheus() {
+        let summaries = [
+            Summary {
+                operation: Operation::LabelLayer,
+                layer: Some(2),
+                count: 3,
+                duration: Duration::from_millis(1500),
+                bytes: 1024,
+            },
+            Summary {
+                operation: Operation::GpuWait,
+                layer: None,
+                count: 1,
+                duration: Duration::from_millis(250),
+                bytes: 0,
+            },
+        ];
+
+        assert_eq!(
+            format_prometheus(&summaries),
+            "# HELP fil_proofs_duration_seconds Time spent in long-running operations.\n\
+             # TYPE fil_proofs_duration_seconds summary\n\
+             fil_proofs_duration_seconds_sum{operation=\"label_layer\",layer=\"2\"} 1.5\n\
+             fil_proofs_duration_seconds_count{operation=\"label_layer\",layer=\"2\"} 3\n\
+             fil_proofs_duration_seconds_sum{operation=\"gpu_wait\"} 0.25\n\
+             fil_proofs_duration_seconds_count{operation=\"gpu_wait\"} 1\n\
+             # HELP fil_proofs_bytes_total Bytes processed by long-running operations.\n\
+             # TYPE fil_proofs_bytes_total counter\n\
+             fil_proofs_bytes_total{operation=\"label_layer\",layer=\"2\"} 1024\n"
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        // Other tests measure concurrently, so only look at a layer nothing else labels.
+        let layer = Some(usize::max_value());
+        record(Operation::LabelLayer, layer, Duration::from_secs(1), 10);
+        measure::<_, (), _>(Operation::LabelLayer, layer, 20, || Ok(())).unwrap();
+        assert!(measure::<(), _, _>(Operation::LabelLayer, layer, 40, || Err(())).is_err());
+
+        let summary = summaries()
+            .into_iter()
+            .find(|summary| summary.operation == Operation::LabelLayer && summary.layer == layer)
+            .expect("missing summary");
+        assert_eq!(summary.count, 2);
+        assert_eq!(summary.bytes, 30);
+        assert!(summary.duration >= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,212 @@
+use std::io::{self, Write};
+
+use merkletree::hash::Algorithm;
+use rayon::prelude::*;
+
+use crate::error::{Error, Result};
+use crate::hasher::{Domain, Hasher};
+use crate::merkle::{MerkleStore, MerkleTree, Store};
+use crate::util::NODE_SIZE;
+
+/// The number of leaves `TreeBuilder::new` hashes at a time, 2MiB of leaf data.
+pub const DEFAULT_CHUNK_LEAFS: usize = 1 << 16;
+
+/// Builds a merkle tree from its leaf data, written in order and in any number of writes, e.g.
+/// copied from a staged sector with `io::copy`.
+///
+/// Leaf data is hashed in chunks of a power of two leaves as soon as a chunk is complete, each
+/// level of a chunk in parallel, so the data never has to be held or read again in full. The
+/// tree is identical, node for node, to the one `build_tree` returns for the same leaves.
+#[derive(Debug)]
+pub struct TreeBuilder<H: Hasher> {
+    leafs: usize,
+    chunk_leafs: usize,
+    /// The hashed rows of the tree, from the leaves up.
+    rows: Vec<Vec<H::Domain>>,
+    /// Leaf data of the current chunk, not yet hashed.
+    pending: Vec<u8>,
+}
+
+impl<H: Hasher> TreeBuilder<H> {
+    /// A builder of a tree of `leafs` leaves, which must be a power of two.
+    pub fn new(leafs: usize) -> Result<Self> {
+        Self::with_chunk_leafs(leafs, DEFAULT_CHUNK_LEAFS)
+    }
+
+    /// A builder which hashes `chunk_leafs` leaves at a time, a power of two as well.
+    pub fn with_chunk_leafs(leafs: usize, chunk_leafs: usize) -> Result<Self> {
+        if !leafs.is_power_of_two() || !chunk_leafs.is_power_of_two() {
+            return Err(Error::MerkleTreeGenerationError(format!(
+                "{} leaves in chunks of {} are not powers of two",
+                leafs, chunk_leafs
+            )));
+        }
+        let chunk_leafs = chunk_leafs.min(leafs);
+
+        Ok(TreeBuilder {
+            leafs,
+            chunk_leafs,
+            rows: (0..=tree_height(leafs))
+                .map(|height| Vec::with_capacity(leafs >> height))
+                .collect(),
+            pending: Vec::with_capacity(chunk_leafs * NODE_SIZE),
+        })
+    }
+
+    /// The number of bytes of leaf data written so far.
+    pub fn written_bytes(&self) -> usize {
+        self.rows[0].len() * NODE_SIZE + self.pending.len()
+    }
+
+    /// Appends `data` to the leaf data.
+    pub fn write_leaf_data(&mut self, mut data: &[u8]) -> Result<()> {
+        let tree_bytes = self.leafs * NODE_SIZE;
+        if self.written_bytes() + data.len() > tree_bytes {
+            return Err(Error::OutOfBounds(
+                self.written_bytes() + data.len(),
+                tree_bytes,
+            ));
+        }
+
+        let chunk_bytes = self.chunk_leafs * NODE_SIZE;
+        while !data.is_empty() {
+            let len = (chunk_bytes - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..len]);
+            data = &data[len..];
+
+            if self.pending.len() == chunk_bytes {
+                self.hash_chunk()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hashes the complete chunk in `pending` up to its root.
+    fn hash_chunk(&mut self) -> Result<()> {
+        let mut row = self
+            .pending
+            .par_chunks(NODE_SIZE)
+            .map(H::Domain::try_from_bytes)
+            .collect::<Result<Vec<_>>>()?;
+        self.pending.clear();
+
+        for height in 0..tree_height(self.chunk_leafs) {
+            let parents = hash_row::<H>(&row, height);
+            self.rows[height].extend(row);
+            row = parents;
+        }
+        self.rows[tree_height(self.chunk_leafs)].extend(row);
+
+        Ok(())
+    }
+
+    /// Hashes the rows above the chunks and returns the tree. Fails unless all leaf data has
+    /// been written.
+    pub fn finish(mut self) -> Result<MerkleTree<H::Domain, H::Function>> {
+        let tree_bytes = self.leafs * NODE_SIZE;
+        if self.written_bytes() != tree_bytes {
+            return Err(Error::InsufficientData(self.written_bytes(), tree_bytes));
+        }
+
+        for height in tree_height(self.chunk_leafs)..tree_height(self.leafs) {
+            let parents = hash_row::<H>(&self.rows[height], height);
+            self.rows[height + 1] = parents;
+        }
+
+        let nodes = 2 * self.leafs - 1;
+        let mut data = vec![0; nodes * NODE_SIZE];
+        for (node, bytes) in self.rows.iter().flatten().zip(data.chunks_mut(NODE_SIZE)) {
+            node.write_bytes(bytes)?;
+        }
+
+        let store = MerkleStore::new_from_slice(nodes, &data)?;
+
+        Ok(MerkleTree::from_data_store(store, self.leafs))
+    }
+}
+
+impl<H: Hasher> Write for TreeBuilder<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_leaf_data(buf)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn tree_height(leafs: usize) -> usize {
+    leafs.trailing_zeros() as usize
+}
+
+/// Hashes each pair of nodes of the row at `height` into their parent.
+fn hash_row<H: Hasher>(row: &[H::Domain], height: usize) -> Vec<H::Domain> {
+    row.par_chunks(2)
+        .map(|pair| H::Function::default().node(pair[0], pair[1], height))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paired::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::fr32::fr_into_bytes;
+    use crate::hasher::{PedersenHasher, Sha256Hasher};
+    use crate::merkle::build_tree;
+
+    fn builder_matches_build_tree<H: Hasher>() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let leafs = 64;
+        let data: Vec<u8> = (0..leafs)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen::<Fr>()))
+            .collect();
+
+        let expected = build_tree::<H, _>(
+            leafs,
+            |i| H::Domain::try_from_bytes(&data[i * NODE_SIZE..(i + 1) * NODE_SIZE]).unwrap(),
+            true,
+        )
+        .unwrap();
+
+        for &chunk_leafs in &[1, 4, 64, DEFAULT_CHUNK_LEAFS] {
+            let mut builder = TreeBuilder::<H>::with_chunk_leafs(leafs, chunk_leafs).unwrap();
+            // Writes which do not line up with leaves or chunks.
+            for write in data.chunks(100) {
+                builder.write_all(write).unwrap();
+            }
+            let tree = builder.finish().unwrap();
+
+            assert_eq!(tree.len(), expected.len());
+            for i in 0..tree.len() {
+                assert_eq!(tree.read_at(i), expected.read_at(i), "node {} differs", i);
+            }
+        }
+    }
+
+    #[test]
+    fn builder_matches_build_tree_pedersen() {
+        builder_matches_build_tree::<PedersenHasher>();
+    }
+
+    #[test]
+    fn builder_matches_build_tree_sha256() {
+        builder_matches_build_tree::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn builder_requires_all_leaf_data() {
+        let mut builder = TreeBuilder::<PedersenHasher>::with_chunk_leafs(4, 2).unwrap();
+        builder.write_leaf_data(&[0; 3 * NODE_SIZE]).unwrap();
+        assert!(builder.write_leaf_data(&[0; 2 * NODE_SIZE]).is_err());
+        assert!(builder.finish().is_err());
+
+        assert!(TreeBuilder::<PedersenHasher>::new(3).is_err());
+    }
+}
@@ -1,14 +1,24 @@
 #![allow(clippy::len_without_is_empty)]
 
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
 use std::marker::PhantomData;
+use std::path::Path;
 
 // Reexport here, so we don't depend on merkletree directly in other places.
-use merkletree::hash::Algorithm;
 use merkletree::merkle;
+use merkletree::merkle::FromIndexedParallelIterator;
 use merkletree::proof;
 use paired::bls12_381::Fr;
+use rayon::prelude::*;
 
+use crate::error::{Error, Result};
 use crate::hasher::{Domain, Hasher};
+use crate::settings;
+use crate::util::NODE_SIZE;
+
+pub mod builder;
+pub mod verify;
 
 pub use merkletree::merkle::next_pow2;
 pub use merkletree::store::Store;
@@ -27,6 +37,51 @@ pub type MerkleTree<T, A> = merkle::MerkleTree<T, A, VecStore<T>>;
 #[cfg(feature = "mem-trees")]
 pub type MerkleStore<T> = VecStore<T>;
 
+/// Builds a merkle tree over `leafs` leaves, where `leaf_at` returns the leaf at a given index.
+///
+/// Unless `deterministic_trees` is set, `parallel` trees are built using all available threads.
+/// With it, trees are always built in a single sequential pass, so the result can never depend on
+/// scheduling. If `check_tree_roots` is set, every tree is built a second time, sequentially, and
+/// an error is returned if the roots of both runs differ.
+pub fn build_tree<H, F>(
+    leafs: usize,
+    leaf_at: F,
+    parallel: bool,
+) -> Result<MerkleTree<H::Domain, H::Function>>
+where
+    H: Hasher,
+    F: Fn(usize) -> H::Domain + Sync + Send,
+{
+    let (deterministic, check_roots) = {
+        let settings = settings::SETTINGS.lock().unwrap();
+        (settings.deterministic_trees, settings.check_tree_roots)
+    };
+
+    let build = |parallel: bool| -> MerkleTree<H::Domain, H::Function> {
+        if parallel {
+            MerkleTree::from_par_iter((0..leafs).into_par_iter().map(&leaf_at))
+        } else {
+            MerkleTree::new((0..leafs).map(&leaf_at))
+        }
+    };
+
+    let tree = build(parallel && !deterministic);
+
+    if check_roots {
+        let reference_root = build(false).root();
+
+        if tree.root() != reference_root {
+            return Err(Error::MerkleTreeGenerationError(format!(
+                "tree root {:?} does not match the reference root {:?}",
+                tree.root(),
+                reference_root
+            )));
+        }
+    }
+
+    Ok(tree)
+}
+
 /// Representation of a merkle proof.
 /// Each element in the `path` vector consists of a tuple `(hash, is_right)`, with `hash` being the the hash of the node at the current level and `is_right` a boolean indicating if the path is taking the right path.
 /// The first element is the hash of leaf itself, and the last is the root hash.
@@ -61,6 +116,16 @@ impl<H: Hasher> MerkleProof<H> {
         m
     }
 
+    /// A proof of `leaf` with the given `path`, which is not checked against `root`.
+    pub fn from_parts(root: H::Domain, leaf: H::Domain, path: Vec<(H::Domain, bool)>) -> Self {
+        MerkleProof {
+            path,
+            root,
+            leaf,
+            _h: PhantomData,
+        }
+    }
+
     pub fn new_from_proof(p: &proof::Proof<H::Domain>) -> MerkleProof<H> {
         MerkleProof {
             path: p
@@ -102,30 +167,12 @@ impl<H: Hasher> MerkleProof<H> {
     }
 
     fn verify(&self) -> bool {
-        let mut a = H::Function::default();
-
-        self.root()
-            == &(0..self.path.len()).fold(self.leaf, |h, i| {
-                a.reset();
-                let is_right = self.path[i].1;
-
-                let (left, right) = if is_right {
-                    (self.path[i].0, h)
-                } else {
-                    (h, self.path[i].0)
-                };
-
-                a.node(left, right, i)
-            })
+        self.root() == &verify::compute_root::<_, H::Function>(self.leaf, &self.path)
     }
 
     /// Validates the MerkleProof and that it corresponds to the supplied node.
     pub fn validate(&self, node: usize) -> bool {
-        if path_index(&self.path) != node {
-            return false;
-        }
-
-        self.verify()
+        verify::validate::<_, H::Function>(self.root(), self.leaf, &self.path, node)
     }
 
     /// Validates that the data hashes to the leaf of the merkle path.
@@ -179,14 +226,7 @@ impl<H: Hasher> MerkleProof<H> {
     /// proves_challenge returns true if this self.proof corresponds to challenge.
     /// This is useful for verifying that a supplied proof is actually relevant to a given challenge.
     pub fn proves_challenge(&self, challenge: usize) -> bool {
-        let mut c = challenge;
-        for (_, is_right) in self.path().iter() {
-            if ((c & 1) == 1) ^ is_right {
-                return false;
-            };
-            c >>= 1;
-        }
-        true
+        verify::proves_challenge(self.path(), challenge)
     }
 }
 
@@ -217,10 +257,38 @@ impl<H: Hasher> std::ops::Deref for IncludedNode<H> {
     }
 }
 
-fn path_index<T: Domain>(path: &[(T, bool)]) -> usize {
-    path.iter().rev().fold(0, |acc, (_, is_right)| {
-        (acc << 1) + if *is_right { 1 } else { 0 }
-    })
+/// Returns the number of leaves of a tree persisted at `path` by a `DiskStore`, using only the
+/// size of the file.
+pub fn disk_tree_leafs<P: AsRef<Path>>(path: P) -> Result<usize> {
+    let len = fs::metadata(path.as_ref())?.len() as usize;
+
+    if len == 0 || len % NODE_SIZE != 0 {
+        return Err(Error::MalformedMerkleTree);
+    }
+
+    // A full binary tree with `n` leaves is stored as `2n - 1` elements.
+    let elements = len / NODE_SIZE;
+    let leafs = (elements + 1) / 2;
+
+    if !leafs.is_power_of_two() || 2 * leafs - 1 != elements {
+        return Err(Error::MalformedMerkleTree);
+    }
+
+    Ok(leafs)
+}
+
+/// Reads the root of a tree persisted at `path` by a `DiskStore` (e.g. `tree_d`, `tree_c` or
+/// `tree_r_last`), without loading the rest of the tree. The root is the last stored element.
+pub fn disk_tree_root<T: Domain, P: AsRef<Path>>(path: P) -> Result<T> {
+    disk_tree_leafs(path.as_ref())?;
+
+    let mut file = File::open(path.as_ref())?;
+    file.seek(SeekFrom::End(-(NODE_SIZE as i64)))?;
+
+    let mut root = [0u8; NODE_SIZE];
+    file.read_exact(&mut root)?;
+
+    T::try_from_bytes(&root)
 }
 
 #[cfg(test)]
@@ -230,11 +298,12 @@ mod tests {
     use rand::{self, Rng};
     use std::io::Write;
 
-    use crate::drgraph::{new_seed, BucketGraph, Graph, BASE_DEGREE};
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
     use crate::hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher};
+    use crate::test_helper::test_rng;
 
     fn merklepath<H: Hasher>() {
-        let g = BucketGraph::<H>::new(10, BASE_DEGREE, 0, new_seed());
+        let g = BucketGraph::<H>::new(10, BASE_DEGREE, 0, new_seed_from(&mut test_rng()));
         let mut rng = rand::thread_rng();
         let node_size = 32;
         let mut data = Vec::new();
@@ -277,4 +346,79 @@ mod tests {
     fn merklepath_blake2s() {
         merklepath::<Blake2sHasher>();
     }
+
+    fn build_tree_parallel_matches_sequential<H: Hasher>() {
+        let mut rng = rand::thread_rng();
+        let leafs: Vec<H::Domain> = (0..64).map(|_| rng.gen()).collect();
+
+        let parallel = build_tree::<H, _>(leafs.len(), |i| leafs[i], true).unwrap();
+        let sequential = build_tree::<H, _>(leafs.len(), |i| leafs[i], false).unwrap();
+
+        assert_eq!(parallel.len(), sequential.len());
+        for i in 0..parallel.len() {
+            assert_eq!(
+                parallel.read_at(i),
+                sequential.read_at(i),
+                "node {} differs",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn build_tree_parallel_matches_sequential_pedersen() {
+        build_tree_parallel_matches_sequential::<PedersenHasher>();
+    }
+
+    #[test]
+    fn build_tree_parallel_matches_sequential_sha256() {
+        build_tree_parallel_matches_sequential::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn build_tree_parallel_matches_sequential_blake2s() {
+        build_tree_parallel_matches_sequential::<Blake2sHasher>();
+    }
+
+    fn disk_tree_root_matches<H: Hasher>() {
+        let g = BucketGraph::<H>::new(16, BASE_DEGREE, 0, new_seed_from(&mut test_rng()));
+        let mut rng = rand::thread_rng();
+        let data: Vec<u8> = (0..16)
+            .flat_map(|_| {
+                let elt: H::Domain = rng.gen();
+                elt.into_bytes()
+            })
+            .collect();
+
+        let tree = g.merkle_tree(data.as_slice()).unwrap();
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for i in 0..tree.len() {
+            file.write_all(AsRef::<[u8]>::as_ref(&tree.read_at(i)))
+                .unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(disk_tree_leafs(file.path()).unwrap(), 16);
+        assert_eq!(
+            disk_tree_root::<H::Domain, _>(file.path()).unwrap(),
+            tree.root()
+        );
+
+        // Truncated trees are rejected.
+        file.as_file()
+            .set_len((tree.len() - 1) as u64 * NODE_SIZE as u64)
+            .unwrap();
+        assert!(disk_tree_root::<H::Domain, _>(file.path()).is_err());
+    }
+
+    #[test]
+    fn disk_tree_root_pedersen() {
+        disk_tree_root_matches::<PedersenHasher>();
+    }
+
+    #[test]
+    fn disk_tree_root_sha256() {
+        disk_tree_root_matches::<Sha256Hasher>();
+    }
 }
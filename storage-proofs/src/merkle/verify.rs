@@ -0,0 +1,99 @@
+//! Verification of merkle inclusion proofs from their parts.
+//!
+//! Everything a verifier needs to check an inclusion proof, kept apart from tree building: it
+//! only uses `core` and the hashing algorithm, no rayon, stores or IO, so it can be lifted into
+//! embedded or WASM verifiers as is. `MerkleProof` verifies through these functions.
+
+use merkletree::hash::Algorithm;
+
+/// Recomputes the root of the tree from `leaf` and its `path`, the sibling of every level from the
+/// leaves up, together with whether the node is the right child.
+pub fn compute_root<T, A>(leaf: T, path: &[(T, bool)]) -> T
+where
+    T: Copy + AsRef<[u8]>,
+    A: Algorithm<T> + Default,
+{
+    let mut a = A::default();
+
+    path.iter()
+        .enumerate()
+        .fold(leaf, |node, (height, (sibling, is_right))| {
+            a.reset();
+
+            let (left, right) = if *is_right {
+                (*sibling, node)
+            } else {
+                (node, *sibling)
+            };
+
+            a.node(left, right, height)
+        })
+}
+
+/// The index of the leaf `path` leads to.
+pub fn path_index<T>(path: &[(T, bool)]) -> usize {
+    path.iter().rev().fold(0, |acc, (_, is_right)| {
+        (acc << 1) + if *is_right { 1 } else { 0 }
+    })
+}
+
+/// Whether `path` leads to the leaf at index `challenge`, ignoring any higher bits.
+pub fn proves_challenge<T>(path: &[(T, bool)], challenge: usize) -> bool {
+    path.iter()
+        .enumerate()
+        .all(|(height, (_, is_right))| ((challenge >> height) & 1 == 1) == *is_right)
+}
+
+/// Whether `leaf` is included at `node` of the tree with `root`.
+pub fn validate<T, A>(root: &T, leaf: T, path: &[(T, bool)], node: usize) -> bool
+where
+    T: Copy + PartialEq + AsRef<[u8]>,
+    A: Algorithm<T> + Default,
+{
+    path_index(path) == node && &compute_root::<T, A>(leaf, path) == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::hasher::{Domain, Hasher, PedersenHasher};
+    use crate::merkle::make_proof_for_test;
+
+    type H = PedersenHasher;
+
+    #[test]
+    fn test_validate_parts() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaf: <H as Hasher>::Domain = rng.gen();
+        // The leaf at index 0b101.
+        let path: Vec<_> = [true, false, true]
+            .iter()
+            .map(|is_right| (rng.gen(), *is_right))
+            .collect();
+        let root = compute_root::<_, <H as Hasher>::Function>(leaf, &path);
+
+        assert_eq!(path_index(&path), 5);
+        assert!(proves_challenge(&path, 5));
+        assert!(proves_challenge(&path, 5 + 8));
+        assert!(!proves_challenge(&path, 4));
+
+        assert!(validate::<_, <H as Hasher>::Function>(
+            &root, leaf, &path, 5
+        ));
+        assert!(!validate::<_, <H as Hasher>::Function>(
+            &root, leaf, &path, 4
+        ));
+        let other: <H as Hasher>::Domain = rng.gen();
+        assert!(!validate::<_, <H as Hasher>::Function>(
+            &root, other, &path, 5
+        ));
+
+        let proof = make_proof_for_test::<H>(root, leaf, path);
+        assert!(proof.validate(5));
+        assert_eq!(proof.root().into_bytes(), root.into_bytes());
+    }
+}
@@ -0,0 +1,215 @@
+//! Locking of label buffers into memory, within the `RLIMIT_MEMLOCK` budget of the process.
+//!
+//! With the `layer_mlock` setting, the buffers layers are labeled in are locked, so they are
+//! never swapped out in the middle of a layer. A process may only lock up to its
+//! `RLIMIT_MEMLOCK`, so locked bytes are accounted for: a buffer which would exceed the limit, or
+//! which the kernel refuses to lock, simply stays unlocked instead of failing labeling. The
+//! counters returned by `stats` show how often that happens.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static LOCKED_BYTES: AtomicU64 = AtomicU64::new(0);
+static LOCKED_REGIONS: AtomicU64 = AtomicU64::new(0);
+static DEGRADED: AtomicU64 = AtomicU64::new(0);
+
+/// The locking counters of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MlockStats {
+    /// The bytes currently locked.
+    pub locked_bytes: u64,
+    /// The regions currently locked.
+    pub locked_regions: u64,
+    /// How many regions were left unlocked, because of the budget or the kernel.
+    pub degraded: u64,
+    /// The `RLIMIT_MEMLOCK` of the process, if it is limited.
+    pub limit: Option<u64>,
+}
+
+/// Returns the current locking counters.
+pub fn stats() -> MlockStats {
+    MlockStats {
+        locked_bytes: LOCKED_BYTES.load(Ordering::SeqCst),
+        locked_regions: LOCKED_REGIONS.load(Ordering::SeqCst),
+        degraded: DEGRADED.load(Ordering::SeqCst),
+        limit: sys::limit(),
+    }
+}
+
+/// A region locked into memory, unlocked on drop.
+#[derive(Debug)]
+pub struct LockedRegion {
+    ptr: *const u8,
+    len: usize,
+    charged: u64,
+}
+
+// The region only refers to memory owned by whoever holds it.
+unsafe impl Send for LockedRegion {}
+unsafe impl Sync for LockedRegion {}
+
+/// Locks `buffer` into memory, if it fits into the remaining budget and the kernel allows it.
+/// Otherwise the buffer stays unlocked, which is counted in `MlockStats::degraded`.
+///
+/// The region must be dropped before the memory of `buffer` is released.
+pub fn try_lock(buffer: &[u8]) -> Option<LockedRegion> {
+    if buffer.is_empty() {
+        return None;
+    }
+
+    // The kernel locks whole pages.
+    let page_size = sys::page_size();
+    let start = buffer.as_ptr() as usize;
+    let first_page = start - start % page_size;
+    let end = start + buffer.len();
+    let charged = ((end - first_page + page_size - 1) / page_size * page_size) as u64;
+
+    if !reserve(charged, sys::limit()) {
+        debug!(
+            "mlock budget exhausted, leaving {} bytes unlocked ({:?})",
+            charged,
+            stats()
+        );
+        DEGRADED.fetch_add(1, Ordering::SeqCst);
+        return None;
+    }
+
+    if let Err(err) = sys::lock(buffer) {
+        warn!(
+            "failed to lock {} bytes, leaving them unlocked: {}",
+            charged, err
+        );
+        LOCKED_BYTES.fetch_sub(charged, Ordering::SeqCst);
+        DEGRADED.fetch_add(1, Ordering::SeqCst);
+        return None;
+    }
+    LOCKED_REGIONS.fetch_add(1, Ordering::SeqCst);
+
+    Some(LockedRegion {
+        ptr: buffer.as_ptr(),
+        len: buffer.len(),
+        charged,
+    })
+}
+
+/// Adds `bytes` to the locked bytes, unless that would exceed `limit`.
+fn reserve(bytes: u64, limit: Option<u64>) -> bool {
+    let mut locked = LOCKED_BYTES.load(Ordering::SeqCst);
+    loop {
+        let wanted = match locked.checked_add(bytes) {
+            Some(wanted) if limit.map(|limit| wanted <= limit).unwrap_or(true) => wanted,
+            _ => return false,
+        };
+
+        match LOCKED_BYTES.compare_exchange(locked, wanted, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return true,
+            Err(current) => locked = current,
+        }
+    }
+}
+
+impl Drop for LockedRegion {
+    fn drop(&mut self) {
+        if let Err(err) = sys::unlock(self.ptr, self.len) {
+            warn!("failed to unlock {} bytes: {}", self.len, err);
+        }
+        LOCKED_BYTES.fetch_sub(self.charged, Ordering::SeqCst);
+        LOCKED_REGIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::io;
+    use std::mem;
+
+    pub fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    /// The soft `RLIMIT_MEMLOCK`, `None` if it is unlimited.
+    pub fn limit() -> Option<u64> {
+        let mut limit: libc::rlimit = unsafe { mem::zeroed() };
+        if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+            // Without knowing the limit, lock nothing.
+            return Some(0);
+        }
+
+        if limit.rlim_cur == libc::RLIM_INFINITY {
+            None
+        } else {
+            Some(limit.rlim_cur as u64)
+        }
+    }
+
+    pub fn lock(buffer: &[u8]) -> io::Result<()> {
+        if unsafe { libc::mlock(buffer.as_ptr() as *const libc::c_void, buffer.len()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn unlock(ptr: *const u8, len: usize) -> io::Result<()> {
+        if unsafe { libc::munlock(ptr as *const libc::c_void, len) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::io;
+
+    pub fn page_size() -> usize {
+        4 << 10
+    }
+
+    /// Locking is not supported, so there is no budget.
+    pub fn limit() -> Option<u64> {
+        Some(0)
+    }
+
+    pub fn lock(_buffer: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "mlock is not supported on this platform",
+        ))
+    }
+
+    pub fn unlock(_ptr: *const u8, _len: usize) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_respects_limit() {
+        // Other tests may lock concurrently, so the limit is relative to what is locked now.
+        let locked = LOCKED_BYTES.load(Ordering::SeqCst);
+
+        assert!(!reserve(1 << 40, Some(locked)));
+        assert!(reserve(0, None));
+    }
+
+    #[test]
+    fn test_lock_degrades_gracefully() {
+        let buffer = vec![1u8; 64 << 10];
+        let before = stats();
+
+        match try_lock(&buffer) {
+            Some(region) => {
+                assert!(stats().locked_bytes >= region.charged);
+                assert!(stats().locked_regions >= 1);
+            }
+            // Over the limit of this environment, which must be counted.
+            None => assert!(stats().degraded > before.degraded),
+        }
+
+        assert!(try_lock(&[]).is_none());
+    }
+}
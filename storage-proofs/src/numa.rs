@@ -0,0 +1,323 @@
+//! NUMA placement of layer labeling.
+//!
+//! Labeling reads the labels of random parents all over the current and previous layer, so on
+//! machines with several NUMA nodes it is slowed down considerably when the labeling threads and
+//! the layer buffers end up on different nodes. Setting `FIL_PROOFS_SDR_NUMA_NODE` to the index
+//! of a node (as listed in `/sys/devices/system/node`) runs labeling on the CPUs of that node
+//! only, and binds the layer buffers to its memory. Without the setting, placement is left to
+//! the operating system.
+//!
+//! Placement is only supported on Linux. Elsewhere, setting a node fails labeling.
+
+use std::fs;
+use std::path::Path;
+
+use crate::cancel;
+use crate::error::{Error, Result};
+use crate::label_buffer::LabelBuffer;
+use crate::progress;
+use crate::settings;
+
+/// Where the NUMA nodes of the machine are listed.
+const NODES_PATH: &str = "/sys/devices/system/node";
+
+/// Returns the NUMA node labeling is placed on, `FIL_PROOFS_SDR_NUMA_NODE`, if any.
+pub fn get_sdr_numa_node() -> Option<usize> {
+    settings::SETTINGS.lock().unwrap().sdr_numa_node
+}
+
+/// Returns the indices of the NUMA nodes of the machine, in order. Machines, or kernels, without
+/// NUMA support have none.
+pub fn nodes() -> Result<Vec<usize>> {
+    let path = Path::new(NODES_PATH);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut nodes: Vec<usize> = fs::read_dir(path)?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            name.to_str()?.trim_start_matches("node").parse().ok()
+        })
+        .collect();
+    nodes.sort();
+
+    Ok(nodes)
+}
+
+/// Returns the CPUs of NUMA node `node`.
+pub fn node_cpus(node: usize) -> Result<Vec<usize>> {
+    let path = Path::new(NODES_PATH).join(format!("node{}/cpulist", node));
+    let cpus = fs::read_to_string(&path)
+        .map_err(|_| Error::Numa(format!("node {} does not exist", node)))?;
+
+    parse_cpu_list(&cpus)
+}
+
+/// Parses a kernel CPU list, such as `0-3,8-11,16`.
+pub(crate) fn parse_cpu_list(list: &str) -> Result<Vec<usize>> {
+    let invalid = || Error::Numa(format!("invalid CPU list: {}", list.trim()));
+
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let start: usize = bounds
+            .next()
+            .and_then(|start| start.parse().ok())
+            .ok_or_else(invalid)?;
+        let end: usize = match bounds.next() {
+            Some(end) => end.parse().map_err(|_| invalid())?,
+            None => start,
+        };
+        if end < start {
+            return Err(invalid());
+        }
+
+        cpus.extend(start..=end);
+    }
+
+    Ok(cpus)
+}
+
+/// The NUMA node labeling runs on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    node: usize,
+    cpus: Vec<usize>,
+}
+
+impl Placement {
+    /// Places labeling on NUMA node `node`.
+    pub fn new(node: usize) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(Error::Numa("only supported on Linux".into()));
+        }
+
+        let cpus = node_cpus(node)?;
+        if cpus.is_empty() {
+            return Err(Error::Numa(format!("node {} has no CPUs", node)));
+        }
+
+        Ok(Placement { node, cpus })
+    }
+
+    /// The placement chosen by the `sdr_numa_node` setting, if any.
+    pub fn from_settings() -> Result<Option<Self>> {
+        get_sdr_numa_node().map(Placement::new).transpose()
+    }
+
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    pub fn cpus(&self) -> &[usize] {
+        &self.cpus
+    }
+
+    /// Runs `f` in a thread pool with one thread per CPU of the node, each pinned to the node, so
+    /// that `f` and the parallel iterators it uses only run there.
+    pub fn install<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send,
+        F: FnOnce() -> R + Send,
+    {
+        let cpus = self.cpus.clone();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.cpus.len())
+            .thread_name(move |i| format!("sdr-numa-{}", i))
+            .start_handler(move |_| {
+                if let Err(err) = sys::bind_current_thread(&cpus) {
+                    warn!("failed to pin labeling thread: {}", err);
+                }
+            })
+            .build()
+            .map_err(|err| Error::Numa(err.to_string()))?;
+
+        Ok(pool.install(f))
+    }
+
+    /// Binds the memory of `buffer` to the node. Pages already touched are moved there. Only the
+    /// pages wholly within the buffer are bound, those at its ends may be shared with others.
+    pub fn bind_buffer(&self, buffer: &mut [u8]) -> Result<()> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        sys::bind_memory(buffer, self.node)
+    }
+}
+
+/// Runs the labeling `f` on the NUMA node chosen by the `sdr_numa_node` setting, see `Placement`,
+/// or simply runs it when no node is set. `f` is handed the placement to bind its buffers with.
+pub fn place_labeling<R, F>(f: F) -> Result<R>
+where
+    R: Send,
+    F: FnOnce(Option<&Placement>) -> Result<R> + Send,
+{
+    match Placement::from_settings()? {
+        Some(placement) => {
+            info!(
+                "labeling on NUMA node {} (cpus {:?})",
+                placement.node(),
+                placement.cpus()
+            );
+            // The pool's threads report to the caller's observer, and are cancelled with it.
+            let progress = progress::current();
+            let token = cancel::current();
+            placement.install(|| token.install(|| progress.observe(|| f(Some(&placement)))))?
+        }
+        None => f(None),
+    }
+}
+
+/// Pins the current thread to `cpus`.
+pub(crate) fn bind_current_thread(cpus: &[usize]) -> Result<()> {
+    sys::bind_current_thread(cpus)
+}
+
+/// Allocates a zeroed buffer of `len` bytes for labels, see `LabelBuffer`, bound to the node of
+/// `placement`, if any.
+pub fn layer_buffer(placement: Option<&Placement>, len: usize) -> Result<LabelBuffer> {
+    let mut buffer = LabelBuffer::new(len)?;
+    if let Some(placement) = placement {
+        placement.bind_buffer(&mut buffer)?;
+    }
+
+    Ok(buffer)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::mem;
+
+    use crate::error::Result;
+
+    /// Allocations are only served from the given nodes.
+    const MPOL_BIND: libc::c_int = 2;
+    /// Pages already allocated elsewhere are moved.
+    const MPOL_MF_MOVE: libc::c_uint = 1 << 1;
+
+    pub fn bind_current_thread(cpus: &[usize]) -> Result<()> {
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            libc::CPU_ZERO(&mut set);
+            for cpu in cpus {
+                libc::CPU_SET(*cpu, &mut set);
+            }
+
+            if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn bind_memory(buffer: &mut [u8], node: usize) -> Result<()> {
+        let bits = 8 * mem::size_of::<libc::c_ulong>();
+        let mut node_mask: Vec<libc::c_ulong> = vec![0; node / bits + 1];
+        node_mask[node / bits] |= 1 << (node % bits);
+
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let (start, len) = match inner_pages(buffer.as_ptr() as usize, buffer.len(), page_size) {
+            Some(pages) => pages,
+            None => return Ok(()),
+        };
+
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                start,
+                len,
+                MPOL_BIND,
+                node_mask.as_ptr(),
+                // The kernel expects one more than the number of bits in the mask.
+                node_mask.len() * bits + 1,
+                MPOL_MF_MOVE,
+            )
+        };
+        if res != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the start and length of the whole pages within the `len` bytes at `start`, if
+    /// there are any. mbind works on whole pages, and the pages the buffer only partly covers
+    /// may hold other allocations, which must not be bound or moved along with it.
+    pub fn inner_pages(start: usize, len: usize, page_size: usize) -> Option<(usize, usize)> {
+        let first = (start + page_size - 1) / page_size * page_size;
+        let end = (start + len) / page_size * page_size;
+
+        if end > first {
+            Some((first, end - first))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use crate::error::{Error, Result};
+
+    pub fn bind_current_thread(_cpus: &[usize]) -> Result<()> {
+        Err(Error::Numa("only supported on Linux".into()))
+    }
+
+    pub fn bind_memory(_buffer: &mut [u8], _node: usize) -> Result<()> {
+        Err(Error::Numa("only supported on Linux".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0").unwrap(), vec![0]);
+        assert_eq!(parse_cpu_list("0-3\n").unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(parse_cpu_list("0-1,8-9,16").unwrap(), vec![0, 1, 8, 9, 16]);
+        assert!(parse_cpu_list("").unwrap().is_empty());
+
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a-b").is_err());
+        assert!(parse_cpu_list("1-").is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_inner_pages() {
+        assert_eq!(sys::inner_pages(4096, 8192, 4096), Some((4096, 8192)));
+        assert_eq!(sys::inner_pages(4000, 8400, 4096), Some((4096, 8192)));
+        assert_eq!(sys::inner_pages(4100, 8000, 4096), None);
+        assert_eq!(sys::inner_pages(100, 4000, 4096), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_placement_on_first_node() {
+        let nodes = nodes().unwrap();
+        if nodes.is_empty() {
+            // No NUMA support in this environment.
+            return;
+        }
+
+        let placement = Placement::new(nodes[0]).unwrap();
+        assert_eq!(placement.node(), nodes[0]);
+        assert!(!placement.cpus().is_empty());
+
+        let sum = placement
+            .install(|| {
+                use rayon::prelude::*;
+                (0..1000u64).into_par_iter().sum::<u64>()
+            })
+            .unwrap();
+        assert_eq!(sum, 499_500);
+
+        assert!(Placement::new(nodes[nodes.len() - 1] + 1).is_err());
+    }
+}
@@ -1,23 +1,29 @@
+use crate::circuit::shape::ShapeCS;
 use crate::error::*;
 use bellperson::groth16::Parameters;
 use bellperson::{groth16, Circuit};
+use blake2b_simd::State as Blake2b;
 use fil_sapling_crypto::jubjub::JubjubEngine;
 use fs2::FileExt;
 use itertools::Itertools;
 use rand::{SeedableRng, XorShiftRng};
 use sha2::{Digest, Sha256};
 
-use std::env;
+use std::collections::BTreeMap;
 use std::fs::{self, create_dir_all, File};
-use std::io::{self, SeekFrom};
+use std::io::{self, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 use std::time::Instant;
 
-use crate::error::Error::Unclassified;
+use crate::settings;
+use crate::util::ensure_writable;
 
-/// Bump this when circuits change to invalidate the cache.
-pub const VERSION: usize = 14;
+/// Bump this when the format of cached files or any circuit changes, to invalidate the cache.
+/// Circuits changed without a bump are caught by `CacheableParameters::check_circuit_identifier`.
+pub const VERSION: usize = 16;
 
+/// Overrides the `parameter_cache` setting, like any other setting.
 pub const PARAMETER_CACHE_ENV_VAR: &str = "FIL_PROOFS_PARAMETER_CACHE";
 
 pub const PARAMETER_CACHE_DIR: &str = "/var/tmp/filecoin-proof-parameters/";
@@ -31,15 +37,20 @@ pub const PARAMETER_METADATA_EXT: &str = "meta";
 
 pub const VERIFYING_KEY_EXT: &str = "vk";
 
+lazy_static! {
+    /// Known-good digests of cached files, keyed by file name.
+    static ref PARAMETER_DIGESTS: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+}
+
 #[derive(Debug)]
 struct LockedFile(File);
 
 // TODO: use in memory lock as well, as file locks do not guarantee exclusive access acros OSes.
 
 impl LockedFile {
-    pub fn open_exclusive_read<P: AsRef<Path>>(p: P) -> io::Result<Self> {
+    pub fn open_shared_read<P: AsRef<Path>>(p: P) -> io::Result<Self> {
         let f = fs::OpenOptions::new().read(true).open(p)?;
-        f.lock_exclusive()?;
+        f.lock_shared()?;
 
         Ok(LockedFile(f))
     }
@@ -51,6 +62,8 @@ impl LockedFile {
             .create(true)
             .open(p)?;
         f.lock_exclusive()?;
+        // Only truncate once the lock is held, so concurrent readers never see a partial file.
+        f.set_len(0)?;
 
         Ok(LockedFile(f))
     }
@@ -87,10 +100,7 @@ impl Drop for LockedFile {
 }
 
 fn parameter_cache_dir_name() -> String {
-    match env::var(PARAMETER_CACHE_ENV_VAR) {
-        Ok(dir) => dir,
-        Err(_) => String::from(PARAMETER_CACHE_DIR),
-    }
+    settings::SETTINGS.lock().unwrap().parameter_cache.clone()
 }
 
 pub fn parameter_cache_dir() -> PathBuf {
@@ -121,7 +131,58 @@ pub fn parameter_cache_verifying_key_path(parameter_set_identifier: &str) -> Pat
     ))
 }
 
-fn ensure_ancestor_dirs_exist(cache_entry_path: PathBuf) -> Result<PathBuf> {
+/// Registers known-good digests (as produced by `parameter_digest`) for cached files, keyed by file
+/// name. Whenever a file with a registered digest is loaded from the cache, its content is verified
+/// against the digest first.
+pub fn register_parameter_digests<I>(digests: I)
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    PARAMETER_DIGESTS.write().unwrap().extend(digests);
+}
+
+/// Returns the BLAKE2b digest of the file at `path`, truncated to 32 hex characters.
+pub fn parameter_digest<P: AsRef<Path>>(path: P) -> Result<String> {
+    let mut file = File::open(path)?;
+
+    digest_file(&mut file)
+}
+
+fn digest_file<R: io::Read>(file: &mut R) -> Result<String> {
+    let mut hasher = Blake2b::new();
+    io::copy(file, &mut hasher)?;
+
+    Ok(hasher.finalize().to_hex()[..32].into())
+}
+
+/// Verifies the content of an opened cache file against its registered digest, if there is one.
+fn verify_parameter_digest(cache_entry_path: &PathBuf, file: &mut LockedFile) -> Result<()> {
+    let expected = match cache_entry_path.file_name().and_then(|name| {
+        PARAMETER_DIGESTS
+            .read()
+            .unwrap()
+            .get(&*name.to_string_lossy())
+            .cloned()
+    }) {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+
+    info!("verifying digest of {:?}", cache_entry_path);
+    let digest = digest_file(file)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if digest != expected {
+        return Err(Error::ParameterDigestMismatch(format!(
+            "{:?} (expected {}, got {})",
+            cache_entry_path, expected, digest
+        )));
+    }
+
+    Ok(())
+}
+
+fn ensure_ancestor_dirs_exist(cache_entry_path: &Path) -> Result<()> {
     info!(
         "ensuring that all ancestor directories for: {:?} exist",
         cache_entry_path
@@ -131,17 +192,16 @@ fn ensure_ancestor_dirs_exist(cache_entry_path: PathBuf) -> Result<PathBuf> {
         if let Err(err) = create_dir_all(&parent_dir) {
             match err.kind() {
                 io::ErrorKind::AlreadyExists => {}
-                _ => return Err(From::from(err)),
+                _ => return Err(Error::IoAt(parent_dir.to_path_buf(), err)),
             }
         }
     } else {
-        return Err(Unclassified(format!(
-            "{:?} has no parent directory",
-            cache_entry_path
-        )));
+        return Err(Error::InvalidParameterCachePath(
+            cache_entry_path.to_path_buf(),
+        ));
     }
 
-    Ok(cache_entry_path)
+    Ok(())
 }
 
 pub trait ParameterSetMetadata: Clone {
@@ -152,6 +212,10 @@ pub trait ParameterSetMetadata: Clone {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CacheEntryMetadata {
     pub sector_size: u64,
+    /// The shape digest of the circuit the parameters were generated for, see
+    /// `CacheableParameters::circuit_digest`. Missing in metadata written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit_digest: Option<String>,
 }
 
 pub trait CacheableParameters<E, C, P>
@@ -165,9 +229,13 @@ where
     fn cache_meta(pub_params: &P) -> CacheEntryMetadata {
         CacheEntryMetadata {
             sector_size: pub_params.sector_size(),
+            circuit_digest: None,
         }
     }
 
+    /// The identifier the parameters for `pub_params` are cached under. The shape of the circuit
+    /// is not part of it, but recorded in the cached metadata when the parameters are generated,
+    /// see `check_circuit_identifier`.
     fn cache_identifier(pub_params: &P) -> String {
         let param_identifier = pub_params.identifier();
         info!("parameter set identifier for cache: {}", param_identifier);
@@ -181,24 +249,64 @@ where
         )
     }
 
-    fn get_param_metadata(_circuit: C, pub_params: &P) -> Result<CacheEntryMetadata> {
+    /// The digest of the shape of `circuit`, see `ShapeCS`. Synthesizing large circuits takes a
+    /// while, so it is only computed when parameters are generated or explicitly checked.
+    fn circuit_digest(circuit: C) -> Result<String> {
+        let mut cs = ShapeCS::<E>::new();
+        circuit.synthesize(&mut cs)?;
+
+        Ok(format!("{:02x}", cs.digest().iter().format("")))
+    }
+
+    /// Loads the metadata cached for `pub_params`, writing it with the digest of `circuit` if
+    /// there is none.
+    fn get_param_metadata(circuit: C, pub_params: &P) -> Result<CacheEntryMetadata> {
         let id = Self::cache_identifier(pub_params);
 
         // generate (or load) metadata
-        let meta_path = ensure_ancestor_dirs_exist(parameter_cache_metadata_path(&id))?;
-        read_cached_metadata(&meta_path)
-            .or_else(|_| write_cached_metadata(&meta_path, Self::cache_meta(pub_params)))
+        let meta_path = parameter_cache_metadata_path(&id);
+        read_cached_metadata(&meta_path).or_else(|_| {
+            write_cached_metadata(&meta_path, Self::circuit_meta(circuit, pub_params)?)
+        })
+    }
+
+    /// The metadata of parameters generated for `circuit`, including its digest.
+    fn circuit_meta(circuit: C, pub_params: &P) -> Result<CacheEntryMetadata> {
+        Ok(CacheEntryMetadata {
+            circuit_digest: Some(Self::circuit_digest(circuit)?),
+            ..Self::cache_meta(pub_params)
+        })
+    }
+
+    /// Checks that `circuit` is the circuit the parameters cached for `pub_params` were generated
+    /// for, by comparing its digest against the one recorded in their metadata. Fails if there is
+    /// no metadata, or if it has no digest, as metadata written by older versions does.
+    fn check_circuit_identifier(circuit: C, pub_params: &P) -> Result<()> {
+        let id = Self::cache_identifier(pub_params);
+        let meta = read_cached_metadata(&parameter_cache_metadata_path(&id))?;
+        let digest = Self::circuit_digest(circuit)?;
+
+        match meta.circuit_digest {
+            Some(ref cached) if *cached == digest => Ok(()),
+            _ => Err(Error::CircuitChanged(id)),
+        }
     }
 
-    fn get_groth_params(circuit: C, pub_params: &P) -> Result<groth16::Parameters<E>> {
-        // Always seed the rng identically so parameter generation will be deterministic.
+    /// Loads the Groth parameters of the circuit built by `blank_circuit`, generating them if
+    /// they are not cached. The circuit is only built for the generation, once for the digest in
+    /// the metadata and once for the parameters.
+    fn get_groth_params<F>(blank_circuit: F, pub_params: &P) -> Result<groth16::Parameters<E>>
+    where
+        F: Fn() -> C,
+    {
         let id = Self::cache_identifier(pub_params);
 
         let generate = || {
+            // Always seed the rng identically so parameter generation will be deterministic.
             let rng = &mut XorShiftRng::from_seed(PARAMETER_RNG_SEED);
             info!("Actually generating groth params. (id: {})", &id);
             let start = Instant::now();
-            let parameters = groth16::generate_random_parameters::<E, _, _>(circuit, rng);
+            let parameters = groth16::generate_random_parameters::<E, _, _>(blank_circuit(), rng);
             let generation_time = start.elapsed();
             info!(
                 "groth_parameter_generation_time: {:?} (id: {})",
@@ -208,33 +316,43 @@ where
         };
 
         // generate (or load) Groth parameters
-        let cache_path = ensure_ancestor_dirs_exist(parameter_cache_params_path(&id))?;
-        read_cached_params(&cache_path).or_else(|_| write_cached_params(&cache_path, generate()?))
+        let cache_path = parameter_cache_params_path(&id);
+        match read_cached_params(&cache_path) {
+            Ok(params) => Ok(params),
+            // Never silently replace published parameters which failed verification.
+            Err(err @ Error::ParameterDigestMismatch(_)) => Err(err),
+            Err(_) => {
+                // Fail before spending time on parameters which could not be stored.
+                ensure_writable(&cache_path)?;
+                let meta = Self::circuit_meta(blank_circuit(), pub_params)?;
+                write_cached_metadata(&parameter_cache_metadata_path(&id), meta)?;
+                write_cached_params(&cache_path, generate()?)
+            }
+        }
     }
 
-    fn get_verifying_key(circuit: C, pub_params: &P) -> Result<groth16::VerifyingKey<E>> {
+    fn get_verifying_key<F>(blank_circuit: F, pub_params: &P) -> Result<groth16::VerifyingKey<E>>
+    where
+        F: Fn() -> C,
+    {
         let id = Self::cache_identifier(pub_params);
 
         let generate = || -> Result<groth16::VerifyingKey<E>> {
-            let groth_params = Self::get_groth_params(circuit, pub_params)?;
+            let groth_params = Self::get_groth_params(&blank_circuit, pub_params)?;
             info!("Getting verifying key. (id: {})", &id);
             Ok(groth_params.vk)
         };
 
         // generate (or load) verifying key
-        let cache_path = ensure_ancestor_dirs_exist(parameter_cache_verifying_key_path(&id))?;
-        read_cached_verifying_key(&cache_path)
-            .or_else(|_| write_cached_verifying_key(&cache_path, generate()?))
-    }
-}
-
-fn ensure_parent(path: &PathBuf) -> Result<()> {
-    match path.parent() {
-        Some(dir) => {
-            create_dir_all(dir)?;
-            Ok(())
+        let cache_path = parameter_cache_verifying_key_path(&id);
+        match read_cached_verifying_key(&cache_path) {
+            Ok(vk) => Ok(vk),
+            Err(err @ Error::ParameterDigestMismatch(_)) => Err(err),
+            Err(_) => {
+                ensure_writable(&cache_path)?;
+                write_cached_verifying_key(&cache_path, generate()?)
+            }
         }
-        None => Ok(()),
     }
 }
 
@@ -242,7 +360,9 @@ fn read_cached_params<E: JubjubEngine>(
     cache_entry_path: &PathBuf,
 ) -> Result<groth16::Parameters<E>> {
     info!("checking cache_path: {:?} for parameters", cache_entry_path);
-    with_exclusive_read_lock(cache_entry_path, |mut f| {
+    with_shared_read_lock(cache_entry_path, |mut f| {
+        verify_parameter_digest(cache_entry_path, f)?;
+
         Parameters::read(&mut f, false)
             .map_err(Error::from)
             .map(|value| {
@@ -259,7 +379,9 @@ fn read_cached_verifying_key<E: JubjubEngine>(
         "checking cache_path: {:?} for verifying key",
         cache_entry_path
     );
-    with_exclusive_read_lock(cache_entry_path, |mut file| {
+    with_shared_read_lock(cache_entry_path, |mut file| {
+        verify_parameter_digest(cache_entry_path, file)?;
+
         groth16::VerifyingKey::read(&mut file)
             .map_err(Error::from)
             .map(|value| {
@@ -271,7 +393,7 @@ fn read_cached_verifying_key<E: JubjubEngine>(
 
 fn read_cached_metadata(cache_entry_path: &PathBuf) -> Result<CacheEntryMetadata> {
     info!("checking cache_path: {:?} for metadata", cache_entry_path);
-    with_exclusive_read_lock(cache_entry_path, |file| {
+    with_shared_read_lock(cache_entry_path, |file| {
         serde_json::from_reader(file)
             .map_err(Error::from)
             .map(|value| {
@@ -323,14 +445,16 @@ fn with_exclusive_lock<T>(
     file_path: &PathBuf,
     f: impl FnOnce(&mut LockedFile) -> Result<T>,
 ) -> Result<T> {
+    ensure_writable(file_path)?;
+    ensure_ancestor_dirs_exist(file_path)?;
     with_open_file(file_path, LockedFile::open_exclusive, f)
 }
 
-fn with_exclusive_read_lock<T>(
+fn with_shared_read_lock<T>(
     file_path: &PathBuf,
     f: impl FnOnce(&mut LockedFile) -> Result<T>,
 ) -> Result<T> {
-    with_open_file(file_path, LockedFile::open_exclusive_read, f)
+    with_open_file(file_path, LockedFile::open_shared_read, f)
 }
 
 fn with_open_file<'a, T>(
@@ -338,6 +462,59 @@ fn with_open_file<'a, T>(
     open_file: impl FnOnce(&'a PathBuf) -> io::Result<LockedFile>,
     f: impl FnOnce(&mut LockedFile) -> Result<T>,
 ) -> Result<T> {
-    ensure_parent(&file_path)?;
     f(&mut open_file(&file_path)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    #[test]
+    fn test_verify_parameter_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test-verify-parameter-digest.params");
+        fs::write(&path, b"some parameters").unwrap();
+
+        // Files without a registered digest are not verified.
+        let mut file = LockedFile::open_shared_read(&path).unwrap();
+        assert!(verify_parameter_digest(&path, &mut file).is_ok());
+        drop(file);
+
+        register_parameter_digests(vec![(
+            "test-verify-parameter-digest.params".to_string(),
+            parameter_digest(&path).unwrap(),
+        )]);
+
+        let mut file = LockedFile::open_shared_read(&path).unwrap();
+        assert!(verify_parameter_digest(&path, &mut file).is_ok());
+        drop(file);
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b" which were tampered with")
+            .unwrap();
+
+        let mut file = LockedFile::open_shared_read(&path).unwrap();
+        match verify_parameter_digest(&path, &mut file) {
+            Err(Error::ParameterDigestMismatch(_)) => {}
+            other => panic!("expected a digest mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_does_not_create_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+
+        assert!(read_cached_metadata(&missing.join("test.meta")).is_err());
+        assert!(
+            read_cached_verifying_key::<paired::bls12_381::Bls12>(&missing.join("test.vk"))
+                .is_err()
+        );
+        assert!(!missing.exists());
+    }
+}
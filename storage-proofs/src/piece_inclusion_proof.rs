@@ -65,7 +65,7 @@ impl<H: Hasher> TryFrom<&[u8]> for PieceInclusionProof<H> {
 }
 
 /// `position`, `length` are in H::Domain units
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PieceSpec {
     pub comm_p: Fr32Ary,
     pub position: usize,
@@ -145,9 +145,7 @@ pub fn generate_piece_commitment_bytes_from_source<H: Hasher>(
     }
 
     if total_bytes_read < NODE_SIZE {
-        return Err(Error::Unclassified(
-            "insufficient data to generate piece commitment".to_string(),
-        ));
+        return Err(Error::InsufficientData(total_bytes_read, NODE_SIZE));
     }
 
     let mut comm_p_bytes = [0; NODE_SIZE];
@@ -168,6 +166,20 @@ pub fn piece_inclusion_proofs<H: Hasher>(
 }
 
 impl<H: Hasher> PieceInclusionProof<H> {
+    /// Creates a proof for the piece starting at leaf `position` from the sibling subtree roots
+    /// along its path, ordered from the root of the piece up to (excluding) the root of the tree.
+    pub fn from_elements(position: usize, proof_elements: Vec<H::Domain>) -> Self {
+        PieceInclusionProof {
+            position,
+            proof_elements,
+        }
+    }
+
+    /// Returns the leaf at which the proven piece starts.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
     pub fn new(
         piece_spec: PieceSpec,
         tree: &MerkleTree<H::Domain, H::Function>,
@@ -368,8 +380,9 @@ fn subtree_capacity(pos: usize, total: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::drgraph::{new_seed, BucketGraph, Graph, BASE_DEGREE};
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
     use crate::hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher};
+    use crate::test_helper::test_rng;
     use crate::util::NODE_SIZE;
     use rand::Rng;
     use std::convert::TryInto;
@@ -442,7 +455,7 @@ mod tests {
     ) {
         assert_eq!(node_lengths.len(), 1); // For now.
         let size = nodes * NODE_SIZE;
-        let g = BucketGraph::<H>::new(nodes, BASE_DEGREE, 0, new_seed());
+        let g = BucketGraph::<H>::new(nodes, BASE_DEGREE, 0, new_seed_from(&mut test_rng()));
         let mut data = vec![0u8; size]; //Vec::<u8>::with_capacity(nodes);
 
         let data_size = node_lengths[0] * NODE_SIZE;
@@ -1,3 +1,4 @@
+use crate::data::Data;
 use crate::error::Result;
 use crate::hasher::{Domain, HashFunction, Hasher};
 use crate::merkle::MerkleTree;
@@ -51,10 +52,10 @@ pub trait PoRep<'a, H: Hasher>: ProofScheme<'a> {
     type Tau;
     type ProverAux;
 
-    fn replicate(
+    fn replicate<'b>(
         pub_params: &'a Self::PublicParams,
         replica_id: &H::Domain,
-        data: &mut [u8],
+        data: Data<'b>,
         data_tree: Option<MerkleTree<H::Domain, H::Function>>,
     ) -> Result<(Self::Tau, Self::ProverAux)>;
 
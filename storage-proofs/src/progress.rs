@@ -0,0 +1,224 @@
+//! Progress of long-running operations.
+//!
+//! Replication and proving report `PhaseEvent`s to the `SealProgress` observer installed with
+//! `observe`, so UIs can show real progress instead of parsing log lines. The observer is
+//! installed for the current thread; work moved to other threads keeps reporting to the observer
+//! of the thread that started it. Without an observer, nothing is reported.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Phase {
+    /// Labeling the layers, reported per layer.
+    Labeling,
+    /// Building the tree over the original data, `comm_d`.
+    TreeD,
+    /// Encoding the data with the keys of the last layer.
+    Encoding,
+    /// Hashing the columns and building the tree over them, `comm_c`.
+    TreeC,
+    /// Building the tree over the replica, `comm_r_last`.
+    TreeRLast,
+    /// Generating the circuit proofs of all partitions.
+    CircuitProof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PhaseEvent {
+    pub phase: Phase,
+    /// The layer being labeled (starting at 1), only set for `Phase::Labeling`.
+    pub layer: Option<usize>,
+    /// How much of the phase, or of the layer, is done, from 0 to 100. Every phase starts with 0
+    /// and, unless it fails, ends with 100.
+    pub percent: u8,
+}
+
+/// Receives the progress of long-running operations. It is called from the threads doing the
+/// work, so it should return quickly.
+pub trait SealProgress: Send + Sync {
+    fn on_event(&self, event: PhaseEvent);
+}
+
+impl<F: Fn(PhaseEvent) + Send + Sync> SealProgress for F {
+    fn on_event(&self, event: PhaseEvent) {
+        self(event)
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Progress> = RefCell::new(Progress::default());
+}
+
+/// Runs `f`, reporting the progress of everything it does to `observer`.
+pub fn observe<R, F: FnOnce() -> R>(observer: Arc<dyn SealProgress>, f: F) -> R {
+    Progress(Some(observer)).observe(f)
+}
+
+/// The observer of the current thread. Capture it before moving work to other threads, and
+/// report to it or `observe` with it there.
+pub fn current() -> Progress {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// An observer, if any.
+#[derive(Clone, Default)]
+pub struct Progress(Option<Arc<dyn SealProgress>>);
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Progress")
+            .field(&self.0.as_ref().map(|_| "SealProgress"))
+            .finish()
+    }
+}
+
+impl Progress {
+    pub fn report(&self, phase: Phase, layer: Option<usize>, percent: u8) {
+        if let Some(observer) = &self.0 {
+            observer.on_event(PhaseEvent {
+                phase,
+                layer,
+                percent,
+            });
+        }
+    }
+
+    /// Runs `f` with this as the observer of the current thread, see `current`.
+    pub fn observe<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let previous = CURRENT.with(|current| current.replace(self.clone()));
+        // Restored on unwinding too, the thread may be reused by a pool.
+        let _restore = Restore(previous);
+
+        f()
+    }
+}
+
+struct Restore(Progress);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = mem::replace(&mut self.0, Progress::default());
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Reports the progress of a phase made of `total` steps, which may be done in parallel. Reports
+/// 0 when created, and then every time another percent is done.
+#[derive(Debug)]
+pub struct Tracker {
+    progress: Progress,
+    phase: Phase,
+    layer: Option<usize>,
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl Tracker {
+    /// Reports to the observer of the current thread.
+    pub fn new(phase: Phase, layer: Option<usize>, total: usize) -> Self {
+        let progress = current();
+        progress.report(phase, layer, 0);
+
+        Tracker {
+            progress,
+            phase,
+            layer,
+            total,
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    /// Marks `steps` more steps as done. Parallel steps may report their percentages out of
+    /// order.
+    pub fn advance(&self, steps: usize) {
+        if self.progress.0.is_none() {
+            return;
+        }
+
+        let before = self.done.fetch_add(steps, Ordering::Relaxed);
+        let percent = self.percent(before + steps);
+        if percent > self.percent(before) {
+            self.progress.report(self.phase, self.layer, percent);
+        }
+    }
+
+    fn percent(&self, done: usize) -> u8 {
+        if self.total == 0 || done >= self.total {
+            return 100;
+        }
+
+        (done as u128 * 100 / self.total as u128) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    fn recorder() -> (Arc<dyn SealProgress>, Arc<Mutex<Vec<PhaseEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let observer = move |event: PhaseEvent| recorded.lock().unwrap().push(event);
+
+        (Arc::new(observer), events)
+    }
+
+    #[test]
+    fn test_observe_follows_work_to_other_threads() {
+        let (observer, events) = recorder();
+
+        observe(observer, || {
+            current().report(Phase::TreeD, None, 0);
+
+            let progress = current();
+            std::thread::spawn(move || {
+                progress.observe(|| current().report(Phase::TreeD, None, 100))
+            })
+            .join()
+            .unwrap();
+
+            // A thread without an observer reports nothing.
+            std::thread::spawn(|| current().report(Phase::TreeC, None, 100))
+                .join()
+                .unwrap();
+        });
+
+        // The observer is gone once `observe` returns.
+        current().report(Phase::TreeC, None, 0);
+
+        let percents: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| (event.phase, event.percent))
+            .collect();
+        assert_eq!(percents, vec![(Phase::TreeD, 0), (Phase::TreeD, 100)]);
+    }
+
+    #[test]
+    fn test_tracker() {
+        let (observer, events) = recorder();
+
+        observe(observer, || {
+            let tracker = Tracker::new(Phase::Labeling, Some(2), 400);
+            for _ in 0..400 {
+                tracker.advance(1);
+            }
+        });
+
+        let events = events.lock().unwrap();
+        let percents: Vec<_> = events.iter().map(|event| event.percent).collect();
+        assert_eq!(percents, (0..=100).collect::<Vec<_>>());
+        assert!(events
+            .iter()
+            .all(|event| event.phase == Phase::Labeling && event.layer == Some(2)));
+    }
+}
@@ -95,6 +95,15 @@ pub trait ProofScheme<'a> {
     ) -> bool {
         true
     }
+
+    /// The fewest partitions which satisfy `requirements`, see `satisfies_requirements`, or
+    /// `None` if no number of partitions does.
+    fn minimum_partitions(
+        _pub_params: &Self::PublicParams,
+        _requirements: &Self::Requirements,
+    ) -> Option<usize> {
+        Some(1)
+    }
 }
 
 #[derive(Default)]
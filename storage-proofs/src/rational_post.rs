@@ -5,6 +5,7 @@ use byteorder::{ByteOrder, LittleEndian};
 use serde::de::Deserialize;
 use serde::ser::Serialize;
 
+use crate::challenge_hash::RATIONAL_POST_CHALLENGE_HASH;
 use crate::drgraph::graph_height;
 use crate::error::{Error, Result};
 use crate::hasher::{Domain, Hasher};
@@ -24,7 +25,8 @@ pub struct SetupParams {
     pub challenges_count: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct PublicParams {
     /// The size of a sector.
     pub sector_size: u64,
@@ -46,6 +48,18 @@ impl ParameterSetMetadata for PublicParams {
     }
 }
 
+impl PublicParams {
+    /// The parameters as pretty printed JSON, with the fields in kebab case.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(Into::into)
+    }
+
+    /// Parses parameters written by `to_json`, rejecting missing or unknown fields.
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(Into::into)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PublicInputs<'a, T: 'a + Domain> {
     /// The challenges, which leafs to prove.
@@ -147,7 +161,7 @@ impl<'a, H: 'a + Hasher> ProofScheme<'a> for RationalPoSt<'a, H> {
 
                 if let Some(tree) = priv_inputs.trees.get(&challenge.sector) {
                     if comm_r_last != &tree.root() {
-                        return Err(Error::InvalidCommitment);
+                        return Err(Error::InvalidCommitment("comm_r_last"));
                     }
 
                     Ok(MerkleProof::new_from_proof(
@@ -190,6 +204,11 @@ impl<'a, H: 'a + Hasher> ProofScheme<'a> for RationalPoSt<'a, H> {
         {
             let challenged_leaf = challenge.leaf;
 
+            // faulty sectors must never be challenged
+            if pub_inputs.faults.contains(&challenge.sector) {
+                return Ok(false);
+            }
+
             // verify that H(Comm_c || Comm_r_last) == Comm_R
             // comm_r_last is the root of the proof
             let comm_r_last = merkle_proof.root();
@@ -264,12 +283,8 @@ fn derive_challenge(
     sector_size: u64,
     sectors: &OrderedSectorSet,
 ) -> Challenge {
-    let mut data = seed.to_vec();
-    data.extend_from_slice(&n.to_le_bytes()[..]);
-    data.extend_from_slice(&attempt.to_le_bytes()[..]);
-
-    let hash = blake2b_simd::blake2b(&data);
-    let challenge_bytes = hash.as_bytes();
+    let challenge_bytes =
+        RATIONAL_POST_CHALLENGE_HASH.hash(&[seed, &n.to_le_bytes(), &attempt.to_le_bytes()]);
     let sector_challenge = LittleEndian::read_u64(&challenge_bytes[..8]);
     let leaf_challenge = LittleEndian::read_u64(&challenge_bytes[8..16]);
 
@@ -291,7 +306,7 @@ mod tests {
     use paired::bls12_381::{Bls12, Fr};
     use rand::{Rng, SeedableRng, XorShiftRng};
 
-    use crate::drgraph::{new_seed, BucketGraph, Graph, BASE_DEGREE};
+    use crate::drgraph::{new_seed_from, BucketGraph, Graph, BASE_DEGREE};
     use crate::fr32::fr_into_bytes;
     use crate::hasher::{Blake2sHasher, HashFunction, PedersenHasher, Sha256Hasher};
     use crate::merkle::make_proof_for_test;
@@ -315,8 +330,8 @@ mod tests {
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
 
-        let graph1 = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed());
-        let graph2 = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph1 = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
+        let graph2 = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree1 = graph1.merkle_tree(data1.as_slice()).unwrap();
         let tree2 = graph2.merkle_tree(data2.as_slice()).unwrap();
 
@@ -378,6 +393,19 @@ mod tests {
             .expect("verification failed");
 
         assert!(is_valid);
+
+        // The same proof is rejected once the challenged sector is declared faulty.
+        let mut more_faults = faults.clone();
+        more_faults.insert(891.into());
+        let faulty_pub_inputs = PublicInputs {
+            faults: &more_faults,
+            ..pub_inputs
+        };
+
+        let is_valid = RationalPoSt::<H>::verify(&pub_params, &faulty_pub_inputs, &proof)
+            .expect("verification failed");
+
+        assert!(!is_valid);
     }
 
     #[test]
@@ -429,7 +457,7 @@ mod tests {
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
 
-        let graph = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree = graph.merkle_tree(data.as_slice()).unwrap();
         let seed = (0..32).map(|_| rng.gen()).collect::<Vec<u8>>();
 
@@ -502,7 +530,7 @@ mod tests {
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
 
-        let graph = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed());
+        let graph = BucketGraph::<H>::new(32, BASE_DEGREE, 0, new_seed_from(rng));
         let tree = graph.merkle_tree(data.as_slice()).unwrap();
         let seed = (0..32).map(|_| rng.gen()).collect::<Vec<u8>>();
         let mut faults = OrderedSectorSet::new();
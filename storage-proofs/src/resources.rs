@@ -0,0 +1,213 @@
+//! Estimates of the memory and disk replication needs, and a preflight check of the memory.
+//!
+//! Replication fails right away with `Error::InsufficientMemory` if the memory available on the
+//! machine is below the estimated peak, instead of being killed, or swapping for hours, halfway
+//! through labeling. The `force_low_memory` setting skips the check. Where the available memory
+//! can't be queried (outside of Linux), nothing is checked.
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::settings;
+use crate::stacked::layers_in_memory;
+
+/// Where the labels of the layers are kept, see `LayerStore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LayerStorage {
+    Memory,
+    Disk,
+}
+
+impl LayerStorage {
+    /// The storage the `layer_store` setting chooses for layers of `layer_size` bytes.
+    pub fn from_settings(layer_size: usize) -> Result<Self> {
+        if layers_in_memory(layer_size)? {
+            Ok(LayerStorage::Memory)
+        } else {
+            Ok(LayerStorage::Disk)
+        }
+    }
+}
+
+/// The peak resources of a phase, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PhaseResources {
+    pub memory: u64,
+    pub disk: u64,
+    pub gpu_memory: u64,
+}
+
+/// The resources replication needs, per phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ReplicationResources {
+    /// Labeling the layers, while the data tree is built.
+    pub labeling: PhaseResources,
+    /// Encoding the replica and building the column and replica trees.
+    pub trees: PhaseResources,
+    /// Generating the vanilla proofs, which keeps the layers and trees open.
+    pub vanilla_proof: PhaseResources,
+}
+
+impl ReplicationResources {
+    pub fn peak_memory(&self) -> u64 {
+        self.phases()
+            .iter()
+            .map(|phase| phase.memory)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn peak_disk(&self) -> u64 {
+        self.phases()
+            .iter()
+            .map(|phase| phase.disk)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn phases(&self) -> [PhaseResources; 3] {
+        [self.labeling, self.trees, self.vanilla_proof]
+    }
+}
+
+/// Estimates the resources needed to replicate, and prove, a sector of `sector_bytes` with
+/// `layers` layers.
+///
+/// The estimate counts the buffers of the implementation: two layers while labeling, the layers
+/// themselves in the chosen `storage`, the column hashes, and three trees of twice the sector
+/// size, which are kept in memory with the `mem-trees` feature and in files otherwise. The replica
+/// itself is a file. Buffers of the allocator, the parent cache and the page cache come on top.
+pub fn estimate_replication(
+    sector_bytes: u64,
+    layers: usize,
+    storage: LayerStorage,
+) -> ReplicationResources {
+    let layers_bytes = layers as u64 * sector_bytes;
+    let (layers_memory, layers_disk) = match storage {
+        LayerStorage::Memory => (layers_bytes, 0),
+        LayerStorage::Disk => (0, layers_bytes),
+    };
+
+    let tree_bytes = 2 * sector_bytes;
+    let (tree_memory, tree_disk) = if cfg!(feature = "mem-trees") {
+        (tree_bytes, 0)
+    } else {
+        (0, tree_bytes)
+    };
+
+    let trees_disk = sector_bytes + layers_disk + 3 * tree_disk;
+
+    ReplicationResources {
+        labeling: PhaseResources {
+            memory: 2 * sector_bytes + layers_memory + tree_memory,
+            disk: sector_bytes + layers_disk + tree_disk,
+            gpu_memory: 0,
+        },
+        trees: PhaseResources {
+            memory: layers_memory + sector_bytes + 3 * tree_memory,
+            disk: trees_disk,
+            gpu_memory: 0,
+        },
+        vanilla_proof: PhaseResources {
+            memory: layers_memory + 3 * tree_memory,
+            disk: trees_disk,
+            gpu_memory: 0,
+        },
+    }
+}
+
+/// Fails with `Error::InsufficientMemory` if less than `required` bytes of memory are available,
+/// unless the `force_low_memory` setting is set.
+pub fn ensure_memory(required: u64) -> Result<()> {
+    if settings::SETTINGS.lock().unwrap().force_low_memory {
+        return Ok(());
+    }
+
+    match available_memory() {
+        Some(available) if available < required => {
+            Err(Error::InsufficientMemory(required, available))
+        }
+        Some(_) => Ok(()),
+        None => {
+            debug!(
+                "available memory unknown, not checking for {} bytes",
+                required
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The memory available for new allocations without swapping, as estimated by the kernel.
+pub fn available_memory() -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+
+    std::fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|meminfo| parse_mem_available(&meminfo))
+}
+
+/// Parses `MemAvailable` from the contents of `/proc/meminfo`.
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let mut fields = line["MemAvailable:".len()..].split_whitespace();
+    let value: u64 = fields.next()?.parse().ok()?;
+
+    match fields.next() {
+        Some("kB") => Some(value * 1024),
+        None => Some(value),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mem_available() {
+        let meminfo = "MemTotal:       16310476 kB\n\
+                       MemFree:          494828 kB\n\
+                       MemAvailable:    9311260 kB\n\
+                       Buffers:          792952 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(9311260 * 1024));
+        assert_eq!(parse_mem_available("MemTotal: 1 kB\n"), None);
+        assert_eq!(parse_mem_available("MemAvailable: x kB\n"), None);
+    }
+
+    #[test]
+    fn test_estimate_replication() {
+        let sector = 1 << 30;
+
+        let in_memory = estimate_replication(sector, 4, LayerStorage::Memory);
+        let on_disk = estimate_replication(sector, 4, LayerStorage::Disk);
+
+        // Layers on disk trade memory for disk, in every phase.
+        assert_eq!(
+            in_memory.labeling.memory - on_disk.labeling.memory,
+            4 * sector
+        );
+        assert_eq!(on_disk.peak_disk() - in_memory.peak_disk(), 4 * sector);
+        assert!(in_memory.peak_memory() > on_disk.peak_memory());
+        // Labeling needs two layers of buffers at least.
+        assert!(on_disk.peak_memory() >= 2 * sector);
+    }
+
+    #[test]
+    fn test_ensure_memory() {
+        assert!(ensure_memory(0).is_ok());
+
+        if available_memory().is_some() {
+            match ensure_memory(u64::max_value()) {
+                Err(Error::InsufficientMemory(required, _)) => {
+                    assert_eq!(required, u64::max_value())
+                }
+                other => panic!("expected InsufficientMemory, got {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::alloc::{self, Layout};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::slice;
+
+use crate::error::Result;
+
+/// Alignment of buffers and writes, as required for `O_DIRECT`.
+pub const WRITE_ALIGNMENT: usize = 4096;
+
+/// Writes a file from its start to its end in large blocks.
+///
+/// Data is collected in an aligned buffer and only written once a whole block is available, so
+/// the disk sees few, large, sequential writes. With `direct` set, blocks bypass the page cache
+/// (`O_DIRECT`, Linux only); a trailing partial block is then written through the page cache, as
+/// it can't satisfy the alignment requirements.
+pub struct SequentialWriter {
+    path: PathBuf,
+    file: File,
+    direct: bool,
+    buf: AlignedBuf,
+    filled: usize,
+    offset: u64,
+}
+
+impl SequentialWriter {
+    /// Opens the existing file at `path` for writing, starting at its first byte. `block_size`
+    /// is rounded up to a multiple of `WRITE_ALIGNMENT`.
+    pub fn open<P: AsRef<Path>>(path: P, block_size: usize, direct: bool) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut options = OpenOptions::new();
+        options.write(true);
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            if direct {
+                options.custom_flags(libc::O_DIRECT);
+            }
+        }
+        let file = options.open(&path)?;
+
+        let block_size = std::cmp::max(block_size, 1);
+        let block_size = (block_size + WRITE_ALIGNMENT - 1) / WRITE_ALIGNMENT * WRITE_ALIGNMENT;
+
+        Ok(SequentialWriter {
+            path,
+            file,
+            direct,
+            buf: AlignedBuf::new(block_size),
+            filled: 0,
+            offset: 0,
+        })
+    }
+
+    /// Writes out all buffered data and syncs it to disk.
+    pub fn finish(mut self) -> Result<()> {
+        if self.filled > 0 {
+            if self.direct && self.filled % WRITE_ALIGNMENT != 0 {
+                let mut file = OpenOptions::new().write(true).open(&self.path)?;
+                file.seek(SeekFrom::Start(self.offset))?;
+                file.write_all(&self.buf.as_slice()[..self.filled])?;
+                file.sync_data()?;
+            } else {
+                self.write_block()?;
+            }
+        }
+
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        self.file.write_all(&self.buf.as_slice()[..self.filled])?;
+        self.offset += self.filled as u64;
+        self.filled = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for SequentialWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = std::cmp::min(data.len(), self.buf.len() - self.filled);
+        self.buf.as_mut_slice()[self.filled..self.filled + len].copy_from_slice(&data[..len]);
+        self.filled += len;
+
+        if self.filled == self.buf.len() {
+            self.write_block()?;
+        }
+
+        Ok(len)
+    }
+
+    /// Only whole blocks are written before `finish`, so this does nothing.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A zeroed heap buffer, aligned to `WRITE_ALIGNMENT`.
+pub(crate) struct AlignedBuf {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+// The buffer is exclusively owned.
+unsafe impl Send for AlignedBuf {}
+
+impl AlignedBuf {
+    pub(crate) fn new(len: usize) -> Self {
+        let layout = Layout::from_size_align(len, WRITE_ALIGNMENT).expect("invalid buffer size");
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+
+        AlignedBuf { ptr, layout }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.layout.size()
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len()) }
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len()) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, self.layout) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn test_sequential_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("replica");
+        let data: Vec<u8> = (0..3 * WRITE_ALIGNMENT + 100).map(|i| i as u8).collect();
+
+        // The writer overwrites existing files in place, without truncating them.
+        fs::write(&path, vec![0xff; data.len() + 10]).unwrap();
+
+        let mut writer = SequentialWriter::open(&path, 2 * WRITE_ALIGNMENT, false).unwrap();
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let written = fs::read(&path).unwrap();
+        assert_eq!(&written[..data.len()], &data[..]);
+        assert_eq!(&written[data.len()..], &[0xff; 10][..]);
+    }
+}
@@ -2,6 +2,8 @@ use std::sync::Mutex;
 
 use config::{Config, ConfigError, Environment, File};
 
+use crate::parameter_cache::PARAMETER_CACHE_DIR;
+
 lazy_static! {
     pub static ref SETTINGS: Mutex<Settings> =
         Mutex::new(Settings::new().expect("invalid configuration"));
@@ -17,6 +19,27 @@ pub struct Settings {
     pub num_proving_threads: usize,
     pub replicated_trees_dir: String,
     pub pedersen_hash_exp_window_size: u32,
+    pub use_parent_cache: bool,
+    pub parent_cache: String,
+    pub verify_cache: bool,
+    pub deterministic_trees: bool,
+    pub check_tree_roots: bool,
+    pub audit_dir: String,
+    pub read_only: bool,
+    pub replica_direct_io: bool,
+    pub gpu_index: usize,
+    pub layer_store: String,
+    pub sdr_numa_node: Option<usize>,
+    pub sdr_prefetch: String,
+    pub sdr_prefetch_distance: usize,
+    pub tree_core_binding: String,
+    pub layer_huge_pages: String,
+    pub layer_mlock: bool,
+    pub layer_io: String,
+    pub parameter_cache: String,
+    pub force_low_memory: bool,
+    pub remote_prover: String,
+    pub remote_prover_timeout_secs: u64,
     // Generating MTs in parallel optimizes for speed while generating them
     // in sequence (`false`) optimizes for memory.
 }
@@ -29,6 +52,27 @@ impl Default for Settings {
             num_proving_threads: 1,
             replicated_trees_dir: "".into(),
             pedersen_hash_exp_window_size: 16,
+            use_parent_cache: false,
+            parent_cache: "/var/tmp/filecoin-parents".into(),
+            verify_cache: false,
+            deterministic_trees: false,
+            check_tree_roots: false,
+            audit_dir: "".into(),
+            read_only: false,
+            replica_direct_io: false,
+            gpu_index: 0,
+            layer_store: "auto".into(),
+            sdr_numa_node: None,
+            sdr_prefetch: "none".into(),
+            sdr_prefetch_distance: 4,
+            tree_core_binding: "none".into(),
+            layer_huge_pages: "none".into(),
+            layer_mlock: false,
+            layer_io: "buffered".into(),
+            parameter_cache: PARAMETER_CACHE_DIR.into(),
+            force_low_memory: false,
+            remote_prover: "".into(),
+            remote_prover_timeout_secs: 3600,
         }
     }
 }
@@ -40,6 +84,9 @@ impl Settings {
         s.merge(File::with_name(SETTINGS_PATH).required(false))?;
         s.merge(Environment::with_prefix("FIL_PROOFS"))?;
 
-        s.try_into()
+        let settings: Settings = s.try_into()?;
+        info!("effective settings: {:?}", settings);
+
+        Ok(settings)
     }
 }
@@ -0,0 +1,369 @@
+//! Analysis of the graphs of stacked DRG, for review of the graph construction.
+//!
+//! `StackedGraph::check_invariants` checks what labeling relies on, `StackedGraph::stats`
+//! summarizes the parents, with a greedy estimate of the depth robustness of the base graph, and
+//! `StackedGraph::write_dot` dumps small graphs for drawing with Graphviz.
+
+use std::cmp;
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::drgraph::Graph;
+use crate::error::{Error, Result};
+use crate::hasher::Hasher;
+use crate::parameter_cache::ParameterSetMetadata;
+use crate::stacked::graph::StackedGraph;
+
+/// The most nodes `StackedGraph::write_dot` writes, drawings of larger graphs are unreadable.
+pub const MAX_DOT_NODES: usize = 1 << 10;
+
+/// Statistics of the parents of a `StackedGraph`, see `StackedGraph::stats`.
+///
+/// Histograms are indexed by value: `base_in_degrees[i]` is the number of nodes which are a base
+/// parent of `i` nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphStats {
+    pub nodes: usize,
+    pub base_degree: usize,
+    pub expansion_degree: usize,
+    /// Histogram of the number of nodes each node is a base parent of. A node which is a parent
+    /// of the same node twice counts twice.
+    pub base_in_degrees: Vec<usize>,
+    /// Histogram of the number of nodes each node is an expander parent of.
+    pub exp_in_degrees: Vec<usize>,
+    /// `base_distances[i]` is the number of base edges whose parent is between `2^i` and
+    /// `2^(i + 1) - 1` nodes before its node.
+    pub base_distances: Vec<usize>,
+    /// The number of nodes with a repeated base parent, among them the first two nodes, whose
+    /// base parents are all the first node.
+    pub repeated_base_parents: usize,
+    /// The number of nodes with a repeated expander parent.
+    pub repeated_exp_parents: usize,
+    /// `exp_expansion[i]` is the fewest distinct expander parents of any aligned window of `2^i`
+    /// consecutive nodes, divided by `2^i`.
+    pub exp_expansion: Vec<f64>,
+    /// Pairs of a number of removed nodes and the length, in nodes, of the longest path through
+    /// base edges which is left. The nodes which are the base parent of the most nodes are
+    /// removed first, so the lengths are upper bounds of what an attacker can achieve.
+    pub depth_robustness: Vec<(usize, usize)>,
+}
+
+impl<H, G> StackedGraph<H, G>
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Sync + Send,
+{
+    /// Checks the invariants labeling relies on: base parents precede their node, except for
+    /// the first node, which is its own parent, and expander parents are nodes of the graph.
+    /// The expander is a permutation of the parent slots, so every node must also be the
+    /// expander parent of exactly `expansion_degree` nodes.
+    pub fn check_invariants(&self) -> Result<()> {
+        let nodes = self.size();
+        let base_degree = self.base_graph().degree();
+        let mut exp_children = vec![0; nodes];
+
+        for (node, parents) in self.all_parents().enumerate() {
+            let (base, exp) = parents.split_at(base_degree);
+
+            if let Some(parent) = base.iter().find(|&&parent| parent >= cmp::max(node, 1)) {
+                return Err(invalid_graph(format!(
+                    "base parent {} of node {} does not precede it",
+                    parent, node
+                )));
+            }
+
+            for &parent in exp {
+                if parent >= nodes {
+                    return Err(invalid_graph(format!(
+                        "expander parent {} of node {} is not a node of the graph",
+                        parent, node
+                    )));
+                }
+                exp_children[parent] += 1;
+            }
+        }
+
+        if let Some(node) = (0..nodes).find(|&node| exp_children[node] != self.expansion_degree()) {
+            return Err(invalid_graph(format!(
+                "node {} is the expander parent of {} nodes instead of {}",
+                node,
+                exp_children[node],
+                self.expansion_degree()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Computes the statistics of the parents of all nodes. Every node is visited a few times,
+    /// so this is meant for graphs far smaller than sectors in production.
+    pub fn stats(&self) -> GraphStats {
+        let nodes = self.size();
+        let base_degree = self.base_graph().degree();
+
+        let mut base_children = vec![0; nodes];
+        let mut exp_children = vec![0; nodes];
+        let mut base_distances = Vec::new();
+        let mut repeated_base_parents = 0;
+        let mut repeated_exp_parents = 0;
+
+        for (node, parents) in self.all_parents().enumerate() {
+            let (base, exp) = parents.split_at(base_degree);
+
+            for &parent in base {
+                base_children[parent] += 1;
+                if parent < node {
+                    increment(&mut base_distances, log2(node - parent));
+                }
+            }
+            for &parent in exp {
+                exp_children[parent] += 1;
+            }
+
+            if has_repeats(base) {
+                repeated_base_parents += 1;
+            }
+            if has_repeats(exp) {
+                repeated_exp_parents += 1;
+            }
+        }
+
+        let exp_expansion = (0..=log2(nodes))
+            .map(|i| self.min_window_expansion(1 << i))
+            .collect();
+
+        // Remove the most used parents first, the earlier one if they are used equally.
+        let mut by_children: Vec<usize> = (0..nodes).collect();
+        by_children.sort_by_key(|&node| cmp::Reverse(base_children[node]));
+        let depth_robustness = [0, nodes / 16, nodes / 8, nodes / 4, nodes / 2]
+            .iter()
+            .map(|&count| {
+                let mut removed = vec![false; nodes];
+                for &node in &by_children[..count] {
+                    removed[node] = true;
+                }
+                (count, self.base_depth(&removed))
+            })
+            .collect();
+
+        GraphStats {
+            nodes,
+            base_degree,
+            expansion_degree: self.expansion_degree(),
+            base_in_degrees: histogram(&base_children),
+            exp_in_degrees: histogram(&exp_children),
+            base_distances,
+            repeated_base_parents,
+            repeated_exp_parents,
+            exp_expansion,
+            depth_robustness,
+        }
+    }
+
+    /// Writes the graph in the DOT language, with an edge from every parent to its node. Edges
+    /// from expander parents, which are nodes of the previous layer, are dashed. Fails for
+    /// graphs of more than `MAX_DOT_NODES` nodes.
+    pub fn write_dot<W: Write>(&self, mut writer: W) -> Result<()> {
+        let nodes = self.size();
+        if nodes > MAX_DOT_NODES {
+            return Err(invalid_graph(format!(
+                "{} nodes are too many to draw, at most {} are",
+                nodes, MAX_DOT_NODES
+            )));
+        }
+        let base_degree = self.base_graph().degree();
+
+        writeln!(writer, "digraph stacked {{")?;
+        writeln!(writer, "  // {}", self.identifier())?;
+        for (node, parents) in self.all_parents().enumerate() {
+            let (base, exp) = parents.split_at(base_degree);
+            for parent in base {
+                writeln!(writer, "  {} -> {};", parent, node)?;
+            }
+            for parent in exp {
+                writeln!(writer, "  {} -> {} [style=dashed];", parent, node)?;
+            }
+        }
+        writeln!(writer, "}}")?;
+
+        Ok(())
+    }
+
+    /// The parents of every node in order, base parents first.
+    fn all_parents(&self) -> impl Iterator<Item = Vec<usize>> + '_ {
+        (0..self.size()).map(move |node| {
+            let mut parents = vec![0; self.degree()];
+            self.parents(node, &mut parents);
+            parents
+        })
+    }
+
+    fn min_window_expansion(&self, window: usize) -> f64 {
+        let mut exp_parents = vec![0; self.expansion_degree()];
+
+        let fewest = (0..self.size() / window)
+            .map(|start| {
+                let mut distinct = HashSet::new();
+                for node in start * window..(start + 1) * window {
+                    self.expanded_parents(node, &mut exp_parents);
+                    distinct.extend(exp_parents.iter().cloned());
+                }
+                distinct.len()
+            })
+            .min()
+            .unwrap_or(0);
+
+        fewest as f64 / window as f64
+    }
+
+    /// The length of the longest path through base edges which avoids the `removed` nodes.
+    fn base_depth(&self, removed: &[bool]) -> usize {
+        let mut depths = vec![0; self.size()];
+        let mut parents = vec![0; self.base_graph().degree()];
+
+        for node in (0..self.size()).filter(|&node| !removed[node]) {
+            self.base_parents(node, &mut parents);
+            depths[node] = 1 + parents
+                .iter()
+                .filter(|&&parent| parent < node)
+                .map(|&parent| depths[parent])
+                .max()
+                .unwrap_or(0);
+        }
+
+        depths.into_iter().max().unwrap_or(0)
+    }
+}
+
+fn invalid_graph(msg: String) -> Error {
+    Error::InvalidGraph(msg)
+}
+
+/// The base 2 logarithm of `n`, rounded down.
+fn log2(n: usize) -> usize {
+    assert!(n > 0, "no logarithm of 0");
+    (0usize.leading_zeros() - 1 - n.leading_zeros()) as usize
+}
+
+fn increment(histogram: &mut Vec<usize>, value: usize) {
+    if histogram.len() <= value {
+        histogram.resize(value + 1, 0);
+    }
+    histogram[value] += 1;
+}
+
+fn histogram(values: &[usize]) -> Vec<usize> {
+    let mut histogram = Vec::new();
+    for &value in values {
+        increment(&mut histogram, value);
+    }
+
+    histogram
+}
+
+fn has_repeats<T: Eq + std::hash::Hash>(values: &[T]) -> bool {
+    let mut seen = HashSet::with_capacity(values.len());
+    !values.iter().all(|value| seen.insert(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::drgraph::{GraphConfig, ParentSampler, BASE_DEGREE};
+    use crate::hasher::PedersenHasher;
+    use crate::stacked::{StackedBucketGraph, EXP_DEGREE};
+
+    fn graph(nodes: usize, config: &GraphConfig) -> StackedBucketGraph<PedersenHasher> {
+        StackedBucketGraph::<PedersenHasher>::new_with_config(
+            nodes,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            [1; 7],
+            config,
+        )
+    }
+
+    #[test]
+    fn test_graph_stats() {
+        let nodes = 256;
+        let g = graph(nodes, &GraphConfig::Bucket);
+        g.check_invariants().expect("invalid graph");
+
+        let stats = g.stats();
+        let edges = |histogram: &[usize]| -> usize {
+            histogram
+                .iter()
+                .enumerate()
+                .map(|(i, count)| i * count)
+                .sum()
+        };
+        assert_eq!(edges(&stats.base_in_degrees), nodes * BASE_DEGREE);
+        assert_eq!(stats.exp_in_degrees.len(), EXP_DEGREE + 1);
+        assert_eq!(stats.exp_in_degrees[EXP_DEGREE], nodes);
+        // Only the parents of the first node are at no distance.
+        assert_eq!(
+            stats.base_distances.iter().sum::<usize>(),
+            (nodes - 1) * BASE_DEGREE
+        );
+        assert!(stats.repeated_base_parents >= 2);
+
+        assert_eq!(stats.exp_expansion.len(), 9);
+        assert!(stats.exp_expansion[0] <= EXP_DEGREE as f64);
+        assert!((stats.exp_expansion[8] - 1.0).abs() < std::f64::EPSILON);
+
+        // The predecessors chain all nodes, removing nodes can only shorten the paths.
+        assert_eq!(stats.depth_robustness[0], (0, nodes));
+        assert!(stats
+            .depth_robustness
+            .windows(2)
+            .all(|pair| pair[0].1 >= pair[1].1));
+    }
+
+    /// Makes every node its own parent.
+    #[derive(Debug)]
+    struct LoopSampler;
+
+    impl ParentSampler for LoopSampler {
+        fn identifier(&self) -> String {
+            "loop".into()
+        }
+
+        fn parents(&self, _seed: &[u32; 7], degree: usize, node: usize, parents: &mut [usize]) {
+            for parent in parents.iter_mut().take(degree) {
+                *parent = node;
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_invariants_fails() {
+        let g = graph(16, &GraphConfig::Custom(Arc::new(LoopSampler)));
+        assert!(g.check_invariants().is_err());
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let nodes = 8;
+        let g = graph(nodes, &GraphConfig::Bucket);
+
+        let mut dot = Vec::new();
+        g.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+
+        assert!(dot.starts_with("digraph stacked {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("  6 -> 7;\n"));
+        assert_eq!(dot.matches("[style=dashed]").count(), nodes * EXP_DEGREE);
+        assert_eq!(
+            dot.matches(" -> ").count(),
+            nodes * (BASE_DEGREE + EXP_DEGREE)
+        );
+
+        assert!(graph(MAX_DOT_NODES + 1, &GraphConfig::Bucket)
+            .write_dot(Vec::new())
+            .is_err());
+    }
+}
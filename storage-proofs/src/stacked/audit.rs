@@ -0,0 +1,308 @@
+//! Audit mode for stacked proving.
+//!
+//! When the `audit_dir` setting is non-empty, `prove_layers` writes the intermediate artifacts of
+//! every proof it generates into that directory as JSON, so that they can be compared step by
+//! step against an independent implementation:
+//!
+//! * `layers.json` holds the ids, the tree roots and the root of a tree built over each layer of
+//!   labels.
+//! * `partition-<k>-challenge-<i>.json` holds, for the i-th challenge of partition k, the column
+//!   of the challenged node and of all its parents together with their column hashes, and for each
+//!   proven layer the parent labels and the resulting key.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::hasher::pedersen::PedersenDomain;
+use crate::hasher::Hasher;
+use crate::merkle::build_tree;
+use crate::settings;
+use crate::stacked::{
+    challenges::LayerChallenges,
+    column_proof::ColumnProof,
+    params::{Proof, PublicInputs, TemporaryAux},
+};
+
+/// Returns the directory artifacts are dumped to, if audit mode is enabled.
+pub fn audit_dir() -> Option<PathBuf> {
+    let dir = settings::SETTINGS.lock().unwrap().audit_dir.clone();
+
+    if dir.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(dir))
+    }
+}
+
+#[derive(Serialize)]
+struct LayersAudit<D> {
+    porep_id: String,
+    replica_id: D,
+    comm_d: D,
+    comm_c: D,
+    comm_r_last: D,
+    layer_roots: Vec<D>,
+}
+
+#[derive(Serialize)]
+struct ColumnAudit<D> {
+    node: usize,
+    column_hash: PedersenDomain,
+    labels: Vec<D>,
+}
+
+#[derive(Serialize)]
+struct EncodingAudit<D> {
+    layer: usize,
+    parent_labels: Vec<D>,
+    key: D,
+}
+
+#[derive(Serialize)]
+struct ChallengeAudit<D> {
+    partition: usize,
+    challenge_index: usize,
+    challenge: usize,
+    comm_d_leaf: D,
+    comm_r_last_leaf: D,
+    column: ColumnAudit<D>,
+    drg_parents: Vec<ColumnAudit<D>>,
+    exp_parents: Vec<ColumnAudit<D>>,
+    encodings: Vec<EncodingAudit<D>>,
+}
+
+/// Writes the artifacts behind `proofs`, as returned by `prove_layers`, to `dir`.
+pub(crate) fn record<H: Hasher>(
+    dir: &Path,
+    graph_size: usize,
+    pub_inputs: &PublicInputs<H::Domain>,
+    layer_challenges: &LayerChallenges,
+    porep_id: &[u8; 32],
+    t_aux: &TemporaryAux<H>,
+    proofs: &[Vec<Proof<H>>],
+) -> Result<()> {
+    info!("writing proving audit to {}", dir.display());
+
+    record_layers(dir, porep_id, &pub_inputs.replica_id, t_aux)?;
+
+    for (k, partition_proofs) in proofs.iter().enumerate() {
+        let challenges = pub_inputs.all_challenges(layer_challenges, porep_id, graph_size, Some(k));
+
+        for (challenge_index, (challenge, proof)) in
+            challenges.into_iter().zip(partition_proofs).enumerate()
+        {
+            record_challenge(
+                dir,
+                layer_challenges,
+                porep_id,
+                &pub_inputs.replica_id,
+                k,
+                challenge_index,
+                challenge,
+                proof,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn record_layers<H: Hasher>(
+    dir: &Path,
+    porep_id: &[u8; 32],
+    replica_id: &H::Domain,
+    t_aux: &TemporaryAux<H>,
+) -> Result<()> {
+    let layer_roots = (1..=t_aux.encodings.len())
+        .map(|layer| {
+            let encoding = t_aux.encoding_at_layer(layer);
+            let tree = build_tree::<H, _>(encoding.len(), |i| encoding.read_at(i), false)?;
+            Ok(tree.root())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let audit = LayersAudit {
+        porep_id: porep_id.iter().map(|b| format!("{:02x}", b)).collect(),
+        replica_id: *replica_id,
+        comm_d: t_aux.tree_d.root(),
+        comm_c: t_aux.tree_c.root(),
+        comm_r_last: t_aux.tree_r_last.root(),
+        layer_roots,
+    };
+
+    write_json(&dir.join("layers.json"), &audit)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_challenge<H: Hasher>(
+    dir: &Path,
+    layer_challenges: &LayerChallenges,
+    porep_id: &[u8; 32],
+    replica_id: &H::Domain,
+    partition: usize,
+    challenge_index: usize,
+    challenge: usize,
+    proof: &Proof<H>,
+) -> Result<()> {
+    let rpc = &proof.replica_column_proofs;
+
+    let audit = ChallengeAudit {
+        partition,
+        challenge_index,
+        challenge,
+        comm_d_leaf: *proof.comm_d_proofs.leaf(),
+        comm_r_last_leaf: *proof.comm_r_last_proof.leaf(),
+        column: column_audit(&rpc.c_x),
+        drg_parents: rpc.drg_parents.iter().map(column_audit).collect(),
+        exp_parents: rpc.exp_parents.iter().map(column_audit).collect(),
+        encodings: layer_challenges
+            .included_layers(challenge_index)
+            .into_iter()
+            .zip(&proof.encoding_proofs)
+            .map(|(layer, encoding_proof)| EncodingAudit {
+                layer,
+                parent_labels: encoding_proof.parents.clone(),
+                key: encoding_proof.create_key(porep_id, replica_id),
+            })
+            .collect(),
+    };
+
+    write_json(
+        &dir.join(format!(
+            "partition-{}-challenge-{}.json",
+            partition, challenge_index
+        )),
+        &audit,
+    )
+}
+
+fn column_audit<H: Hasher>(proof: &ColumnProof<H>) -> ColumnAudit<H::Domain> {
+    ColumnAudit {
+        node: proof.column.index(),
+        column_hash: proof.column_hash(),
+        labels: proof.column.rows().to_vec(),
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paired::bls12_381::Bls12;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::drgporep;
+    use crate::drgraph::{new_seed_from, GraphConfig, BASE_DEGREE};
+    use crate::fr32::fr_into_bytes;
+    use crate::hasher::PedersenHasher;
+    use crate::porep::PoRep;
+    use crate::proof::ProofScheme;
+    use crate::stacked::{CommRHasher, PrivateInputs, SetupParams, StackedDrg, EXP_DEGREE};
+    use crate::util::NODE_SIZE;
+
+    #[test]
+    fn test_record_audit() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let nodes = 8;
+        let layers = 4;
+        let partitions = 2;
+        let replica_id: <PedersenHasher as Hasher>::Domain = rng.gen();
+        let mut data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let sp = SetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges: LayerChallenges::new(layers, 2),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        };
+
+        let pp = StackedDrg::<PedersenHasher>::setup(&sp).expect("setup failed");
+        let (tau, (p_aux, t_aux)) = StackedDrg::<PedersenHasher>::replicate(
+            &pp,
+            &replica_id,
+            data.as_mut_slice().into(),
+            None,
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs {
+            replica_id,
+            seed: None,
+            tau: Some(tau),
+            k: None,
+        };
+        let priv_inputs = PrivateInputs { p_aux, t_aux };
+
+        let proofs = StackedDrg::<PedersenHasher>::prove_all_partitions(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            partitions,
+        )
+        .expect("failed to generate partition proofs");
+
+        let dir = tempfile::tempdir().unwrap();
+        record(
+            dir.path(),
+            nodes,
+            &pub_inputs,
+            &sp.layer_challenges,
+            &sp.porep_id,
+            &priv_inputs.t_aux,
+            &proofs,
+        )
+        .expect("failed to record audit");
+
+        let layers_audit: serde_json::Value =
+            serde_json::from_reader(File::open(dir.path().join("layers.json")).unwrap()).unwrap();
+        assert_eq!(
+            layers_audit["layer_roots"].as_array().unwrap().len(),
+            layers
+        );
+
+        for k in 0..partitions {
+            for i in 0..proofs[k].len() {
+                let path = dir
+                    .path()
+                    .join(format!("partition-{}-challenge-{}.json", k, i));
+                let challenge_audit: serde_json::Value =
+                    serde_json::from_reader(File::open(path).unwrap()).unwrap();
+
+                assert_eq!(
+                    challenge_audit["encodings"].as_array().unwrap().len(),
+                    proofs[k][i].encoding_proofs.len()
+                );
+                assert_eq!(
+                    challenge_audit["drg_parents"].as_array().unwrap().len(),
+                    BASE_DEGREE
+                );
+            }
+        }
+    }
+}
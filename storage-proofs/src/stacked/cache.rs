@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use byteorder::{ByteOrder, LittleEndian};
+use itertools::Itertools;
+use memmap::{Mmap, MmapOptions};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+use crate::drgraph::Graph;
+use crate::error::{Error, Result};
+use crate::hasher::Hasher;
+use crate::parameter_cache::ParameterSetMetadata;
+use crate::settings;
+use crate::stacked::graph::StackedGraph;
+use crate::util::{ensure_writable, NODE_SIZE};
+
+/// Bump this when the layout of the cache file or the parents it holds change, to invalidate
+/// existing caches.
+pub const PARENT_CACHE_VERSION: usize = 2;
+
+/// Number of bytes used by the digest, stored at the beginning of every cache file.
+pub const PARENT_CACHE_DIGEST_LEN: usize = 32;
+
+/// Size of a single parent entry in the cache file.
+const PARENT_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Number of nodes for which parents are generated in one go, before being written to disk.
+const NODES_PER_CHUNK: usize = 1 << 16;
+
+/// Known-good digests of parent caches, shipped with the crate.
+const PARENT_CACHE_MANIFEST_JSON: &str = include_str!("../../parent_cache.json");
+
+/// Manifest entry describing the known-good parent cache of a PoRep, for one sector size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParentCacheManifestEntry {
+    /// Hex encoded id of the PoRep.
+    pub porep_id: String,
+    pub sector_size: u64,
+    /// Hex encoded SHA-256 digest of the cached parents.
+    pub digest: String,
+}
+
+impl ParentCacheManifestEntry {
+    pub fn new(porep_id: &[u8; 32], sector_size: u64, digest: &[u8]) -> Self {
+        ParentCacheManifestEntry {
+            porep_id: format!("{:02x}", porep_id.iter().format("")),
+            sector_size,
+            digest: format!("{:02x}", digest.iter().format("")),
+        }
+    }
+}
+
+/// The known-good parent caches, at most one per PoRep id and sector size.
+pub type ParentCacheManifest = Vec<ParentCacheManifestEntry>;
+
+lazy_static! {
+    // Memory-mapped parent caches, indexed by the path of the cache file, so that every graph
+    // with the same identifier shares a single mapping.
+    static ref PARENT_CACHE_FILES: Mutex<HashMap<PathBuf, Arc<ParentCache>>> =
+        Mutex::new(HashMap::new());
+
+    static ref PARENT_CACHE_MANIFEST: ParentCacheManifest =
+        serde_json::from_str(PARENT_CACHE_MANIFEST_JSON).expect("invalid parent cache manifest");
+}
+
+/// Returns the manifest of known-good parent cache digests.
+pub fn parent_cache_manifest() -> &'static ParentCacheManifest {
+    &*PARENT_CACHE_MANIFEST
+}
+
+/// Returns the entry of `manifest` for the parent cache of the PoRep `porep_id` with sectors of
+/// `sector_size` bytes, if it lists one.
+pub fn find_manifest_entry<'a>(
+    manifest: &'a [ParentCacheManifestEntry],
+    porep_id: &[u8; 32],
+    sector_size: u64,
+) -> Option<&'a ParentCacheManifestEntry> {
+    let porep_id = format!("{:02x}", porep_id.iter().format(""));
+
+    manifest
+        .iter()
+        .find(|entry| entry.porep_id == porep_id && entry.sector_size == sector_size)
+}
+
+/// An on-disk cache of all parents (base and expansion) of every node of a `StackedGraph`.
+///
+/// The file starts with the SHA-256 digest of the remaining bytes, followed by
+/// `nodes * degree` little endian `u32` parents, ordered by node.
+#[derive(Debug)]
+pub struct ParentCache {
+    /// Number of nodes in the graph.
+    nodes: usize,
+    /// Number of parents stored per node.
+    degree: usize,
+    /// The digest embedded in the cache file.
+    digest: [u8; PARENT_CACHE_DIGEST_LEN],
+    /// Location of the cache file.
+    path: PathBuf,
+    /// The memory-mapped cache file.
+    data: Mmap,
+}
+
+impl ParentCache {
+    /// Opens the cache at `path`, verifying its content, or generates it if the file does not
+    /// exist yet. `porep_id` is the id of the PoRep the graph belongs to, if any, see `load`.
+    pub fn generate_and_verify<H, G>(
+        path: &Path,
+        graph: &StackedGraph<H, G>,
+        porep_id: Option<&[u8; 32]>,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Sync + Send,
+    {
+        if !path.exists() {
+            Self::generate(path, graph)?;
+        }
+
+        Self::load(path, graph, porep_id, true)
+    }
+
+    /// Opens the existing cache at `path`. If the manifest lists the cache of the PoRep
+    /// `porep_id` for the sector size of the graph, the content of the cache is always hashed
+    /// and checked against it. Otherwise the content is only hashed and checked against the
+    /// embedded digest when `verify` is set.
+    pub fn load<H, G>(
+        path: &Path,
+        graph: &StackedGraph<H, G>,
+        porep_id: Option<&[u8; 32]>,
+        verify: bool,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Sync + Send,
+    {
+        let cache = Self::open(path, graph.size(), graph.degree())?;
+        let verified = cache.verify_manifest(porep_id)?;
+
+        if verify && !verified {
+            cache.verify()?;
+        }
+
+        Ok(cache)
+    }
+
+    /// Removes the cache at `path`, if any, and generates it again.
+    pub fn regenerate<H, G>(
+        path: &Path,
+        graph: &StackedGraph<H, G>,
+        porep_id: Option<&[u8; 32]>,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Sync + Send,
+    {
+        ensure_writable(path)?;
+        warn!("regenerating parent cache: {:?}", path);
+
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        Self::generate_and_verify(path, graph, porep_id)
+    }
+
+    /// Opens the cache at `path`, generating it if it does not exist and regenerating it if
+    /// it fails verification.
+    pub fn load_or_regenerate<H, G>(
+        path: &Path,
+        graph: &StackedGraph<H, G>,
+        porep_id: Option<&[u8; 32]>,
+        verify: bool,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Sync + Send,
+    {
+        if !path.exists() {
+            return Self::generate_and_verify(path, graph, porep_id);
+        }
+
+        match Self::load(path, graph, porep_id, verify) {
+            Ok(cache) => Ok(cache),
+            Err(err) => {
+                warn!("invalid parent cache: {}", err);
+                Self::regenerate(path, graph, porep_id)
+            }
+        }
+    }
+
+    /// Generates the cache file at `path`, writing it atomically. The file is written under a
+    /// unique temporary name next to `path`, so concurrent generations of the same cache never
+    /// write to the same file, and the last one to finish wins with a complete cache.
+    pub fn generate<H, G>(path: &Path, graph: &StackedGraph<H, G>) -> Result<()>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Sync + Send,
+    {
+        ensure_writable(path)?;
+        info!("generating parent cache: {:?}", path);
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(dir)?;
+
+        let nodes = graph.size();
+        let degree = graph.degree();
+        let base_degree = graph.base_graph().degree();
+
+        let mut tmp_file = NamedTempFile::new_in(dir)?;
+        let mut writer = BufWriter::new(tmp_file.as_file_mut());
+
+        // Reserve the space for the digest, which is only known at the end.
+        writer.write_all(&[0u8; PARENT_CACHE_DIGEST_LEN])?;
+
+        let mut hasher = Sha256::new();
+
+        for start in (0..nodes).step_by(NODES_PER_CHUNK) {
+            let end = std::cmp::min(start + NODES_PER_CHUNK, nodes);
+
+            let chunk: Vec<u8> = (start..end)
+                .into_par_iter()
+                .flat_map(|node| {
+                    let mut parents = vec![0; degree];
+                    graph.base_parents(node, &mut parents[..base_degree]);
+
+                    let mut expanded_parents = vec![0; graph.expansion_degree()];
+                    graph.generate_expanded_parents(node, &mut expanded_parents);
+                    for (i, parent) in expanded_parents.iter().enumerate() {
+                        parents[base_degree + i] = *parent as usize;
+                    }
+
+                    let mut bytes = vec![0u8; degree * PARENT_SIZE];
+                    for (parent, buf) in parents.iter().zip(bytes.chunks_mut(PARENT_SIZE)) {
+                        LittleEndian::write_u32(buf, *parent as u32);
+                    }
+
+                    bytes
+                })
+                .collect();
+
+            hasher.input(&chunk);
+            writer.write_all(&chunk)?;
+        }
+
+        writer.flush()?;
+        drop(writer);
+
+        let digest = hasher.result();
+
+        let file = tmp_file.as_file_mut();
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&digest)?;
+        file.sync_all()?;
+
+        tmp_file.persist(path).map_err(|err| err.error)?;
+
+        info!("generated parent cache: {:?}", path);
+
+        Ok(())
+    }
+
+    /// Memory-maps an existing cache file, without verifying its content.
+    fn open(path: &Path, nodes: usize, degree: usize) -> Result<Self> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        let expected_len = PARENT_CACHE_DIGEST_LEN + nodes * degree * PARENT_SIZE;
+        let actual_len = file.metadata()?.len() as usize;
+
+        if actual_len != expected_len {
+            return Err(Error::InvalidParentCache(format!(
+                "{:?} has size {}, expected {}",
+                path, actual_len, expected_len
+            )));
+        }
+
+        let data = unsafe { MmapOptions::new().map(&file)? };
+
+        let mut digest = [0u8; PARENT_CACHE_DIGEST_LEN];
+        digest.copy_from_slice(&data[..PARENT_CACHE_DIGEST_LEN]);
+
+        Ok(ParentCache {
+            nodes,
+            degree,
+            digest,
+            path: path.to_path_buf(),
+            data,
+        })
+    }
+
+    /// Checks the content of the cache against the embedded digest.
+    pub fn verify(&self) -> Result<()> {
+        let actual = Sha256::digest(&self.data[PARENT_CACHE_DIGEST_LEN..]);
+
+        if actual.as_slice() != &self.digest[..] {
+            return Err(Error::InvalidParentCache(format!(
+                "{:?} digest mismatch",
+                self.path
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the content of the cache against the known-good digest listed in the manifest
+    /// for the PoRep `porep_id`, see `check_manifest`.
+    pub fn verify_manifest(&self, porep_id: Option<&[u8; 32]>) -> Result<bool> {
+        self.check_manifest(parent_cache_manifest(), porep_id)
+    }
+
+    /// Hashes the content of the cache and checks it against the digest listed in `manifest`
+    /// for the PoRep `porep_id` and the sector size of the cache, and against the embedded
+    /// digest. Returns whether the content was checked: caches of graphs without a PoRep id, or
+    /// which the manifest doesn't list, can't be, and are only warned about.
+    pub fn check_manifest(
+        &self,
+        manifest: &[ParentCacheManifestEntry],
+        porep_id: Option<&[u8; 32]>,
+    ) -> Result<bool> {
+        let sector_size = (self.nodes * NODE_SIZE) as u64;
+
+        let porep_id = match porep_id {
+            Some(porep_id) => porep_id,
+            None => {
+                warn!(
+                    "parent cache {:?} has no porep id, it is not checked against the manifest",
+                    self.path
+                );
+                return Ok(false);
+            }
+        };
+        let entry = match find_manifest_entry(manifest, porep_id, sector_size) {
+            Some(entry) => entry,
+            None => {
+                warn!(
+                    "parent cache {:?} of porep id {:02x} for {} byte sectors is not in the manifest, \
+                     its content is not checked against a known-good digest",
+                    self.path,
+                    porep_id.iter().format(""),
+                    sector_size
+                );
+                return Ok(false);
+            }
+        };
+
+        let actual = Sha256::digest(&self.data[PARENT_CACHE_DIGEST_LEN..]);
+        let actual_hex = format!("{:02x}", actual.iter().format(""));
+
+        if actual_hex != entry.digest {
+            return Err(Error::InvalidParentCache(format!(
+                "{:?} content digest {} does not match manifest digest {}",
+                self.path, actual_hex, entry.digest
+            )));
+        }
+        if actual.as_slice() != &self.digest[..] {
+            return Err(Error::InvalidParentCache(format!(
+                "{:?} digest mismatch",
+                self.path
+            )));
+        }
+
+        Ok(true)
+    }
+
+    /// The digest embedded in the cache file.
+    pub fn digest(&self) -> &[u8; PARENT_CACHE_DIGEST_LEN] {
+        &self.digest
+    }
+
+    /// The digest embedded in the cache file, hex encoded as in the manifest.
+    pub fn digest_hex(&self) -> String {
+        format!("{:02x}", self.digest.iter().format(""))
+    }
+
+    /// The id of the cache, its file name without extension.
+    pub fn id(&self) -> String {
+        self.path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(ToString::to_string)
+            .unwrap_or_default()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of parents stored per node.
+    pub fn degree(&self) -> usize {
+        self.degree
+    }
+
+    /// Reads all parents of `node` into `parents`.
+    #[inline]
+    pub fn read(&self, node: usize, parents: &mut [usize]) {
+        let bytes = self.node_bytes(node);
+
+        for (parent, buf) in parents.iter_mut().zip(bytes.chunks_exact(PARENT_SIZE)) {
+            *parent = LittleEndian::read_u32(buf) as usize;
+        }
+    }
+
+    /// Reads the parents of `node`, starting at the parent with index `offset`.
+    #[inline]
+    pub fn read_from(&self, node: usize, offset: usize, parents: &mut [u32]) {
+        let bytes = &self.node_bytes(node)[offset * PARENT_SIZE..];
+
+        for (parent, buf) in parents.iter_mut().zip(bytes.chunks_exact(PARENT_SIZE)) {
+            *parent = LittleEndian::read_u32(buf);
+        }
+    }
+
+    /// Reads the parent with index `index` of `node`.
+    #[inline]
+    pub fn parent(&self, node: usize, index: usize) -> u32 {
+        let offset = index * PARENT_SIZE;
+
+        LittleEndian::read_u32(&self.node_bytes(node)[offset..offset + PARENT_SIZE])
+    }
+
+    #[inline]
+    fn node_bytes(&self, node: usize) -> &[u8] {
+        assert!(node < self.nodes, "node {} out of bounds", node);
+
+        let start = PARENT_CACHE_DIGEST_LEN + node * self.degree * PARENT_SIZE;
+        &self.data[start..start + self.degree * PARENT_SIZE]
+    }
+}
+
+/// Returns the id of the parent cache for the given graph, which is also the file name of the
+/// cache.
+pub fn parent_cache_id<H, G>(graph: &StackedGraph<H, G>) -> String
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Sync + Send,
+{
+    // The seed is not part of the graph identifier, but it does determine the parents.
+    let id = format!("{}-{:?}", graph.identifier(), graph.seed());
+    let digest = Sha256::digest(id.as_bytes());
+
+    format!("v{}-{:02x}", PARENT_CACHE_VERSION, digest.iter().format(""))
+}
+
+/// Returns the location of the parent cache for the given graph, inside the configured
+/// `parent_cache` directory.
+pub fn parent_cache_path<H, G>(graph: &StackedGraph<H, G>) -> PathBuf
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Sync + Send,
+{
+    let dir = settings::SETTINGS.lock().unwrap().parent_cache.clone();
+
+    Path::new(&dir).join(format!("{}.cache", parent_cache_id(graph)))
+}
+
+/// Returns the shared, memory-mapped parent cache for the given graph of the PoRep `porep_id`,
+/// if any, generating it on disk if needed. Caches listed in the manifest are always checked
+/// against it before use. Other caches are only checked against their embedded digest, when
+/// the `verify_cache` setting is enabled. A cache which fails either check is regenerated.
+pub fn get_parent_cache<H, G>(
+    graph: &StackedGraph<H, G>,
+    porep_id: Option<&[u8; 32]>,
+) -> Result<Arc<ParentCache>>
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Sync + Send,
+{
+    let path = parent_cache_path(graph);
+    let verify = settings::SETTINGS.lock().unwrap().verify_cache;
+
+    let mut caches = PARENT_CACHE_FILES.lock().unwrap();
+    if let Some(cache) = caches.get(&path) {
+        return Ok(cache.clone());
+    }
+
+    let cache = Arc::new(ParentCache::load_or_regenerate(
+        &path, graph, porep_id, verify,
+    )?);
+    caches.insert(path, cache.clone());
+
+    Ok(cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::Rng;
+
+    use crate::drgraph::{new_seed_from, BASE_DEGREE};
+    use crate::hasher::PedersenHasher;
+    use crate::stacked::{StackedBucketGraph, EXP_DEGREE};
+    use crate::test_helper::test_rng;
+
+    #[test]
+    fn test_parent_cache_roundtrip() {
+        let nodes = 1024;
+        let graph = StackedBucketGraph::<PedersenHasher>::new_stacked(
+            nodes,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            new_seed_from(&mut test_rng()),
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("parents.cache");
+
+        let cache =
+            ParentCache::generate_and_verify(&path, &graph, None).expect("generation failed");
+        assert_eq!(cache.degree(), graph.degree());
+
+        // Loading an existing cache verifies the digest, and must yield the same data.
+        let loaded = ParentCache::generate_and_verify(&path, &graph, None).expect("loading failed");
+        assert_eq!(cache.digest(), loaded.digest());
+
+        for node in 0..nodes {
+            let mut expected = vec![0; graph.degree()];
+            graph.parents(node, &mut expected);
+
+            let mut actual = vec![0; graph.degree()];
+            loaded.read(node, &mut actual);
+
+            assert_eq!(expected, actual, "node {}", node);
+        }
+    }
+
+    #[test]
+    fn test_parent_cache_detects_corruption() {
+        let graph = StackedBucketGraph::<PedersenHasher>::new_stacked(
+            64,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            new_seed_from(&mut test_rng()),
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("parents.cache");
+
+        ParentCache::generate(&path, &graph).expect("generation failed");
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+
+        assert!(ParentCache::generate_and_verify(&path, &graph, None).is_err());
+
+        // Without verification the corruption of a cache the manifest doesn't list goes
+        // unnoticed.
+        assert!(ParentCache::load(&path, &graph, None, false).is_ok());
+
+        // Regeneration restores a valid cache.
+        let cache = ParentCache::load_or_regenerate(&path, &graph, None, true)
+            .expect("regeneration failed");
+        assert!(cache.verify().is_ok());
+    }
+
+    #[test]
+    fn test_parent_cache_manifest() {
+        let mut rng = test_rng();
+        let nodes = 64;
+        let graph = StackedBucketGraph::<PedersenHasher>::new_stacked(
+            nodes,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            new_seed_from(&mut rng),
+        );
+        let porep_id: [u8; 32] = rng.gen();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("parents.cache");
+
+        let cache =
+            ParentCache::generate_and_verify(&path, &graph, None).expect("generation failed");
+        let sector_size = (nodes * NODE_SIZE) as u64;
+        let manifest = vec![ParentCacheManifestEntry::new(
+            &porep_id,
+            sector_size,
+            cache.digest(),
+        )];
+
+        assert_eq!(
+            find_manifest_entry(&manifest, &porep_id, sector_size),
+            Some(&manifest[0])
+        );
+        assert_eq!(
+            find_manifest_entry(&manifest, &porep_id, 2 * sector_size),
+            None
+        );
+
+        assert!(cache.check_manifest(&manifest, Some(&porep_id)).unwrap());
+        assert!(!cache.check_manifest(&manifest, Some(&[0; 32])).unwrap());
+        assert!(!cache.check_manifest(&manifest, None).unwrap());
+        drop(cache);
+
+        // Corrupt the content, and update the embedded digest to match it, which only the
+        // manifest catches.
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let digest = Sha256::digest(&bytes[PARENT_CACHE_DIGEST_LEN..]);
+        bytes[..PARENT_CACHE_DIGEST_LEN].copy_from_slice(&digest);
+        fs::write(&path, &bytes).unwrap();
+
+        let corrupted = ParentCache::load(&path, &graph, None, true).expect("loading failed");
+        assert!(corrupted
+            .check_manifest(&manifest, Some(&porep_id))
+            .is_err());
+    }
+}
@@ -1,26 +1,65 @@
-use blake2s_simd::blake2s;
-use byteorder::{LittleEndian, WriteBytesExt};
+use std::fmt;
+use std::ops::Range;
+
 use num_bigint::BigUint;
 use num_traits::cast::ToPrimitive;
 
+use crate::challenge_hash::ChallengeHash;
 use crate::hasher::Domain;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LayerChallenges {
     /// How many layers we are generating challenges for.
     layers: usize,
-    /// The maximum count of challenges (on layer 1).
+    /// The maximum count of challenges, on the layer with the most.
     max_count: usize,
+    /// How the count shrinks towards the first layer, if it is tapered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    taper: Option<Taper>,
+}
+
+/// A challenge schedule which is densest on the last layer, which ties the replica to the data,
+/// and thins out towards the first layer: every layer has `percent` percent of the challenges of
+/// the next one, rounded down, but never fewer than `floor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Taper {
+    pub percent: usize,
+    pub floor: usize,
 }
 
 impl LayerChallenges {
     pub const fn new(layers: usize, max_count: usize) -> Self {
-        LayerChallenges { layers, max_count }
+        LayerChallenges {
+            layers,
+            max_count,
+            taper: None,
+        }
+    }
+
+    /// Creates a schedule with `max_count` challenges on the last layer, tapered towards the
+    /// first one, see `Taper`.
+    pub fn new_tapered(layers: usize, max_count: usize, taper: Taper) -> Self {
+        assert!(taper.percent <= 100, "taper must not exceed 100%");
+        assert!(
+            taper.floor > 0 && taper.floor <= max_count,
+            "floor must be in 1..=max_count"
+        );
+
+        LayerChallenges {
+            layers,
+            max_count,
+            taper: Some(taper),
+        }
     }
+
     pub fn layers(&self) -> usize {
         self.layers
     }
 
+    pub fn taper(&self) -> Option<Taper> {
+        self.taper
+    }
+
     pub fn challenges_count_all(&self) -> usize {
         self.max_count
     }
@@ -29,79 +68,160 @@ impl LayerChallenges {
         assert!(layer > 0, "Layer starts at 1");
         assert!(layer <= self.layers, "Layer too large");
 
-        // TODO: proper tapering
-        if layer == 1 {
-            self.max_count
-        } else {
-            self.max_count / 2
+        match self.taper {
+            Some(taper) => (layer..self.layers).fold(self.max_count, |count, _| {
+                std::cmp::max(taper.floor, count * taper.percent / 100)
+            }),
+            // The untapered schedule halves the count after the first layer.
+            None if layer == 1 => self.max_count,
+            None => self.max_count / 2,
         }
     }
 
+    /// Returns the number of encoding proofs of a single partition, summed over all layers.
+    pub fn encoding_proofs_count(&self) -> usize {
+        (1..=self.layers)
+            .map(|layer| self.challenges_count(layer))
+            .sum()
+    }
+
     pub fn include_challenge_at_layer(&self, layer: usize, challenge_index: usize) -> bool {
         self.challenges_count(layer) > challenge_index
     }
 
+    /// Returns the layers, in ascending order, which have an encoding proof for the challenge with
+    /// the given index.
+    pub fn included_layers(&self, challenge_index: usize) -> Vec<usize> {
+        (1..=self.layers)
+            .filter(|&layer| self.include_challenge_at_layer(layer, challenge_index))
+            .collect()
+    }
+
+    /// Whether the challenge with the given index is included in any layer after the first. Only
+    /// those layers are labeled with expander parents, so only then are their columns opened.
+    pub fn needs_exp_parents(&self, challenge_index: usize) -> bool {
+        (2..=self.layers).any(|layer| self.include_challenge_at_layer(layer, challenge_index))
+    }
+
     /// Derive all challenges.
     pub fn derive_all<D: Domain>(
         &self,
+        challenge_hash: ChallengeHash,
         leaves: usize,
+        porep_id: &[u8; 32],
         replica_id: &D,
-        commitment: &D,
+        seed: &D,
         k: u8,
     ) -> Vec<usize> {
         self.derive_internal(
+            challenge_hash,
             self.challenges_count_all(),
             leaves,
+            porep_id,
             replica_id,
-            commitment,
+            seed,
             k,
         )
     }
 
     /// Derive a set of challenges, for the given inputs.
+    #[allow(clippy::too_many_arguments)]
     pub fn derive<D: Domain>(
         &self,
+        challenge_hash: ChallengeHash,
         layer: usize,
         leaves: usize,
+        porep_id: &[u8; 32],
         replica_id: &D,
-        commitment: &D,
+        seed: &D,
         k: u8,
     ) -> Vec<usize> {
         let challenges_count = self.challenges_count(layer);
-        self.derive_internal(challenges_count, leaves, replica_id, commitment, k)
+        self.derive_internal(
+            challenge_hash,
+            challenges_count,
+            leaves,
+            porep_id,
+            replica_id,
+            seed,
+            k,
+        )
     }
 
+    /// Derives the challenges of partition `k`, which are those with indices
+    /// `k * challenges_count..(k + 1) * challenges_count`.
+    #[allow(clippy::too_many_arguments)]
     pub fn derive_internal<D: Domain>(
         &self,
+        challenge_hash: ChallengeHash,
         challenges_count: usize,
         leaves: usize,
+        porep_id: &[u8; 32],
         replica_id: &D,
-        commitment: &D,
+        seed: &D,
         k: u8,
     ) -> Vec<usize> {
-        assert!(leaves > 2, "Too few leaves: {}", leaves);
-
-        (0..challenges_count)
-            .map(|i| {
-                let mut bytes = replica_id.into_bytes();
-                let j = ((challenges_count * k as usize) + i) as u32;
-                bytes.extend(commitment.into_bytes());
-
-                // Unwraping here is safe, all hash domains are larger than 4 bytes (the size of a `u32`).
-                bytes.write_u32::<LittleEndian>(j).unwrap();
-
-                let hash = blake2s(bytes.as_slice());
-                let big_challenge = BigUint::from_bytes_le(hash.as_ref());
-
-                // For now, we cannot try to prove the first or last node, so make sure the challenge
-                // can never be 0.
-                let big_mod_challenge = big_challenge % (leaves - 1);
-                let big_mod_challenge = big_mod_challenge
-                    .to_usize()
-                    .expect("`big_mod_challenge` exceeds size of `usize`");
-                big_mod_challenge + 1
-            })
-            .collect()
+        let first = challenges_count * k as usize;
+
+        derive_challenges(
+            challenge_hash,
+            leaves,
+            porep_id,
+            replica_id,
+            seed,
+            first..first + challenges_count,
+        )
+    }
+}
+
+/// Derives the challenges with the given `indices`.
+///
+/// The challenge with index `i` is `H(tag || porep_id || replica_id || seed || i)`, with `i` as
+/// little-endian u32, reduced into `1..leaves`: the first node can't be proven (yet). `H` and the
+/// tag are those of `challenge_hash`, which separates interactive from non-interactive
+/// challenges, and PoRep from PoSt ones.
+///
+/// Indices are global across partitions, so challenges don't depend on how they are split up.
+pub fn derive_challenges<D: Domain>(
+    challenge_hash: ChallengeHash,
+    leaves: usize,
+    porep_id: &[u8; 32],
+    replica_id: &D,
+    seed: &D,
+    indices: Range<usize>,
+) -> Vec<usize> {
+    assert!(leaves > 2, "Too few leaves: {}", leaves);
+
+    let replica_id = replica_id.into_bytes();
+    let seed = seed.into_bytes();
+
+    indices
+        .map(|i| {
+            let hash =
+                challenge_hash.hash(&[porep_id, &replica_id, &seed, &(i as u32).to_le_bytes()]);
+            let big_challenge = BigUint::from_bytes_le(&hash);
+
+            let big_mod_challenge = big_challenge % (leaves - 1);
+            let big_mod_challenge = big_mod_challenge
+                .to_usize()
+                .expect("`big_mod_challenge` exceeds size of `usize`");
+            big_mod_challenge + 1
+        })
+        .collect()
+}
+
+// Untapered challenges are formatted as before tapering existed, since the format is part of
+// the parameter identifiers.
+impl fmt::Debug for LayerChallenges {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("LayerChallenges");
+        debug
+            .field("layers", &self.layers)
+            .field("max_count", &self.max_count);
+        if let Some(ref taper) = self.taper {
+            debug.field("taper", taper);
+        }
+        debug.finish()
     }
 }
 
@@ -110,6 +230,21 @@ pub struct ChallengeRequirements {
     pub minimum_challenges: usize,
 }
 
+impl ChallengeRequirements {
+    /// The fewest partitions of `partition_challenges` challenges each which together have at
+    /// least `minimum_challenges`, or `None` if partitions have no challenges.
+    pub fn minimum_partitions(&self, partition_challenges: usize) -> Option<usize> {
+        if partition_challenges == 0 {
+            return None;
+        }
+
+        let partitions =
+            (self.minimum_challenges + partition_challenges - 1) / partition_challenges;
+
+        Some(std::cmp::max(partitions, 1))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -117,6 +252,12 @@ mod test {
     use rand::{thread_rng, Rng};
     use std::collections::HashMap;
 
+    use crate::challenge_hash::{
+        STACKED_DRG_INTERACTIVE_CHALLENGE_HASH, STACKED_DRG_NON_INTERACTIVE_CHALLENGE_HASH,
+    };
+
+    const HASH: ChallengeHash = STACKED_DRG_INTERACTIVE_CHALLENGE_HASH;
+
     #[test]
     fn challenge_derivation() {
         let n = 200;
@@ -125,6 +266,7 @@ mod test {
         let challenges = LayerChallenges::new(layers, n);
         let leaves = 1 << 30;
         let mut rng = thread_rng();
+        let porep_id: [u8; 32] = rng.gen();
         let replica_id: PedersenDomain = rng.gen();
         let commitment: PedersenDomain = rng.gen();
         let partitions = 5;
@@ -135,8 +277,15 @@ mod test {
         for layer in 1..=layers {
             let mut histogram = HashMap::new();
             for k in 0..partitions {
-                let challenges =
-                    challenges.derive(layer, leaves, &replica_id, &commitment, k as u8);
+                let challenges = challenges.derive(
+                    HASH,
+                    layer,
+                    leaves,
+                    &porep_id,
+                    &replica_id,
+                    &commitment,
+                    k as u8,
+                );
 
                 for challenge in challenges {
                     let counter = histogram.entry(challenge).or_insert(0);
@@ -163,6 +312,7 @@ mod test {
         let n = 40;
         let leaves = 1 << 30;
         let mut rng = thread_rng();
+        let porep_id: [u8; 32] = rng.gen();
         let replica_id: PedersenDomain = rng.gen();
         let commitment: PedersenDomain = rng.gen();
         let partitions = 5;
@@ -171,8 +321,10 @@ mod test {
 
         for layer in 1..=layers {
             let one_partition_challenges = LayerChallenges::new(layers, total_challenges).derive(
+                HASH,
                 layer,
                 leaves,
+                &porep_id,
                 &replica_id,
                 &commitment,
                 0,
@@ -180,8 +332,10 @@ mod test {
             let many_partition_challenges = (0..partitions)
                 .flat_map(|k| {
                     LayerChallenges::new(layers, n).derive(
+                        HASH,
                         layer,
                         leaves,
+                        &porep_id,
                         &replica_id,
                         &commitment,
                         k as u8,
@@ -192,4 +346,109 @@ mod test {
             assert_eq!(one_partition_challenges, many_partition_challenges);
         }
     }
+
+    #[test]
+    fn minimum_partitions() {
+        let requirements = ChallengeRequirements {
+            minimum_challenges: 10,
+        };
+
+        assert_eq!(requirements.minimum_partitions(0), None);
+        assert_eq!(requirements.minimum_partitions(1), Some(10));
+        assert_eq!(requirements.minimum_partitions(3), Some(4));
+        assert_eq!(requirements.minimum_partitions(5), Some(2));
+        assert_eq!(requirements.minimum_partitions(20), Some(1));
+        assert_eq!(
+            ChallengeRequirements::default().minimum_partitions(4),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn tapered_challenge_counts() {
+        let untapered = LayerChallenges::new(4, 10);
+        let counts: Vec<_> = (1..=4).map(|l| untapered.challenges_count(l)).collect();
+        assert_eq!(counts, vec![10, 5, 5, 5]);
+        assert_eq!(untapered.included_layers(7), vec![1]);
+        assert_eq!(
+            format!("{:?}", untapered),
+            "LayerChallenges { layers: 4, max_count: 10 }"
+        );
+
+        let tapered = LayerChallenges::new_tapered(
+            5,
+            100,
+            Taper {
+                percent: 40,
+                floor: 10,
+            },
+        );
+        let counts: Vec<_> = (1..=5).map(|l| tapered.challenges_count(l)).collect();
+        assert_eq!(counts, vec![10, 10, 16, 40, 100]);
+        assert_eq!(tapered.challenges_count_all(), 100);
+        assert_eq!(tapered.encoding_proofs_count(), 176);
+
+        assert_eq!(tapered.included_layers(0), vec![1, 2, 3, 4, 5]);
+        assert_eq!(tapered.included_layers(12), vec![3, 4, 5]);
+        assert_eq!(tapered.included_layers(99), vec![5]);
+    }
+
+    #[test]
+    fn challenges_depend_on_porep_id() {
+        let leaves = 1 << 30;
+        let mut rng = thread_rng();
+        let replica_id: PedersenDomain = rng.gen();
+        let commitment: PedersenDomain = rng.gen();
+        let challenges = LayerChallenges::new(4, 20);
+
+        let a = challenges.derive_all(HASH, leaves, &[0; 32], &replica_id, &commitment, 0);
+        let b = challenges.derive_all(HASH, leaves, &[1; 32], &replica_id, &commitment, 0);
+
+        assert_eq!(a.len(), b.len());
+        assert_ne!(a, b, "distinct porep_ids must yield distinct challenges");
+    }
+
+    #[test]
+    fn challenge_vectors() {
+        let porep_id = [1; 32];
+        let replica_id = PedersenDomain::try_from_bytes(&[2; 32]).unwrap();
+        let seed = PedersenDomain::try_from_bytes(&[3; 32]).unwrap();
+        let leaves = 1 << 30;
+
+        assert_eq!(
+            derive_challenges(
+                STACKED_DRG_INTERACTIVE_CHALLENGE_HASH,
+                leaves,
+                &porep_id,
+                &replica_id,
+                &seed,
+                0..5
+            ),
+            vec![77861676, 600701666, 252392533, 1072719771, 150782359]
+        );
+        assert_eq!(
+            derive_challenges(
+                STACKED_DRG_NON_INTERACTIVE_CHALLENGE_HASH,
+                leaves,
+                &porep_id,
+                &replica_id,
+                &seed,
+                0..5
+            ),
+            vec![143780466, 775255353, 598606344, 714376413, 107520139]
+        );
+
+        // Partition 1 of two challenges each continues with the third challenge.
+        assert_eq!(
+            LayerChallenges::new(4, 2).derive_all(
+                STACKED_DRG_INTERACTIVE_CHALLENGE_HASH,
+                leaves,
+                &porep_id,
+                &replica_id,
+                &seed,
+                1
+            ),
+            vec![252392533, 1072719771]
+        );
+    }
 }
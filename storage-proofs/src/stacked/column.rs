@@ -3,7 +3,11 @@ use std::marker::PhantomData;
 use crate::hasher::pedersen::PedersenDomain;
 use crate::hasher::Hasher;
 use crate::merkle::MerkleProof;
-use crate::stacked::{column_proof::ColumnProof, hash::hash_single_column, params::Tree};
+use crate::stacked::{
+    column_proof::ColumnProof,
+    hash::hash_single_column,
+    params::{Encodings, Tree},
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Column<H: Hasher> {
@@ -50,3 +54,56 @@ impl<H: Hasher> Column<H> {
         ColumnProof::<H>::from_column(self, inclusion_proof)
     }
 }
+
+/// A column of the label stores whose rows are read as they are needed, each at most once.
+///
+/// For callers which read some labels of a column before they open it, reading both through a
+/// `LazyColumn` reads every label only once.
+#[derive(Debug)]
+pub struct LazyColumn<'a, H: Hasher> {
+    index: usize,
+    encodings: &'a Encodings<H>,
+    rows: Vec<Option<H::Domain>>,
+}
+
+impl<'a, H: Hasher> LazyColumn<'a, H> {
+    pub fn new(index: usize, encodings: &'a Encodings<H>) -> Self {
+        LazyColumn {
+            index,
+            encodings,
+            rows: vec![None; encodings.len()],
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The label at `layer`, read from its store on first use.
+    pub fn get_node_at_layer(&mut self, layer: usize) -> H::Domain {
+        assert!(layer > 0, "layer must be greater than 0");
+        let (encodings, index) = (self.encodings, self.index);
+
+        *self.rows[layer - 1]
+            .get_or_insert_with(|| encodings.encoding_at_layer(layer).read_at(index))
+    }
+
+    /// The number of rows read so far.
+    pub fn rows_read(&self) -> usize {
+        self.rows.iter().filter(|row| row.is_some()).count()
+    }
+
+    /// Reads the rows not read yet, and returns the complete column.
+    pub fn load(mut self) -> Column<H> {
+        let rows = (1..=self.rows.len())
+            .map(|layer| self.get_node_at_layer(layer))
+            .collect();
+
+        Column::new(self.index, rows)
+    }
+
+    /// Create a column proof for this column, see `Column::into_proof`.
+    pub fn into_proof(self, tree_c: &Tree<H>) -> ColumnProof<H> {
+        self.load().into_proof(tree_c)
+    }
+}
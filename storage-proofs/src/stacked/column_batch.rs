@@ -0,0 +1,322 @@
+//! Batched column openings, to shrink serialized vanilla proofs.
+//!
+//! Every `Proof` opens the column of its challenge and those of all its parents, each with a full
+//! path into `comm_c`. Within a partition the same columns are opened many times over, and the
+//! paths of all columns share their upper nodes. `ColumnOpenings` stores every column once,
+//! together with only those siblings that can't be recomputed from the columns themselves, and
+//! rebuilds the full paths when decoded, so the decoded proofs verify as before.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+
+use merkletree::hash::Algorithm;
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+
+use crate::error::{Error, Result};
+use crate::hasher::{Domain, Hasher};
+use crate::merkle::{verify, MerkleProof};
+use crate::stacked::{
+    column::Column,
+    column_proof::ColumnProof,
+    encoding_proof::EncodingProof,
+    params::{Proof, ReplicaColumnProof},
+};
+use crate::versioned;
+
+/// Columns opened against the same `comm_c`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnOpenings<H: Hasher> {
+    root: H::Domain,
+    depth: usize,
+    /// Ordered by index, without duplicates.
+    #[serde(bound(
+        serialize = "Column<H>: Serialize",
+        deserialize = "Column<H>: Deserialize<'de>"
+    ))]
+    columns: Vec<Column<H>>,
+    /// The siblings which can't be recomputed from the columns, ordered by height and then by
+    /// index.
+    nodes: Vec<H::Domain>,
+}
+
+impl<H: Hasher> ColumnOpenings<H> {
+    /// Batches the openings of `proofs`, which must all be against the same root. Columns opened
+    /// more than once must be identical.
+    pub fn new<'a, I>(proofs: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = &'a ColumnProof<H>>,
+        H: 'a,
+    {
+        let mut proofs = proofs.into_iter().peekable();
+        let (root, depth) = match proofs.peek() {
+            Some(proof) => (*proof.root(), proof.inclusion_proof.path().len()),
+            None => return Err(Error::MalformedInput),
+        };
+
+        let mut columns = BTreeMap::new();
+        // The siblings of all paths, by height and then by index.
+        let mut siblings = vec![BTreeMap::new(); depth];
+        for proof in proofs {
+            let index = proof.column.index();
+            let path = proof.inclusion_proof.path();
+            let leaf = proof.inclusion_proof.leaf().into_bytes();
+
+            if proof.root() != &root
+                || path.len() != depth
+                || verify::path_index(path) != index
+                || leaf != AsRef::<[u8]>::as_ref(&proof.column_hash())
+            {
+                return Err(Error::MalformedInput);
+            }
+
+            if let Some(column) = columns.insert(index, proof.column.clone()) {
+                if column != proof.column {
+                    return Err(Error::MalformedInput);
+                }
+            }
+
+            for (height, (sibling, _)) in path.iter().enumerate() {
+                if let Some(other) = siblings[height].insert((index >> height) ^ 1, *sibling) {
+                    if other != *sibling {
+                        return Err(Error::MalformedInput);
+                    }
+                }
+            }
+        }
+
+        let mut known: BTreeSet<usize> = columns.keys().cloned().collect();
+        let mut nodes = Vec::new();
+        for siblings in &siblings {
+            for index in &known {
+                if !known.contains(&(index ^ 1)) {
+                    nodes.push(siblings[&(index ^ 1)]);
+                }
+            }
+            known = known.iter().map(|index| index >> 1).collect();
+        }
+
+        Ok(ColumnOpenings {
+            root,
+            depth,
+            columns: columns.into_iter().map(|(_, column)| column).collect(),
+            nodes,
+        })
+    }
+
+    pub fn root(&self) -> &H::Domain {
+        &self.root
+    }
+
+    /// The number of distinct columns.
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Rebuilds the proofs of all columns, ordered by index. Fails with
+    /// `Error::InvalidCommitment` if the columns and nodes don't hash to the root.
+    pub fn proofs(&self) -> Result<Vec<ColumnProof<H>>> {
+        let sorted = self
+            .columns
+            .windows(2)
+            .all(|pair| pair[0].index() < pair[1].index());
+        if !sorted || self.depth >= std::mem::size_of::<usize>() * 8 {
+            return Err(Error::MalformedInput);
+        }
+
+        let leaves = self
+            .columns
+            .iter()
+            .map(|column| H::Domain::try_from_bytes(column.hash().as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut level: BTreeMap<usize, H::Domain> = self
+            .columns
+            .iter()
+            .map(Column::index)
+            .zip(leaves.iter().cloned())
+            .collect();
+        let mut levels = Vec::with_capacity(self.depth);
+        let mut nodes = self.nodes.iter();
+        let mut a = H::Function::default();
+
+        for height in 0..self.depth {
+            let missing: Vec<usize> = level
+                .keys()
+                .map(|index| index ^ 1)
+                .filter(|sibling| !level.contains_key(sibling))
+                .collect();
+            for sibling in missing {
+                let node = nodes.next().ok_or(Error::MalformedInput)?;
+                level.insert(sibling, *node);
+            }
+
+            let parents = level
+                .iter()
+                .filter(|(index, _)| *index & 1 == 0)
+                .map(|(index, left)| {
+                    a.reset();
+                    (index >> 1, a.node(*left, level[&(index | 1)], height))
+                })
+                .collect();
+            levels.push(level);
+            level = parents;
+        }
+
+        if nodes.next().is_some() {
+            return Err(Error::MalformedInput);
+        }
+        if level.len() != 1 || level.get(&0) != Some(&self.root) {
+            return Err(Error::InvalidCommitment("comm_c"));
+        }
+
+        Ok(self
+            .columns
+            .iter()
+            .zip(leaves)
+            .map(|(column, leaf)| {
+                let path = levels
+                    .iter()
+                    .enumerate()
+                    .map(|(height, level)| {
+                        let index = column.index() >> height;
+                        (level[&(index ^ 1)], index & 1 == 1)
+                    })
+                    .collect();
+
+                ColumnProof::from_column(
+                    column.clone(),
+                    MerkleProof::from_parts(self.root, leaf, path),
+                )
+            })
+            .collect())
+    }
+}
+
+/// The vanilla proofs of a partition, with all their columns opened in a single batch. Encodes to
+/// a fraction of the size of the proofs themselves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchedProofs<H: Hasher> {
+    #[serde(bound(
+        serialize = "ColumnOpenings<H>: Serialize",
+        deserialize = "ColumnOpenings<H>: Deserialize<'de>"
+    ))]
+    columns: ColumnOpenings<H>,
+    #[serde(bound(
+        serialize = "BatchedChallenge<H>: Serialize",
+        deserialize = "BatchedChallenge<H>: Deserialize<'de>"
+    ))]
+    challenges: Vec<BatchedChallenge<H>>,
+}
+
+/// A `Proof` with its columns replaced by their indices.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchedChallenge<H: Hasher> {
+    #[serde(bound(
+        serialize = "MerkleProof<H>: Serialize",
+        deserialize = "MerkleProof<H>: Deserialize<'de>"
+    ))]
+    comm_d_proofs: MerkleProof<H>,
+    #[serde(bound(
+        serialize = "MerkleProof<H>: Serialize",
+        deserialize = "MerkleProof<H>: Deserialize<'de>"
+    ))]
+    comm_r_last_proof: MerkleProof<H>,
+    c_x: usize,
+    drg_parents: Vec<usize>,
+    exp_parents: Vec<usize>,
+    #[serde(bound(
+        serialize = "EncodingProof<H>: Serialize",
+        deserialize = "EncodingProof<H>: Deserialize<'de>"
+    ))]
+    encoding_proofs: Vec<EncodingProof<H>>,
+}
+
+impl<H: Hasher> BatchedProofs<H> {
+    /// Batches the proofs of a single partition, see `ColumnOpenings::new`.
+    pub fn new(proofs: &[Proof<H>]) -> Result<Self> {
+        let columns = ColumnOpenings::new(proofs.iter().flat_map(|proof| {
+            let columns = &proof.replica_column_proofs;
+            std::iter::once(&columns.c_x)
+                .chain(&columns.drg_parents)
+                .chain(&columns.exp_parents)
+        }))?;
+
+        let challenges = proofs
+            .iter()
+            .map(|proof| {
+                let columns = &proof.replica_column_proofs;
+                BatchedChallenge {
+                    comm_d_proofs: proof.comm_d_proofs.clone(),
+                    comm_r_last_proof: proof.comm_r_last_proof.clone(),
+                    c_x: columns.c_x.column.index(),
+                    drg_parents: column_indices(&columns.drg_parents),
+                    exp_parents: column_indices(&columns.exp_parents),
+                    encoding_proofs: proof.encoding_proofs.clone(),
+                }
+            })
+            .collect();
+
+        Ok(BatchedProofs {
+            columns,
+            challenges,
+        })
+    }
+
+    /// Rebuilds the proofs, with the full paths of their columns.
+    pub fn proofs(&self) -> Result<Vec<Proof<H>>> {
+        let opened: BTreeMap<usize, ColumnProof<H>> = self
+            .columns
+            .proofs()?
+            .into_iter()
+            .map(|proof| (proof.column.index(), proof))
+            .collect();
+        let open = |index: &usize| opened.get(index).cloned().ok_or(Error::MalformedInput);
+
+        self.challenges
+            .iter()
+            .map(|challenge| {
+                Ok(Proof {
+                    comm_d_proofs: challenge.comm_d_proofs.clone(),
+                    comm_r_last_proof: challenge.comm_r_last_proof.clone(),
+                    replica_column_proofs: ReplicaColumnProof {
+                        c_x: open(&challenge.c_x)?,
+                        drg_parents: challenge
+                            .drg_parents
+                            .iter()
+                            .map(open)
+                            .collect::<Result<_>>()?,
+                        exp_parents: challenge
+                            .exp_parents
+                            .iter()
+                            .map(open)
+                            .collect::<Result<_>>()?,
+                    },
+                    encoding_proofs: challenge.encoding_proofs.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the canonical, versioned encoding, as written by `write_into`.
+    pub fn serialize(&self) -> Vec<u8> {
+        versioned::to_bytes(self).expect("failed to serialize proofs")
+    }
+
+    /// Writes the canonical, versioned encoding, see `versioned`.
+    pub fn write_into<W: Write>(&self, writer: W) -> Result<()> {
+        versioned::write_into(self, writer)
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        versioned::read_from(reader)
+    }
+}
+
+fn column_indices<H: Hasher>(proofs: &[ColumnProof<H>]) -> Vec<usize> {
+    proofs.iter().map(|proof| proof.column.index()).collect()
+}
@@ -18,3 +18,42 @@ pub fn decode<T: Domain>(key: T, value: T) -> T {
     result.sub_assign(&key);
     result.into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ff::PrimeField;
+    use proptest::prelude::*;
+
+    use crate::fr32::bytes_into_fr_repr_safe;
+    use crate::hasher::{Blake2sHasher, Hasher, PedersenHasher, Sha256Hasher};
+
+    /// Masks arbitrary bytes into a field element, the way data and labels are.
+    fn masked<T: Domain>(bytes: &[u8; 32]) -> T {
+        Fr::from_repr(bytes_into_fr_repr_safe(bytes))
+            .expect("masked bytes are a field element")
+            .into()
+    }
+
+    fn encode_decode_roundtrip<H: Hasher>(key: &[u8; 32], value: &[u8; 32]) {
+        let key: H::Domain = masked(key);
+        let value: H::Domain = masked(value);
+
+        let encoded = encode(key, value);
+        assert_eq!(decode(key, encoded), value);
+        assert_eq!(
+            H::Domain::try_from_bytes(&encoded.into_bytes()).unwrap(),
+            encoded
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_encode_decode_roundtrip(key in any::<[u8; 32]>(), value in any::<[u8; 32]>()) {
+            encode_decode_roundtrip::<PedersenHasher>(&key, &value);
+            encode_decode_roundtrip::<Sha256Hasher>(&key, &value);
+            encode_decode_roundtrip::<Blake2sHasher>(&key, &value);
+        }
+    }
+}
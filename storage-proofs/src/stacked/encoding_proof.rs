@@ -1,10 +1,9 @@
 use std::marker::PhantomData;
 
-use blake2s_simd::Params as Blake2s;
-
 use crate::fr32::bytes_into_fr_repr_safe;
 use crate::hasher::Hasher;
 use crate::stacked::encode::encode;
+use crate::stacked::label::Labeler;
 use crate::util::NODE_SIZE;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,29 +23,27 @@ impl<H: Hasher> EncodingProof<H> {
         }
     }
 
-    fn create_key(&self, replica_id: &H::Domain) -> H::Domain {
-        let mut hasher = Blake2s::new().hash_length(NODE_SIZE).to_state();
-
-        // replica_id
-        hasher.update(AsRef::<[u8]>::as_ref(replica_id));
+    pub(crate) fn create_key(&self, porep_id: &[u8; 32], replica_id: &H::Domain) -> H::Domain {
+        let labeler = Labeler::new(porep_id, AsRef::<[u8]>::as_ref(replica_id), NODE_SIZE);
 
-        // node id
-        hasher.update(&(self.node as u64).to_le_bytes());
-
-        for parent in &self.parents {
-            hasher.update(AsRef::<[u8]>::as_ref(parent));
-        }
+        let mut label = [0u8; NODE_SIZE];
+        labeler.label(
+            self.node,
+            self.parents.iter().map(AsRef::<[u8]>::as_ref),
+            &mut label,
+        );
 
-        bytes_into_fr_repr_safe(hasher.finalize().as_ref()).into()
+        bytes_into_fr_repr_safe(&label).into()
     }
 
     pub fn verify(
         &self,
+        porep_id: &[u8; 32],
         replica_id: &H::Domain,
         exp_encoded_node: &H::Domain,
         decoded_node: Option<&H::Domain>,
     ) -> bool {
-        let key = self.create_key(replica_id);
+        let key = self.create_key(porep_id, replica_id);
 
         let encoded_node = if let Some(decoded_node) = decoded_node {
             encode(key, *decoded_node)
@@ -2,16 +2,46 @@ use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::sync::{Arc, RwLock};
 
-use crate::crypto::feistel::{self, FeistelPrecomputed};
-use crate::drgraph::{BucketGraph, Graph, BASE_DEGREE};
+use blake2s_simd::Params as Blake2s;
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::crypto::feistel::{self, FeistelKeys, FeistelPrecomputed};
+use crate::drgraph::{BucketGraph, Graph, GraphConfig, BASE_DEGREE};
 use crate::error::Result;
 use crate::hasher::Hasher;
 use crate::parameter_cache::ParameterSetMetadata;
 use crate::settings;
+use crate::stacked::cache;
 
 /// The expansion degree used for Stacked Graphs.
 pub const EXP_DEGREE: usize = 8;
 
+/// Derives the seed of the graph used by the PoRep identified by `porep_id` from the configured
+/// DRG seed, so that PoReps with distinct ids never share a graph or a parent cache.
+pub fn derive_graph_seed(seed: [u32; 7], porep_id: &[u8; 32]) -> [u32; 7] {
+    let mut hasher = Blake2s::new().hash_length(28).to_state();
+
+    for word in seed.iter() {
+        hasher.update(&word.to_le_bytes());
+    }
+    hasher.update(porep_id);
+
+    let hash = hasher.finalize();
+    let mut derived = [0u32; 7];
+    LittleEndian::read_u32_into(hash.as_bytes(), &mut derived);
+
+    derived
+}
+
+/// Derives the round keys of the expander permutation of a graph from its seed. As the seed of a
+/// stacked graph is derived from its PoRep id, so are the keys.
+pub fn derive_feistel_keys(seed: [u32; 7]) -> FeistelKeys {
+    let mut bytes = [0u8; 28];
+    LittleEndian::write_u32_into(&seed, &mut bytes);
+
+    feistel::derive_keys(&bytes)
+}
+
 lazy_static! {
     // This parents cache is currently used for the *expanded parents only*, generated
     // by the expensive Feistel operations in the Stacked, it doesn't contain the
@@ -69,8 +99,11 @@ where
     expansion_degree: usize,
     base_graph: G,
     feistel_precomputed: FeistelPrecomputed,
+    // Round keys of the expander permutation, derived from the seed.
+    feistel_keys: FeistelKeys,
     id: String,
     use_cache: bool,
+    parent_cache: Option<Arc<cache::ParentCache>>,
     _h: PhantomData<H>,
 }
 
@@ -79,14 +112,67 @@ pub type StackedBucketGraph<H> = StackedGraph<H, BucketGraph<H>>;
 impl<H, G> StackedGraph<H, G>
 where
     H: Hasher,
-    G: Graph<H> + ParameterSetMetadata,
+    G: Graph<H> + ParameterSetMetadata + Sync + Send,
 {
+    /// Builds the graph and, if the `use_parent_cache` setting is enabled, loads or generates its
+    /// parent cache, which fails if the cache can neither be loaded nor written.
     pub fn new(
         base_graph: Option<G>,
         nodes: usize,
         base_degree: usize,
         expansion_degree: usize,
         seed: [u32; 7],
+        porep_id: Option<&[u8; 32]>,
+    ) -> Result<Self> {
+        let mut res =
+            Self::new_without_parent_cache(base_graph, nodes, base_degree, expansion_degree, seed);
+
+        let use_parent_cache = settings::SETTINGS.lock().unwrap().use_parent_cache;
+        if use_parent_cache {
+            info!("using parent cache file");
+            assert!(nodes <= std::u32::MAX as usize);
+
+            res.parent_cache = Some(cache::get_parent_cache(&res, porep_id)?);
+        }
+
+        Ok(res)
+    }
+
+    // The `Graph` constructors can't fail, so graphs built by them compute their parents as they
+    // are needed if the parent cache can't be loaded.
+    fn new_or_without_parent_cache(
+        base_graph: G,
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        seed: [u32; 7],
+    ) -> Self {
+        Self::new(
+            Some(base_graph.clone()),
+            nodes,
+            base_degree,
+            expansion_degree,
+            seed,
+            None,
+        )
+        .unwrap_or_else(|err| {
+            warn!("not using the parent cache, it failed to load: {}", err);
+            Self::new_without_parent_cache(
+                Some(base_graph),
+                nodes,
+                base_degree,
+                expansion_degree,
+                seed,
+            )
+        })
+    }
+
+    fn new_without_parent_cache(
+        base_graph: Option<G>,
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        seed: [u32; 7],
     ) -> Self {
         if !cfg!(feature = "unchecked-degrees") {
             assert_eq!(base_degree, BASE_DEGREE, "Invalid base degree");
@@ -100,6 +186,7 @@ where
             None => G::new(nodes, base_degree, 0, seed),
         };
         let bg_id = base_graph.identifier();
+        let feistel_keys = derive_feistel_keys(base_graph.seed());
 
         let res = StackedGraph {
             base_graph,
@@ -109,7 +196,9 @@ where
             ),
             expansion_degree,
             use_cache,
+            parent_cache: None,
             feistel_precomputed: feistel::precompute((expansion_degree * nodes) as feistel::Index),
+            feistel_keys,
             _h: PhantomData,
         };
 
@@ -146,7 +235,7 @@ where
 impl<H, G> Graph<H> for StackedGraph<H, G>
 where
     H: Hasher,
-    G: Graph<H> + ParameterSetMetadata,
+    G: Graph<H> + ParameterSetMetadata + Sync + Send,
 {
     type Key = Vec<u8>;
 
@@ -160,13 +249,17 @@ where
 
     #[inline]
     fn parents(&self, raw_node: usize, parents: &mut [usize]) {
-        self.base_parents(raw_node, &mut parents[..self.base_graph().degree()]);
+        if let Some(ref parent_cache) = self.parent_cache {
+            // All parents are precomputed, no need to touch the base graph or Feistel.
+            parent_cache.read(raw_node, &mut parents[..self.degree()]);
+            return;
+        }
 
-        // expanded_parents takes raw_node
-        self.expanded_parents(raw_node, |expanded_parents| {
-            for (ii, value) in expanded_parents.iter().enumerate() {
-                parents[ii + self.base_graph().degree()] = *value as usize
-            }
+        let base_degree = self.base_graph.degree();
+        self.base_parents(raw_node, &mut parents[..base_degree]);
+
+        self.for_each_expanded_parent(raw_node, |i, parent| {
+            parents[base_degree + i] = parent as usize;
         });
 
         debug_assert!(parents.len() == self.degree());
@@ -180,6 +273,20 @@ where
         Self::new_stacked(nodes, base_degree, expansion_degree, seed)
     }
 
+    /// The construction in `config` picks the parents of the base graph, the expander is
+    /// unaffected.
+    fn new_with_config(
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        seed: [u32; 7],
+        config: &GraphConfig,
+    ) -> Self {
+        let base_graph = G::new_with_config(nodes, base_degree, 0, seed, config);
+
+        Self::new_or_without_parent_cache(base_graph, nodes, base_degree, expansion_degree, seed)
+    }
+
     fn create_key(
         &self,
         _id: &H::Domain,
@@ -195,7 +302,7 @@ where
 impl<'a, H, G> StackedGraph<H, G>
 where
     H: Hasher,
-    G: Graph<H> + ParameterSetMetadata,
+    G: Graph<H> + ParameterSetMetadata + Sync + Send,
 {
     /// Assign one parent to `node` using a Chung's construction with a reversible
     /// permutation function from a Feistel cipher (controlled by `invert_permutation`).
@@ -225,12 +332,10 @@ where
         // with indexes bigger than 2 (if in the `forward` direction, smaller than 2 if the
         // inverse), will be removed.
         let a = (node * self.expansion_degree) as feistel::Index + i as feistel::Index;
-        let feistel_keys = &[1, 2, 3, 4];
-
         let transformed = feistel::permute(
             self.size() as feistel::Index * self.expansion_degree as feistel::Index,
             a,
-            feistel_keys,
+            &self.feistel_keys,
             self.feistel_precomputed,
         );
         transformed as usize / self.expansion_degree
@@ -254,18 +359,15 @@ where
         }
     }
 
-    fn generate_expanded_parents(&self, node: usize) -> Vec<u32> {
-        let mut expanded_parents = Vec::with_capacity(self.expansion_degree);
-        // Generate half of the parents from one permutation order and the other with its inverse.
-        for i in 0..self.expansion_degree {
-            let other = self.correspondent(node, i);
-            expanded_parents.push(other as u32);
+    /// Computes the expansion parents of `node` into `expanded_parents`, bypassing all caches.
+    pub(crate) fn generate_expanded_parents(&self, node: usize, expanded_parents: &mut [u32]) {
+        for (i, parent) in expanded_parents
+            .iter_mut()
+            .take(self.expansion_degree)
+            .enumerate()
+        {
+            *parent = self.correspondent(node, i) as u32;
         }
-
-        // Add padding parents.
-        expanded_parents.resize(self.expansion_degree, 0);
-
-        expanded_parents
     }
 
     pub fn new_stacked(
@@ -274,7 +376,32 @@ where
         expansion_degree: usize,
         seed: [u32; 7],
     ) -> Self {
-        Self::new(None, nodes, base_degree, expansion_degree, seed)
+        let base_graph = G::new(nodes, base_degree, 0, seed);
+
+        Self::new_or_without_parent_cache(base_graph, nodes, base_degree, expansion_degree, seed)
+    }
+
+    /// Builds the graph of the PoRep `porep_id`, like `new_with_config`, from a `seed` derived
+    /// with `derive_graph_seed`. Its parent cache, if used, is checked against the known-good
+    /// digest listed for the PoRep in the manifest, see `new`.
+    pub fn new_for_porep(
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        seed: [u32; 7],
+        config: &GraphConfig,
+        porep_id: &[u8; 32],
+    ) -> Result<Self> {
+        let base_graph = G::new_with_config(nodes, base_degree, 0, seed, config);
+
+        Self::new(
+            Some(base_graph),
+            nodes,
+            base_degree,
+            expansion_degree,
+            seed,
+            Some(porep_id),
+        )
     }
 
     pub fn base_graph(&self) -> G {
@@ -290,23 +417,46 @@ where
     }
 
     /// Assign `self.expansion_degree` parents to `node` using an invertible permutation
-    /// that is applied one way for the forward layers and one way for the reversed
-    /// ones.
+    /// keyed by the graph seed, writing them into `expanded_parents`, which must hold at least
+    /// that many entries.
+    #[inline]
+    pub fn expanded_parents(&self, node: usize, expanded_parents: &mut [u32]) {
+        assert!(
+            expanded_parents.len() >= self.expansion_degree,
+            "buffer too small for expanded parents"
+        );
+
+        self.for_each_expanded_parent(node, |i, parent| expanded_parents[i] = parent);
+    }
+
+    /// Calls `f` with the index and value of every expansion parent of `node`, from the
+    /// cheapest available source.
     #[inline]
-    pub fn expanded_parents<F, T>(&self, node: usize, mut cb: F) -> T
+    fn for_each_expanded_parent<F>(&self, node: usize, mut f: F)
     where
-        F: FnMut(&Vec<u32>) -> T,
+        F: FnMut(usize, u32),
     {
+        if let Some(ref parent_cache) = self.parent_cache {
+            let base_degree = self.base_graph.degree();
+            for i in 0..self.expansion_degree {
+                f(i, parent_cache.parent(node, base_degree + i));
+            }
+            return;
+        }
+
         if !self.use_cache {
             // No cache usage, generate on demand.
-            return cb(&self.generate_expanded_parents(node));
+            for i in 0..self.expansion_degree {
+                f(i, self.correspondent(node, i) as u32);
+            }
+            return;
         }
 
         // Check if we need to fill the cache.
         if !self.contains_parents_cache(node) {
             // Cache is empty so we need to generate the parents.
-            let parents = self.generate_expanded_parents(node);
-            assert_eq!(parents.len(), self.expansion_degree());
+            let mut parents = vec![0; self.expansion_degree];
+            self.generate_expanded_parents(node, &mut parents);
 
             // Store the newly generated cached value.
             let mut cache_lock = PARENT_CACHE.write().unwrap();
@@ -321,7 +471,11 @@ where
         let cache = cache_lock
             .get(&self.id)
             .expect("Invalid cache construction");
-        cache.read(node as u32, |parents| cb(parents.unwrap()))
+        cache.read(node as u32, |parents| {
+            for (i, parent) in parents.unwrap().iter().enumerate() {
+                f(i, *parent);
+            }
+        })
     }
 }
 
@@ -348,6 +502,8 @@ mod tests {
 
     use std::collections::HashSet;
 
+    use crate::hasher::PedersenHasher;
+
     // Test that 3 (or more) rounds of the Feistel cipher can be used
     // as a pseudorandom permutation, that is, each input will be mapped
     // to a unique output (and though not test here, since the cipher
@@ -386,4 +542,65 @@ mod tests {
         // have skipped as duplicates).
         assert_eq!(shuffled.len(), (n * d) as usize);
     }
+
+    #[test]
+    fn test_expanded_parents_keyed_by_seed() {
+        let nodes = 64;
+        let g1 = StackedBucketGraph::<PedersenHasher>::new_stacked(
+            nodes,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            derive_graph_seed([1; 7], &[0; 32]),
+        );
+        let g2 = StackedBucketGraph::<PedersenHasher>::new_stacked(
+            nodes,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            derive_graph_seed([1; 7], &[1; 32]),
+        );
+
+        let mut all1 = Vec::new();
+        let mut all2 = Vec::new();
+        for node in 0..nodes {
+            let mut p1 = [0; EXP_DEGREE];
+            let mut p2 = [0; EXP_DEGREE];
+            g1.expanded_parents(node, &mut p1);
+            g2.expanded_parents(node, &mut p2);
+
+            let mut parents = vec![0; g1.degree()];
+            g1.parents(node, &mut parents);
+            let expected: Vec<usize> = p1.iter().map(|p| *p as usize).collect();
+            assert_eq!(&parents[BASE_DEGREE..], &expected[..]);
+
+            let mut generated = [0; EXP_DEGREE];
+            g1.generate_expanded_parents(node, &mut generated);
+            assert_eq!(generated, p1);
+
+            all1.extend_from_slice(&p1);
+            all2.extend_from_slice(&p2);
+        }
+
+        assert_ne!(
+            all1, all2,
+            "distinct porep ids should give distinct expanders"
+        );
+    }
+
+    #[test]
+    fn test_derive_graph_seed() {
+        let seed = [1, 2, 3, 4, 5, 6, 7];
+
+        assert_eq!(
+            derive_graph_seed(seed, &[0; 32]),
+            derive_graph_seed(seed, &[0; 32])
+        );
+        assert_ne!(
+            derive_graph_seed(seed, &[0; 32]),
+            derive_graph_seed(seed, &[1; 32])
+        );
+        assert_ne!(
+            derive_graph_seed(seed, &[0; 32]),
+            derive_graph_seed([0; 7], &[0; 32])
+        );
+    }
 }
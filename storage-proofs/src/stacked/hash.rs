@@ -1,6 +1,48 @@
+use ff::PrimeField;
+use paired::bls12_381::Fr;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::domain::HashDomain;
 use crate::crypto::pedersen::{pedersen_md_no_padding_bits, Bits};
+use crate::fr32::bytes_into_fr_repr_safe;
 use crate::hasher::pedersen::PedersenDomain;
 
+/// The hash combining `comm_c` and `comm_r_last` into `comm_r`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommRHasher {
+    /// Pedersen over `comm_c || comm_r_last`, see `hash2`.
+    Pedersen,
+    /// Blake2s over `comm_c || comm_r_last`, personalized with `HashDomain::CommR` and
+    /// truncated to fit into a field element.
+    Blake2s,
+}
+
+impl Default for CommRHasher {
+    fn default() -> Self {
+        CommRHasher::Pedersen
+    }
+}
+
+impl CommRHasher {
+    /// Computes `comm_r = H(comm_c || comm_r_last)`.
+    pub fn hash<S: AsRef<[u8]>, T: AsRef<[u8]>>(self, comm_c: S, comm_r_last: T) -> Fr {
+        match self {
+            CommRHasher::Pedersen => hash2(comm_c, comm_r_last).into(),
+            CommRHasher::Blake2s => {
+                let hash = HashDomain::CommR
+                    .blake2s()
+                    .to_state()
+                    .update(comm_c.as_ref())
+                    .update(comm_r_last.as_ref())
+                    .finalize();
+
+                Fr::from_repr(bytes_into_fr_repr_safe(hash.as_ref()))
+                    .expect("truncated hash is a valid field element")
+            }
+        }
+    }
+}
+
 /// Hash 2 individual elements.
 pub fn hash2<S: AsRef<[u8]>, T: AsRef<[u8]>>(a: S, b: T) -> PedersenDomain {
     hash1(Bits::new_many(vec![a.as_ref(), b.as_ref()].into_iter()))
@@ -15,3 +57,44 @@ pub fn hash_single_column<T: AsRef<[u8]>>(column: &[T]) -> PedersenDomain {
 pub fn hash1<'a, S: Iterator<Item = &'a [u8]>>(data: Bits<&'a [u8], S>) -> PedersenDomain {
     pedersen_md_no_padding_bits(data).into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use paired::bls12_381::Bls12;
+
+    use crate::fr32::{fr_into_bytes, u32_into_fr};
+
+    #[test]
+    fn test_comm_r_hasher() {
+        let comm_c = fr_into_bytes::<Bls12>(&u32_into_fr::<Bls12>(1));
+        let comm_r_last = fr_into_bytes::<Bls12>(&u32_into_fr::<Bls12>(2));
+
+        // Pedersen is what comm_r has always been computed with.
+        assert_eq!(CommRHasher::default(), CommRHasher::Pedersen);
+        assert_eq!(
+            CommRHasher::Pedersen.hash(&comm_c, &comm_r_last),
+            Fr::from(hash2(&comm_c, &comm_r_last))
+        );
+
+        let blake2s = fr_into_bytes::<Bls12>(&CommRHasher::Blake2s.hash(&comm_c, &comm_r_last));
+        assert_eq!(
+            blake2s,
+            vec![
+                0x86, 0x78, 0x56, 0x47, 0xab, 0x3b, 0x7f, 0x7f, 0x76, 0xfb, 0xb4, 0x4a, 0x34, 0x49,
+                0x84, 0x4c, 0x49, 0xe5, 0x96, 0x35, 0x15, 0xf7, 0x31, 0x29, 0x4f, 0xfd, 0xcb, 0x33,
+                0xab, 0x79, 0x5b, 0x24,
+            ]
+        );
+
+        for hasher in &[CommRHasher::Pedersen, CommRHasher::Blake2s] {
+            assert_ne!(
+                hasher.hash(&comm_c, &comm_r_last),
+                hasher.hash(&comm_r_last, &comm_c),
+                "{:?}",
+                hasher
+            );
+        }
+    }
+}
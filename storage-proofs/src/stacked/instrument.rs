@@ -0,0 +1,270 @@
+//! Instrumented proving for research and benchmarking.
+//!
+//! `StackedDrg::prove_layers_instrumented` generates exactly the same proofs as
+//! `prove_all_partitions`, but reports the time spent on every challenge and the number of label
+//! bytes opened per layer, both through caller supplied `ProveLayersHooks` and as a
+//! `ProveLayersStats` summary.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::hasher::Hasher;
+use crate::stacked::params::{PrivateInputs, Proof, PublicInputs, PublicParams};
+use crate::stacked::proof::StackedDrg;
+
+/// Callbacks invoked while proving. They are called concurrently from the proving threads.
+pub trait ProveLayersHooks: Sync {
+    /// Called once a challenge has been proven, with the time it took.
+    fn on_challenge(
+        &self,
+        _partition: usize,
+        _challenge_index: usize,
+        _challenge: usize,
+        _elapsed: Duration,
+    ) {
+    }
+
+    /// Called for every layer of every proven challenge, with the number of label bytes the proof
+    /// opens in that layer (`layer` starts at 1).
+    fn on_layer_bytes(&self, _layer: usize, _bytes: usize) {}
+}
+
+/// Hooks that do nothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoHooks;
+
+impl ProveLayersHooks for NoHooks {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChallengeTiming {
+    pub partition: usize,
+    pub challenge_index: usize,
+    pub challenge: usize,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ProveLayersStats {
+    /// Wall clock time of the whole run.
+    pub total: Duration,
+    /// Per challenge timings, ordered by partition and challenge index.
+    pub challenges: Vec<ChallengeTiming>,
+    /// Label bytes opened, summed over all challenges, indexed by `layer - 1`.
+    pub layer_bytes: Vec<u64>,
+}
+
+/// Collects stats while forwarding every event to the caller's hooks.
+struct Recorder<'a> {
+    inner: &'a dyn ProveLayersHooks,
+    stats: Mutex<ProveLayersStats>,
+}
+
+impl<'a> ProveLayersHooks for Recorder<'a> {
+    fn on_challenge(
+        &self,
+        partition: usize,
+        challenge_index: usize,
+        challenge: usize,
+        elapsed: Duration,
+    ) {
+        self.inner
+            .on_challenge(partition, challenge_index, challenge, elapsed);
+
+        self.stats.lock().unwrap().challenges.push(ChallengeTiming {
+            partition,
+            challenge_index,
+            challenge,
+            elapsed,
+        });
+    }
+
+    fn on_layer_bytes(&self, layer: usize, bytes: usize) {
+        self.inner.on_layer_bytes(layer, bytes);
+
+        let mut stats = self.stats.lock().unwrap();
+        if stats.layer_bytes.len() < layer {
+            stats.layer_bytes.resize(layer, 0);
+        }
+        stats.layer_bytes[layer - 1] += bytes as u64;
+    }
+}
+
+impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
+    /// Proves all partitions like `prove_all_partitions`, reporting progress to `hooks` and
+    /// returning the proofs together with the collected stats.
+    ///
+    /// Unlike `prove_all_partitions`, invalid inputs are reported as errors instead of panicking.
+    pub fn prove_layers_instrumented(
+        pub_params: &PublicParams<H>,
+        pub_inputs: &PublicInputs<H::Domain>,
+        priv_inputs: &PrivateInputs<H>,
+        partition_count: usize,
+        hooks: &dyn ProveLayersHooks,
+    ) -> Result<(Vec<Vec<Proof<H>>>, ProveLayersStats)> {
+        let layers = pub_params.layer_challenges.layers();
+
+        if partition_count == 0 {
+            return Err(format_err!("partition_count must be greater than zero").into());
+        }
+        if layers == 0 {
+            return Err(format_err!("at least one layer is required").into());
+        }
+        if priv_inputs.t_aux.encodings.len() != layers {
+            return Err(format_err!(
+                "expected {} layers of encodings, got {}",
+                layers,
+                priv_inputs.t_aux.encodings.len()
+            )
+            .into());
+        }
+
+        let recorder = Recorder {
+            inner: hooks,
+            stats: Mutex::new(ProveLayersStats {
+                layer_bytes: vec![0; layers],
+                ..Default::default()
+            }),
+        };
+
+        let start = Instant::now();
+        let proofs = Self::prove_layers(
+            &pub_params.graph,
+            pub_inputs,
+            &priv_inputs.p_aux,
+            &priv_inputs.t_aux,
+            &pub_params.layer_challenges,
+            &pub_params.porep_id,
+            layers,
+            layers,
+            partition_count,
+            &recorder,
+        )?;
+
+        let mut stats = recorder.stats.into_inner().unwrap();
+        stats.total = start.elapsed();
+        stats
+            .challenges
+            .sort_by_key(|timing| (timing.partition, timing.challenge_index));
+
+        Ok((proofs, stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use paired::bls12_381::Bls12;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::drgporep;
+    use crate::drgraph::{new_seed_from, GraphConfig, BASE_DEGREE};
+    use crate::fr32::fr_into_bytes;
+    use crate::hasher::PedersenHasher;
+    use crate::porep::PoRep;
+    use crate::proof::ProofScheme;
+    use crate::stacked::{CommRHasher, LayerChallenges, SetupParams, EXP_DEGREE};
+    use crate::util::NODE_SIZE;
+
+    #[derive(Default)]
+    struct Counter {
+        challenges: AtomicUsize,
+        bytes: AtomicUsize,
+    }
+
+    impl ProveLayersHooks for Counter {
+        fn on_challenge(&self, _: usize, _: usize, _: usize, _: Duration) {
+            self.challenges.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_layer_bytes(&self, _layer: usize, bytes: usize) {
+            self.bytes.fetch_add(bytes, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_prove_layers_instrumented() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let nodes = 8;
+        let layers = 4;
+        let partitions = 2;
+        let replica_id: <PedersenHasher as Hasher>::Domain = rng.gen();
+        let mut data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let sp = SetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges: LayerChallenges::new(layers, 2),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        };
+
+        let pp = StackedDrg::<PedersenHasher>::setup(&sp).expect("setup failed");
+        let (tau, (p_aux, t_aux)) = StackedDrg::<PedersenHasher>::replicate(
+            &pp,
+            &replica_id,
+            data.as_mut_slice().into(),
+            None,
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs {
+            replica_id,
+            seed: None,
+            tau: Some(tau),
+            k: None,
+        };
+        let priv_inputs = PrivateInputs { p_aux, t_aux };
+
+        assert!(StackedDrg::<PedersenHasher>::prove_layers_instrumented(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            0,
+            &NoHooks
+        )
+        .is_err());
+
+        let counter = Counter::default();
+        let (proofs, stats) = StackedDrg::<PedersenHasher>::prove_layers_instrumented(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            partitions,
+            &counter,
+        )
+        .expect("failed to generate partition proofs");
+
+        assert!(
+            StackedDrg::<PedersenHasher>::verify_all_partitions(&pp, &pub_inputs, &proofs)
+                .expect("failed to verify partition proofs")
+        );
+
+        let proof_count: usize = proofs.iter().map(Vec::len).sum();
+        assert_eq!(stats.challenges.len(), proof_count);
+        assert_eq!(counter.challenges.load(Ordering::SeqCst), proof_count);
+        assert_eq!(stats.challenges[0].partition, 0);
+        assert_eq!(stats.challenges[proof_count - 1].partition, partitions - 1);
+
+        assert_eq!(stats.layer_bytes.len(), layers);
+        assert!(stats.layer_bytes.iter().all(|bytes| *bytes > 0));
+        assert_eq!(
+            stats.layer_bytes.iter().sum::<u64>(),
+            counter.bytes.load(Ordering::SeqCst) as u64
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use blake2s_simd::{many, Params as Blake2s, State};
+
+use crate::drgraph::Graph;
+use crate::error::Result;
+use crate::hasher::Hasher;
+use crate::stacked::graph::StackedBucketGraph;
+use crate::stacked::prefetch::{ParentsAhead, Prefetch};
+use crate::util::data_at_sized_node;
+
+/// Derives the labels of the stacked graph.
+///
+/// This is the single definition of the labeling function, used both to generate the layers
+/// during replication and to recompute the labels opened by encoding proofs.
+#[derive(Clone)]
+pub struct Labeler {
+    base: State,
+}
+
+impl Labeler {
+    /// Creates a labeler for `replica_id`, producing labels of `node_size` bytes.
+    pub fn new(porep_id: &[u8; 32], replica_id: &[u8], node_size: usize) -> Self {
+        let mut base = Blake2s::new().hash_length(node_size).to_state();
+        base.update(porep_id);
+        base.update(replica_id);
+
+        Labeler { base }
+    }
+
+    /// Writes the label of `node`, given the labels of its `parents` in order, into `label`.
+    ///
+    /// The two most significant bits are stripped, so the label is always a valid `Fr`.
+    pub(crate) fn label<'p>(
+        &self,
+        node: u64,
+        parents: impl IntoIterator<Item = &'p [u8]>,
+        label: &mut [u8],
+    ) {
+        let mut hasher = self.base.clone();
+        hasher.update(&node.to_le_bytes());
+        for parent in parents {
+            hasher.update(parent);
+        }
+
+        finish(&hasher, label);
+    }
+
+    /// Writes the labels of `nodes` into `labels`, like `label` would, but hashes them side by
+    /// side with SIMD. A single chain of labels leaves most of the hashing units idle, so nodes
+    /// whose parents are all labeled already are cheaper in batches. `parents` holds the parent
+    /// labels of each node in order, with the same number of parents for every node.
+    pub(crate) fn label_many(&self, nodes: &[u64], parents: &[u8], labels: &mut [u8]) {
+        if nodes.is_empty() {
+            return;
+        }
+        assert_eq!(labels.len() % nodes.len(), 0, "uneven labels");
+        assert_eq!(parents.len() % nodes.len(), 0, "uneven parents");
+
+        let mut states: Vec<State> = nodes
+            .iter()
+            .map(|node| {
+                let mut state = self.base.clone();
+                state.update(&node.to_le_bytes());
+                state
+            })
+            .collect();
+        if !parents.is_empty() {
+            many::update_many(
+                states
+                    .iter_mut()
+                    .zip(parents.chunks(parents.len() / nodes.len())),
+            );
+        }
+
+        for (state, label) in states
+            .iter()
+            .zip(labels.chunks_mut(labels.len() / nodes.len()))
+        {
+            finish(state, label);
+        }
+    }
+}
+
+/// The number of labels `Labeler::label_many` hashes side by side on this CPU.
+pub(crate) fn batch_size() -> usize {
+    many::degree()
+}
+
+/// Writes the hash of `state` to `label`, with the two most significant bits stripped.
+fn finish(state: &State, label: &mut [u8]) {
+    label.copy_from_slice(state.finalize().as_bytes());
+    let last = label.len() - 1;
+    label[last] &= 0b0011_1111;
+}
+
+/// Labels a layer of `graph` into `labels`, with the labels of the previous layer in
+/// `exp_labels`, from the second layer on. Nodes are bound to their index plus `first_node`.
+///
+/// Runs of consecutive nodes which don't depend on each other are labeled together by
+/// `label_many`. Bucket sampling always makes the previous node a parent, so that only happens
+/// with custom constructions. `on_labeled` is called with the number of nodes labeled after
+/// every batch, and stops labeling if it fails. Parent labels are prefetched as chosen by
+/// `prefetch`.
+#[allow(clippy::too_many_arguments)]
+pub fn label_layer<H, F>(
+    graph: &StackedBucketGraph<H>,
+    labeler: &Labeler,
+    first_node: u64,
+    labels: &mut [u8],
+    exp_labels: Option<&[u8]>,
+    node_size: usize,
+    prefetch: Prefetch,
+    mut on_labeled: F,
+) -> Result<()>
+where
+    H: Hasher,
+    F: FnMut(usize) -> Result<()>,
+{
+    let size = graph.size();
+    let degree = graph.degree();
+    let base_degree = graph.base_graph().degree();
+    // Expander parents only exist from the second layer on.
+    let parents_count = if exp_labels.is_some() {
+        degree
+    } else {
+        base_degree
+    };
+    let max_batch = batch_size();
+
+    let mut parents = vec![0; max_batch * degree];
+    let mut inputs = vec![0u8; max_batch * parents_count * node_size];
+    let mut nodes = Vec::with_capacity(max_batch);
+    let mut label = vec![0u8; node_size];
+
+    // The first node has no parents.
+    labeler.label(first_node, std::iter::empty(), &mut labels[..node_size]);
+    on_labeled(1)?;
+
+    let mut ahead = ParentsAhead::new(graph, prefetch);
+    let mut start = 1;
+    while start < size {
+        let mut count = 0;
+        while start + count < size && count < max_batch {
+            let node_parents = ahead.parents(start + count, labels, exp_labels, node_size);
+            // A node which depends on the batch starts the next one.
+            if count > 0 && node_parents[..base_degree].iter().any(|&p| p >= start) {
+                break;
+            }
+            parents[count * degree..(count + 1) * degree].copy_from_slice(node_parents);
+            count += 1;
+        }
+
+        // Base parents always come before the batch.
+        let labeled = &labels[..start * node_size];
+        if count == 1 {
+            let node_parents = &parents[..degree];
+            let base_parents = node_parents[..base_degree].iter().map(|parent| {
+                data_at_sized_node(labeled, *parent, node_size).expect("invalid node")
+            });
+            let exp_parents = exp_labels.iter().flat_map(|exp_labels| {
+                node_parents[base_degree..].iter().map(move |parent| {
+                    data_at_sized_node(exp_labels, *parent, node_size).expect("invalid node")
+                })
+            });
+
+            labeler.label(
+                first_node + start as u64,
+                base_parents.chain(exp_parents),
+                &mut label,
+            );
+            labels[start * node_size..(start + 1) * node_size].copy_from_slice(&label);
+        } else {
+            let inputs = &mut inputs[..count * parents_count * node_size];
+            for (node_parents, input) in parents
+                .chunks(degree)
+                .zip(inputs.chunks_mut(parents_count * node_size))
+            {
+                for (i, (parent, input)) in node_parents
+                    .iter()
+                    .zip(input.chunks_mut(node_size))
+                    .enumerate()
+                {
+                    let source = if i < base_degree {
+                        labeled
+                    } else {
+                        exp_labels.expect("no expander parents")
+                    };
+                    input.copy_from_slice(
+                        data_at_sized_node(source, *parent, node_size).expect("invalid node"),
+                    );
+                }
+            }
+
+            nodes.clear();
+            nodes.extend((start..start + count).map(|node| first_node + node as u64));
+            labeler.label_many(
+                &nodes,
+                inputs,
+                &mut labels[start * node_size..(start + count) * node_size],
+            );
+        }
+
+        on_labeled(count)?;
+        start += count;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use paired::bls12_381::Bls12;
+    use rand::Rng;
+
+    use crate::drgraph::{new_seed_from, GraphConfig, ParentSampler, BASE_DEGREE};
+    use crate::fr32::bytes_into_fr;
+    use crate::hasher::PedersenHasher;
+    use crate::stacked::graph::EXP_DEGREE;
+    use crate::stacked::prefetch::PrefetchHint;
+    use crate::test_helper::test_rng;
+    use crate::util::NODE_SIZE;
+
+    const STRIDE: usize = 16;
+
+    /// Makes the base parents of every node precede it by at least `STRIDE` nodes, so that runs
+    /// of `STRIDE` nodes are independent of each other.
+    #[derive(Debug)]
+    struct StrideSampler;
+
+    impl ParentSampler for StrideSampler {
+        fn identifier(&self) -> String {
+            "stride".into()
+        }
+
+        fn parents(&self, _seed: &[u32; 7], degree: usize, node: usize, parents: &mut [usize]) {
+            for (k, parent) in parents.iter_mut().take(degree).enumerate() {
+                *parent = node.saturating_sub(STRIDE + k);
+            }
+        }
+    }
+
+    /// Labels the nodes one after the other, with `Labeler::label` only.
+    fn label_sequentially(
+        graph: &StackedBucketGraph<PedersenHasher>,
+        labeler: &Labeler,
+        exp_labels: Option<&[u8]>,
+    ) -> Vec<u8> {
+        let base_degree = graph.base_graph().degree();
+        let mut parents = vec![0; graph.degree()];
+        let mut labels = vec![0u8; graph.size() * NODE_SIZE];
+        let node_at = |data: &[u8], node: usize| -> Vec<u8> {
+            data[node * NODE_SIZE..(node + 1) * NODE_SIZE].to_vec()
+        };
+
+        for node in 0..graph.size() {
+            let mut parent_labels = Vec::new();
+            if node > 0 {
+                graph.parents(node, &mut parents);
+                for &parent in &parents[..base_degree] {
+                    parent_labels.push(node_at(&labels, parent));
+                }
+                if let Some(exp_labels) = exp_labels {
+                    for &parent in &parents[base_degree..] {
+                        parent_labels.push(node_at(exp_labels, parent));
+                    }
+                }
+            }
+
+            labeler.label(
+                node as u64,
+                parent_labels.iter().map(Vec::as_slice),
+                &mut labels[node * NODE_SIZE..(node + 1) * NODE_SIZE],
+            );
+        }
+
+        labels
+    }
+
+    #[test]
+    fn test_label_is_fr() {
+        let labeler = Labeler::new(&[1; 32], &[2; 32], 32);
+        let parents = vec![[0xffu8; 32]; 3];
+
+        let mut label = [0u8; 32];
+        labeler.label(7, parents.iter().map(|p| &p[..]), &mut label);
+        assert!(bytes_into_fr::<Bls12>(&label).is_ok());
+
+        let mut other = [0u8; 32];
+        labeler.label(8, parents.iter().map(|p| &p[..]), &mut other);
+        assert_ne!(label, other);
+    }
+
+    #[test]
+    fn test_label_many() {
+        let labeler = Labeler::new(&[1; 32], &[2; 32], 32);
+        let nodes = [3, 9, 4];
+        let parents: Vec<u8> = (0..nodes.len() * 2 * 32).map(|i| i as u8).collect();
+
+        let mut labels = vec![0u8; nodes.len() * 32];
+        labeler.label_many(&nodes, &parents, &mut labels);
+
+        for (i, &node) in nodes.iter().enumerate() {
+            let mut label = [0u8; 32];
+            let node_parents = &parents[i * 64..(i + 1) * 64];
+            labeler.label(node, node_parents.chunks(32), &mut label);
+            assert_eq!(&labels[i * 32..(i + 1) * 32], &label[..]);
+        }
+    }
+
+    #[test]
+    fn test_label_layer() {
+        let rng = &mut test_rng();
+        let seed = new_seed_from(rng);
+        let labeler = Labeler::new(&[1; 32], &[2; 32], NODE_SIZE);
+        let nodes = 64;
+        let exp_labels: Vec<u8> = (0..nodes * NODE_SIZE).map(|_| rng.gen()).collect();
+
+        // Bucket sampling is labeled one node at a time, strides in batches.
+        let configs = vec![
+            GraphConfig::default(),
+            GraphConfig::Custom(Arc::new(StrideSampler)),
+        ];
+        // Lookahead past the end of batches and of the layer doesn't change labels.
+        let prefetches = vec![
+            Prefetch::none(),
+            Prefetch::new(PrefetchHint::T0, 3).unwrap(),
+            Prefetch::new(PrefetchHint::T1, STRIDE * 2).unwrap(),
+        ];
+        for config in &configs {
+            let graph = StackedBucketGraph::<PedersenHasher>::new_with_config(
+                nodes,
+                BASE_DEGREE,
+                EXP_DEGREE,
+                seed,
+                config,
+            );
+
+            for &exp_labels in &[None, Some(&exp_labels[..])] {
+                let expected = label_sequentially(&graph, &labeler, exp_labels);
+                for &prefetch in &prefetches {
+                    let mut labels = vec![0u8; nodes * NODE_SIZE];
+                    let mut labeled = 0;
+                    label_layer(
+                        &graph,
+                        &labeler,
+                        0,
+                        &mut labels,
+                        exp_labels,
+                        NODE_SIZE,
+                        prefetch,
+                        |count| {
+                            labeled += count;
+                            Ok(())
+                        },
+                    )
+                    .unwrap();
+
+                    assert_eq!(labeled, nodes);
+                    assert_eq!(labels, expected);
+                }
+            }
+        }
+    }
+}
@@ -1,25 +1,51 @@
 #[macro_use]
 mod macros;
 
+mod analysis;
+mod audit;
+mod cache;
 mod challenges;
 mod column;
+mod column_batch;
 mod column_proof;
 mod encode;
 mod encoding_proof;
 mod graph;
 pub(crate) mod hash;
+mod instrument;
+pub(crate) mod label;
 mod params;
+mod params_json;
 mod porep;
+mod prefetch;
 mod proof;
 mod proof_scheme;
+#[cfg(test)]
+pub(crate) mod tamper;
+mod verify;
+pub mod window;
 
-pub use self::challenges::{ChallengeRequirements, LayerChallenges};
-pub use self::column::Column;
+pub use self::analysis::{GraphStats, MAX_DOT_NODES};
+pub use self::cache::{
+    find_manifest_entry, get_parent_cache, parent_cache_id, parent_cache_manifest,
+    parent_cache_path, ParentCache, ParentCacheManifest, ParentCacheManifestEntry,
+};
+pub use self::challenges::{derive_challenges, ChallengeRequirements, LayerChallenges, Taper};
+pub use self::column::{Column, LazyColumn};
+pub use self::column_batch::{BatchedChallenge, BatchedProofs, ColumnOpenings};
 pub use self::column_proof::ColumnProof;
 pub use self::encoding_proof::EncodingProof;
-pub use self::graph::{StackedBucketGraph, StackedGraph, EXP_DEGREE};
+pub use self::graph::{derive_graph_seed, StackedBucketGraph, StackedGraph, EXP_DEGREE};
+pub use self::hash::CommRHasher;
+pub use self::instrument::{ChallengeTiming, NoHooks, ProveLayersHooks, ProveLayersStats};
+pub use self::label::{label_layer, Labeler};
 pub use self::params::{
-    generate_replica_id, PersistentAux, PrivateInputs, Proof, PublicInputs, PublicParams,
-    ReplicaColumnProof, SetupParams, Tau, TemporaryAux,
+    generate_replica_id, layer_file_name, LayerStore, PersistentAux, PrivateInputs, Proof,
+    PublicInputs, PublicParams, ReplicaColumnProof, SetupParams, StoredElements, Tau, TemporaryAux,
+    TemporaryAuxAsStored, MAX_IN_MEMORY_LAYER_SIZE, TREE_C_FILE, TREE_D_FILE, TREE_R_LAST_FILE,
 };
-pub use self::proof::StackedDrg;
+pub use self::prefetch::{Prefetch, PrefetchHint, MAX_PREFETCH_DISTANCE};
+pub use self::proof::{StackedDrg, EXTRACT_CHUNK_NODES};
+pub use self::verify::verify_seal_proofs;
+
+pub(crate) use self::params::layers_in_memory;
@@ -1,24 +1,38 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 use crate::crypto::pedersen::{pedersen_md_no_padding_bits, Bits};
-use merkletree::store::DiskStore;
-use merkletree::store::Store;
-use paired::bls12_381::Fr;
+use merkletree::merkle::Element;
+use merkletree::store::{DiskStore, Store, VecStore};
 use serde::{Deserialize, Serialize};
 
+use crate::challenge_hash::{
+    ChallengeHash, STACKED_DRG_INTERACTIVE_CHALLENGE_HASH,
+    STACKED_DRG_NON_INTERACTIVE_CHALLENGE_HASH,
+};
 use crate::drgporep;
 use crate::drgraph::Graph;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::fr32::bytes_into_fr_repr_safe;
 use crate::hasher::pedersen::PedersenDomain;
 use crate::hasher::{Domain, Hasher};
-use crate::merkle::{MerkleProof, MerkleTree};
+use crate::label_file::{Access, LabelFile, LayerIo};
+use crate::merkle::{build_tree, MerkleProof, MerkleTree};
 use crate::parameter_cache::ParameterSetMetadata;
+use crate::settings;
 use crate::stacked::{
-    column::Column, column_proof::ColumnProof, encoding_proof::EncodingProof,
-    graph::StackedBucketGraph, hash::hash2, LayerChallenges,
+    column::{Column, LazyColumn},
+    column_proof::ColumnProof,
+    encoding_proof::EncodingProof,
+    graph::StackedBucketGraph,
+    hash::CommRHasher,
+    LayerChallenges,
 };
-use crate::util::{data_at_node, NODE_SIZE};
+use crate::util::{data_at_sized_node, ensure_writable, NODE_SIZE};
+use crate::versioned;
 
 pub type Tree<H> = MerkleTree<<H as Hasher>::Domain, <H as Hasher>::Function>;
 
@@ -26,6 +40,13 @@ pub type Tree<H> = MerkleTree<<H as Hasher>::Domain, <H as Hasher>::Function>;
 pub struct SetupParams {
     pub drg: drgporep::DrgParams,
     pub layer_challenges: LayerChallenges,
+    /// Separates this PoRep from all others; mixed into the graph seed, every label and every
+    /// challenge.
+    pub porep_id: [u8; 32],
+    /// The hash combining `comm_c` and `comm_r_last` into `comm_r`.
+    pub comm_r_hasher: CommRHasher,
+    /// Bytes per node in the data, labels and trees; must match the hasher's domain elements.
+    pub node_size: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -35,6 +56,9 @@ where
 {
     pub graph: StackedBucketGraph<H>,
     pub layer_challenges: LayerChallenges,
+    pub porep_id: [u8; 32],
+    pub comm_r_hasher: CommRHasher,
+    pub node_size: usize,
     _h: PhantomData<H>,
 }
 
@@ -42,10 +66,19 @@ impl<H> PublicParams<H>
 where
     H: Hasher,
 {
-    pub fn new(graph: StackedBucketGraph<H>, layer_challenges: LayerChallenges) -> Self {
+    pub fn new(
+        graph: StackedBucketGraph<H>,
+        layer_challenges: LayerChallenges,
+        porep_id: [u8; 32],
+        comm_r_hasher: CommRHasher,
+        node_size: usize,
+    ) -> Self {
         PublicParams {
             graph,
             layer_challenges,
+            porep_id,
+            comm_r_hasher,
+            node_size,
             _h: PhantomData,
         }
     }
@@ -57,9 +90,12 @@ where
 {
     fn identifier(&self) -> String {
         format!(
-            "layered_drgporep::PublicParams{{ graph: {}, challenges: {:?} }}",
+            "layered_drgporep::PublicParams{{ graph: {}, challenges: {:?}, porep_id: {:?}, comm_r_hasher: {:?}, node_size: {} }}",
             self.graph.identifier(),
             self.layer_challenges,
+            self.porep_id,
+            self.comm_r_hasher,
+            self.node_size,
         )
     }
 
@@ -73,11 +109,18 @@ where
     H: Hasher,
 {
     fn from(other: &PublicParams<H>) -> PublicParams<H> {
-        PublicParams::new(other.graph.clone(), other.layer_challenges.clone())
+        PublicParams::new(
+            other.graph.clone(),
+            other.layer_challenges.clone(),
+            other.porep_id,
+            other.comm_r_hasher,
+            other.node_size,
+        )
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct PublicInputs<T: Domain> {
     pub replica_id: T,
     pub seed: Option<T>,
@@ -89,42 +132,63 @@ impl<T: Domain> PublicInputs<T> {
     pub fn challenges(
         &self,
         layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
         layer: usize,
         leaves: usize,
         partition_k: Option<usize>,
     ) -> Vec<usize> {
         let k = partition_k.unwrap_or(0);
+        let (challenge_hash, seed) = self.challenge_seed();
 
-        if let Some(ref seed) = self.seed {
-            layer_challenges.derive::<T>(layer, leaves, &self.replica_id, seed, k as u8)
-        } else {
-            layer_challenges.derive::<T>(
-                layer,
-                leaves,
-                &self.replica_id,
-                &self.tau.as_ref().expect("missing comm_r").comm_r,
-                k as u8,
-            )
-        }
+        layer_challenges.derive::<T>(
+            challenge_hash,
+            layer,
+            leaves,
+            porep_id,
+            &self.replica_id,
+            seed,
+            k as u8,
+        )
     }
 
     pub fn all_challenges(
         &self,
         layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
         leaves: usize,
         partition_k: Option<usize>,
     ) -> Vec<usize> {
         let k = partition_k.unwrap_or(0);
+        let (challenge_hash, seed) = self.challenge_seed();
+
+        layer_challenges.derive_all::<T>(
+            challenge_hash,
+            leaves,
+            porep_id,
+            &self.replica_id,
+            seed,
+            k as u8,
+        )
+    }
 
-        if let Some(ref seed) = self.seed {
-            layer_challenges.derive_all::<T>(leaves, &self.replica_id, seed, k as u8)
-        } else {
-            layer_challenges.derive_all::<T>(
-                leaves,
-                &self.replica_id,
+    /// Writes the canonical, versioned encoding, see `versioned`.
+    pub fn write_into<W: Write>(&self, writer: W) -> Result<()> {
+        versioned::write_into(self, writer)
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        versioned::read_from(reader)
+    }
+
+    /// Challenges are derived interactively from `seed` if there is one, and from `comm_r`
+    /// otherwise.
+    fn challenge_seed(&self) -> (ChallengeHash, &T) {
+        match self.seed {
+            Some(ref seed) => (STACKED_DRG_INTERACTIVE_CHALLENGE_HASH, seed),
+            None => (
+                STACKED_DRG_NON_INTERACTIVE_CHALLENGE_HASH,
                 &self.tau.as_ref().expect("missing comm_r").comm_r,
-                k as u8,
-            )
+            ),
         }
     }
 }
@@ -169,8 +233,8 @@ impl<H: Hasher> Proof<H> {
         self.replica_column_proofs.c_x.root()
     }
 
-    fn comm_r(&self) -> H::Domain {
-        Fr::from(hash2(self.comm_c(), self.comm_r_last())).into()
+    fn comm_r(&self, comm_r_hasher: CommRHasher) -> H::Domain {
+        comm_r_hasher.hash(self.comm_c(), self.comm_r_last()).into()
     }
 
     /// Verify the full proof.
@@ -188,7 +252,7 @@ impl<H: Hasher> Proof<H> {
         check!(pub_inputs.tau.is_some());
 
         // just grabbing the first one
-        let actual_comm_r = self.comm_r();
+        let actual_comm_r = self.comm_r(pub_params.comm_r_hasher);
         let expected_comm_r = if let Some(ref tau) = pub_inputs.tau {
             &tau.comm_r
         } else {
@@ -200,7 +264,7 @@ impl<H: Hasher> Proof<H> {
         // Verify initial data layer
         trace!("verify initial data layer");
 
-        check!(self.comm_d_proofs.proves_challenge(challenge));
+        check!(self.comm_d_proofs.validate(challenge));
         if let Some(ref tau) = pub_inputs.tau {
             check_eq!(self.comm_d_proofs.root(), &tau.comm_d);
         } else {
@@ -210,21 +274,40 @@ impl<H: Hasher> Proof<H> {
         // Verify replica column openings
         trace!("verify replica column openings");
         check!(self.replica_column_proofs.verify());
+        // Expander parents are only opened for challenges of the layers after the first.
+        if !pub_params
+            .layer_challenges
+            .needs_exp_parents(challenge_index)
+        {
+            check!(self.replica_column_proofs.exp_parents.is_empty());
+        }
 
         check!(self.verify_final_replica_layer(challenge));
 
-        check!(self.verify_encodings(replica_id, &pub_params.layer_challenges, challenge_index));
+        check!(self.verify_encodings(
+            &pub_params.porep_id,
+            replica_id,
+            &pub_params.layer_challenges,
+            challenge,
+            challenge_index
+        ));
 
         true
     }
 
-    /// Verify all encodings.
+    /// Verify all encodings, and that their keys are derived from the labels in the opened
+    /// columns.
     fn verify_encodings(
         &self,
+        porep_id: &[u8; 32],
         replica_id: &H::Domain,
         layer_challenges: &LayerChallenges,
+        challenge: usize,
         challenge_index: usize,
     ) -> bool {
+        // Encoding proofs are only present for the included layers, in order.
+        let mut encoding_proofs = self.encoding_proofs.iter();
+
         // Verify Encoding Layer 1..layers
         for layer in 1..=layer_challenges.layers() {
             let expect_challenge =
@@ -248,21 +331,42 @@ impl<H: Hasher> Proof<H> {
             };
 
             if expect_challenge {
-                check!(self.encoding_proofs.get(layer - 1).is_some());
-                let encoding_proof = &self.encoding_proofs[layer - 1];
-                check!(encoding_proof.verify(replica_id, encoded_node, decoded_node));
-            } else {
-                check!(self.encoding_proofs.get(layer - 1).is_none());
+                let encoding_proof = match encoding_proofs.next() {
+                    Some(encoding_proof) => encoding_proof,
+                    None => return false,
+                };
+                check_eq!(encoding_proof.node, challenge as u64);
+                check!(self.opens_parents(layer, &encoding_proof.parents));
+                check!(encoding_proof.verify(porep_id, replica_id, encoded_node, decoded_node));
             }
         }
+        check!(encoding_proofs.next().is_none());
 
         true
     }
 
+    /// Whether `parents` are the labels the label at `layer` is derived from, as opened by the
+    /// columns: those of the base parents at the same layer and, after the first layer, those of
+    /// the expander parents at the previous one.
+    fn opens_parents(&self, layer: usize, parents: &[H::Domain]) -> bool {
+        let columns = &self.replica_column_proofs;
+        let base_parents = columns
+            .drg_parents
+            .iter()
+            .map(|column| column.get_node_at_layer(layer));
+        let exp_parents = columns
+            .exp_parents
+            .iter()
+            .filter(|_| layer > 1)
+            .map(|column| column.get_node_at_layer(layer - 1));
+
+        base_parents.chain(exp_parents).eq(parents.iter())
+    }
+
     /// Verify final replica layer openings
     fn verify_final_replica_layer(&self, challenge: usize) -> bool {
         trace!("verify final replica layer openings");
-        check!(self.comm_r_last_proof.proves_challenge(challenge));
+        check!(self.comm_r_last_proof.validate(challenge));
 
         true
     }
@@ -311,8 +415,18 @@ impl<H: Hasher> ReplicaColumnProof<H> {
 }
 
 impl<H: Hasher> Proof<H> {
+    /// Returns the canonical, versioned encoding, as written by `write_into`.
     pub fn serialize(&self) -> Vec<u8> {
-        unimplemented!();
+        versioned::to_bytes(self).expect("failed to serialize proof")
+    }
+
+    /// Writes the canonical, versioned encoding, see `versioned`.
+    pub fn write_into<W: Write>(&self, writer: W) -> Result<()> {
+        versioned::write_into(self, writer)
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        versioned::read_from(reader)
     }
 }
 
@@ -323,12 +437,24 @@ pub type TransformedLayers<H> = (
 );
 
 /// Tau for a single parition.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct Tau<D: Domain> {
     pub comm_d: D,
     pub comm_r: D,
 }
 
+impl<D: Domain> Tau<D> {
+    /// Writes the canonical, versioned encoding, see `versioned`.
+    pub fn write_into<W: Write>(&self, writer: W) -> Result<()> {
+        versioned::write_into(self, writer)
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        versioned::read_from(reader)
+    }
+}
+
 /// Stored along side the sector on disk.
 #[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PersistentAux<D> {
@@ -346,7 +472,7 @@ pub struct TemporaryAux<H: Hasher> {
 }
 
 impl<H: Hasher> TemporaryAux<H> {
-    pub fn encoding_at_layer(&self, layer: usize) -> &DiskStore<H::Domain> {
+    pub fn encoding_at_layer(&self, layer: usize) -> &LayerStore<H::Domain> {
         self.encodings.encoding_at_layer(layer)
     }
 
@@ -357,16 +483,300 @@ impl<H: Hasher> TemporaryAux<H> {
     pub fn column(&self, column_index: usize) -> Result<Column<H>> {
         self.encodings.column(column_index)
     }
+
+    pub fn lazy_column(&self, column_index: usize) -> LazyColumn<H> {
+        self.encodings.lazy_column(column_index)
+    }
+
+    pub fn columns(&self, nodes: Range<usize>) -> Vec<Column<H>> {
+        self.encodings.columns(nodes)
+    }
+
+    pub fn read_columns(&self, nodes: &[usize]) -> Vec<Column<H>> {
+        self.encodings.read_columns(nodes)
+    }
+
+    /// Writes the layers and trees to files in `cache_dir`, and returns where they are, so they
+    /// can be loaded again with `TemporaryAux::load`, by this or a later process.
+    pub fn convert_to_stored(&self, cache_dir: &Path) -> Result<TemporaryAuxAsStored> {
+        ensure_writable(cache_dir)?;
+        fs::create_dir_all(cache_dir)?;
+
+        let encodings = (1..=self.encodings.len())
+            .map(|layer| {
+                let encoding = self.encoding_at_layer(layer);
+                StoredElements::write(
+                    cache_dir.join(layer_file_name(layer)),
+                    encoding.len(),
+                    |i| encoding.read_at(i),
+                )
+            })
+            .collect::<Result<_>>()?;
+
+        let store_tree = |name: &str, tree: &Tree<H>| {
+            StoredElements::write(cache_dir.join(name), tree.len(), |i| tree.read_at(i))
+        };
+
+        Ok(TemporaryAuxAsStored {
+            encodings,
+            tree_d: store_tree(TREE_D_FILE, &self.tree_d)?,
+            tree_r_last: store_tree(TREE_R_LAST_FILE, &self.tree_r_last)?,
+            tree_c: store_tree(TREE_C_FILE, &self.tree_c)?,
+        })
+    }
+
+    /// Loads the layers and trees written by `convert_to_stored`. The trees are rebuilt from their
+    /// stored leaves, and rejected if their roots differ from the stored ones.
+    pub fn load(stored: &TemporaryAuxAsStored) -> Result<Self> {
+        let encodings = stored
+            .encodings
+            .iter()
+            .map(|layer| {
+                let bytes = layer.read()?;
+                LayerStore::new_from_slice(bytes.len(), &bytes)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(TemporaryAux {
+            encodings: Encodings::new(encodings),
+            tree_d: load_tree::<H>(&stored.tree_d)?,
+            tree_r_last: load_tree::<H>(&stored.tree_r_last)?,
+            tree_c: load_tree::<H>(&stored.tree_c)?,
+        })
+    }
+}
+
+/// Name of the file `convert_to_stored` writes `tree_d` to.
+pub const TREE_D_FILE: &str = "tree-d.dat";
+/// Name of the file `convert_to_stored` writes `tree_r_last` to.
+pub const TREE_R_LAST_FILE: &str = "tree-r-last.dat";
+/// Name of the file `convert_to_stored` writes `tree_c` to.
+pub const TREE_C_FILE: &str = "tree-c.dat";
+
+/// Name of the file `convert_to_stored` writes the labels of `layer` to.
+pub fn layer_file_name(layer: usize) -> String {
+    format!("layer-{}.dat", layer)
+}
+
+/// Where `convert_to_stored` wrote the parts of a `TemporaryAux`. It can be serialized, so that
+/// proving can resume from it after the replicating process is gone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemporaryAuxAsStored {
+    /// The labels of the layers, from the first to the last.
+    pub encodings: Vec<StoredElements>,
+    /// All elements of the trees, in the order of their stores: leaves first, root last.
+    pub tree_d: StoredElements,
+    pub tree_r_last: StoredElements,
+    pub tree_c: StoredElements,
+}
+
+/// A run of `len` consecutive domain elements in the file at `path`, starting `offset` bytes into
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredElements {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub len: usize,
+}
+
+impl StoredElements {
+    fn write<E, F>(path: PathBuf, len: usize, element_at: F) -> Result<Self>
+    where
+        E: AsRef<[u8]>,
+        F: Fn(usize) -> E,
+    {
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for i in 0..len {
+            writer.write_all(element_at(i).as_ref())?;
+        }
+        writer.into_inner().map_err(io::Error::from)?.sync_all()?;
+
+        Ok(StoredElements {
+            path,
+            offset: 0,
+            len,
+        })
+    }
+
+    fn read(&self) -> Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+
+        let mut bytes = vec![0u8; self.len * NODE_SIZE];
+        file.read_exact(&mut bytes)?;
+
+        Ok(bytes)
+    }
+}
+
+fn load_tree<H: Hasher>(stored: &StoredElements) -> Result<Tree<H>> {
+    // A full binary tree with `n` leaves is stored as `2n - 1` elements.
+    let leafs = (stored.len + 1) / 2;
+    if stored.len == 0 || !leafs.is_power_of_two() || 2 * leafs - 1 != stored.len {
+        return Err(Error::MalformedMerkleTree);
+    }
+
+    let bytes = stored.read()?;
+    let tree = build_tree::<H, _>(
+        leafs,
+        |i| get_node::<H>(&bytes, i, NODE_SIZE).expect("invalid node"),
+        true,
+    )?;
+
+    if tree.root() != get_node::<H>(&bytes, stored.len - 1, NODE_SIZE)? {
+        return Err(Error::InvalidCommitment("stored tree root"));
+    }
+
+    Ok(tree)
+}
+
+/// Layers of sectors up to this size are kept in memory, unless the `layer_store` setting says
+/// otherwise.
+pub const MAX_IN_MEMORY_LAYER_SIZE: usize = 8 << 20;
+
+/// Where the labels of a layer are kept: in a temporary file, or in memory for small sectors,
+/// which spares tests and small sectors the IO. Temporary files are `LabelFile`s if the
+/// `layer_io` setting asks for more than the default page cache behavior.
+#[derive(Debug)]
+pub enum LayerStore<E: Element> {
+    Disk(DiskStore<E>),
+    File(LabelFile<E>),
+    Memory(VecStore<E>),
+}
+
+impl<E: Element> LayerStore<E> {
+    /// Stores the labels of a layer of `layer_size` bytes, in the store chosen by the
+    /// `layer_store` setting.
+    pub fn new_from_slice(layer_size: usize, data: &[u8]) -> Result<Self> {
+        if layers_in_memory(layer_size)? {
+            // Sized by elements, the store never grows.
+            Ok(LayerStore::Memory(VecStore::new_from_slice(
+                data.len() / E::byte_len(),
+                data,
+            )?))
+        } else {
+            match LayerIo::from_settings()? {
+                LayerIo::Buffered => Ok(LayerStore::Disk(DiskStore::new_from_slice(
+                    layer_size, data,
+                )?)),
+                LayerIo::Advise => Ok(LayerStore::File(LabelFile::new_from_slice(data, false)?)),
+                LayerIo::Direct => Ok(LayerStore::File(LabelFile::new_from_slice(data, true)?)),
+            }
+        }
+    }
+
+    /// Tells a `LabelFile` how it is read from now on, other stores ignore this.
+    pub fn advise(&self, access: Access) {
+        if let LayerStore::File(store) = self {
+            store.advise(access);
+        }
+    }
+
+    pub fn is_in_memory(&self) -> bool {
+        match self {
+            LayerStore::Disk(_) | LayerStore::File(_) => false,
+            LayerStore::Memory(_) => true,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            LayerStore::Disk(store) => store.len(),
+            LayerStore::File(store) => store.len(),
+            LayerStore::Memory(store) => store.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn read_at(&self, index: usize) -> E {
+        match self {
+            LayerStore::Disk(store) => store.read_at(index),
+            LayerStore::File(store) => store.read_at(index),
+            LayerStore::Memory(store) => store.read_at(index),
+        }
+    }
+
+    pub fn read_range(&self, range: Range<usize>) -> Vec<E> {
+        match self {
+            LayerStore::Disk(store) => store.read_range(range),
+            LayerStore::File(store) => store.read_range(range),
+            LayerStore::Memory(store) => store.read_range(range),
+        }
+    }
+
+    /// Reads the elements at `indices`, in that order. The distinct indices are read in
+    /// ascending order, and indices at most `MAX_READ_GAP` elements apart with a single read, so
+    /// that scattered reads from a disk become fewer, mostly sequential ones.
+    pub fn read_many(&self, indices: &[usize]) -> Vec<E> {
+        let mut sorted = indices.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+
+        let runs: Vec<(usize, Vec<E>)> = coalesce(&sorted, MAX_READ_GAP)
+            .into_iter()
+            .map(|run| (run.start, self.read_range(run)))
+            .collect();
+
+        indices
+            .iter()
+            .map(|&index| {
+                let run = match runs.binary_search_by_key(&index, |(start, _)| *start) {
+                    Ok(run) => run,
+                    Err(run) => run - 1,
+                };
+                let (start, elements) = &runs[run];
+                elements[index - start].clone()
+            })
+            .collect()
+    }
+}
+
+/// The most elements `LayerStore::read_many` reads in between two requested ones rather than
+/// issue another read, a page of 32 byte elements.
+pub const MAX_READ_GAP: usize = 128;
+
+/// Covers the ascending `indices` with ranges, merging indices at most `max_gap` elements apart.
+#[allow(clippy::range_plus_one)]
+fn coalesce(indices: &[usize], max_gap: usize) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for &index in indices {
+        if let Some(run) = runs.last_mut() {
+            if index <= run.end + max_gap {
+                run.end = index + 1;
+                continue;
+            }
+        }
+        runs.push(index..index + 1);
+    }
+
+    runs
+}
+
+/// Whether layers of `layer_size` bytes are kept in memory, according to the `layer_store`
+/// setting: `auto` keeps layers of up to `MAX_IN_MEMORY_LAYER_SIZE` bytes in memory, `memory`
+/// and `disk` force either store.
+pub(crate) fn layers_in_memory(layer_size: usize) -> Result<bool> {
+    let layer_store = settings::SETTINGS.lock().unwrap().layer_store.clone();
+
+    match layer_store.as_str() {
+        "auto" => Ok(layer_size <= MAX_IN_MEMORY_LAYER_SIZE),
+        "memory" => Ok(true),
+        "disk" => Ok(false),
+        other => Err(Error::InvalidSetting("layer_store", other.to_string())),
+    }
 }
 
 #[derive(Debug)]
 pub struct Encodings<H: Hasher> {
-    encodings: Vec<DiskStore<H::Domain>>,
+    encodings: Vec<LayerStore<H::Domain>>,
     _h: PhantomData<H>,
 }
 
 impl<H: Hasher> Encodings<H> {
-    pub fn new(encodings: Vec<DiskStore<H::Domain>>) -> Self {
+    pub fn new(encodings: Vec<LayerStore<H::Domain>>) -> Self {
         Encodings {
             encodings,
             _h: PhantomData,
@@ -381,7 +791,7 @@ impl<H: Hasher> Encodings<H> {
         self.encodings.is_empty()
     }
 
-    pub fn encoding_at_layer(&self, layer: usize) -> &DiskStore<H::Domain> {
+    pub fn encoding_at_layer(&self, layer: usize) -> &LayerStore<H::Domain> {
         assert!(layer != 0, "Layer cannot be 0");
         assert!(
             layer <= self.layers(),
@@ -395,10 +805,17 @@ impl<H: Hasher> Encodings<H> {
     }
 
     /// Returns encoding on the last layer.
-    pub fn encoding_at_last_layer(&self) -> &DiskStore<H::Domain> {
+    pub fn encoding_at_last_layer(&self) -> &LayerStore<H::Domain> {
         &self.encodings[self.encodings.len() - 1]
     }
 
+    /// Tells the layers how they are read from now on, see `LayerStore::advise`.
+    pub fn advise(&self, access: Access) {
+        for encoding in &self.encodings {
+            encoding.advise(access);
+        }
+    }
+
     /// How many layers are available.
     fn layers(&self) -> usize {
         self.encodings.len()
@@ -415,6 +832,50 @@ impl<H: Hasher> Encodings<H> {
         Ok(Column::new(node, rows))
     }
 
+    /// Build the columns of `nodes`, in that order, with a single pass over each layer, see
+    /// `LayerStore::read_many`.
+    pub fn read_columns(&self, nodes: &[usize]) -> Vec<Column<H>> {
+        let mut rows: Vec<Vec<H::Domain>> = nodes
+            .iter()
+            .map(|_| Vec::with_capacity(self.layers()))
+            .collect();
+        for encoding in &self.encodings {
+            for (column, label) in rows.iter_mut().zip(encoding.read_many(nodes)) {
+                column.push(label);
+            }
+        }
+
+        nodes
+            .iter()
+            .zip(rows)
+            .map(|(&node, rows)| Column::new(node, rows))
+            .collect()
+    }
+
+    /// The column of `node`, with its rows read on demand.
+    pub fn lazy_column(&self, node: usize) -> LazyColumn<H> {
+        LazyColumn::new(node, self)
+    }
+
+    /// Build the columns of a range of nodes, reading each layer in bulk, e.g. for contiguous
+    /// challenges.
+    pub fn columns(&self, nodes: Range<usize>) -> Vec<Column<H>> {
+        let mut rows: Vec<Vec<H::Domain>> = nodes
+            .clone()
+            .map(|_| Vec::with_capacity(self.layers()))
+            .collect();
+        for encoding in &self.encodings {
+            for (column, label) in rows.iter_mut().zip(encoding.read_range(nodes.clone())) {
+                column.push(label);
+            }
+        }
+
+        nodes
+            .zip(rows)
+            .map(|(node, rows)| Column::new(node, rows))
+            .collect()
+    }
+
     /// Calculate the hash of the column at the given node, reducing intermediary allocations.
     pub fn column_hash(&self, node: usize) -> PedersenDomain {
         let rows = self.encodings.iter().map(|encoding| encoding.read_at(node));
@@ -423,8 +884,10 @@ impl<H: Hasher> Encodings<H> {
     }
 }
 
-pub fn get_node<H: Hasher>(data: &[u8], index: usize) -> Result<H::Domain> {
-    H::Domain::try_from_bytes(data_at_node(data, index).expect("invalid node math"))
+pub fn get_node<H: Hasher>(data: &[u8], index: usize, node_size: usize) -> Result<H::Domain> {
+    H::Domain::try_from_bytes(
+        data_at_sized_node(data, index, node_size).expect("invalid node math"),
+    )
 }
 
 /// Generate the replica id as expected for Stacked DRG.
@@ -454,11 +917,37 @@ mod tests {
     use rand::{Rng, SeedableRng, XorShiftRng};
 
     #[test]
-    fn test_column_hash() {
+    fn test_layer_stores() {
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
-        let layers = 4;
-        let sector_size = 256;
+        let nodes = 16;
+        let elements: Vec<PedersenDomain> = (0..nodes).map(|_| rng.gen()).collect();
+        let data: Vec<u8> = elements.iter().flat_map(|el| el.into_bytes()).collect();
+
+        let stores = vec![
+            LayerStore::Disk(DiskStore::<PedersenDomain>::new_from_slice(nodes, &data).unwrap()),
+            LayerStore::File(LabelFile::new_from_slice(&data, false).unwrap()),
+            LayerStore::File(LabelFile::new_from_slice(&data, true).unwrap()),
+            LayerStore::Memory(VecStore::new_from_slice(nodes, &data).unwrap()),
+        ];
+
+        for store in &stores {
+            store.advise(Access::Random);
+            assert_eq!(store.len(), nodes);
+            assert_eq!(store.read_range(0..nodes), elements);
+            for (i, el) in elements.iter().enumerate() {
+                assert_eq!(&store.read_at(i), el);
+            }
+        }
+
+        // Small layers are kept in memory by default.
+        let store = LayerStore::<PedersenDomain>::new_from_slice(data.len(), &data).unwrap();
+        assert!(store.is_in_memory());
+        assert!(!layers_in_memory(MAX_IN_MEMORY_LAYER_SIZE + 1).unwrap());
+    }
+
+    fn random_encodings(layers: usize, sector_size: usize) -> Encodings<PedersenHasher> {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let rows = (0..layers)
             .map(|_| {
@@ -468,11 +957,17 @@ mod tests {
                         el.into_bytes()
                     })
                     .collect();
-                DiskStore::<PedersenDomain>::new_from_slice(sector_size, &row[..]).unwrap()
+                LayerStore::<PedersenDomain>::new_from_slice(sector_size, &row[..]).unwrap()
             })
             .collect();
 
-        let encodings = Encodings::<PedersenHasher>::new(rows);
+        Encodings::new(rows)
+    }
+
+    #[test]
+    fn test_column_hash() {
+        let layers = 4;
+        let encodings = random_encodings(layers, 256);
 
         for layer in 1..=layers {
             assert_eq!(
@@ -483,4 +978,50 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_lazy_and_bulk_columns() {
+        let encodings = random_encodings(4, 256);
+
+        let mut lazy = encodings.lazy_column(5);
+        assert_eq!(lazy.rows_read(), 0);
+        assert_eq!(
+            &lazy.get_node_at_layer(2),
+            encodings.column(5).unwrap().get_node_at_layer(2)
+        );
+        assert_eq!(lazy.rows_read(), 1);
+        assert_eq!(lazy.load(), encodings.column(5).unwrap());
+
+        let columns = encodings.columns(2..6);
+        assert_eq!(columns.len(), 4);
+        for (node, column) in (2..6).zip(&columns) {
+            assert_eq!(column, &encodings.column(node).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_coalesce() {
+        assert!(coalesce(&[], 2).is_empty());
+        assert_eq!(coalesce(&[4], 2), vec![4..5]);
+        assert_eq!(coalesce(&[1, 3, 6, 10, 11], 2), vec![1..7, 10..12]);
+        assert_eq!(coalesce(&[1, 2, 3], 0), vec![1..4]);
+    }
+
+    #[test]
+    fn test_read_many() {
+        let nodes = 4 * MAX_READ_GAP;
+        let encodings = random_encodings(2, nodes * NODE_SIZE);
+        let store = encodings.encoding_at_layer(1);
+
+        // Unsorted, repeated, close together and far apart.
+        let indices = [nodes - 1, 3, 0, 3, 3 + MAX_READ_GAP, 4 + 2 * MAX_READ_GAP];
+        let expected: Vec<_> = indices.iter().map(|&i| store.read_at(i)).collect();
+        assert_eq!(store.read_many(&indices), expected);
+        assert!(store.read_many(&[]).is_empty());
+
+        let columns = encodings.read_columns(&indices);
+        for (&node, column) in indices.iter().zip(&columns) {
+            assert_eq!(column, &encodings.column(node).unwrap());
+        }
+    }
 }
@@ -0,0 +1,264 @@
+//! Human readable public parameters, so that a deployment can check exactly which parameters its
+//! provers and verifiers use.
+
+use serde::{Deserialize, Serialize};
+
+use crate::drgraph::{Graph, GraphConfig, BASE_DEGREE};
+use crate::error::{Error, Result};
+use crate::hasher::Hasher;
+use crate::stacked::{
+    challenges::{LayerChallenges, Taper},
+    graph::{StackedBucketGraph, EXP_DEGREE},
+    hash::CommRHasher,
+    params::PublicParams,
+};
+use crate::util::check_node_size;
+use crate::verifier_export::{from_hex, to_hex};
+
+/// The JSON form of `PublicParams`. Every field is required and no others are allowed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct PublicParamsJson {
+    hasher: String,
+    nodes: usize,
+    node_size: usize,
+    base_degree: usize,
+    expansion_degree: usize,
+    /// The seed of the graph, which `setup` derives from the configured seed and the `porep_id`.
+    graph_seed: [u32; 7],
+    layers: usize,
+    max_challenges: usize,
+    /// `null` for untapered challenges, but never missing.
+    #[serde(deserialize_with = "Option::deserialize")]
+    taper: Option<TaperJson>,
+    /// Hex encoded, with a `0x` prefix.
+    porep_id: String,
+    comm_r_hasher: CommRHasher,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaperJson {
+    percent: usize,
+    floor: usize,
+}
+
+impl<H> PublicParams<H>
+where
+    H: 'static + Hasher,
+{
+    /// The parameters as pretty printed JSON, e.g.
+    ///
+    /// ```json
+    /// {
+    ///   "hasher": "PedersenHasher",
+    ///   "nodes": 32,
+    ///   "node-size": 32,
+    ///   "base-degree": 6,
+    ///   "expansion-degree": 8,
+    ///   "graph-seed": [1, 2, 3, 4, 5, 6, 7],
+    ///   "layers": 4,
+    ///   "max-challenges": 2,
+    ///   "taper": null,
+    ///   "porep-id": "0x0101...01",
+    ///   "comm-r-hasher": "Pedersen"
+    /// }
+    /// ```
+    ///
+    /// Only graphs with the default construction can be exported, as custom ones are code.
+    pub fn to_json(&self) -> Result<String> {
+        let base_graph = self.graph.base_graph();
+        if base_graph.config() != &GraphConfig::Bucket {
+            return Err(Error::InvalidPublicParams(
+                "custom graph constructions can't be exported".to_string(),
+            ));
+        }
+
+        let json = PublicParamsJson {
+            hasher: H::name(),
+            nodes: self.graph.size(),
+            node_size: self.node_size,
+            base_degree: base_graph.degree(),
+            expansion_degree: self.graph.expansion_degree(),
+            graph_seed: self.graph.seed(),
+            layers: self.layer_challenges.layers(),
+            max_challenges: self.layer_challenges.challenges_count_all(),
+            taper: self.layer_challenges.taper().map(|taper| TaperJson {
+                percent: taper.percent,
+                floor: taper.floor,
+            }),
+            porep_id: to_hex(&self.porep_id),
+            comm_r_hasher: self.comm_r_hasher,
+        };
+
+        serde_json::to_string_pretty(&json).map_err(Into::into)
+    }
+
+    /// Parses parameters written by `to_json`. Anything `setup` would not accept, parameters for
+    /// another hasher, and missing or unknown fields are rejected.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let json: PublicParamsJson = serde_json::from_str(json)?;
+        let invalid = Error::InvalidPublicParams;
+
+        if json.hasher != H::name() {
+            return Err(invalid(format!(
+                "parameters for {}, not {}",
+                json.hasher,
+                H::name()
+            )));
+        }
+        check_node_size::<H>(json.node_size)?;
+        if json.nodes < 2 {
+            return Err(invalid(format!("{} nodes", json.nodes)));
+        }
+        if !cfg!(feature = "unchecked-degrees")
+            && (json.base_degree, json.expansion_degree) != (BASE_DEGREE, EXP_DEGREE)
+        {
+            return Err(invalid(format!(
+                "degrees {} and {}, not {} and {}",
+                json.base_degree, json.expansion_degree, BASE_DEGREE, EXP_DEGREE
+            )));
+        }
+        if json.layers == 0 || json.max_challenges == 0 {
+            return Err(invalid("no layers or challenges".to_string()));
+        }
+
+        let layer_challenges = match json.taper {
+            None => LayerChallenges::new(json.layers, json.max_challenges),
+            Some(TaperJson { percent, floor }) => {
+                if percent > 100 || floor == 0 || floor > json.max_challenges {
+                    return Err(invalid(format!("taper of {}% down to {}", percent, floor)));
+                }
+                LayerChallenges::new_tapered(
+                    json.layers,
+                    json.max_challenges,
+                    Taper { percent, floor },
+                )
+            }
+        };
+
+        let mut porep_id = [0; 32];
+        porep_id.copy_from_slice(
+            &from_hex(&json.porep_id, 32)
+                .map_err(|_| invalid(format!("invalid porep id {}", json.porep_id)))?,
+        );
+
+        let graph = StackedBucketGraph::<H>::new_for_porep(
+            json.nodes,
+            json.base_degree,
+            json.expansion_degree,
+            json.graph_seed,
+            &GraphConfig::Bucket,
+            &porep_id,
+        )?;
+
+        Ok(PublicParams::new(
+            graph,
+            layer_challenges,
+            porep_id,
+            json.comm_r_hasher,
+            json.node_size,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::drgporep;
+    use crate::drgraph::new_seed_from;
+    use crate::hasher::{Blake2sHasher, PedersenHasher};
+    use crate::parameter_cache::ParameterSetMetadata;
+    use crate::proof::ProofScheme;
+    use crate::stacked::{SetupParams, StackedDrg};
+    use crate::test_helper::test_rng;
+    use crate::util::NODE_SIZE;
+
+    fn setup<H: 'static + Hasher>(layer_challenges: LayerChallenges) -> PublicParams<H> {
+        let sp = SetupParams {
+            drg: drgporep::DrgParams {
+                nodes: 32,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed_from(&mut test_rng()),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges,
+            porep_id: [7; 32],
+            comm_r_hasher: CommRHasher::Blake2s,
+            node_size: NODE_SIZE,
+        };
+
+        StackedDrg::<H>::setup(&sp).expect("setup failed")
+    }
+
+    #[test]
+    fn test_public_params_json_roundtrip() {
+        let tapered = LayerChallenges::new_tapered(
+            4,
+            10,
+            Taper {
+                percent: 50,
+                floor: 2,
+            },
+        );
+        for layer_challenges in vec![LayerChallenges::new(4, 2), tapered] {
+            let pp = setup::<PedersenHasher>(layer_challenges);
+            let json = pp.to_json().unwrap();
+            let imported = PublicParams::<PedersenHasher>::from_json(&json).unwrap();
+
+            assert_eq!(imported.identifier(), pp.identifier());
+            assert_eq!(imported.graph.seed(), pp.graph.seed());
+            assert_eq!(imported.to_json().unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn test_public_params_json_is_strict() {
+        let pp = setup::<PedersenHasher>(LayerChallenges::new(4, 2));
+        let json: serde_json::Value = serde_json::from_str(&pp.to_json().unwrap()).unwrap();
+
+        let import = |change: &dyn Fn(&mut serde_json::Map<String, serde_json::Value>)| {
+            let mut json = json.clone();
+            change(json.as_object_mut().unwrap());
+            PublicParams::<PedersenHasher>::from_json(&json.to_string())
+        };
+
+        assert!(import(&|_| {}).is_ok());
+        assert!(import(&|json| {
+            json.insert("extra".into(), 1.into());
+        })
+        .is_err());
+        assert!(import(&|json| {
+            json.remove("taper");
+        })
+        .is_err());
+        assert!(import(&|json| {
+            json.insert("nodes".into(), 1.into());
+        })
+        .is_err());
+        assert!(import(&|json| {
+            json.insert("base-degree".into(), 5.into());
+        })
+        .is_err());
+        assert!(import(&|json| {
+            json.insert("node-size".into(), 31.into());
+        })
+        .is_err());
+        assert!(import(&|json| {
+            json.insert("porep-id".into(), "0x07".into());
+        })
+        .is_err());
+        assert!(import(&|json| {
+            json.insert(
+                "taper".into(),
+                serde_json::json!({ "percent": 101, "floor": 1 }),
+            );
+        })
+        .is_err());
+
+        // Parameters are bound to their hasher.
+        assert!(PublicParams::<Blake2sHasher>::from_json(&json.to_string()).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::data::Data;
 use crate::error::Result;
 use crate::hasher::Hasher;
 use crate::porep::PoRep;
@@ -10,15 +11,18 @@ impl<'a, 'c, H: 'static + Hasher> PoRep<'a, H> for StackedDrg<'a, H> {
     type Tau = Tau<<H as Hasher>::Domain>;
     type ProverAux = (PersistentAux<H::Domain>, TemporaryAux<H>);
 
-    fn replicate(
+    fn replicate<'b>(
         pp: &'a PublicParams<H>,
         replica_id: &H::Domain,
-        data: &mut [u8],
+        data: Data<'b>,
         data_tree: Option<Tree<H>>,
     ) -> Result<(Self::Tau, Self::ProverAux)> {
         let (tau, p_aux, t_aux) = Self::transform_and_replicate_layers(
             &pp.graph,
             &pp.layer_challenges,
+            &pp.porep_id,
+            pp.node_size,
+            pp.comm_r_hasher,
             replica_id,
             data,
             data_tree,
@@ -37,6 +41,8 @@ impl<'a, 'c, H: 'static + Hasher> PoRep<'a, H> for StackedDrg<'a, H> {
         Self::extract_and_invert_transform_layers(
             &pp.graph,
             &pp.layer_challenges,
+            &pp.porep_id,
+            pp.node_size,
             replica_id,
             &mut data,
         )?;
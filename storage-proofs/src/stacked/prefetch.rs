@@ -0,0 +1,231 @@
+//! Prefetching of parent labels during labeling, tuned by the `sdr_prefetch` and
+//! `sdr_prefetch_distance` settings.
+//!
+//! Labeling a node reads the labels of its parents, which are scattered over the current and the
+//! previous layer, so most of these reads miss the CPU caches. With prefetching, the parents of
+//! the node `distance` nodes ahead are looked up early, and their labels are fetched while the
+//! nodes in between are hashed. Which distance hides the memory latency best, and whether labels
+//! are better fetched into all cache levels (`t0`) or only into L2 (`t1`), depends on the CPU,
+//! the `prefetch` bench sweeps both. Prefetching is only implemented on x86_64, it does nothing
+//! elsewhere.
+
+use crate::drgraph::Graph;
+use crate::error::{Error, Result};
+use crate::hasher::Hasher;
+use crate::settings;
+use crate::stacked::graph::StackedBucketGraph;
+
+/// The farthest ahead of the labeled node parents can be prefetched.
+pub const MAX_PREFETCH_DISTANCE: usize = 256;
+
+/// Which caches parent labels are prefetched into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchHint {
+    None,
+    /// All cache levels, `_MM_HINT_T0`.
+    T0,
+    /// L2 and up, `_MM_HINT_T1`.
+    T1,
+}
+
+/// How parent labels are prefetched during labeling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Prefetch {
+    hint: PrefetchHint,
+    distance: usize,
+}
+
+impl Prefetch {
+    /// Prefetches the parents of the node `distance` nodes ahead of the one being labeled. The
+    /// distance is ignored without a hint, and must be between 1 and `MAX_PREFETCH_DISTANCE`
+    /// otherwise.
+    pub fn new(hint: PrefetchHint, distance: usize) -> Result<Self> {
+        if hint == PrefetchHint::None {
+            return Ok(Prefetch::none());
+        }
+        if distance == 0 || distance > MAX_PREFETCH_DISTANCE {
+            return Err(Error::InvalidSetting(
+                "sdr_prefetch_distance",
+                distance.to_string(),
+            ));
+        }
+
+        Ok(Prefetch { hint, distance })
+    }
+
+    /// No prefetching, parents are looked up as their node is labeled.
+    pub fn none() -> Self {
+        Prefetch {
+            hint: PrefetchHint::None,
+            distance: 0,
+        }
+    }
+
+    /// The prefetching chosen by the `sdr_prefetch` and `sdr_prefetch_distance` settings.
+    pub fn from_settings() -> Result<Self> {
+        let settings = settings::SETTINGS.lock().unwrap();
+        Prefetch::parse(&settings.sdr_prefetch, settings.sdr_prefetch_distance)
+    }
+
+    fn parse(hint: &str, distance: usize) -> Result<Self> {
+        let hint = match hint {
+            "none" => PrefetchHint::None,
+            "t0" => PrefetchHint::T0,
+            "t1" => PrefetchHint::T1,
+            other => return Err(Error::InvalidSetting("sdr_prefetch", other.to_string())),
+        };
+
+        Prefetch::new(hint, distance)
+    }
+
+    pub fn hint(&self) -> PrefetchHint {
+        self.hint
+    }
+
+    pub fn distance(&self) -> usize {
+        self.distance
+    }
+}
+
+/// Looks up the parents of the nodes of a layer in order, `distance` nodes ahead of the node
+/// asked for, and prefetches the labels of the parents it looks up.
+pub(crate) struct ParentsAhead<'a, H: 'static + Hasher> {
+    graph: &'a StackedBucketGraph<H>,
+    prefetch: Prefetch,
+    base_degree: usize,
+    /// The parents of the last `distance + 1` nodes looked up, in slots of the node modulo
+    /// `distance + 1`.
+    parents: Vec<usize>,
+    /// The next node to look up. The first node has no parents.
+    next: usize,
+}
+
+impl<'a, H: 'static + Hasher> ParentsAhead<'a, H> {
+    pub(crate) fn new(graph: &'a StackedBucketGraph<H>, prefetch: Prefetch) -> Self {
+        ParentsAhead {
+            graph,
+            prefetch,
+            base_degree: graph.base_graph().degree(),
+            parents: vec![0; (prefetch.distance + 1) * graph.degree()],
+            next: 1,
+        }
+    }
+
+    /// The parents of `node`, which may be at most `distance` nodes before the last node asked
+    /// for. Labels are prefetched from `labels` for base parents, and from `exp_labels` for
+    /// expander parents, with `node_size` bytes per label.
+    pub(crate) fn parents(
+        &mut self,
+        node: usize,
+        labels: &[u8],
+        exp_labels: Option<&[u8]>,
+        node_size: usize,
+    ) -> &[usize] {
+        let degree = self.graph.degree();
+        let slots = self.prefetch.distance + 1;
+        assert!(node > 0, "the first node has no parents");
+        assert!(node + slots >= self.next, "node {} was dropped", node);
+
+        let last = std::cmp::min(node + self.prefetch.distance, self.graph.size() - 1);
+        while self.next <= last {
+            let slot = self.next % slots;
+            let parents = &mut self.parents[slot * degree..(slot + 1) * degree];
+            self.graph.parents(self.next, parents);
+
+            if self.prefetch.hint != PrefetchHint::None {
+                let (base, exp) = parents.split_at(self.base_degree);
+                for parent in base {
+                    if let Some(label) = labels.get(parent * node_size) {
+                        prefetch(self.prefetch.hint, label);
+                    }
+                }
+                if let Some(exp_labels) = exp_labels {
+                    for parent in exp {
+                        if let Some(label) = exp_labels.get(parent * node_size) {
+                            prefetch(self.prefetch.hint, label);
+                        }
+                    }
+                }
+            }
+            self.next += 1;
+        }
+
+        let slot = node % slots;
+        &self.parents[slot * degree..(slot + 1) * degree]
+    }
+}
+
+/// Fetches the cache line of `data` into the caches chosen by `hint`.
+#[inline(always)]
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn prefetch(hint: PrefetchHint, data: &u8) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0, _MM_HINT_T1};
+
+        let ptr = data as *const u8 as *const i8;
+        match hint {
+            PrefetchHint::None => {}
+            PrefetchHint::T0 => _mm_prefetch(ptr, _MM_HINT_T0),
+            PrefetchHint::T1 => _mm_prefetch(ptr, _MM_HINT_T1),
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = (hint, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::drgraph::{new_seed_from, BASE_DEGREE};
+    use crate::hasher::PedersenHasher;
+    use crate::stacked::graph::EXP_DEGREE;
+    use crate::test_helper::test_rng;
+    use crate::util::NODE_SIZE;
+
+    #[test]
+    fn test_parse_prefetch() {
+        assert_eq!(Prefetch::parse("none", 7).unwrap(), Prefetch::none());
+        let t0 = Prefetch::parse("t0", 4).unwrap();
+        assert_eq!((t0.hint(), t0.distance()), (PrefetchHint::T0, 4));
+        assert_eq!(Prefetch::parse("t1", 1).unwrap().hint(), PrefetchHint::T1);
+
+        assert!(Prefetch::parse("T0", 4).is_err());
+        assert!(Prefetch::parse("t0", 0).is_err());
+        assert!(Prefetch::parse("t1", MAX_PREFETCH_DISTANCE + 1).is_err());
+    }
+
+    #[test]
+    fn test_parents_ahead() {
+        let nodes = 64;
+        let graph = StackedBucketGraph::<PedersenHasher>::new_stacked(
+            nodes,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            new_seed_from(&mut test_rng()),
+        );
+        let labels = vec![0u8; nodes * NODE_SIZE];
+        let mut expected = vec![0; graph.degree()];
+
+        for &prefetch in &[
+            Prefetch::none(),
+            Prefetch::new(PrefetchHint::T0, 1).unwrap(),
+            Prefetch::new(PrefetchHint::T1, 8).unwrap(),
+        ] {
+            let mut ahead = ParentsAhead::new(&graph, prefetch);
+            for node in 1..nodes {
+                graph.parents(node, &mut expected);
+                assert_eq!(
+                    ahead.parents(node, &labels, Some(&labels), NODE_SIZE),
+                    &expected[..]
+                );
+                // Asking for a node again, like a batch which stopped before it does.
+                assert_eq!(ahead.parents(node, &labels, None, NODE_SIZE), &expected[..]);
+            }
+        }
+    }
+}
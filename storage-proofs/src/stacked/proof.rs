@@ -1,28 +1,56 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Instant;
 
-use blake2s_simd::Params as Blake2s;
-use merkletree::merkle::FromIndexedParallelIterator;
-use merkletree::store::DiskStore;
-use paired::bls12_381::Fr;
+use merkletree::merkle::Element;
 use rayon::prelude::*;
 
+use crate::cancel;
+use crate::core_binding::TreePool;
+use crate::data::Data;
 use crate::drgraph::Graph;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::hasher::{Domain, Hasher};
-use crate::merkle::{MerkleProof, MerkleTree, Store};
+use crate::label_buffer::LabelBuffer;
+use crate::label_file::Access;
+use crate::measurements::{self, Operation};
+use crate::merkle::{build_tree, MerkleProof};
+use crate::numa::{self, Placement};
+use crate::progress::{self, Phase, Tracker};
+use crate::resources::{self, LayerStorage};
+use crate::sequential_writer::SequentialWriter;
+use crate::settings;
 use crate::stacked::{
+    audit,
     challenges::LayerChallenges,
     column::Column,
+    column_proof::ColumnProof,
     encode::{decode, encode},
     encoding_proof::EncodingProof,
     graph::StackedBucketGraph,
-    hash::hash2,
+    hash::CommRHasher,
+    instrument::ProveLayersHooks,
+    label::{label_layer, Labeler},
     params::{
-        get_node, Encodings, PersistentAux, Proof, PublicInputs, ReplicaColumnProof, Tau,
-        TemporaryAux, TransformedLayers, Tree,
+        get_node, Encodings, LayerStore, PersistentAux, Proof, PublicInputs, PublicParams,
+        ReplicaColumnProof, Tau, TemporaryAux, TransformedLayers, Tree,
     },
+    prefetch::Prefetch,
 };
-use crate::util::{data_at_node, data_at_node_offset, NODE_SIZE};
+
+/// The number of nodes `extract_range` decodes at a time.
+pub const EXTRACT_CHUNK_NODES: usize = 1 << 14;
+
+/// Where `extract_range` reads the keys of the last layer from.
+enum Keys<H: Hasher> {
+    Stored(File),
+    Generated(Encodings<H>),
+}
 
 #[derive(Debug)]
 pub struct StackedDrg<'a, H: 'a + Hasher> {
@@ -37,54 +65,75 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
         _p_aux: &PersistentAux<H::Domain>,
         t_aux: &TemporaryAux<H>,
         layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
         layers: usize,
         _total_layers: usize,
         partition_count: usize,
+        hooks: &dyn ProveLayersHooks,
     ) -> Result<Vec<Vec<Proof<H>>>> {
         assert!(layers > 0);
         assert_eq!(t_aux.encodings.len(), layers);
 
         let graph_size = graph.size();
+        let token = cancel::current();
 
-        let get_drg_parents_columns = |x: usize| -> Result<Vec<Column<H>>> {
-            let base_degree = graph.base_graph().degree();
-
-            let mut columns = Vec::with_capacity(base_degree);
-
-            let mut parents = vec![0; base_degree];
-            graph.base_parents(x, &mut parents);
-
-            for parent in &parents {
-                columns.push(t_aux.column(*parent)?);
-            }
-
-            debug_assert!(columns.len() == base_degree);
+        let degree = graph.degree();
+        let base_degree = graph.base_graph().degree();
 
-            Ok(columns)
-        };
-
-        let get_exp_parents_columns = |x: usize| -> Result<Vec<Column<H>>> {
-            graph.expanded_parents(x, |parents| {
-                parents
-                    .iter()
-                    .map(|parent| t_aux.column(*parent as usize))
-                    .collect()
-            })
-        };
+        // Challenges and their parents are scattered over the layers.
+        t_aux.encodings.advise(Access::Random);
 
-        (0..partition_count)
+        let proofs = (0..partition_count)
             .map(|k| {
                 trace!("proving partition {}/{}", k + 1, partition_count);
 
                 // Derive the set of challenges we are proving over.
-                let challenges = pub_inputs.all_challenges(layer_challenges, graph_size, Some(k));
+                let challenges =
+                    pub_inputs.all_challenges(layer_challenges, porep_id, graph_size, Some(k));
+
+                // The parents of every challenge, base parents first, looked up once for the
+                // columns and all layers.
+                let mut parents = vec![0; challenges.len() * degree];
+                parents
+                    .par_chunks_mut(degree)
+                    .zip(challenges.par_iter())
+                    .for_each(|(parents, &challenge)| graph.parents(challenge, parents));
+
+                // Read the columns of all challenges and their parents in one pass over each
+                // layer, rather than a random read for every label.
+                let mut nodes = challenges.clone();
+                for (challenge_index, parents) in parents.chunks(degree).enumerate() {
+                    // Only layers after the first are labeled from the expander parents, so
+                    // their columns are only opened if such a layer is proven.
+                    if layer_challenges.needs_exp_parents(challenge_index) {
+                        nodes.extend_from_slice(parents);
+                    } else {
+                        nodes.extend_from_slice(&parents[..base_degree]);
+                    }
+                }
+                nodes.sort_unstable();
+                nodes.dedup();
+                let columns: HashMap<usize, Column<H>> = nodes
+                    .iter()
+                    .cloned()
+                    .zip(t_aux.read_columns(&nodes))
+                    .collect();
+                let open_columns = |nodes: &[usize]| -> Vec<ColumnProof<H>> {
+                    nodes
+                        .iter()
+                        .map(|node| columns[node].clone().into_proof(&t_aux.tree_c))
+                        .collect()
+                };
 
                 // Stacked commitment specifics
                 challenges
                     .into_par_iter()
+                    .zip(parents.par_chunks(degree))
                     .enumerate()
-                    .map(|(challenge_index, challenge)| {
+                    .map(|(challenge_index, (challenge, parents))| {
                         trace!(" challenge {} ({})", challenge, challenge_index);
+                        token.check()?;
+                        let start = Instant::now();
                         assert!(challenge < graph.size(), "Invalid challenge");
                         assert!(challenge > 0, "Invalid challenge");
 
@@ -92,32 +141,13 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
                         let comm_d_proof =
                             MerkleProof::new_from_proof(&t_aux.tree_d.gen_proof(challenge));
 
-                        // Stacked replica column openings
-                        let rpc = {
-                            // All labels in C_X
-                            trace!("  c_x");
-                            let c_x = t_aux.column(challenge)?.into_proof(&t_aux.tree_c);
-
-                            // All labels in the DRG parents.
-                            trace!("  drg_parents");
-                            let drg_parents = get_drg_parents_columns(challenge)?
-                                .into_iter()
-                                .map(|column| column.into_proof(&t_aux.tree_c))
-                                .collect::<Vec<_>>();
-
-                            // Labels for the expander parents
-                            trace!("  exp_parents");
-                            let exp_parents = get_exp_parents_columns(challenge)?
-                                .into_iter()
-                                .map(|column| column.into_proof(&t_aux.tree_c))
-                                .collect::<Vec<_>>();
-
-                            ReplicaColumnProof {
-                                c_x,
-                                drg_parents,
-                                exp_parents,
-                            }
-                        };
+                        // All labels in C_X
+                        trace!("  c_x");
+                        let c_x = columns[&challenge].clone().into_proof(&t_aux.tree_c);
+
+                        let drg_nodes = &parents[..base_degree];
+                        let exp_nodes = &parents[base_degree..];
+                        let needs_exp_parents = layer_challenges.needs_exp_parents(challenge_index);
 
                         // Final replica layer openings
                         trace!("final replica layer openings");
@@ -127,6 +157,16 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
                         // Encoding Proof Layer 1..l
                         let mut encoding_proofs = Vec::with_capacity(layers);
 
+                        // Every column holds one label per layer.
+                        let opened = 1
+                            + drg_nodes.len()
+                            + if needs_exp_parents {
+                                exp_nodes.len()
+                            } else {
+                                0
+                            };
+                        let mut layer_labels = vec![opened; layers];
+
                         for layer in 1..=layers {
                             let include_challenge =
                                 layer_challenges.include_challenge_at_layer(layer, challenge_index);
@@ -141,33 +181,16 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
                                 continue;
                             }
 
-                            let parents_data = if layer == 1 {
-                                let mut parents = vec![0; graph.base_graph().degree()];
-                                graph.base_parents(challenge, &mut parents);
-
-                                parents
-                                    .into_iter()
-                                    .map(|parent| t_aux.domain_node_at_layer(layer, parent))
-                                    .collect::<Result<_>>()?
-                            } else {
-                                let mut parents = vec![0; graph.degree()];
-                                graph.parents(challenge, &mut parents);
-                                let base_parents_count = graph.base_graph().degree();
-
-                                parents
-                                    .into_iter()
-                                    .enumerate()
-                                    .map(|(i, parent)| {
-                                        if i < base_parents_count {
-                                            // parents data for base parents is from the current layer
-                                            t_aux.domain_node_at_layer(layer, parent)
-                                        } else {
-                                            // parents data for exp parents is from the previous layer
-                                            t_aux.domain_node_at_layer(layer - 1, parent)
-                                        }
-                                    })
-                                    .collect::<Result<_>>()?
-                            };
+                            // parents data for base parents is from the current layer, for exp
+                            // parents, which the first layer has none of, from the previous layer
+                            let base_labels = drg_nodes
+                                .iter()
+                                .map(|node| *columns[node].get_node_at_layer(layer));
+                            let exp_labels = exp_nodes
+                                .iter()
+                                .filter(|_| layer > 1)
+                                .map(|node| *columns[node].get_node_at_layer(layer - 1));
+                            let parents_data = base_labels.chain(exp_labels).collect();
 
                             let proof = EncodingProof::<H>::new(challenge as u64, parents_data);
 
@@ -175,11 +198,12 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
                                 let (encoded_node, decoded_node) = if layer == layers {
                                     (comm_r_last_proof.leaf(), Some(comm_d_proof.leaf()))
                                 } else {
-                                    (rpc.c_x.get_node_at_layer(layer), None)
+                                    (c_x.get_node_at_layer(layer), None)
                                 };
 
                                 assert!(
                                     proof.verify(
+                                        porep_id,
                                         &pub_inputs.replica_id,
                                         &encoded_node,
                                         decoded_node
@@ -188,9 +212,30 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
                                 );
                             }
 
+                            layer_labels[layer - 1] += proof.parents.len();
                             encoding_proofs.push(proof);
                         }
 
+                        // Stacked replica column openings
+                        trace!("  drg_parents");
+                        let drg_parents = open_columns(drg_nodes);
+                        trace!("  exp_parents");
+                        let exp_parents = if needs_exp_parents {
+                            open_columns(exp_nodes)
+                        } else {
+                            Vec::new()
+                        };
+                        let rpc = ReplicaColumnProof {
+                            c_x,
+                            drg_parents,
+                            exp_parents,
+                        };
+
+                        for (i, labels) in layer_labels.into_iter().enumerate() {
+                            hooks.on_layer_bytes(i + 1, labels * H::Domain::byte_len());
+                        }
+                        hooks.on_challenge(k, challenge_index, challenge, start.elapsed());
+
                         Ok(Proof {
                             comm_d_proofs: comm_d_proof,
                             replica_column_proofs: rpc,
@@ -200,12 +245,28 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
                     })
                     .collect()
             })
-            .collect()
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(dir) = audit::audit_dir() {
+            audit::record(
+                &dir,
+                graph_size,
+                pub_inputs,
+                layer_challenges,
+                porep_id,
+                t_aux,
+                &proofs,
+            )?;
+        }
+
+        Ok(proofs)
     }
 
     pub(crate) fn extract_and_invert_transform_layers(
         graph: &StackedBucketGraph<H>,
         layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
+        node_size: usize,
         replica_id: &<H as Hasher>::Domain,
         data: &mut [u8],
     ) -> Result<()> {
@@ -215,15 +276,25 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
         assert!(layers > 0);
 
         // generate encodings
-        let encodings = Self::generate_layers(graph, layer_challenges, replica_id)?;
+        let encodings =
+            Self::generate_layers(graph, layer_challenges, porep_id, node_size, replica_id)?;
+
+        Self::decode_with_layers(&encodings, node_size, data)
+    }
 
+    /// Decodes the replica in `data` in place, with the keys of the last of `encodings`.
+    pub(crate) fn decode_with_layers(
+        encodings: &Encodings<H>,
+        node_size: usize,
+        data: &mut [u8],
+    ) -> Result<()> {
         let size = encodings.encoding_at_last_layer().len();
 
         for (key, encoded_node_bytes) in encodings
             .encoding_at_last_layer()
             .read_range(0..size)
             .into_iter()
-            .zip(data.chunks_mut(NODE_SIZE))
+            .zip(data.chunks_mut(node_size))
         {
             let encoded_node = H::Domain::try_from_bytes(encoded_node_bytes)?;
             let data_node = decode::<H::Domain>(key, encoded_node);
@@ -235,74 +306,167 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
         Ok(())
     }
 
+    /// Decodes the nodes `nodes` of a replica from `replica`, which must be positioned at the
+    /// first of them, and passes the data to `sink` in order, `EXTRACT_CHUNK_NODES` nodes at a
+    /// time but for the last chunk. Unlike `extract_all`, neither the replica nor the data are
+    /// ever held in memory as a whole.
+    ///
+    /// The keys are read from `last_layer`, the labels of the last layer as stored by
+    /// `TemporaryAux::convert_to_stored`, if given, and regenerated otherwise.
+    pub fn extract_range<R, F>(
+        pp: &PublicParams<H>,
+        replica_id: &H::Domain,
+        last_layer: Option<&Path>,
+        replica: &mut R,
+        nodes: Range<usize>,
+        mut sink: F,
+    ) -> Result<()>
+    where
+        R: Read,
+        F: FnMut(&[u8]) -> Result<()>,
+    {
+        let node_size = pp.node_size;
+        let size = pp.graph.size();
+        if nodes.start > nodes.end || nodes.end > size {
+            return Err(Error::OutOfBounds(nodes.end, size));
+        }
+
+        let mut keys = match last_layer {
+            Some(path) => {
+                let mut file =
+                    File::open(path).map_err(|err| Error::IoAt(path.to_path_buf(), err))?;
+                let len = file.metadata()?.len() as usize;
+                if len < size * node_size {
+                    return Err(Error::InsufficientData(len, size * node_size));
+                }
+                file.seek(SeekFrom::Start((nodes.start * node_size) as u64))?;
+                Keys::Stored(file)
+            }
+            None => Keys::Generated(Self::generate_layers(
+                &pp.graph,
+                &pp.layer_challenges,
+                &pp.porep_id,
+                node_size,
+                replica_id,
+            )?),
+        };
+
+        let mut buf = vec![0; EXTRACT_CHUNK_NODES * node_size];
+        let mut key_buf = Vec::new();
+        let mut start = nodes.start;
+        while start < nodes.end {
+            let end = min(start + EXTRACT_CHUNK_NODES, nodes.end);
+            let chunk = &mut buf[..(end - start) * node_size];
+            replica.read_exact(chunk)?;
+
+            let chunk_keys = match keys {
+                Keys::Stored(ref mut file) => {
+                    key_buf.resize(chunk.len(), 0);
+                    file.read_exact(&mut key_buf)?;
+                    key_buf
+                        .chunks(node_size)
+                        .map(H::Domain::try_from_bytes)
+                        .collect::<Result<Vec<_>>>()?
+                }
+                Keys::Generated(ref encodings) => {
+                    encodings.encoding_at_last_layer().read_range(start..end)
+                }
+            };
+
+            for (key, node) in chunk_keys.into_iter().zip(chunk.chunks_mut(node_size)) {
+                let data_node = decode::<H::Domain>(key, H::Domain::try_from_bytes(node)?);
+                node.copy_from_slice(AsRef::<[u8]>::as_ref(&data_node));
+            }
+
+            sink(chunk)?;
+            start = end;
+        }
+
+        Ok(())
+    }
+
     fn generate_layers(
         graph: &StackedBucketGraph<H>,
         layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
+        node_size: usize,
+        replica_id: &<H as Hasher>::Domain,
+    ) -> Result<Encodings<H>> {
+        numa::place_labeling(|placement| {
+            Self::label_layers(
+                graph,
+                layer_challenges,
+                porep_id,
+                node_size,
+                replica_id,
+                placement,
+            )
+        })
+    }
+
+    fn label_layers(
+        graph: &StackedBucketGraph<H>,
+        layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
+        node_size: usize,
         replica_id: &<H as Hasher>::Domain,
+        placement: Option<&Placement>,
     ) -> Result<Encodings<H>> {
         info!("generate layers");
         let layers = layer_challenges.layers();
-        let mut encodings: Vec<DiskStore<H::Domain>> = Vec::with_capacity(layers);
+        let mut encodings: Vec<LayerStore<H::Domain>> = Vec::with_capacity(layers);
 
-        let layer_size = graph.size() * NODE_SIZE;
-        let mut parents = vec![0; graph.degree()];
-        let mut encoding = vec![0u8; layer_size];
+        let layer_size = graph.size() * node_size;
+        let mut encoding = numa::layer_buffer(placement, layer_size)?;
 
-        let mut exp_parents_data: Option<Vec<u8>> = None;
+        let mut exp_parents_data: Option<LabelBuffer> = None;
 
-        // setup hasher to reuse
-        let mut base_hasher = Blake2s::new().hash_length(NODE_SIZE).to_state();
-        // hash replica id
-        base_hasher.update(AsRef::<[u8]>::as_ref(replica_id));
+        let labeler = Labeler::new(porep_id, AsRef::<[u8]>::as_ref(replica_id), node_size);
+        let prefetch = Prefetch::from_settings()?;
+        let token = cancel::current();
 
         for i in 0..layers {
             let layer = i + 1;
             info!("generating layer: {}", layer);
+            let tracker = Tracker::new(Phase::Labeling, Some(layer), graph.size());
+            let start = Instant::now();
+
+            label_layer(
+                graph,
+                &labeler,
+                0,
+                &mut encoding,
+                exp_parents_data
+                    .as_ref()
+                    .map(|parents_data| &parents_data[..]),
+                node_size,
+                prefetch,
+                |count| {
+                    token.check()?;
+                    tracker.advance(count);
+                    Ok(())
+                },
+            )?;
 
-            for node in 0..graph.size() {
-                graph.parents(node, &mut parents);
-
-                // CreateKey inlined, to avoid borrow issues
-
-                let mut hasher = base_hasher.clone();
-
-                // hash node id
-                let node_arr = (node as u64).to_le_bytes();
-                hasher.update(&node_arr);
-
-                // hash parents for all non 0 nodes
-                if node > 0 {
-                    let base_parents_count = graph.base_graph().degree();
-
-                    // Base parents
-                    for parent in parents.iter().take(base_parents_count) {
-                        let buf = data_at_node(&encoding, *parent).expect("invalid node");
-                        hasher.update(buf);
-                    }
-
-                    if let Some(ref parents_data) = exp_parents_data {
-                        // Expander parents
-                        for parent in parents.iter().skip(base_parents_count) {
-                            let buf = data_at_node(parents_data, *parent).expect("invalid node");
-                            hasher.update(&buf);
-                        }
-                    }
+            // NOTE: this means we currently keep 2x sector size around, to improve speed.
+            match exp_parents_data {
+                Some(ref mut parents_data) => parents_data.copy_from_slice(&encoding),
+                None => {
+                    let mut parents_data = numa::layer_buffer(placement, layer_size)?;
+                    parents_data.copy_from_slice(&encoding);
+                    exp_parents_data = Some(parents_data);
                 }
-
-                let start = data_at_node_offset(node);
-                let end = start + NODE_SIZE;
-
-                // store resulting key
-                encoding[start..end].copy_from_slice(hasher.finalize().as_ref());
-                // strip last two bits, to ensure result is in Fr.
-                encoding[start + NODE_SIZE - 1] &= 0b0011_1111;
             }
 
-            // NOTE: this means we currently keep 2x sector size around, to improve speed.
-            exp_parents_data = Some(encoding.clone());
-
-            // Write the result to disk to avoid keeping it in memory all the time.
-            encodings.push(DiskStore::new_from_slice(layer_size, &encoding)?);
+            // Write the result to disk to avoid keeping it in memory all the time, unless the
+            // sector is small enough to keep all layers in memory.
+            encodings.push(LayerStore::new_from_slice(layer_size, &encoding)?);
+            measurements::record(
+                Operation::LabelLayer,
+                Some(layer),
+                start.elapsed(),
+                layer_size as u64,
+            );
         }
 
         assert_eq!(
@@ -314,33 +478,78 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
         Ok(Encodings::<H>::new(encodings))
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn transform_and_replicate_layers(
         graph: &StackedBucketGraph<H>,
         layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
+        node_size: usize,
+        comm_r_hasher: CommRHasher,
         replica_id: &<H as Hasher>::Domain,
-        data: &mut [u8],
+        data: Data,
         data_tree: Option<Tree<H>>,
     ) -> Result<TransformedLayers<H>> {
         trace!("transform_and_replicate_layers");
-        let nodes_count = graph.size();
 
-        assert_eq!(data.len(), nodes_count * NODE_SIZE);
+        Self::replicate_with_layers(
+            graph.size(),
+            layer_challenges.layers(),
+            node_size,
+            comm_r_hasher,
+            data,
+            data_tree,
+            || Self::generate_layers(graph, layer_challenges, porep_id, node_size, replica_id),
+        )
+    }
 
-        let layers = layer_challenges.layers();
+    /// Encodes `data` with the keys of the last of the `layers` returned by `generate_layers`,
+    /// and commits to the data, the replica and the columns of all layers. The layers are
+    /// generated while the data tree is built.
+    pub(crate) fn replicate_with_layers<F>(
+        nodes_count: usize,
+        layers: usize,
+        node_size: usize,
+        comm_r_hasher: CommRHasher,
+        mut data: Data,
+        data_tree: Option<Tree<H>>,
+        generate_layers: F,
+    ) -> Result<TransformedLayers<H>>
+    where
+        F: FnOnce() -> Result<Encodings<H>> + Send,
+    {
         assert!(layers > 0);
 
+        // Fail before hours of labeling, rather than halfway through them.
+        let sector_size = nodes_count * node_size;
+        let storage = LayerStorage::from_settings(sector_size)?;
+        resources::ensure_memory(
+            resources::estimate_replication(sector_size as u64, layers, storage).peak_memory(),
+        )?;
+
+        // Both trees built after labeling share one pool, so that they don't oversubscribe the
+        // cores when it is bound to them.
+        let tree_pool = TreePool::from_settings()?;
+
+        data.ensure_data()?;
+        assert_eq!(data.len(), nodes_count * node_size);
+
         let build_tree = |tree_data: &[u8]| {
             trace!("building tree (size: {})", tree_data.len());
 
-            let leafs = tree_data.len() / NODE_SIZE;
-            assert_eq!(tree_data.len() % NODE_SIZE, 0);
-            MerkleTree::from_par_iter(
-                (0..leafs)
-                    .into_par_iter()
-                    .map(|i| get_node::<H>(tree_data, i).unwrap()),
+            let leafs = tree_data.len() / node_size;
+            assert_eq!(tree_data.len() % node_size, 0);
+            build_tree::<H, _>(
+                leafs,
+                |i| get_node::<H>(tree_data, i, node_size).unwrap(),
+                true,
             )
         };
 
+        // The work below is spread over several threads, which all report to the caller's observer
+        // and are cancelled with the caller.
+        let progress = progress::current();
+        let token = cancel::current();
+
         #[allow(clippy::type_complexity)]
         let (tree_d, tree_r_last, tree_c, comm_r, encodings): (
             Tree<H>,
@@ -350,81 +559,131 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
             Encodings<_>,
         ) = crossbeam::thread::scope(|s| -> Result<_> {
             // encode all layers
-            let encodings_handle =
-                s.spawn(move |_| Self::generate_layers(graph, layer_challenges, replica_id));
+            let labeling_progress = progress.clone();
+            let labeling_token = token.clone();
+            let encodings_handle = s.spawn(move |_| {
+                labeling_token.install(|| labeling_progress.observe(generate_layers))
+            });
 
             // Build the MerkleTree over the original data
             info!("building merkle tree for the original data");
+            progress.report(Phase::TreeD, None, 0);
             let tree_d = match data_tree {
                 Some(t) => t,
-                None => build_tree(&data),
+                None => {
+                    let tree_data = data.as_slice()?;
+                    measurements::measure(Operation::TreeD, None, tree_data.len() as u64, || {
+                        build_tree(tree_data)
+                    })?
+                }
             };
+            progress.report(Phase::TreeD, None, 100);
+
+            // the data is not needed until all layers are encoded
+            data.drop_data()?;
 
             // encode layers
             let encodings = encodings_handle.join().expect("failed to encode layers")?;
+            assert_eq!(encodings.len(), layers, "wrong number of layers");
             let size = encodings.encoding_at_last_layer().len();
 
             // encode original data into the last layer
+            token.check()?;
             info!("encoding data");
-            encodings
-                .encoding_at_last_layer()
-                .read_range(0..size)
-                .into_par_iter()
-                .zip(data.par_chunks_mut(NODE_SIZE))
-                .try_for_each(|(key, data_node_bytes)| -> Result<()> {
-                    let data_node = H::Domain::try_from_bytes(data_node_bytes)?;
-                    let encoded_node = encode::<H::Domain>(key, data_node);
-
-                    // store result in the data
-                    data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
+            progress.report(Phase::Encoding, None, 0);
+            data.ensure_data()?;
+            match data.path().map(Path::to_path_buf) {
+                Some(path) => {
+                    // Write the replica sequentially in large blocks, rather than through the
+                    // mapping, which scatters small writes across the file.
+                    encode_into_file::<H>(
+                        encodings.encoding_at_last_layer(),
+                        data.as_slice()?,
+                        &path,
+                        node_size,
+                    )?;
+
+                    // Map the file again, so no stale pages can be read.
+                    data.drop_data()?;
+                    data.ensure_data()?;
+                }
+                None => {
+                    encodings
+                        .encoding_at_last_layer()
+                        .read_range(0..size)
+                        .into_par_iter()
+                        .zip(data.as_mut_slice()?.par_chunks_mut(node_size))
+                        .try_for_each(|(key, data_node_bytes)| -> Result<()> {
+                            let data_node = H::Domain::try_from_bytes(data_node_bytes)?;
+                            let encoded_node = encode::<H::Domain>(key, data_node);
+
+                            // store result in the data
+                            data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
+
+                            Ok(())
+                        })?;
+                }
+            }
 
-                    Ok(())
-                })?;
+            progress.report(Phase::Encoding, None, 100);
 
-            // the last layer is now stored in the data slice
-            let r_last = data;
+            // the last layer is now stored in the data
+            let r_last = data.as_slice()?;
 
             // construct final replica commitment
-            let tree_r_last_handle = s.spawn(move |_| build_tree(&r_last));
+            let tree_r_last_progress = progress.clone();
+            let tree_r_last_pool = &tree_pool;
+            let tree_r_last_handle = s.spawn(move |_| -> Result<_> {
+                tree_r_last_progress.report(Phase::TreeRLast, None, 0);
+                let tree_r_last =
+                    measurements::measure(Operation::TreeRLast, None, r_last.len() as u64, || {
+                        tree_r_last_pool.install(|| build_tree(r_last))
+                    })?;
+                tree_r_last_progress.report(Phase::TreeRLast, None, 100);
+
+                Ok(tree_r_last)
+            });
 
             // construct column commitments
+            token.check()?;
             info!("constructing column commitments");
+            progress.report(Phase::TreeC, None, 0);
 
             // For now split into 4 chunks to trade space (memory) vs speed reasonably.
             let chunks = 4;
 
-            let len = nodes_count * NODE_SIZE;
+            let len = nodes_count * node_size;
             let node_part_len = nodes_count / chunks;
 
             let mut cs = vec![0; len];
 
-            let part_len = node_part_len * NODE_SIZE;
+            let part_len = node_part_len * node_size;
             let (p1, p2) = cs.split_at_mut(part_len * 2);
             let (a, b) = p1.split_at_mut(part_len);
             let (c, d) = p2.split_at_mut(part_len);
 
             crossbeam::thread::scope(|s| {
                 let a_handle = s.spawn(|_| {
-                    for (x, chunk) in (0..node_part_len).zip(a.chunks_exact_mut(NODE_SIZE)) {
+                    for (x, chunk) in (0..node_part_len).zip(a.chunks_exact_mut(node_size)) {
                         chunk.copy_from_slice(AsRef::<[u8]>::as_ref(&encodings.column_hash(x)));
                     }
                 });
                 let b_handle = s.spawn(|_| {
                     for (x, chunk) in
-                        (node_part_len..2 * node_part_len).zip(b.chunks_exact_mut(NODE_SIZE))
+                        (node_part_len..2 * node_part_len).zip(b.chunks_exact_mut(node_size))
                     {
                         chunk.copy_from_slice(AsRef::<[u8]>::as_ref(&encodings.column_hash(x)));
                     }
                 });
                 let c_handle = s.spawn(|_| {
                     for (x, chunk) in
-                        (2 * node_part_len..3 * node_part_len).zip(c.chunks_exact_mut(NODE_SIZE))
+                        (2 * node_part_len..3 * node_part_len).zip(c.chunks_exact_mut(node_size))
                     {
                         chunk.copy_from_slice(AsRef::<[u8]>::as_ref(&encodings.column_hash(x)));
                     }
                 });
                 let d_handle = s.spawn(|_| {
-                    for (x, chunk) in (3 * node_part_len..).zip(d.chunks_exact_mut(NODE_SIZE)) {
+                    for (x, chunk) in (3 * node_part_len..).zip(d.chunks_exact_mut(node_size)) {
                         chunk.copy_from_slice(AsRef::<[u8]>::as_ref(&encodings.column_hash(x)));
                     }
                 });
@@ -436,26 +695,33 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
             })?;
 
             // build the tree for CommC
-            let tree_c = build_tree(&cs);
+            let tree_c = measurements::measure(Operation::TreeC, None, cs.len() as u64, || {
+                tree_pool.install(|| build_tree(&cs))
+            })?;
+            progress.report(Phase::TreeC, None, 100);
 
             // sanity checks
-            debug_assert_eq!(AsRef::<[u8]>::as_ref(&tree_c.read_at(0)), &cs[..NODE_SIZE]);
+            debug_assert_eq!(AsRef::<[u8]>::as_ref(&tree_c.read_at(0)), &cs[..node_size]);
             debug_assert_eq!(
                 AsRef::<[u8]>::as_ref(&tree_c.read_at(1)),
-                &cs[NODE_SIZE..NODE_SIZE * 2]
+                &cs[node_size..node_size * 2]
             );
 
             // drop memory for cs asap
             drop(cs);
 
-            let tree_r_last = tree_r_last_handle.join()?;
+            let tree_r_last = tree_r_last_handle.join()??;
 
             // comm_r = H(comm_c || comm_r_last)
-            let comm_r: H::Domain = Fr::from(hash2(tree_c.root(), tree_r_last.root())).into();
+            let comm_r: H::Domain = comm_r_hasher.hash(tree_c.root(), tree_r_last.root()).into();
 
             Ok((tree_d, tree_r_last, tree_c, comm_r, encodings))
         })??;
 
+        // The replica is complete, write it back if it is backed by a file. `tree_r_last` borrowed
+        // it for the whole scope above, so this can only happen once the scope has ended.
+        data.drop_data()?;
+
         Ok((
             Tau {
                 comm_d: tree_d.root(),
@@ -475,6 +741,47 @@ impl<'a, H: 'static + Hasher> StackedDrg<'a, H> {
     }
 }
 
+/// Number of nodes encoded at once when writing a replica file, 16MiB of 32 byte nodes.
+const ENCODE_CHUNK_NODES: usize = 1 << 19;
+
+/// Encodes `data` with the keys of the `last_layer`, and writes the result to the file at `path`.
+fn encode_into_file<H: Hasher>(
+    last_layer: &LayerStore<H::Domain>,
+    data: &[u8],
+    path: &Path,
+    node_size: usize,
+) -> Result<()> {
+    let direct = settings::SETTINGS.lock().unwrap().replica_direct_io;
+    let nodes = data.len() / node_size;
+
+    let mut writer = SequentialWriter::open(path, ENCODE_CHUNK_NODES * node_size, direct)?;
+    let mut encoded = vec![0u8; std::cmp::min(nodes, ENCODE_CHUNK_NODES) * node_size];
+
+    for start in (0..nodes).step_by(ENCODE_CHUNK_NODES) {
+        let end = std::cmp::min(start + ENCODE_CHUNK_NODES, nodes);
+        let chunk = &mut encoded[..(end - start) * node_size];
+
+        last_layer
+            .read_range(start..end)
+            .into_par_iter()
+            .zip(data[start * node_size..end * node_size].par_chunks(node_size))
+            .zip(chunk.par_chunks_mut(node_size))
+            .try_for_each(
+                |((key, data_node_bytes), encoded_node_bytes)| -> Result<()> {
+                    let data_node = H::Domain::try_from_bytes(data_node_bytes)?;
+                    let encoded_node = encode::<H::Domain>(key, data_node);
+                    encoded_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
+
+                    Ok(())
+                },
+            )?;
+
+        writer.write_all(chunk)?;
+    }
+
+    writer.finish()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -483,12 +790,17 @@ mod tests {
     use rand::{Rng, SeedableRng, XorShiftRng};
 
     use crate::drgporep;
-    use crate::drgraph::{new_seed, BASE_DEGREE};
+    use crate::drgraph::{new_seed_from, GraphConfig, BASE_DEGREE};
     use crate::fr32::fr_into_bytes;
-    use crate::hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher};
+    use crate::hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher, TestHasher};
     use crate::porep::PoRep;
     use crate::proof::ProofScheme;
-    use crate::stacked::{PrivateInputs, SetupParams, EXP_DEGREE};
+    use crate::stacked::{
+        BatchedProofs, CommRHasher, PrivateInputs, SetupParams, Taper, TemporaryAux,
+        TemporaryAuxAsStored, EXP_DEGREE,
+    };
+    use crate::test_helper::test_rng;
+    use crate::util::NODE_SIZE;
 
     const DEFAULT_STACKED_LAYERS: usize = 4;
 
@@ -501,6 +813,16 @@ mod tests {
         assert_eq!(expected as usize, calculated_count);
     }
 
+    #[test]
+    fn test_needs_exp_parents() {
+        // The later layers have half of the 10 challenges, the others only label layer 1.
+        let layer_challenges = LayerChallenges::new(4, 10);
+        assert!((0..5).all(|i| layer_challenges.needs_exp_parents(i)));
+        assert!((5..10).all(|i| !layer_challenges.needs_exp_parents(i)));
+
+        assert!(!LayerChallenges::new(1, 10).needs_exp_parents(0));
+    }
+
     #[test]
     fn extract_all_pedersen() {
         test_extract_all::<PedersenHasher>();
@@ -541,14 +863,18 @@ mod tests {
                 nodes,
                 degree: BASE_DEGREE,
                 expansion_degree: EXP_DEGREE,
-                seed: new_seed(),
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
             },
             layer_challenges: challenges.clone(),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
         };
 
         let pp = StackedDrg::<H>::setup(&sp).expect("setup failed");
 
-        StackedDrg::<H>::replicate(&pp, &replica_id, data_copy.as_mut_slice(), None)
+        StackedDrg::<H>::replicate(&pp, &replica_id, data_copy.as_mut_slice().into(), None)
             .expect("replication failed");
 
         assert_ne!(data, data_copy);
@@ -559,15 +885,263 @@ mod tests {
         assert_eq!(data, decoded_data);
     }
 
+    #[test]
+    fn extract_range() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let replica_id: <PedersenHasher as Hasher>::Domain = rng.gen();
+        let nodes = 8;
+
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let sp = SetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        };
+        let pp = StackedDrg::<PedersenHasher>::setup(&sp).expect("setup failed");
+
+        let mut replica = data.clone();
+        let (_, (_, t_aux)) = StackedDrg::<PedersenHasher>::replicate(
+            &pp,
+            &replica_id,
+            replica.as_mut_slice().into(),
+            None,
+        )
+        .expect("replication failed");
+        let cache_dir = tempfile::tempdir().unwrap();
+        let stored = t_aux.convert_to_stored(cache_dir.path()).unwrap();
+        let last_layer = &stored.encodings[DEFAULT_STACKED_LAYERS - 1].path;
+
+        for keys in &[None, Some(last_layer.as_path())] {
+            let range = 2..7;
+            let mut extracted = Vec::new();
+            StackedDrg::<PedersenHasher>::extract_range(
+                &pp,
+                &replica_id,
+                *keys,
+                &mut &replica[range.start * NODE_SIZE..],
+                range.clone(),
+                |chunk| {
+                    extracted.extend_from_slice(chunk);
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+            assert_eq!(
+                extracted,
+                &data[range.start * NODE_SIZE..range.end * NODE_SIZE]
+            );
+        }
+
+        let out_of_bounds = StackedDrg::<PedersenHasher>::extract_range(
+            &pp,
+            &replica_id,
+            None,
+            &mut &replica[..],
+            0..nodes + 1,
+            |_| Ok(()),
+        );
+        assert!(out_of_bounds.is_err());
+    }
+
+    #[test]
+    fn replicate_from_path() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let replica_id: <PedersenHasher as Hasher>::Domain = rng.gen();
+        let nodes = 8;
+
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let sp = SetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        };
+        let pp = StackedDrg::<PedersenHasher>::setup(&sp).expect("setup failed");
+
+        let mut data_copy = data.clone();
+        StackedDrg::<PedersenHasher>::replicate(
+            &pp,
+            &replica_id,
+            data_copy.as_mut_slice().into(),
+            None,
+        )
+        .expect("replication failed");
+
+        // File backed data is written sequentially instead, with the same result.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("replica");
+        std::fs::write(&path, &data).unwrap();
+
+        StackedDrg::<PedersenHasher>::replicate(
+            &pp,
+            &replica_id,
+            Data::from_path(path.clone()),
+            None,
+        )
+        .expect("replication failed");
+
+        assert_eq!(std::fs::read(&path).unwrap(), data_copy);
+    }
+
+    #[test]
+    fn replicate_reports_progress() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let replica_id: <PedersenHasher as Hasher>::Domain = rng.gen();
+        let nodes = 8;
+
+        let mut data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let sp = SetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        };
+        let pp = StackedDrg::<PedersenHasher>::setup(&sp).expect("setup failed");
+
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let observer = move |event: progress::PhaseEvent| recorded.lock().unwrap().push(event);
+
+        progress::observe(std::sync::Arc::new(observer), || {
+            StackedDrg::<PedersenHasher>::replicate(
+                &pp,
+                &replica_id,
+                data.as_mut_slice().into(),
+                None,
+            )
+        })
+        .expect("replication failed");
+
+        let events = events.lock().unwrap();
+        for layer in 1..=DEFAULT_STACKED_LAYERS {
+            assert!(events.iter().any(|event| event.phase == Phase::Labeling
+                && event.layer == Some(layer)
+                && event.percent == 100));
+        }
+        for phase in &[
+            Phase::TreeD,
+            Phase::Encoding,
+            Phase::TreeC,
+            Phase::TreeRLast,
+        ] {
+            let percents: Vec<_> = events
+                .iter()
+                .filter(|event| event.phase == *phase)
+                .map(|event| event.percent)
+                .collect();
+            assert_eq!(percents, vec![0, 100], "{:?}", phase);
+        }
+    }
+
+    #[test]
+    fn replicate_can_be_cancelled() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let replica_id: <PedersenHasher as Hasher>::Domain = rng.gen();
+        let nodes = 8;
+
+        let mut data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let sp = SetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        };
+        let pp = StackedDrg::<PedersenHasher>::setup(&sp).expect("setup failed");
+
+        // Cancel as soon as the second layer is started.
+        let token = cancel::CancellationToken::new();
+        let canceller = token.clone();
+        let observer = move |event: progress::PhaseEvent| {
+            if event.phase == Phase::Labeling && event.layer == Some(2) {
+                canceller.cancel();
+            }
+        };
+
+        let result = progress::observe(std::sync::Arc::new(observer), || {
+            cancel::cancellable(&token, || {
+                StackedDrg::<PedersenHasher>::replicate(
+                    &pp,
+                    &replica_id,
+                    data.as_mut_slice().into(),
+                    None,
+                )
+            })
+        });
+
+        match result {
+            Err(crate::error::Error::Cancelled) => {}
+            other => panic!("expected Cancelled, got {:?}", other.map(|_| ())),
+        }
+    }
+
     fn prove_verify_fixed(n: usize) {
         let challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
 
-        test_prove_verify::<PedersenHasher>(n, challenges.clone());
-        test_prove_verify::<Sha256Hasher>(n, challenges.clone());
-        test_prove_verify::<Blake2sHasher>(n, challenges.clone());
+        test_prove_verify::<PedersenHasher>(n, challenges.clone(), CommRHasher::Pedersen);
+        test_prove_verify::<Sha256Hasher>(n, challenges.clone(), CommRHasher::Pedersen);
+        test_prove_verify::<Blake2sHasher>(n, challenges.clone(), CommRHasher::Pedersen);
+        test_prove_verify::<PedersenHasher>(n, challenges.clone(), CommRHasher::Blake2s);
+        test_prove_verify::<TestHasher>(n, challenges.clone(), CommRHasher::Pedersen);
+
+        // 1, 1, 2 and 5 challenges on layers 1 to 4, so most challenges skip the first layers.
+        let tapered = LayerChallenges::new_tapered(
+            DEFAULT_STACKED_LAYERS,
+            5,
+            Taper {
+                percent: 50,
+                floor: 1,
+            },
+        );
+        test_prove_verify::<PedersenHasher>(n, tapered, CommRHasher::Pedersen);
     }
 
-    fn test_prove_verify<H: 'static + Hasher>(n: usize, challenges: LayerChallenges) {
+    fn test_prove_verify<H: 'static + Hasher>(
+        n: usize,
+        challenges: LayerChallenges,
+        comm_r_hasher: CommRHasher,
+    ) {
         // This will be called multiple times, only the first one succeeds, and that is ok.
         // femme::pretty::Logger::new()
         //     .start(log::LevelFilter::Trace)
@@ -591,14 +1165,18 @@ mod tests {
                 nodes: n,
                 degree,
                 expansion_degree,
-                seed: new_seed(),
+                seed: new_seed_from(rng),
+                graph_config: GraphConfig::default(),
             },
             layer_challenges: challenges.clone(),
+            porep_id: [1; 32],
+            comm_r_hasher,
+            node_size: NODE_SIZE,
         };
 
         let pp = StackedDrg::<H>::setup(&sp).expect("setup failed");
         let (tau, (p_aux, t_aux)) =
-            StackedDrg::<H>::replicate(&pp, &replica_id, data_copy.as_mut_slice(), None)
+            StackedDrg::<H>::replicate(&pp, &replica_id, data_copy.as_mut_slice().into(), None)
                 .expect("replication failed");
         assert_ne!(data, data_copy);
 
@@ -620,6 +1198,95 @@ mod tests {
                 .expect("failed to verify partition proofs");
 
         assert!(proofs_are_valid);
+
+        // The temporary aux can be stored, and proving resumed from it later.
+        let cache_dir = tempfile::tempdir().unwrap();
+        let stored = priv_inputs
+            .t_aux
+            .convert_to_stored(cache_dir.path())
+            .unwrap();
+        let stored: TemporaryAuxAsStored =
+            serde_json::from_str(&serde_json::to_string(&stored).unwrap()).unwrap();
+        let loaded_inputs = PrivateInputs {
+            p_aux: priv_inputs.p_aux.clone(),
+            t_aux: TemporaryAux::load(&stored).expect("failed to load temporary aux"),
+        };
+        let loaded_proofs =
+            StackedDrg::<H>::prove_all_partitions(&pp, &pub_inputs, &loaded_inputs, partitions)
+                .expect("failed to generate partition proofs from loaded aux");
+        assert!(
+            StackedDrg::<H>::verify_all_partitions(&pp, &pub_inputs, &loaded_proofs)
+                .expect("failed to verify partition proofs from loaded aux")
+        );
+
+        // Corrupted trees are rejected.
+        let mut tree_c = std::fs::read(&stored.tree_c.path).unwrap();
+        tree_c[0] ^= 1;
+        std::fs::write(&stored.tree_c.path, tree_c).unwrap();
+        assert!(TemporaryAux::<H>::load(&stored).is_err());
+
+        // Proofs and inputs still verify after a roundtrip through their canonical encoding.
+        let mut bytes = Vec::new();
+        pub_inputs.write_into(&mut bytes).unwrap();
+        let decoded_inputs = PublicInputs::<H::Domain>::read_from(&bytes[..]).unwrap();
+        assert_eq!(decoded_inputs.tau, pub_inputs.tau);
+
+        let decoded_proofs: Vec<Vec<Proof<H>>> = all_partition_proofs
+            .iter()
+            .map(|proofs| {
+                proofs
+                    .iter()
+                    .map(|proof| {
+                        let bytes = proof.serialize();
+                        let decoded = Proof::read_from(&bytes[..]).unwrap();
+                        assert_eq!(decoded.serialize(), bytes);
+                        decoded
+                    })
+                    .collect()
+            })
+            .collect();
+
+        assert!(
+            StackedDrg::<H>::verify_all_partitions(&pp, &decoded_inputs, &decoded_proofs)
+                .expect("failed to verify decoded partition proofs")
+        );
+
+        // Batching the column openings shrinks the encoding, and the rebuilt proofs verify.
+        let unbatched_proofs: Vec<Vec<Proof<H>>> = all_partition_proofs
+            .iter()
+            .map(|proofs| {
+                let batched = BatchedProofs::new(proofs).unwrap();
+                let bytes = batched.serialize();
+                let size: usize = proofs.iter().map(|proof| proof.serialize().len()).sum();
+                assert!(bytes.len() < size, "{} >= {}", bytes.len(), size);
+
+                BatchedProofs::<H>::read_from(&bytes[..])
+                    .unwrap()
+                    .proofs()
+                    .unwrap()
+            })
+            .collect();
+        for (proofs, unbatched) in all_partition_proofs.iter().zip(&unbatched_proofs) {
+            assert_eq!(
+                unbatched
+                    .iter()
+                    .map(|proof| proof.serialize())
+                    .collect::<Vec<_>>(),
+                proofs
+                    .iter()
+                    .map(|proof| proof.serialize())
+                    .collect::<Vec<_>>()
+            );
+        }
+        assert!(
+            StackedDrg::<H>::verify_all_partitions(&pp, &pub_inputs, &unbatched_proofs)
+                .expect("failed to verify unbatched partition proofs")
+        );
+
+        // Columns which don't match their inclusion proofs are not batched.
+        let mut tampered = all_partition_proofs[0].clone();
+        tampered[0].replica_column_proofs.c_x.column.rows[0] = rng.gen();
+        assert!(BatchedProofs::new(&tampered).is_err());
     }
 
     table_tests! {
@@ -628,6 +1295,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn setup_rejects_invalid_node_size() {
+        let sp = |node_size| SetupParams {
+            drg: drgporep::DrgParams {
+                nodes: 8,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: new_seed_from(&mut test_rng()),
+                graph_config: GraphConfig::default(),
+            },
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size,
+        };
+
+        assert!(StackedDrg::<PedersenHasher>::setup(&sp(NODE_SIZE)).is_ok());
+        assert!(StackedDrg::<PedersenHasher>::setup(&sp(16)).is_err());
+        assert!(StackedDrg::<Sha256Hasher>::setup(&sp(64)).is_err());
+    }
+
     #[test]
     // We are seeing a bug, in which setup never terminates for some sector sizes.
     // This test is to debug that and should remain as a regression teset.
@@ -641,9 +1329,13 @@ mod tests {
                 nodes,
                 degree,
                 expansion_degree,
-                seed: new_seed(),
+                seed: new_seed_from(&mut test_rng()),
+                graph_config: GraphConfig::default(),
             },
             layer_challenges: layer_challenges.clone(),
+            porep_id: [1; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
         };
 
         // When this fails, the call to setup should panic, but seems to actually hang (i.e. neither return nor panic) for some reason.
@@ -6,10 +6,12 @@ use crate::hasher::Hasher;
 use crate::proof::ProofScheme;
 use crate::stacked::{
     challenges::ChallengeRequirements,
-    graph::StackedBucketGraph,
+    graph::{derive_graph_seed, StackedBucketGraph},
+    instrument::NoHooks,
     params::{PrivateInputs, Proof, PublicInputs, PublicParams, SetupParams},
     proof::StackedDrg,
 };
+use crate::util::check_node_size;
 
 impl<'a, 'c, H: 'static + Hasher> ProofScheme<'a> for StackedDrg<'c, H> {
     type PublicParams = PublicParams<H>;
@@ -20,14 +22,24 @@ impl<'a, 'c, H: 'static + Hasher> ProofScheme<'a> for StackedDrg<'c, H> {
     type Requirements = ChallengeRequirements;
 
     fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
-        let graph = StackedBucketGraph::<H>::new_stacked(
+        check_node_size::<H>(sp.node_size)?;
+
+        let graph = StackedBucketGraph::<H>::new_for_porep(
             sp.drg.nodes,
             sp.drg.degree,
             sp.drg.expansion_degree,
-            sp.drg.seed,
-        );
-
-        Ok(PublicParams::new(graph, sp.layer_challenges.clone()))
+            derive_graph_seed(sp.drg.seed, &sp.porep_id),
+            &sp.drg.graph_config,
+            &sp.porep_id,
+        )?;
+
+        Ok(PublicParams::new(
+            graph,
+            sp.layer_challenges.clone(),
+            sp.porep_id,
+            sp.comm_r_hasher,
+            sp.node_size,
+        ))
     }
 
     fn prove<'b>(
@@ -66,9 +78,11 @@ impl<'a, 'c, H: 'static + Hasher> ProofScheme<'a> for StackedDrg<'c, H> {
             &priv_inputs.p_aux,
             &priv_inputs.t_aux,
             &pub_params.layer_challenges,
+            &pub_params.porep_id,
             pub_params.layer_challenges.layers(),
             pub_params.layer_challenges.layers(),
             partition_count,
+            &NoHooks,
         )
     }
 
@@ -89,8 +103,17 @@ impl<'a, 'c, H: 'static + Hasher> ProofScheme<'a> for StackedDrg<'c, H> {
                 partition_proofs.len()
             );
 
-            let challenges =
-                pub_inputs.all_challenges(&pub_params.layer_challenges, graph.size(), Some(k));
+            let challenges = pub_inputs.all_challenges(
+                &pub_params.layer_challenges,
+                &pub_params.porep_id,
+                graph.size(),
+                Some(k),
+            );
+
+            // Every challenge must be answered, otherwise a truncated proof would verify.
+            if proofs.len() != challenges.len() {
+                return Ok(false);
+            }
 
             let valid = proofs.par_iter().enumerate().all(|(i, proof)| {
                 trace!("verify challenge {}/{}", i + 1, challenges.len());
@@ -127,4 +150,11 @@ impl<'a, 'c, H: 'static + Hasher> ProofScheme<'a> for StackedDrg<'c, H> {
 
         partition_challenges * partitions >= requirements.minimum_challenges
     }
+
+    fn minimum_partitions(
+        public_params: &PublicParams<H>,
+        requirements: &ChallengeRequirements,
+    ) -> Option<usize> {
+        requirements.minimum_partitions(public_params.layer_challenges.challenges_count_all())
+    }
 }
@@ -0,0 +1,225 @@
+//! Tampering with valid proofs, to check that no value a proof carries can be changed without
+//! the proof failing to verify.
+
+use std::iter;
+
+use paired::bls12_381::Bls12;
+use rand::Rng;
+
+use crate::drgporep;
+use crate::drgraph::{new_seed_from, GraphConfig, BASE_DEGREE};
+use crate::fr32::fr_into_bytes;
+use crate::hasher::{Domain, Hasher};
+use crate::merkle::MerkleProof;
+use crate::porep::PoRep;
+use crate::proof::ProofScheme;
+use crate::stacked::{
+    ColumnProof, CommRHasher, LayerChallenges, PrivateInputs, Proof, PublicInputs, PublicParams,
+    SetupParams, StackedDrg, EXP_DEGREE,
+};
+use crate::test_helper::test_rng;
+use crate::util::NODE_SIZE;
+
+/// A flip of the lowest bit of one value of a `Proof`.
+///
+/// Columns are numbered as they are opened: `c_x` first, then the base parents and the expander
+/// parents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tamper {
+    CommDLeaf,
+    CommDPath(usize),
+    CommRLastLeaf,
+    CommRLastPath(usize),
+    ColumnLabel { column: usize, layer: usize },
+    ColumnPath { column: usize, height: usize },
+    EncodingParent { proof: usize, parent: usize },
+}
+
+impl Tamper {
+    /// Every value of `proof` there is to tamper with, grouped by variant.
+    pub fn all<H: Hasher>(proof: &Proof<H>) -> Vec<Tamper> {
+        let mut tampers = vec![Tamper::CommDLeaf];
+        tampers.extend((0..proof.comm_d_proofs.path().len()).map(Tamper::CommDPath));
+        tampers.push(Tamper::CommRLastLeaf);
+        tampers.extend((0..proof.comm_r_last_proof.path().len()).map(Tamper::CommRLastPath));
+
+        let columns: Vec<_> = columns(proof).collect();
+        for (column, column_proof) in columns.iter().enumerate() {
+            tampers.extend(
+                (1..=column_proof.column.rows.len())
+                    .map(|layer| Tamper::ColumnLabel { column, layer }),
+            );
+        }
+        for (column, column_proof) in columns.iter().enumerate() {
+            tampers.extend(
+                (0..column_proof.inclusion_proof.path().len())
+                    .map(|height| Tamper::ColumnPath { column, height }),
+            );
+        }
+
+        for (index, encoding_proof) in proof.encoding_proofs.iter().enumerate() {
+            tampers.extend((0..encoding_proof.parents.len()).map(|parent| {
+                Tamper::EncodingParent {
+                    proof: index,
+                    parent,
+                }
+            }));
+        }
+
+        tampers
+    }
+
+    /// A copy of `proof` with the value this names flipped.
+    pub fn apply<H: Hasher>(self, proof: &Proof<H>) -> Proof<H> {
+        let mut proof = proof.clone();
+
+        match self {
+            Tamper::CommDLeaf => proof.comm_d_proofs = flip_leaf(&proof.comm_d_proofs),
+            Tamper::CommDPath(height) => {
+                proof.comm_d_proofs = flip_sibling(&proof.comm_d_proofs, height)
+            }
+            Tamper::CommRLastLeaf => proof.comm_r_last_proof = flip_leaf(&proof.comm_r_last_proof),
+            Tamper::CommRLastPath(height) => {
+                proof.comm_r_last_proof = flip_sibling(&proof.comm_r_last_proof, height)
+            }
+            Tamper::ColumnLabel { column, layer } => {
+                let column = columns_mut(&mut proof).nth(column).expect("no such column");
+                let label = &mut column.column.rows[layer - 1];
+                *label = flip(label);
+            }
+            Tamper::ColumnPath { column, height } => {
+                let column = columns_mut(&mut proof).nth(column).expect("no such column");
+                column.inclusion_proof = flip_sibling(&column.inclusion_proof, height);
+            }
+            Tamper::EncodingParent {
+                proof: index,
+                parent,
+            } => {
+                let parent = &mut proof.encoding_proofs[index].parents[parent];
+                *parent = flip(parent);
+            }
+        }
+
+        proof
+    }
+}
+
+fn columns<H: Hasher>(proof: &Proof<H>) -> impl Iterator<Item = &ColumnProof<H>> {
+    let columns = &proof.replica_column_proofs;
+    iter::once(&columns.c_x)
+        .chain(columns.drg_parents.iter())
+        .chain(columns.exp_parents.iter())
+}
+
+fn columns_mut<H: Hasher>(proof: &mut Proof<H>) -> impl Iterator<Item = &mut ColumnProof<H>> {
+    let columns = &mut proof.replica_column_proofs;
+    iter::once(&mut columns.c_x)
+        .chain(columns.drg_parents.iter_mut())
+        .chain(columns.exp_parents.iter_mut())
+}
+
+fn flip<T: Domain>(value: &T) -> T {
+    let mut bytes = value.into_bytes();
+    bytes[0] ^= 1;
+    T::try_from_bytes(&bytes).expect("invalid flipped value")
+}
+
+fn flip_leaf<H: Hasher>(proof: &MerkleProof<H>) -> MerkleProof<H> {
+    MerkleProof::from_parts(*proof.root(), flip(proof.leaf()), proof.path().clone())
+}
+
+fn flip_sibling<H: Hasher>(proof: &MerkleProof<H>, height: usize) -> MerkleProof<H> {
+    let mut path = proof.path().clone();
+    path[height].0 = flip(&path[height].0);
+    MerkleProof::from_parts(*proof.root(), *proof.leaf(), path)
+}
+
+/// Replicates `nodes` nodes of random data and proves them in a single partition.
+pub fn valid_proofs<H: 'static + Hasher>(
+    nodes: usize,
+    layer_challenges: LayerChallenges,
+) -> (PublicParams<H>, PublicInputs<H::Domain>, Vec<Proof<H>>) {
+    let rng = &mut test_rng();
+
+    let replica_id: H::Domain = rng.gen();
+    let mut data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+        .collect();
+
+    let sp = SetupParams {
+        drg: drgporep::DrgParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            seed: new_seed_from(rng),
+            graph_config: GraphConfig::default(),
+        },
+        layer_challenges,
+        porep_id: [1; 32],
+        comm_r_hasher: CommRHasher::Pedersen,
+        node_size: NODE_SIZE,
+    };
+
+    let pp = StackedDrg::<H>::setup(&sp).expect("setup failed");
+    let (tau, (p_aux, t_aux)) =
+        StackedDrg::<H>::replicate(&pp, &replica_id, data.as_mut_slice().into(), None)
+            .expect("replication failed");
+
+    let pub_inputs = PublicInputs {
+        replica_id,
+        seed: None,
+        tau: Some(tau),
+        k: None,
+    };
+    let priv_inputs = PrivateInputs { p_aux, t_aux };
+
+    let mut proofs = StackedDrg::<H>::prove_all_partitions(&pp, &pub_inputs, &priv_inputs, 1)
+        .expect("failed to generate partition proofs");
+    assert!(
+        StackedDrg::<H>::verify_all_partitions(&pp, &pub_inputs, &proofs)
+            .expect("failed to verify partition proofs"),
+        "untampered proofs are invalid"
+    );
+
+    (pp, pub_inputs, proofs.remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hasher::{Blake2sHasher, PedersenHasher};
+
+    fn tampered_proofs_are_invalid<H: 'static + Hasher>() {
+        // The second challenge only labels the first layer, so both shapes of proof are covered.
+        let (pp, pub_inputs, proofs) = valid_proofs::<H>(16, LayerChallenges::new(2, 2));
+        assert!(proofs[1].replica_column_proofs.exp_parents.is_empty());
+
+        for (i, proof) in proofs.iter().enumerate() {
+            let tampers = Tamper::all(proof);
+            assert!(tampers.contains(&Tamper::EncodingParent {
+                proof: 0,
+                parent: 0
+            }));
+
+            for tamper in tampers {
+                let mut tampered = proofs.clone();
+                tampered[i] = tamper.apply(proof);
+
+                let valid = StackedDrg::<H>::verify_all_partitions(&pp, &pub_inputs, &[tampered])
+                    .expect("failed to verify partition proofs");
+                assert!(!valid, "proof {} verifies with {:?}", i, tamper);
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_proofs_are_invalid_pedersen() {
+        tampered_proofs_are_invalid::<PedersenHasher>();
+    }
+
+    #[test]
+    fn tampered_proofs_are_invalid_blake2s() {
+        tampered_proofs_are_invalid::<Blake2sHasher>();
+    }
+}
@@ -0,0 +1,42 @@
+//! Verification of the vanilla proofs of a seal on their own. It needs neither circuits nor
+//! Groth parameters, so services which only check vanilla proofs can do without the SNARK side of
+//! this crate and without `filecoin-proofs`.
+
+use crate::error::Result;
+use crate::hasher::Hasher;
+use crate::proof::ProofScheme;
+use crate::stacked::{
+    challenges::ChallengeRequirements,
+    params::{Proof, PublicInputs, PublicParams, Tau},
+    proof::StackedDrg,
+};
+
+/// Verifies the vanilla proofs of all `partitions` of a seal, for the sector replicated under
+/// `replica_id` with the commitments `tau`. Proofs of another number of partitions fail, as do
+/// public params which challenge fewer than `minimum_challenges` nodes.
+pub fn verify_seal_proofs<H: 'static + Hasher>(
+    pub_params: &PublicParams<H>,
+    partitions: usize,
+    minimum_challenges: usize,
+    replica_id: H::Domain,
+    tau: Tau<H::Domain>,
+    partition_proofs: &[Vec<Proof<H>>],
+) -> Result<bool> {
+    if partition_proofs.len() != partitions {
+        return Ok(false);
+    }
+
+    let requirements = ChallengeRequirements { minimum_challenges };
+    if !StackedDrg::<H>::satisfies_requirements(pub_params, &requirements, partitions) {
+        return Ok(false);
+    }
+
+    let public_inputs = PublicInputs {
+        replica_id,
+        tau: Some(tau),
+        seed: None,
+        k: None,
+    };
+
+    StackedDrg::<H>::verify_all_partitions(pub_params, &public_inputs, partition_proofs)
+}
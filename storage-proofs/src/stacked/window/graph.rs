@@ -0,0 +1,226 @@
+use crate::drgraph::{Graph, GraphConfig};
+use crate::hasher::Hasher;
+use crate::parameter_cache::ParameterSetMetadata;
+use crate::stacked::graph::StackedBucketGraph;
+
+/// The graph of a windowed stacked PoRep.
+///
+/// The sector is split into `windows` consecutive windows of `window_size` nodes. Every window is
+/// labeled with the same stacked graph, shifted to the nodes of the window, so windows only depend
+/// on themselves and can be labeled in parallel. The wrapper layer then ties the windows together:
+/// every node of it has the expansion parents of a stacked graph over the whole sector, in the
+/// last window layer.
+#[derive(Debug, Clone)]
+pub struct WindowGraph<H: 'static + Hasher> {
+    window_graph: StackedBucketGraph<H>,
+    wrapper_graph: StackedBucketGraph<H>,
+    windows: usize,
+}
+
+impl<H: 'static + Hasher> WindowGraph<H> {
+    pub fn new(
+        nodes: usize,
+        window_size: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        seed: [u32; 7],
+        config: &GraphConfig,
+    ) -> Self {
+        assert!(window_size > 2, "windows must have more than two nodes");
+        assert_eq!(
+            nodes % window_size,
+            0,
+            "the sector must be split into whole windows"
+        );
+
+        let window_graph = StackedBucketGraph::<H>::new_with_config(
+            window_size,
+            base_degree,
+            expansion_degree,
+            seed,
+            config,
+        );
+        // Only the expansion parents of the wrapper graph are used.
+        let wrapper_graph = StackedBucketGraph::<H>::new_with_config(
+            nodes,
+            base_degree,
+            expansion_degree,
+            seed,
+            config,
+        );
+
+        WindowGraph {
+            window_graph,
+            wrapper_graph,
+            windows: nodes / window_size,
+        }
+    }
+
+    /// The number of nodes in the sector.
+    pub fn size(&self) -> usize {
+        self.wrapper_graph.size()
+    }
+
+    /// The number of nodes in a window.
+    pub fn window_size(&self) -> usize {
+        self.window_graph.size()
+    }
+
+    pub fn windows(&self) -> usize {
+        self.windows
+    }
+
+    /// The graph every window is labeled with, in window local node indices.
+    pub fn window_graph(&self) -> &StackedBucketGraph<H> {
+        &self.window_graph
+    }
+
+    /// The graph over the whole sector, whose expansion parents the wrapper layer uses.
+    pub fn wrapper_graph(&self) -> &StackedBucketGraph<H> {
+        &self.wrapper_graph
+    }
+
+    pub fn base_degree(&self) -> usize {
+        self.window_graph.base_graph().degree()
+    }
+
+    pub fn expansion_degree(&self) -> usize {
+        self.window_graph.expansion_degree()
+    }
+
+    pub fn merkle_tree_depth(&self) -> u64 {
+        self.wrapper_graph.merkle_tree_depth()
+    }
+
+    /// The first node of the window `node` is in.
+    pub fn window_start(&self, node: usize) -> usize {
+        node - node % self.window_size()
+    }
+
+    /// Writes the base parents of `node` within its window into `parents`, as sector node
+    /// indices. The first node of a window has no parents.
+    pub fn base_parents(&self, node: usize, parents: &mut [usize]) {
+        let start = self.window_start(node);
+        assert!(node > start, "the first node of a window has no parents");
+
+        self.window_graph.base_parents(node - start, parents);
+        for parent in parents.iter_mut().take(self.base_degree()) {
+            *parent += start;
+        }
+    }
+
+    /// Writes the expansion parents of `node` within its window into `parents`, as sector node
+    /// indices. They are labels of the previous layer.
+    pub fn expanded_parents(&self, node: usize, parents: &mut [usize]) {
+        let start = self.window_start(node);
+        let mut local = vec![0; self.expansion_degree()];
+        self.window_graph.expanded_parents(node - start, &mut local);
+
+        for (parent, local) in parents.iter_mut().zip(local) {
+            *parent = start + local as usize;
+        }
+    }
+
+    /// Writes the parents of `node` in the wrapper layer into `parents`. They are labels of the
+    /// last window layer, anywhere in the sector.
+    pub fn wrapper_parents(&self, node: usize, parents: &mut [usize]) {
+        let mut wrapper = vec![0; self.expansion_degree()];
+        self.wrapper_graph.expanded_parents(node, &mut wrapper);
+
+        for (parent, wrapper) in parents.iter_mut().zip(wrapper) {
+            *parent = wrapper as usize;
+        }
+    }
+
+    /// The number of nodes challenges are drawn from, plus one, to be passed to
+    /// `derive_challenges` as the number of leaves. The first node of every window is left out.
+    pub fn challenge_leaves(&self) -> usize {
+        self.windows * (self.window_size() - 1) + 1
+    }
+
+    /// Maps a derived challenge in `1..challenge_leaves()` to the node it challenges, skipping the
+    /// first node of every window, which has no parents to prove.
+    pub fn challenged_node(&self, challenge: usize) -> usize {
+        assert!(challenge > 0 && challenge < self.challenge_leaves());
+
+        let per_window = self.window_size() - 1;
+        let index = challenge - 1;
+
+        (index / per_window) * self.window_size() + 1 + index % per_window
+    }
+}
+
+impl<H: 'static + Hasher> ParameterSetMetadata for WindowGraph<H> {
+    fn identifier(&self) -> String {
+        format!(
+            "window_graph::WindowGraph{{windows: {}, window_graph: {}, wrapper_graph: {} }}",
+            self.windows,
+            self.window_graph.identifier(),
+            self.wrapper_graph.identifier(),
+        )
+    }
+
+    fn sector_size(&self) -> u64 {
+        self.wrapper_graph.sector_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    use crate::drgraph::{new_seed_from, BASE_DEGREE};
+    use crate::hasher::PedersenHasher;
+    use crate::stacked::EXP_DEGREE;
+    use crate::test_helper::test_rng;
+
+    fn graph(nodes: usize, window_size: usize) -> WindowGraph<PedersenHasher> {
+        WindowGraph::new(
+            nodes,
+            window_size,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            new_seed_from(&mut test_rng()),
+            &GraphConfig::default(),
+        )
+    }
+
+    #[test]
+    fn test_window_parents_stay_in_window() {
+        let g = graph(64, 16);
+        assert_eq!(g.windows(), 4);
+
+        for node in 0..g.size() {
+            let start = g.window_start(node);
+            let end = start + g.window_size();
+
+            let mut exp_parents = vec![0; EXP_DEGREE];
+            g.expanded_parents(node, &mut exp_parents);
+            assert!(exp_parents.iter().all(|p| *p >= start && *p < end));
+
+            if node == start {
+                continue;
+            }
+
+            let mut base_parents = vec![0; BASE_DEGREE];
+            g.base_parents(node, &mut base_parents);
+            assert!(base_parents.iter().all(|p| *p >= start && *p < node));
+        }
+    }
+
+    #[test]
+    fn test_challenged_nodes() {
+        let g = graph(64, 16);
+
+        let nodes: HashSet<usize> = (1..g.challenge_leaves())
+            .map(|challenge| g.challenged_node(challenge))
+            .collect();
+
+        // Every node but the first of each window is challenged by exactly one challenge.
+        assert_eq!(nodes.len(), g.challenge_leaves() - 1);
+        assert!(nodes.iter().all(|node| node % g.window_size() != 0));
+        assert!(nodes.iter().all(|node| *node < g.size()));
+    }
+}
@@ -0,0 +1,11 @@
+//! Windowed stacked DRG PoRep, see `WindowStackedDrg`.
+
+mod graph;
+mod params;
+mod porep;
+mod proof;
+mod proof_scheme;
+
+pub use self::graph::WindowGraph;
+pub use self::params::{WindowProof, WindowPublicParams, WindowSetupParams};
+pub use self::proof::WindowStackedDrg;
@@ -0,0 +1,317 @@
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use crate::drgporep;
+use crate::error::Result;
+use crate::hasher::Hasher;
+use crate::merkle::MerkleProof;
+use crate::parameter_cache::ParameterSetMetadata;
+use crate::stacked::{
+    challenges::LayerChallenges,
+    column_proof::ColumnProof,
+    encode::encode,
+    encoding_proof::EncodingProof,
+    hash::CommRHasher,
+    params::{PublicInputs, ReplicaColumnProof},
+    window::graph::WindowGraph,
+};
+use crate::versioned;
+
+#[derive(Debug)]
+pub struct WindowSetupParams {
+    /// The graph of the whole sector. Windows use the same degrees, seed and construction.
+    pub drg: drgporep::DrgParams,
+    /// Nodes per window, which must divide the nodes of the sector.
+    pub window_size: usize,
+    /// The challenges of all layers, the last of which is the wrapper layer.
+    pub layer_challenges: LayerChallenges,
+    pub porep_id: [u8; 32],
+    pub comm_r_hasher: CommRHasher,
+    pub node_size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowPublicParams<H>
+where
+    H: 'static + Hasher,
+{
+    pub graph: WindowGraph<H>,
+    pub layer_challenges: LayerChallenges,
+    pub porep_id: [u8; 32],
+    pub comm_r_hasher: CommRHasher,
+    pub node_size: usize,
+    _h: PhantomData<H>,
+}
+
+impl<H> WindowPublicParams<H>
+where
+    H: Hasher,
+{
+    pub fn new(
+        graph: WindowGraph<H>,
+        layer_challenges: LayerChallenges,
+        porep_id: [u8; 32],
+        comm_r_hasher: CommRHasher,
+        node_size: usize,
+    ) -> Self {
+        assert!(
+            layer_challenges.layers() > 1,
+            "the wrapper layer needs a window layer below it"
+        );
+
+        WindowPublicParams {
+            graph,
+            layer_challenges,
+            porep_id,
+            comm_r_hasher,
+            node_size,
+            _h: PhantomData,
+        }
+    }
+
+    /// The window layers are `1..wrapper_layer()`.
+    pub fn wrapper_layer(&self) -> usize {
+        self.layer_challenges.layers()
+    }
+
+    /// The nodes challenged in partition `k`.
+    pub fn all_challenges(
+        &self,
+        pub_inputs: &PublicInputs<H::Domain>,
+        partition_k: Option<usize>,
+    ) -> Vec<usize> {
+        pub_inputs
+            .all_challenges(
+                &self.layer_challenges,
+                &self.porep_id,
+                self.graph.challenge_leaves(),
+                partition_k,
+            )
+            .into_iter()
+            .map(|challenge| self.graph.challenged_node(challenge))
+            .collect()
+    }
+}
+
+impl<H> ParameterSetMetadata for WindowPublicParams<H>
+where
+    H: Hasher,
+{
+    fn identifier(&self) -> String {
+        format!(
+            "window_drgporep::PublicParams{{ graph: {}, challenges: {:?}, porep_id: {:?}, comm_r_hasher: {:?}, node_size: {} }}",
+            self.graph.identifier(),
+            self.layer_challenges,
+            self.porep_id,
+            self.comm_r_hasher,
+            self.node_size,
+        )
+    }
+
+    fn sector_size(&self) -> u64 {
+        self.graph.sector_size()
+    }
+}
+
+impl<'a, H> From<&'a WindowPublicParams<H>> for WindowPublicParams<H>
+where
+    H: Hasher,
+{
+    fn from(other: &WindowPublicParams<H>) -> WindowPublicParams<H> {
+        WindowPublicParams::new(
+            other.graph.clone(),
+            other.layer_challenges.clone(),
+            other.porep_id,
+            other.comm_r_hasher,
+            other.node_size,
+        )
+    }
+}
+
+/// The proof of one challenged node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowProof<H: Hasher> {
+    #[serde(bound(
+        serialize = "MerkleProof<H>: Serialize",
+        deserialize = "MerkleProof<H>: Deserialize<'de>"
+    ))]
+    pub comm_d_proofs: MerkleProof<H>,
+    #[serde(bound(
+        serialize = "MerkleProof<H>: Serialize",
+        deserialize = "MerkleProof<H>: Deserialize<'de>"
+    ))]
+    pub comm_r_last_proof: MerkleProof<H>,
+    /// The columns of the node and of its parents within its window.
+    #[serde(bound(
+        serialize = "ReplicaColumnProof<H>: Serialize",
+        deserialize = "ReplicaColumnProof<H>: Deserialize<'de>"
+    ))]
+    pub replica_column_proofs: ReplicaColumnProof<H>,
+    /// The columns of the parents of the node in the wrapper layer.
+    #[serde(bound(
+        serialize = "ColumnProof<H>: Serialize",
+        deserialize = "ColumnProof<H>: Deserialize<'de>"
+    ))]
+    pub wrapper_parents: Vec<ColumnProof<H>>,
+    /// Indexed by the included window layers, in order.
+    #[serde(bound(
+        serialize = "EncodingProof<H>: Serialize",
+        deserialize = "EncodingProof<H>: Deserialize<'de>"
+    ))]
+    pub encoding_proofs: Vec<EncodingProof<H>>,
+    /// Derives the key the data is encoded with from the wrapper parents.
+    #[serde(bound(
+        serialize = "EncodingProof<H>: Serialize",
+        deserialize = "EncodingProof<H>: Deserialize<'de>"
+    ))]
+    pub wrapper_proof: EncodingProof<H>,
+}
+
+impl<H: Hasher> WindowProof<H> {
+    pub fn comm_r_last(&self) -> &H::Domain {
+        self.comm_r_last_proof.root()
+    }
+
+    pub fn comm_c(&self) -> &H::Domain {
+        self.replica_column_proofs.c_x.root()
+    }
+
+    /// Verify the full proof of `challenge`, the `challenge_index`th node challenged.
+    #[allow(clippy::cognitive_complexity)]
+    pub fn verify(
+        &self,
+        pub_params: &WindowPublicParams<H>,
+        pub_inputs: &PublicInputs<H::Domain>,
+        challenge: usize,
+        challenge_index: usize,
+    ) -> bool {
+        let graph = &pub_params.graph;
+        let wrapper_layer = pub_params.wrapper_layer();
+
+        check!(challenge < graph.size());
+        check!(challenge != graph.window_start(challenge));
+
+        let tau = match pub_inputs.tau {
+            Some(ref tau) => tau,
+            None => return false,
+        };
+
+        let comm_r: H::Domain = pub_params
+            .comm_r_hasher
+            .hash(self.comm_c(), self.comm_r_last())
+            .into();
+        check_eq!(&tau.comm_r, &comm_r);
+
+        trace!("verify initial data layer");
+        check!(self.comm_d_proofs.validate(challenge));
+        check_eq!(self.comm_d_proofs.root(), &tau.comm_d);
+
+        trace!("verify final replica layer openings");
+        check!(self.comm_r_last_proof.validate(challenge));
+
+        trace!("verify replica column openings");
+        let rpc = &self.replica_column_proofs;
+        check!(rpc.verify());
+        check!(rpc.c_x.inclusion_proof.proves_challenge(challenge));
+
+        let mut drg_parents = vec![0; graph.base_degree()];
+        graph.base_parents(challenge, &mut drg_parents);
+        check!(opens_nodes(&rpc.drg_parents, &drg_parents));
+
+        let mut exp_parents = vec![0; graph.expansion_degree()];
+        graph.expanded_parents(challenge, &mut exp_parents);
+        check!(opens_nodes(&rpc.exp_parents, &exp_parents));
+
+        let mut wrapper_parents = vec![0; graph.expansion_degree()];
+        graph.wrapper_parents(challenge, &mut wrapper_parents);
+        check!(opens_nodes(&self.wrapper_parents, &wrapper_parents));
+        for proof in &self.wrapper_parents {
+            check!(proof.verify());
+            check_eq!(proof.root(), self.comm_c());
+        }
+
+        trace!("verify window encodings");
+        let mut encoding_proofs = self.encoding_proofs.iter();
+        for layer in 1..wrapper_layer {
+            if !pub_params
+                .layer_challenges
+                .include_challenge_at_layer(layer, challenge_index)
+            {
+                continue;
+            }
+            let encoding_proof = match encoding_proofs.next() {
+                Some(encoding_proof) => encoding_proof,
+                None => return false,
+            };
+
+            // Base parents are in the same layer, expansion parents in the previous one.
+            let mut expected_parents: Vec<&H::Domain> = rpc
+                .drg_parents
+                .iter()
+                .map(|parent| parent.get_node_at_layer(layer))
+                .collect();
+            if layer > 1 {
+                expected_parents.extend(
+                    rpc.exp_parents
+                        .iter()
+                        .map(|parent| parent.get_node_at_layer(layer - 1)),
+                );
+            }
+
+            check_eq!(encoding_proof.node, challenge as u64);
+            check!(encoding_proof
+                .parents
+                .iter()
+                .eq(expected_parents.iter().cloned()));
+            check!(encoding_proof.verify(
+                &pub_params.porep_id,
+                &pub_inputs.replica_id,
+                rpc.c_x.get_node_at_layer(layer),
+                None
+            ));
+        }
+        check!(encoding_proofs.next().is_none());
+
+        trace!("verify wrapper encoding");
+        let wrapper_proof = &self.wrapper_proof;
+        check_eq!(wrapper_proof.node, challenge as u64);
+        check!(wrapper_proof.parents.iter().eq(self
+            .wrapper_parents
+            .iter()
+            .map(|parent| parent.get_node_at_layer(wrapper_layer - 1))));
+
+        let key = wrapper_proof.create_key(&pub_params.porep_id, &pub_inputs.replica_id);
+        check_eq!(rpc.c_x.get_node_at_layer(wrapper_layer), &key);
+        check_eq!(
+            self.comm_r_last_proof.leaf(),
+            &encode(key, *self.comm_d_proofs.leaf())
+        );
+
+        true
+    }
+
+    /// Returns the canonical, versioned encoding, as written by `write_into`.
+    pub fn serialize(&self) -> Vec<u8> {
+        versioned::to_bytes(self).expect("failed to serialize proof")
+    }
+
+    /// Writes the canonical, versioned encoding, see `versioned`.
+    pub fn write_into<W: Write>(&self, writer: W) -> Result<()> {
+        versioned::write_into(self, writer)
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        versioned::read_from(reader)
+    }
+}
+
+/// Whether `columns` are the openings of exactly the columns at `nodes`, in order.
+fn opens_nodes<H: Hasher>(columns: &[ColumnProof<H>], nodes: &[usize]) -> bool {
+    columns.len() == nodes.len()
+        && columns
+            .iter()
+            .zip(nodes)
+            .all(|(column, node)| column.inclusion_proof.proves_challenge(*node))
+}
@@ -0,0 +1,63 @@
+use crate::data::Data;
+use crate::error::{Error, Result};
+use crate::hasher::Hasher;
+use crate::porep::PoRep;
+use crate::stacked::{
+    params::{PersistentAux, Tau, TemporaryAux, Tree},
+    window::{params::WindowPublicParams, proof::WindowStackedDrg},
+};
+
+impl<'a, 'c, H: 'static + Hasher> PoRep<'a, H> for WindowStackedDrg<'a, H> {
+    type Tau = Tau<<H as Hasher>::Domain>;
+    type ProverAux = (PersistentAux<H::Domain>, TemporaryAux<H>);
+
+    fn replicate<'b>(
+        pp: &'a WindowPublicParams<H>,
+        replica_id: &H::Domain,
+        data: Data<'b>,
+        data_tree: Option<Tree<H>>,
+    ) -> Result<(Self::Tau, Self::ProverAux)> {
+        let (tau, p_aux, t_aux) = Self::transform_and_replicate_layers(
+            &pp.graph,
+            &pp.layer_challenges,
+            &pp.porep_id,
+            pp.node_size,
+            pp.comm_r_hasher,
+            replica_id,
+            data,
+            data_tree,
+        )?;
+
+        Ok((tau, (p_aux, t_aux)))
+    }
+
+    fn extract_all<'b>(
+        pp: &'b WindowPublicParams<H>,
+        replica_id: &'b <H as Hasher>::Domain,
+        data: &'b [u8],
+    ) -> Result<Vec<u8>> {
+        let mut data = data.to_vec();
+
+        Self::extract_and_invert_transform_layers(
+            &pp.graph,
+            &pp.layer_challenges,
+            &pp.porep_id,
+            pp.node_size,
+            replica_id,
+            &mut data,
+        )?;
+
+        Ok(data)
+    }
+
+    // A single node can't be extracted without the labels of the layers below it, which are
+    // not kept, so only whole replicas are.
+    fn extract(
+        _pp: &WindowPublicParams<H>,
+        _replica_id: &<H as Hasher>::Domain,
+        _data: &[u8],
+        _node: usize,
+    ) -> Result<Vec<u8>> {
+        Err(Error::Unclassified("extract is not supported for windowed SDR".into()))
+    }
+}
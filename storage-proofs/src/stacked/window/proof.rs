@@ -0,0 +1,494 @@
+use std::marker::PhantomData;
+use std::time::Instant;
+
+use rayon::prelude::*;
+
+use crate::cancel;
+use crate::data::Data;
+use crate::error::Result;
+use crate::hasher::Hasher;
+use crate::label_buffer::LabelBuffer;
+use crate::label_file::Access;
+use crate::measurements::{self, Operation};
+use crate::merkle::MerkleProof;
+use crate::numa::{self, Placement};
+use crate::progress::{Phase, Tracker};
+use crate::stacked::{
+    challenges::LayerChallenges,
+    encoding_proof::EncodingProof,
+    hash::CommRHasher,
+    label::{self, label_layer, Labeler},
+    params::{Encodings, LayerStore, ReplicaColumnProof, TemporaryAux},
+    params::{TransformedLayers, Tree},
+    prefetch::Prefetch,
+    proof::StackedDrg,
+    window::{graph::WindowGraph, params::WindowProof},
+};
+use crate::util::data_at_sized_node;
+
+/// Stacked DRG PoRep with windows.
+///
+/// All layers but the last are labeled one window at a time, every window independently of the
+/// others, so the sequential part of labeling a layer is the labeling of one window rather than
+/// the whole sector. The last layer, the wrapper layer, labels every node from nodes of the last
+/// window layer all over the sector, and its labels are the keys the data is encoded with.
+#[derive(Debug)]
+pub struct WindowStackedDrg<'a, H: 'a + Hasher> {
+    _a: PhantomData<&'a H>,
+}
+
+impl<'a, H: 'static + Hasher> WindowStackedDrg<'a, H> {
+    pub(crate) fn prove_layers(
+        graph: &WindowGraph<H>,
+        t_aux: &TemporaryAux<H>,
+        layer_challenges: &LayerChallenges,
+        challenges: &[Vec<usize>],
+    ) -> Result<Vec<Vec<WindowProof<H>>>> {
+        let wrapper_layer = layer_challenges.layers();
+        assert_eq!(t_aux.encodings.len(), wrapper_layer);
+
+        // Challenges and their parents are scattered over the layers.
+        t_aux.encodings.advise(Access::Random);
+
+        let columns = |nodes: &[usize]| -> Result<Vec<_>> {
+            nodes
+                .iter()
+                .map(|node| Ok(t_aux.column(*node)?.into_proof(&t_aux.tree_c)))
+                .collect()
+        };
+        let labels = |layer: usize, nodes: &[usize]| -> Result<Vec<H::Domain>> {
+            nodes
+                .iter()
+                .map(|node| t_aux.domain_node_at_layer(layer, *node))
+                .collect()
+        };
+
+        challenges
+            .iter()
+            .enumerate()
+            .map(|(k, partition_challenges)| {
+                trace!("proving partition {}/{}", k + 1, challenges.len());
+
+                partition_challenges
+                    .par_iter()
+                    .enumerate()
+                    .map(|(challenge_index, &challenge)| {
+                        trace!(" challenge {} ({})", challenge, challenge_index);
+                        assert!(challenge < graph.size(), "Invalid challenge");
+
+                        let mut drg_parents = vec![0; graph.base_degree()];
+                        graph.base_parents(challenge, &mut drg_parents);
+                        let mut exp_parents = vec![0; graph.expansion_degree()];
+                        graph.expanded_parents(challenge, &mut exp_parents);
+                        let mut wrapper_parents = vec![0; graph.expansion_degree()];
+                        graph.wrapper_parents(challenge, &mut wrapper_parents);
+
+                        let comm_d_proof =
+                            MerkleProof::new_from_proof(&t_aux.tree_d.gen_proof(challenge));
+                        let comm_r_last_proof =
+                            MerkleProof::new_from_proof(&t_aux.tree_r_last.gen_proof(challenge));
+
+                        let rpc = ReplicaColumnProof {
+                            c_x: t_aux.column(challenge)?.into_proof(&t_aux.tree_c),
+                            drg_parents: columns(&drg_parents)?,
+                            exp_parents: columns(&exp_parents)?,
+                        };
+
+                        let mut encoding_proofs = Vec::with_capacity(wrapper_layer - 1);
+                        for layer in 1..wrapper_layer {
+                            // Due to tapering for some layers and some challenges we do not
+                            // create an encoding proof.
+                            if !layer_challenges.include_challenge_at_layer(layer, challenge_index)
+                            {
+                                continue;
+                            }
+
+                            // Base parents are in the same layer, expansion parents in the
+                            // previous one.
+                            let mut parents_data = labels(layer, &drg_parents)?;
+                            if layer > 1 {
+                                parents_data.extend(labels(layer - 1, &exp_parents)?);
+                            }
+
+                            encoding_proofs
+                                .push(EncodingProof::new(challenge as u64, parents_data));
+                        }
+
+                        let wrapper_proof = EncodingProof::new(
+                            challenge as u64,
+                            labels(wrapper_layer - 1, &wrapper_parents)?,
+                        );
+
+                        Ok(WindowProof {
+                            comm_d_proofs: comm_d_proof,
+                            comm_r_last_proof,
+                            replica_column_proofs: rpc,
+                            wrapper_parents: columns(&wrapper_parents)?,
+                            encoding_proofs,
+                            wrapper_proof,
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Labels the window layers and the wrapper layer.
+    pub(crate) fn generate_layers(
+        graph: &WindowGraph<H>,
+        layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
+        node_size: usize,
+        replica_id: &H::Domain,
+    ) -> Result<Encodings<H>> {
+        numa::place_labeling(|placement| {
+            Self::label_layers(
+                graph,
+                layer_challenges,
+                porep_id,
+                node_size,
+                replica_id,
+                placement,
+            )
+        })
+    }
+
+    fn label_layers(
+        graph: &WindowGraph<H>,
+        layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
+        node_size: usize,
+        replica_id: &H::Domain,
+        placement: Option<&Placement>,
+    ) -> Result<Encodings<H>> {
+        info!("generate window layers");
+        let wrapper_layer = layer_challenges.layers();
+        assert!(wrapper_layer > 1);
+
+        let layer_size = graph.size() * node_size;
+        let window_bytes = graph.window_size() * node_size;
+        let labeler = Labeler::new(porep_id, AsRef::<[u8]>::as_ref(replica_id), node_size);
+        let prefetch = Prefetch::from_settings()?;
+
+        let mut encodings: Vec<LayerStore<H::Domain>> = Vec::with_capacity(wrapper_layer);
+        let mut previous: Option<LabelBuffer> = None;
+        let token = cancel::current();
+
+        for layer in 1..wrapper_layer {
+            info!("generating window layer: {}", layer);
+            let tracker = Tracker::new(Phase::Labeling, Some(layer), graph.windows());
+            let start = Instant::now();
+
+            let mut labels = numa::layer_buffer(placement, layer_size)?;
+            labels
+                .par_chunks_mut(window_bytes)
+                .enumerate()
+                .try_for_each(|(window, window_labels)| -> Result<()> {
+                    token.check()?;
+
+                    // Labels are bound to the sector node, so no two windows share labels.
+                    let window_range = window * window_bytes..(window + 1) * window_bytes;
+                    label_layer(
+                        graph.window_graph(),
+                        &labeler,
+                        (window * graph.window_size()) as u64,
+                        window_labels,
+                        previous.as_ref().map(|previous| &previous[window_range]),
+                        node_size,
+                        prefetch,
+                        |_| Ok(()),
+                    )?;
+                    tracker.advance(1);
+
+                    Ok(())
+                })?;
+
+            encodings.push(LayerStore::new_from_slice(layer_size, &labels)?);
+            measurements::record(
+                Operation::LabelLayer,
+                Some(layer),
+                start.elapsed(),
+                layer_size as u64,
+            );
+            previous = Some(labels);
+        }
+
+        info!("generating wrapper layer: {}", wrapper_layer);
+        let tracker = Tracker::new(Phase::Labeling, Some(wrapper_layer), graph.size());
+        let start = Instant::now();
+        let previous = previous.expect("no window layer");
+
+        // The parents of the wrapper layer are all in the last window layer, so its nodes are
+        // independent of each other, and labeled in batches.
+        let batch = label::batch_size();
+        let mut keys = numa::layer_buffer(placement, layer_size)?;
+        keys.par_chunks_mut(batch * node_size)
+            .enumerate()
+            .try_for_each(|(i, batch_keys)| -> Result<()> {
+                token.check()?;
+
+                let nodes: Vec<u64> = (0..batch_keys.len() / node_size)
+                    .map(|node| (i * batch + node) as u64)
+                    .collect();
+                let mut parents = vec![0; graph.expansion_degree()];
+                let mut parents_data = vec![0u8; nodes.len() * parents.len() * node_size];
+                for (&node, node_parents_data) in nodes
+                    .iter()
+                    .zip(parents_data.chunks_mut(parents.len() * node_size))
+                {
+                    graph.wrapper_parents(node as usize, &mut parents);
+                    for (parent, data) in
+                        parents.iter().zip(node_parents_data.chunks_mut(node_size))
+                    {
+                        data.copy_from_slice(
+                            data_at_sized_node(&previous, *parent, node_size)
+                                .expect("invalid node"),
+                        );
+                    }
+                }
+
+                labeler.label_many(&nodes, &parents_data, batch_keys);
+                tracker.advance(nodes.len());
+
+                Ok(())
+            })?;
+
+        encodings.push(LayerStore::new_from_slice(layer_size, &keys)?);
+        measurements::record(
+            Operation::LabelLayer,
+            Some(wrapper_layer),
+            start.elapsed(),
+            layer_size as u64,
+        );
+
+        Ok(Encodings::new(encodings))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn transform_and_replicate_layers(
+        graph: &WindowGraph<H>,
+        layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
+        node_size: usize,
+        comm_r_hasher: CommRHasher,
+        replica_id: &H::Domain,
+        data: Data,
+        data_tree: Option<Tree<H>>,
+    ) -> Result<TransformedLayers<H>> {
+        trace!("transform_and_replicate_layers");
+
+        StackedDrg::<H>::replicate_with_layers(
+            graph.size(),
+            layer_challenges.layers(),
+            node_size,
+            comm_r_hasher,
+            data,
+            data_tree,
+            || Self::generate_layers(graph, layer_challenges, porep_id, node_size, replica_id),
+        )
+    }
+
+    pub(crate) fn extract_and_invert_transform_layers(
+        graph: &WindowGraph<H>,
+        layer_challenges: &LayerChallenges,
+        porep_id: &[u8; 32],
+        node_size: usize,
+        replica_id: &H::Domain,
+        data: &mut [u8],
+    ) -> Result<()> {
+        trace!("extract_and_invert_transform_layers");
+
+        let encodings =
+            Self::generate_layers(graph, layer_challenges, porep_id, node_size, replica_id)?;
+
+        StackedDrg::<H>::decode_with_layers(&encodings, node_size, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::drgporep;
+    use crate::drgraph::{GraphConfig, BASE_DEGREE};
+    use crate::hasher::{Blake2sHasher, Domain, PedersenHasher};
+    use crate::porep::PoRep;
+    use crate::proof::ProofScheme;
+    use crate::stacked::{
+        window::params::{WindowPublicParams, WindowSetupParams},
+        PrivateInputs, PublicInputs, EXP_DEGREE,
+    };
+    use crate::util::NODE_SIZE;
+
+    const LAYERS: usize = 3;
+
+    fn setup_params(nodes: usize, window_size: usize) -> WindowSetupParams {
+        WindowSetupParams {
+            drg: drgporep::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: EXP_DEGREE,
+                seed: [1, 2, 3, 4, 5, 6, 7],
+                graph_config: GraphConfig::default(),
+            },
+            window_size,
+            layer_challenges: LayerChallenges::new(LAYERS, 6),
+            porep_id: [3; 32],
+            comm_r_hasher: CommRHasher::Pedersen,
+            node_size: NODE_SIZE,
+        }
+    }
+
+    fn setup<H: 'static + Hasher>(nodes: usize, window_size: usize) -> WindowPublicParams<H> {
+        WindowStackedDrg::<H>::setup(&setup_params(nodes, window_size)).expect("setup failed")
+    }
+
+    #[test]
+    fn test_setup_rejects_invalid_params() {
+        let try_setup = |sp: &WindowSetupParams| WindowStackedDrg::<PedersenHasher>::setup(sp);
+
+        assert!(try_setup(&setup_params(32, 8)).is_ok());
+        assert!(try_setup(&setup_params(32, 2)).is_err());
+        assert!(try_setup(&setup_params(32, 12)).is_err());
+
+        let mut sp = setup_params(32, 8);
+        sp.layer_challenges = LayerChallenges::new(1, 6);
+        assert!(try_setup(&sp).is_err());
+    }
+
+    fn random_data<H: Hasher>(rng: &mut XorShiftRng, nodes: usize) -> Vec<u8> {
+        (0..nodes)
+            .flat_map(|_| {
+                let v: H::Domain = rng.gen();
+                v.into_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extract_all_pedersen() {
+        test_extract_all::<PedersenHasher>();
+    }
+
+    #[test]
+    fn extract_all_blake2s() {
+        test_extract_all::<Blake2sHasher>();
+    }
+
+    fn test_extract_all<H: 'static + Hasher>() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let replica_id: H::Domain = rng.gen();
+        let data = random_data::<H>(rng, 32);
+
+        let pp = setup::<H>(32, 8);
+
+        let mut replica = data.clone();
+        WindowStackedDrg::<H>::replicate(&pp, &replica_id, replica.as_mut_slice().into(), None)
+            .expect("replication failed");
+        assert_ne!(data, replica);
+
+        let decoded = WindowStackedDrg::<H>::extract_all(&pp, &replica_id, &replica)
+            .expect("failed to extract data");
+        assert_eq!(data, decoded);
+
+        // Single nodes can only be extracted along with the whole replica.
+        assert!(WindowStackedDrg::<H>::extract(&pp, &replica_id, &replica, 0).is_err());
+    }
+
+    #[test]
+    fn test_windows_are_labeled_independently() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let replica_id: <PedersenHasher as Hasher>::Domain = rng.gen();
+
+        let single = setup::<PedersenHasher>(16, 16);
+        let many = setup::<PedersenHasher>(64, 16);
+
+        let generate = |pp: &WindowPublicParams<PedersenHasher>| {
+            WindowStackedDrg::<PedersenHasher>::generate_layers(
+                &pp.graph,
+                &pp.layer_challenges,
+                &pp.porep_id,
+                pp.node_size,
+                &replica_id,
+            )
+            .expect("failed to generate layers")
+        };
+        let single_layers = generate(&single);
+        let many_layers = generate(&many);
+
+        // The first window of a sector is labeled as if it were the whole sector, up to the
+        // wrapper layer, which depends on all windows.
+        for layer in 1..LAYERS {
+            assert_eq!(
+                single_layers.encoding_at_layer(layer).read_range(0..16),
+                many_layers.encoding_at_layer(layer).read_range(0..16),
+                "layer {}",
+                layer
+            );
+        }
+        assert_ne!(
+            single_layers.encoding_at_layer(LAYERS).read_range(0..16),
+            many_layers.encoding_at_layer(LAYERS).read_range(0..16)
+        );
+    }
+
+    #[test]
+    fn prove_verify_pedersen() {
+        test_prove_verify::<PedersenHasher>();
+    }
+
+    #[test]
+    fn prove_verify_blake2s() {
+        test_prove_verify::<Blake2sHasher>();
+    }
+
+    fn test_prove_verify<H: 'static + Hasher>() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let replica_id: H::Domain = rng.gen();
+        let seed: H::Domain = rng.gen();
+        let mut replica = random_data::<H>(rng, 64);
+        let partitions = 2;
+
+        let pp = setup::<H>(64, 16);
+        let (tau, (p_aux, t_aux)) =
+            WindowStackedDrg::<H>::replicate(&pp, &replica_id, replica.as_mut_slice().into(), None)
+                .expect("replication failed");
+
+        let pub_inputs = PublicInputs::<H::Domain> {
+            replica_id,
+            seed: Some(seed),
+            tau: Some(tau),
+            k: None,
+        };
+        let priv_inputs = PrivateInputs { p_aux, t_aux };
+
+        let all_partition_proofs =
+            WindowStackedDrg::<H>::prove_all_partitions(&pp, &pub_inputs, &priv_inputs, partitions)
+                .expect("failed to generate partition proofs");
+
+        assert!(WindowStackedDrg::<H>::verify_all_partitions(
+            &pp,
+            &pub_inputs,
+            &all_partition_proofs
+        )
+        .expect("failed to verify partition proofs"));
+
+        // A proof of the wrong key is rejected.
+        let mut tampered = all_partition_proofs.clone();
+        tampered[0][0].wrapper_proof = tampered[0][1].wrapper_proof.clone();
+        assert!(
+            !WindowStackedDrg::<H>::verify_all_partitions(&pp, &pub_inputs, &tampered)
+                .expect("failed to verify partition proofs")
+        );
+
+        // So is a truncated one.
+        let mut truncated = all_partition_proofs;
+        truncated[1].pop();
+        assert!(
+            !WindowStackedDrg::<H>::verify_all_partitions(&pp, &pub_inputs, &truncated)
+                .expect("failed to verify partition proofs")
+        );
+    }
+}
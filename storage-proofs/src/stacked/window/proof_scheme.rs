@@ -0,0 +1,175 @@
+use rayon::prelude::*;
+
+use crate::error::{Error, Result};
+use crate::hasher::Hasher;
+use crate::proof::ProofScheme;
+use crate::stacked::{
+    challenges::ChallengeRequirements,
+    graph::derive_graph_seed,
+    params::{PrivateInputs, PublicInputs},
+    window::{
+        graph::WindowGraph,
+        params::{WindowProof, WindowPublicParams, WindowSetupParams},
+        proof::WindowStackedDrg,
+    },
+};
+use crate::util::check_node_size;
+
+impl<'a, 'c, H: 'static + Hasher> ProofScheme<'a> for WindowStackedDrg<'c, H> {
+    type PublicParams = WindowPublicParams<H>;
+    type SetupParams = WindowSetupParams;
+    type PublicInputs = PublicInputs<<H as Hasher>::Domain>;
+    type PrivateInputs = PrivateInputs<H>;
+    type Proof = Vec<WindowProof<H>>;
+    type Requirements = ChallengeRequirements;
+
+    fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+        check_node_size::<H>(sp.node_size)?;
+        check_window_params(sp)?;
+
+        let graph = WindowGraph::<H>::new(
+            sp.drg.nodes,
+            sp.window_size,
+            sp.drg.degree,
+            sp.drg.expansion_degree,
+            derive_graph_seed(sp.drg.seed, &sp.porep_id),
+            &sp.drg.graph_config,
+        );
+
+        Ok(WindowPublicParams::new(
+            graph,
+            sp.layer_challenges.clone(),
+            sp.porep_id,
+            sp.comm_r_hasher,
+            sp.node_size,
+        ))
+    }
+
+    fn prove<'b>(
+        pub_params: &'b Self::PublicParams,
+        pub_inputs: &'b Self::PublicInputs,
+        priv_inputs: &'b Self::PrivateInputs,
+    ) -> Result<Self::Proof> {
+        let proofs = Self::prove_all_partitions(pub_params, pub_inputs, priv_inputs, 1)?;
+        let k = pub_inputs.k.unwrap_or(0);
+        // As for `StackedDrg`, partitions are proven in one pass by `prove_all_partitions`.
+        assert!(
+            k < 1,
+            "It is a programmer error to call WindowStackedDrg::prove with more than one partition."
+        );
+
+        Ok(proofs[k].to_owned())
+    }
+
+    fn prove_all_partitions<'b>(
+        pub_params: &'b Self::PublicParams,
+        pub_inputs: &'b Self::PublicInputs,
+        priv_inputs: &'b Self::PrivateInputs,
+        partition_count: usize,
+    ) -> Result<Vec<Self::Proof>> {
+        trace!("prove_all_partitions");
+        assert!(partition_count > 0);
+
+        let challenges: Vec<_> = (0..partition_count)
+            .map(|k| pub_params.all_challenges(pub_inputs, Some(k)))
+            .collect();
+
+        Self::prove_layers(
+            &pub_params.graph,
+            &priv_inputs.t_aux,
+            &pub_params.layer_challenges,
+            &challenges,
+        )
+    }
+
+    fn verify_all_partitions(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        partition_proofs: &[Self::Proof],
+    ) -> Result<bool> {
+        trace!("verify_all_partitions");
+
+        for (k, proofs) in partition_proofs.iter().enumerate() {
+            trace!(
+                "verifying partition proof {}/{}",
+                k + 1,
+                partition_proofs.len()
+            );
+
+            let challenges = pub_params.all_challenges(pub_inputs, Some(k));
+
+            // Every challenge must be answered, otherwise a truncated proof would verify.
+            if proofs.len() != challenges.len() {
+                return Ok(false);
+            }
+
+            let valid = proofs
+                .par_iter()
+                .zip(challenges.par_iter())
+                .enumerate()
+                .all(|(i, (proof, challenge))| {
+                    trace!("verify challenge {}/{}", i + 1, challenges.len());
+                    proof.verify(pub_params, pub_inputs, *challenge, i)
+                });
+
+            if !valid {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn with_partition(pub_in: Self::PublicInputs, k: Option<usize>) -> Self::PublicInputs {
+        PublicInputs {
+            replica_id: pub_in.replica_id,
+            seed: pub_in.seed,
+            tau: pub_in.tau,
+            k,
+        }
+    }
+
+    fn satisfies_requirements(
+        public_params: &WindowPublicParams<H>,
+        requirements: &ChallengeRequirements,
+        partitions: usize,
+    ) -> bool {
+        let partition_challenges = public_params.layer_challenges.challenges_count_all();
+
+        partition_challenges * partitions >= requirements.minimum_challenges
+    }
+
+    fn minimum_partitions(
+        public_params: &WindowPublicParams<H>,
+        requirements: &ChallengeRequirements,
+    ) -> Option<usize> {
+        requirements.minimum_partitions(public_params.layer_challenges.challenges_count_all())
+    }
+}
+
+/// Checks what `WindowGraph::new` and `WindowPublicParams::new` assert, so that invalid setup
+/// parameters fail the setup instead of panicking.
+fn check_window_params(sp: &WindowSetupParams) -> Result<()> {
+    if sp.window_size <= 2 {
+        return Err(Error::InvalidGraph(format!(
+            "windows must have more than two nodes, got {}",
+            sp.window_size
+        )));
+    }
+
+    if sp.drg.nodes % sp.window_size != 0 {
+        return Err(Error::InvalidGraph(format!(
+            "{} nodes can't be split into whole windows of {} nodes",
+            sp.drg.nodes, sp.window_size
+        )));
+    }
+
+    if sp.layer_challenges.layers() < 2 {
+        return Err(Error::InvalidGraph(format!(
+            "the wrapper layer needs a window layer below it, got {} layers",
+            sp.layer_challenges.layers()
+        )));
+    }
+
+    Ok(())
+}
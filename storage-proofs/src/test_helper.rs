@@ -1,7 +1,7 @@
 use ff::{BitIterator, PrimeField, PrimeFieldRepr};
 use fil_sapling_crypto::pedersen_hash;
 use paired::bls12_381::{Bls12, Fr};
-use rand::Rng;
+use rand::{Rng, SeedableRng, XorShiftRng};
 
 use crate::crypto;
 use crate::error;
@@ -24,6 +24,14 @@ macro_rules! table_tests {
     }
 }
 
+/// The seed of `test_rng`, the one tests have always seeded their rngs with.
+pub const TEST_SEED: [u32; 4] = [0x3dbe_6259, 0x8d31_3d76, 0x3237_db17, 0xe5bc_0654];
+
+/// A deterministic rng for tests and fuzzing, seeded with `TEST_SEED`.
+pub fn test_rng() -> XorShiftRng {
+    XorShiftRng::from_seed(TEST_SEED)
+}
+
 pub struct FakeDrgParams {
     pub replica_id: Fr,
     pub replica_nodes: Vec<Fr>,
@@ -166,7 +174,7 @@ pub fn random_merkle_path_with_value<R: Rng>(
             lhs.into_iter()
                 .take(Fr::NUM_BITS as usize)
                 .chain(rhs.into_iter().take(Fr::NUM_BITS as usize)),
-            &crypto::pedersen::JJ_PARAMS,
+            *crypto::pedersen::JJ_PARAMS,
         )
         .into_xy()
         .0;
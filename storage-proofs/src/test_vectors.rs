@@ -0,0 +1,153 @@
+//! Deterministic test vectors for the stacked PoRep.
+//!
+//! `stacked_test_vector` seals and proves a fixed 2KiB sector with fixed parameters and returns
+//! everything a change to labeling, hashing, tree building, challenge derivation or proof
+//! encoding would alter, so that two builds can be compared for consensus breaks. It serializes
+//! to JSON, with every value hex encoded.
+
+use itertools::Itertools;
+
+use crate::drgporep;
+use crate::drgraph::{GraphConfig, BASE_DEGREE};
+use crate::error::Result;
+use crate::hasher::{Domain, Hasher, PedersenHasher};
+use crate::porep::PoRep;
+use crate::proof::ProofScheme;
+use crate::stacked::{
+    CommRHasher, LayerChallenges, PrivateInputs, PublicInputs, SetupParams, StackedDrg, EXP_DEGREE,
+};
+use crate::util::NODE_SIZE;
+
+pub const SECTOR_SIZE: usize = 2048;
+pub const LAYERS: usize = 4;
+pub const CHALLENGES: usize = 4;
+pub const PARTITIONS: usize = 2;
+pub const GRAPH_SEED: [u32; 7] = [1, 2, 3, 4, 5, 6, 7];
+pub const POREP_ID: [u8; 32] = [5; 32];
+
+/// The inputs and outputs of sealing and proving the test sector. Every value is hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StackedTestVector {
+    pub sector_size: usize,
+    pub layers: usize,
+    pub challenges: usize,
+    pub replica_id: String,
+    pub seed: String,
+    pub comm_d: String,
+    pub comm_c: String,
+    pub comm_r_last: String,
+    pub comm_r: String,
+    /// The labels of every layer, one entry per node.
+    pub labels: Vec<Vec<String>>,
+    /// The canonical encoding of the vanilla proofs of every partition.
+    pub proofs: Vec<Vec<String>>,
+}
+
+/// A valid field element, which differs for every `index` and `salt`.
+fn fixed_element(index: usize, salt: u8) -> [u8; NODE_SIZE] {
+    let mut bytes = [0u8; NODE_SIZE];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (index as u8)
+            .wrapping_mul(31)
+            .wrapping_add(i as u8)
+            .wrapping_add(salt);
+    }
+    // Clear the top bits, so the little-endian value is below the field modulus.
+    bytes[NODE_SIZE - 1] &= 0x3f;
+
+    bytes
+}
+
+fn to_hex<T: AsRef<[u8]>>(bytes: T) -> String {
+    format!("{:02x}", bytes.as_ref().iter().format(""))
+}
+
+/// Seals and proves the test sector.
+pub fn stacked_test_vector() -> Result<StackedTestVector> {
+    type H = PedersenHasher;
+
+    let nodes = SECTOR_SIZE / NODE_SIZE;
+    let replica_id = <H as Hasher>::Domain::try_from_bytes(&fixed_element(0, 1))?;
+    let seed = <H as Hasher>::Domain::try_from_bytes(&fixed_element(0, 2))?;
+    let mut data: Vec<u8> = (0..nodes)
+        .flat_map(|i| fixed_element(i, 0).to_vec())
+        .collect();
+
+    let sp = SetupParams {
+        drg: drgporep::DrgParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            seed: GRAPH_SEED,
+            graph_config: GraphConfig::default(),
+        },
+        layer_challenges: LayerChallenges::new(LAYERS, CHALLENGES),
+        porep_id: POREP_ID,
+        comm_r_hasher: CommRHasher::Pedersen,
+        node_size: NODE_SIZE,
+    };
+    let pp = StackedDrg::<H>::setup(&sp)?;
+
+    let (tau, (p_aux, t_aux)) =
+        StackedDrg::<H>::replicate(&pp, &replica_id, data.as_mut_slice().into(), None)?;
+
+    let labels = (1..=LAYERS)
+        .map(|layer| {
+            t_aux
+                .encodings
+                .encoding_at_layer(layer)
+                .read_range(0..nodes)
+                .into_iter()
+                .map(to_hex)
+                .collect()
+        })
+        .collect();
+
+    let pub_inputs = PublicInputs {
+        replica_id,
+        seed: Some(seed),
+        tau: Some(tau.clone()),
+        k: None,
+    };
+    let priv_inputs = PrivateInputs {
+        p_aux: p_aux.clone(),
+        t_aux,
+    };
+    let proofs = StackedDrg::<H>::prove_all_partitions(&pp, &pub_inputs, &priv_inputs, PARTITIONS)?
+        .iter()
+        .map(|partition| {
+            partition
+                .iter()
+                .map(|proof| to_hex(proof.serialize()))
+                .collect()
+        })
+        .collect();
+
+    Ok(StackedTestVector {
+        sector_size: SECTOR_SIZE,
+        layers: LAYERS,
+        challenges: CHALLENGES,
+        replica_id: to_hex(replica_id),
+        seed: to_hex(seed),
+        comm_d: to_hex(tau.comm_d),
+        comm_c: to_hex(p_aux.comm_c),
+        comm_r_last: to_hex(p_aux.comm_r_last),
+        comm_r: to_hex(tau.comm_r),
+        labels,
+        proofs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stacked_test_vector_is_deterministic() {
+        assert_eq!(
+            stacked_test_vector().unwrap(),
+            stacked_test_vector().unwrap()
+        );
+    }
+}
@@ -1,25 +1,74 @@
+use std::path::Path;
+
 use bellperson::{ConstraintSystem, SynthesisError};
+use ff::PrimeField;
 use fil_sapling_crypto::circuit::boolean::{self, AllocatedBit, Boolean};
+use merkletree::merkle::Element;
+use paired::bls12_381::Fr;
 use paired::Engine;
 
 use crate::error;
+use crate::hasher::Hasher;
+use crate::settings;
 
 pub const NODE_SIZE: usize = 32;
 
+/// Fails with `Error::ReadOnly` if the `read_only` setting is enabled, as `path` would have to be
+/// written.
+pub fn ensure_writable(path: &Path) -> error::Result<()> {
+    if settings::SETTINGS.lock().unwrap().read_only {
+        return Err(error::Error::ReadOnly(path.display().to_string()));
+    }
+
+    Ok(())
+}
+
 /// Returns the start position of the data, 0-indexed.
 pub fn data_at_node_offset(v: usize) -> usize {
-    v * NODE_SIZE
+    data_at_sized_node_offset(v, NODE_SIZE)
 }
 
 /// Returns the byte slice representing one node (of uniform size, NODE_SIZE) at position v in data.
 pub fn data_at_node(data: &[u8], v: usize) -> error::Result<&[u8]> {
-    let offset = data_at_node_offset(v);
+    data_at_sized_node(data, v, NODE_SIZE)
+}
+
+/// Returns the start position of the data, 0-indexed, for nodes of `node_size` bytes.
+pub fn data_at_sized_node_offset(v: usize, node_size: usize) -> usize {
+    v * node_size
+}
+
+/// Returns the byte slice representing one node (of uniform size, node_size) at position v in data.
+pub fn data_at_sized_node(data: &[u8], v: usize, node_size: usize) -> error::Result<&[u8]> {
+    let offset = data_at_sized_node_offset(v, node_size);
+
+    if offset + node_size > data.len() {
+        return Err(error::Error::OutOfBounds(offset + node_size, data.len()));
+    }
 
-    if offset + NODE_SIZE > data.len() {
-        return Err(error::Error::OutOfBounds(offset + NODE_SIZE, data.len()));
+    Ok(&data[offset..offset + node_size])
+}
+
+/// Checks that a node of `node_size` bytes can hold a field element, and holds exactly one
+/// element of the domain of `H`.
+pub fn check_node_size<H: Hasher>(node_size: usize) -> error::Result<()> {
+    let field_bytes = (Fr::NUM_BITS as usize + 7) / 8;
+    if node_size < field_bytes {
+        return Err(error::Error::InvalidNodeSize(
+            node_size,
+            format!("a field element needs {} bytes", field_bytes),
+        ));
+    }
+
+    let domain_bytes = H::Domain::byte_len();
+    if node_size != domain_bytes {
+        return Err(error::Error::InvalidNodeSize(
+            node_size,
+            format!("{} elements are {} bytes", H::name(), domain_bytes),
+        ));
     }
 
-    Ok(&data[offset..offset + NODE_SIZE])
+    Ok(())
 }
 
 /// Converts bytes into their bit representation, in little endian format.
@@ -89,6 +138,27 @@ mod tests {
     use paired::bls12_381::*;
     use rand::{Rng, SeedableRng, XorShiftRng};
 
+    use crate::hasher::{PedersenHasher, Sha256Hasher};
+
+    #[test]
+    fn test_data_at_sized_node() {
+        let data: Vec<u8> = (0..48).collect();
+
+        assert_eq!(data_at_sized_node(&data, 2, 16).unwrap(), &data[32..48]);
+        assert_eq!(data_at_sized_node(&data, 1, 32).unwrap(), &data[32..]);
+        assert!(data_at_sized_node(&data, 1, 24).is_ok());
+        assert!(data_at_sized_node(&data, 2, 24).is_err());
+        assert_eq!(data_at_node(&data, 0).unwrap(), &data[..NODE_SIZE]);
+    }
+
+    #[test]
+    fn test_check_node_size() {
+        assert!(check_node_size::<PedersenHasher>(NODE_SIZE).is_ok());
+        assert!(check_node_size::<Sha256Hasher>(NODE_SIZE).is_ok());
+        assert!(check_node_size::<PedersenHasher>(16).is_err());
+        assert!(check_node_size::<PedersenHasher>(64).is_err());
+    }
+
     #[test]
     fn test_bytes_into_boolean_vec() {
         let mut cs = TestConstraintSystem::<Bls12>::new();
@@ -0,0 +1,441 @@
+//! Export of Groth16 verifying keys, for verifiers written outside of Rust.
+//!
+//! A `VerifierExport` is the verifying key of a circuit as plain JSON. Every point is given by its
+//! affine coordinates, each hex encoded big-endian with a `0x` prefix, as Solidity and most other
+//! ecosystems take them. The coordinates of G2 points are elements of Fq2 and are given imaginary
+//! part first, `[c1, c0]`, in the order of the uncompressed encoding and of EIP-197. The point at
+//! infinity has all coordinates zero. Public inputs are elements of Fr, encoded the same way.
+//!
+//! An export may carry sample proofs together with their public inputs, which a ported verifier
+//! can be tested against. `VerifierExport::self_check` re-imports the export from its JSON and
+//! verifies the samples with the re-imported key, so a broken export is caught before it ships.
+
+use bellperson::groth16;
+use ff::{PrimeField, PrimeFieldRepr};
+use itertools::Itertools;
+use paired::bls12_381::{Bls12, Fr, FrRepr, G1Affine, G1Uncompressed, G2Affine, G2Uncompressed};
+use paired::{CurveAffine, EncodedPoint};
+
+use crate::error::{Error, Result};
+
+/// The size of an encoded coordinate of Fq.
+pub const FQ_BYTES: usize = 48;
+/// The size of an encoded element of Fr.
+pub const FR_BYTES: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct G1Point {
+    pub x: String,
+    pub y: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct G2Point {
+    /// `[c1, c0]`
+    pub x: [String; 2],
+    /// `[c1, c0]`
+    pub y: [String; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VerifyingKeyExport {
+    pub alpha_g1: G1Point,
+    pub beta_g1: G1Point,
+    pub beta_g2: G2Point,
+    pub gamma_g2: G2Point,
+    pub delta_g1: G1Point,
+    pub delta_g2: G2Point,
+    /// One point per public input, after the one for the constant input.
+    pub ic: Vec<G1Point>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofExport {
+    pub a: G1Point,
+    pub b: G2Point,
+    pub c: G1Point,
+}
+
+/// A circuit proof, with the public inputs it verifies against.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SampleExport {
+    pub proof: ProofExport,
+    pub public_inputs: Vec<String>,
+}
+
+/// The verifying key of a circuit, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VerifierExport {
+    /// Identifies the circuit, like the keys of the parameter cache, e.g. `STACKED[1024]`.
+    pub circuit: String,
+    /// The number of circuit proofs a full proof consists of, each checked against the key.
+    pub partitions: usize,
+    /// The number of public inputs of each circuit proof.
+    pub public_inputs: usize,
+    pub verifying_key: VerifyingKeyExport,
+    pub samples: Vec<SampleExport>,
+}
+
+impl VerifierExport {
+    pub fn new(
+        circuit: &str,
+        partitions: usize,
+        verifying_key: &groth16::VerifyingKey<Bls12>,
+    ) -> Self {
+        VerifierExport {
+            circuit: circuit.to_string(),
+            partitions,
+            public_inputs: verifying_key.ic.len().saturating_sub(1),
+            verifying_key: VerifyingKeyExport::from_key(verifying_key),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Adds a proof, which must verify against `public_inputs`, as a sample.
+    pub fn add_sample(
+        &mut self,
+        proof: &groth16::Proof<Bls12>,
+        public_inputs: &[Fr],
+    ) -> Result<()> {
+        if public_inputs.len() != self.public_inputs {
+            return Err(Error::InvalidVerifierExport(format!(
+                "{} public inputs given, but the circuit has {}",
+                public_inputs.len(),
+                self.public_inputs
+            )));
+        }
+
+        self.samples.push(SampleExport {
+            proof: ProofExport::from_proof(proof),
+            public_inputs: public_inputs.iter().map(export_fr).collect(),
+        });
+
+        Ok(())
+    }
+
+    /// Imports the verifying key, checking every point is on its curve and in its subgroup.
+    pub fn verifying_key(&self) -> Result<groth16::VerifyingKey<Bls12>> {
+        let verifying_key = self.verifying_key.to_key()?;
+        if verifying_key.ic.len() != self.public_inputs + 1 {
+            return Err(Error::InvalidVerifierExport(format!(
+                "{} points in ic, but {} public inputs",
+                verifying_key.ic.len(),
+                self.public_inputs
+            )));
+        }
+
+        Ok(verifying_key)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(Into::into)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(Into::into)
+    }
+
+    /// Re-imports the export from its JSON, and verifies every sample with the re-imported key.
+    /// Fails if there are no samples, or if any of them does not verify.
+    pub fn self_check(&self) -> Result<()> {
+        let imported = VerifierExport::from_json(&self.to_json()?)?;
+        if imported != *self {
+            return Err(Error::InvalidVerifierExport(
+                "the export changes when re-imported".to_string(),
+            ));
+        }
+        if imported.samples.is_empty() {
+            return Err(Error::InvalidVerifierExport(
+                "there is no sample proof to check".to_string(),
+            ));
+        }
+
+        let pvk = groth16::prepare_verifying_key(&imported.verifying_key()?);
+        for (i, sample) in imported.samples.iter().enumerate() {
+            let proof = sample.proof.to_proof()?;
+            let public_inputs = sample
+                .public_inputs
+                .iter()
+                .map(String::as_str)
+                .map(import_fr)
+                .collect::<Result<Vec<_>>>()?;
+
+            if public_inputs.len() != imported.public_inputs
+                || !groth16::verify_proof(&pvk, &proof, &public_inputs)?
+            {
+                return Err(Error::InvalidVerifierExport(format!(
+                    "sample {} does not verify",
+                    i
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl VerifyingKeyExport {
+    pub fn from_key(verifying_key: &groth16::VerifyingKey<Bls12>) -> Self {
+        VerifyingKeyExport {
+            alpha_g1: export_g1(&verifying_key.alpha_g1),
+            beta_g1: export_g1(&verifying_key.beta_g1),
+            beta_g2: export_g2(&verifying_key.beta_g2),
+            gamma_g2: export_g2(&verifying_key.gamma_g2),
+            delta_g1: export_g1(&verifying_key.delta_g1),
+            delta_g2: export_g2(&verifying_key.delta_g2),
+            ic: verifying_key.ic.iter().map(export_g1).collect(),
+        }
+    }
+
+    pub fn to_key(&self) -> Result<groth16::VerifyingKey<Bls12>> {
+        Ok(groth16::VerifyingKey {
+            alpha_g1: import_g1(&self.alpha_g1)?,
+            beta_g1: import_g1(&self.beta_g1)?,
+            beta_g2: import_g2(&self.beta_g2)?,
+            gamma_g2: import_g2(&self.gamma_g2)?,
+            delta_g1: import_g1(&self.delta_g1)?,
+            delta_g2: import_g2(&self.delta_g2)?,
+            ic: self.ic.iter().map(import_g1).collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl ProofExport {
+    pub fn from_proof(proof: &groth16::Proof<Bls12>) -> Self {
+        ProofExport {
+            a: export_g1(&proof.a),
+            b: export_g2(&proof.b),
+            c: export_g1(&proof.c),
+        }
+    }
+
+    pub fn to_proof(&self) -> Result<groth16::Proof<Bls12>> {
+        Ok(groth16::Proof {
+            a: import_g1(&self.a)?,
+            b: import_g2(&self.b)?,
+            c: import_g1(&self.c)?,
+        })
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{:02x}", bytes.iter().format(""))
+}
+
+pub(crate) fn from_hex(hex: &str, len: usize) -> Result<Vec<u8>> {
+    let invalid =
+        || Error::InvalidVerifierExport(format!("invalid {} byte hex value {}", len, hex));
+
+    if !hex.starts_with("0x") || hex.len() != 2 + 2 * len || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    (0..len)
+        .map(|i| u8::from_str_radix(&hex[2 + 2 * i..4 + 2 * i], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+/// Splits an encoded point into its hex encoded coordinates.
+fn split_coordinates(encoded: &[u8]) -> Vec<String> {
+    encoded.chunks(FQ_BYTES).map(to_hex).collect()
+}
+
+/// Joins hex encoded coordinates into an encoded point, or `None` for the point at infinity.
+fn encoded_point(coordinates: &[&String]) -> Result<Option<Vec<u8>>> {
+    let mut encoded = Vec::with_capacity(coordinates.len() * FQ_BYTES);
+    for coordinate in coordinates {
+        encoded.extend(from_hex(coordinate, FQ_BYTES)?);
+    }
+
+    if encoded.iter().all(|byte| *byte == 0) {
+        Ok(None)
+    } else {
+        Ok(Some(encoded))
+    }
+}
+
+fn export_g1(point: &G1Affine) -> G1Point {
+    let mut coordinates = if point.is_zero() {
+        split_coordinates(&[0; 2 * FQ_BYTES])
+    } else {
+        split_coordinates(point.into_uncompressed().as_ref())
+    };
+
+    G1Point {
+        y: coordinates.pop().expect("two coordinates"),
+        x: coordinates.pop().expect("two coordinates"),
+    }
+}
+
+fn import_g1(point: &G1Point) -> Result<G1Affine> {
+    let encoded = match encoded_point(&[&point.x, &point.y])? {
+        Some(encoded) => encoded,
+        None => return Ok(G1Affine::zero()),
+    };
+
+    let mut uncompressed = G1Uncompressed::empty();
+    uncompressed.as_mut().copy_from_slice(&encoded);
+    uncompressed
+        .into_affine()
+        .map_err(|err| Error::InvalidVerifierExport(format!("invalid G1 point: {}", err)))
+}
+
+fn export_g2(point: &G2Affine) -> G2Point {
+    let coordinates = if point.is_zero() {
+        split_coordinates(&[0; 4 * FQ_BYTES])
+    } else {
+        split_coordinates(point.into_uncompressed().as_ref())
+    };
+
+    G2Point {
+        x: [coordinates[0].clone(), coordinates[1].clone()],
+        y: [coordinates[2].clone(), coordinates[3].clone()],
+    }
+}
+
+fn import_g2(point: &G2Point) -> Result<G2Affine> {
+    let encoded = match encoded_point(&[&point.x[0], &point.x[1], &point.y[0], &point.y[1]])? {
+        Some(encoded) => encoded,
+        None => return Ok(G2Affine::zero()),
+    };
+
+    let mut uncompressed = G2Uncompressed::empty();
+    uncompressed.as_mut().copy_from_slice(&encoded);
+    uncompressed
+        .into_affine()
+        .map_err(|err| Error::InvalidVerifierExport(format!("invalid G2 point: {}", err)))
+}
+
+fn export_fr(fr: &Fr) -> String {
+    let mut bytes = Vec::with_capacity(FR_BYTES);
+    fr.into_repr()
+        .write_be(&mut bytes)
+        .expect("in memory write failed");
+
+    to_hex(&bytes)
+}
+
+fn import_fr(hex: &str) -> Result<Fr> {
+    let mut repr = FrRepr::default();
+    repr.read_be(&from_hex(hex, FR_BYTES)?[..])?;
+
+    Fr::from_repr(repr)
+        .map_err(|err| Error::InvalidVerifierExport(format!("invalid public input: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+    use ff::Field;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    /// Proves knowledge of the square roots of its public inputs.
+    struct SquaresCircuit {
+        roots: Vec<Option<Fr>>,
+    }
+
+    impl Circuit<Bls12> for SquaresCircuit {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> std::result::Result<(), SynthesisError> {
+            for (i, root) in self.roots.into_iter().enumerate() {
+                let root_var = cs.alloc(
+                    || format!("root_{}", i),
+                    || root.ok_or(SynthesisError::AssignmentMissing),
+                )?;
+                let square_var = cs.alloc_input(
+                    || format!("square_{}", i),
+                    || {
+                        let mut square = root.ok_or(SynthesisError::AssignmentMissing)?;
+                        square.square();
+                        Ok(square)
+                    },
+                )?;
+                cs.enforce(
+                    || format!("square_{}", i),
+                    |lc| lc + root_var,
+                    |lc| lc + root_var,
+                    |lc| lc + square_var,
+                );
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_verifier_export() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let params = groth16::generate_random_parameters::<Bls12, _, _>(
+            SquaresCircuit {
+                roots: vec![None; 3],
+            },
+            rng,
+        )
+        .unwrap();
+
+        let roots: Vec<Fr> = (0..3).map(|_| rng.gen()).collect();
+        let squares: Vec<Fr> = roots
+            .iter()
+            .map(|root| {
+                let mut square = *root;
+                square.square();
+                square
+            })
+            .collect();
+        let proof = groth16::create_random_proof(
+            SquaresCircuit {
+                roots: roots.into_iter().map(Some).collect(),
+            },
+            &params,
+            rng,
+        )
+        .unwrap();
+
+        let mut export = VerifierExport::new("SQUARES", 1, &params.vk);
+        assert_eq!(export.public_inputs, 3);
+        // Without a sample there is nothing to check.
+        assert!(export.self_check().is_err());
+
+        export.add_sample(&proof, &squares).unwrap();
+        assert!(export.add_sample(&proof, &squares[1..]).is_err());
+        export.self_check().expect("self check failed");
+
+        let imported = VerifierExport::from_json(&export.to_json().unwrap()).unwrap();
+        assert!(imported.verifying_key().unwrap() == params.vk);
+        assert!(imported.samples[0].proof.to_proof().unwrap() == proof);
+        assert_eq!(imported.verifying_key.alpha_g1.x.len(), 2 + 2 * FQ_BYTES);
+
+        // A proof checked against other inputs must fail the check.
+        let mut tampered = export.clone();
+        tampered.samples[0].public_inputs.swap(0, 1);
+        assert!(tampered.self_check().is_err());
+
+        // Points are checked to be on the curve.
+        let mut tampered = export.clone();
+        tampered.verifying_key.alpha_g1.y = tampered.verifying_key.alpha_g1.x.clone();
+        assert!(tampered.verifying_key().is_err());
+        assert!(tampered.self_check().is_err());
+    }
+
+    #[test]
+    fn test_point_at_infinity() {
+        let g1 = export_g1(&G1Affine::zero());
+        let g2 = export_g2(&G2Affine::zero());
+        assert_eq!(g1.x, to_hex(&[0; FQ_BYTES]));
+        assert!(import_g1(&g1).unwrap().is_zero());
+        assert!(import_g2(&g2).unwrap().is_zero());
+
+        assert!(from_hex("0x00", 2).is_err());
+        assert!(from_hex("00", 1).is_err());
+        assert!(from_hex("0xzz", 1).is_err());
+        assert_eq!(from_hex("0x0aff", 2).unwrap(), vec![0x0a, 0xff]);
+    }
+}
@@ -0,0 +1,70 @@
+//! Canonical binary encoding for values shipped between processes or stored on chain.
+//!
+//! Values are encoded with bincode after a four byte header holding `SERIALIZATION_VERSION` as
+//! little-endian u32. The encoding only contains fixed size integers, byte arrays and length
+//! prefixed sequences, so equal values always encode to identical bytes.
+
+use std::io::{Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+/// The version written by `write_into`, and the only one `read_from` accepts.
+pub const SERIALIZATION_VERSION: u32 = 1;
+
+/// Writes the version header followed by the encoding of `value`.
+pub fn write_into<T: Serialize, W: Write>(value: &T, mut writer: W) -> Result<()> {
+    writer.write_all(&SERIALIZATION_VERSION.to_le_bytes())?;
+    bincode::serialize_into(writer, value)?;
+
+    Ok(())
+}
+
+/// Reads a value written by `write_into`, failing if it was written with another version.
+pub fn read_from<T: DeserializeOwned, R: Read>(mut reader: R) -> Result<T> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+
+    let version = u32::from_le_bytes(header);
+    if version != SERIALIZATION_VERSION {
+        return Err(Error::UnsupportedSerializationVersion(version));
+    }
+
+    Ok(bincode::deserialize_from(reader)?)
+}
+
+/// Returns the bytes written by `write_into`.
+pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_into(value, &mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versioned_roundtrip() {
+        let value = (7u64, vec![[1u8; 32], [2u8; 32]]);
+
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(&bytes[..4], &[1, 0, 0, 0]);
+        assert_eq!(
+            read_from::<(u64, Vec<[u8; 32]>), _>(&bytes[..]).unwrap(),
+            value
+        );
+
+        let mut other_version = bytes.clone();
+        other_version[0] = 2;
+        match read_from::<(u64, Vec<[u8; 32]>), _>(&other_version[..]) {
+            Err(Error::UnsupportedSerializationVersion(2)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert!(read_from::<(u64, Vec<[u8; 32]>), _>(&bytes[..bytes.len() - 1]).is_err());
+    }
+}